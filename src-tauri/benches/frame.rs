@@ -0,0 +1,22 @@
+//! Building a positions frame and serializing it to JSON, for chains of a
+//! few lengths.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use double_pendulum_lib::FrameSource;
+
+const BOB_COUNTS: [usize; 4] = [2, 4, 10, 30];
+
+fn frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame");
+    group.throughput(Throughput::Elements(1));
+    for n in BOB_COUNTS {
+        let mut source = FrameSource::new(n);
+        group.bench_function(BenchmarkId::new("build_and_serialize", n), |b| {
+            b.iter(|| source.next_json())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
@@ -1,3 +1,5 @@
 fn main() {
+    // only the app has a window to build for
+    #[cfg(feature = "app")]
     tauri_build::build()
 }
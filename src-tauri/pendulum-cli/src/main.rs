@@ -0,0 +1,4 @@
+// Batch runs without a window: `pendulum-cli --help` lists the commands.
+fn main() -> std::process::ExitCode {
+    double_pendulum_lib::run_headless()
+}
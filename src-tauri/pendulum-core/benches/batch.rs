@@ -0,0 +1,42 @@
+//! A cloud of two-bob chains stepped as cloned `Pendulum`s and as one
+//! `Batch2`, in copy-steps a second.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use pendulum_core::{Batch2, Bob, Pendulum};
+use std::hint::black_box;
+
+const COPIES: usize = 2_000;
+const DT: f64 = 1e-3;
+
+fn ensembles(c: &mut Criterion) {
+    let reference = Pendulum::new(vec![
+        Bob::new(1.0, 1.0, 2.0, 0.0),
+        Bob::new(0.8, 1.5, 3.5, 1.0),
+    ]);
+    let mut members: Vec<Pendulum> = (0..COPIES)
+        .map(|k| {
+            let mut member = reference.clone();
+            member.bobs[0].theta += 1e-6 * k as f64;
+            member
+        })
+        .collect();
+    let mut batch = Batch2::new(&reference).expect("two free bobs");
+    for member in &members {
+        batch.push(member);
+    }
+
+    let mut group = c.benchmark_group("ensemble_step");
+    group.throughput(Throughput::Elements(COPIES as u64));
+    group.bench_function("cloned_pendulums", |b| {
+        b.iter(|| {
+            for member in &mut members {
+                member.step(black_box(DT));
+            }
+        })
+    });
+    group.bench_function("batch2_f32", |b| b.iter(|| batch.step(black_box(DT))));
+    group.finish();
+}
+
+criterion_group!(benches, ensembles);
+criterion_main!(benches);
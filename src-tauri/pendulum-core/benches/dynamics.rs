@@ -0,0 +1,39 @@
+//! The dynamics, for chains of a few lengths. Run before and after a
+//! physics change; criterion compares against the last run.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pendulum_core::{Bob, Pendulum};
+use std::hint::black_box;
+
+const BOB_COUNTS: [usize; 4] = [2, 4, 10, 30];
+const DT: f64 = 1e-3;
+
+/// `n` bobs fanned out, so no term is zero.
+fn chain(n: usize) -> Pendulum {
+    let bobs = (0..n)
+        .map(|i| Bob::new(1.0, 1.0, 2.0 + 0.1 * i as f64, 0.5))
+        .collect();
+    Pendulum::new(bobs)
+}
+
+fn dynamics(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dynamics");
+    group.throughput(Throughput::Elements(1));
+    for n in BOB_COUNTS {
+        let pendulum = chain(n);
+        group.bench_with_input(BenchmarkId::new("mass_matrix", n), &pendulum, |b, p| {
+            b.iter(|| black_box(p).mass_matrix())
+        });
+        group.bench_with_input(BenchmarkId::new("coriolis", n), &pendulum, |b, p| {
+            b.iter(|| black_box(p).coriolis())
+        });
+        let mut stepped = pendulum.clone();
+        group.bench_function(BenchmarkId::new("step", n), |b| {
+            b.iter(|| stepped.step(black_box(DT)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dynamics);
+criterion_main!(benches);
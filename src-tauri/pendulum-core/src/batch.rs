@@ -0,0 +1,334 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::integrator::{Integrator, UpdateOrder};
+use crate::pendulum::{Coordinate, Pendulum, GRAVITATIONAL_ACCELERATION};
+
+/// Many copies of one two-bob chain that differ only in their angles and
+/// angular velocities, kept as one f32 array per quantity and stepped with
+/// the closed-form equations of motion. For clouds of thousands of copies,
+/// where the count matters more than the last digits of any one; each copy
+/// steps as `Pendulum::step` would, only in single precision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch2 {
+    /// The chain every copy is, its own angles aside.
+    template: Pendulum,
+    theta1: Vec<f32>,
+    theta2: Vec<f32>,
+    omega1: Vec<f32>,
+    omega2: Vec<f32>,
+}
+
+/// What the equations of motion need of the chain, worked out once a step
+/// rather than once a copy.
+#[derive(Clone, Copy)]
+struct Terms {
+    /// The constant entries of the mass matrix, and the factor of cos Δ in
+    /// the other.
+    m11: f32,
+    m22: f32,
+    m12: f32,
+    /// The factors of sin θ in the gravity terms.
+    g1: f32,
+    g2: f32,
+    damping: f32,
+}
+
+impl Terms {
+    fn of(template: &Pendulum) -> Self {
+        let [first, second] = [&template.bobs[0], &template.bobs[1]];
+        let (l1, l2) = (first.length_rod, second.length_rod);
+        let below = first.mass + second.mass;
+        let g = GRAVITATIONAL_ACCELERATION;
+        Self {
+            m11: (below * l1 * l1) as f32,
+            m22: (second.mass * l2 * l2) as f32,
+            m12: (second.mass * l1 * l2) as f32,
+            g1: (below * g * l1) as f32,
+            g2: (second.mass * g * l2) as f32,
+            damping: template.damping as f32,
+        }
+    }
+}
+
+/// A number the equations are solved in: a single f32, or with the `simd`
+/// feature eight of them at once.
+trait Lane:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+{
+    fn splat(value: f32) -> Self;
+    fn sin_cos(self) -> (Self, Self);
+}
+
+impl Lane for f32 {
+    fn splat(value: f32) -> Self {
+        value
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        f32::sin_cos(self)
+    }
+}
+
+#[cfg(feature = "simd")]
+impl Lane for wide::f32x8 {
+    fn splat(value: f32) -> Self {
+        wide::f32x8::splat(value)
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        wide::f32x8::sin_cos(self)
+    }
+}
+
+/// θ̈ of both joints, from M θ̈ = -(C + G + D) with the 2×2 M inverted in
+/// closed form. Its determinant is l₁²l₂²m₂(m₁ + m₂ sin²Δ), never zero for
+/// positive masses, so unlike the general solve this one can't fail.
+fn accelerations<L: Lane>(terms: Terms, theta: [L; 2], omega: [L; 2]) -> [L; 2] {
+    let s = L::splat;
+    let (sin_d, cos_d) = (theta[0] - theta[1]).sin_cos();
+    let (sin1, _) = theta[0].sin_cos();
+    let (sin2, _) = theta[1].sin_cos();
+    let m12 = s(terms.m12) * cos_d;
+    let coriolis = s(terms.m12) * sin_d;
+    let c = s(terms.damping);
+    let r1 =
+        coriolis * omega[1] * omega[1] - s(terms.g1) * sin1 + c * (s(2.0) * omega[0] - omega[1]);
+    let r2 =
+        s(0.0) - coriolis * omega[0] * omega[0] - s(terms.g2) * sin2 + c * (omega[1] - omega[0]);
+    let det = s(terms.m11) * s(terms.m22) - m12 * m12;
+    [
+        (m12 * r2 - s(terms.m22) * r1) / det,
+        (m12 * r1 - s(terms.m11) * r2) / det,
+    ]
+}
+
+/// One symplectic Euler step of one lane of copies.
+fn step_lane<L: Lane>(
+    terms: Terms,
+    order: UpdateOrder,
+    dt: f32,
+    theta: &mut [L; 2],
+    omega: &mut [L; 2],
+) {
+    let dt = L::splat(dt);
+    if order == UpdateOrder::PositionFirst {
+        theta[0] = theta[0] + omega[0] * dt;
+        theta[1] = theta[1] + omega[1] * dt;
+    }
+    let [a1, a2] = accelerations(terms, *theta, *omega);
+    omega[0] = omega[0] + a1 * dt;
+    omega[1] = omega[1] + a2 * dt;
+    if order == UpdateOrder::VelocityFirst {
+        theta[0] = theta[0] + omega[0] * dt;
+        theta[1] = theta[1] + omega[1] * dt;
+    }
+}
+
+impl Batch2 {
+    /// An empty batch of copies of `template`, or `None` unless it has two
+    /// bobs, neither locked.
+    pub fn new(template: &Pendulum) -> Option<Self> {
+        if template.n() != 2 || template.bobs.iter().any(|b| b.locked) {
+            return None;
+        }
+        Some(Self {
+            template: template.clone(),
+            theta1: Vec::new(),
+            theta2: Vec::new(),
+            omega1: Vec::new(),
+            omega2: Vec::new(),
+        })
+    }
+
+    /// Adds a copy with the angles of `member`'s bobs, ignoring the rest of
+    /// it.
+    pub fn push(&mut self, member: &Pendulum) {
+        let theta = |i: usize| member.bobs.get(i).map_or(0.0, |b| b.theta as f32);
+        let omega = |i: usize| member.bobs.get(i).map_or(0.0, |b| b.omega as f32);
+        self.theta1.push(theta(0));
+        self.theta2.push(theta(1));
+        self.omega1.push(omega(0));
+        self.omega2.push(omega(1));
+    }
+
+    pub fn len(&self) -> usize {
+        self.theta1.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.theta1.is_empty()
+    }
+
+    /// Copy `i` as a chain of its own, in double precision again.
+    pub fn get(&self, i: usize) -> Option<Pendulum> {
+        let mut member = self.template.clone();
+        let theta = [*self.theta1.get(i)?, self.theta2[i]];
+        let omega = [self.omega1[i], self.omega2[i]];
+        for (bob, (theta, omega)) in member.bobs.iter_mut().zip(theta.into_iter().zip(omega)) {
+            (bob.theta, bob.omega) = (f64::from(theta), f64::from(omega));
+        }
+        member.update_coordinates();
+        Some(member)
+    }
+
+    /// Hangs every copy from `pivot`.
+    pub fn set_pivot(&mut self, pivot: Coordinate) {
+        self.template.pivot = pivot;
+    }
+
+    /// Steps every copy with `integrator`.
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.template.integrator = integrator;
+    }
+
+    /// Where each copy's tip is, in copy order.
+    pub fn tips(&self) -> impl Iterator<Item = Coordinate> + '_ {
+        let pivot = self.template.pivot;
+        let (l1, l2) = (
+            self.template.bobs[0].length_rod,
+            self.template.bobs[1].length_rod,
+        );
+        self.theta1.iter().zip(&self.theta2).map(move |(&t1, &t2)| {
+            let (t1, t2) = (f64::from(t1), f64::from(t2));
+            Coordinate::new(
+                pivot.x + l1 * t1.sin() + l2 * t2.sin(),
+                pivot.y + l1 * t1.cos() + l2 * t2.cos(),
+            )
+        })
+    }
+
+    /// Advances every copy by `dt`, in one pass over the arrays.
+    pub fn step(&mut self, dt: f64) {
+        let terms = Terms::of(&self.template);
+        let Integrator::SymplecticEuler { order } = self.template.integrator;
+        let dt = dt as f32;
+        // whole lanes of eight with the `simd` feature, then one at a time
+        #[cfg(feature = "simd")]
+        let start = self.step_lanes(terms, order, dt);
+        #[cfg(not(feature = "simd"))]
+        let start = 0;
+        let rest = (self.theta1[start..]
+            .iter_mut()
+            .zip(&mut self.theta2[start..]))
+        .zip(
+            self.omega1[start..]
+                .iter_mut()
+                .zip(&mut self.omega2[start..]),
+        );
+        for ((t1, t2), (w1, w2)) in rest {
+            let (mut theta, mut omega) = ([*t1, *t2], [*w1, *w2]);
+            step_lane(terms, order, dt, &mut theta, &mut omega);
+            ([*t1, *t2], [*w1, *w2]) = (theta, omega);
+        }
+    }
+    /// Steps the copies eight at a time, as far as they fill whole lanes,
+    /// and returns how many it stepped.
+    #[cfg(feature = "simd")]
+    fn step_lanes(&mut self, terms: Terms, order: UpdateOrder, dt: f32) -> usize {
+        use wide::f32x8;
+        let load = |values: &[f32]| f32x8::new(values.try_into().expect("8 lanes"));
+        let whole = self.len() - self.len() % 8;
+        for start in (0..whole).step_by(8) {
+            let lanes = start..start + 8;
+            let mut theta = [
+                load(&self.theta1[lanes.clone()]),
+                load(&self.theta2[lanes.clone()]),
+            ];
+            let mut omega = [
+                load(&self.omega1[lanes.clone()]),
+                load(&self.omega2[lanes.clone()]),
+            ];
+            step_lane(terms, order, dt, &mut theta, &mut omega);
+            self.theta1[lanes.clone()].copy_from_slice(&theta[0].to_array());
+            self.theta2[lanes.clone()].copy_from_slice(&theta[1].to_array());
+            self.omega1[lanes.clone()].copy_from_slice(&omega[0].to_array());
+            self.omega2[lanes].copy_from_slice(&omega[1].to_array());
+        }
+        whole
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pendulum::Bob;
+
+    /// A chaotic two-bob chain, the second rod swung well out.
+    fn chain() -> Pendulum {
+        Pendulum::new(vec![
+            Bob::new(1.0, 1.0, 2.0, 0.0),
+            Bob::new(0.8, 1.5, 3.5, 1.0),
+        ])
+    }
+
+    /// RMS distance of the copies' angles from `reference`'s.
+    fn spread(reference: &Pendulum, angles: impl Iterator<Item = [f64; 2]>) -> f64 {
+        let (mut sum, mut count) = (0.0, 0.0);
+        for [t1, t2] in angles {
+            sum += (t1 - reference.bobs[0].theta).powi(2) + (t2 - reference.bobs[1].theta).powi(2);
+            count += 1.0;
+        }
+        (sum / count).sqrt()
+    }
+
+    #[test]
+    fn single_precision_copies_follow_and_diverge_like_double_ones() {
+        let reference = chain();
+        let mut batch = Batch2::new(&reference).unwrap();
+        let mut members = Vec::new();
+        for k in 0..45 {
+            let mut member = reference.clone();
+            member.bobs[0].theta += 1e-6 * f64::from(k - 22);
+            member.bobs[1].theta -= 1e-6 * f64::from(k % 7);
+            batch.push(&member);
+            members.push(member);
+        }
+        assert_eq!(batch.len(), 45);
+
+        // over a short horizon a copy stays on its f64 twin
+        let mut short = batch.clone();
+        let mut twin = short.get(21).unwrap();
+        for _ in 0..200 {
+            short.step(1e-3);
+            twin.step(1e-3);
+        }
+        let copy = short.get(21).unwrap();
+        for (a, b) in copy.bobs.iter().zip(&twin.bobs) {
+            assert!((a.theta - b.theta).abs() < 1e-4, "{} {}", a.theta, b.theta);
+        }
+        let tip = short.tips().nth(21).unwrap();
+        assert!((tip.x - copy.bobs[1].coordinate.x).abs() < 1e-12);
+
+        // and the cloud fans out at the same mean rate, in e-folds a second
+        let (steps, dt) = (5_000, 1e-3);
+        let angles = |m: &Pendulum| [m.bobs[0].theta, m.bobs[1].theta];
+        let batched = |batch: &Batch2| -> Vec<[f64; 2]> {
+            (0..batch.len())
+                .map(|i| angles(&batch.get(i).unwrap()))
+                .collect()
+        };
+        let mut reference = reference.clone();
+        let before = (
+            spread(&reference, batched(&batch).into_iter()),
+            spread(&reference, members.iter().map(angles)),
+        );
+        for _ in 0..steps {
+            reference.step(dt);
+            batch.step(dt);
+            for member in &mut members {
+                member.step(dt);
+            }
+        }
+        let rate = |before: f64, after: f64| (after / before).ln() / (steps as f64 * dt);
+        let single = rate(before.0, spread(&reference, batched(&batch).into_iter()));
+        let double = rate(before.1, spread(&reference, members.iter().map(angles)));
+        assert!(double > 0.25, "{double}");
+        assert!((single - double).abs() < 0.25 * double, "{single} {double}");
+
+        // only two free bobs are batched
+        assert!(Batch2::new(&Pendulum::default()).is_none());
+        let mut locked = chain();
+        locked.bobs[1].locked = true;
+        assert!(Batch2::new(&locked).is_none());
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pendulum::INTEGRATOR;
+
+/// Which of θ and ω a symplectic Euler step moves first. Either way the
+/// step is first order; the two are each other's adjoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UpdateOrder {
+    /// ω from the accelerations at the current pose, then θ with the new ω.
+    #[default]
+    VelocityFirst,
+    /// θ with the current ω, then ω from the accelerations at the new pose.
+    PositionFirst,
+}
+
+impl UpdateOrder {
+    /// The orders' names as they serialize, the default first.
+    pub const NAMES: [&'static str; 2] = ["velocityFirst", "positionFirst"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::VelocityFirst => Self::NAMES[0],
+            Self::PositionFirst => Self::NAMES[1],
+        }
+    }
+}
+
+/// How `Pendulum::step` integrates, with the method's settings. Serialized
+/// tagged by its id.
+///
+/// ```
+/// use pendulum_core::{Bob, Integrator, Pendulum, UpdateOrder};
+///
+/// let mut pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, 2.0, 0.0)]);
+/// pendulum.integrator = Integrator::SymplecticEuler {
+///     order: UpdateOrder::PositionFirst,
+/// };
+/// // from rest, the angle only moves from the second step on
+/// pendulum.step(0.01);
+/// assert_eq!(pendulum.bobs[0].theta, 2.0);
+/// assert_eq!(pendulum.integrator.id(), "symplecticEuler");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "id")]
+pub enum Integrator {
+    SymplecticEuler { order: UpdateOrder },
+}
+
+impl Default for Integrator {
+    fn default() -> Self {
+        Self::SymplecticEuler {
+            order: UpdateOrder::default(),
+        }
+    }
+}
+
+impl Integrator {
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::SymplecticEuler { .. } => INTEGRATOR,
+        }
+    }
+}
@@ -0,0 +1,13 @@
+mod batch;
+mod integrator;
+mod modes;
+mod pendulum;
+
+pub use batch::Batch2;
+pub use integrator::{Integrator, UpdateOrder};
+pub use modes::{LinearSolution, NormalModes, HANGING_THETA};
+pub use pendulum::{
+    minimum, unwrap_near, validate_parameter, wrap_angle, Bob, Coordinate, DynamicsTerms,
+    InvalidBob, Pendulum, DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA,
+    GRAVITATIONAL_ACCELERATION, INTEGRATOR, MIN_LENGTH_ROD, MIN_MASS,
+};
@@ -0,0 +1,250 @@
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+use crate::pendulum::{wrap_angle, Pendulum, GRAVITATIONAL_ACCELERATION};
+
+/// Angle of every joint at the stable equilibrium. The gravity term in
+/// `Pendulum::gravity` makes θ = 0 the inverted configuration, so the chain
+/// hangs straight down at θ = π.
+pub const HANGING_THETA: f64 = PI;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalModes {
+    /// Angular frequencies in rad/s, ascending.
+    pub frequencies: Vec<f64>,
+    /// One shape per frequency: the joint-angle offsets from the hanging
+    /// equilibrium, scaled so the largest component is 1.
+    pub shapes: Vec<Vec<f64>>,
+}
+
+impl Pendulum {
+    /// Mass matrix with every joint at the same angle, where cos(θi - θj) = 1.
+    fn equilibrium_mass_matrix(&self) -> DMatrix<f64> {
+        let n = self.n();
+        let suffix = self.suffix_masses();
+        DMatrix::from_fn(n, n, |i, j| {
+            self.bobs[i].length_rod * self.bobs[j].length_rod * suffix[std::cmp::max(i, j)]
+        })
+    }
+
+    /// Hessian of the potential at the hanging equilibrium. The potential
+    /// only couples each angle to itself, so the matrix is diagonal.
+    fn stiffness_matrix(&self) -> DMatrix<f64> {
+        let n = self.n();
+        let suffix = self.suffix_masses();
+        let mut k = DMatrix::<f64>::zeros(n, n);
+        for i in 0..n {
+            k[(i, i)] = -self.bobs[i].length_rod
+                * suffix[i]
+                * GRAVITATIONAL_ACCELERATION
+                * HANGING_THETA.cos();
+        }
+        k
+    }
+
+    /// Solves K v = ω² M v for the chain linearized about the hanging
+    /// equilibrium. Returns `None` when the mass matrix is not positive
+    /// definite (a zero mass or length somewhere in the chain).
+    pub fn normal_modes(&self) -> Option<NormalModes> {
+        let n = self.n();
+        // nalgebra's decompositions panic on 0×0 matrices
+        if n == 0 {
+            return Some(NormalModes {
+                frequencies: Vec::new(),
+                shapes: Vec::new(),
+            });
+        }
+        let m = self.equilibrium_mass_matrix();
+        let k = self.stiffness_matrix();
+
+        // reduce to a standard symmetric problem: A = L⁻¹ K L⁻ᵀ with M = L Lᵀ
+        let l = m.cholesky()?.l();
+        let l_inv_k = l.solve_lower_triangular(&k)?;
+        let a = l.solve_lower_triangular(&l_inv_k.transpose())?;
+        let a = (&a + a.transpose()) * 0.5;
+        let eigen = SymmetricEigen::new(a);
+
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&i, &j| eigen.eigenvalues[i].total_cmp(&eigen.eigenvalues[j]));
+
+        let lt = l.transpose();
+        let mut frequencies = Vec::with_capacity(n);
+        let mut shapes = Vec::with_capacity(n);
+        for r in order {
+            frequencies.push(eigen.eigenvalues[r].max(0.0).sqrt());
+            let v = lt.solve_upper_triangular(&eigen.eigenvectors.column(r).into_owned())?;
+            let peak = v
+                .iter()
+                .fold(0.0_f64, |acc, x| if x.abs() > acc.abs() { *x } else { acc });
+            shapes.push(v.iter().map(|x| x / peak).collect());
+        }
+
+        Some(NormalModes {
+            frequencies,
+            shapes,
+        })
+    }
+
+    /// Puts the chain at rest in the given mode shape, each joint displaced
+    /// from the hanging equilibrium by `amplitude` times its shape component.
+    pub fn excite_mode(&mut self, shape: &[f64], amplitude: f64) {
+        for (bob, s) in self.bobs.iter_mut().zip(shape) {
+            bob.theta = HANGING_THETA + amplitude * s;
+            bob.omega = 0.0;
+        }
+    }
+}
+
+/// Offset of `theta` from the hanging equilibrium, wrapped to (-π, π].
+fn offset_from_hanging(theta: f64) -> f64 {
+    wrap_angle(theta - HANGING_THETA)
+}
+
+/// Closed-form solution of the linearized dynamics: a superposition of
+/// normal modes fitted to the state at `t0`. Evaluating it never integrates
+/// anything, so it carries no numerical drift.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinearSolution {
+    t0: f64,
+    modes: NormalModes,
+    /// Modal displacements at `t0`.
+    q0: Vec<f64>,
+    /// Modal velocities at `t0`.
+    qd0: Vec<f64>,
+}
+
+impl LinearSolution {
+    /// Decomposes the pendulum's current state into modal coordinates.
+    pub fn fit(pendulum: &Pendulum, t0: f64) -> Option<Self> {
+        let n = pendulum.n();
+        let modes = pendulum.normal_modes()?;
+        if n == 0 {
+            return Some(Self {
+                t0,
+                modes,
+                q0: Vec::new(),
+                qd0: Vec::new(),
+            });
+        }
+        let shapes = DMatrix::from_fn(n, n, |i, r| modes.shapes[r][i]);
+        let lu = shapes.lu();
+        let offsets = DVector::from_iterator(
+            n,
+            pendulum.bobs.iter().map(|b| offset_from_hanging(b.theta)),
+        );
+        let omegas = DVector::from_iterator(n, pendulum.bobs.iter().map(|b| b.omega));
+        let q0 = lu.solve(&offsets)?;
+        let qd0 = lu.solve(&omegas)?;
+        Some(Self {
+            t0,
+            modes,
+            q0: q0.iter().copied().collect(),
+            qd0: qd0.iter().copied().collect(),
+        })
+    }
+
+    pub fn n(&self) -> usize {
+        self.q0.len()
+    }
+
+    /// Joint angles predicted by the linear model at sim time `t`.
+    pub fn thetas_at(&self, t: f64) -> Vec<f64> {
+        let tau = t - self.t0;
+        let mut thetas = vec![HANGING_THETA; self.n()];
+        for (r, shape) in self.modes.shapes.iter().enumerate() {
+            let w = self.modes.frequencies[r];
+            let q = if w > 0.0 {
+                self.q0[r] * (w * tau).cos() + self.qd0[r] / w * (w * tau).sin()
+            } else {
+                self.q0[r] + self.qd0[r] * tau
+            };
+            for (theta, s) in thetas.iter_mut().zip(shape) {
+                *theta += q * s;
+            }
+        }
+        thetas
+    }
+
+    /// Wrapped angular difference between each bob and its linear prediction.
+    pub fn discrepancy(&self, pendulum: &Pendulum, t: f64) -> Vec<f64> {
+        self.thetas_at(t)
+            .iter()
+            .zip(&pendulum.bobs)
+            .map(|(linear, bob)| offset_from_hanging(bob.theta - linear + HANGING_THETA))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pendulum::Bob;
+
+    #[test]
+    fn equal_double_pendulum_matches_textbook_frequencies() {
+        let l = 1.5;
+        let pendulum = Pendulum::new(vec![
+            Bob::new(l, 2.0, HANGING_THETA, 0.0),
+            Bob::new(l, 2.0, HANGING_THETA, 0.0),
+        ]);
+        let modes = pendulum.normal_modes().unwrap();
+
+        let g = GRAVITATIONAL_ACCELERATION;
+        let slow = ((2.0 - 2.0_f64.sqrt()) * g / l).sqrt();
+        let fast = ((2.0 + 2.0_f64.sqrt()) * g / l).sqrt();
+        assert!((modes.frequencies[0] - slow).abs() < 1e-9);
+        assert!((modes.frequencies[1] - fast).abs() < 1e-9);
+
+        // in-phase and anti-phase shapes with the √2 amplitude ratio
+        let ratio0 = modes.shapes[0][0] / modes.shapes[0][1];
+        let ratio1 = modes.shapes[1][0] / modes.shapes[1][1];
+        assert!((ratio0 - 1.0 / 2.0_f64.sqrt()).abs() < 1e-9);
+        assert!((ratio1 + 1.0 / 2.0_f64.sqrt()).abs() < 1e-9);
+    }
+
+    fn max_discrepancy(initial_offset: f64) -> f64 {
+        let mut pendulum = Pendulum::new(vec![
+            Bob::new(1.0, 1.0, HANGING_THETA + initial_offset, 0.0),
+            Bob::new(1.0, 1.0, HANGING_THETA + initial_offset, 0.0),
+        ]);
+        let linear = LinearSolution::fit(&pendulum, 0.0).unwrap();
+        let dt = 1e-4;
+        let mut worst: f64 = 0.0;
+        for step in 1..=30_000 {
+            pendulum.step(dt);
+            let t = step as f64 * dt;
+            for d in linear.discrepancy(&pendulum, t) {
+                worst = worst.max(d.abs());
+            }
+        }
+        worst
+    }
+
+    #[test]
+    fn linear_solution_tracks_small_oscillations_only() {
+        assert!(max_discrepancy(0.05) < 2e-3);
+        assert!(max_discrepancy(1.0) > 0.2);
+    }
+
+    #[test]
+    fn linear_solution_reproduces_fitted_state() {
+        let pendulum = Pendulum::default();
+        let linear = LinearSolution::fit(&pendulum, 3.0).unwrap();
+        for d in linear.discrepancy(&pendulum, 3.0) {
+            assert!(d.abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn excited_mode_starts_at_rest() {
+        let mut pendulum = Pendulum::default();
+        let modes = pendulum.normal_modes().unwrap();
+        pendulum.excite_mode(&modes.shapes[1], 0.1);
+        for (bob, s) in pendulum.bobs.iter().zip(&modes.shapes[1]) {
+            assert_eq!(bob.theta, HANGING_THETA + 0.1 * s);
+            assert_eq!(bob.omega, 0.0);
+        }
+    }
+}
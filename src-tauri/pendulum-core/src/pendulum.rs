@@ -0,0 +1,788 @@
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+use serde::{Deserialize, Serialize};
+use std::{f64::consts::PI, fmt};
+
+use crate::integrator::{Integrator, UpdateOrder};
+
+/// In m/s². Lengths and positions are in meters and masses in kilograms,
+/// so energies come out in joules and tensions in newtons.
+pub const GRAVITATIONAL_ACCELERATION: f64 = 9.81;
+
+/// What a bob added to an empty chain is made of.
+pub const DEFAULT_LENGTH_ROD: f64 = 1.2;
+pub const DEFAULT_MASS: f64 = 1.0;
+pub const DEFAULT_THETA: f64 = PI / 10.0;
+
+/// Shortest rod a bob can have. A zero length makes the mass matrix
+/// singular, and a much shorter rod swings faster than any usable step can
+/// follow.
+pub const MIN_LENGTH_ROD: f64 = 0.01;
+/// Lightest a bob can be, which keeps the mass matrix well enough
+/// conditioned against bobs of the default mass to solve.
+pub const MIN_MASS: f64 = 1e-6;
+
+/// Id of the integration scheme `Pendulum::step` uses unless told otherwise.
+pub const INTEGRATOR: &str = "symplecticEuler";
+
+/// Wraps an angle to (-π, π].
+pub fn wrap_angle(theta: f64) -> f64 {
+    let wrapped = theta.rem_euclid(2.0 * PI);
+    if wrapped > PI {
+        wrapped - 2.0 * PI
+    } else {
+        wrapped
+    }
+}
+
+/// `theta` moved by whole turns to within half a turn of `reference`, so
+/// an angle given either wrapped or unbounded lands on the same turn as the
+/// one it replaces. Left exactly as is when it already is there.
+pub fn unwrap_near(theta: f64, reference: f64) -> f64 {
+    let turns = ((theta - reference) / (2.0 * PI)).round();
+    theta - 2.0 * PI * turns
+}
+
+/// A point in the plane, in meters, with y up.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Coordinate {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Coordinate {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+/// One rod and the mass at its end. `theta` is the rod's angle from
+/// straight up, clockwise, and is never wrapped, so it counts the turns the
+/// rod has made; `omega` is its rate of change.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bob {
+    pub length_rod: f64,
+    pub mass: f64,
+    pub theta: f64,
+    pub omega: f64,
+    /// Where the bob is, as `Pendulum::update_coordinates` last put it.
+    #[serde(default)]
+    pub coordinate: Coordinate,
+    /// Handed out by whoever builds the chain, so the bob can be told apart
+    /// once indices shift. The physics never reads it.
+    #[serde(default)]
+    pub id: u64,
+    /// Held at its angle, at rest, while the bobs below it keep swinging.
+    #[serde(default)]
+    pub locked: bool,
+}
+
+impl Bob {
+    pub fn new(length_rod: f64, mass: f64, theta: f64, omega: f64) -> Self {
+        Self {
+            length_rod,
+            mass,
+            theta,
+            omega,
+            coordinate: Coordinate::default(),
+            id: 0,
+            locked: false,
+        }
+    }
+}
+
+/// Why a bob's parameters were refused; `field` is the parameter's name.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "constraint"
+)]
+pub enum InvalidBob {
+    /// A value that is NaN or infinite.
+    NotFinite { field: &'static str, value: f64 },
+    /// A rod length or mass under its minimum, zero and negatives included.
+    BelowMinimum {
+        field: &'static str,
+        value: f64,
+        min: f64,
+    },
+}
+
+impl fmt::Display for InvalidBob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFinite { field, value } => write!(f, "{field} must be finite, got {value}"),
+            Self::BelowMinimum { field, value, min } => {
+                write!(f, "{field} must be at least {min}, got {value}")
+            }
+        }
+    }
+}
+
+/// Smallest value the bob parameter `field` may take, if it has one.
+pub fn minimum(field: &str) -> Option<f64> {
+    match field {
+        "length_rod" => Some(MIN_LENGTH_ROD),
+        "mass" => Some(MIN_MASS),
+        _ => None,
+    }
+}
+
+/// Checks one bob parameter: every one must be finite, and rod lengths and
+/// masses at least their minimum. Every way of setting a bob goes through
+/// here.
+pub fn validate_parameter(field: &'static str, value: f64) -> Result<(), InvalidBob> {
+    if !value.is_finite() {
+        return Err(InvalidBob::NotFinite { field, value });
+    }
+    match minimum(field) {
+        Some(min) if value < min => Err(InvalidBob::BelowMinimum { field, value, min }),
+        _ => Ok(()),
+    }
+}
+
+/// The terms of M θ̈ + C + G = 0 for one instant.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicsTerms {
+    pub n: usize,
+    /// The n×n mass matrix, row-major.
+    pub mass_matrix: Vec<f64>,
+    pub coriolis: Vec<f64>,
+    pub gravity: Vec<f64>,
+    /// θ̈ solved from the other terms and the joint friction, if any.
+    pub acceleration: Vec<f64>,
+}
+
+/// A chain of bobs hanging from a pivot, the first bob's rod from the
+/// pivot and every other's from the bob before it.
+///
+/// ```
+/// use pendulum_core::{Bob, Pendulum};
+///
+/// let mut pendulum = Pendulum::new(vec![
+///     Bob::new(1.0, 1.0, 2.0, 0.0),
+///     Bob::new(1.0, 1.0, 2.5, 0.0),
+/// ]);
+/// let energy = pendulum.total_energy();
+/// for _ in 0..1_000 {
+///     assert!(pendulum.step(1e-3));
+/// }
+/// // symplectic Euler keeps the energy close, without drifting
+/// assert!((pendulum.total_energy() - energy).abs() < 0.05 * energy.abs());
+/// let tip = pendulum.bobs[1].coordinate;
+/// assert!(tip.x.hypot(tip.y) <= 2.0 + 1e-9);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pendulum {
+    pub bobs: Vec<Bob>,
+    /// Where the first rod hangs from; the bobs' coordinates are in the same
+    /// frame. Only their positions depend on it, never the motion.
+    #[serde(default)]
+    pub pivot: Coordinate,
+    /// How `step` integrates; copies, like predictions, step the same way.
+    #[serde(default)]
+    pub integrator: Integrator,
+    /// Viscous friction in every joint, in N·m·s/rad: each joint resists
+    /// its two rods turning against each other, the first one its rod
+    /// turning against the pivot. Zero, the default, conserves energy.
+    #[serde(default)]
+    pub damping: f64,
+}
+
+impl Pendulum {
+    /// The chain of `bobs`, from a pivot at the origin, without friction.
+    /// Their coordinates are only filled in by the first step, or by
+    /// `update_coordinates`.
+    pub fn new(bobs: Vec<Bob>) -> Self {
+        Self {
+            bobs,
+            pivot: Coordinate::default(),
+            integrator: Integrator::default(),
+            damping: 0.0,
+        }
+    }
+
+    pub fn n(&self) -> usize {
+        self.bobs.len()
+    }
+
+    pub fn mass_matrix(&self) -> DMatrix<f64> {
+        let n = self.n();
+        let mut mtx = DMatrix::<f64>::zeros(n, n);
+
+        for i in 0..n {
+            let li = self.bobs[i].length_rod;
+            for j in 0..n {
+                let lj = self.bobs[j].length_rod;
+                let theta_diff = self.bobs[i].theta - self.bobs[j].theta;
+                let mut sum_m = 0.0;
+                for k in std::cmp::max(i, j)..n {
+                    sum_m += self.bobs[k].mass;
+                }
+                mtx[(i, j)] = li * lj * sum_m * theta_diff.cos();
+            }
+        }
+        mtx
+    }
+
+    pub fn suffix_masses(&self) -> Vec<f64> {
+        let n = self.n();
+        let mut s = vec![0.0; n];
+        let mut acc = 0.0;
+        for i in (0..n).rev() {
+            acc += self.bobs[i].mass;
+            s[i] = acc;
+        }
+        s
+    }
+
+    fn d_mass_matrix_dtheta(&self, i: usize, j: usize, k: usize, suffix: &[f64]) -> f64 {
+        let li = self.bobs[i].length_rod;
+        let lj = self.bobs[j].length_rod;
+        let s_ij = suffix[std::cmp::max(i, j)];
+        let theta_diff = self.bobs[i].theta - self.bobs[j].theta;
+        let delta = (if i == k { 1.0 } else { 0.0 }) - (if j == k { 1.0 } else { 0.0 });
+        -s_ij * li * lj * theta_diff.sin() * delta
+    }
+
+    pub fn coriolis(&self) -> DVector<f64> {
+        let n = self.n();
+        let mut c = DVector::<f64>::zeros(n);
+        let suffix = self.suffix_masses();
+
+        for i in 0..n {
+            let mut ci = 0.0;
+            for j in 0..n {
+                for k in 0..n {
+                    let dm_ik_dth_j = self.d_mass_matrix_dtheta(i, k, j, &suffix);
+                    let dm_ij_dth_k = self.d_mass_matrix_dtheta(i, j, k, &suffix);
+                    let dm_jk_dth_i = self.d_mass_matrix_dtheta(j, k, i, &suffix);
+                    let gamma = 0.5 * (dm_ik_dth_j + dm_ij_dth_k - dm_jk_dth_i);
+                    ci += gamma * self.bobs[j].omega * self.bobs[k].omega;
+                }
+            }
+            c[i] = ci;
+        }
+        c
+    }
+
+    pub fn gravity(&self) -> DVector<f64> {
+        let n = self.n();
+        let suffix = self.suffix_masses();
+        let mut g_vec = DVector::<f64>::zeros(n);
+
+        for i in 0..n {
+            let li = self.bobs[i].length_rod;
+            let s_i = suffix[i];
+            // ∂U/∂θ_i = - l_i * sin(theta_i) * (sum_{k>=i} m_k * g)
+            g_vec[i] = -li * self.bobs[i].theta.sin() * (s_i * GRAVITATIONAL_ACCELERATION);
+        }
+        g_vec
+    }
+
+    /// D in M θ̈ + C + G + D = 0: the joint friction's generalized forces,
+    /// from the dissipation function R = ½ c Σ (ω_i - ω_{i-1})² with ω_0 = 0
+    /// for the pivot, as D_i = ∂R/∂ω_i.
+    pub fn damping_forces(&self) -> DVector<f64> {
+        let n = self.n();
+        let omega = |i: usize| self.bobs[i].omega;
+        // relative turning rate of joint i, the one above bob i
+        let joint = |i: usize| omega(i) - if i == 0 { 0.0 } else { omega(i - 1) };
+        DVector::from_fn(n, |i, _| {
+            let below = if i + 1 < n { joint(i + 1) } else { 0.0 };
+            self.damping * (joint(i) - below)
+        })
+    }
+
+    /// C + D, every force that depends on the angular velocities; both are
+    /// zero at rest.
+    fn velocity_forces(&self) -> DVector<f64> {
+        if self.damping == 0.0 {
+            self.coriolis()
+        } else {
+            self.coriolis() + self.damping_forces()
+        }
+    }
+
+    /// T = ½ ωᵀ M ω.
+    pub fn kinetic_energy(&self) -> f64 {
+        let omega = DVector::from_iterator(self.n(), self.bobs.iter().map(|b| b.omega));
+        0.5 * omega.dot(&(self.mass_matrix() * &omega))
+    }
+
+    /// U = g Σ m_k y_k, written per joint so it matches `gravity` (its gradient).
+    pub fn potential_energy(&self) -> f64 {
+        let suffix = self.suffix_masses();
+        self.bobs
+            .iter()
+            .zip(suffix)
+            .map(|(bob, s)| GRAVITATIONAL_ACCELERATION * s * bob.length_rod * bob.theta.cos())
+            .sum()
+    }
+
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy() + self.potential_energy()
+    }
+
+    /// Power the joint friction takes out, ωᵀ D = 2R, in watts.
+    pub fn dissipated_power(&self) -> f64 {
+        if self.damping == 0.0 {
+            return 0.0;
+        }
+        let omega = DVector::from_iterator(self.n(), self.bobs.iter().map(|b| b.omega));
+        omega.dot(&self.damping_forces())
+    }
+
+    /// Generalized momenta p = M ω, one per joint.
+    pub fn momenta(&self) -> Vec<f64> {
+        let omega = DVector::from_iterator(self.n(), self.bobs.iter().map(|b| b.omega));
+        (self.mass_matrix() * omega).as_slice().to_vec()
+    }
+
+    /// Tension in each rod, positive when pulling, given the angular
+    /// accelerations. Rod i carries everything beyond it: its tension is the
+    /// along-rod part of Σ_{k≥i} m_k (a_k + g ŷ).
+    pub fn rod_tensions(&self, alphas: &[f64]) -> Vec<f64> {
+        // Cartesian acceleration of each bob, summed from the root
+        let mut ax = 0.0;
+        let mut ay = 0.0;
+        let accelerations: Vec<(f64, f64)> = self
+            .bobs
+            .iter()
+            .zip(alphas)
+            .map(|(bob, alpha)| {
+                let (sin, cos) = bob.theta.sin_cos();
+                let w2 = bob.omega * bob.omega;
+                ax += bob.length_rod * (alpha * cos - w2 * sin);
+                ay += bob.length_rod * (-alpha * sin - w2 * cos);
+                (ax, ay)
+            })
+            .collect();
+        let mut fx = 0.0;
+        let mut fy = 0.0;
+        let mut tensions = vec![0.0; self.n()];
+        for i in (0..self.n()).rev() {
+            let bob = &self.bobs[i];
+            fx += bob.mass * accelerations[i].0;
+            fy += bob.mass * (accelerations[i].1 + GRAVITATIONAL_ACCELERATION);
+            let (sin, cos) = bob.theta.sin_cos();
+            tensions[i] = -(fx * sin + fy * cos);
+        }
+        tensions
+    }
+
+    /// Ratio of the largest to the smallest eigenvalue of the mass matrix.
+    /// Grows without bound as the chain approaches a singular configuration.
+    pub fn mass_matrix_condition(&self) -> f64 {
+        // nothing to be ill-conditioned, and the decomposition would panic
+        if self.n() == 0 {
+            return 1.0;
+        }
+        let eigenvalues = SymmetricEigen::new(self.mass_matrix()).eigenvalues;
+        let max = eigenvalues.iter().fold(0.0_f64, |acc, &x| acc.max(x.abs()));
+        let min = eigenvalues
+            .iter()
+            .fold(f64::INFINITY, |acc, &x| acc.min(x.abs()));
+        if min > 0.0 {
+            max / min
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    /// Positions the bobs would have at the given joint angles.
+    pub fn positions_for(&self, thetas: &[f64]) -> Vec<Coordinate> {
+        let mut cum_x = self.pivot.x;
+        let mut cum_y = self.pivot.y;
+        self.bobs
+            .iter()
+            .zip(thetas)
+            .map(|(bob, theta)| {
+                cum_x += bob.length_rod * theta.sin();
+                cum_y += bob.length_rod * theta.cos();
+                Coordinate::new(cum_x, cum_y)
+            })
+            .collect()
+    }
+
+    /// Joint angles that put the bobs as near `points` as the rod lengths
+    /// allow, one point per bob. Each rod starts out aimed from the previous
+    /// point at its own, keeping the previous rod's direction where the two
+    /// points coincide (the current angle for the first), and a few damped
+    /// Gauss–Newton steps then bring the total squared miss down.
+    pub fn fit_pose(&self, points: &[Coordinate]) -> Vec<f64> {
+        let n = self.n().min(points.len());
+        let mut thetas = Vec::with_capacity(n);
+        let mut parent = self.pivot;
+        for (bob, point) in self.bobs.iter().zip(points) {
+            let (dx, dy) = (point.x - parent.x, point.y - parent.y);
+            let previous = thetas.last().copied().unwrap_or(bob.theta);
+            thetas.push(if dx == 0.0 && dy == 0.0 {
+                previous
+            } else {
+                dx.atan2(dy)
+            });
+            parent = *point;
+        }
+        // nalgebra's LU solve panics on a 0×0 system
+        if n == 0 {
+            return thetas;
+        }
+
+        let miss = |thetas: &[f64]| -> DVector<f64> {
+            let positions = self.positions_for(thetas);
+            DVector::from_iterator(
+                2 * n,
+                positions
+                    .iter()
+                    .zip(points)
+                    .flat_map(|(p, q)| [p.x - q.x, p.y - q.y]),
+            )
+        };
+        let mut residual = miss(&thetas);
+        let mut damping = 1e-3;
+        for _ in 0..50 {
+            // ∂x_k/∂θ_i = l_i cos θ_i, ∂y_k/∂θ_i = -l_i sin θ_i, for i ≤ k
+            let jacobian = DMatrix::from_fn(2 * n, n, |row, i| {
+                let (k, bob) = (row / 2, &self.bobs[i]);
+                match (i <= k, row % 2) {
+                    (false, _) => 0.0,
+                    (true, 0) => bob.length_rod * thetas[i].cos(),
+                    (true, _) => -bob.length_rod * thetas[i].sin(),
+                }
+            });
+            let jt = jacobian.transpose();
+            let mut normal = &jt * &jacobian;
+            for i in 0..n {
+                normal[(i, i)] *= 1.0 + damping;
+            }
+            let Some(step) = normal.lu().solve(&(-(&jt * &residual))) else {
+                break;
+            };
+            let trial: Vec<f64> = thetas.iter().zip(step.iter()).map(|(t, d)| t + d).collect();
+            let trial_residual = miss(&trial);
+            if trial_residual.norm_squared() < residual.norm_squared() {
+                let converged = residual.norm_squared() - trial_residual.norm_squared()
+                    <= 1e-15 * residual.norm_squared();
+                thetas = trial;
+                residual = trial_residual;
+                damping *= 0.1;
+                if converged {
+                    break;
+                }
+            } else {
+                damping *= 10.0;
+                if damping > 1e6 {
+                    break;
+                }
+            }
+        }
+        thetas
+    }
+
+    /// Linear velocity of each bob, summed from the root like the positions.
+    pub fn velocities(&self) -> Vec<Coordinate> {
+        let mut vx = 0.0;
+        let mut vy = 0.0;
+        self.bobs
+            .iter()
+            .map(|bob| {
+                let (sin, cos) = bob.theta.sin_cos();
+                vx += bob.length_rod * bob.omega * cos;
+                vy -= bob.length_rod * bob.omega * sin;
+                Coordinate::new(vx, vy)
+            })
+            .collect()
+    }
+
+    fn solve_accelerations(
+        &self,
+        m: &DMatrix<f64>,
+        c: &DVector<f64>,
+        g: &DVector<f64>,
+    ) -> DVector<f64> {
+        self.try_solve_constrained(m, c, g, &[]).unwrap_or_else(|| {
+            // fallback: if matrix singular, zero accelerations
+            DVector::zeros(m.nrows())
+        })
+    }
+
+    /// θ̈ with the locked joints held still and the `prescribed` ones driven,
+    /// as `try_solve_prescribed`; a lock wins over a prescription.
+    fn try_solve_constrained(
+        &self,
+        m: &DMatrix<f64>,
+        c: &DVector<f64>,
+        g: &DVector<f64>,
+        prescribed: &[(usize, f64)],
+    ) -> Option<DVector<f64>> {
+        let locked = |i: usize| self.bobs.get(i).is_some_and(|b| b.locked);
+        let driven: Vec<(usize, f64)> = (prescribed.iter().copied())
+            .filter(|&(i, _)| !locked(i))
+            .chain((0..self.n()).filter(|&i| locked(i)).map(|i| (i, 0.0)))
+            .collect();
+        if driven.is_empty() {
+            Self::try_solve_accelerations(m, c, g)
+        } else {
+            Self::try_solve_prescribed(m, c, g, &driven)
+        }
+    }
+
+    /// θ̈, or `None` when the mass matrix is singular.
+    fn try_solve_accelerations(
+        m: &DMatrix<f64>,
+        c: &DVector<f64>,
+        g: &DVector<f64>,
+    ) -> Option<DVector<f64>> {
+        // nalgebra's LU solve panics on a 0×0 system
+        if m.is_empty() {
+            return Some(DVector::zeros(0));
+        }
+        // Equations: M * theta_dd + C + G = 0  => theta_dd = - M^{-1} (C + G)
+        let rhs = -(c + g);
+        m.clone().lu().solve(&rhs)
+    }
+
+    /// θ̈ with some of it given: only the rows of the free joints are solved,
+    /// M_ff θ̈_f = -(C + G)_f - M_fd θ̈_d, so the driven joints' motion still
+    /// acts on the rest. `None` when M_ff is singular.
+    fn try_solve_prescribed(
+        m: &DMatrix<f64>,
+        c: &DVector<f64>,
+        g: &DVector<f64>,
+        prescribed: &[(usize, f64)],
+    ) -> Option<DVector<f64>> {
+        let n = m.nrows();
+        let mut a = DVector::zeros(n);
+        let mut driven = vec![false; n];
+        for &(i, alpha) in prescribed {
+            a[i] = alpha;
+            driven[i] = true;
+        }
+        let free: Vec<usize> = (0..n).filter(|&i| !driven[i]).collect();
+        if free.is_empty() {
+            return Some(a);
+        }
+        // a is zero on the free joints, so M a is M_:d θ̈_d
+        let rhs = -(c + g) - m * &a;
+        let m_ff = DMatrix::from_fn(free.len(), free.len(), |r, k| m[(free[r], free[k])]);
+        let rhs_f = DVector::from_fn(free.len(), |r, _| rhs[free[r]]);
+        let a_f = m_ff.lu().solve(&rhs_f)?;
+        for (r, &i) in free.iter().enumerate() {
+            a[i] = a_f[r];
+        }
+        Some(a)
+    }
+
+    /// Angular accelerations at the current state.
+    pub fn accelerations(&self) -> Vec<f64> {
+        let c = self.velocity_forces();
+        let a = self.solve_accelerations(&self.mass_matrix(), &c, &self.gravity());
+        a.as_slice().to_vec()
+    }
+
+    /// Every term of the equations of motion at the current state.
+    pub fn dynamics_terms(&self) -> DynamicsTerms {
+        let m = self.mass_matrix();
+        let c = self.coriolis();
+        let g = self.gravity();
+        let a = self.solve_accelerations(&m, &self.velocity_forces(), &g);
+        DynamicsTerms {
+            n: self.n(),
+            mass_matrix: m.transpose().as_slice().to_vec(),
+            coriolis: c.as_slice().to_vec(),
+            gravity: g.as_slice().to_vec(),
+            acceleration: a.as_slice().to_vec(),
+        }
+    }
+
+    /// Advances the state by `dt`, the locked joints staying put while the
+    /// rest of the chain swings from them. Returns `false` when the mass
+    /// matrix was singular and the step was taken with zero accelerations.
+    pub fn step(&mut self, dt: f64) -> bool {
+        self.step_prescribed(dt, &[])
+    }
+
+    /// `step` with the joints in `prescribed`, (index, θ̈) pairs, driven at
+    /// the given accelerations; the other joints move under the forces the
+    /// driven ones pass along the chain.
+    pub fn step_prescribed(&mut self, dt: f64, prescribed: &[(usize, f64)]) -> bool {
+        let n = self.n();
+        for bob in self.bobs.iter_mut().filter(|b| b.locked) {
+            bob.omega = 0.0;
+        }
+        let Integrator::SymplecticEuler { order } = self.integrator;
+        if order == UpdateOrder::PositionFirst {
+            for bob in &mut self.bobs {
+                bob.theta += bob.omega * dt;
+            }
+        }
+        let m = self.mass_matrix();
+        let c = self.velocity_forces();
+        let g = self.gravity();
+
+        // solve for accelerations
+        let solved = self.try_solve_constrained(&m, &c, &g, prescribed);
+        let regular = solved.is_some();
+        let a = solved.unwrap_or_else(|| DVector::zeros(n));
+
+        // symplectic Euler integrate
+        for i in 0..n {
+            self.bobs[i].omega += a[i] * dt;
+        }
+        if order == UpdateOrder::VelocityFirst {
+            for i in 0..n {
+                self.bobs[i].theta += self.bobs[i].omega * dt;
+            }
+        }
+
+        self.update_coordinates();
+        regular
+    }
+
+    /// Recomputes the positions from the angles and rod lengths — cumulative
+    /// sums from the pivot.
+    pub fn update_coordinates(&mut self) {
+        let mut cum_x = self.pivot.x;
+        let mut cum_y = self.pivot.y;
+        for bob in &mut self.bobs {
+            cum_x += bob.length_rod * bob.theta.sin();
+            cum_y += bob.length_rod * bob.theta.cos();
+            bob.coordinate = Coordinate::new(cum_x, cum_y);
+        }
+    }
+
+    /// Scales every angular velocity by the one factor that makes the total
+    /// energy `energy`; kinetic energy goes with its square. Where that
+    /// can't be done, because the pose alone has more potential energy or
+    /// there is no motion to scale, the bobs are stopped instead and `false`
+    /// returned.
+    pub fn rescale_to_energy(&mut self, energy: f64) -> bool {
+        let kinetic = energy - self.potential_energy();
+        let factor = (kinetic / self.kinetic_energy()).sqrt();
+        let feasible = kinetic >= 0.0 && factor.is_finite();
+        for bob in &mut self.bobs {
+            bob.omega = if feasible { bob.omega * factor } else { 0.0 };
+        }
+        feasible
+    }
+
+    /// Whether every angle and angular velocity is finite.
+    pub fn is_finite(&self) -> bool {
+        self.bobs
+            .iter()
+            .all(|b| b.theta.is_finite() && b.omega.is_finite())
+    }
+}
+
+impl Default for Pendulum {
+    fn default() -> Self {
+        Self::new(vec![
+            Bob::new(DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA, 0.0),
+            Bob::new(DEFAULT_LENGTH_ROD, 2.0 * DEFAULT_MASS, DEFAULT_THETA, 0.0),
+            Bob::new(DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA, 0.0),
+            Bob::new(DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA, 0.0),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rod_tensions_carry_the_chain_beyond() {
+        // at rest, hanging: each rod holds the weight of everything below it
+        let mut pendulum = Pendulum::default();
+        for bob in &mut pendulum.bobs {
+            bob.theta = PI;
+            bob.omega = 0.0;
+        }
+        let tensions = pendulum.rod_tensions(&pendulum.accelerations());
+        for (t, s) in tensions.iter().zip(pendulum.suffix_masses()) {
+            assert!((t - s * GRAVITATIONAL_ACCELERATION).abs() < 1e-9);
+        }
+
+        // a single swinging bob: T = m (l ω² - g cos θ)
+        let (l, m, theta, omega) = (2.0, 3.0, 2.5, 1.5);
+        let single = Pendulum::new(vec![Bob::new(l, m, theta, omega)]);
+        let tension = single.rod_tensions(&single.accelerations())[0];
+        let expected = m * (l * omega * omega - GRAVITATIONAL_ACCELERATION * theta.cos());
+        assert!((tension - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn momenta_give_back_the_kinetic_energy() {
+        let mut pendulum = Pendulum::default();
+        for (i, bob) in pendulum.bobs.iter_mut().enumerate() {
+            bob.omega = 0.3 * i as f64 - 0.4;
+        }
+        let p_dot_omega: f64 = pendulum
+            .momenta()
+            .iter()
+            .zip(&pendulum.bobs)
+            .map(|(p, b)| p * b.omega)
+            .sum();
+        assert!((0.5 * p_dot_omega - pendulum.kinetic_energy()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn poses_fit_the_points_as_closely_as_the_rods_allow() {
+        let pendulum = Pendulum::default();
+        let thetas = [2.0, -1.0, 0.5, 3.0];
+        let points = pendulum.positions_for(&thetas);
+        let fitted = pendulum.fit_pose(&points);
+        assert!(fitted.iter().zip(thetas).all(|(a, b)| (a - b).abs() < 1e-9));
+
+        // points a rod length can't reach: the polish beats aiming rod by rod
+        let stretched: Vec<Coordinate> = points
+            .iter()
+            .map(|p| Coordinate::new(1.3 * p.x + 5.0, 0.8 * p.y))
+            .collect();
+        let cost = |thetas: &[f64]| -> f64 {
+            let positions = pendulum.positions_for(thetas);
+            let miss = positions.iter().zip(&stretched);
+            miss.map(|(p, q)| (p.x - q.x).powi(2) + (p.y - q.y).powi(2))
+                .sum()
+        };
+        let mut aimed = Vec::new();
+        let mut parent = Coordinate::default();
+        for point in &stretched {
+            aimed.push((point.x - parent.x).atan2(point.y - parent.y));
+            parent = *point;
+        }
+        assert!(cost(&pendulum.fit_pose(&stretched)) < cost(&aimed));
+
+        // a point on top of the previous one keeps the previous direction
+        let mut doubled = points.clone();
+        doubled[2] = doubled[1];
+        let fitted = pendulum.fit_pose(&doubled);
+        assert!(fitted.iter().all(|t| t.is_finite()));
+        assert!(Pendulum::new(Vec::new()).fit_pose(&[]).is_empty());
+    }
+
+    #[test]
+    fn a_chain_serializes_whole_and_reads_back_the_same() {
+        let mut pendulum = Pendulum {
+            damping: 0.25,
+            pivot: Coordinate::new(1.0, -2.0),
+            ..Pendulum::default()
+        };
+        pendulum.bobs[2].locked = true;
+        pendulum.step(1e-3);
+        let json = serde_json::to_value(&pendulum).unwrap();
+        assert_eq!(json["integrator"]["id"], INTEGRATOR);
+        assert_eq!(json["integrator"]["order"], "velocityFirst");
+        assert_eq!(json["bobs"][0]["lengthRod"], DEFAULT_LENGTH_ROD);
+        let back: Pendulum = serde_json::from_value(json).unwrap();
+        assert_eq!(back, pendulum);
+
+        // only the bobs' parameters are needed
+        let bare = r#"{ "bobs": [{ "lengthRod": 1, "mass": 2, "theta": 3, "omega": 0 }] }"#;
+        let bare: Pendulum = serde_json::from_str(bare).unwrap();
+        assert_eq!(bare, Pendulum::new(vec![Bob::new(1.0, 2.0, 3.0, 0.0)]));
+    }
+}
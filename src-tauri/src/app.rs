@@ -0,0 +1,2805 @@
+use pendulum_core::{Coordinate, DynamicsTerms, Integrator, LinearSolution, NormalModes};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tauri::{ipc::Channel, AppHandle, Emitter, Manager, WindowEvent};
+
+use crate::benchmark::BenchmarkReport;
+use crate::bounds::{Bounds, BoundsConfig};
+use crate::clock::MAX_TIME_SCALE;
+use crate::compact::FrameFormat;
+use crate::comparison::ComparisonInfo;
+use crate::config::ImportReport;
+use crate::dense::{DenseProgress, DenseStart};
+use crate::digest::{TrajectoryDigest, DEFAULT_DIGEST_TOLERANCE};
+use crate::energy::EnergySource;
+use crate::ensemble::{Ensemble, EnsembleEditPolicy, EnsembleInfo, EnsemblePrecision};
+use crate::error::CommandError;
+use crate::events::{AlertQuantity, AlertRule, EventSink, SimEvent};
+use crate::heatmap::{Colormap, HeatmapReport, DEFAULT_HEATMAP_RESOLUTION};
+use crate::history::{Edit, HistorySummary};
+use crate::instances::{InstanceConfig, InstanceInfo, Instances};
+use crate::integrator::{IntegratorExt, IntegratorInfo, IntegratorSettings};
+use crate::logs::{LogEntry, LogLevel, Logs, LOG_FILE, RECENT_LOG_ENTRIES};
+#[cfg(feature = "osc")]
+use crate::osc;
+use crate::pendulum::{BobMeta, BobSpec};
+use crate::phase::{PhaseSource, PhaseSpaceOptions};
+use crate::prediction::Prediction;
+use crate::presets::PresetInfo;
+use crate::randomize::RandomRanges;
+use crate::recording::{validate_offset, Ghost, Recording, RecordingSummary};
+use crate::replay::{Replay, ReplayStatus};
+use crate::rewind::{PastState, RewindConfig, RewindStatus};
+use crate::savefile::{Autosave, AUTOSAVE_FILE};
+use crate::sensitivity::{SensitivityProgress, SensitivityReport};
+use crate::slowmo::{SlowMotion, SlowMotionConfig};
+use crate::snapshots::SnapshotInfo;
+use crate::startup::{ReloadReport, Startup, StartupConfig, STARTUP_CONFIG_FILE};
+use crate::state::{
+    AddedBob, AppDataInner, BobUpdate, FrameSchema, ModifiedBob, PoseEditPolicy, SetStateOptions,
+    StateSnapshot, StreamFrame, DYNAMICS_OVERLAY_EVERY, FRAME_SCHEMA_VERSION,
+    HERMITE_INTERPOLATION,
+};
+use crate::stream::{
+    FrameSink, Pong, SimulationStats, StepReport, StreamPolicy, StreamStats, SubscribeOptions,
+    Topic, DEFAULT_CHANGE_EPSILON,
+};
+use crate::svg::{SvgOptions, SvgReport, SvgSource};
+use crate::sweep::{SweepProgress, SweepReport, SweepSpec};
+use crate::trails::{SimplifiedTrail, Trail, TrailConfig};
+use crate::trajectory::{
+    read_recording, FileReport, RecordingFormat, RecordingOptions, TrajectoryFile,
+    TrajectoryRecording,
+};
+use crate::units::{
+    validate_pixels_per_meter, AngleConvention, AngleUnit, ThetaPolicy, WorldFrame,
+};
+use crate::userpresets::{UserPresetInfo, UserPresets, USER_PRESETS_DIR};
+use crate::warmup::{SteppedState, WarmUpProgress, WarmUpReport, WARM_UP_WALL_BUDGET};
+use crate::wave::{WaveInfo, DEFAULT_WAVE_AMPLITUDE};
+#[cfg(feature = "websocket")]
+use crate::ws;
+use crate::{
+    benchmark, dense, digest, energy, ensemble, heatmap, integrator, phase, prediction, presets,
+    savefile, sensitivity, stream, svg, sweep, trails, trajectory, transition, warmup,
+};
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let builder = tauri::Builder::default();
+    #[cfg(feature = "deep-link")]
+    let builder = builder.plugin(tauri_plugin_deep_link::init());
+    builder
+        .setup(|app| {
+            let app_data_dir = app.path().app_data_dir()?;
+            let logs = Arc::new(Logs::new(Some(app_data_dir.join(LOG_FILE))));
+            Logs::install(logs.clone());
+            app.manage(logs);
+            let config_path = app.path().app_config_dir()?.join(STARTUP_CONFIG_FILE);
+            let (config, mut diagnostics) = StartupConfig::load(&config_path);
+            let mut data = AppDataInner::default();
+            diagnostics.extend(config.apply(&mut data));
+            for diagnostic in diagnostics {
+                tracing::warn!(path = %config_path.display(), "{diagnostic}");
+            }
+            let (instances, mut physics) = Instances::new(data);
+            app.manage(instances.clone());
+            app.manage(Startup::new(config_path, config));
+            app.manage(UserPresets::new(app_data_dir.join(USER_PRESETS_DIR)));
+            let autosave = Autosave::new(app_data_dir.join(AUTOSAVE_FILE));
+            autosave.restore(physics.world.primary());
+            app.manage(autosave);
+            #[cfg(feature = "deep-link")]
+            open_deep_links(app, physics.world.primary())?;
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                stream::physics_loop(physics, &handle).await;
+            });
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                handle.state::<Autosave>().run(&instances).await;
+            });
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            if matches!(
+                event,
+                WindowEvent::Resized(_) | WindowEvent::Focused(_) | WindowEvent::Destroyed
+            ) {
+                update_background(window.app_handle());
+            }
+            if let WindowEvent::CloseRequested { .. } = event {
+                autosave(window.app_handle());
+            }
+        })
+        .plugin(tauri_plugin_opener::init())
+        .invoke_handler(tauri::generate_handler![
+            create_instance,
+            destroy_instance,
+            list_instances,
+            start_comparison,
+            stop_comparison,
+            setup_pendulum_wave,
+            pendulum_state,
+            subscribe_energetics,
+            subscribe_diagnostics,
+            unsubscribe,
+            set_stream_rate,
+            set_frame_suppression,
+            get_stream_stats,
+            get_state,
+            get_simulation_stats,
+            get_recent_logs,
+            get_log_path,
+            reset_stats,
+            request_keyframe,
+            frame_schema,
+            set_angle_unit,
+            set_angle_convention,
+            set_world_frame,
+            get_render_scale,
+            set_render_scale,
+            set_max_bobs,
+            set_pose_edit_policy,
+            set_pose_transition,
+            set_joint_locked,
+            set_bob_meta,
+            ping,
+            set_time_scale,
+            set_slow_motion,
+            list_integrators,
+            get_integrator_settings,
+            set_integrator_settings,
+            compute_trajectory_digest,
+            set_tick_period,
+            set_pause_without_subscribers,
+            set_paused,
+            pause,
+            resume,
+            single_step,
+            set_pause_in_background,
+            set_state,
+            set_initial_conditions,
+            randomize,
+            list_presets,
+            undo,
+            redo,
+            get_history,
+            load_preset,
+            save_user_preset,
+            list_user_presets,
+            load_user_preset,
+            delete_user_preset,
+            export_state_string,
+            import_state_string,
+            export_config,
+            import_config,
+            reload_config,
+            save_simulation,
+            load_simulation,
+            set_autosave_interval,
+            open_link,
+            reset,
+            add_bob,
+            insert_bob,
+            remove_bob,
+            remove_bob_by_id,
+            modify_bob,
+            modify_bob_by_id,
+            modify_bobs,
+            modify_all_bobs,
+            set_bob_count,
+            zero_velocities,
+            mirror,
+            scale,
+            set_pose_from_points,
+            set_pivot,
+            compute_normal_modes,
+            excite_mode,
+            set_analytic_overlay,
+            resync_analytic,
+            timestep_sensitivity,
+            cancel_timestep_sensitivity,
+            export_dense,
+            cancel_dense_export,
+            run_parameter_sweep,
+            cancel_parameter_sweep,
+            export_energy_series,
+            export_phase_space,
+            predict,
+            warm_up,
+            cancel_warm_up,
+            step_n,
+            run_benchmark,
+            get_dynamics_terms,
+            set_dynamics_overlay,
+            add_alert_rule,
+            remove_alert_rule,
+            list_alert_rules,
+            start_memory_recording,
+            stop_memory_recording,
+            start_recording,
+            stop_recording,
+            start_ghost,
+            stop_ghost,
+            load_recording,
+            delete_recording,
+            convert_recording,
+            start_replay,
+            stop_replay,
+            seek_replay,
+            save_snapshot,
+            list_snapshots,
+            restore_snapshot,
+            delete_snapshot,
+            rewind,
+            get_rewind_buffer,
+            get_trails,
+            get_simplified_trail,
+            export_trail_svg,
+            export_heatmap_png,
+            set_trail_buffer,
+            get_motion_bounds,
+            set_motion_bounds,
+            get_state_at,
+            set_rewind_buffer,
+            create_ensemble,
+            dissolve_ensemble,
+            start_osc,
+            stop_osc,
+            start_ws_server,
+            stop_ws_server
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                autosave(app);
+                // closes the WebSocket connections while the runtime is still up
+                #[cfg(feature = "websocket")]
+                let _ = app.state::<Instances>().world_blocking(|world| {
+                    for id in world.ids() {
+                        world.get(Some(id))?.ws_server = None;
+                    }
+                    Ok(())
+                });
+            }
+        });
+}
+
+/// Saves the primary instance for the next launch; see `Autosave`.
+fn autosave(app: &AppHandle) {
+    let saved = app.state::<Autosave>().save_now(&app.state::<Instances>());
+    if let Err(error) = saved {
+        tracing::error!("Autosave failed: {error}");
+    }
+}
+
+impl FrameSink for Channel<StreamFrame> {
+    fn send(&self, frame: StreamFrame) -> bool {
+        Channel::send(self, frame).is_ok()
+    }
+}
+
+/// Records whether every window is hidden or minimized. Minimizing shows up
+/// as a resize, hiding and restoring as focus changes.
+fn update_background(app: &AppHandle) {
+    let in_background = app.webview_windows().values().all(|window| {
+        !window.is_visible().unwrap_or(true) || window.is_minimized().unwrap_or(false)
+    });
+    let instances = app.state::<Instances>().inner().clone();
+    tauri::async_runtime::spawn(async move {
+        let _ = (instances.world(move |world| {
+            for id in world.ids() {
+                world.get(Some(id))?.in_background = in_background;
+            }
+            Ok(())
+        }))
+        .await;
+    });
+}
+
+/// Opens the link the app was launched with, before the physics task starts
+/// so the first frame already shows it, and every link it gets from then on
+/// with a frame pushed at once; see `AppDataInner::open_link`.
+#[cfg(feature = "deep-link")]
+fn open_deep_links(
+    app: &tauri::App,
+    primary: &mut AppDataInner,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use tauri_plugin_deep_link::DeepLinkExt;
+
+    let deep_link = app.deep_link();
+    // on Linux and Windows a scheme only works once registered, which an
+    // installed bundle does but a dev build doesn't
+    #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+    deep_link.register_all()?;
+    for url in deep_link.get_current()?.unwrap_or_default() {
+        primary.open_link(url.as_str());
+    }
+    let instances = app.state::<Instances>().inner().clone();
+    deep_link.on_open_url(move |event| {
+        for url in event.urls() {
+            let instances = instances.clone();
+            tauri::async_runtime::spawn(async move {
+                let edit = instances.edit(None, move |app_data| {
+                    app_data.open_link(url.as_str());
+                    Ok(())
+                });
+                let _ = edit.await;
+            });
+        }
+    });
+    Ok(())
+}
+
+impl EventSink for AppHandle {
+    fn emit(&self, event: SimEvent) {
+        // only fails when the app is shutting down
+        let _ = Emitter::emit(self, event.name(), &event);
+    }
+}
+
+/// Starts another simulation next to the others, with its own chain,
+/// settings, statistics and subscriptions, and returns its id for the
+/// `instance` argument every other command takes; without one they act on
+/// the primary instance. At most `MAX_INSTANCES` run at once, their bob
+/// caps within `MAX_TOTAL_BOBS` together.
+#[tauri::command]
+async fn create_instance(
+    instances: tauri::State<'_, Instances>,
+    config: Option<InstanceConfig>,
+) -> Result<u64, CommandError> {
+    let config = config.unwrap_or_default();
+    instances.world(move |world| world.create(&config)).await
+}
+
+/// Stops an instance and ends its subscriptions. The primary one can't be
+/// destroyed.
+#[tauri::command]
+async fn destroy_instance(
+    instances: tauri::State<'_, Instances>,
+    id: u64,
+) -> Result<(), CommandError> {
+    instances.world(move |world| world.destroy(id)).await
+}
+
+/// The running instances, the primary one first.
+#[tauri::command]
+async fn list_instances(
+    instances: tauri::State<'_, Instances>,
+) -> Result<Vec<InstanceInfo>, CommandError> {
+    instances.world(|world| Ok(world.list())).await
+}
+
+/// Clones the instance into a new one that differs only by `damping` in
+/// every joint, in N·m·s/rad, and steps the clone in lockstep with it. The
+/// instance's positions frames then carry the clone's bobs, with their tip
+/// distance, angle divergence and energy gap, as `comparison`. With
+/// `mirror_edits` each edit of the instance is passed on, so the clone
+/// starts from the same state again.
+#[tauri::command]
+async fn start_comparison(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    damping: f64,
+    mirror_edits: Option<bool>,
+) -> Result<ComparisonInfo, CommandError> {
+    let mirror_edits = mirror_edits.unwrap_or(true);
+    (instances.world(move |world| world.start_comparison(instance, damping, mirror_edits))).await
+}
+
+/// Ends the comparison and destroys the clone, returning whether there was
+/// one.
+#[tauri::command]
+async fn stop_comparison(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<bool, CommandError> {
+    instances
+        .world(move |world| world.stop_comparison(instance))
+        .await
+}
+
+/// Starts a pendulum wave of `n` single pendulums, one new instance each,
+/// whose lengths make the i-th swing `base_cycles + i` times in `period`
+/// seconds under the configured gravity, so they all line up again after
+/// each period. They are released together `amplitude` from hanging, in
+/// the angle unit, `DEFAULT_WAVE_AMPLITUDE` if left out. The first instance
+/// steps the others; subscribing to it gets every tip in each frame, as
+/// `wave`, and destroying it ends the wave.
+#[tauri::command]
+async fn setup_pendulum_wave(
+    instances: tauri::State<'_, Instances>,
+    n: usize,
+    period: f64,
+    base_cycles: u32,
+    amplitude: Option<f64>,
+) -> Result<WaveInfo, CommandError> {
+    (instances.world(move |world| {
+        let unit = world.primary().angle_unit;
+        let amplitude = amplitude.map_or(DEFAULT_WAVE_AMPLITUDE, |a| unit.to_radians(a));
+        world.setup_pendulum_wave(n, period, base_cycles, amplitude)
+    }))
+    .await
+}
+
+/// Subscribes the channel to the frames broadcast by the physics task and
+/// returns the subscription id. The subscription ends with `unsubscribe` or
+/// when the frontend drops the channel. `fps` limits how often frames are
+/// sent; the physics keeps stepping at full rate regardless. Frames reach
+/// the channel through a small queue on their own task, so a busy webview
+/// loses the oldest frames instead of slowing the physics. `format` picks
+/// the compact layouts over the default full frames, and `theta_policy`
+/// wrapped angles over the unbounded ones. This is the positions topic;
+/// see `subscribe_energetics` and `subscribe_diagnostics` for the others.
+// the arguments are the invoke call's, one optional setting each
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn pendulum_state(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    channel: Channel<StreamFrame>,
+    policy: Option<StreamPolicy>,
+    fps: Option<u32>,
+    suppress_unchanged: Option<bool>,
+    change_epsilon: Option<f64>,
+    format: Option<FrameFormat>,
+    theta_policy: Option<ThetaPolicy>,
+) -> Result<u64, CommandError> {
+    let options = SubscribeOptions {
+        topic: Topic::Positions,
+        policy: policy.unwrap_or_default(),
+        change_epsilon: suppression(suppress_unchanged.unwrap_or(true), change_epsilon),
+        format: format.unwrap_or_default(),
+        theta_policy: theta_policy.unwrap_or_default(),
+        ..SubscribeOptions::default()
+    };
+    spawn_subscription(&instances, instance, channel, fps, options).await
+}
+
+/// Subscribes with `options` at `fps`, the instance's default if `None`.
+async fn spawn_subscription(
+    instances: &Instances,
+    instance: Option<u64>,
+    channel: Channel<StreamFrame>,
+    fps: Option<u32>,
+    options: SubscribeOptions,
+) -> Result<u64, CommandError> {
+    let (id, drain) = (instances.call(instance, move |app_data| {
+        let fps = fps.unwrap_or(app_data.stream_fps);
+        let options = SubscribeOptions { fps, ..options };
+        stream::subscribe(app_data, Arc::new(channel), options)
+    }))
+    .await?;
+    tauri::async_runtime::spawn(drain);
+    Ok(id)
+}
+
+/// Subscribes the channel to energy, generalized momentum and rod tension
+/// frames. They are only computed while someone is subscribed. Ends the
+/// same way as `pendulum_state`.
+#[tauri::command]
+async fn subscribe_energetics(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    channel: Channel<StreamFrame>,
+    policy: Option<StreamPolicy>,
+    fps: Option<u32>,
+) -> Result<u64, CommandError> {
+    let options = SubscribeOptions {
+        topic: Topic::Energetics,
+        policy: policy.unwrap_or_default(),
+        ..SubscribeOptions::default()
+    };
+    spawn_subscription(&instances, instance, channel, fps, options).await
+}
+
+/// Subscribes the channel to mass matrix conditioning, substep count and
+/// energy drift frames, which are only computed while someone is
+/// subscribed.
+#[tauri::command]
+async fn subscribe_diagnostics(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    channel: Channel<StreamFrame>,
+    policy: Option<StreamPolicy>,
+    fps: Option<u32>,
+) -> Result<u64, CommandError> {
+    let options = SubscribeOptions {
+        topic: Topic::Diagnostics,
+        policy: policy.unwrap_or_default(),
+        ..SubscribeOptions::default()
+    };
+    spawn_subscription(&instances, instance, channel, fps, options).await
+}
+
+fn suppression(enabled: bool, change_epsilon: Option<f64>) -> Option<f64> {
+    enabled.then(|| change_epsilon.unwrap_or(DEFAULT_CHANGE_EPSILON))
+}
+
+#[tauri::command]
+async fn set_frame_suppression(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    id: u64,
+    enabled: bool,
+    change_epsilon: Option<f64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            stream::set_frame_suppression(app_data, id, suppression(enabled, change_epsilon))
+        })
+        .await
+}
+
+#[tauri::command]
+async fn set_stream_rate(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    id: u64,
+    fps: u32,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            stream::set_stream_rate(app_data, id, fps)
+        })
+        .await
+}
+
+/// Per-subscription queue depth and dropped-frame counts, to spot a
+/// frontend that can't keep up.
+#[tauri::command]
+async fn get_stream_stats(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<StreamStats, CommandError> {
+    instances
+        .call(instance, move |app_data| stream::stats(app_data))
+        .await
+}
+
+/// The current state and configuration, without subscribing to anything.
+/// The angles are unbounded unless `theta_policy` says otherwise.
+#[tauri::command]
+async fn get_state(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    theta_policy: Option<ThetaPolicy>,
+) -> Result<StateSnapshot, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let wall_time_ms = app_data.wall_time_ms(tokio::time::Instant::now());
+            Ok(app_data.snapshot(wall_time_ms, theta_policy.unwrap_or_default()))
+        })
+        .await
+}
+
+/// Counters of the physics task, for when the simulation feels off.
+#[tauri::command]
+async fn get_simulation_stats(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<SimulationStats, CommandError> {
+    instances
+        .call(instance, move |app_data| stream::simulation_stats(app_data))
+        .await
+}
+
+#[tauri::command]
+async fn reset_stats(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances.call(instance, stream::reset_stats).await
+}
+
+/// The newest `limit` log entries, all that are kept if `None`, at `level`
+/// or more severe, info and up if `None`. Oldest first; see `Logs`.
+#[tauri::command]
+fn get_recent_logs(
+    logs: tauri::State<'_, Arc<Logs>>,
+    level: Option<LogLevel>,
+    limit: Option<usize>,
+) -> Vec<LogEntry> {
+    let level = level.unwrap_or(LogLevel::Info);
+    logs.recent(level, limit.unwrap_or(RECENT_LOG_ENTRIES))
+}
+
+/// Where the log file is, for attaching to a bug report. Older entries are
+/// in the same path ending in `.1` and `.2`.
+#[tauri::command]
+fn get_log_path(logs: tauri::State<'_, Arc<Logs>>) -> Result<String, CommandError> {
+    Ok(logs.path()?.display().to_string())
+}
+
+/// Sets the unit of the angles that commands take and return; see
+/// `AngleUnit`. The streamed frames stay in radians.
+#[tauri::command]
+async fn set_angle_unit(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    unit: AngleUnit,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.angle_unit = unit;
+            Ok(())
+        })
+        .await
+}
+
+/// Sets what the angles that commands take and return are measured from,
+/// and which way; see `AngleConvention`. The streamed frames keep the
+/// simulation's own.
+#[tauri::command]
+async fn set_angle_convention(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    convention: AngleConvention,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.angle_convention = convention;
+            Ok(())
+        })
+        .await
+}
+
+/// Sets which way y points in the positions that commands take and return,
+/// pivots included; see `WorldFrame`. The streamed frames keep y up.
+#[tauri::command]
+async fn set_world_frame(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    frame: WorldFrame,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.world_frame = frame;
+            Ok(())
+        })
+        .await
+}
+
+/// How many pixels the frontends draw a meter as. Every positions frame
+/// carries it too, as `pixelsPerMeter`.
+#[tauri::command]
+async fn get_render_scale(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<f64, CommandError> {
+    instances
+        .call(instance, move |app_data| Ok(app_data.pixels_per_meter))
+        .await
+}
+
+/// Sets how many pixels the frontends draw a meter as and pushes a frame
+/// with it at once. Only the drawing changes; the simulation stays in
+/// meters.
+#[tauri::command]
+async fn set_render_scale(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    pixels_per_meter: f64,
+) -> Result<(), CommandError> {
+    validate_pixels_per_meter(pixels_per_meter)?;
+    instances
+        .edit(instance, move |app_data| {
+            app_data.pixels_per_meter = pixels_per_meter;
+            Ok(())
+        })
+        .await
+}
+
+/// Sets how many bobs edits may leave the chain with, `DEFAULT_MAX_BOBS` to
+/// begin with, within what the other instances leave of `MAX_TOTAL_BOBS`.
+/// Returns a warning if that is past where steps get slow enough to fall
+/// behind wall time.
+#[tauri::command]
+async fn set_max_bobs(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    max: usize,
+) -> Result<Option<String>, CommandError> {
+    instances
+        .world(move |world| world.set_max_bobs(instance, max))
+        .await
+}
+
+/// Sets what angle edits do to the angular velocities; see
+/// `PoseEditPolicy`.
+#[tauri::command]
+async fn set_pose_edit_policy(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    policy: PoseEditPolicy,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.pose_edit_policy = policy;
+            Ok(())
+        })
+        .await
+}
+
+/// Makes angle edits ease over `duration` sim seconds instead of jumping, or
+/// jump again with `None`; see `AppDataInner::modify_bob`.
+#[tauri::command]
+async fn set_pose_transition(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    duration: Option<f64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            transition::validate_duration(duration)?;
+            app_data.pose_transition = duration;
+            Ok(())
+        })
+        .await
+}
+
+/// Version of the streamed frames and how to interpolate between them.
+#[tauri::command]
+fn frame_schema() -> FrameSchema {
+    FrameSchema {
+        version: FRAME_SCHEMA_VERSION,
+        interpolation: HERMITE_INTERPOLATION,
+        angle_unit: AngleUnit::Radians,
+        angle_convention: AngleConvention::default(),
+        world_frame: WorldFrame::default(),
+    }
+}
+
+#[tauri::command]
+async fn request_keyframe(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    id: u64,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            stream::request_keyframe(app_data, id)
+        })
+        .await
+}
+
+/// Latency probe: echoes `client_timestamp` with the backend's receive time
+/// and, for `subscription`, the `seq` of its latest frame.
+#[tauri::command]
+async fn ping(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    client_timestamp: f64,
+    subscription: Option<u64>,
+) -> Result<Pong, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            stream::ping(app_data, client_timestamp, subscription)
+        })
+        .await
+}
+
+#[tauri::command]
+async fn unsubscribe(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    id: u64,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, move |app_data| stream::unsubscribe(app_data, id))
+        .await
+}
+
+/// Stops or resumes stepping. Frames keep coming while paused, carrying the
+/// pause reason.
+#[tauri::command]
+async fn set_paused(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    paused: bool,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.user_paused = paused;
+            Ok(())
+        })
+        .await
+}
+
+/// Stops stepping, like `set_paused(true)`. Frames keep coming and edits
+/// still show up in them at once.
+#[tauri::command]
+async fn pause(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    set_paused(instances, instance, true).await
+}
+
+/// Continues stepping from the sim time it stopped at; the paused wall time is
+/// never caught up.
+#[tauri::command]
+async fn resume(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    set_paused(instances, instance, false).await
+}
+
+/// Advances a paused simulation by one substep of `dt`, the configured one by
+/// default, and pushes the result to every subscriber straight away.
+#[tauri::command]
+async fn single_step(
+    app: AppHandle,
+    instance: Option<u64>,
+    dt: Option<f64>,
+) -> Result<StepReport, CommandError> {
+    let (report, events) = (app.state::<Instances>().call(instance, move |app_data| {
+        let mut events = Vec::new();
+        let report = stream::single_step(app_data, dt, &mut events);
+        Ok((report, events))
+    }))
+    .await?;
+    for event in events {
+        EventSink::emit(&app, event);
+    }
+    report
+}
+
+/// Whether stepping stops while every window is hidden or minimized. On by
+/// default; when the window comes back the simulation picks up where it
+/// stopped rather than catching up.
+#[tauri::command]
+async fn set_pause_in_background(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    enabled: bool,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.pause_in_background = enabled;
+            Ok(())
+        })
+        .await
+}
+
+#[tauri::command]
+async fn set_pause_without_subscribers(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    enabled: bool,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.pause_without_subscribers = enabled;
+            Ok(())
+        })
+        .await
+}
+
+/// Sets how many sim seconds pass per wall second.
+#[tauri::command]
+async fn set_time_scale(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    scale: f64,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            if !(scale.is_finite() && scale > 0.0 && scale <= MAX_TIME_SCALE) {
+                return Err(CommandError::invalid(
+                    "scale",
+                    format!("must be in (0, {MAX_TIME_SCALE}], got {scale}"),
+                ));
+            }
+            app_data.time_scale = scale;
+            Ok(())
+        })
+        .await
+}
+
+/// Every integration method there is, with the schema of its settings.
+#[tauri::command]
+fn list_integrators() -> Vec<IntegratorInfo> {
+    integrator::list()
+}
+
+/// The method the chain is stepped with and its settings.
+#[tauri::command]
+async fn get_integrator_settings(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<IntegratorSettings, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data.pendulum.integrator.settings())
+        })
+        .await
+}
+
+/// Switches to the method `settings.id` with `settings.values`, checked
+/// against its schema from `list_integrators`; see
+/// `AppDataInner::set_integrator`.
+#[tauri::command]
+async fn set_integrator_settings(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    settings: IntegratorSettings,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let integrator = Integrator::from_settings(&settings)?;
+            app_data.set_integrator(integrator);
+            Ok(())
+        })
+        .await
+}
+
+/// A hash of the trajectory of the default chain, from where the app
+/// starts it, over `steps` steps of `dt`, with θ and ω rounded to
+/// `tolerance`, `DEFAULT_DIGEST_TOLERANCE` if `None`. It is stepped with
+/// `integrator`, the default if `None`, and never touches the live state,
+/// so two builds that give the same digest for the same arguments and
+/// version step the same way.
+#[tauri::command]
+async fn compute_trajectory_digest(
+    steps: u64,
+    dt: f64,
+    tolerance: Option<f64>,
+    integrator: Option<IntegratorSettings>,
+) -> Result<TrajectoryDigest, CommandError> {
+    let tolerance = tolerance.unwrap_or(DEFAULT_DIGEST_TOLERANCE);
+    digest::validate(steps, dt, tolerance)?;
+    let integrator = match integrator {
+        Some(settings) => Integrator::from_settings(&settings)?,
+        None => Integrator::default(),
+    };
+    tokio::task::spawn_blocking(move || digest::canonical(integrator, steps, dt, tolerance))
+        .await
+        .map_err(CommandError::internal)
+}
+
+/// Turns automatic slow motion around flips on or off, with `min_omega` in
+/// the angle unit set. The multiplier it applies shows up as `slowMotion`
+/// in the frames.
+#[tauri::command]
+async fn set_slow_motion(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    enabled: bool,
+    config: Option<SlowMotionConfig>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let mut config = config.unwrap_or_default();
+            config.min_omega = app_data.angle_format().incoming_magnitude(config.min_omega);
+            config.validate()?;
+            app_data.slow_motion = enabled.then(|| SlowMotion::new(config));
+            Ok(())
+        })
+        .await
+}
+
+#[tauri::command]
+async fn set_tick_period(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    period_ms: f64,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let period =
+                std::time::Duration::try_from_secs_f64(period_ms / 1000.0).map_err(|_| {
+                    CommandError::invalid(
+                        "period_ms",
+                        format!("must be a duration, got {period_ms}"),
+                    )
+                })?;
+            let period = stream::validate_tick_period(period)?;
+            app_data.tick_period = period;
+            Ok(())
+        })
+        .await
+}
+
+/// Replaces the whole chain in one go, or leaves it alone if any bob is
+/// invalid. Also how saved configurations are loaded; ones saved with their
+/// lengths in pixels load with `lengthsInPixels`.
+#[tauri::command]
+async fn set_state(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    bobs: Vec<BobSpec>,
+    options: Option<SetStateOptions>,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let bobs = app_data.angle_format().incoming(bobs);
+            let options = app_data.world_frame.incoming(options.unwrap_or_default());
+            app_data.set_state(&bobs, options)
+        })
+        .await
+}
+
+/// Stops every bob where it is and pushes a frame of it at once.
+#[tauri::command]
+async fn zero_velocities(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, AppDataInner::zero_velocities)
+        .await
+}
+
+/// Reflects the pendulum about the vertical and pushes a frame of it at once.
+#[tauri::command]
+async fn mirror(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances.edit(instance, AppDataInner::mirror).await
+}
+
+/// Multiplies every rod length and mass; see `AppDataInner::scale`. Missing
+/// factors are 1, and `dynamically_similar` is on unless turned off.
+#[tauri::command]
+async fn scale(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    length_factor: Option<f64>,
+    mass_factor: Option<f64>,
+    dynamically_similar: Option<bool>,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            app_data.scale(
+                length_factor.unwrap_or(1.0),
+                mass_factor.unwrap_or(1.0),
+                dynamically_similar.unwrap_or(true),
+            )
+        })
+        .await
+}
+
+/// Poses the chain at rest through one point per bob and returns how far
+/// each bob had to snap from its point.
+#[tauri::command]
+async fn set_pose_from_points(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    points: Vec<Coordinate>,
+) -> Result<Vec<f64>, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let points = app_data.world_frame.incoming(points);
+            app_data.set_pose_from_points(&points)
+        })
+        .await
+}
+
+/// Moves the point the chain hangs from, and the chain with it, and pushes a
+/// frame of it at once.
+#[tauri::command]
+async fn set_pivot(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    x: f64,
+    y: f64,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let pivot = app_data.world_frame.incoming(Coordinate::new(x, y));
+            app_data.set_pivot(pivot)
+        })
+        .await
+}
+
+/// Takes back the last edit of the bobs and says which kind it was.
+#[tauri::command]
+async fn undo(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<Edit, CommandError> {
+    instances
+        .edit(instance, move |app_data| app_data.undo())
+        .await
+}
+
+#[tauri::command]
+async fn redo(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<Edit, CommandError> {
+    instances
+        .edit(instance, move |app_data| app_data.redo())
+        .await
+}
+
+#[tauri::command]
+async fn get_history(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<HistorySummary, CommandError> {
+    instances
+        .call(instance, move |app_data| Ok(app_data.history.summary()))
+        .await
+}
+
+/// Names and descriptions of the built-in presets, then the user's own,
+/// each saying which it is.
+#[tauri::command]
+fn list_presets(
+    user_presets: tauri::State<'_, UserPresets>,
+) -> Result<Vec<PresetInfo>, CommandError> {
+    let mut presets = presets::list();
+    presets.extend(user_presets.list()?.iter().map(UserPresetInfo::info));
+    Ok(presets)
+}
+
+/// The current setup as a short URL-safe string; see
+/// `AppDataInner::export_state_string`.
+#[tauri::command]
+async fn export_state_string(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<String, CommandError> {
+    instances
+        .call(instance, move |app_data| Ok(app_data.export_state_string()))
+        .await
+}
+
+/// Loads a setup from `export_state_string` and pushes a frame of it at
+/// once; see `AppDataInner::import_state_string`.
+#[tauri::command]
+async fn import_state_string(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    s: String,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| app_data.import_state_string(&s))
+        .await
+}
+
+/// Writes the configuration to a JSON file at `path`: every bob's
+/// parameters and the world settings, versioned. Angles are canonical
+/// radians, whatever the angle settings.
+#[tauri::command]
+async fn export_config(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+) -> Result<(), CommandError> {
+    let config = (instances.call(instance, |app_data| Ok(app_data.export_config()))).await?;
+    tokio::task::spawn_blocking(move || {
+        let json = serde_json::to_string_pretty(&config).map_err(CommandError::internal)?;
+        std::fs::write(&path, json).map_err(|e| CommandError::io(&path, e))
+    })
+    .await
+    .map_err(CommandError::internal)?
+}
+
+/// Applies a file from `export_config`, with a frame pushed at once. It is
+/// applied whole or, if any value is invalid, not at all, with the error
+/// naming the field; unknown fields are left out, each with a warning.
+#[tauri::command]
+async fn import_config(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+) -> Result<ImportReport, CommandError> {
+    let text = tokio::task::spawn_blocking(move || {
+        std::fs::read_to_string(&path).map_err(|e| CommandError::io(&path, e))
+    })
+    .await
+    .map_err(CommandError::internal)??;
+    (instances.edit(instance, move |app_data| app_data.import_config(&text))).await
+}
+
+/// Reads the startup configuration file again, `config.toml` in the app's
+/// config directory, and applies what changed of the world settings to the
+/// primary instance, and of the bob limit. What only takes effect at
+/// startup, the chain, the stream rate and the history, is listed as
+/// needing a restart. Unknown keys and invalid values come back as warnings,
+/// the values left as they were.
+#[tauri::command]
+async fn reload_config(app: AppHandle) -> Result<ReloadReport, CommandError> {
+    let loaded = app.state::<Startup>().read();
+    let handle = app.clone();
+    (app.state::<Instances>())
+        .world(move |world| Ok(handle.state::<Startup>().reload(loaded, world)))
+        .await
+}
+
+/// Writes everything it takes to carry on from this moment to a JSON file
+/// at `path`: the configuration, with the angles and velocities as they
+/// are, and sim time, the run clock, flip detection, the energy baseline
+/// and the eases under way.
+#[tauri::command]
+async fn save_simulation(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+) -> Result<(), CommandError> {
+    let saved = (instances.call(instance, |app_data| Ok(app_data.save_simulation()))).await?;
+    tokio::task::spawn_blocking(move || savefile::save_to(&saved, path.as_ref()))
+        .await
+        .map_err(CommandError::internal)?
+}
+
+/// Sets how often the primary instance is autosaved for the next launch, in
+/// seconds, or turns periodic saves off for `None`; it is saved on exit
+/// either way. The default is every minute.
+#[tauri::command]
+fn set_autosave_interval(
+    autosave: tauri::State<'_, Autosave>,
+    seconds: Option<f64>,
+) -> Result<(), CommandError> {
+    autosave.set_interval(seconds)
+}
+
+/// Carries on from a file of `save_simulation` exactly where it was saved,
+/// with a frame pushed at once. A corrupt file or one with invalid values
+/// changes nothing.
+#[tauri::command]
+async fn load_simulation(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+) -> Result<(), CommandError> {
+    let text = tokio::task::spawn_blocking(move || {
+        std::fs::read_to_string(&path).map_err(|e| CommandError::io(&path, e))
+    })
+    .await
+    .map_err(CommandError::internal)??;
+    (instances.edit(instance, move |app_data| app_data.load_simulation(&text))).await
+}
+
+/// Opens a `double-pendulum://` link pasted into the app as if the app had
+/// been sent it, with a frame pushed at once; see `AppDataInner::open_link`.
+/// A link that can't be opened is reported with a `link-rejected` event,
+/// not as an error.
+#[tauri::command]
+async fn open_link(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    url: String,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            app_data.open_link(&url);
+            Ok(())
+        })
+        .await
+}
+
+/// Loads a built-in preset by name; see `AppDataInner::load_preset`.
+#[tauri::command]
+async fn load_preset(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    name: String,
+) -> Result<(), CommandError> {
+    let preset = presets::find(&name)?;
+    (instances.edit(instance, move |app_data| app_data.load_preset(preset))).await
+}
+
+/// Saves the current configuration as a user preset named `name`, which
+/// may be anything that isn't blank or too long; the file it goes in is
+/// named after it. One of the same name is only replaced with `overwrite`.
+#[tauri::command]
+async fn save_user_preset(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    user_presets: tauri::State<'_, UserPresets>,
+    name: String,
+    overwrite: Option<bool>,
+) -> Result<UserPresetInfo, CommandError> {
+    let user_presets = user_presets.inner().clone();
+    instances
+        .call(instance, move |app_data| {
+            user_presets.save(&name, app_data, overwrite.unwrap_or(false))
+        })
+        .await
+}
+
+/// The user presets, by name, each with its bob count and starting
+/// positions for a thumbnail.
+#[tauri::command]
+fn list_user_presets(
+    user_presets: tauri::State<'_, UserPresets>,
+) -> Result<Vec<UserPresetInfo>, CommandError> {
+    user_presets.list()
+}
+
+/// Loads a user preset as an undoable edit, with a frame pushed at once.
+/// Like `import_config`, it is applied whole or not at all.
+#[tauri::command]
+async fn load_user_preset(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    user_presets: tauri::State<'_, UserPresets>,
+    name: String,
+) -> Result<ImportReport, CommandError> {
+    let (config, warnings) = user_presets.load(&name)?;
+    instances
+        .edit(instance, move |app_data| {
+            app_data.apply_config(&config, Edit::LoadPreset)?;
+            Ok(ImportReport { warnings })
+        })
+        .await
+}
+
+#[tauri::command]
+fn delete_user_preset(
+    user_presets: tauri::State<'_, UserPresets>,
+    name: String,
+) -> Result<(), CommandError> {
+    user_presets.delete(&name)
+}
+
+/// Redraws the bobs at random; see `AppDataInner::randomize`. Returns the
+/// seed used.
+#[tauri::command]
+async fn randomize(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    seed: Option<u64>,
+    ranges: Option<RandomRanges>,
+) -> Result<u64, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let ranges = app_data.angle_format().incoming(ranges.unwrap_or_default());
+            app_data.randomize(seed, &ranges)
+        })
+        .await
+}
+
+/// Makes the current configuration the one `reset` goes back to.
+#[tauri::command]
+async fn set_initial_conditions(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.initial = app_data.pendulum.clone();
+            Ok(())
+        })
+        .await
+}
+
+/// Goes back to the initial conditions with sim time at 0; see
+/// `AppDataInner::reset`.
+#[tauri::command]
+async fn reset(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            app_data.reset();
+            Ok(())
+        })
+        .await
+}
+
+/// Appends a bob and says where it went. Every parameter is optional; see
+/// `AppDataInner::add_bob` for the defaults.
+#[tauri::command]
+async fn add_bob(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    length_rod: Option<f64>,
+    mass: Option<f64>,
+    theta: Option<f64>,
+    omega: Option<f64>,
+) -> Result<AddedBob, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let format = app_data.angle_format();
+            let theta = theta.map(|t| format.incoming_angle(t));
+            let omega = omega.map(|o| format.incoming_rate(o));
+            app_data.add_bob(length_rod, mass, theta, omega)
+        })
+        .await
+}
+
+/// Splices a bob into the chain at `index`; `index` equal to the bob count
+/// appends. See `AppDataInner::insert_bob` for `preserve_pose`.
+// the arguments are the invoke call's
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn insert_bob(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    index: usize,
+    length_rod: f64,
+    mass: f64,
+    theta: f64,
+    omega: f64,
+    preserve_pose: Option<bool>,
+) -> Result<AddedBob, CommandError> {
+    let spec = BobSpec {
+        length_rod,
+        mass,
+        theta,
+        omega,
+        id: None,
+        locked: false,
+        meta: BobMeta::default(),
+    };
+    instances
+        .edit(instance, move |app_data| {
+            let spec = app_data.angle_format().incoming(spec);
+            app_data.insert_bob(index, spec, preserve_pose.unwrap_or(false))
+        })
+        .await
+}
+
+#[tauri::command]
+async fn remove_bob(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    index: usize,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| app_data.remove_bob(index))
+        .await
+}
+
+/// `remove_bob` by the bob's id, which can't go stale as other bobs come and
+/// go.
+#[tauri::command]
+async fn remove_bob_by_id(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    id: u64,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let index = app_data.index_of(id)?;
+            app_data.remove_bob(index)
+        })
+        .await
+}
+
+/// Sets the given properties of bob `index` and pushes a frame of the result
+/// at once, so the edit shows even while paused.
+#[tauri::command]
+async fn modify_bob(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    index: usize,
+    length: Option<f64>,
+    mass: Option<f64>,
+    theta: Option<f64>,
+    omega: Option<f64>,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let format = app_data.angle_format();
+            let theta = theta.map(|t| format.incoming_angle(t));
+            let omega = omega.map(|o| format.incoming_rate(o));
+            app_data.modify_bob(index, length, mass, theta, omega)
+        })
+        .await
+}
+
+/// Sets the color and label bob `index` is shown with; see
+/// `AppDataInner::set_bob_meta`.
+#[tauri::command]
+async fn set_bob_meta(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    index: usize,
+    meta: BobMeta,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| app_data.set_bob_meta(index, meta))
+        .await
+}
+
+/// Locks or unlocks the joint of bob `index`; see
+/// `AppDataInner::set_joint_locked`.
+#[tauri::command]
+async fn set_joint_locked(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    index: usize,
+    locked: bool,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            app_data.set_joint_locked(index, locked)
+        })
+        .await
+}
+
+#[tauri::command]
+async fn set_bob_count(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    count: usize,
+    template: Option<BobSpec>,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            app_data.set_bob_count(count, template)
+        })
+        .await
+}
+
+/// Applies several bob edits in one job, so they land between the same
+/// two steps.
+#[tauri::command]
+async fn modify_bobs(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    updates: Vec<BobUpdate>,
+) -> Result<Vec<ModifiedBob>, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let updates = app_data.angle_format().incoming(updates);
+            app_data.modify_bobs(&updates)
+        })
+        .await
+}
+
+/// Sets, or with `scale` multiplies, a property of every bob at once; see
+/// `AppDataInner::modify_all_bobs`.
+#[tauri::command]
+async fn modify_all_bobs(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    length: Option<f64>,
+    mass: Option<f64>,
+    omega: Option<f64>,
+    scale: Option<bool>,
+) -> Result<Vec<ModifiedBob>, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let scale = scale.unwrap_or(false);
+            // a factor has no unit
+            let format = app_data.angle_format();
+            let omega = omega.map(|o| if scale { o } else { format.incoming_rate(o) });
+            app_data.modify_all_bobs(length, mass, omega, scale)
+        })
+        .await
+}
+
+/// `modify_bob` by the bob's id.
+#[tauri::command]
+async fn modify_bob_by_id(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    id: u64,
+    length: Option<f64>,
+    mass: Option<f64>,
+    theta: Option<f64>,
+    omega: Option<f64>,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let index = app_data.index_of(id)?;
+            let format = app_data.angle_format();
+            let theta = theta.map(|t| format.incoming_angle(t));
+            let omega = omega.map(|o| format.incoming_rate(o));
+            app_data.modify_bob(index, length, mass, theta, omega)
+        })
+        .await
+}
+
+#[tauri::command]
+async fn compute_normal_modes(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<NormalModes, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data
+                .pendulum
+                .normal_modes()
+                .ok_or_else(|| CommandError::unavailable("Mass matrix is not positive definite"))
+        })
+        .await
+}
+
+#[tauri::command]
+async fn excite_mode(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    index: usize,
+    amplitude: f64,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let modes = app_data
+                .pendulum
+                .normal_modes()
+                .ok_or_else(|| CommandError::unavailable("Mass matrix is not positive definite"))?;
+            let len = modes.shapes.len();
+            let shape = modes
+                .shapes
+                .get(index)
+                .ok_or(CommandError::IndexOutOfBounds { index, len })?;
+            if !amplitude.is_finite() {
+                return Err(CommandError::invalid(
+                    "amplitude",
+                    format!("must be finite, got {amplitude}"),
+                ));
+            }
+            let amplitude = app_data.angle_format().incoming_rate(amplitude);
+            app_data.pendulum.excite_mode(shape, amplitude);
+            app_data.state_edited();
+            Ok(())
+        })
+        .await
+}
+
+#[tauri::command]
+async fn set_analytic_overlay(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    enabled: bool,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.analytic = if enabled {
+                let fit = LinearSolution::fit(&app_data.pendulum, app_data.sim_time).ok_or_else(
+                    || CommandError::unavailable("Mass matrix is not positive definite"),
+                )?;
+                Some(fit)
+            } else {
+                None
+            };
+            Ok(())
+        })
+        .await
+}
+
+#[tauri::command]
+async fn resync_analytic(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            if app_data.analytic.is_none() {
+                return Err(CommandError::unavailable("Analytic overlay is not enabled"));
+            }
+            app_data.resync_analytic();
+            Ok(())
+        })
+        .await
+}
+
+/// Runs the current state forward once per dt on cloned pendulums, off the
+/// physics task. Starting a new run cancels the previous one.
+#[tauri::command]
+async fn timestep_sensitivity(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    dts: Vec<f64>,
+    horizon: f64,
+    tolerance: f64,
+    progress: Channel<SensitivityProgress>,
+) -> Result<SensitivityReport, CommandError> {
+    sensitivity::validate(&dts, horizon, tolerance)?;
+    let (pendulum, cancel) = (instances.call(instance, move |app_data| {
+        app_data.sensitivity_cancel.store(true, Ordering::Relaxed);
+        app_data.sensitivity_cancel = Arc::new(AtomicBool::new(false));
+        Ok((
+            app_data.pendulum.clone(),
+            app_data.sensitivity_cancel.clone(),
+        ))
+    }))
+    .await?;
+    tokio::task::spawn_blocking(move || {
+        sensitivity::timestep_sensitivity(&pendulum, &dts, horizon, tolerance, &cancel, |p| {
+            let _ = progress.send(p);
+        })
+    })
+    .await
+    .map_err(CommandError::internal)?
+    .ok_or_else(|| CommandError::Cancelled {
+        reason: "Timestep sensitivity run was cancelled".into(),
+    })
+}
+
+#[tauri::command]
+async fn cancel_timestep_sensitivity(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.sensitivity_cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        })
+        .await
+}
+
+/// Writes the run from the current state, or from the snapshot named
+/// `snapshot`, at exactly every `1 / sample_rate` of sim time for
+/// `duration` sim seconds, to `path` in `format`, for rendering offline.
+/// A copy is integrated with a small fixed dt off the physics task and sampled
+/// between its steps, so the live run is left as it is, and the dt it
+/// uses doesn't matter. Starting a new export cancels the previous one.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn export_dense(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+    duration: f64,
+    sample_rate: f64,
+    format: Option<RecordingFormat>,
+    snapshot: Option<String>,
+    progress: Channel<DenseProgress>,
+) -> Result<FileReport, CommandError> {
+    let total = dense::validate(duration, sample_rate)?;
+    let (start, cancel) = (instances.call(instance, move |app_data| {
+        let start = DenseStart::new(app_data, snapshot.as_deref())?;
+        app_data.dense_cancel.store(true, Ordering::Relaxed);
+        app_data.dense_cancel = Arc::new(AtomicBool::new(false));
+        Ok((start, app_data.dense_cancel.clone()))
+    }))
+    .await?;
+    let format = format.unwrap_or_default();
+    tokio::task::spawn_blocking(move || {
+        dense::export(
+            std::path::Path::new(&path),
+            format,
+            start,
+            sample_rate,
+            total,
+            &cancel,
+            |p| {
+                let _ = progress.send(p);
+            },
+        )
+    })
+    .await
+    .map_err(CommandError::internal)??
+    .ok_or_else(|| CommandError::Cancelled {
+        reason: "Dense export was cancelled".into(),
+    })
+}
+
+#[tauri::command]
+async fn cancel_dense_export(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.dense_cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        })
+        .await
+}
+
+/// Runs the current state forward for `spec.horizon` sim seconds once per
+/// combination of the values of its one or two axes, each on a copy with
+/// those values applied, and writes a summary CSV of `spec.quantities` to
+/// `output_dir`, with a trajectory per run if `spec.recordings` asks for
+/// them; see `SweepSpec`. The runs share `spec.threads`, all but one
+/// core by default, off the physics task, so the live run carries on as
+/// it was. Starting a new sweep cancels the previous one.
+#[tauri::command]
+async fn run_parameter_sweep(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    spec: SweepSpec,
+    output_dir: String,
+    progress: Channel<SweepProgress>,
+) -> Result<SweepReport, CommandError> {
+    let (spec, pendulum, dt, cancel) = (instances.call(instance, move |app_data| {
+        spec.validate(&app_data.pendulum, app_data.dt)?;
+        app_data.sweep_cancel.store(true, Ordering::Relaxed);
+        app_data.sweep_cancel = Arc::new(AtomicBool::new(false));
+        let cancel = app_data.sweep_cancel.clone();
+        Ok((spec, app_data.pendulum.clone(), app_data.dt, cancel))
+    }))
+    .await?;
+    tokio::task::spawn_blocking(move || {
+        sweep::run_sweep(&spec, &pendulum, dt, output_dir.as_ref(), &cancel, |p| {
+            let _ = progress.send(p);
+        })
+    })
+    .await
+    .map_err(CommandError::internal)??
+    .ok_or_else(|| CommandError::Cancelled {
+        reason: "Parameter sweep was cancelled".into(),
+    })
+}
+
+#[tauri::command]
+async fn cancel_parameter_sweep(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.sweep_cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        })
+        .await
+}
+
+/// Writes the kinetic, potential and total energy to the CSV file at
+/// `path`, at every step of a run forward from the current state or every
+/// sample of a recording or trajectory file, worked out again from its
+/// angles and the configuration it keeps. With joint friction on there is
+/// a column of the energy it has taken out since the first row. The physics
+/// task only copies what is worked from.
+#[tauri::command]
+async fn export_energy_series(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+    source: EnergySource,
+) -> Result<FileReport, CommandError> {
+    let input = (instances.call(instance, move |app_data| source.capture(app_data))).await?;
+    tokio::task::spawn_blocking(move || energy::export(path.as_ref(), input))
+        .await
+        .map_err(CommandError::internal)?
+}
+
+/// Writes the phase-space trajectory of `bobs`, by index, every bob if
+/// `None`, to the CSV file at `path`: a row per sample of the rewind
+/// history or a recording, each bob's angle both wrapped to (-π, π] and
+/// unwrapped, its angular velocity and, with `options.momenta`, its
+/// momentum. With `options.section` only the crossings of the Poincaré
+/// section are written. Angles are canonical radians whatever the commands
+/// show them as.
+#[tauri::command]
+async fn export_phase_space(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+    bobs: Option<Vec<usize>>,
+    source: PhaseSource,
+    options: Option<PhaseSpaceOptions>,
+) -> Result<FileReport, CommandError> {
+    let input = (instances.call(instance, move |app_data| source.capture(app_data))).await?;
+    let options = options.unwrap_or_default();
+    tokio::task::spawn_blocking(move || phase::export(path.as_ref(), input, bobs, options))
+        .await
+        .map_err(CommandError::internal)?
+}
+
+/// Where the pendulum would go from here, for previewing an edit while
+/// paused. Integrates a copy off the physics task; a newer prediction cancels
+/// this one.
+#[tauri::command]
+async fn predict(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    horizon_seconds: f64,
+    sample_dt: f64,
+) -> Result<Prediction, CommandError> {
+    let (pendulum, sim_time, dt, cancel, world_frame) =
+        (instances.call(instance, move |app_data| {
+            prediction::validate(horizon_seconds, sample_dt, app_data.dt)?;
+            app_data.prediction_cancel.store(true, Ordering::Relaxed);
+            app_data.prediction_cancel = Arc::new(AtomicBool::new(false));
+            Ok((
+                app_data.pendulum.clone(),
+                app_data.sim_time,
+                app_data.dt,
+                app_data.prediction_cancel.clone(),
+                app_data.world_frame,
+            ))
+        }))
+        .await?;
+    let prediction = tokio::task::spawn_blocking(move || {
+        prediction::predict(pendulum, sim_time, dt, horizon_seconds, sample_dt, &cancel)
+    })
+    .await
+    .map_err(CommandError::internal)?
+    .ok_or_else(|| CommandError::Cancelled {
+        reason: "Prediction was superseded by a newer one".into(),
+    })?;
+    Ok(world_frame.outgoing(prediction))
+}
+
+/// Skips the simulation `seconds` ahead as fast as possible, for getting past
+/// a transient, then lets real-time stepping resume from there. Starting a
+/// new warm-up cancels the previous one.
+#[tauri::command]
+async fn warm_up(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    seconds: f64,
+    progress: Channel<WarmUpProgress>,
+) -> Result<WarmUpReport, CommandError> {
+    warmup::validate(seconds)?;
+    let cancel = (instances.call(instance, move |app_data| {
+        app_data.warm_up_cancel.store(true, Ordering::Relaxed);
+        app_data.warm_up_cancel = Arc::new(AtomicBool::new(false));
+        Ok(app_data.warm_up_cancel.clone())
+    }))
+    .await?;
+    let instances = instances.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        warmup::warm_up(
+            &instances,
+            instance,
+            seconds,
+            WARM_UP_WALL_BUDGET,
+            &cancel,
+            |p| {
+                let _ = progress.send(p);
+            },
+        )
+    })
+    .await
+    .map_err(CommandError::internal)?
+}
+
+/// Takes exactly `steps` substeps of `dt` and returns the state they lead
+/// to, for scripts and regression tests. Real-time stepping waits meanwhile;
+/// a warm-up started meanwhile cancels it.
+#[tauri::command]
+async fn step_n(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    steps: u64,
+    dt: f64,
+) -> Result<SteppedState, CommandError> {
+    warmup::validate_step_n(steps, dt)?;
+    let (cancel, format, world_frame) = (instances.call(instance, move |app_data| {
+        if app_data.warming_up {
+            return Err(CommandError::busy("A warm-up is in progress"));
+        }
+        app_data.warm_up_cancel = Arc::new(AtomicBool::new(false));
+        Ok((
+            app_data.warm_up_cancel.clone(),
+            app_data.angle_format(),
+            app_data.world_frame,
+        ))
+    }))
+    .await?;
+    let instances = instances.inner().clone();
+    let stepped = tokio::task::spawn_blocking(move || {
+        warmup::step_n(
+            &instances,
+            instance,
+            steps,
+            dt,
+            WARM_UP_WALL_BUDGET,
+            &cancel,
+        )
+    })
+    .await
+    .map_err(CommandError::internal)??;
+    Ok(world_frame.outgoing(format.outgoing(stepped)))
+}
+
+#[tauri::command]
+async fn cancel_warm_up(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data.warm_up_cancel.store(true, Ordering::Relaxed);
+            Ok(())
+        })
+        .await
+}
+
+/// Measures the substeps a second this machine manages with the current
+/// chain, dt and ensemble, for `seconds` of wall time, and how fast frames
+/// of it are built. It steps a copy off the physics task, so the run
+/// carries on unchanged meanwhile.
+#[tauri::command]
+async fn run_benchmark(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    seconds: f64,
+) -> Result<BenchmarkReport, CommandError> {
+    let seconds = benchmark::validate(seconds)?;
+    let copy = (instances.call(instance, |app_data| Ok(benchmark::copy(app_data)))).await?;
+    tokio::task::spawn_blocking(move || benchmark::run(copy, seconds))
+        .await
+        .map_err(CommandError::internal)
+}
+
+#[tauri::command]
+async fn get_dynamics_terms(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<DynamicsTerms, CommandError> {
+    let pendulum = (instances.call(instance, |app_data| Ok(app_data.pendulum.clone()))).await?;
+    Ok(pendulum.dynamics_terms())
+}
+
+#[tauri::command]
+async fn set_dynamics_overlay(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    enabled: bool,
+    every_n_frames: Option<u32>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let every = every_n_frames.unwrap_or(DYNAMICS_OVERLAY_EVERY);
+            if every == 0 {
+                return Err(CommandError::invalid(
+                    "every_n_frames",
+                    "must be at least 1",
+                ));
+            }
+            app_data.dynamics_overlay_every = enabled.then_some(every);
+            Ok(())
+        })
+        .await
+}
+
+/// Adds a rule that emits `pendulum://alert` when `quantity` rises above
+/// `threshold`, and returns its id. The rule rearms once the quantity drops
+/// below `threshold - hysteresis`; `hysteresis` defaults to 5% of the
+/// threshold. Both are in the angle unit set for `maxAngularSpeed`.
+#[tauri::command]
+async fn add_alert_rule(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    quantity: AlertQuantity,
+    threshold: f64,
+    hysteresis: Option<f64>,
+) -> Result<u64, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let (threshold, hysteresis) = match quantity {
+                AlertQuantity::MaxAngularSpeed => {
+                    let format = app_data.angle_format();
+                    let hysteresis = hysteresis.map(|h| format.incoming_magnitude(h));
+                    (format.incoming_magnitude(threshold), hysteresis)
+                }
+                _ => (threshold, hysteresis),
+            };
+            app_data.alerts.add(quantity, threshold, hysteresis)
+        })
+        .await
+}
+
+#[tauri::command]
+async fn remove_alert_rule(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    id: u64,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, move |app_data| Ok(app_data.alerts.remove(id)))
+        .await
+}
+
+#[tauri::command]
+async fn list_alert_rules(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<Vec<AlertRule>, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let unit = app_data.angle_unit;
+            let mut rules = app_data.alerts.list();
+            for rule in &mut rules {
+                if rule.quantity == AlertQuantity::MaxAngularSpeed {
+                    rule.threshold = unit.in_unit(rule.threshold);
+                    rule.hysteresis = unit.in_unit(rule.hysteresis);
+                }
+            }
+            Ok(rules)
+        })
+        .await
+}
+
+/// Starts capturing every substep in memory, for playing back as a ghost or
+/// a replay. `start_recording` writes them to a file instead.
+#[tauri::command]
+async fn start_memory_recording(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            if app_data.recording.is_some() {
+                return Err(CommandError::busy("Already recording"));
+            }
+            let mut recording = Recording::default();
+            recording.push(&app_data.pendulum, app_data.sim_time);
+            app_data.recording = Some(recording);
+            Ok(())
+        })
+        .await
+}
+
+/// Ends the recording in memory and keeps it under a new id.
+#[tauri::command]
+async fn stop_memory_recording(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<RecordingSummary, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            app_data
+                .finish_recording()
+                .ok_or_else(|| CommandError::unavailable("Not recording"))
+        })
+        .await
+}
+
+/// Starts writing the trajectory to a file at `path`, replacing what is
+/// there, as CSV by default: a `# configuration` line with the masses and
+/// joint friction as JSON, a header row naming the columns with their
+/// units, then a row per substep, or per `options.decimation` of them, with
+/// the sim time, each bob's θ, ω, x and y, and the pivot, in canonical
+/// radians and meters with y up. With `options.format` binary, the file
+/// holds the same substeps as binary frames instead, with each bob's length
+/// and mass for its position. The file is created before the recording
+/// starts and written on a thread of its own; if writing fails, or the
+/// number of bobs changes, the recording stops with a
+/// `pendulum://recording-failed` event.
+#[tauri::command]
+async fn start_recording(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+    options: Option<RecordingOptions>,
+) -> Result<(), CommandError> {
+    let busy = || CommandError::busy("Already recording to a file");
+    // checked before creating the file, which may be the one being written
+    let recording = instances.call(instance, |app_data| {
+        Ok(app_data.trajectory_recording.is_some())
+    });
+    if recording.await? {
+        return Err(busy());
+    }
+    let file = tokio::task::spawn_blocking(move || {
+        TrajectoryFile::create(path, options.unwrap_or_default())
+    })
+    .await
+    .map_err(CommandError::internal)??;
+    instances
+        .call(instance, move |app_data| {
+            if app_data.trajectory_recording.is_some() {
+                return Err(busy());
+            }
+            let recording = TrajectoryRecording::start(file, &app_data.pendulum, app_data.dt)?;
+            app_data.trajectory_recording = Some(recording);
+            Ok(())
+        })
+        .await
+}
+
+/// Ends the recording to a file, once the last rows are in it, with how
+/// many rows it has and how large it is.
+#[tauri::command]
+async fn stop_recording(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<FileReport, CommandError> {
+    let recording = (instances.call(instance, |app_data| {
+        (app_data.trajectory_recording.take())
+            .ok_or_else(|| CommandError::unavailable("Not recording to a file"))
+    }))
+    .await?;
+    tokio::task::spawn_blocking(move || recording.finish())
+        .await
+        .map_err(CommandError::internal)?
+}
+
+/// Plays a recording back in every positions frame's `ghostBobs`, starting
+/// `offset` sim seconds into it and running on the live sim clock.
+#[tauri::command]
+async fn start_ghost(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    recording_id: u64,
+    offset: Option<f64>,
+    looped: Option<bool>,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let offset = validate_offset(offset.unwrap_or(0.0))?;
+            let recording = app_data
+                .recordings
+                .iter()
+                .find(|(id, _)| *id == recording_id)
+                .map(|(_, recording)| recording.clone())
+                .ok_or_else(|| CommandError::not_found("recording", recording_id))?;
+            let ghost = Ghost::new(
+                recording,
+                app_data.sim_time,
+                offset,
+                looped.unwrap_or(false),
+            );
+            app_data.ghost = Some(ghost);
+            Ok(())
+        })
+        .await
+}
+
+/// Stops the ghost, returning whether one was playing.
+#[tauri::command]
+async fn stop_ghost(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data.ghost.take().is_some())
+        })
+        .await
+}
+
+/// Reads a file `start_recording` wrote, CSV or binary, and keeps it as
+/// a recording under a new id, for `start_replay` and `start_ghost`; see
+/// `read_recording`.
+#[tauri::command]
+async fn load_recording(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+) -> Result<RecordingSummary, CommandError> {
+    let recording = tokio::task::spawn_blocking(move || {
+        let bytes = std::fs::read(&path).map_err(|e| CommandError::io(&path, e))?;
+        read_recording(&bytes)
+    })
+    .await
+    .map_err(CommandError::internal)??;
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data.keep_recording(recording))
+        })
+        .await
+}
+
+/// Drops the recording `recording_id`, returning whether it was kept. A
+/// ghost or replay of it plays on to its end.
+#[tauri::command]
+async fn delete_recording(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    recording_id: u64,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data.delete_recording(recording_id))
+        })
+        .await
+}
+
+/// Writes a binary recording at `path` out as CSV next to it, for other
+/// tools, or a CSV one as compressed binary; see `trajectory::convert`.
+#[tauri::command]
+async fn convert_recording(path: String, to_csv: bool) -> Result<FileReport, CommandError> {
+    tokio::task::spawn_blocking(move || trajectory::convert(path.as_ref(), to_csv))
+        .await
+        .map_err(CommandError::internal)?
+}
+
+/// Plays a recording, the newest by default, in the positions frames in
+/// place of the live run, at `speed` times the pace it was recorded at,
+/// from its start. The live run stands still meanwhile, untouched, so the
+/// recording may have any number of bobs. Replaces a replay playing
+/// already.
+#[tauri::command]
+async fn start_replay(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    recording_id: Option<u64>,
+    speed: Option<f64>,
+    looped: Option<bool>,
+) -> Result<ReplayStatus, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let (id, recording) = match recording_id {
+                Some(wanted) => app_data.recordings.iter().find(|(id, _)| *id == wanted),
+                None => app_data.recordings.last(),
+            }
+            .cloned()
+            .ok_or_else(|| match recording_id {
+                Some(id) => CommandError::not_found("recording", id),
+                None => CommandError::unavailable("No recordings"),
+            })?;
+            let replay = Replay::new(id, recording, speed.unwrap_or(1.0), looped.unwrap_or(false))?;
+            let status = replay.status();
+            app_data.replay = Some(replay);
+            app_data.replay_changed();
+            Ok(status)
+        })
+        .await
+}
+
+/// Ends the replay, returning whether one was playing. The live run carries
+/// on from where it stood.
+#[tauri::command]
+async fn stop_replay(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<bool, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let stopped = app_data.replay.take().is_some();
+            if stopped {
+                app_data.replay_changed();
+            }
+            Ok(stopped)
+        })
+        .await
+}
+
+/// Jumps the replay to `time`, in sim seconds on the recording's axis.
+#[tauri::command]
+async fn seek_replay(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    time: f64,
+) -> Result<ReplayStatus, CommandError> {
+    instances
+        .edit(instance, move |app_data| {
+            let replay = (app_data.replay.as_mut())
+                .ok_or_else(|| CommandError::unavailable("No replay is playing"))?;
+            replay.seek(time)?;
+            let status = replay.status();
+            app_data.replay_changed();
+            Ok(status)
+        })
+        .await
+}
+
+/// Keeps the current moment in memory under `name`, for `restore_snapshot`,
+/// replacing one saved under it before. Returns whether one was.
+#[tauri::command]
+async fn save_snapshot(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    name: String,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let saved_at_ms = app_data.wall_time_ms(tokio::time::Instant::now());
+            app_data.save_snapshot(&name, saved_at_ms)
+        })
+        .await
+}
+
+/// The saved snapshots, oldest first.
+#[tauri::command]
+async fn list_snapshots(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<Vec<SnapshotInfo>, CommandError> {
+    instances
+        .call(instance, move |app_data| Ok(app_data.snapshots.list()))
+        .await
+}
+
+/// Goes back to the moment saved under `name` and pushes a frame of it at
+/// once, with a `restored` notice.
+#[tauri::command]
+async fn restore_snapshot(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    name: String,
+) -> Result<(), CommandError> {
+    instances
+        .edit(instance, move |app_data| app_data.restore_snapshot(&name))
+        .await
+}
+
+/// Goes back about `seconds` of sim time and carries on from there, with a
+/// frame of it pushed at once; see `AppDataInner::rewind`. Returns the sim
+/// time landed at.
+#[tauri::command]
+async fn rewind(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    seconds: f64,
+) -> Result<f64, CommandError> {
+    instances
+        .edit(instance, move |app_data| app_data.rewind(seconds))
+        .await
+}
+
+/// The chain at a past `sim_time`, worked out from the states kept for
+/// `rewind` without touching the live simulation; see
+/// `RewindBuffer::state_at`. Older than the oldest kept, or before a
+/// structural change, is an `outOfHistory` error.
+#[tauri::command]
+async fn get_state_at(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    sim_time: f64,
+) -> Result<PastState, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let past = (app_data.rewind_buffer).state_at(
+                sim_time,
+                &app_data.pendulum,
+                app_data.sim_time,
+                app_data.dt,
+            )?;
+            Ok(app_data
+                .world_frame
+                .outgoing(app_data.angle_format().outgoing(past)))
+        })
+        .await
+}
+
+/// How the states for `rewind` are kept and how far back it can go now.
+#[tauri::command]
+async fn get_rewind_buffer(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<RewindStatus, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data.rewind_buffer.status(app_data.sim_time))
+        })
+        .await
+}
+
+/// Sets how often states are kept for `rewind` and for how many sim
+/// seconds, `DEFAULT_REWIND_INTERVAL` and `DEFAULT_REWIND_SECONDS` to begin
+/// with. Those already kept stay as far as they fit.
+#[tauri::command]
+async fn set_rewind_buffer(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    config: RewindConfig,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            config.validate()?;
+            app_data.rewind_buffer.configure(config);
+            Ok(())
+        })
+        .await
+}
+
+/// Every bob's recent positions, in chain order, with the sim time and
+/// speed of each and the range of those speeds, the newest `max_points` of
+/// them if given; see `TrailBuffer`. A bob moved by a structural change,
+/// and every bob after a reset, has an empty trail until it moves again.
+#[tauri::command]
+async fn get_trails(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    max_points: Option<usize>,
+) -> Result<Vec<Trail>, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            trails::validate_max_points(max_points)?;
+            Ok(app_data
+                .world_frame
+                .outgoing(app_data.trails.trails(max_points)))
+        })
+        .await
+}
+
+/// Bob `bob`'s trail simplified with Douglas–Peucker to at most
+/// `max_points` points, none of those left out farther than `tolerance`, in
+/// meters, from the polyline that is left unless the cap is reached first;
+/// `maxError` says how far they are. The speeds of the points left out
+/// are carried by the nearest kept ones; see `SimplifiedTrail::speeds`.
+/// The physics task only copies the trail.
+#[tauri::command]
+async fn get_simplified_trail(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    bob: usize,
+    tolerance: f64,
+    max_points: usize,
+) -> Result<SimplifiedTrail, CommandError> {
+    trails::validate_simplification(tolerance, max_points)?;
+    let (bob_id, points, world_frame) = (instances.call(instance, move |app_data| {
+        let len = app_data.pendulum.n();
+        let bob_id = (app_data.pendulum.bobs.get(bob))
+            .ok_or(CommandError::IndexOutOfBounds { index: bob, len })?
+            .id;
+        let points = app_data.trails.trail_of(bob_id);
+        Ok((bob_id, points, app_data.world_frame))
+    }))
+    .await?;
+    let trail = trails::simplified(bob_id, &points, tolerance, max_points);
+    Ok(world_frame.outgoing(trail))
+}
+
+/// Draws the stored trail of the tip, or of `options.bobs`, or their paths
+/// through a recording, as an SVG file at `path`, framed by the box around
+/// the motion; see `SvgOptions`. The physics task only copies what is
+/// drawn.
+#[tauri::command]
+async fn export_trail_svg(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+    options: Option<SvgOptions>,
+) -> Result<SvgReport, CommandError> {
+    let options = options.unwrap_or_default();
+    options.validate()?;
+    let capture = options.clone();
+    let source = (instances.call(instance, move |app_data| {
+        SvgSource::capture(app_data, &capture)
+    }))
+    .await?;
+    tokio::task::spawn_blocking(move || svg::export(path.as_ref(), source, &options))
+        .await
+        .map_err(CommandError::internal)?
+}
+
+/// Renders where the tip has been since the chain last changed shape or
+/// started over, a count per substep, to a square PNG at `path` of
+/// `resolution` pixels a side, `DEFAULT_HEATMAP_RESOLUTION` if `None`. The
+/// counts are colored in log scale by `colormap`, with the pivot and the
+/// circle the tip can reach drawn on top if `overlay`. The path is checked
+/// first, and the render is done on a copy of the counts.
+#[tauri::command]
+async fn export_heatmap_png(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    path: String,
+    colormap: Option<Colormap>,
+    resolution: Option<u32>,
+    overlay: Option<bool>,
+) -> Result<HeatmapReport, CommandError> {
+    let resolution =
+        heatmap::validate_resolution(resolution.unwrap_or(DEFAULT_HEATMAP_RESOLUTION))?;
+    heatmap::check_writable(path.as_ref())?;
+    let grid = (instances.call(instance, |app_data| Ok(app_data.occupancy.clone()))).await?;
+    let (colormap, overlay) = (colormap.unwrap_or_default(), overlay.unwrap_or(false));
+    tokio::task::spawn_blocking(move || {
+        heatmap::export(path.as_ref(), &grid, colormap, resolution, overlay)
+    })
+    .await
+    .map_err(CommandError::internal)?
+}
+
+/// Sets how often trail points are kept and how many per bob,
+/// `DEFAULT_TRAIL_INTERVAL` and `DEFAULT_TRAIL_POINTS` to begin with. Those
+/// already kept stay as far as they fit.
+#[tauri::command]
+async fn set_trail_buffer(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    config: TrailConfig,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            config.validate()?;
+            app_data.trails.configure(config);
+            Ok(())
+        })
+        .await
+}
+
+/// The box the pivot and every bob have kept inside over the bounds
+/// window, for framing the view; see `MotionBounds`.
+#[tauri::command]
+async fn get_motion_bounds(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<Bounds, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data
+                .world_frame
+                .outgoing(app_data.motion_bounds.bounds(&app_data.pendulum)))
+        })
+        .await
+}
+
+/// Sets how many sim seconds of motion the bounds cover,
+/// `DEFAULT_BOUNDS_WINDOW` to begin with, and how often the positions
+/// frames carry them, never to begin with.
+#[tauri::command]
+async fn set_motion_bounds(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    config: BoundsConfig,
+) -> Result<(), CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            config.validate()?;
+            let app_data = &mut *app_data;
+            (app_data.motion_bounds).configure(
+                config,
+                &app_data.pendulum,
+                &app_data.trails,
+                app_data.sim_time,
+            );
+            Ok(())
+        })
+        .await
+}
+
+/// Forgets a snapshot, returning whether there was one under `name`.
+#[tauri::command]
+async fn delete_snapshot(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    name: String,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data.snapshots.remove(&name))
+        })
+        .await
+}
+
+/// Replaces any ensemble with `size` copies of the pendulum, angles
+/// perturbed by noise of standard deviation `scale`, in the angle unit set.
+/// Without a seed a random one is drawn; it is returned for recreating the
+/// same cloud. Single `precision` steps the copies of a two-bob chain many
+/// times faster.
+#[tauri::command]
+async fn create_ensemble(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    size: usize,
+    scale: f64,
+    seed: Option<u64>,
+    on_edit: Option<EnsembleEditPolicy>,
+    precision: Option<EnsemblePrecision>,
+) -> Result<EnsembleInfo, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            let scale = app_data.angle_format().incoming_magnitude(scale);
+            ensemble::validate(size, scale)?;
+            let precision = precision.unwrap_or_default();
+            ensemble::validate_precision(precision, &app_data.pendulum)?;
+            let seed = seed.unwrap_or_else(rand::random);
+            let ensemble = Ensemble::new(
+                &app_data.pendulum,
+                size,
+                scale,
+                seed,
+                on_edit.unwrap_or_default(),
+                precision,
+            );
+            let mut info = ensemble.info();
+            info.scale = app_data.angle_unit.in_unit(info.scale);
+            app_data.ensemble = Some(ensemble);
+            Ok(info)
+        })
+        .await
+}
+
+/// Drops the ensemble, returning whether there was one.
+#[tauri::command]
+async fn dissolve_ensemble(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, move |app_data| {
+            Ok(app_data.ensemble.take().is_some())
+        })
+        .await
+}
+
+/// Default OSC address prefix: bobs go to `/pendulum/bob/<i>/theta` etc.
+const DEFAULT_OSC_PREFIX: &str = "/pendulum";
+
+/// Sends the bobs to an OSC receiver at `host:port`, `rate` bundles per
+/// second, independently of the UI stream. Replaces any running sender.
+#[tauri::command]
+async fn start_osc(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    host: String,
+    port: u16,
+    rate: u32,
+    address_prefix: Option<String>,
+) -> Result<(), CommandError> {
+    #[cfg(feature = "osc")]
+    {
+        let prefix = address_prefix.as_deref().unwrap_or(DEFAULT_OSC_PREFIX);
+        let sender = osc::OscSender::connect(&host, port, rate, prefix)?;
+        instances
+            .call(instance, move |app_data| {
+                app_data.osc = Some(sender);
+                Ok(())
+            })
+            .await
+    }
+    #[cfg(not(feature = "osc"))]
+    {
+        let _ = (host, port, rate, address_prefix, DEFAULT_OSC_PREFIX);
+        instances.call(instance, |_| Ok(())).await?;
+        Err(CommandError::Unsupported {
+            feature: "OSC",
+            flag: "osc",
+        })
+    }
+}
+
+/// Stops the OSC sender, returning whether one was running.
+#[tauri::command]
+async fn stop_osc(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, |app_data| {
+            #[cfg(feature = "osc")]
+            return Ok(app_data.osc.take().is_some());
+            #[cfg(not(feature = "osc"))]
+            {
+                let _ = app_data;
+                Ok(false)
+            }
+        })
+        .await
+}
+
+/// Serves the positions frames over WebSocket on `port` (0 for any free
+/// port), to local clients only unless `allow_remote`, replacing a running
+/// server. Clients connect with the returned token and may send `pause`,
+/// `resume` and `modify_bob` control messages.
+#[cfg(feature = "websocket")]
+#[tauri::command]
+async fn start_ws_server(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+    port: u16,
+    allow_remote: Option<bool>,
+) -> Result<ws::WsServerInfo, CommandError> {
+    let (listener, server) = ws::bind(port, allow_remote.unwrap_or(false)).await?;
+    let info = server.info();
+    let shutdown = server.shutdown_signal();
+    (instances.call(instance, move |app_data| {
+        app_data.ws_server = Some(server);
+        Ok(())
+    }))
+    .await?;
+    let token = info.token.clone();
+    let instances = instances.inner().clone();
+    tauri::async_runtime::spawn(async move {
+        ws::serve(&instances, instance, listener, &token, &shutdown).await;
+    });
+    Ok(info)
+}
+
+#[cfg(not(feature = "websocket"))]
+#[tauri::command]
+fn start_ws_server(
+    instance: Option<u64>,
+    port: u16,
+    allow_remote: Option<bool>,
+) -> Result<(), CommandError> {
+    let _ = (instance, port, allow_remote);
+    Err(CommandError::Unsupported {
+        feature: "WebSocket",
+        flag: "websocket",
+    })
+}
+
+/// Shuts the WebSocket server down, returning whether one was running.
+#[tauri::command]
+async fn stop_ws_server(
+    instances: tauri::State<'_, Instances>,
+    instance: Option<u64>,
+) -> Result<bool, CommandError> {
+    instances
+        .call(instance, |app_data| {
+            #[cfg(feature = "websocket")]
+            return Ok(app_data.ws_server.take().is_some());
+            #[cfg(not(feature = "websocket"))]
+            {
+                let _ = app_data;
+                Ok(false)
+            }
+        })
+        .await
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::error::CommandError;
+use crate::state::AppDataInner;
+
+/// Longest `run_benchmark` may run for, in wall seconds.
+pub(crate) const MAX_BENCHMARK_SECONDS: f64 = 10.0;
+/// Share of a benchmark spent building frames rather than stepping.
+const FRAME_SHARE: f64 = 0.1;
+/// Substeps or frames between reads of the clock.
+const BETWEEN_READS: u64 = 16;
+
+/// What `run_benchmark` returns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BenchmarkReport {
+    /// Substeps taken, each with everything that rides along, and the wall
+    /// seconds they took.
+    pub(crate) steps: u64,
+    pub(crate) seconds: f64,
+    pub(crate) steps_per_second: f64,
+    /// Sim seconds a wall second at that rate and the configured dt; under
+    /// 1 the run can't keep up in real time.
+    pub(crate) real_time_factor: f64,
+    /// Positions frames built and serialized to JSON a second.
+    pub(crate) frames_per_second: f64,
+    /// Size of one, in bytes.
+    pub(crate) frame_bytes: usize,
+}
+
+pub(crate) fn validate(seconds: f64) -> Result<f64, CommandError> {
+    if seconds > 0.0 && seconds <= MAX_BENCHMARK_SECONDS {
+        Ok(seconds)
+    } else {
+        Err(CommandError::invalid(
+            "seconds",
+            format!("must be positive and at most {MAX_BENCHMARK_SECONDS} s, got {seconds}"),
+        ))
+    }
+}
+
+/// A state to time in place of `data`'s: what its substeps step, the chain,
+/// dt and ensemble, and what its frames carry, without the recordings, the
+/// comparison or the wave, so the run is left alone and nothing is written.
+pub(crate) fn copy(data: &AppDataInner) -> AppDataInner {
+    AppDataInner {
+        pendulum: data.pendulum.clone(),
+        dt: data.dt,
+        ensemble: data.ensemble.clone(),
+        dynamics_overlay_every: data.dynamics_overlay_every,
+        ..AppDataInner::default()
+    }
+}
+
+/// The default state with a chain of some length, whose positions frames
+/// the frame bench builds.
+pub struct FrameSource {
+    data: AppDataInner,
+    built: u64,
+}
+
+impl FrameSource {
+    /// The default state, with `bobs` bobs.
+    pub fn new(bobs: usize) -> Self {
+        let mut data = AppDataInner::default();
+        data.set_bob_count(bobs, None)
+            .expect("a positive bob count");
+        Self { data, built: 0 }
+    }
+
+    /// The next frame, serialized to JSON as it is sent.
+    pub fn next_json(&mut self) -> Vec<u8> {
+        self.built += 1;
+        serialized_frame(&self.data, self.built - 1)
+    }
+}
+
+fn serialized_frame(data: &AppDataInner, frame: u64) -> Vec<u8> {
+    serde_json::to_vec(&data.frame(frame, 0.0)).expect("frames serialize")
+}
+
+/// Positions frames of `data` built and serialized a second, over `budget`,
+/// and the size of the last.
+pub(crate) fn frame_throughput(data: &AppDataInner, budget: Duration) -> (f64, usize) {
+    let started = Instant::now();
+    let (mut frames, mut bytes) = (0, 0);
+    while frames == 0 || started.elapsed() < budget {
+        for _ in 0..BETWEEN_READS {
+            bytes = serialized_frame(data, frames).len();
+            frames += 1;
+        }
+    }
+    (frames as f64 / started.elapsed().as_secs_f64(), bytes)
+}
+
+/// Steps `data` for most of `seconds` of wall time and builds frames of it
+/// for the rest.
+pub(crate) fn run(mut data: AppDataInner, seconds: f64) -> BenchmarkReport {
+    let stepping = Duration::from_secs_f64(seconds * (1.0 - FRAME_SHARE));
+    let (dt, mut events) = (data.dt, Vec::new());
+    let started = Instant::now();
+    let mut steps = 0;
+    while steps == 0 || started.elapsed() < stepping {
+        for _ in 0..BETWEEN_READS {
+            data.substep(dt, &mut events);
+        }
+        steps += BETWEEN_READS;
+        events.clear();
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    let steps_per_second = steps as f64 / elapsed;
+    let frames = Duration::from_secs_f64(seconds * FRAME_SHARE);
+    let (frames_per_second, frame_bytes) = frame_throughput(&data, frames);
+    BenchmarkReport {
+        steps,
+        seconds: elapsed,
+        steps_per_second,
+        real_time_factor: steps_per_second * dt,
+        frames_per_second,
+        frame_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ensemble::{Ensemble, EnsembleEditPolicy, EnsemblePrecision};
+
+    #[test]
+    fn the_benchmark_steps_a_copy_and_leaves_the_run_alone() {
+        let mut live = AppDataInner::default();
+        live.ensemble = Some(Ensemble::new(
+            &live.pendulum,
+            8,
+            1e-3,
+            1,
+            EnsembleEditPolicy::Dissolve,
+            EnsemblePrecision::Double,
+        ));
+        let before = live.pendulum.clone();
+        let report = run(copy(&live), 0.2);
+        assert_eq!(live.pendulum, before);
+        assert_eq!(live.sim_time, 0.0);
+        assert!(report.steps > 0 && report.steps.is_multiple_of(BETWEEN_READS));
+        assert!(report.seconds >= 0.2 * (1.0 - FRAME_SHARE));
+        let rate = report.steps as f64 / report.seconds;
+        assert!((report.steps_per_second - rate).abs() < 1e-9);
+        assert!((report.real_time_factor - rate * live.dt).abs() < 1e-9);
+        assert!(report.frames_per_second > 0.0 && report.frame_bytes > 0);
+
+        // a longer chain takes longer a step
+        let mut long = copy(&live);
+        long.set_bob_count(12, None).unwrap();
+        assert!(run(long, 0.2).steps_per_second < report.steps_per_second);
+
+        assert!(validate(0.0).is_err());
+        assert!(validate(MAX_BENCHMARK_SECONDS + 1.0).is_err());
+        assert_eq!(validate(1.0).unwrap(), 1.0);
+    }
+}
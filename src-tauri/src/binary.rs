@@ -0,0 +1,344 @@
+use pendulum_core::{Coordinate, Pendulum};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::error::CommandError;
+use crate::lz4;
+use crate::recording::{
+    RecordedBob, Recording, RecordingConfiguration, Sample, MAX_RECORDING_SAMPLES,
+};
+
+/// First bytes of every binary recording.
+pub(crate) const BINARY_MAGIC: &[u8; 4] = b"DPTR";
+/// Version of the binary recordings `TrajectoryRecording` writes. Version 1 had
+/// no configuration after the field lists.
+pub(crate) const BINARY_VERSION: u8 = 2;
+/// Ends a complete file, followed by the number of frames in it. A file
+/// without it was cut short.
+const FOOTER_MAGIC: &[u8; 4] = b"DPTE";
+const FOOTER_LEN: usize = FOOTER_MAGIC.len() + 8;
+/// Header flag of files whose frames are in LZ4 blocks.
+const COMPRESSED: u8 = 1;
+
+/// The values each frame starts with, in order.
+const FRAME_FIELDS: [&str; 3] = ["sim_time", "pivot_x", "pivot_y"];
+/// The values of each bob that follow them, in order: canonical radians,
+/// radians per second, meters and kilograms.
+const BOB_FIELDS: [&str; 4] = ["theta", "omega", "length_rod", "mass"];
+
+/// What a binary recording's header says. The field lists let newer files
+/// add values older readers skip.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BinaryHeader {
+    pub(crate) bobs: usize,
+    /// Sim seconds between frames as the recording started.
+    pub(crate) dt: f64,
+    pub(crate) compressed: bool,
+    /// Written as JSON after the field lists, its length first; a length
+    /// of zero for none.
+    pub(crate) configuration: Option<RecordingConfiguration>,
+}
+
+impl BinaryHeader {
+    /// Values in a frame.
+    pub(crate) fn columns(&self) -> usize {
+        FRAME_FIELDS.len() + BOB_FIELDS.len() * self.bobs
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let bobs = u16::try_from(self.bobs)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many bobs"))?;
+        out.write_all(BINARY_MAGIC)?;
+        out.write_all(&[BINARY_VERSION, if self.compressed { COMPRESSED } else { 0 }])?;
+        out.write_all(&bobs.to_le_bytes())?;
+        out.write_all(&self.dt.to_le_bytes())?;
+        for fields in [&FRAME_FIELDS[..], &BOB_FIELDS[..]] {
+            out.write_all(&[fields.len() as u8])?;
+            for field in fields {
+                out.write_all(&[field.len() as u8])?;
+                out.write_all(field.as_bytes())?;
+            }
+        }
+        let configuration = match &self.configuration {
+            Some(configuration) => serde_json::to_vec(configuration)?,
+            None => Vec::new(),
+        };
+        out.write_all(&(configuration.len() as u32).to_le_bytes())?;
+        out.write_all(&configuration)?;
+        Ok(())
+    }
+}
+
+/// Appends the frame of `pendulum` at `sim_time`, in field order.
+pub(crate) fn row(pendulum: &Pendulum, sim_time: f64, row: &mut Vec<f64>) {
+    row.extend([sim_time, pendulum.pivot.x, pendulum.pivot.y]);
+    for bob in &pendulum.bobs {
+        row.extend([bob.theta, bob.omega, bob.length_rod, bob.mass]);
+    }
+}
+
+/// The writer's side of a binary recording: the header, then the frames of
+/// every chunk, each chunk one LZ4 block if compressed, then the footer
+/// once the chunks run out. Returns the frames written.
+pub(crate) fn write_frames(
+    file: File,
+    header: &BinaryHeader,
+    chunks: impl IntoIterator<Item = Vec<f64>>,
+) -> io::Result<u64> {
+    let mut out = BufWriter::new(file);
+    header.write(&mut out)?;
+    let mut frames = 0;
+    for chunk in chunks {
+        let bytes: Vec<u8> = chunk.iter().flat_map(|v| v.to_le_bytes()).collect();
+        if header.compressed {
+            let block = lz4::compress(&bytes);
+            out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            out.write_all(&(block.len() as u32).to_le_bytes())?;
+            out.write_all(&block)?;
+        } else {
+            out.write_all(&bytes)?;
+        }
+        frames += (chunk.len() / header.columns()) as u64;
+        out.flush()?;
+    }
+    out.write_all(FOOTER_MAGIC)?;
+    out.write_all(&frames.to_le_bytes())?;
+    out.flush()?;
+    Ok(frames)
+}
+
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(BINARY_MAGIC)
+}
+
+/// Reads through a file, every shortfall an error naming what was cut off.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize, what: &str) -> Result<&'a [u8], CommandError> {
+        let taken = (self.at.checked_add(len))
+            .and_then(|end| self.bytes.get(self.at..end))
+            .ok_or_else(|| truncated(&format!("in the {what}")))?;
+        self.at += len;
+        Ok(taken)
+    }
+
+    fn u8(&mut self, what: &str) -> Result<u8, CommandError> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    fn u32(&mut self, what: &str) -> Result<u32, CommandError> {
+        Ok(u32::from_le_bytes(self.take(4, what)?.try_into().unwrap()))
+    }
+
+    fn fields(&mut self) -> Result<Vec<String>, CommandError> {
+        let count = self.u8("header")?;
+        let mut fields = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let len = usize::from(self.u8("header")?);
+            let name = self.take(len, "header")?;
+            fields.push(String::from_utf8_lossy(name).into_owned());
+        }
+        Ok(fields)
+    }
+}
+
+fn truncated(detail: &str) -> CommandError {
+    CommandError::invalid("file", format!("is truncated {detail}"))
+}
+
+/// Where each of `wanted` is among `fields`.
+fn positions(fields: &[String], wanted: &[&str]) -> Result<Vec<usize>, CommandError> {
+    (wanted.iter())
+        .map(|name| {
+            (fields.iter().position(|f| f == name))
+                .ok_or_else(|| CommandError::invalid("file", format!("has no {name} field")))
+        })
+        .collect()
+}
+
+/// Reads back a binary recording, for `load_recording`, exactly as it was
+/// written: every value comes back bit for bit. A file cut short, as by a
+/// crash, is refused rather than read in part. Frames past
+/// `MAX_RECORDING_SAMPLES` are left out, as a recording would.
+pub(crate) fn read(bytes: &[u8]) -> Result<Recording, CommandError> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(4, "header")? != BINARY_MAGIC {
+        return Err(CommandError::invalid("file", "isn't a binary recording"));
+    }
+    let version = reader.u8("header")?;
+    if version > BINARY_VERSION {
+        return Err(CommandError::UnsupportedVersion {
+            what: "Binary recording",
+            version: version.into(),
+            newest: BINARY_VERSION.into(),
+        });
+    }
+    let compressed = reader.u8("header")? & COMPRESSED != 0;
+    let bobs = usize::from(u16::from_le_bytes(
+        reader.take(2, "header")?.try_into().unwrap(),
+    ));
+    reader.take(8, "header")?;
+    let frame_fields = reader.fields()?;
+    let bob_fields = reader.fields()?;
+    let frame_at = positions(&frame_fields, &FRAME_FIELDS)?;
+    let bob_at = positions(&bob_fields, &BOB_FIELDS)?;
+    let columns = frame_fields.len() + bob_fields.len() * bobs;
+    let configuration = match version {
+        1 => None,
+        _ => {
+            let len = reader.u32("header")? as usize;
+            let json = reader.take(len, "header")?;
+            (len > 0)
+                .then(|| serde_json::from_slice(json))
+                .transpose()
+                .map_err(|e| CommandError::invalid("file", format!("configuration: {e}")))?
+        }
+    };
+
+    let body_end = (bytes.len().checked_sub(FOOTER_LEN))
+        .filter(|&end| end >= reader.at && bytes[end..].starts_with(FOOTER_MAGIC))
+        .ok_or_else(|| truncated("before its end"))?;
+    let frames = u64::from_le_bytes(bytes[body_end + 4..].try_into().unwrap());
+    let mut body = Vec::new();
+    let mut blocks = Reader {
+        bytes: &bytes[..body_end],
+        at: reader.at,
+    };
+    if compressed {
+        while blocks.at < body_end {
+            let raw_len = blocks.u32("block header")? as usize;
+            let len = blocks.u32("block header")? as usize;
+            let block = blocks.take(len, "block")?;
+            let raw = lz4::decompress(block, raw_len)
+                .ok_or_else(|| CommandError::invalid("file", "has a corrupt block"))?;
+            body.extend_from_slice(&raw);
+        }
+    } else {
+        body.extend_from_slice(&bytes[blocks.at..body_end]);
+    }
+    let frame_len = 8 * columns;
+    if body.len() as u64 != frames.saturating_mul(frame_len as u64) {
+        return Err(truncated(&format!(
+            "or corrupt: {} bytes of frames where its footer says {frames} frames",
+            body.len()
+        )));
+    }
+
+    let mut start = None;
+    let mut samples = Vec::new();
+    for frame in body.chunks_exact(frame_len).take(MAX_RECORDING_SAMPLES) {
+        let value = |i: usize| f64::from_le_bytes(frame[8 * i..8 * i + 8].try_into().unwrap());
+        let sim_time = value(frame_at[0]);
+        let start = *start.get_or_insert(sim_time);
+        let bobs = (0..bobs)
+            .map(|b| {
+                let field = |f: usize| value(frame_fields.len() + b * bob_fields.len() + bob_at[f]);
+                RecordedBob {
+                    theta: field(0),
+                    omega: field(1),
+                    length_rod: field(2),
+                    mass: field(3),
+                }
+            })
+            .collect();
+        samples.push(Sample {
+            time: sim_time - start,
+            pivot: Coordinate::new(value(frame_at[1]), value(frame_at[2])),
+            bobs,
+        });
+    }
+    let start = start.ok_or_else(|| CommandError::invalid("file", "has no frames"))?;
+    Ok(Recording::from_samples(start, samples, configuration))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppDataInner;
+    use crate::trajectory::{
+        self, RecordingFormat, RecordingOptions, TrajectoryFile, TrajectoryRecording,
+    };
+
+    #[test]
+    fn binary_recordings_read_back_bit_for_bit_and_cut_files_are_refused() {
+        let dir = std::env::temp_dir();
+        for compressed in [false, true] {
+            let path = dir.join(format!("binary-{compressed}-{}.dptr", std::process::id()));
+            let mut data = AppDataInner::default();
+            data.pendulum.bobs[0].mass = 1.25;
+            let options = RecordingOptions {
+                format: RecordingFormat::Binary { compressed },
+                ..RecordingOptions::default()
+            };
+            let file = TrajectoryFile::create(&path, options).unwrap();
+            let mut file = TrajectoryRecording::start(file, &data.pendulum, data.dt).unwrap();
+            let mut memory = Recording::default();
+            for _ in 0..1000 {
+                let dt = data.dt;
+                data.substep(dt, &mut Vec::new());
+                file.push(&data.pendulum, data.sim_time).unwrap();
+                memory.push(&data.pendulum, data.sim_time);
+            }
+            let report = file.finish().unwrap();
+            assert_eq!(report.rows, 1000);
+            let bytes = std::fs::read(&path).unwrap();
+            assert_eq!(report.bytes, bytes.len() as u64);
+            let reopened = trajectory::read_recording(&bytes).unwrap();
+            assert_eq!(reopened, memory);
+            assert_eq!(reopened.pendulum_at(0.7), memory.pendulum_at(0.7));
+            if !compressed {
+                // fixed-size frames between the header and the footer
+                let header = BinaryHeader {
+                    bobs: data.pendulum.n(),
+                    dt: data.dt,
+                    compressed,
+                    configuration: Some(RecordingConfiguration::of(&data.pendulum)),
+                };
+                let mut header_bytes = Vec::new();
+                header.write(&mut header_bytes).unwrap();
+                let frames = 1000 * 8 * header.columns();
+                assert_eq!(bytes.len(), header_bytes.len() + frames + FOOTER_LEN);
+            }
+
+            // cut anywhere, the file is refused as truncated
+            for len in [
+                bytes.len() - 1,
+                bytes.len() - FOOTER_LEN,
+                bytes.len() / 2,
+                10,
+            ] {
+                match read(&bytes[..len]) {
+                    Err(CommandError::InvalidParameter { reason, .. }) => {
+                        assert!(reason.contains("truncated"), "{reason}");
+                    }
+                    other => panic!("expected a truncated file, got {other:?}"),
+                }
+            }
+
+            // and it converts to CSV for other tools
+            let report = trajectory::convert(&path, true).unwrap();
+            let csv = std::fs::read(&report.path).unwrap();
+            let from_csv = trajectory::read_recording(&csv).unwrap();
+            assert_eq!(from_csv.samples().len(), 1000);
+            assert_eq!(
+                from_csv.samples()[999].bobs[1].theta,
+                memory.samples()[999].bobs[1].theta
+            );
+            assert!(trajectory::convert(&path, false).is_err());
+            std::fs::remove_file(&report.path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            if compressed {
+                assert!(
+                    bytes.len() < csv.len() / 2,
+                    "{} vs {}",
+                    bytes.len(),
+                    csv.len()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use pendulum_core::{Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::trails::TrailBuffer;
+
+/// Sim seconds of motion the box covers, to begin with.
+pub(crate) const DEFAULT_BOUNDS_WINDOW: f64 = 10.0;
+/// Longest window the box may cover, in sim seconds.
+pub(crate) const MAX_BOUNDS_WINDOW: f64 = 3600.0;
+/// Sim seconds between working the box out again from the trails, which is
+/// what lets it shrink once the motion has.
+pub(crate) const BOUNDS_RECOMPUTE_INTERVAL: f64 = 0.5;
+
+/// How long a stretch of motion the box covers, and whether it goes out
+/// with the positions frames.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct BoundsConfig {
+    /// In sim seconds. Only as much as the trails keep can be covered; see
+    /// `set_trail_buffer`.
+    pub(crate) window: f64,
+    /// Send the box with every n-th positions frame, when set.
+    pub(crate) frame_every: Option<u32>,
+}
+
+impl Default for BoundsConfig {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_BOUNDS_WINDOW,
+            frame_every: None,
+        }
+    }
+}
+
+impl BoundsConfig {
+    pub(crate) fn validate(&self) -> Result<(), CommandError> {
+        if !(self.window.is_finite() && self.window > 0.0 && self.window <= MAX_BOUNDS_WINDOW) {
+            return Err(CommandError::invalid(
+                "window",
+                format!(
+                    "must be positive and at most {MAX_BOUNDS_WINDOW} s, got {}",
+                    self.window
+                ),
+            ));
+        }
+        if self.frame_every == Some(0) {
+            return Err(CommandError::invalid("frame_every", "must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+/// An axis-aligned box, in meters.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Bounds {
+    pub(crate) min: Coordinate,
+    pub(crate) max: Coordinate,
+}
+
+impl Bounds {
+    pub(crate) fn around(point: Coordinate) -> Self {
+        Self {
+            min: point,
+            max: point,
+        }
+    }
+
+    pub(crate) fn extend(&mut self, point: Coordinate) {
+        self.min = Coordinate::new(self.min.x.min(point.x), self.min.y.min(point.y));
+        self.max = Coordinate::new(self.max.x.max(point.x), self.max.y.max(point.y));
+    }
+
+    /// The box around the pivot and every bob.
+    fn of(pendulum: &Pendulum) -> Self {
+        let mut bounds = Self::around(pendulum.pivot);
+        for bob in &pendulum.bobs {
+            bounds.extend(bob.coordinate);
+        }
+        bounds
+    }
+
+    /// The box with both corners mapped, put back in order, as mirroring y
+    /// swaps its top and bottom.
+    pub(crate) fn mapped(self, map: &dyn Fn(Coordinate) -> Coordinate) -> Self {
+        let mut bounds = Self::around(map(self.min));
+        bounds.extend(map(self.max));
+        bounds
+    }
+}
+
+/// The box every bob and the pivot have kept inside over the last `window`
+/// of sim time, for framing the view. Each substep only widens it, which is
+/// cheap; every `BOUNDS_RECOMPUTE_INTERVAL` it is worked out again from the
+/// trails, so motion older than the window drops out of it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MotionBounds {
+    config: BoundsConfig,
+    bounds: Option<Bounds>,
+    /// Sim time of the last recomputation.
+    recomputed_at: f64,
+}
+
+impl MotionBounds {
+    pub(crate) fn configure(
+        &mut self,
+        config: BoundsConfig,
+        pendulum: &Pendulum,
+        trails: &TrailBuffer,
+        sim_time: f64,
+    ) {
+        self.config = config;
+        self.recompute(pendulum, trails, sim_time);
+    }
+
+    /// Widens the box to the chain as it is at `sim_time`, working it out
+    /// again if it is due.
+    pub(crate) fn observe(&mut self, pendulum: &Pendulum, trails: &TrailBuffer, sim_time: f64) {
+        let due = !(0.0..BOUNDS_RECOMPUTE_INTERVAL).contains(&(sim_time - self.recomputed_at));
+        match &mut self.bounds {
+            Some(bounds) if !due => {
+                bounds.extend(pendulum.pivot);
+                for bob in &pendulum.bobs {
+                    bounds.extend(bob.coordinate);
+                }
+            }
+            _ => self.recompute(pendulum, trails, sim_time),
+        }
+    }
+
+    /// The box around the chain now and its trails over the window.
+    pub(crate) fn recompute(&mut self, pendulum: &Pendulum, trails: &TrailBuffer, sim_time: f64) {
+        let mut bounds = Bounds::of(pendulum);
+        trails.for_each_since(sim_time - self.config.window, |point| bounds.extend(point));
+        self.bounds = Some(bounds);
+        self.recomputed_at = sim_time;
+    }
+
+    /// The box, worked out now if it hasn't been yet.
+    pub(crate) fn bounds(&self, pendulum: &Pendulum) -> Bounds {
+        self.bounds.unwrap_or_else(|| Bounds::of(pendulum))
+    }
+
+    /// The box for positions frame number `frame`, if it goes with it.
+    pub(crate) fn for_frame(&self, frame: u64, pendulum: &Pendulum) -> Option<Bounds> {
+        (self.config.frame_every)
+            .filter(|&every| frame.is_multiple_of(u64::from(every)))
+            .map(|_| self.bounds(pendulum))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppDataInner;
+
+    fn run(data: &mut AppDataInner, steps: usize) {
+        for _ in 0..steps {
+            let dt = data.dt;
+            data.substep(dt, &mut Vec::new());
+        }
+    }
+
+    #[test]
+    fn the_box_holds_the_recent_motion_and_shrinks_after_it() {
+        let mut data = AppDataInner::default();
+        data.motion_bounds.configure(
+            BoundsConfig {
+                window: 2.0,
+                frame_every: Some(4),
+            },
+            &data.pendulum,
+            &data.trails,
+            data.sim_time,
+        );
+        run(&mut data, 2_000);
+        let bounds = data.motion_bounds.bounds(&data.pendulum);
+        let pivot = data.pendulum.pivot;
+        assert!(bounds.min.x <= pivot.x && pivot.x <= bounds.max.x);
+        assert!(bounds.min.y <= pivot.y && pivot.y <= bounds.max.y);
+        for trail in data.trails.trails(None) {
+            for (p, t) in trail.positions.iter().zip(&trail.sim_times) {
+                if *t >= data.sim_time - 2.0 {
+                    assert!(bounds.min.x <= p.x && p.x <= bounds.max.x, "{p:?}");
+                    assert!(bounds.min.y <= p.y && p.y <= bounds.max.y, "{p:?}");
+                }
+            }
+        }
+        let reach: f64 = data.pendulum.bobs.iter().map(|b| b.length_rod).sum();
+        assert!(bounds.max.x - bounds.min.x <= 2.0 * reach + 1e-9);
+
+        // once the chain hangs still, the swing drops out of the box
+        for bob in &mut data.pendulum.bobs {
+            (bob.theta, bob.omega) = (std::f64::consts::PI, 0.0);
+        }
+        data.pendulum.update_coordinates();
+        run(&mut data, 1_600);
+        let still = data.motion_bounds.bounds(&data.pendulum);
+        assert!(still.max.x - still.min.x < 1e-6, "{still:?}");
+        assert!((still.min.y - (pivot.y - reach)).abs() < 1e-6, "{still:?}");
+
+        // every fourth frame carries it, mirrored with the world frame
+        assert!(data.motion_bounds.for_frame(8, &data.pendulum).is_some());
+        assert!(data.motion_bounds.for_frame(9, &data.pendulum).is_none());
+        let flipped = still.mapped(&|p| Coordinate::new(p.x, -p.y));
+        assert_eq!(flipped.min.y, -still.max.y);
+        assert!(BoundsConfig {
+            window: 0.0,
+            frame_every: None
+        }
+        .validate()
+        .is_err());
+    }
+}
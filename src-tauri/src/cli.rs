@@ -0,0 +1,584 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use pendulum_core::{Bob, Pendulum};
+
+use crate::config;
+use crate::energy::{EnergyInput, EnergySeries, EnergySource};
+use crate::error::CommandError;
+use crate::history::Edit;
+use crate::integrator::IntegratorSettings;
+use crate::pendulum::{BobMeta, BobSpec};
+use crate::recording::RecordingConfiguration;
+use crate::state::AppDataInner;
+use crate::sweep::{self, BobField, SweepAxis, SweepParameter, SweepQuantity, SweepSpec};
+use crate::trajectory;
+
+/// Exit code of a run that failed on the way, like a file that couldn't be
+/// written.
+pub(crate) const EXIT_FAILED: u8 = 1;
+/// Exit code of arguments, a configuration or a spec that were refused.
+pub(crate) const EXIT_INVALID: u8 = 2;
+/// Angles a side of a flip map takes unless told otherwise.
+pub(crate) const DEFAULT_FLIP_MAP_RESOLUTION: u32 = 100;
+/// Sim seconds a flip map waits for each flip unless told otherwise.
+pub(crate) const DEFAULT_FLIP_MAP_HORIZON: f64 = 10.0;
+
+const USAGE: &str = "\
+Runs the simulation without a window.
+
+Usage: pendulum-cli <command> [options]
+
+Commands:
+  run        Steps the chain for --duration and writes its states
+  energy     Steps the chain for --duration and writes its energies
+  flip-map   Times the first flip from a grid of the first two bobs' angles
+  sweep      Runs the parameter sweep in --spec, as run_parameter_sweep does
+
+The chain:
+  --config <path>             A configuration file, as export_config writes
+  --bob <l,m,theta,omega>     A bob, in meters, kilograms and canonical
+                              radians; repeated, they replace the chain
+  --dt <s>                    The step
+  --damping <N m s/rad>       Joint friction
+  --integrator <id>           As list_integrators names them
+  --integrator-param <k=v>    One of the integrator's parameters; repeatable
+
+Output:
+  --format <csv|ndjson>       csv unless told otherwise
+  --output <path>             Standard output unless given
+
+Commands' options:
+  --duration <s>              run, energy: sim seconds to step
+  --every <n>                 run: write every n-th step only
+  --resolution <n>            flip-map: angles a side, 100 unless told otherwise
+  --horizon <s>               flip-map: sim seconds to wait for a flip, 10
+                              unless told otherwise
+  --spec <path>               sweep: the spec, as JSON
+  --output-dir <path>         sweep: where recordings asked for are written
+  --threads <n>               flip-map, sweep: threads the runs share, all the
+                              cores but one unless told otherwise
+
+Progress goes to standard error. The exit code is 0 on success, 1 if the
+run failed on the way and 2 if the input was refused.
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+    Help,
+    Run,
+    Energy,
+    FlipMap,
+    Sweep,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    #[default]
+    Csv,
+    /// A JSON object per line.
+    Ndjson,
+}
+
+/// The command line, read but not yet checked against the chain.
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    command: Command,
+    config: Option<PathBuf>,
+    bobs: Vec<BobSpec>,
+    dt: Option<f64>,
+    damping: Option<f64>,
+    integrator: Option<String>,
+    integrator_params: Vec<(String, String)>,
+    format: OutputFormat,
+    output: Option<PathBuf>,
+    duration: Option<f64>,
+    every: u64,
+    resolution: u32,
+    horizon: f64,
+    spec: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    threads: Option<usize>,
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, CommandError> {
+    (value.parse())
+        .map_err(|_| CommandError::invalid(flag, format!("must be a number, got {value}")))
+}
+
+fn parse(args: &[String]) -> Result<Options, CommandError> {
+    let mut args = args.iter();
+    let command = match args.next().map(String::as_str) {
+        None | Some("help" | "--help" | "-h") => Command::Help,
+        Some("run") => Command::Run,
+        Some("energy") => Command::Energy,
+        Some("flip-map") => Command::FlipMap,
+        Some("sweep") => Command::Sweep,
+        Some(other) => {
+            return Err(CommandError::invalid(
+                "command",
+                format!("must be one of run, energy, flip-map and sweep, got {other}"),
+            ))
+        }
+    };
+    let mut options = Options {
+        command,
+        config: None,
+        bobs: Vec::new(),
+        dt: None,
+        damping: None,
+        integrator: None,
+        integrator_params: Vec::new(),
+        format: OutputFormat::default(),
+        output: None,
+        duration: None,
+        every: 1,
+        resolution: DEFAULT_FLIP_MAP_RESOLUTION,
+        horizon: DEFAULT_FLIP_MAP_HORIZON,
+        spec: None,
+        output_dir: None,
+        threads: None,
+    };
+    while let Some(flag) = args.next() {
+        if matches!(flag.as_str(), "--help" | "-h") {
+            options.command = Command::Help;
+            continue;
+        }
+        let flag = flag.as_str();
+        let value = args.next().ok_or_else(|| {
+            let reason = if flag.starts_with("--") {
+                "needs a value"
+            } else {
+                "isn't an option"
+            };
+            CommandError::invalid(flag, reason)
+        })?;
+        match flag {
+            "--config" => options.config = Some(value.into()),
+            "--bob" => {
+                let values: Vec<f64> = (value.split(','))
+                    .map(|v| number(flag, v.trim()))
+                    .collect::<Result<_, _>>()?;
+                let [length_rod, mass, theta, omega] = values[..] else {
+                    return Err(CommandError::invalid(
+                        flag,
+                        format!("must be four numbers, length, mass, theta and omega, got {value}"),
+                    ));
+                };
+                options.bobs.push(BobSpec {
+                    length_rod,
+                    mass,
+                    theta,
+                    omega,
+                    id: None,
+                    locked: false,
+                    meta: BobMeta::default(),
+                });
+            }
+            "--dt" => options.dt = Some(number(flag, value)?),
+            "--damping" => options.damping = Some(number(flag, value)?),
+            "--integrator" => options.integrator = Some(value.clone()),
+            "--integrator-param" => {
+                let (name, v) = value.split_once('=').ok_or_else(|| {
+                    CommandError::invalid(flag, format!("must be name=value, got {value}"))
+                })?;
+                options.integrator_params.push((name.into(), v.into()));
+            }
+            "--format" => {
+                options.format = match value.as_str() {
+                    "csv" => OutputFormat::Csv,
+                    "ndjson" => OutputFormat::Ndjson,
+                    other => {
+                        return Err(CommandError::invalid(
+                            flag,
+                            format!("must be csv or ndjson, got {other}"),
+                        ))
+                    }
+                }
+            }
+            "--output" => options.output = Some(value.into()),
+            "--duration" => options.duration = Some(number(flag, value)?),
+            "--every" => options.every = number(flag, value)?,
+            "--resolution" => options.resolution = number(flag, value)?,
+            "--horizon" => options.horizon = number(flag, value)?,
+            "--spec" => options.spec = Some(value.into()),
+            "--output-dir" => options.output_dir = Some(value.into()),
+            "--threads" => options.threads = Some(number(flag, value)?),
+            other => return Err(CommandError::invalid(other, "isn't an option")),
+        }
+    }
+    if options.every == 0 {
+        return Err(CommandError::invalid("--every", "must be at least 1"));
+    }
+    Ok(options)
+}
+
+/// The state the command starts from: the configuration file, or the
+/// app's default chain, with the options on the command line applied over
+/// it and the whole checked as `import_config` would.
+fn start(options: &Options) -> Result<AppDataInner, CommandError> {
+    let mut data = AppDataInner::default();
+    let mut config = match &options.config {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| CommandError::io(path, e))?;
+            let (config, warnings) = config::parse(&text)?;
+            for warning in warnings {
+                eprintln!("warning: {warning}");
+            }
+            config
+        }
+        None => data.export_config(),
+    };
+    if !options.bobs.is_empty() {
+        config.bobs = options.bobs.clone();
+    }
+    if let Some(dt) = options.dt {
+        config.world.dt = dt;
+    }
+    if let Some(damping) = options.damping {
+        config.world.damping = damping;
+    }
+    if let Some(id) = &options.integrator {
+        config.world.integrator = IntegratorSettings {
+            id: id.clone(),
+            values: Map::new(),
+        };
+    }
+    for (name, value) in &options.integrator_params {
+        let values = &mut config.world.integrator.values;
+        values.insert(name.clone(), Value::String(value.clone()));
+    }
+    data.apply_config(&config, Edit::ImportConfig)?;
+    Ok(data)
+}
+
+/// Tells standard error how far a job has got, a line each whole percent.
+struct Progress {
+    what: &'static str,
+    total: u64,
+    reported: AtomicU64,
+}
+
+impl Progress {
+    fn new(what: &'static str, total: u64) -> Self {
+        Self {
+            what,
+            total: total.max(1),
+            reported: AtomicU64::new(0),
+        }
+    }
+
+    /// `done` of the total are done; safe to call from any thread.
+    fn update(&self, done: u64) {
+        let percent = done.min(self.total) * 100 / self.total;
+        if percent > self.reported.fetch_max(percent, Ordering::Relaxed) {
+            eprintln!("{}: {percent}% of {}", self.what, self.total);
+        }
+    }
+}
+
+/// `pendulum` and `steps` steps of `dt` on from it, with their sim times.
+fn run_from(
+    mut pendulum: Pendulum,
+    sim_time: f64,
+    dt: f64,
+    steps: u64,
+    progress: &Progress,
+) -> impl Iterator<Item = (Pendulum, f64)> + '_ {
+    (0..=steps).map(move |step| {
+        if step > 0 {
+            pendulum.step(dt);
+            progress.update(step);
+        }
+        (pendulum.clone(), sim_time + step as f64 * dt)
+    })
+}
+
+fn duration(options: &Options) -> Result<f64, CommandError> {
+    match options.duration {
+        Some(duration) if duration.is_finite() && duration > 0.0 => Ok(duration),
+        Some(duration) => Err(CommandError::invalid(
+            "--duration",
+            format!("must be positive and finite, got {duration}"),
+        )),
+        None => Err(CommandError::invalid("--duration", "is needed")),
+    }
+}
+
+/// A line of `run`'s NDJSON.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunLine<'a> {
+    sim_time: f64,
+    bobs: &'a [Bob],
+}
+
+fn output(path: Option<&Path>) -> Result<Box<dyn Write>, CommandError> {
+    match path {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| CommandError::io(path, e))?;
+            Ok(Box::new(BufWriter::new(file)))
+        }
+        None => Ok(Box::new(io::stdout().lock())),
+    }
+}
+
+/// Where `output` writes, for its errors.
+fn output_name(options: &Options) -> PathBuf {
+    (options.output.clone()).unwrap_or_else(|| PathBuf::from("standard output"))
+}
+
+fn run(options: &Options, data: &AppDataInner) -> Result<(), CommandError> {
+    let steps = ((duration(options)? / data.dt).round() as u64).max(1);
+    let progress = Progress::new("steps", steps);
+    let frames = run_from(
+        data.pendulum.clone(),
+        data.sim_time,
+        data.dt,
+        steps,
+        &progress,
+    );
+    let mut frames = (frames.enumerate())
+        .filter(|(step, _)| (*step as u64).is_multiple_of(options.every))
+        .map(|(_, frame)| frame);
+    let mut out = output(options.output.as_deref())?;
+    let written = match options.format {
+        OutputFormat::Csv => {
+            let configuration = RecordingConfiguration::of(&data.pendulum);
+            let bobs = data.pendulum.n();
+            trajectory::write_csv(&mut out, bobs, Some(&configuration), frames).map(|_| ())
+        }
+        OutputFormat::Ndjson => frames.try_for_each(|(pendulum, sim_time)| {
+            let bobs = &pendulum.bobs;
+            serde_json::to_writer(&mut out, &RunLine { sim_time, bobs })?;
+            out.write_all(b"\n")
+        }),
+    };
+    written
+        .and_then(|()| out.flush())
+        .map_err(|e| CommandError::io(output_name(options), e))
+}
+
+fn energy(options: &Options, data: &AppDataInner) -> Result<String, CommandError> {
+    let source = EnergySource::Run {
+        duration: duration(options)?,
+    };
+    let EnergyInput::Run {
+        pendulum,
+        sim_time,
+        dt,
+        steps,
+    } = source.capture(data).map_err(|e| match e {
+        CommandError::InvalidParameter { reason, .. } => CommandError::InvalidParameter {
+            name: "--duration".into(),
+            reason,
+        },
+        other => other,
+    })?
+    else {
+        unreachable!("a run is captured as one");
+    };
+    let friction = pendulum.damping > 0.0;
+    let progress = Progress::new("steps", steps);
+    let series = EnergySeries::of(run_from(pendulum, sim_time, dt, steps, &progress), friction);
+    Ok(match options.format {
+        OutputFormat::Csv => series.csv(),
+        OutputFormat::Ndjson => {
+            let mut text = String::new();
+            for &[sim_time, kinetic, potential, dissipated] in &series.rows {
+                let mut line = Map::new();
+                line.insert("simTime".into(), sim_time.into());
+                line.insert("kinetic".into(), kinetic.into());
+                line.insert("potential".into(), potential.into());
+                line.insert("total".into(), (kinetic + potential).into());
+                if series.friction {
+                    line.insert("dissipated".into(), dissipated.into());
+                }
+                text += &Value::Object(line).to_string();
+                text += "\n";
+            }
+            text
+        }
+    })
+}
+
+/// The `spec` of a flip map: the first two bobs' angles over every
+/// direction, `resolution` of them a side, each run timed to the first flip
+/// of any bob. The sides stop a step short of 2π, which is 0 again, so the
+/// map has no seam of repeated runs.
+fn flip_map_spec(resolution: u32, horizon: f64) -> SweepSpec {
+    let axis = |index| SweepAxis {
+        parameter: SweepParameter::Bob {
+            index,
+            field: BobField::Theta,
+        },
+        from: 0.0,
+        to: 2.0 * PI * (1.0 - 1.0 / f64::from(resolution)),
+        steps: resolution,
+    };
+    SweepSpec {
+        axes: vec![axis(0), axis(1)],
+        horizon,
+        quantities: vec![SweepQuantity::FirstFlipTime],
+        recordings: None,
+        threads: None,
+    }
+}
+
+fn sweep(options: &Options, data: &AppDataInner) -> Result<String, CommandError> {
+    let mut spec = match options.command {
+        Command::FlipMap => flip_map_spec(options.resolution, options.horizon),
+        _ => {
+            let path = (options.spec.as_ref())
+                .ok_or_else(|| CommandError::invalid("--spec", "is needed"))?;
+            let text = std::fs::read_to_string(path).map_err(|e| CommandError::io(path, e))?;
+            serde_json::from_str(&text).map_err(|e| CommandError::invalid("spec", e.to_string()))?
+        }
+    };
+    spec.threads = options.threads.or(spec.threads);
+    let runs = spec.validate(&data.pendulum, data.dt)?;
+    let progress = Progress::new("runs", runs);
+    let never = AtomicBool::new(false);
+    let swept = sweep::sweep(
+        &spec,
+        &data.pendulum,
+        data.dt,
+        options.output_dir.as_deref(),
+        &never,
+        |p| progress.update(p.completed_runs),
+    )?;
+    let (table, _) = swept.expect("a sweep that isn't cancelled finishes");
+    Ok(match options.format {
+        OutputFormat::Csv => table.csv(),
+        OutputFormat::Ndjson => table.ndjson(),
+    })
+}
+
+fn execute(args: &[String]) -> Result<(), CommandError> {
+    let options = parse(args)?;
+    if options.command == Command::Help {
+        print!("{USAGE}");
+        return Ok(());
+    }
+    let data = start(&options)?;
+    let text = match options.command {
+        Command::Run => return run(&options, &data),
+        Command::Energy => energy(&options, &data)?,
+        _ => sweep(&options, &data)?,
+    };
+    let mut out = output(options.output.as_deref())?;
+    (out.write_all(text.as_bytes()).and_then(|()| out.flush()))
+        .map_err(|e| CommandError::io(output_name(&options), e))
+}
+
+/// What the process exits with after `error`.
+fn exit_code(error: &CommandError) -> u8 {
+    match error {
+        CommandError::Io { .. }
+        | CommandError::Internal { .. }
+        | CommandError::Cancelled { .. } => EXIT_FAILED,
+        _ => EXIT_INVALID,
+    }
+}
+
+/// Runs the command in `args`, the program's name left out, reporting
+/// errors on standard error.
+pub(crate) fn main(args: &[String]) -> std::process::ExitCode {
+    match execute(args) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            let code = exit_code(&error);
+            if code == EXIT_INVALID {
+                eprintln!("See pendulum-cli --help for the options.");
+            }
+            code.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn commands_write_their_tables_and_refuse_bad_input() {
+        let dir = std::env::temp_dir().join(format!("cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = |name: &str| dir.join(name).display().to_string();
+
+        let energy = out("energy.csv");
+        let line = format!("energy --bob 1,1,3,0 --bob 1,2,2,0 --dt 1e-3 --damping 0.1 --duration 0.5 --output {energy}");
+        execute(&args(&line)).unwrap();
+        let text = std::fs::read_to_string(&energy).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 502);
+        assert!(lines[0].ends_with(",dissipated_joint_friction_j"));
+        let mut pendulum = Pendulum::new(vec![
+            Bob::new(1.0, 1.0, 3.0, 0.0),
+            Bob::new(1.0, 2.0, 2.0, 0.0),
+        ]);
+        pendulum.damping = 0.1;
+        for _ in 0..500 {
+            pendulum.step(1e-3);
+        }
+        let last: Vec<f64> = lines[501].split(',').map(|v| v.parse().unwrap()).collect();
+        assert_eq!(last[1], pendulum.kinetic_energy());
+
+        // every other step of the same run, as a JSON object a line
+        let run = out("run.ndjson");
+        let line = format!("run --bob 1,1,3,0 --bob 1,2,2,0 --dt 1e-3 --damping 0.1 --duration 0.5 --every 2 --format ndjson --output {run}");
+        execute(&args(&line)).unwrap();
+        let text = std::fs::read_to_string(&run).unwrap();
+        let lines: Vec<Value> = (text.lines().map(|l| serde_json::from_str(l).unwrap())).collect();
+        assert_eq!(lines.len(), 251);
+        let tip = &lines[250]["bobs"][1];
+        assert_eq!(tip["theta"], pendulum.bobs[1].theta);
+        assert_eq!(lines[250]["simTime"], 0.5);
+
+        let map = out("flips.ndjson");
+        let line = format!("flip-map --bob 1,1,0,0 --bob 1,1,0,0 --resolution 4 --horizon 1 --dt 1e-2 --threads 2 --format ndjson --output {map}");
+        execute(&args(&line)).unwrap();
+        let text = std::fs::read_to_string(&map).unwrap();
+        let rows: Vec<Value> = (text.lines().map(|l| serde_json::from_str(l).unwrap())).collect();
+        assert_eq!(rows.len(), 16);
+        assert_eq!(
+            (&rows[10]["run"], &rows[10]["bobs[0].theta"]),
+            (&11.into(), &PI.into())
+        );
+        // hanging straight down, it never flips
+        assert_eq!(rows[10]["first_flip_time_s"], Value::Null);
+        // and the last column stops short of the first again
+        let last = rows[3]["bobs[1].theta"].as_f64().unwrap();
+        assert!((last - 1.5 * PI).abs() < 1e-12, "{last}");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let refused = |line: &str| exit_code(&execute(&args(line)).unwrap_err());
+        assert_eq!(refused("spin"), EXIT_INVALID);
+        assert_eq!(refused("run"), EXIT_INVALID);
+        assert_eq!(refused("run --duration 1 --dt 0"), EXIT_INVALID);
+        assert_eq!(refused("energy --duration 1 --bob 1,1"), EXIT_INVALID);
+        assert_eq!(
+            refused("energy --duration 1 --integrator rk45"),
+            EXIT_INVALID
+        );
+        assert_eq!(refused("flip-map --bob 1,1,0,0"), EXIT_INVALID);
+        assert_eq!(refused("flip-map --threads 0"), EXIT_INVALID);
+        assert_eq!(refused("sweep --spec"), EXIT_INVALID);
+        assert_eq!(refused("sweep --spec /nonexistent/spec.json"), EXIT_FAILED);
+        let missing = out("missing/energy.csv");
+        assert_eq!(
+            refused(&format!("energy --duration 0.1 --output {missing}")),
+            EXIT_FAILED
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use tokio::time::Instant;
+
+/// Most substeps a single tick may take. When the physics falls further
+/// behind than this, the excess is dropped and counted as deficit instead
+/// of being simulated in ever longer bursts.
+pub(crate) const MAX_SUBSTEPS_PER_TICK: u32 = 128;
+
+/// Largest accepted time scale, which keeps the substeps per tick well under
+/// the catch-up cap at the default dt.
+pub(crate) const MAX_TIME_SCALE: f64 = 16.0;
+
+/// Ties simulation time to wall time: each tick owes the elapsed wall time
+/// (scaled) to the simulation, paid out in whole fixed-dt substeps with the
+/// remainder carried to the next tick.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SimClock {
+    last_tick: Option<Instant>,
+    /// Sim seconds owed but smaller than one substep.
+    carry: f64,
+    /// Sim seconds dropped in total because catch-up was capped.
+    pub(crate) deficit: f64,
+    /// Sim seconds integrated since the last reset, the sum of every
+    /// substep's dt. Unlike `sim_time` it never jumps: restoring, rewinding
+    /// or replacing the state leaves it running on.
+    pub(crate) elapsed: f64,
+    /// Substeps integrated since the last reset.
+    pub(crate) steps: u64,
+}
+
+impl SimClock {
+    /// Returns how many substeps of `dt` to take for the wall time elapsed
+    /// since the previous tick. The first tick after `pause` takes none.
+    pub(crate) fn advance(&mut self, now: Instant, time_scale: f64, dt: f64) -> u32 {
+        let Some(last) = self.last_tick.replace(now) else {
+            return 0;
+        };
+        let owed = self.carry + (now - last).as_secs_f64() * time_scale;
+        let steps = (owed / dt).floor();
+        let max = f64::from(MAX_SUBSTEPS_PER_TICK);
+        if steps > max {
+            self.deficit += owed - max * dt;
+            self.carry = 0.0;
+            MAX_SUBSTEPS_PER_TICK
+        } else {
+            self.carry = owed - steps * dt;
+            steps as u32
+        }
+    }
+
+    /// Counts one integrated substep of `dt`.
+    pub(crate) fn record(&mut self, dt: f64) {
+        self.elapsed += dt;
+        self.steps += 1;
+    }
+
+    /// Forgets the previous tick, so the time until the next one is not
+    /// simulated.
+    pub(crate) fn pause(&mut self) {
+        self.last_tick = None;
+        self.carry = 0.0;
+    }
+}
+
+/// Smoothing factor of the achieved tick interval average.
+const TICK_AVERAGE_WEIGHT: f64 = 0.1;
+
+/// Measures how far apart physics ticks actually land, as an exponential
+/// moving average.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TickMeter {
+    last: Option<Instant>,
+    /// Average seconds between ticks, `None` until two ticks have happened.
+    pub(crate) average: Option<f64>,
+}
+
+impl TickMeter {
+    pub(crate) fn record(&mut self, now: Instant) {
+        if let Some(last) = self.last.replace(now) {
+            let interval = (now - last).as_secs_f64();
+            self.average = Some(match self.average {
+                Some(avg) => avg + TICK_AVERAGE_WEIGHT * (interval - avg),
+                None => interval,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn carries_the_remainder_between_ticks() {
+        let start = Instant::now();
+        let mut clock = SimClock::default();
+        assert_eq!(clock.advance(start, 1.0, 0.016), 0);
+
+        // 1000 ticks of 8 ms: every other tick owes a whole 16 ms step
+        let steps: u32 = (1..=1000)
+            .map(|i| clock.advance(start + Duration::from_millis(8 * i), 1.0, 0.016))
+            .sum();
+        assert_eq!(steps, 500);
+        assert_eq!(clock.deficit, 0.0);
+    }
+
+    #[test]
+    fn caps_catch_up_and_reports_the_deficit() {
+        let start = Instant::now();
+        let mut clock = SimClock::default();
+        clock.advance(start, 2.0, 0.01);
+        let steps = clock.advance(start + Duration::from_secs(1), 2.0, 0.01);
+        assert_eq!(steps, MAX_SUBSTEPS_PER_TICK);
+        let expected = 2.0 - f64::from(MAX_SUBSTEPS_PER_TICK) * 0.01;
+        assert!((clock.deficit - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pause_skips_the_gap() {
+        let start = Instant::now();
+        let mut clock = SimClock::default();
+        clock.advance(start, 1.0, 0.01);
+        clock.pause();
+        assert_eq!(clock.advance(start + Duration::from_secs(60), 1.0, 0.01), 0);
+        assert_eq!(clock.deficit, 0.0);
+    }
+}
@@ -0,0 +1,383 @@
+use pendulum_core::Coordinate;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::state::{BobState, PauseReason, PendulumState, StreamFrame, StructuralChange};
+
+/// Default keyframe spacing of the delta format.
+pub(crate) const DEFAULT_KEYFRAME_EVERY: u32 = 30;
+
+/// Wire format of a subscription's frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "kind"
+)]
+pub(crate) enum FrameFormat {
+    /// `PendulumState` as is, one struct per bob.
+    #[default]
+    Full,
+    /// `CompactFrame`s, every one a keyframe.
+    Compact,
+    /// `CompactFrame`s holding deltas from the latest keyframe, with a
+    /// keyframe every `keyframe_every` frames.
+    CompactDelta {
+        #[serde(default = "default_keyframe_every")]
+        keyframe_every: u32,
+    },
+}
+
+fn default_keyframe_every() -> u32 {
+    DEFAULT_KEYFRAME_EVERY
+}
+
+/// A frame with one flat array per bob field, in bob order, for clients that
+/// read them straight into `Float64Array`s. Carries no overlays, transitions
+/// or bob colors and labels.
+///
+/// A keyframe holds the values themselves. A delta frame holds each value
+/// minus the same value in the keyframe numbered `delta_from`; adding the
+/// two back gives the value bit for bit. A delta frame only ever follows its
+/// keyframe, so a client that missed the keyframe waits for the next one or
+/// asks for it with `request_keyframe`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CompactFrame {
+    pub(crate) schema: u32,
+    pub(crate) seq: u64,
+    pub(crate) span: f64,
+    pub(crate) sim_time: f64,
+    pub(crate) wall_time_ms: f64,
+    pub(crate) time_deficit: f64,
+    pub(crate) elapsed_time: f64,
+    pub(crate) step_count: u64,
+    pub(crate) tick_interval: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) paused: Option<PauseReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) slow_motion: Option<f64>,
+    /// As in `PendulumState`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) replay: bool,
+    /// `seq` of the keyframe this frame is relative to, `None` for a keyframe.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) delta_from: Option<u64>,
+    pub(crate) theta: Vec<f64>,
+    pub(crate) omega: Vec<f64>,
+    pub(crate) alpha: Vec<f64>,
+    pub(crate) x: Vec<f64>,
+    pub(crate) y: Vec<f64>,
+    pub(crate) mass: Vec<f64>,
+    pub(crate) length_rod: Vec<f64>,
+    /// Bob ids, never delta-encoded.
+    pub(crate) id: Vec<u64>,
+    /// As in `PendulumState`, never delta-encoded either.
+    pub(crate) pixels_per_meter: f64,
+    /// As in `PendulumState`, never delta-encoded either.
+    pub(crate) pivot: Coordinate,
+    /// Indices of the bobs whose joints are locked, never delta-encoded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) locked: Vec<usize>,
+    /// As in `PendulumState`. A layout change always comes with a keyframe.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) structure_changes: Vec<StructuralChange>,
+}
+
+impl CompactFrame {
+    fn keyframe(state: &PendulumState) -> Self {
+        let field = |f: fn(&BobState) -> f64| state.bobs.iter().map(f).collect();
+        Self {
+            schema: state.schema,
+            seq: state.seq,
+            span: state.span,
+            sim_time: state.sim_time,
+            wall_time_ms: state.wall_time_ms,
+            time_deficit: state.time_deficit,
+            elapsed_time: state.elapsed_time,
+            step_count: state.step_count,
+            tick_interval: state.tick_interval,
+            paused: state.paused,
+            slow_motion: state.slow_motion,
+            replay: state.replay,
+            delta_from: None,
+            theta: field(|b| b.theta),
+            omega: field(|b| b.omega),
+            alpha: field(|b| b.alpha),
+            x: field(|b| b.position.x),
+            y: field(|b| b.position.y),
+            mass: field(|b| b.mass),
+            length_rod: field(|b| b.length_rod),
+            id: state.bobs.iter().map(|b| b.id).collect(),
+            pixels_per_meter: state.pixels_per_meter,
+            pivot: state.pivot,
+            locked: (state.bobs.iter().enumerate())
+                .filter(|(_, b)| b.locked)
+                .map(|(i, _)| i)
+                .collect(),
+            structure_changes: state.structure_changes.clone(),
+        }
+    }
+
+    fn arrays(&self) -> [&Vec<f64>; 7] {
+        [
+            &self.theta,
+            &self.omega,
+            &self.alpha,
+            &self.x,
+            &self.y,
+            &self.mass,
+            &self.length_rod,
+        ]
+    }
+
+    fn arrays_mut(&mut self) -> [&mut Vec<f64>; 7] {
+        [
+            &mut self.theta,
+            &mut self.omega,
+            &mut self.alpha,
+            &mut self.x,
+            &mut self.y,
+            &mut self.mass,
+            &mut self.length_rod,
+        ]
+    }
+
+    /// This keyframe re-expressed relative to `key`, or `None` when the bob
+    /// count differs or some delta would not add back exactly.
+    fn delta(&self, key: &CompactFrame) -> Option<Self> {
+        if self.theta.len() != key.theta.len() {
+            return None;
+        }
+        let mut delta = self.clone();
+        delta.delta_from = Some(key.seq);
+        for (values, base) in delta.arrays_mut().into_iter().zip(key.arrays()) {
+            for (v, b) in values.iter_mut().zip(base) {
+                let d = *v - b;
+                if (b + d).to_bits() != v.to_bits() {
+                    return None;
+                }
+                *v = d;
+            }
+        }
+        Some(delta)
+    }
+}
+
+/// Turns a subscriber's frames into its format, remembering the keyframe
+/// deltas are taken from.
+pub(crate) struct FrameEncoder {
+    format: FrameFormat,
+    keyframe: Option<CompactFrame>,
+    since_keyframe: u32,
+}
+
+impl FrameEncoder {
+    pub(crate) fn new(format: FrameFormat) -> Self {
+        Self {
+            format,
+            keyframe: None,
+            since_keyframe: 0,
+        }
+    }
+
+    /// Makes the next frame a keyframe.
+    pub(crate) fn resync(&mut self) {
+        self.keyframe = None;
+    }
+
+    pub(crate) fn encode(&mut self, state: PendulumState) -> StreamFrame {
+        let keyframe_every = match self.format {
+            FrameFormat::Full => return StreamFrame::Full(state),
+            FrameFormat::Compact => return StreamFrame::Compact(CompactFrame::keyframe(&state)),
+            FrameFormat::CompactDelta { keyframe_every } => keyframe_every,
+        };
+        let frame = CompactFrame::keyframe(&state);
+        if self.since_keyframe < keyframe_every {
+            if let Some(delta) = self.keyframe.as_ref().and_then(|key| frame.delta(key)) {
+                self.since_keyframe += 1;
+                return StreamFrame::Compact(delta);
+            }
+        }
+        self.keyframe = Some(frame.clone());
+        self.since_keyframe = 1;
+        StreamFrame::Compact(frame)
+    }
+}
+
+pub(crate) fn validate_format(format: FrameFormat) -> Result<FrameFormat, CommandError> {
+    match format {
+        FrameFormat::CompactDelta { keyframe_every: 0 } => Err(CommandError::invalid(
+            "keyframe_every",
+            "must be at least 1",
+        )),
+        _ => Ok(format),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pendulum::BobMeta;
+    use crate::state::{AppDataInner, DEFAULT_DT};
+
+    /// The client side: rebuilds full frames from a compact stream.
+    #[derive(Default)]
+    struct Decoder {
+        keyframe: Option<CompactFrame>,
+    }
+
+    impl Decoder {
+        fn decode(&mut self, frame: CompactFrame) -> Option<PendulumState> {
+            let mut values = frame.clone();
+            match frame.delta_from {
+                None => self.keyframe = Some(frame),
+                Some(seq) => {
+                    let key = self.keyframe.as_ref().filter(|k| k.seq == seq)?;
+                    for (v, b) in values.arrays_mut().into_iter().zip(key.arrays()) {
+                        for (v, b) in v.iter_mut().zip(b) {
+                            *v += b;
+                        }
+                    }
+                }
+            }
+            let bobs = (0..values.theta.len())
+                .map(|i| BobState {
+                    id: values.id[i],
+                    theta: values.theta[i],
+                    omega: values.omega[i],
+                    alpha: values.alpha[i],
+                    position: Coordinate::new(values.x[i], values.y[i]),
+                    mass: values.mass[i],
+                    length_rod: values.length_rod[i],
+                    locked: values.locked.contains(&i),
+                    meta: BobMeta::default(),
+                })
+                .collect();
+            Some(PendulumState {
+                schema: values.schema,
+                seq: values.seq,
+                span: values.span,
+                sim_time: values.sim_time,
+                wall_time_ms: values.wall_time_ms,
+                time_deficit: values.time_deficit,
+                elapsed_time: values.elapsed_time,
+                step_count: values.step_count,
+                tick_interval: values.tick_interval,
+                paused: values.paused,
+                slow_motion: values.slow_motion,
+                pixels_per_meter: values.pixels_per_meter,
+                pivot: values.pivot,
+                bobs,
+                analytic: None,
+                dynamics: None,
+                ghost_bobs: None,
+                ensemble: None,
+                comparison: None,
+                wave: None,
+                bounds: None,
+                replay: values.replay,
+                transitions: Vec::new(),
+                structure_changes: values.structure_changes,
+            })
+        }
+    }
+
+    fn full_frames(count: u64) -> Vec<PendulumState> {
+        let mut data = AppDataInner::default();
+        let mut frames = Vec::new();
+        for seq in 0..count {
+            data.pendulum.step(DEFAULT_DT);
+            data.sim_time += DEFAULT_DT;
+            if seq == count / 2 {
+                data.pendulum.bobs.pop();
+            }
+            frames.push(PendulumState {
+                seq,
+                span: DEFAULT_DT,
+                ..data.frame(seq, seq as f64 * 8.0)
+            });
+        }
+        frames
+    }
+
+    fn assert_same(decoded: &PendulumState, full: &PendulumState) {
+        assert_eq!(
+            serde_json::to_value(decoded).unwrap(),
+            serde_json::to_value(full).unwrap()
+        );
+    }
+
+    #[test]
+    fn compact_streams_decode_to_the_full_frames() {
+        let frames = full_frames(200);
+        for format in [
+            FrameFormat::Compact,
+            FrameFormat::CompactDelta { keyframe_every: 1 },
+            FrameFormat::CompactDelta { keyframe_every: 30 },
+        ] {
+            let mut encoder = FrameEncoder::new(format);
+            let mut decoder = Decoder::default();
+            for full in &frames {
+                // through JSON, as the client sees it
+                let json = serde_json::to_string(&encoder.encode(full.clone())).unwrap();
+                let compact: CompactFrame = serde_json::from_str(&json).unwrap();
+                assert_same(&decoder.decode(compact).unwrap(), full);
+            }
+        }
+    }
+
+    #[test]
+    fn keyframes_recur_and_resync_on_request() {
+        let frames = full_frames(100);
+        let mut encoder = FrameEncoder::new(FrameFormat::CompactDelta { keyframe_every: 10 });
+        let mut keyframes = Vec::new();
+        for full in &frames[..40] {
+            if full.seq == 25 {
+                encoder.resync();
+            }
+            let StreamFrame::Compact(frame) = encoder.encode(full.clone()) else {
+                panic!("expected a compact frame");
+            };
+            if frame.delta_from.is_none() {
+                keyframes.push(frame.seq);
+            }
+        }
+        // at most ten frames per keyframe, and one right after the request
+        assert!(keyframes.windows(2).all(|w| w[1] - w[0] <= 10));
+        assert!(keyframes.contains(&25));
+        assert_eq!(keyframes[0], 0);
+
+        // a joiner that missed the keyframe resynchronizes at the next one
+        let mut decoder = Decoder::default();
+        let mut decoded = 0;
+        for full in &frames[40..] {
+            let StreamFrame::Compact(frame) = encoder.encode(full.clone()) else {
+                panic!("expected a compact frame");
+            };
+            if let Some(state) = decoder.decode(frame) {
+                assert_same(&state, full);
+                decoded += 1;
+            }
+        }
+        assert!(decoded >= 50);
+    }
+
+    #[test]
+    fn full_format_is_unchanged() {
+        let full = full_frames(1).remove(0);
+        let mut encoder = FrameEncoder::new(FrameFormat::Full);
+        assert_eq!(
+            serde_json::to_value(encoder.encode(full.clone())).unwrap(),
+            serde_json::to_value(&full).unwrap()
+        );
+        assert!(validate_format(FrameFormat::CompactDelta { keyframe_every: 0 }).is_err());
+        let format: FrameFormat = serde_json::from_str(r#"{"kind":"compactDelta"}"#).unwrap();
+        assert_eq!(
+            format,
+            FrameFormat::CompactDelta {
+                keyframe_every: DEFAULT_KEYFRAME_EVERY
+            }
+        );
+    }
+}
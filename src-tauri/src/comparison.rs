@@ -0,0 +1,203 @@
+use pendulum_core::{Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::state::AppDataInner;
+
+/// Most joint damping a comparison takes, in N·m·s/rad; far past where the
+/// chain just creeps to rest.
+pub(crate) const MAX_DAMPING: f64 = 100.0;
+
+pub(crate) fn validate_damping(damping: f64) -> Result<f64, CommandError> {
+    if damping.is_finite() && (0.0..=MAX_DAMPING).contains(&damping) {
+        Ok(damping)
+    } else {
+        Err(CommandError::invalid(
+            "damping",
+            format!("must be between 0 and {MAX_DAMPING}, got {damping}"),
+        ))
+    }
+}
+
+/// The damped clone of an instance, as `start_comparison` makes it: an
+/// instance of its own, but kept and stepped by the instance it clones,
+/// substep for substep, instead of by its own clock.
+pub(crate) struct Comparison {
+    pub(crate) instance: u64,
+    pub(crate) data: Box<AppDataInner>,
+    pub(crate) damping: f64,
+    /// Whether edits of the original are passed on.
+    pub(crate) mirror_edits: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ComparisonInfo {
+    /// Id of the clone, for subscribing to it on its own.
+    pub(crate) instance: u64,
+    pub(crate) damping: f64,
+    pub(crate) mirror_edits: bool,
+}
+
+/// The clone and how far it has drifted from the original, in every
+/// positions frame of the original.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ComparisonState {
+    pub(crate) instance: u64,
+    pub(crate) damping: f64,
+    /// The clone's bob positions, in the same frame as the original's.
+    pub(crate) positions: Vec<Coordinate>,
+    /// Distance between the two tips, in meters.
+    pub(crate) tip_distance: f64,
+    /// √Σ(Δθ)² over the joints, in radians.
+    pub(crate) angle_divergence: f64,
+    /// The original's total energy less the clone's, in joules: what the
+    /// friction has taken so far.
+    pub(crate) energy_gap: f64,
+}
+
+/// `pendulum` with the joint friction of the clone.
+fn damped(pendulum: &Pendulum, damping: f64) -> Pendulum {
+    Pendulum {
+        damping,
+        ..pendulum.clone()
+    }
+}
+
+impl Comparison {
+    /// The clone of `original`: the same chain, settings and clocks, with
+    /// `damping` in its joints, and nothing running on it yet.
+    pub(crate) fn clone_of(original: &AppDataInner, damping: f64) -> AppDataInner {
+        AppDataInner {
+            pendulum: damped(&original.pendulum, damping),
+            initial: damped(&original.initial, damping),
+            next_bob_id: original.next_bob_id,
+            bob_meta: original.bob_meta.clone(),
+            max_bobs: original.max_bobs,
+            angle_unit: original.angle_unit,
+            angle_convention: original.angle_convention,
+            world_frame: original.world_frame,
+            pixels_per_meter: original.pixels_per_meter,
+            sim_time: original.sim_time,
+            dt: original.dt,
+            time_scale: original.time_scale,
+            clock: original.clock.clone(),
+            lockstep: true,
+            ..AppDataInner::default()
+        }
+    }
+
+    pub(crate) fn info(&self) -> ComparisonInfo {
+        ComparisonInfo {
+            instance: self.instance,
+            damping: self.damping,
+            mirror_edits: self.mirror_edits,
+        }
+    }
+
+    /// Takes the substep the original just took, the clone's events kept
+    /// for its own next tick.
+    pub(crate) fn step(&mut self, dt: f64) {
+        let clone = &mut self.data;
+        let mut events = Vec::new();
+        clone.substep(dt, &mut events);
+        clone.pending_events.append(&mut events);
+    }
+
+    /// Puts the edited `original` into the clone, friction aside, when
+    /// edits are mirrored, so the damping stays the only difference.
+    pub(crate) fn follow(&mut self, original: &AppDataInner) {
+        if !self.mirror_edits {
+            return;
+        }
+        let clone = &mut self.data;
+        clone.pendulum = damped(&original.pendulum, self.damping);
+        clone.initial = damped(&original.initial, self.damping);
+        clone.bob_meta = original.bob_meta.clone();
+        clone.sim_time = original.sim_time;
+        clone.dt = original.dt;
+        clone.clock = original.clock.clone();
+        clone.state_edited();
+    }
+
+    pub(crate) fn state(&self, original: &Pendulum) -> ComparisonState {
+        let ours = &self.data.pendulum;
+        let tip = |p: &Pendulum| p.bobs.last().map(|b| b.coordinate);
+        let tip_distance = match (tip(original), tip(ours)) {
+            (Some(a), Some(b)) => (a.x - b.x).hypot(a.y - b.y),
+            _ => 0.0,
+        };
+        let angle_divergence = (original.bobs.iter().zip(&ours.bobs))
+            .map(|(a, b)| (a.theta - b.theta).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        ComparisonState {
+            instance: self.instance,
+            damping: self.damping,
+            positions: ours.bobs.iter().map(|b| b.coordinate).collect(),
+            tip_distance,
+            angle_divergence,
+            energy_gap: original.total_energy() - ours.total_energy(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instances::{World, PRIMARY_INSTANCE};
+
+    fn run(data: &mut AppDataInner, steps: usize) {
+        for _ in 0..steps {
+            let dt = data.dt;
+            data.substep(dt, &mut Vec::new());
+        }
+    }
+
+    #[test]
+    fn the_damped_clone_keeps_in_step_and_only_loses_energy() {
+        let mut world = World::new(AppDataInner::default());
+        assert!(world.start_comparison(None, -1.0, false).is_err());
+        let info = world.start_comparison(None, 0.5, true).unwrap();
+        assert!(world.start_comparison(None, 0.5, true).is_err());
+        assert!(world
+            .start_comparison(Some(info.instance), 0.5, true)
+            .is_err());
+        let clone = Some(info.instance);
+
+        let mut last_gap = 0.0;
+        for _ in 0..10 {
+            run(world.primary(), 100);
+            let frame = world.primary().frame(0, 0.0);
+            let comparison = frame.comparison.unwrap();
+            assert!(comparison.energy_gap > last_gap, "{comparison:?}");
+            last_gap = comparison.energy_gap;
+            assert_eq!(comparison.positions.len(), frame.bobs.len());
+            let clone = world.get(clone).unwrap();
+            assert_eq!(clone.sim_time, frame.sim_time);
+            assert_eq!(clone.clock.steps, frame.step_count);
+        }
+        let frame = world.primary().frame(0, 0.0);
+        assert!(frame.comparison.unwrap().angle_divergence > 0.0);
+
+        // a mirrored edit leaves the two the same again but for the damping
+        world.primary().zero_velocities().unwrap();
+        let original = world.primary().pendulum.clone();
+        let cloned = world.get(clone).unwrap().pendulum.clone();
+        assert_eq!(original.bobs, cloned.bobs);
+        assert_eq!((original.damping, cloned.damping), (0.0, 0.5));
+
+        assert!(world.stop_comparison(None).unwrap());
+        assert!(!world.stop_comparison(None).unwrap());
+        assert!(world.get(clone).is_err());
+        assert!(world.primary().comparison.is_none());
+
+        // destroying the clone itself ends the comparison as well
+        let info = world.start_comparison(None, 1.0, false).unwrap();
+        world.destroy(info.instance).unwrap();
+        assert!(world.primary().frame(0, 0.0).comparison.is_none());
+        assert_eq!(world.list().len(), 1);
+        assert_eq!(world.list()[0].id, PRIMARY_INSTANCE);
+    }
+}
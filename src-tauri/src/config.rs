@@ -0,0 +1,259 @@
+use pendulum_core::{Coordinate, Integrator, GRAVITATIONAL_ACCELERATION};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::comparison;
+use crate::error::CommandError;
+use crate::integrator::{IntegratorExt, IntegratorSettings};
+use crate::pendulum::BobSpec;
+use crate::presets::Preset;
+
+/// Version of the configuration files `export_config` writes. Files from
+/// newer versions are refused; older ones would be migrated on import.
+pub(crate) const CONFIG_VERSION: u32 = 1;
+
+const FILE_FIELDS: &[&str] = &["version", "bobs", "world"];
+const BOB_FIELDS: &[&str] = &[
+    "lengthRod",
+    "mass",
+    "theta",
+    "omega",
+    "id",
+    "locked",
+    "color",
+    "label",
+];
+const WORLD_FIELDS: &[&str] = &[
+    "gravity",
+    "dt",
+    "timeScale",
+    "integrator",
+    "damping",
+    "pivot",
+];
+
+/// Everything that defines a run but not where it has got to: the bobs as
+/// they are now, with their colors and labels, and the world they swing
+/// in. Angles are canonical radians and lengths meters, whatever the
+/// commands show them as.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ConfigFile {
+    pub(crate) version: u32,
+    pub(crate) bobs: Vec<BobSpec>,
+    pub(crate) world: WorldConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WorldConfig {
+    /// In m/s². Only the gravity the simulation runs with is accepted; it
+    /// is written down so a file says what it was made for.
+    pub(crate) gravity: f64,
+    pub(crate) dt: f64,
+    pub(crate) time_scale: f64,
+    pub(crate) integrator: IntegratorSettings,
+    /// Joint friction, in N·m·s/rad.
+    pub(crate) damping: f64,
+    pub(crate) pivot: Coordinate,
+}
+
+/// What `import_config` returns: the fields it didn't know and left alone.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImportReport {
+    pub(crate) warnings: Vec<String>,
+}
+
+/// A warning for each key of `object` not in `known`.
+fn unknown_fields(
+    object: &Map<String, Value>,
+    known: &[&str],
+    at: &str,
+    warnings: &mut Vec<String>,
+) {
+    for key in object.keys().filter(|key| !known.contains(&key.as_str())) {
+        warnings.push(format!("Ignored unknown field {at}{key}"));
+    }
+}
+
+fn object<'a>(value: &'a Value, name: &str) -> Result<&'a Map<String, Value>, CommandError> {
+    value
+        .as_object()
+        .ok_or_else(|| CommandError::invalid(name, "must be an object"))
+}
+
+/// Field `key` of `object`, read as a `T`, the error naming it as `name`.
+fn field<T: DeserializeOwned>(
+    object: &Map<String, Value>,
+    key: &str,
+    name: &str,
+) -> Result<T, CommandError> {
+    let value = (object.get(key)).ok_or_else(|| CommandError::invalid(name, "is missing"))?;
+    serde_json::from_value(value.clone()).map_err(|e| CommandError::invalid(name, e.to_string()))
+}
+
+/// Reads a configuration file, field by field so that a value of the wrong
+/// type is reported with its name, and warns about fields it doesn't know.
+/// The values themselves are checked by `ConfigFile::validate`.
+pub(crate) fn parse(text: &str) -> Result<(ConfigFile, Vec<String>), CommandError> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|e| CommandError::invalid("file", format!("isn't valid JSON: {e}")))?;
+    let root = object(&value, "file")?;
+    let version: u32 = field(root, "version", "version")?;
+    if version > CONFIG_VERSION {
+        return Err(CommandError::UnsupportedVersion {
+            what: "Configuration file",
+            version,
+            newest: CONFIG_VERSION,
+        });
+    }
+    if version == 0 {
+        return Err(CommandError::invalid("version", "must be at least 1"));
+    }
+    let mut warnings = Vec::new();
+    unknown_fields(root, FILE_FIELDS, "", &mut warnings);
+
+    let entries: Vec<Value> = field(root, "bobs", "bobs")?;
+    let mut bobs = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        let name = format!("bobs[{i}]");
+        unknown_fields(
+            object(entry, &name)?,
+            BOB_FIELDS,
+            &format!("{name}."),
+            &mut warnings,
+        );
+        let bob = serde_json::from_value(entry.clone())
+            .map_err(|e| CommandError::invalid(&name, e.to_string()))?;
+        bobs.push(bob);
+    }
+
+    let world = (root.get("world")).ok_or_else(|| CommandError::invalid("world", "is missing"))?;
+    let world = object(world, "world")?;
+    unknown_fields(world, WORLD_FIELDS, "world.", &mut warnings);
+    let world = WorldConfig {
+        gravity: field(world, "gravity", "world.gravity")?,
+        dt: field(world, "dt", "world.dt")?,
+        time_scale: field(world, "timeScale", "world.timeScale")?,
+        integrator: field(world, "integrator", "world.integrator")?,
+        damping: field(world, "damping", "world.damping")?,
+        pivot: field(world, "pivot", "world.pivot")?,
+    };
+    Ok((
+        ConfigFile {
+            version,
+            bobs,
+            world,
+        },
+        warnings,
+    ))
+}
+
+impl ConfigFile {
+    /// The bobs and world settings as a preset, for `apply_preset`.
+    pub(crate) fn preset(&self) -> Preset {
+        Preset {
+            name: String::new(),
+            description: String::new(),
+            bobs: self.bobs.clone(),
+            dt: self.world.dt,
+            time_scale: self.world.time_scale,
+            pivot: Some(self.world.pivot),
+        }
+    }
+
+    /// Checks the world settings, returning the integrator they name; the
+    /// bobs and pivot are checked by `set_state`.
+    pub(crate) fn validate(&self) -> Result<Integrator, CommandError> {
+        let world = &self.world;
+        if world.gravity != GRAVITATIONAL_ACCELERATION {
+            return Err(CommandError::invalid(
+                "world.gravity",
+                format!(
+                    "must be {GRAVITATIONAL_ACCELERATION}, the gravity the simulation runs with, \
+                     got {}",
+                    world.gravity
+                ),
+            ));
+        }
+        self.preset().validate().map_err(|e| e.within("world"))?;
+        comparison::validate_damping(world.damping).map_err(|e| e.within("world"))?;
+        Integrator::from_settings(&world.integrator).map_err(|e| e.within("world.integrator"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppDataInner;
+
+    #[test]
+    fn configs_round_trip_and_the_v1_fixture_still_loads() {
+        let mut data = AppDataInner::default();
+        data.pendulum.bobs[1].theta = 0.25;
+        data.pendulum.damping = 0.1;
+        data.time_scale = 0.5;
+        let exported = serde_json::to_string_pretty(&data.export_config()).unwrap();
+
+        let mut other = AppDataInner::default();
+        let report = other.import_config(&exported).unwrap();
+        assert!(report.warnings.is_empty(), "{report:?}");
+        assert_eq!(other.export_config(), data.export_config());
+        assert_eq!(other.pendulum.damping, 0.1);
+
+        // the checked-in file of the first version loads as it did
+        let fixture = include_str!("fixtures/config-v1.json");
+        let report = other.import_config(fixture).unwrap();
+        assert_eq!(report.warnings, ["Ignored unknown field world.wind"]);
+        assert_eq!(other.pendulum.n(), 2);
+        assert_eq!(other.pendulum.bobs[0].theta, 2.0);
+        assert_eq!((other.dt, other.pendulum.damping), (0.001, 0.05));
+        assert_eq!(other.pendulum.pivot, Coordinate::new(0.5, 0.0));
+        let config = other.export_config();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.bobs[1].meta.label.as_deref(), Some("tip"));
+
+        // bad files change nothing and name what is wrong
+        let before = other.export_config();
+        let name = |text: &str| match parse(text).and_then(|(config, _)| config.validate()) {
+            Err(CommandError::InvalidParameter { name, .. }) => name,
+            other => panic!("expected an invalid parameter, got {other:?}"),
+        };
+        let with = |path: &str, value: Value| {
+            let mut json: Value = serde_json::from_str(fixture).unwrap();
+            *json.pointer_mut(path).unwrap() = value;
+            json.to_string()
+        };
+        assert_eq!(name(&with("/world/dt", Value::from(-1.0))), "world.dt");
+        assert_eq!(name(&with("/world/dt", Value::from("fast"))), "world.dt");
+        assert_eq!(
+            name(&with("/world/gravity", Value::from(1.6))),
+            "world.gravity"
+        );
+        assert_eq!(
+            name(&with("/world/damping", Value::from(1e9))),
+            "world.damping"
+        );
+        assert_eq!(
+            name(&with(
+                "/world/integrator/values/updateOrder",
+                Value::from("x")
+            )),
+            "world.integrator.values.updateOrder"
+        );
+        assert_eq!(name(&with("/bobs/1/mass", Value::from("heavy"))), "bobs[1]");
+        assert_eq!(name("[]"), "file");
+        let negative_mass = with("/bobs/1/mass", Value::from(-1.0));
+        assert!(matches!(
+            other.import_config(&negative_mass),
+            Err(CommandError::InvalidParameter { name, .. }) if name == "bobs[1].mass"
+        ));
+        assert!(matches!(
+            other.import_config(&with("/version", Value::from(9))),
+            Err(CommandError::UnsupportedVersion { version: 9, .. })
+        ));
+        assert_eq!(other.export_config(), before);
+    }
+}
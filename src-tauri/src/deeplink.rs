@@ -0,0 +1,178 @@
+use pendulum_core::{DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA};
+
+use crate::error::CommandError;
+use crate::pendulum::{BobMeta, BobSpec};
+use crate::presets::Preset;
+use crate::share;
+
+/// The URL scheme links to the app use, as in
+/// `double-pendulum://open?state=…`.
+pub(crate) const DEEP_LINK_SCHEME: &str = "double-pendulum";
+/// Most bobs a link's query parameters may ask for.
+pub(crate) const MAX_LINK_BOBS: usize = 64;
+
+/// What a link asks the app to open.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LinkPayload {
+    /// `?state=`, a string from `export_state_string`.
+    Setup(Preset),
+    /// `?bobs=2&theta1=1.2&theta2=2.0`: a chain of that many bobs, each
+    /// optionally with `thetaN`, `omegaN`, `lengthN` and `massN`, numbered
+    /// from 1. Angles are canonical radians; the rest is the default bob's.
+    Bobs(Vec<BobSpec>),
+}
+
+fn invalid(reason: impl Into<String>) -> CommandError {
+    CommandError::invalid("url", reason)
+}
+
+/// `%XX` escapes decoded, and `+` read as a space, as in a form-encoded
+/// query.
+fn percent_decode(text: &str) -> Result<String, CommandError> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'%' => {
+                let hex = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| invalid(format!("has a bad escape in {text:?}")))?;
+                bytes.push(hex);
+                rest = &rest[2..];
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid(format!("{text:?} isn't UTF-8 once decoded")))
+}
+
+/// Reads a link to the app: the scheme must be `DEEP_LINK_SCHEME`, and the
+/// query either `state=` alone or the bob parameters. Unknown parameters
+/// are refused, so a typo doesn't go unnoticed. The values are only checked
+/// when the payload is applied.
+pub(crate) fn parse(url: &str) -> Result<LinkPayload, CommandError> {
+    let rest = (url.trim().split_once(':'))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(DEEP_LINK_SCHEME))
+        .map(|(_, rest)| rest)
+        .ok_or_else(|| invalid(format!("must start with {DEEP_LINK_SCHEME}:")))?;
+    let query = rest.split_once('?').map_or("", |(_, query)| query);
+    let query = query.split_once('#').map_or(query, |(query, _)| query);
+    let mut params = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params.push((percent_decode(key)?, percent_decode(value)?));
+    }
+    if params.is_empty() {
+        return Err(invalid("has no query to open"));
+    }
+    if let Some((_, state)) = params.iter().find(|(key, _)| key == "state") {
+        if params.len() > 1 {
+            return Err(invalid("state= goes alone, without other parameters"));
+        }
+        return share::decode(state).map(LinkPayload::Setup);
+    }
+
+    let number = |key: &str, value: &str| {
+        value
+            .parse::<f64>()
+            .map_err(|_| invalid(format!("parameter {key} must be a number, got {value:?}")))
+    };
+    let count = params
+        .iter()
+        .find(|(key, _)| key == "bobs")
+        .ok_or_else(|| invalid("needs state= or bobs="))?
+        .1
+        .parse::<usize>()
+        .ok()
+        .filter(|count| (1..=MAX_LINK_BOBS).contains(count))
+        .ok_or_else(|| {
+            invalid(format!(
+                "bobs must be a whole number from 1 to {MAX_LINK_BOBS}"
+            ))
+        })?;
+    let mut bobs = vec![
+        BobSpec {
+            length_rod: DEFAULT_LENGTH_ROD,
+            mass: DEFAULT_MASS,
+            theta: DEFAULT_THETA,
+            omega: 0.0,
+            id: None,
+            locked: false,
+            meta: BobMeta::default(),
+        };
+        count
+    ];
+    for (key, value) in params.iter().filter(|(key, _)| key != "bobs") {
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let (field, index) = key.split_at(split);
+        let bob = index
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| bobs.get_mut(i))
+            .ok_or_else(|| invalid(format!("has an unknown parameter {key:?}")))?;
+        let target = match field {
+            "theta" => &mut bob.theta,
+            "omega" => &mut bob.omega,
+            "length" => &mut bob.length_rod,
+            "mass" => &mut bob.mass,
+            _ => return Err(invalid(format!("has an unknown parameter {key:?}"))),
+        };
+        *target = number(key, value)?;
+    }
+    Ok(LinkPayload::Bobs(bobs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::SimEvent;
+    use crate::state::AppDataInner;
+
+    #[test]
+    fn links_open_their_setup_and_bad_ones_only_warn() {
+        let LinkPayload::Bobs(bobs) =
+            parse("double-pendulum://open?bobs=2&theta1=1.2&theta2=2.0&mass2=%33").unwrap()
+        else {
+            panic!("expected bobs");
+        };
+        assert_eq!(bobs.len(), 2);
+        assert_eq!((bobs[0].theta, bobs[1].theta), (1.2, 2.0));
+        assert_eq!((bobs[0].mass, bobs[1].mass), (DEFAULT_MASS, 3.0));
+
+        let mut data = AppDataInner::default();
+        data.pendulum.bobs[0].theta = 0.5;
+        let shared = data.export_state_string();
+        let link = format!("{DEEP_LINK_SCHEME}://open?state={shared}");
+        assert!(matches!(parse(&link), Ok(LinkPayload::Setup(_))));
+
+        for bad in [
+            "https://example.com/?bobs=2",
+            "double-pendulum://open",
+            "double-pendulum://open?bobs=0",
+            "double-pendulum://open?bobs=2&theta3=1",
+            "double-pendulum://open?bobs=2&theta1=up",
+            "double-pendulum://open?bobs=2&speed1=1",
+            "double-pendulum://open?state=AAAA",
+            "double-pendulum://open?state=x&bobs=2",
+        ] {
+            assert!(parse(bad).is_err(), "{bad}");
+        }
+
+        // opening one applies it; a bad one leaves the chain and says why
+        let mut data = AppDataInner::default();
+        data.open_link(&link);
+        assert_eq!(data.export_state_string(), shared);
+        assert!(data.pending_events.is_empty());
+        data.open_link("double-pendulum://open?bobs=2&mass1=-1");
+        assert_eq!(data.export_state_string(), shared);
+        let [SimEvent::LinkRejected(event)] = &data.pending_events[..] else {
+            panic!("expected a rejected link event");
+        };
+        assert!(event.reason.contains("mass"), "{}", event.reason);
+    }
+}
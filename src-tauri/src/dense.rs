@@ -0,0 +1,268 @@
+use pendulum_core::Pendulum;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::CommandError;
+use crate::recording::{hermite, RecordingConfiguration};
+use crate::state::AppDataInner;
+use crate::trajectory::{write_trajectory, FileReport, RecordingFormat};
+
+/// Step an export takes, unless the live dt is finer.
+pub(crate) const DENSE_DT: f64 = 1e-4;
+/// Longest stretch of sim time one export covers, in seconds.
+pub(crate) const MAX_DENSE_DURATION: f64 = 3600.0;
+/// Most samples per sim second an export takes.
+pub(crate) const MAX_SAMPLE_RATE: f64 = 10_000.0;
+/// Most samples one export writes.
+pub(crate) const MAX_DENSE_SAMPLES: u64 = 10_000_000;
+/// Samples between progress reports and looks at the cancel flag.
+const PROGRESS_INTERVAL: u64 = 1_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DenseProgress {
+    pub(crate) written_samples: u64,
+    pub(crate) total_samples: u64,
+}
+
+/// Checks the request and returns the number of samples it will write: one
+/// at the start and one at every sample interval up to `duration`.
+pub(crate) fn validate(duration: f64, sample_rate: f64) -> Result<u64, CommandError> {
+    if !(duration.is_finite() && duration > 0.0 && duration <= MAX_DENSE_DURATION) {
+        return Err(CommandError::invalid(
+            "duration",
+            format!("must be positive and at most {MAX_DENSE_DURATION} s, got {duration}"),
+        ));
+    }
+    if !(sample_rate.is_finite() && sample_rate > 0.0 && sample_rate <= MAX_SAMPLE_RATE) {
+        return Err(CommandError::invalid(
+            "sample_rate",
+            format!("must be positive and at most {MAX_SAMPLE_RATE} per s, got {sample_rate}"),
+        ));
+    }
+    // a hair of slack, so 1 s at 60 per s ends on the 60th sample
+    let samples = (duration * sample_rate * (1.0 + 1e-12)).floor() as u64 + 1;
+    if samples > MAX_DENSE_SAMPLES {
+        return Err(CommandError::invalid(
+            "sample_rate",
+            format!(
+                "of {sample_rate} over {duration} s makes {samples} samples, more than the limit of {MAX_DENSE_SAMPLES}"
+            ),
+        ));
+    }
+    Ok(samples)
+}
+
+/// Where an export starts from.
+#[derive(Clone, Debug)]
+pub(crate) struct DenseStart {
+    pub(crate) pendulum: Pendulum,
+    pub(crate) sim_time: f64,
+    /// The step the export takes.
+    pub(crate) dt: f64,
+}
+
+impl DenseStart {
+    /// A copy of the live chain, or of the one saved under `snapshot`.
+    /// Gravity, damping and the integrator are the live ones either way.
+    pub(crate) fn new(data: &AppDataInner, snapshot: Option<&str>) -> Result<Self, CommandError> {
+        let mut pendulum = data.pendulum.clone();
+        let Some(name) = snapshot else {
+            return Ok(Self {
+                pendulum,
+                sim_time: data.sim_time,
+                dt: data.dt.min(DENSE_DT),
+            });
+        };
+        let snapshot = data.snapshots.get(name)?;
+        pendulum.bobs = snapshot.configuration.bobs.clone();
+        pendulum.pivot = snapshot.configuration.pivot;
+        pendulum.update_coordinates();
+        Ok(Self {
+            pendulum,
+            sim_time: snapshot.sim_time,
+            dt: snapshot.configuration.dt.min(DENSE_DT),
+        })
+    }
+}
+
+/// The chain every `1 / sample_rate` seconds from where it is, as steps of
+/// `dt` take it, each sample with its seconds since the first. Samples
+/// fall between steps: the angles there follow the cubic Hermite through
+/// both steps' angles and angular velocities, and the angular velocities
+/// the one through their accelerations, so every sample is at its exact
+/// time rather than at the step nearest it.
+struct DenseSamples {
+    before: Pendulum,
+    after: Pendulum,
+    /// Of `before` and `after`, worked out once a sample lands between them.
+    accelerations: Option<(Vec<f64>, Vec<f64>)>,
+    dt: f64,
+    /// Steps `after` has taken.
+    steps: u64,
+    sample_rate: f64,
+    next: u64,
+    total: u64,
+}
+
+impl DenseSamples {
+    fn new(pendulum: Pendulum, dt: f64, sample_rate: f64, total: u64) -> Self {
+        Self {
+            before: pendulum.clone(),
+            after: pendulum,
+            accelerations: None,
+            dt,
+            steps: 0,
+            sample_rate,
+            next: 0,
+            total,
+        }
+    }
+}
+
+impl Iterator for DenseSamples {
+    type Item = (Pendulum, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == self.total {
+            return None;
+        }
+        let time = self.next as f64 / self.sample_rate;
+        self.next += 1;
+        while time > self.steps as f64 * self.dt {
+            self.before.clone_from(&self.after);
+            self.after.step(self.dt);
+            self.steps += 1;
+            self.accelerations = None;
+        }
+        if self.steps == 0 {
+            return Some((self.after.clone(), time));
+        }
+        let h = self.dt;
+        let s = (time - (self.steps - 1) as f64 * h) / h;
+        let (before, after) = (&self.before, &self.after);
+        let (alpha_a, alpha_b) = (self.accelerations)
+            .get_or_insert_with(|| (before.accelerations(), after.accelerations()));
+        let mut sample = after.clone();
+        for (i, bob) in sample.bobs.iter_mut().enumerate() {
+            let (a, b) = (&before.bobs[i], &after.bobs[i]);
+            bob.theta = hermite(s, h, (a.theta, a.omega), (b.theta, b.omega));
+            bob.omega = hermite(s, h, (a.omega, alpha_a[i]), (b.omega, alpha_b[i]));
+        }
+        sample.update_coordinates();
+        Some((sample, time))
+    }
+}
+
+/// Writes `total` samples from `start` on, every `1 / sample_rate` of sim
+/// time, to `path` in `format`; see `DenseSamples`. Returns `None`, with
+/// the file removed, if `cancel` is raised first.
+pub(crate) fn export(
+    path: &Path,
+    format: RecordingFormat,
+    start: DenseStart,
+    sample_rate: f64,
+    total: u64,
+    cancel: &AtomicBool,
+    progress: impl Fn(DenseProgress),
+) -> Result<Option<FileReport>, CommandError> {
+    let bobs = start.pendulum.n();
+    let configuration = RecordingConfiguration::of(&start.pendulum);
+    let mut written = 0;
+    let samples = DenseSamples::new(start.pendulum, start.dt, sample_rate, total).map_while(
+        |(pendulum, time)| {
+            if written % PROGRESS_INTERVAL == 0 && cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            written += 1;
+            if written % PROGRESS_INTERVAL == 0 || written == total {
+                progress(DenseProgress {
+                    written_samples: written,
+                    total_samples: total,
+                });
+            }
+            Some((pendulum, start.sim_time + time))
+        },
+    );
+    let interval = 1.0 / sample_rate;
+    let report = write_trajectory(path, format, bobs, interval, Some(&configuration), samples)?;
+    if report.rows < total {
+        let _ = std::fs::remove_file(path);
+        return Ok(None);
+    }
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trajectory::read_recording;
+
+    #[test]
+    fn samples_land_on_exact_times_between_steps() {
+        let mut data = AppDataInner::default();
+        for bob in &mut data.pendulum.bobs {
+            (bob.theta, bob.omega) = (std::f64::consts::PI + 0.1, 0.0);
+        }
+        data.pendulum.update_coordinates();
+        let start = DenseStart::new(&data, None).unwrap();
+        assert_eq!((start.sim_time, start.dt), (0.0, DENSE_DT));
+        assert!(DenseStart::new(&data, Some("none")).is_err());
+        let pendulum = start.pendulum.clone();
+        assert_eq!(validate(1.0, 60.0).unwrap(), 61);
+        assert!(validate(0.0, 60.0).is_err());
+        assert!(validate(MAX_DENSE_DURATION, MAX_SAMPLE_RATE).is_err());
+
+        // 1/60 s isn't a whole number of 1e-3 steps, so most samples fall
+        // between them; a much finer run of the gentle swing agrees with
+        // what they interpolate
+        let samples: Vec<_> = DenseSamples::new(pendulum.clone(), 1e-3, 60.0, 61).collect();
+        assert_eq!(samples.len(), 61);
+        let mut fine = pendulum.clone();
+        let fine_dt = 1e-3 / 60.0;
+        for (k, (sample, time)) in samples.iter().enumerate() {
+            assert_eq!(*time, k as f64 / 60.0);
+            if k > 0 {
+                for _ in 0..1000 {
+                    fine.step(fine_dt);
+                }
+            }
+            for (a, b) in sample.bobs.iter().zip(&fine.bobs) {
+                assert!((a.theta - b.theta).abs() < 1e-3, "{k}: {a:?} {b:?}");
+                assert!((a.omega - b.omega).abs() < 1e-3, "{k}: {a:?} {b:?}");
+            }
+        }
+        // where a sample is on a step, it is that step
+        let mut stepped = pendulum.clone();
+        for _ in 0..20 {
+            stepped.step(1e-3);
+        }
+        let on_step = DenseSamples::new(pendulum.clone(), 1e-3, 50.0, 2)
+            .nth(1)
+            .unwrap();
+        assert!((on_step.0.bobs[0].theta - stepped.bobs[0].theta).abs() < 1e-12);
+
+        // written whole, or not at all once cancelled
+        let path = std::env::temp_dir().join(format!("dense-{}.dptr", std::process::id()));
+        let format = RecordingFormat::Binary { compressed: true };
+        let cancel = AtomicBool::new(false);
+        let reports = std::cell::Cell::new(0);
+        let start = DenseStart {
+            pendulum,
+            sim_time: 2.0,
+            dt: 1e-3,
+        };
+        let count = |_| reports.set(reports.get() + 1);
+        let report = export(&path, format, start.clone(), 60.0, 3001, &cancel, count);
+        let report = report.unwrap().unwrap();
+        assert_eq!((report.rows, reports.get()), (3001, 4));
+        let recording = read_recording(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(recording.start(), 2.0);
+        assert!((recording.duration() - 50.0).abs() < 1e-9);
+        cancel.store(true, Ordering::Relaxed);
+        let cancelled = export(&path, format, start, 60.0, 3001, &cancel, |_| {});
+        assert_eq!(cancelled.unwrap(), None);
+        assert!(!path.exists());
+    }
+}
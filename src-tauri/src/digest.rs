@@ -0,0 +1,158 @@
+use pendulum_core::{Integrator, Pendulum};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::integrator::{IntegratorExt, IntegratorSettings};
+
+/// Version of what a digest is taken of. It goes up whenever the canonical
+/// start, the quantization or the hash changes, so digests of different
+/// versions aren't compared.
+pub(crate) const DIGEST_VERSION: u32 = 1;
+/// Step a digest quantizes θ and ω to, to begin with: fine enough that any
+/// change to the dynamics shows, coarse enough that a last-bit difference
+/// in a sum mostly doesn't.
+pub(crate) const DEFAULT_DIGEST_TOLERANCE: f64 = 1e-9;
+/// Most steps one digest takes.
+pub(crate) const MAX_DIGEST_STEPS: u64 = 10_000_000;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// What `compute_trajectory_digest` returns: the digest with everything it
+/// depends on, so two of them are only compared when those agree.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrajectoryDigest {
+    /// 16 hex digits.
+    pub(crate) digest: String,
+    pub(crate) version: u32,
+    pub(crate) steps: u64,
+    pub(crate) dt: f64,
+    pub(crate) tolerance: f64,
+    pub(crate) integrator: IntegratorSettings,
+}
+
+/// FNV-1a, which is simple enough to stay the same on every platform and
+/// in every version, unlike the standard library's hasher.
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+pub(crate) fn validate(steps: u64, dt: f64, tolerance: f64) -> Result<(), CommandError> {
+    if !(1..=MAX_DIGEST_STEPS).contains(&steps) {
+        return Err(CommandError::invalid(
+            "steps",
+            format!("must be between 1 and {MAX_DIGEST_STEPS}, got {steps}"),
+        ));
+    }
+    if !(dt.is_finite() && dt > 0.0) {
+        return Err(CommandError::invalid(
+            "dt",
+            format!("must be positive and finite, got {dt}"),
+        ));
+    }
+    if !(tolerance.is_finite() && tolerance > 0.0) {
+        return Err(CommandError::invalid(
+            "tolerance",
+            format!("must be positive and finite, got {tolerance}"),
+        ));
+    }
+    Ok(())
+}
+
+/// The hash of `pendulum`'s trajectory over `steps` steps of `dt`: every
+/// bob's θ and ω before the first step and after each, rounded to whole
+/// multiples of `tolerance`. A value that sits on a rounding boundary can
+/// still flip, but one that close is rare.
+pub(crate) fn digest(mut pendulum: Pendulum, steps: u64, dt: f64, tolerance: f64) -> u64 {
+    let mut hash = Fnv(FNV_OFFSET);
+    hash.write(&(pendulum.n() as u64).to_le_bytes());
+    for step in 0..=steps {
+        if step > 0 {
+            pendulum.step(dt);
+        }
+        for bob in &pendulum.bobs {
+            for value in [bob.theta, bob.omega] {
+                // non-finite values saturate, so a blow-up still hashes the same
+                let quantized = (value / tolerance).round() as i64;
+                hash.write(&quantized.to_le_bytes());
+            }
+        }
+    }
+    hash.0
+}
+
+/// The digest of the default chain, hanging as the app starts it, stepped
+/// with `integrator`.
+pub(crate) fn canonical(
+    integrator: Integrator,
+    steps: u64,
+    dt: f64,
+    tolerance: f64,
+) -> TrajectoryDigest {
+    let pendulum = Pendulum {
+        integrator,
+        ..Pendulum::default()
+    };
+    TrajectoryDigest {
+        digest: format!("{:016x}", digest(pendulum, steps, dt, tolerance)),
+        version: DIGEST_VERSION,
+        steps,
+        dt,
+        tolerance,
+        integrator: integrator.settings(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DEFAULT_DT;
+    use pendulum_core::UpdateOrder;
+
+    /// Digests of the canonical run, 2 s at the default dt, that the
+    /// dynamics are held to. Only update one on purpose, together with
+    /// whatever changed the dynamics, and say so in the commit. They hold
+    /// where `sin` and `cos` round as they do on the CI machines.
+    const GOLDEN: [(UpdateOrder, &str); 2] = [
+        (UpdateOrder::VelocityFirst, "5f4153cb4e5ae8a2"),
+        (UpdateOrder::PositionFirst, "89ae69d8d6ace7b8"),
+    ];
+
+    #[test]
+    fn the_canonical_trajectories_match_their_golden_digests() {
+        for (order, golden) in GOLDEN {
+            let integrator = Integrator::SymplecticEuler { order };
+            let run = canonical(integrator, 1_000, DEFAULT_DT, DEFAULT_DIGEST_TOLERANCE);
+            assert_eq!(run.digest, golden, "{order:?}");
+            // the same run again is the same digest
+            let again = canonical(integrator, 1_000, DEFAULT_DT, DEFAULT_DIGEST_TOLERANCE);
+            assert_eq!(again, run);
+        }
+        assert_eq!(Integrator::default(), {
+            let (order, _) = GOLDEN[0];
+            Integrator::SymplecticEuler { order }
+        });
+
+        // a last-bit nudge doesn't change it, a real difference does
+        let pendulum = Pendulum::default();
+        let base = digest(pendulum.clone(), 100, DEFAULT_DT, 1e-6);
+        let mut nudged = pendulum.clone();
+        nudged.bobs[0].theta = f64::from_bits(nudged.bobs[0].theta.to_bits() + 1);
+        assert_eq!(digest(nudged, 100, DEFAULT_DT, 1e-6), base);
+        let mut moved = pendulum.clone();
+        moved.bobs[0].theta += 1e-5;
+        assert_ne!(digest(moved, 100, DEFAULT_DT, 1e-6), base);
+        assert_ne!(digest(pendulum, 100, DEFAULT_DT / 2.0, 1e-6), base);
+
+        assert!(validate(0, DEFAULT_DT, 1e-9).is_err());
+        assert!(validate(10, 0.0, 1e-9).is_err());
+        assert!(validate(10, DEFAULT_DT, -1.0).is_err());
+    }
+}
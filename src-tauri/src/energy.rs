@@ -0,0 +1,259 @@
+use pendulum_core::Pendulum;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::CommandError;
+use crate::recording::Recording;
+use crate::savefile;
+use crate::state::AppDataInner;
+use crate::trajectory::{read_recording, FileReport};
+
+/// Most rows one energy series holds: a run forward is cut to this many
+/// steps, and a recording never has more samples.
+pub(crate) const MAX_ENERGY_ROWS: u64 = 1_000_000;
+
+/// What `export_energy_series` works the energies out from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "kind"
+)]
+pub(crate) enum EnergySource {
+    /// A copy of the live state, stepped `duration` sim seconds on at the
+    /// live dt, a row per step.
+    Run { duration: f64 },
+    /// A finished recording, a row per sample.
+    Recording { id: u64 },
+    /// A trajectory file, CSV or binary, a row per frame.
+    File { path: String },
+}
+
+/// An `EnergySource` with what it needs copied out of the state, so the
+/// work is done without it.
+#[derive(Clone, Debug)]
+pub(crate) enum EnergyInput {
+    Run {
+        pendulum: Pendulum,
+        sim_time: f64,
+        dt: f64,
+        steps: u64,
+    },
+    Recording(Arc<Recording>),
+    File(PathBuf),
+}
+
+impl EnergySource {
+    pub(crate) fn capture(self, data: &AppDataInner) -> Result<EnergyInput, CommandError> {
+        match self {
+            Self::Run { duration } => {
+                if !(duration.is_finite() && duration > 0.0) {
+                    return Err(CommandError::invalid(
+                        "source.duration",
+                        format!("must be positive and finite, got {duration}"),
+                    ));
+                }
+                let steps = (duration / data.dt).round().max(1.0);
+                if steps > MAX_ENERGY_ROWS as f64 {
+                    return Err(CommandError::invalid(
+                        "source.duration",
+                        format!(
+                            "of {duration} s takes {steps} steps of {} s, more than the limit of {MAX_ENERGY_ROWS}",
+                            data.dt
+                        ),
+                    ));
+                }
+                Ok(EnergyInput::Run {
+                    pendulum: data.pendulum.clone(),
+                    sim_time: data.sim_time,
+                    dt: data.dt,
+                    steps: steps as u64,
+                })
+            }
+            Self::Recording { id } => (data.recordings.iter())
+                .find(|(recording_id, _)| *recording_id == id)
+                .map(|(_, recording)| EnergyInput::Recording(recording.clone()))
+                .ok_or_else(|| CommandError::not_found("recording", id)),
+            Self::File { path } => Ok(EnergyInput::File(path.into())),
+        }
+    }
+}
+
+/// Each instant's energies, in joules, from the first to the last.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EnergySeries {
+    /// Whether there is joint friction, and so a dissipated column.
+    pub(crate) friction: bool,
+    /// Sim time, kinetic, potential, and what the friction has taken out
+    /// since the first row.
+    pub(crate) rows: Vec<[f64; 4]>,
+}
+
+impl EnergySeries {
+    /// The energies of `chains`, each with its sim time. What the friction
+    /// takes out between two of them is the trapezoid of its power.
+    pub(crate) fn of(chains: impl Iterator<Item = (Pendulum, f64)>, friction: bool) -> Self {
+        let mut rows: Vec<[f64; 4]> = Vec::new();
+        let mut last_power = 0.0;
+        for (pendulum, sim_time) in chains {
+            let power = pendulum.dissipated_power();
+            let dissipated = rows.last().map_or(0.0, |row| {
+                row[3] + 0.5 * (last_power + power) * (sim_time - row[0])
+            });
+            last_power = power;
+            let (kinetic, potential) = (pendulum.kinetic_energy(), pendulum.potential_energy());
+            rows.push([sim_time, kinetic, potential, dissipated]);
+        }
+        Self { friction, rows }
+    }
+
+    /// A recording's energies, worked out again from its angles, angular
+    /// velocities and rods and the configuration it keeps.
+    fn of_recording(recording: &Recording) -> Result<Self, CommandError> {
+        let configuration = recording.configuration().ok_or_else(|| {
+            CommandError::invalid(
+                "source",
+                "the recording doesn't keep its configuration, as files from before it was kept don't",
+            )
+        })?;
+        let damping = configuration.damping;
+        let chains = recording.samples().iter().map(|sample| {
+            let pendulum = Pendulum {
+                damping,
+                ..sample.pendulum()
+            };
+            (pendulum, recording.start() + sample.time)
+        });
+        Ok(Self::of(chains, damping > 0.0))
+    }
+
+    pub(crate) fn work_out(input: EnergyInput) -> Result<Self, CommandError> {
+        match input {
+            EnergyInput::Run {
+                mut pendulum,
+                sim_time,
+                dt,
+                steps,
+            } => {
+                let friction = pendulum.damping > 0.0;
+                let mut step = 0;
+                let chains = std::iter::from_fn(|| {
+                    if step > steps {
+                        return None;
+                    }
+                    if step > 0 {
+                        pendulum.step(dt);
+                    }
+                    step += 1;
+                    Some((pendulum.clone(), sim_time + (step - 1) as f64 * dt))
+                });
+                Ok(Self::of(chains, friction))
+            }
+            EnergyInput::Recording(recording) => Self::of_recording(&recording),
+            EnergyInput::File(path) => {
+                let bytes = std::fs::read(&path).map_err(|e| CommandError::io(&path, e))?;
+                Self::of_recording(&read_recording(&bytes)?)
+            }
+        }
+    }
+
+    /// A header row with the units, then a row per instant, total energy
+    /// after potential.
+    pub(crate) fn csv(&self) -> String {
+        let mut lines = vec![String::from("sim_time_s,kinetic_j,potential_j,total_j")];
+        if self.friction {
+            lines[0] += ",dissipated_joint_friction_j";
+        }
+        for &[sim_time, kinetic, potential, dissipated] in &self.rows {
+            let mut line = format!("{sim_time},{kinetic},{potential},{}", kinetic + potential);
+            if self.friction {
+                line += &format!(",{dissipated}");
+            }
+            lines.push(line);
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+pub(crate) fn export(path: &Path, input: EnergyInput) -> Result<FileReport, CommandError> {
+    let series = EnergySeries::work_out(input)?;
+    let csv = series.csv();
+    savefile::write_atomically(path, csv.as_bytes()).map_err(|e| CommandError::io(path, e))?;
+    Ok(FileReport {
+        path: path.display().to_string(),
+        rows: series.rows.len() as u64,
+        bytes: csv.len() as u64,
+        dropped_rows: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trajectory::{RecordingOptions, TrajectoryFile, TrajectoryRecording};
+
+    #[test]
+    fn energies_balance_against_what_friction_takes_out() {
+        let mut data = AppDataInner::default();
+        data.pendulum.damping = 0.5;
+        data.pendulum.bobs[0].mass = 2.0;
+        for bob in &mut data.pendulum.bobs {
+            (bob.theta, bob.omega) = (std::f64::consts::PI + 0.5, 1.0);
+        }
+        data.pendulum.update_coordinates();
+        let run = EnergySource::Run { duration: 2.0 };
+        let series = EnergySeries::work_out(run.capture(&data).unwrap()).unwrap();
+        assert!(series.friction);
+        assert_eq!(series.rows.len(), 1001);
+        let [_, k0, u0, _] = series.rows[0];
+        let [t, k, u, dissipated] = series.rows[1000];
+        assert!((t - 2.0).abs() < 1e-9);
+        assert!(dissipated > 0.0);
+        // what is lost is what the friction took out, to within the
+        // integrator's drift
+        let residual = (k + u + dissipated - (k0 + u0)).abs();
+        assert!(residual < 0.05 * dissipated, "{residual} of {dissipated}");
+        assert!(EnergySource::Run { duration: -1.0 }.capture(&data).is_err());
+
+        // a file of the same run gives the same energies back, masses and
+        // friction from the configuration it keeps
+        let path = std::env::temp_dir().join(format!("energy-{}.csv", std::process::id()));
+        let file = TrajectoryFile::create(&path, RecordingOptions::default()).unwrap();
+        let mut file = TrajectoryRecording::start(file, &data.pendulum, data.dt).unwrap();
+        let mut copy = data.pendulum.clone();
+        file.push(&copy, 0.0).unwrap();
+        for step in 1..=1000 {
+            copy.step(data.dt);
+            file.push(&copy, f64::from(step) * data.dt).unwrap();
+        }
+        file.finish().unwrap();
+        let from_file = EnergySource::File {
+            path: path.display().to_string(),
+        };
+        let report = export(
+            &path.with_extension("energy"),
+            from_file.capture(&data).unwrap(),
+        );
+        let report = report.unwrap();
+        let from_file = std::fs::read_to_string(&report.path).unwrap();
+        std::fs::remove_file(&report.path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = from_file.lines().collect();
+        assert_eq!(lines.len(), 1002);
+        assert!(lines[0].ends_with(",total_j,dissipated_joint_friction_j"));
+        let last: Vec<f64> = lines[1001].split(',').map(|v| v.parse().unwrap()).collect();
+        for (a, b) in last.iter().zip([t, k, u, k + u, dissipated]) {
+            assert!((a - b).abs() < 1e-9, "{last:?}");
+        }
+
+        // without its configuration a recording is refused
+        let mut bare = Recording::default();
+        bare.push(&data.pendulum, 0.0);
+        let bare = Recording::from_samples(0.0, bare.samples().to_vec(), None);
+        data.recordings.push((3, Arc::new(bare)));
+        let input = EnergySource::Recording { id: 3 }.capture(&data).unwrap();
+        assert!(EnergySeries::work_out(input).is_err());
+        assert!(EnergySource::Recording { id: 4 }.capture(&data).is_err());
+    }
+}
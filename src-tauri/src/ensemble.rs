@@ -0,0 +1,388 @@
+use pendulum_core::{Batch2, Coordinate, Integrator, Pendulum};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::parallel;
+
+/// Most copies an ensemble may have.
+pub(crate) const MAX_ENSEMBLE_SIZE: usize = 2_000;
+
+/// Ensembles at least this large are stepped in parallel; below it the
+/// thread hand-off costs more than it saves.
+const PARALLEL_THRESHOLD: usize = 64;
+
+/// What happens to the ensemble when the reference pendulum is edited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum EnsembleEditPolicy {
+    /// The copies no longer derive from the reference, so they go.
+    #[default]
+    Dissolve,
+    /// The copies are redrawn around the edited reference, with the same
+    /// size, scale and seed.
+    Propagate,
+}
+
+/// What the copies are stepped in. The reference is always stepped in
+/// double precision.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum EnsemblePrecision {
+    /// Each copy a `Pendulum` of its own, as exact as the reference.
+    #[default]
+    Double,
+    /// All the copies in one f32 `Batch2`, many times faster and plenty
+    /// for the cloud; only for chains of two bobs, neither locked.
+    Single,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Members {
+    Double(Vec<Pendulum>),
+    Single(Batch2),
+}
+
+/// Copies of the pendulum with perturbed angles, stepped alongside it to
+/// show how fast nearby trajectories fan out.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Ensemble {
+    members: Members,
+    /// Standard deviation of the angle perturbations, in rad.
+    scale: f64,
+    seed: u64,
+    on_edit: EnsembleEditPolicy,
+}
+
+/// The copies' tips, one array per coordinate, in member order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EnsembleState {
+    pub(crate) tip_x: Vec<f64>,
+    pub(crate) tip_y: Vec<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EnsembleInfo {
+    pub(crate) size: usize,
+    pub(crate) scale: f64,
+    /// Seed of the perturbations; passing it again recreates the ensemble.
+    pub(crate) seed: u64,
+    pub(crate) on_edit: EnsembleEditPolicy,
+    pub(crate) precision: EnsemblePrecision,
+}
+
+pub(crate) fn validate(size: usize, scale: f64) -> Result<(), CommandError> {
+    if !(1..=MAX_ENSEMBLE_SIZE).contains(&size) {
+        return Err(CommandError::invalid(
+            "size",
+            format!("must be between 1 and {MAX_ENSEMBLE_SIZE}, got {size}"),
+        ));
+    }
+    if !(scale.is_finite() && scale >= 0.0) {
+        return Err(CommandError::invalid(
+            "scale",
+            format!("must be non-negative and finite, got {scale}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if `reference` can't be copied in `precision`.
+pub(crate) fn validate_precision(
+    precision: EnsemblePrecision,
+    reference: &Pendulum,
+) -> Result<(), CommandError> {
+    if precision == EnsemblePrecision::Single && Batch2::new(reference).is_none() {
+        return Err(CommandError::invalid(
+            "precision",
+            format!(
+                "single precision needs a chain of two bobs, neither locked, got {} bobs",
+                reference.n()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+impl Ensemble {
+    /// `size` copies of `reference`, each joint angle offset by a draw from
+    /// N(0, scale²), stepped in `precision` where `validate_precision`
+    /// allows it and in double precision otherwise.
+    pub(crate) fn new(
+        reference: &Pendulum,
+        size: usize,
+        scale: f64,
+        seed: u64,
+        on_edit: EnsembleEditPolicy,
+        precision: EnsemblePrecision,
+    ) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let normal = Normal::new(0.0, scale).expect("scale is validated");
+        let members: Vec<Pendulum> = (0..size)
+            .map(|_| {
+                let mut member = reference.clone();
+                for bob in &mut member.bobs {
+                    bob.theta += normal.sample(&mut rng);
+                }
+                member.update_coordinates();
+                member
+            })
+            .collect();
+        let batch = match precision {
+            EnsemblePrecision::Single => Batch2::new(reference),
+            EnsemblePrecision::Double => None,
+        };
+        let members = match batch {
+            Some(mut batch) => {
+                for member in &members {
+                    batch.push(member);
+                }
+                Members::Single(batch)
+            }
+            None => Members::Double(members),
+        };
+        Self {
+            members,
+            scale,
+            seed,
+            on_edit,
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        match &self.members {
+            Members::Double(members) => members.len(),
+            Members::Single(batch) => batch.len(),
+        }
+    }
+
+    pub(crate) fn precision(&self) -> EnsemblePrecision {
+        match self.members {
+            Members::Double(_) => EnsemblePrecision::Double,
+            Members::Single(_) => EnsemblePrecision::Single,
+        }
+    }
+
+    pub(crate) fn info(&self) -> EnsembleInfo {
+        EnsembleInfo {
+            size: self.size(),
+            scale: self.scale,
+            seed: self.seed,
+            on_edit: self.on_edit,
+            precision: self.precision(),
+        }
+    }
+
+    /// The ensemble after `reference` was edited, per the edit policy. A
+    /// single precision one redrawn around a chain it can't batch falls
+    /// back to double precision.
+    pub(crate) fn follow(self, reference: &Pendulum) -> Option<Self> {
+        match self.on_edit {
+            EnsembleEditPolicy::Dissolve => None,
+            EnsembleEditPolicy::Propagate => Some(Self::new(
+                reference,
+                self.size(),
+                self.scale,
+                self.seed,
+                self.on_edit,
+                self.precision(),
+            )),
+        }
+    }
+
+    /// Hangs every copy from `pivot`, like the reference.
+    pub(crate) fn set_pivot(&mut self, pivot: Coordinate) {
+        match &mut self.members {
+            Members::Double(members) => {
+                for member in members {
+                    member.pivot = pivot;
+                    member.update_coordinates();
+                }
+            }
+            Members::Single(batch) => batch.set_pivot(pivot),
+        }
+    }
+
+    /// Steps every copy with `integrator`, like the reference.
+    pub(crate) fn set_integrator(&mut self, integrator: Integrator) {
+        match &mut self.members {
+            Members::Double(members) => {
+                for member in members {
+                    member.integrator = integrator;
+                }
+            }
+            Members::Single(batch) => batch.set_integrator(integrator),
+        }
+    }
+
+    pub(crate) fn step(&mut self, dt: f64) {
+        match &mut self.members {
+            Members::Double(members) if members.len() >= PARALLEL_THRESHOLD => {
+                parallel::for_each_mut(members, |m| {
+                    m.step(dt);
+                });
+            }
+            Members::Double(members) => {
+                for member in members {
+                    member.step(dt);
+                }
+            }
+            // a single pass over the arrays is quicker than handing it out
+            Members::Single(batch) => batch.step(dt),
+        }
+    }
+
+    pub(crate) fn state(&self) -> EnsembleState {
+        let tips: Vec<Coordinate> = match &self.members {
+            Members::Double(members) => members
+                .iter()
+                .filter_map(|m| m.bobs.last())
+                .map(|b| b.coordinate)
+                .collect(),
+            Members::Single(batch) => batch.tips().collect(),
+        };
+        EnsembleState {
+            tip_x: tips.iter().map(|c| c.x).collect(),
+            tip_y: tips.iter().map(|c| c.y).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tips_after(ensemble: &mut Ensemble, steps: usize) -> EnsembleState {
+        for _ in 0..steps {
+            ensemble.step(0.01);
+        }
+        ensemble.state()
+    }
+
+    fn doubles(ensemble: &Ensemble) -> &[Pendulum] {
+        match &ensemble.members {
+            Members::Double(members) => members,
+            Members::Single(_) => panic!("single precision"),
+        }
+    }
+
+    #[test]
+    fn same_seed_same_cloud_in_parallel_or_not() {
+        let reference = Pendulum::default();
+        let policy = EnsembleEditPolicy::Dissolve;
+        let mut small = Ensemble::new(&reference, 3, 1e-3, 7, policy, EnsemblePrecision::Double);
+        let mut large = Ensemble::new(
+            &reference,
+            PARALLEL_THRESHOLD * 2,
+            1e-3,
+            7,
+            policy,
+            EnsemblePrecision::Double,
+        );
+        let small = tips_after(&mut small, 100);
+        let large = tips_after(&mut large, 100);
+        // the first draws of a seed are the same however many follow
+        assert_eq!(small.tip_x[..], large.tip_x[..3]);
+        assert_eq!(small.tip_y[..], large.tip_y[..3]);
+
+        let other =
+            Ensemble::new(&reference, 3, 1e-3, 8, policy, EnsemblePrecision::Double).state();
+        assert_ne!(
+            other.tip_x,
+            Ensemble::new(&reference, 3, 1e-3, 7, policy, EnsemblePrecision::Double)
+                .state()
+                .tip_x
+        );
+    }
+
+    #[test]
+    fn perturbations_have_the_requested_scale() {
+        let reference = Pendulum::default();
+        let ensemble = Ensemble::new(
+            &reference,
+            1_000,
+            0.01,
+            1,
+            EnsembleEditPolicy::Dissolve,
+            EnsemblePrecision::Double,
+        );
+        let offsets: Vec<f64> = doubles(&ensemble)
+            .iter()
+            .flat_map(|m| m.bobs.iter().zip(&reference.bobs))
+            .map(|(bob, base)| bob.theta - base.theta)
+            .collect();
+        let n = offsets.len() as f64;
+        let mean = offsets.iter().sum::<f64>() / n;
+        let sd = (offsets.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n).sqrt();
+        assert!(mean.abs() < 1e-3);
+        assert!((sd - 0.01).abs() < 1e-3);
+    }
+
+    #[test]
+    fn edits_dissolve_or_propagate() {
+        let mut reference = Pendulum::default();
+        let dissolving = Ensemble::new(
+            &reference,
+            4,
+            0.1,
+            3,
+            EnsembleEditPolicy::Dissolve,
+            EnsemblePrecision::Double,
+        );
+        let propagating = Ensemble::new(
+            &reference,
+            4,
+            0.1,
+            3,
+            EnsembleEditPolicy::Propagate,
+            EnsemblePrecision::Double,
+        );
+        reference.bobs.pop();
+        assert_eq!(dissolving.follow(&reference), None);
+        let followed = propagating.follow(&reference).unwrap();
+        assert_eq!(followed.info().size, 4);
+        assert!(doubles(&followed).iter().all(|m| m.n() == 3));
+        assert!(validate(MAX_ENSEMBLE_SIZE + 1, 0.1).is_err());
+        assert!(validate(0, 0.1).is_err());
+        assert!(validate(10, -1.0).is_err());
+    }
+
+    #[test]
+    fn single_precision_tracks_double_for_two_bobs() {
+        let mut reference = Pendulum::default();
+        reference.bobs.truncate(2);
+        reference.pivot = Coordinate::new(0.5, -1.0);
+        let policy = EnsembleEditPolicy::Propagate;
+        let new = |precision| Ensemble::new(&reference, 20, 1e-3, 5, policy, precision);
+        let (mut single, mut double) = (
+            new(EnsemblePrecision::Single),
+            new(EnsemblePrecision::Double),
+        );
+        assert_eq!(single.info().precision, EnsemblePrecision::Single);
+        assert_eq!(single.size(), 20);
+        let (single_tips, double_tips) = (tips_after(&mut single, 50), tips_after(&mut double, 50));
+        for (a, b) in single_tips.tip_x.iter().zip(&double_tips.tip_x) {
+            assert!((a - b).abs() < 1e-3, "{a} {b}");
+        }
+        single.set_pivot(Coordinate::default());
+        assert!(single
+            .state()
+            .tip_x
+            .iter()
+            .zip(&single_tips.tip_x)
+            .all(|(a, b)| (b - a - 0.5).abs() < 1e-9));
+
+        // a chain that can't be batched is refused, or followed in double
+        assert!(validate_precision(EnsemblePrecision::Single, &reference).is_ok());
+        assert!(validate_precision(EnsemblePrecision::Single, &Pendulum::default()).is_err());
+        assert!(validate_precision(EnsemblePrecision::Double, &Pendulum::default()).is_ok());
+        reference.bobs.push(reference.bobs[1]);
+        let followed = single.follow(&reference).unwrap();
+        assert_eq!(followed.precision(), EnsemblePrecision::Double);
+        assert_eq!(doubles(&followed).len(), 20);
+    }
+}
@@ -0,0 +1,396 @@
+use pendulum_core::InvalidBob;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+/// What a command failed with. It reaches the frontend as an object with a
+/// camelCase `code` naming the variant, a `message` for people, and the
+/// variant's fields, so callers can branch on the code instead of the
+/// wording.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub(crate) enum CommandError {
+    /// An index past the end of the chain, or of whatever else it indexes.
+    #[error("Index {index} is out of bounds, there are {len}")]
+    IndexOutOfBounds { index: usize, len: usize },
+    /// An edit would make the chain longer than `set_max_bobs` allows.
+    #[error("The chain may have at most {max} bobs")]
+    TooManyBobs { max: usize },
+    /// `create_instance` with `MAX_INSTANCES` already running.
+    #[error("At most {max} instances may run at once")]
+    TooManyInstances { max: usize },
+    /// A bob cap more than the other instances leave of `MAX_TOTAL_BOBS`.
+    #[error(
+        "Only {available} of the {budget} bobs all instances share are left, {requested} asked for"
+    )]
+    BobBudgetExceeded {
+        requested: usize,
+        available: usize,
+        budget: usize,
+    },
+    /// Something looked up by id or name that isn't there.
+    #[error("No {what} {key}")]
+    NotFound { what: &'static str, key: String },
+    /// An argument outside what it may be; `name` says which, down to the
+    /// field for structured ones.
+    #[error("{name} {reason}")]
+    InvalidParameter { name: String, reason: String },
+    /// Something already running is in the way.
+    #[error("{reason}")]
+    SimulationBusy { reason: String },
+    /// Only possible while paused.
+    #[error("Only possible while the simulation is paused")]
+    NotPaused,
+    /// Nothing to act on in the current state.
+    #[error("{reason}")]
+    Unavailable { reason: String },
+    /// A background run ended without a result.
+    #[error("{reason}")]
+    Cancelled { reason: String },
+    /// A sim time before the oldest state kept for going back to. `change`
+    /// names the structural change the kept states start after, if that is
+    /// what is in the way rather than the buffer's length.
+    #[error(
+        "Sim time {requested} is before the oldest state kept, at {oldest}{}",
+        .change.map(|c| format!(", just after the {c}")).unwrap_or_default()
+    )]
+    OutOfHistory {
+        requested: f64,
+        oldest: f64,
+        change: Option<&'static str>,
+    },
+    /// Written by a newer version of the app than this one. `what` names
+    /// the kind of file or string, like "Save file".
+    #[error("{what} version {version} is newer than this app reads, {newest}; update it")]
+    UnsupportedVersion {
+        what: &'static str,
+        version: u32,
+        newest: u32,
+    },
+    /// Left out of this build.
+    #[cfg_attr(all(feature = "osc", feature = "websocket"), allow(dead_code))]
+    #[error("Built without {feature} support; enable the `{flag}` feature")]
+    Unsupported {
+        feature: &'static str,
+        flag: &'static str,
+    },
+    /// Reading or writing a file failed.
+    #[error("{path}: {message}")]
+    Io { path: String, message: String },
+    /// Something outside the simulation failed, like a socket or a task.
+    #[error("{message}")]
+    Internal { message: String },
+}
+
+impl CommandError {
+    pub(crate) fn invalid(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        let (name, reason) = (name.into(), reason.into());
+        tracing::info!("Rejected {name}: {reason}");
+        Self::InvalidParameter { name, reason }
+    }
+
+    pub(crate) fn not_found(what: &'static str, key: impl ToString) -> Self {
+        Self::NotFound {
+            what,
+            key: key.to_string(),
+        }
+    }
+
+    pub(crate) fn busy(reason: impl Into<String>) -> Self {
+        Self::SimulationBusy {
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn unavailable(reason: impl Into<String>) -> Self {
+        Self::Unavailable {
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn internal(error: impl ToString) -> Self {
+        Self::Internal {
+            message: error.to_string(),
+        }
+    }
+
+    pub(crate) fn io(path: impl AsRef<std::path::Path>, error: impl ToString) -> Self {
+        Self::Io {
+            path: path.as_ref().display().to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    /// The same error with `parent` in front of an invalid parameter's name,
+    /// like `bobs[2]` before `mass`.
+    pub(crate) fn within(self, parent: &str) -> Self {
+        match self {
+            Self::InvalidParameter { name, reason } => Self::InvalidParameter {
+                name: format!("{parent}.{name}"),
+                reason,
+            },
+            other => other,
+        }
+    }
+
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Self::IndexOutOfBounds { .. } => "indexOutOfBounds",
+            Self::TooManyBobs { .. } => "tooManyBobs",
+            Self::TooManyInstances { .. } => "tooManyInstances",
+            Self::BobBudgetExceeded { .. } => "bobBudgetExceeded",
+            Self::NotFound { .. } => "notFound",
+            Self::InvalidParameter { .. } => "invalidParameter",
+            Self::SimulationBusy { .. } => "simulationBusy",
+            Self::NotPaused => "notPaused",
+            Self::Unavailable { .. } => "unavailable",
+            Self::Cancelled { .. } => "cancelled",
+            Self::OutOfHistory { .. } => "outOfHistory",
+            Self::UnsupportedVersion { .. } => "unsupportedVersion",
+            Self::Unsupported { .. } => "unsupported",
+            Self::Io { .. } => "io",
+            Self::Internal { .. } => "internal",
+        }
+    }
+}
+
+impl Serialize for CommandError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::IndexOutOfBounds { index, len } => {
+                map.serialize_entry("index", index)?;
+                map.serialize_entry("len", len)?;
+            }
+            Self::TooManyBobs { max } | Self::TooManyInstances { max } => {
+                map.serialize_entry("max", max)?
+            }
+            Self::BobBudgetExceeded {
+                requested,
+                available,
+                budget,
+            } => {
+                map.serialize_entry("requested", requested)?;
+                map.serialize_entry("available", available)?;
+                map.serialize_entry("budget", budget)?;
+            }
+            Self::NotFound { what, key } => {
+                map.serialize_entry("what", what)?;
+                map.serialize_entry("key", key)?;
+            }
+            Self::InvalidParameter { name, reason } => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("reason", reason)?;
+            }
+            Self::SimulationBusy { reason }
+            | Self::Unavailable { reason }
+            | Self::Cancelled { reason } => map.serialize_entry("reason", reason)?,
+            Self::OutOfHistory {
+                requested,
+                oldest,
+                change,
+            } => {
+                map.serialize_entry("requested", requested)?;
+                map.serialize_entry("oldest", oldest)?;
+                map.serialize_entry("change", change)?;
+            }
+            Self::UnsupportedVersion {
+                what,
+                version,
+                newest,
+            } => {
+                map.serialize_entry("what", what)?;
+                map.serialize_entry("version", version)?;
+                map.serialize_entry("newest", newest)?;
+            }
+            Self::Unsupported { feature, flag } => {
+                map.serialize_entry("feature", feature)?;
+                map.serialize_entry("flag", flag)?;
+            }
+            Self::Io { path, .. } => map.serialize_entry("path", path)?,
+            Self::NotPaused | Self::Internal { .. } => {}
+        }
+        map.end()
+    }
+}
+
+impl From<InvalidBob> for CommandError {
+    fn from(error: InvalidBob) -> Self {
+        let name = match error {
+            InvalidBob::NotFinite { field, .. } | InvalidBob::BelowMinimum { field, .. } => field,
+        };
+        let reason = match error {
+            InvalidBob::NotFinite { value, .. } => format!("must be finite, got {value}"),
+            InvalidBob::BelowMinimum { value, min, .. } => {
+                format!("must be at least {min}, got {value}")
+            }
+        };
+        Self::invalid(name, reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Every variant's wire shape, as the frontend reads it.
+    #[test]
+    fn every_variant_serializes_with_its_code_message_and_fields() {
+        let cases = [
+            (
+                CommandError::IndexOutOfBounds { index: 5, len: 4 },
+                json!({
+                    "code": "indexOutOfBounds",
+                    "message": "Index 5 is out of bounds, there are 4",
+                    "index": 5,
+                    "len": 4,
+                }),
+            ),
+            (
+                CommandError::TooManyBobs { max: 64 },
+                json!({
+                    "code": "tooManyBobs",
+                    "message": "The chain may have at most 64 bobs",
+                    "max": 64,
+                }),
+            ),
+            (
+                CommandError::TooManyInstances { max: 8 },
+                json!({
+                    "code": "tooManyInstances",
+                    "message": "At most 8 instances may run at once",
+                    "max": 8,
+                }),
+            ),
+            (
+                CommandError::BobBudgetExceeded {
+                    requested: 64,
+                    available: 20,
+                    budget: 1024,
+                },
+                json!({
+                    "code": "bobBudgetExceeded",
+                    "message": "Only 20 of the 1024 bobs all instances share are left, 64 asked for",
+                    "requested": 64,
+                    "available": 20,
+                    "budget": 1024,
+                }),
+            ),
+            (
+                CommandError::not_found("bob with id", 7),
+                json!({
+                    "code": "notFound",
+                    "message": "No bob with id 7",
+                    "what": "bob with id",
+                    "key": "7",
+                }),
+            ),
+            (
+                CommandError::from(InvalidBob::BelowMinimum {
+                    field: "mass",
+                    value: -1.0,
+                    min: 1e-6,
+                })
+                .within("bobs[2]"),
+                json!({
+                    "code": "invalidParameter",
+                    "message": "bobs[2].mass must be at least 0.000001, got -1",
+                    "name": "bobs[2].mass",
+                    "reason": "must be at least 0.000001, got -1",
+                }),
+            ),
+            (
+                CommandError::busy("A warm-up is in progress"),
+                json!({
+                    "code": "simulationBusy",
+                    "message": "A warm-up is in progress",
+                    "reason": "A warm-up is in progress",
+                }),
+            ),
+            (
+                CommandError::NotPaused,
+                json!({
+                    "code": "notPaused",
+                    "message": "Only possible while the simulation is paused",
+                }),
+            ),
+            (
+                CommandError::unavailable("Nothing to undo"),
+                json!({
+                    "code": "unavailable",
+                    "message": "Nothing to undo",
+                    "reason": "Nothing to undo",
+                }),
+            ),
+            (
+                CommandError::Cancelled {
+                    reason: "Prediction was superseded by a newer one".into(),
+                },
+                json!({
+                    "code": "cancelled",
+                    "message": "Prediction was superseded by a newer one",
+                    "reason": "Prediction was superseded by a newer one",
+                }),
+            ),
+            (
+                CommandError::OutOfHistory {
+                    requested: 1.5,
+                    oldest: 2.0,
+                    change: Some("removal of a bob"),
+                },
+                json!({
+                    "code": "outOfHistory",
+                    "message": "Sim time 1.5 is before the oldest state kept, at 2, just after \
+                                the removal of a bob",
+                    "requested": 1.5,
+                    "oldest": 2.0,
+                    "change": "removal of a bob",
+                }),
+            ),
+            (
+                CommandError::UnsupportedVersion {
+                    what: "Save file",
+                    version: 300,
+                    newest: 1,
+                },
+                json!({
+                    "code": "unsupportedVersion",
+                    "message": "Save file version 300 is newer than this app reads, 1; update it",
+                    "what": "Save file",
+                    "version": 300,
+                    "newest": 1,
+                }),
+            ),
+            (
+                CommandError::Unsupported {
+                    feature: "OSC",
+                    flag: "osc",
+                },
+                json!({
+                    "code": "unsupported",
+                    "message": "Built without OSC support; enable the `osc` feature",
+                    "feature": "OSC",
+                    "flag": "osc",
+                }),
+            ),
+            (
+                CommandError::io("/tmp/run.csv", "No space left on device"),
+                json!({
+                    "code": "io",
+                    "message": "/tmp/run.csv: No space left on device",
+                    "path": "/tmp/run.csv",
+                }),
+            ),
+            (
+                CommandError::internal("address in use"),
+                json!({
+                    "code": "internal",
+                    "message": "address in use",
+                }),
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(serde_json::to_value(&error).unwrap(), expected, "{error:?}");
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use pendulum_core::Pendulum;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+use crate::error::CommandError;
+
+/// Name of the event emitted when a bob goes over the top.
+pub(crate) const FLIP_EVENT: &str = "pendulum://flip";
+/// Name of the event emitted when an alert rule trips.
+pub(crate) const ALERT_EVENT: &str = "pendulum://alert";
+/// Name of the event emitted when a tick runs out of time for its substeps.
+pub(crate) const OVERLOADED_EVENT: &str = "pendulum://overloaded";
+/// Name of the event emitted after getting past a panic that poisoned the
+/// state lock.
+pub(crate) const RECOVERED_EVENT: &str = "pendulum://recovered";
+/// Name of the event emitted when a link to the app couldn't be opened.
+pub(crate) const LINK_REJECTED_EVENT: &str = "pendulum://link-rejected";
+/// Name of the event emitted when the autosave couldn't be written or read.
+pub(crate) const AUTOSAVE_FAILED_EVENT: &str = "pendulum://autosave-failed";
+/// Name of the event emitted when a trajectory recording stops on its own.
+pub(crate) const RECORDING_FAILED_EVENT: &str = "pendulum://recording-failed";
+
+/// Hysteresis of a rule created without one, as a fraction of its threshold.
+pub(crate) const DEFAULT_HYSTERESIS_FRACTION: f64 = 0.05;
+
+/// Which way a bob's angle was moving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Direction {
+    Increasing,
+    Decreasing,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FlipEvent {
+    pub(crate) index: usize,
+    pub(crate) direction: Direction,
+    /// Sim time of the crossing, interpolated within the substep.
+    pub(crate) sim_time: f64,
+    /// The bob's linear speed at the end of the substep.
+    pub(crate) speed: f64,
+}
+
+/// Sent when a tick has to give up on some of its substeps to stay within
+/// its wall time budget, once per stretch of such ticks. The simulation then
+/// runs slower than wall time, and the time given up shows as deficit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OverloadEvent {
+    /// Substeps the tick took.
+    pub(crate) substeps: u32,
+    /// Substeps it was due.
+    pub(crate) due: u32,
+    pub(crate) sim_time: f64,
+}
+
+/// Sent once the state lock is usable again after a panic while it was held.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RecoveryEvent {
+    /// Whether the chain was left invalid and replaced by the default one;
+    /// otherwise the simulation carries on where the panic left it.
+    pub(crate) reset: bool,
+    /// Sim time once recovered, 0 after a reset.
+    pub(crate) sim_time: f64,
+}
+
+/// Sent when a link the app was opened with, or got while running, was
+/// refused. The chain stays as it was: the default one at launch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LinkRejectedEvent {
+    pub(crate) url: String,
+    pub(crate) reason: String,
+}
+
+/// Sent when a trajectory recording had to stop before `stop_recording`: the
+/// file couldn't be written, or the chain changed its number of bobs. The
+/// rows handed to the writer before that are kept.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RecordingFailedEvent {
+    pub(crate) path: String,
+    pub(crate) rows: u64,
+    pub(crate) reason: String,
+}
+
+/// What the autosave was doing when it failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AutosaveStage {
+    /// Carrying on from it at launch; the default chain runs instead.
+    Restore,
+    /// Writing it; the previous one is still there.
+    Save,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AutosaveFailedEvent {
+    pub(crate) path: String,
+    pub(crate) stage: AutosaveStage,
+    pub(crate) reason: String,
+}
+
+/// Something the physics task tells the frontend about, outside the frame
+/// stream.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub(crate) enum SimEvent {
+    Flip(FlipEvent),
+    Alert(AlertEvent),
+    Recovered(RecoveryEvent),
+    Overloaded(OverloadEvent),
+    LinkRejected(LinkRejectedEvent),
+    RecordingFailed(RecordingFailedEvent),
+    AutosaveFailed(AutosaveFailedEvent),
+}
+
+impl SimEvent {
+    /// The event name it is emitted under.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SimEvent::Flip(_) => FLIP_EVENT,
+            SimEvent::Alert(_) => ALERT_EVENT,
+            SimEvent::Recovered(_) => RECOVERED_EVENT,
+            SimEvent::Overloaded(_) => OVERLOADED_EVENT,
+            SimEvent::LinkRejected(_) => LINK_REJECTED_EVENT,
+            SimEvent::RecordingFailed(_) => RECORDING_FAILED_EVENT,
+            SimEvent::AutosaveFailed(_) => AUTOSAVE_FAILED_EVENT,
+        }
+    }
+}
+
+/// Where the physics task emits events. Implemented for the Tauri app handle
+/// and for test doubles.
+pub(crate) trait EventSink: Send + Sync {
+    fn emit(&self, event: SimEvent);
+}
+
+/// Which crossing of `level + 2πk` the angle is past.
+fn crossings(theta: f64, level: f64) -> f64 {
+    ((theta - level) / (2.0 * PI)).floor()
+}
+
+/// Watches the unwrapped joint angles substep by substep for bobs going over
+/// the top (θ = 0 mod 2π, since the chain hangs at π).
+///
+/// A bob hovering at the top could cross it back and forth many times
+/// without turning over, so after a flip the bob has to swing through the
+/// bottom before its next flip counts.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FlipDetector {
+    last: Vec<f64>,
+    /// Whether each bob's next top crossing counts as a flip.
+    armed: Vec<bool>,
+    skip_next: bool,
+}
+
+impl FlipDetector {
+    /// Takes the next observation as the new baseline without looking for
+    /// flips, for when the angles were set rather than integrated.
+    pub(crate) fn suppress_next(&mut self) {
+        self.skip_next = true;
+    }
+
+    /// Follows the angles being negated, so the turns counted so far and a
+    /// flip under way carry over, mirrored.
+    pub(crate) fn mirror(&mut self) {
+        for theta in &mut self.last {
+            *theta = -*theta;
+        }
+    }
+
+    /// Compares the state after a substep of `dt` ending at `sim_time` with
+    /// the previous one.
+    pub(crate) fn observe(
+        &mut self,
+        pendulum: &Pendulum,
+        sim_time: f64,
+        dt: f64,
+    ) -> Vec<FlipEvent> {
+        let thetas: Vec<f64> = pendulum.bobs.iter().map(|b| b.theta).collect();
+        if self.skip_next || self.last.len() != thetas.len() {
+            self.skip_next = false;
+            self.armed = vec![true; thetas.len()];
+            self.last = thetas;
+            return Vec::new();
+        }
+        let mut flips = Vec::new();
+        let mut velocities = None;
+        for (i, (&before, &after)) in self.last.iter().zip(&thetas).enumerate() {
+            if crossings(before, PI) != crossings(after, PI) {
+                self.armed[i] = true;
+            }
+            let (top_before, top_after) = (crossings(before, 0.0), crossings(after, 0.0));
+            if top_before == top_after || !self.armed[i] {
+                continue;
+            }
+            self.armed[i] = false;
+            let direction = if after > before {
+                Direction::Increasing
+            } else {
+                Direction::Decreasing
+            };
+            let top = 2.0 * PI * top_before.max(top_after);
+            let fraction = (top - before) / (after - before);
+            let velocities = velocities.get_or_insert_with(|| pendulum.velocities());
+            let v = velocities[i];
+            flips.push(FlipEvent {
+                index: i,
+                direction,
+                sim_time: sim_time - dt * (1.0 - fraction),
+                speed: v.x.hypot(v.y),
+            });
+        }
+        self.last = thetas;
+        flips
+    }
+}
+
+/// What an alert rule watches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AlertQuantity {
+    /// Kinetic plus potential energy.
+    TotalEnergy,
+    /// Linear speed of the last bob.
+    TipSpeed,
+    /// The largest |ω| of any joint.
+    MaxAngularSpeed,
+}
+
+impl AlertQuantity {
+    fn measure(self, pendulum: &Pendulum) -> f64 {
+        match self {
+            AlertQuantity::TotalEnergy => pendulum.total_energy(),
+            AlertQuantity::TipSpeed => pendulum.velocities().last().map_or(0.0, |v| v.x.hypot(v.y)),
+            AlertQuantity::MaxAngularSpeed => pendulum
+                .bobs
+                .iter()
+                .fold(0.0, |acc, b| f64::max(acc, b.omega.abs())),
+        }
+    }
+}
+
+/// Fires once when its quantity rises above `threshold`, then stays quiet
+/// until the quantity has dropped below `threshold - hysteresis`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AlertRule {
+    pub(crate) id: u64,
+    pub(crate) quantity: AlertQuantity,
+    pub(crate) threshold: f64,
+    pub(crate) hysteresis: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AlertEvent {
+    pub(crate) rule_id: u64,
+    pub(crate) quantity: AlertQuantity,
+    pub(crate) value: f64,
+    pub(crate) sim_time: f64,
+}
+
+/// The alert rules, checked after every substep.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Alerts {
+    /// Each rule with whether it has fired and not yet rearmed.
+    rules: Vec<(AlertRule, bool)>,
+    next_id: u64,
+}
+
+impl Alerts {
+    pub(crate) fn add(
+        &mut self,
+        quantity: AlertQuantity,
+        threshold: f64,
+        hysteresis: Option<f64>,
+    ) -> Result<u64, CommandError> {
+        if !threshold.is_finite() {
+            return Err(CommandError::invalid(
+                "threshold",
+                format!("must be finite, got {threshold}"),
+            ));
+        }
+        let hysteresis = hysteresis.unwrap_or(DEFAULT_HYSTERESIS_FRACTION * threshold.abs());
+        if !(hysteresis.is_finite() && hysteresis >= 0.0) {
+            return Err(CommandError::invalid(
+                "hysteresis",
+                format!("must be non-negative and finite, got {hysteresis}"),
+            ));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let rule = AlertRule {
+            id,
+            quantity,
+            threshold,
+            hysteresis,
+        };
+        self.rules.push((rule, false));
+        Ok(id)
+    }
+
+    /// Removes a rule, returning whether it existed.
+    pub(crate) fn remove(&mut self, id: u64) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|(rule, _)| rule.id != id);
+        self.rules.len() != before
+    }
+
+    pub(crate) fn list(&self) -> Vec<AlertRule> {
+        self.rules.iter().map(|(rule, _)| rule.clone()).collect()
+    }
+
+    /// Checks every rule against the state at `sim_time`. Each quantity is
+    /// measured at most once, and only if some rule watches it.
+    pub(crate) fn evaluate(&mut self, pendulum: &Pendulum, sim_time: f64) -> Vec<AlertEvent> {
+        let mut measured: Vec<(AlertQuantity, f64)> = Vec::new();
+        let mut events = Vec::new();
+        for (rule, fired) in &mut self.rules {
+            let value = match measured.iter().find(|(q, _)| *q == rule.quantity) {
+                Some(&(_, value)) => value,
+                None => {
+                    let value = rule.quantity.measure(pendulum);
+                    measured.push((rule.quantity, value));
+                    value
+                }
+            };
+            if *fired {
+                *fired = value >= rule.threshold - rule.hysteresis;
+            } else if value > rule.threshold {
+                *fired = true;
+                events.push(AlertEvent {
+                    rule_id: rule.id,
+                    quantity: rule.quantity,
+                    value,
+                    sim_time,
+                });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pendulum_core::Bob;
+
+    fn track(detector: &mut FlipDetector, thetas: &[f64]) -> Vec<FlipEvent> {
+        let mut pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, 0.0, 0.0)]);
+        let mut events = Vec::new();
+        for (i, &theta) in thetas.iter().enumerate() {
+            pendulum.bobs[0].theta = theta;
+            events.extend(detector.observe(&pendulum, i as f64, 1.0));
+        }
+        events
+    }
+
+    #[test]
+    fn spinning_bob_flips_once_per_turn() {
+        // fast enough at the bottom to keep going round
+        let mut pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, PI, 12.0)]);
+        let mut detector = FlipDetector::default();
+        let dt = 1e-3;
+        let mut flips = Vec::new();
+        for step in 1..=10_000 {
+            pendulum.step(dt);
+            let events = detector.observe(&pendulum, step as f64 * dt, dt);
+            assert!(events.len() <= 1);
+            flips.extend(events);
+        }
+        // started at π, so every multiple of 2π passed is one turn
+        let turns = (pendulum.bobs[0].theta / (2.0 * PI)).floor() as usize;
+        assert!(turns > 5);
+        assert_eq!(flips.len(), turns);
+        assert!(flips.iter().all(|f| f.direction == Direction::Increasing));
+        assert!(flips.windows(2).all(|w| w[1].sim_time > w[0].sim_time));
+    }
+
+    #[test]
+    fn hovering_at_the_top_counts_once() {
+        let mut detector = FlipDetector::default();
+        let events = track(&mut detector, &[-0.3, -0.01, 0.01, -0.01, 0.02, -0.02, 0.5]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].direction, Direction::Increasing);
+        // crossing at 0 between the observations at t = 1 and t = 2
+        assert!((events[0].sim_time - 1.5).abs() < 1e-9);
+
+        // through the bottom and over again counts again
+        let events = track(&mut detector, &[0.5, 3.5, 6.0, 6.5]);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn edits_are_not_flips() {
+        let mut detector = FlipDetector::default();
+        track(&mut detector, &[PI, PI + 0.1]);
+        detector.suppress_next();
+        assert!(track(&mut detector, &[2.0 * PI + 0.1, 2.0 * PI + 0.2]).is_empty());
+        // the same jump without the edit would have been a flip
+        let mut detector = FlipDetector::default();
+        assert_eq!(
+            track(&mut detector, &[PI, PI + 0.1, 2.0 * PI + 0.1]).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn alerts_fire_once_per_excursion() {
+        let mut alerts = Alerts::default();
+        let id = alerts
+            .add(AlertQuantity::MaxAngularSpeed, 2.0, Some(0.5))
+            .unwrap();
+        let mut pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, PI, 0.0)]);
+        let mut fired = Vec::new();
+        // hovering around the threshold, then dropping below the hysteresis
+        // band and rising again
+        for (t, omega) in [1.0, 2.1, 1.9, 2.2, -2.05, 1.6, 1.4, 2.5]
+            .iter()
+            .enumerate()
+        {
+            pendulum.bobs[0].omega = *omega;
+            fired.extend(alerts.evaluate(&pendulum, t as f64));
+        }
+        assert_eq!(fired.len(), 2);
+        assert_eq!((fired[0].rule_id, fired[0].sim_time), (id, 1.0));
+        assert_eq!((fired[1].value, fired[1].sim_time), (2.5, 7.0));
+
+        assert_eq!(alerts.list().len(), 1);
+        assert!(alerts.remove(id));
+        assert!(!alerts.remove(id));
+        assert!(alerts.evaluate(&pendulum, 8.0).is_empty());
+        assert!(alerts.add(AlertQuantity::TipSpeed, f64::NAN, None).is_err());
+        assert!(alerts
+            .add(AlertQuantity::TipSpeed, 1.0, Some(-1.0))
+            .is_err());
+    }
+
+    #[test]
+    fn alert_quantities_measure_the_state() {
+        let pendulum = Pendulum::new(vec![
+            Bob::new(1.0, 1.0, PI, 1.0),
+            Bob::new(2.0, 1.0, PI, -3.0),
+        ]);
+        let tip = AlertQuantity::TipSpeed.measure(&pendulum);
+        // both rods horizontal in velocity at the bottom: 1·1 + 2·(-3)
+        assert!((tip - 5.0).abs() < 1e-9);
+        assert_eq!(AlertQuantity::MaxAngularSpeed.measure(&pendulum), 3.0);
+        assert_eq!(
+            AlertQuantity::TotalEnergy.measure(&pendulum),
+            pendulum.total_energy()
+        );
+    }
+}
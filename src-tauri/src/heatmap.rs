@@ -0,0 +1,306 @@
+use pendulum_core::{Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::CommandError;
+use crate::savefile;
+
+/// Cells along each side of the occupancy grid.
+pub(crate) const HEATMAP_CELLS: usize = 256;
+/// Side of an exported image, in pixels, to begin with.
+pub(crate) const DEFAULT_HEATMAP_RESOLUTION: u32 = 1024;
+/// Smallest and largest side an exported image may have, in pixels.
+pub(crate) const MIN_HEATMAP_RESOLUTION: u32 = 16;
+pub(crate) const MAX_HEATMAP_RESOLUTION: u32 = 8192;
+/// Radius of the pivot's dot in the overlay, in pixels.
+const PIVOT_RADIUS: f64 = 3.0;
+
+/// How often the tip has been in each part of the square it can reach: a
+/// count per cell of `HEATMAP_CELLS` by `HEATMAP_CELLS`, a sample per
+/// substep. The square is centered on the pivot with sides of twice the
+/// chain's length, and starts over empty whenever either changes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Occupancy {
+    pivot: Coordinate,
+    /// The chain's length.
+    reach: f64,
+    /// Row by row from the top, empty until the first sample.
+    counts: Vec<u32>,
+    samples: u64,
+}
+
+impl Occupancy {
+    pub(crate) fn observe(&mut self, pendulum: &Pendulum) {
+        let Some(tip) = pendulum.bobs.last() else {
+            return;
+        };
+        let reach: f64 = pendulum.bobs.iter().map(|b| b.length_rod).sum();
+        if self.counts.is_empty() || reach != self.reach || pendulum.pivot != self.pivot {
+            self.clear();
+            (self.pivot, self.reach) = (pendulum.pivot, reach);
+            self.counts = vec![0; HEATMAP_CELLS * HEATMAP_CELLS];
+        }
+        let cell = |offset: f64| {
+            let fraction = (offset + reach) / (2.0 * reach);
+            ((fraction * HEATMAP_CELLS as f64) as usize).min(HEATMAP_CELLS - 1)
+        };
+        let (dx, dy) = (
+            tip.coordinate.x - self.pivot.x,
+            tip.coordinate.y - self.pivot.y,
+        );
+        if !(dx.is_finite() && dy.is_finite() && reach > 0.0) {
+            return;
+        }
+        let (column, row) = (cell(dx), HEATMAP_CELLS - 1 - cell(dy));
+        let count = &mut self.counts[row * HEATMAP_CELLS + column];
+        *count = count.saturating_add(1);
+        self.samples += 1;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counts.clear();
+        self.samples = 0;
+    }
+}
+
+/// How the densities are colored, from the empty cells to the densest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Colormap {
+    /// Black through purple and orange to pale yellow.
+    #[default]
+    Inferno,
+    /// Dark blue through teal to yellow.
+    Viridis,
+    /// Black to white.
+    Grayscale,
+}
+
+impl Colormap {
+    /// Evenly spaced stops, walked in straight lines.
+    fn stops(self) -> &'static [[u8; 3]] {
+        match self {
+            Self::Inferno => &[
+                [0, 0, 4],
+                [87, 16, 110],
+                [188, 55, 84],
+                [249, 142, 9],
+                [252, 255, 164],
+            ],
+            Self::Viridis => &[
+                [68, 1, 84],
+                [59, 82, 139],
+                [33, 145, 140],
+                [94, 201, 98],
+                [253, 231, 37],
+            ],
+            Self::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+        }
+    }
+
+    /// The color at `t`, from 0 to 1.
+    fn color(self, t: f64) -> [u8; 3] {
+        let stops = self.stops();
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let i = (position as usize).min(stops.len() - 2);
+        let s = position - i as f64;
+        let (a, b) = (stops[i], stops[i + 1]);
+        [0, 1, 2].map(|c| (f64::from(a[c]) + s * (f64::from(b[c]) - f64::from(a[c]))).round() as u8)
+    }
+
+    /// What the overlay is drawn in, to stand out from every density.
+    fn overlay(self) -> [u8; 3] {
+        match self {
+            Self::Inferno | Self::Viridis => [255, 255, 255],
+            Self::Grayscale => [230, 40, 40],
+        }
+    }
+}
+
+/// What `export_heatmap_png` returns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HeatmapReport {
+    pub(crate) path: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// Size of the file, in bytes.
+    pub(crate) bytes: u64,
+    /// Samples in the grid.
+    pub(crate) samples: u64,
+    /// Samples in the densiest cell.
+    pub(crate) max_count: u32,
+    /// The densiest cell's share of the samples per square meter, so the
+    /// tip spent that fraction of the time there per m².
+    pub(crate) max_density: f64,
+}
+
+pub(crate) fn validate_resolution(resolution: u32) -> Result<u32, CommandError> {
+    if (MIN_HEATMAP_RESOLUTION..=MAX_HEATMAP_RESOLUTION).contains(&resolution) {
+        Ok(resolution)
+    } else {
+        Err(CommandError::invalid(
+            "resolution",
+            format!(
+                "must be between {MIN_HEATMAP_RESOLUTION} and {MAX_HEATMAP_RESOLUTION} pixels, got {resolution}"
+            ),
+        ))
+    }
+}
+
+/// Fails if a file can't be made at `path`, leaving what is there alone, so
+/// a render isn't done for nothing.
+pub(crate) fn check_writable(path: &Path) -> Result<(), CommandError> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| CommandError::io(path, e))?;
+    }
+    let existed = path.exists();
+    let file = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path);
+    file.map_err(|e| CommandError::io(path, e))?;
+    if !existed {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+/// The grid as `resolution` by `resolution` RGB pixels, row by row from the
+/// top, each the color of the log of its cell's count over that of the
+/// densiest, with the pivot's dot and the circle the tip can reach on top
+/// if `overlay`.
+fn render(grid: &Occupancy, colormap: Colormap, resolution: u32, overlay: bool) -> Vec<u8> {
+    let max = grid.counts.iter().copied().max().unwrap_or(0);
+    let scale = f64::from(max).ln_1p();
+    let side = resolution as usize;
+    let mut pixels = Vec::with_capacity(side * side * 3);
+    let cells_per_pixel = HEATMAP_CELLS as f64 / f64::from(resolution);
+    // the circle is the reach, half the side, and a pixel wide
+    let center = f64::from(resolution) / 2.0;
+    for y in 0..side {
+        for x in 0..side {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let from_pivot = (px - center).hypot(py - center);
+            let on_overlay =
+                overlay && (from_pivot <= PIVOT_RADIUS || (from_pivot - center).abs() <= 0.5);
+            let color = if on_overlay {
+                colormap.overlay()
+            } else {
+                let cell = |p: f64| ((p * cells_per_pixel) as usize).min(HEATMAP_CELLS - 1);
+                let count = (grid.counts.get(cell(py) * HEATMAP_CELLS + cell(px))).copied();
+                let count = f64::from(count.unwrap_or(0));
+                let t = if scale > 0.0 {
+                    count.ln_1p() / scale
+                } else {
+                    0.0
+                };
+                colormap.color(t)
+            };
+            pixels.extend(color);
+        }
+    }
+    pixels
+}
+
+/// Renders `grid` and writes it to `path` as a PNG; see `render`.
+pub(crate) fn export(
+    path: &Path,
+    grid: &Occupancy,
+    colormap: Colormap,
+    resolution: u32,
+    overlay: bool,
+) -> Result<HeatmapReport, CommandError> {
+    let pixels = render(grid, colormap, resolution, overlay);
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, resolution, resolution);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(CommandError::internal)?;
+    writer
+        .write_image_data(&pixels)
+        .map_err(CommandError::internal)?;
+    writer.finish().map_err(CommandError::internal)?;
+    savefile::write_atomically(path, &bytes).map_err(|e| CommandError::io(path, e))?;
+    let max_count = grid.counts.iter().copied().max().unwrap_or(0);
+    let cell_side = 2.0 * grid.reach / HEATMAP_CELLS as f64;
+    let max_density = if grid.samples == 0 {
+        0.0
+    } else {
+        f64::from(max_count) / grid.samples as f64 / (cell_side * cell_side)
+    };
+    Ok(HeatmapReport {
+        path: path.display().to_string(),
+        width: resolution,
+        height: resolution,
+        bytes: bytes.len() as u64,
+        samples: grid.samples,
+        max_count,
+        max_density,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppDataInner;
+
+    #[test]
+    fn the_density_is_rendered_in_log_scale_with_the_overlay() {
+        let mut data = AppDataInner::default();
+        for _ in 0..5_000 {
+            let dt = data.dt;
+            data.substep(dt, &mut Vec::new());
+        }
+        let grid = data.occupancy.clone();
+        assert_eq!(grid.samples, 5_000);
+        assert_eq!(
+            grid.counts.iter().map(|&c| u64::from(c)).sum::<u64>(),
+            5_000
+        );
+
+        // the tip is never outside the circle it can reach
+        let half = HEATMAP_CELLS as f64 / 2.0;
+        for (i, _) in grid.counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+            let (x, y) = (
+                (i % HEATMAP_CELLS) as f64 + 0.5,
+                (i / HEATMAP_CELLS) as f64 + 0.5,
+            );
+            assert!((x - half).hypot(y - half) <= half + 1.0, "{i}");
+        }
+        // a pixel a cell, so the densest is white
+        let side = HEATMAP_CELLS as u32;
+        let pixels = render(&grid, Colormap::Grayscale, side, false);
+        assert_eq!(pixels.len(), HEATMAP_CELLS * HEATMAP_CELLS * 3);
+        assert_eq!(&pixels[..3], &[0, 0, 0]);
+        assert_eq!(pixels.iter().copied().max(), Some(255));
+        let overlaid = render(&grid, Colormap::Grayscale, 64, true);
+        assert_eq!(&overlaid[(32 * 64 + 32) * 3..][..3], &[230, 40, 40]);
+        assert_eq!(&overlaid[(32 * 64) * 3..][..3], &[230, 40, 40]);
+        assert_eq!(Colormap::Inferno.color(1.0), [252, 255, 164]);
+        assert_eq!(Colormap::Viridis.color(0.125), [64, 42, 112]);
+
+        let path = std::env::temp_dir().join(format!("heatmap-{}.png", std::process::id()));
+        check_writable(&path).unwrap();
+        assert!(!path.exists());
+        let report = export(&path, &grid, Colormap::Inferno, 100, true).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((report.width, report.height), (100, 100));
+        assert_eq!(
+            (report.bytes as usize, &bytes[1..4]),
+            (bytes.len(), &b"PNG"[..])
+        );
+        assert!(report.max_count > 0 && report.max_density > 0.0);
+        assert!(validate_resolution(8).is_err());
+        let cell = 2.0 * grid.reach / HEATMAP_CELLS as f64;
+        let share = f64::from(report.max_count) / 5_000.0;
+        assert!((report.max_density * cell * cell - share).abs() < 1e-12);
+
+        // a new layout starts over
+        data.set_bob_count(data.pendulum.n() + 1, None).unwrap();
+        let dt = data.dt;
+        data.substep(dt, &mut Vec::new());
+        assert_eq!(data.occupancy.samples, 1);
+    }
+}
@@ -0,0 +1,249 @@
+use pendulum_core::{Bob, Coordinate};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::Duration};
+use tokio::time::Instant;
+
+/// Most edits that can be undone; older ones are forgotten.
+pub(crate) const MAX_HISTORY: usize = 100;
+/// Edits of the same fields this close together undo as one, so a slider
+/// drag is a single step back.
+pub(crate) const MERGE_WINDOW: Duration = Duration::from_millis(500);
+
+/// The kinds of edits that can be undone, named after their commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Edit {
+    AddBob,
+    InsertBob,
+    RemoveBob,
+    ModifyBob,
+    ModifyBobs,
+    ModifyAllBobs,
+    SetState,
+    SetBobCount,
+    LoadPreset,
+    Randomize,
+    ZeroVelocities,
+    Mirror,
+    Scale,
+    SetPose,
+    SetPivot,
+    LockJoint,
+    ImportState,
+    ImportConfig,
+    LoadSimulation,
+}
+
+impl Edit {
+    fn merges(self) -> bool {
+        matches!(
+            self,
+            Self::ModifyBob | Self::ModifyBobs | Self::ModifyAllBobs | Self::SetPivot
+        )
+    }
+}
+
+/// What edits change and undoing them puts back: the bobs, ids included,
+/// the pivot, and the world settings a preset brings.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Configuration {
+    pub(crate) bobs: Vec<Bob>,
+    pub(crate) pivot: Coordinate,
+    pub(crate) dt: f64,
+    pub(crate) time_scale: f64,
+}
+
+impl Configuration {
+    /// The (bob id, field) pairs that differ, if the two have the same bobs
+    /// in the same order. A moved pivot isn't among them, so dragging it
+    /// merges like dragging a bob.
+    fn changed_fields(&self, other: &Self) -> Option<Vec<(u64, &'static str)>> {
+        if !self
+            .bobs
+            .iter()
+            .map(|b| b.id)
+            .eq(other.bobs.iter().map(|b| b.id))
+        {
+            return None;
+        }
+        let mut changed = Vec::new();
+        for (a, b) in self.bobs.iter().zip(&other.bobs) {
+            for (field, x, y) in [
+                ("length_rod", a.length_rod, b.length_rod),
+                ("mass", a.mass, b.mass),
+                ("theta", a.theta, b.theta),
+                ("omega", a.omega, b.omega),
+            ] {
+                if x != y {
+                    changed.push((a.id, field));
+                }
+            }
+        }
+        Some(changed)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    edit: Edit,
+    /// The configuration to go back to.
+    before: Configuration,
+    /// What the edit changed, for merging; `None` once it can't merge.
+    changed: Option<Vec<(u64, &'static str)>>,
+    /// When the edit, or the last one merged into it, happened.
+    last_at: Instant,
+}
+
+/// Undo and redo stacks of configurations.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct History {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    /// Set while an edit runs, so the edits it is made of aren't recorded
+    /// on their own.
+    pub(crate) in_edit: bool,
+}
+
+/// The edits that `undo` and `redo` would take back or do again, the next
+/// one first.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistorySummary {
+    pub(crate) undo: Vec<Edit>,
+    pub(crate) redo: Vec<Edit>,
+}
+
+impl History {
+    /// Notes that `edit` at `now` led from `before` to `after`. Edits that
+    /// changed nothing are not kept, and a modification of the same fields
+    /// as the previous one within `MERGE_WINDOW` joins it. Anything that
+    /// could be redone is forgotten.
+    pub(crate) fn record(
+        &mut self,
+        edit: Edit,
+        before: Configuration,
+        after: &Configuration,
+        now: Instant,
+    ) {
+        if before == *after {
+            return;
+        }
+        self.redo.clear();
+        let changed = before.changed_fields(after);
+        if let Some(last) = self.undo.back_mut() {
+            if edit.merges()
+                && last.edit.merges()
+                && changed.is_some()
+                && last.changed == changed
+                && now.saturating_duration_since(last.last_at) <= MERGE_WINDOW
+            {
+                last.last_at = now;
+                return;
+            }
+        }
+        self.undo.push_back(Entry {
+            edit,
+            before,
+            changed: if edit.merges() { changed } else { None },
+            last_at: now,
+        });
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Takes back the last edit: returns the configuration before it, and
+    /// keeps `current` for `redo`.
+    pub(crate) fn undo(&mut self, current: Configuration) -> Option<(Edit, Configuration)> {
+        let entry = self.undo.pop_back()?;
+        self.redo.push(Entry {
+            before: current,
+            changed: None,
+            ..entry.clone()
+        });
+        Some((entry.edit, entry.before))
+    }
+
+    /// Does the last undone edit again, keeping `current` for `undo`.
+    pub(crate) fn redo(&mut self, current: Configuration) -> Option<(Edit, Configuration)> {
+        let entry = self.redo.pop()?;
+        self.undo.push_back(Entry {
+            before: current,
+            ..entry.clone()
+        });
+        Some((entry.edit, entry.before))
+    }
+
+    pub(crate) fn summary(&self) -> HistorySummary {
+        HistorySummary {
+            undo: self.undo.iter().rev().map(|e| e.edit).collect(),
+            redo: self.redo.iter().rev().map(|e| e.edit).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuration(thetas: &[f64]) -> Configuration {
+        let bobs = thetas
+            .iter()
+            .enumerate()
+            .map(|(id, &theta)| Bob {
+                id: id as u64,
+                ..Bob::new(1.0, 1.0, theta, 0.0)
+            })
+            .collect();
+        Configuration {
+            bobs,
+            pivot: Coordinate::default(),
+            dt: 0.01,
+            time_scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn drags_merge_and_the_history_stays_bounded() {
+        let mut history = History::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        // dragging bob 0's angle, 100 ms apart, then bob 1's, then a pause
+        // and bob 1's again
+        let steps = [
+            (0.0, 0.0, 0),
+            (0.1, 0.0, 100),
+            (0.2, 0.0, 200),
+            (0.2, 0.1, 300),
+            (0.2, 0.2, 400),
+            (0.2, 0.3, 1_500),
+        ];
+        let mut current = configuration(&[0.0, 0.0]);
+        for (a, b, ms) in steps.into_iter().skip(1) {
+            let next = configuration(&[a, b]);
+            history.record(Edit::ModifyBob, current, &next, at(ms));
+            current = next;
+        }
+        history.record(Edit::ModifyBob, current.clone(), &current, at(1_600));
+        assert_eq!(history.summary().undo, [Edit::ModifyBob; 3]);
+
+        let (_, before) = history.undo(current.clone()).unwrap();
+        assert_eq!(before, configuration(&[0.2, 0.2]));
+        let (_, before) = history.undo(before).unwrap();
+        assert_eq!(before, configuration(&[0.2, 0.0]));
+        let (_, after) = history.redo(before).unwrap();
+        assert_eq!(after, configuration(&[0.2, 0.2]));
+        assert_eq!(history.summary().redo, [Edit::ModifyBob]);
+
+        // a new edit drops what could be redone
+        history.record(Edit::RemoveBob, after, &configuration(&[0.2]), at(2_000));
+        assert!(history.summary().redo.is_empty());
+
+        for i in 0..2 * MAX_HISTORY {
+            let before = configuration(&[i as f64]);
+            let after = configuration(&[i as f64 + 0.5]);
+            history.record(Edit::SetState, before, &after, at(3_000));
+        }
+        assert_eq!(history.summary().undo.len(), MAX_HISTORY);
+    }
+}
@@ -0,0 +1,665 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::comparison::{self, Comparison, ComparisonInfo};
+use crate::error::CommandError;
+use crate::presets;
+use crate::share;
+use crate::state::AppDataInner;
+use crate::stream;
+use crate::wave::{self, Wave, WaveInfo};
+
+/// Id of the instance there always is, which commands act on when they are
+/// given no `instance`.
+pub(crate) const PRIMARY_INSTANCE: u64 = 0;
+/// Most instances at once, the primary one included.
+pub(crate) const MAX_INSTANCES: usize = 32;
+/// Most bobs all the instances' `set_max_bobs` caps add up to, so that
+/// stepping every chain still fits in a tick.
+pub(crate) const MAX_TOTAL_BOBS: usize = 1024;
+
+/// What `create_instance` starts a simulation from. With neither a preset
+/// nor a state string it is the default chain.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InstanceConfig {
+    /// A built-in preset, by name.
+    #[serde(default)]
+    pub(crate) preset: Option<String>,
+    /// A setup from `export_state_string`.
+    #[serde(default)]
+    pub(crate) state: Option<String>,
+    /// Its `set_max_bobs` cap, `DEFAULT_MAX_BOBS` if left out.
+    #[serde(default)]
+    pub(crate) max_bobs: Option<usize>,
+}
+
+/// An instance as `list_instances` describes it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InstanceInfo {
+    pub(crate) id: u64,
+    pub(crate) primary: bool,
+    pub(crate) bob_count: usize,
+    pub(crate) max_bobs: usize,
+    pub(crate) sim_time: f64,
+    pub(crate) paused: bool,
+    pub(crate) subscribers: usize,
+}
+
+/// A command for the physics task, run on the instances between two ticks.
+pub(crate) type Job = Box<dyn FnOnce(&mut World) + Send>;
+
+/// Where an instance lives.
+enum Slot {
+    /// Stepped by its own clock.
+    Leads(Box<AppDataInner>),
+    /// A comparison clone or wave member, kept in the `Comparison` or `Wave`
+    /// of the instance with this id, which steps it.
+    Follows(u64),
+}
+
+/// The simulations side by side, each a whole `AppDataInner` with its own
+/// chain, settings, statistics and subscribers. The physics task owns them
+/// and is the only one to touch them, so nothing is locked: commands reach
+/// them as jobs sent through `Instances`, run between two ticks.
+pub(crate) struct World {
+    slots: BTreeMap<u64, Slot>,
+    next_id: u64,
+}
+
+/// The follower of `leader` with `id`, if it has one.
+fn follower(leader: &mut AppDataInner, id: u64) -> Option<&mut AppDataInner> {
+    if let Some(comparison) = (leader.comparison.as_mut()).filter(|c| c.instance == id) {
+        return Some(&mut comparison.data);
+    }
+    let members = &mut leader.wave.as_mut()?.members;
+    (members.iter_mut())
+        .find(|(member, _)| *member == id)
+        .map(|(_, data)| data)
+}
+
+impl World {
+    pub(crate) fn new(primary: AppDataInner) -> Self {
+        Self {
+            slots: BTreeMap::from([(PRIMARY_INSTANCE, Slot::Leads(Box::new(primary)))]),
+            next_id: PRIMARY_INSTANCE + 1,
+        }
+    }
+
+    /// The instance with `id`, the primary one for `None`.
+    pub(crate) fn get(&mut self, id: Option<u64>) -> Result<&mut AppDataInner, CommandError> {
+        let id = id.unwrap_or(PRIMARY_INSTANCE);
+        let not_found = || CommandError::not_found("instance", id);
+        let leader = match self.slots.get(&id) {
+            Some(Slot::Leads(_)) => id,
+            Some(&Slot::Follows(leader)) => leader,
+            None => return Err(not_found()),
+        };
+        let Some(Slot::Leads(data)) = self.slots.get_mut(&leader) else {
+            return Err(not_found());
+        };
+        if leader == id {
+            Ok(data)
+        } else {
+            follower(data, id).ok_or_else(not_found)
+        }
+    }
+
+    pub(crate) fn primary(&mut self) -> &mut AppDataInner {
+        self.get(None)
+            .expect("the primary instance is never destroyed")
+    }
+
+    /// Every instance's id, in the order they were created.
+    pub(crate) fn ids(&self) -> Vec<u64> {
+        self.slots.keys().copied().collect()
+    }
+
+    /// The bob budget left when the instance `except` gives its cap up.
+    fn budget_left(&mut self, except: Option<u64>) -> usize {
+        let reserved: usize = (self.ids().into_iter())
+            .filter(|&id| Some(id) != except)
+            .filter_map(|id| self.get(Some(id)).ok().map(|data| data.max_bobs))
+            .sum();
+        MAX_TOTAL_BOBS.saturating_sub(reserved)
+    }
+
+    fn check_budget(available: usize, requested: usize) -> Result<(), CommandError> {
+        if requested > available {
+            return Err(CommandError::BobBudgetExceeded {
+                requested,
+                available,
+                budget: MAX_TOTAL_BOBS,
+            });
+        }
+        Ok(())
+    }
+
+    /// Starts another simulation from `config` and returns its id. Its cap
+    /// counts against `MAX_TOTAL_BOBS` from the start, however few bobs it
+    /// has yet.
+    pub(crate) fn create(&mut self, config: &InstanceConfig) -> Result<u64, CommandError> {
+        let setup = match (&config.preset, &config.state) {
+            (Some(_), Some(_)) => {
+                return Err(CommandError::invalid(
+                    "config",
+                    "takes a preset or a state string, not both",
+                ))
+            }
+            (Some(name), None) => Some(presets::find(name)?.clone()),
+            (None, Some(state)) => Some(share::decode(state).map_err(|e| e.within("config"))?),
+            (None, None) => None,
+        };
+        let mut data = AppDataInner::default();
+        if let Some(max) = config.max_bobs {
+            data.set_max_bobs(max).map_err(|e| e.within("config"))?;
+        }
+        if let Some(setup) = &setup {
+            data.apply_preset(setup)?;
+        }
+        let available = self.budget_left(None);
+        let id = self.reserve(data.max_bobs, available)?;
+        self.slots.insert(id, Slot::Leads(Box::new(data)));
+        Ok(id)
+    }
+
+    /// The id of an instance of `max_bobs` about to be added, within the
+    /// limits, `available` being what is left of the bob budget.
+    fn reserve(&mut self, max_bobs: usize, available: usize) -> Result<u64, CommandError> {
+        if self.slots.len() >= MAX_INSTANCES {
+            return Err(CommandError::TooManyInstances { max: MAX_INSTANCES });
+        }
+        Self::check_budget(available, max_bobs)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// Clones instance `id` into a new one with `damping` in its joints and
+    /// steps the two in lockstep from here on; see `Comparison`. Edits of
+    /// the original are passed on to the clone when `mirror_edits`.
+    pub(crate) fn start_comparison(
+        &mut self,
+        id: Option<u64>,
+        damping: f64,
+        mirror_edits: bool,
+    ) -> Result<ComparisonInfo, CommandError> {
+        comparison::validate_damping(damping)?;
+        let leader = id.unwrap_or(PRIMARY_INSTANCE);
+        let data = self.get(id)?;
+        if data.lockstep {
+            return Err(CommandError::invalid(
+                "instance",
+                "is stepped by another instance already",
+            ));
+        }
+        if data.comparison.is_some() {
+            return Err(CommandError::busy(
+                "A comparison is running already; stop it first",
+            ));
+        }
+        let clone = Comparison::clone_of(data, damping);
+        let available = self.budget_left(None);
+        let instance = self.reserve(clone.max_bobs, available)?;
+        self.slots.insert(instance, Slot::Follows(leader));
+        let comparison = Comparison {
+            instance,
+            data: Box::new(clone),
+            damping,
+            mirror_edits,
+        };
+        let info = comparison.info();
+        self.get(id)?.comparison = Some(comparison);
+        Ok(info)
+    }
+
+    /// Starts a pendulum wave: `n` single pendulums, one instance each,
+    /// released together `amplitude` from hanging, the i-th swinging
+    /// `base_cycles + i` times in `period`; see `wave::lengths`. The first
+    /// steps the rest in lockstep, so pausing it pauses the wave, and its
+    /// frames carry every tip; destroying it ends the wave. Nothing is made
+    /// unless all of them fit the limits.
+    pub(crate) fn setup_pendulum_wave(
+        &mut self,
+        n: usize,
+        period: f64,
+        base_cycles: u32,
+        amplitude: f64,
+    ) -> Result<WaveInfo, CommandError> {
+        wave::validate(n, period, base_cycles, amplitude)?;
+        let lengths = wave::lengths(n, period, base_cycles, amplitude);
+        let mut members = (lengths.iter())
+            .map(|&length| wave::member(length, amplitude))
+            .collect::<Result<Vec<_>, _>>()?;
+        for member in &mut members[1..] {
+            member.lockstep = true;
+        }
+        if self.slots.len() + n > MAX_INSTANCES {
+            return Err(CommandError::TooManyInstances { max: MAX_INSTANCES });
+        }
+        let mut available = self.budget_left(None);
+        Self::check_budget(available, n)?;
+        let mut created = Vec::with_capacity(n);
+        for member in members {
+            let max_bobs = member.max_bobs;
+            created.push((self.reserve(max_bobs, available)?, member));
+            available -= max_bobs;
+        }
+        let (leader, mut leader_data) = created.remove(0);
+        let instances = std::iter::once(leader)
+            .chain(created.iter().map(|(id, _)| *id))
+            .collect();
+        for (id, _) in &created {
+            self.slots.insert(*id, Slot::Follows(leader));
+        }
+        leader_data.wave = Some(Wave {
+            leader,
+            members: created,
+        });
+        self.slots
+            .insert(leader, Slot::Leads(Box::new(leader_data)));
+        Ok(WaveInfo {
+            instances,
+            lengths,
+            period,
+            base_cycles,
+            amplitude,
+        })
+    }
+
+    /// Ends instance `id`'s comparison and destroys the clone, returning
+    /// whether there was one.
+    pub(crate) fn stop_comparison(&mut self, id: Option<u64>) -> Result<bool, CommandError> {
+        let Some(comparison) = self.get(id)?.comparison.take() else {
+            return Ok(false);
+        };
+        self.slots.remove(&comparison.instance);
+        Ok(true)
+    }
+
+    /// Stops and forgets an instance, and its comparison's clone and the
+    /// rest of its wave if it has them. Its subscriptions end as their
+    /// queues close; the primary instance stays.
+    pub(crate) fn destroy(&mut self, id: u64) -> Result<(), CommandError> {
+        if id == PRIMARY_INSTANCE {
+            return Err(CommandError::invalid(
+                "id",
+                "is the primary instance, which can't be destroyed",
+            ));
+        }
+        let slot = self.slots.remove(&id);
+        let data = match slot.ok_or_else(|| CommandError::not_found("instance", id))? {
+            Slot::Leads(data) => *data,
+            Slot::Follows(leader) => {
+                // a clone going on its own ends the comparison it was part
+                // of, while a wave just goes on without the member
+                let leader = self.get(Some(leader))?;
+                match leader.comparison.take_if(|c| c.instance == id) {
+                    Some(comparison) => *comparison.data,
+                    None => {
+                        let members = &mut (leader.wave.as_mut())
+                            .ok_or_else(|| CommandError::not_found("instance", id))?
+                            .members;
+                        let index = (members.iter().position(|(member, _)| *member == id))
+                            .ok_or_else(|| CommandError::not_found("instance", id))?;
+                        members.remove(index).1
+                    }
+                }
+            }
+        };
+        let followers = (data.comparison.iter().map(|c| c.instance)).chain(
+            data.wave
+                .iter()
+                .flat_map(|w| w.members.iter().map(|(id, _)| *id)),
+        );
+        for follower in followers {
+            self.slots.remove(&follower);
+        }
+        Ok(())
+    }
+
+    /// `AppDataInner::set_max_bobs` for one instance, within what the
+    /// others leave of the bob budget.
+    pub(crate) fn set_max_bobs(
+        &mut self,
+        id: Option<u64>,
+        max: usize,
+    ) -> Result<Option<String>, CommandError> {
+        let id = id.unwrap_or(PRIMARY_INSTANCE);
+        self.get(Some(id))?;
+        Self::check_budget(self.budget_left(Some(id)), max)?;
+        self.get(Some(id))?.set_max_bobs(max)
+    }
+
+    pub(crate) fn list(&mut self) -> Vec<InstanceInfo> {
+        (self.ids().into_iter())
+            .filter_map(|id| {
+                let data = self.get(Some(id)).ok()?;
+                Some(InstanceInfo {
+                    id,
+                    primary: id == PRIMARY_INSTANCE,
+                    bob_count: data.pendulum.n(),
+                    max_bobs: data.max_bobs,
+                    sim_time: data.sim_time,
+                    paused: data.pause_reason().is_some(),
+                    subscribers: data.subscribers.len(),
+                })
+            })
+            .collect()
+    }
+
+    /// Puts every instance right after a job on several of them panicked;
+    /// see `AppDataInner::recover`.
+    fn recover(&mut self) {
+        for id in self.ids() {
+            if let Ok(data) = self.get(Some(id)) {
+                data.recover();
+            }
+        }
+    }
+}
+
+/// What `physics_loop` runs: the instances, and the jobs sent to them.
+pub(crate) struct Physics {
+    pub(crate) world: World,
+    pub(crate) jobs: mpsc::UnboundedReceiver<Job>,
+}
+
+/// The way to the instances for everyone but the physics task. Each call
+/// sends a job there and waits until it has run, between two ticks, so a
+/// burst of commands delays no frame by more than its own jobs take.
+#[derive(Clone)]
+pub(crate) struct Instances {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+fn stopped() -> CommandError {
+    CommandError::unavailable("The physics task has stopped")
+}
+
+fn panicked() -> CommandError {
+    CommandError::internal("The command failed unexpectedly")
+}
+
+/// `job` as a job on the instance with `id`. One that panics is answered
+/// with an error, that instance put right first.
+fn on_instance<T>(
+    id: Option<u64>,
+    job: impl FnOnce(&mut AppDataInner) -> Result<T, CommandError>,
+) -> impl FnOnce(&mut World) -> Result<T, CommandError> {
+    move |world| {
+        let data = world.get(id)?;
+        panic::catch_unwind(AssertUnwindSafe(|| job(&mut *data))).unwrap_or_else(|_| {
+            data.recover();
+            Err(panicked())
+        })
+    }
+}
+
+impl Instances {
+    /// A handle to `primary` and whatever instances come after it, and the
+    /// physics task's side of it, for `physics_loop`. Until that runs the
+    /// world can be set up directly through `Physics::world`.
+    pub(crate) fn new(primary: AppDataInner) -> (Self, Physics) {
+        let (jobs, queue) = mpsc::unbounded_channel();
+        let physics = Physics {
+            world: World::new(primary),
+            jobs: queue,
+        };
+        (Self { jobs }, physics)
+    }
+
+    /// Sends `job` and returns where its result will come. A job that
+    /// panics is answered with an error, every instance put right first.
+    fn send<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut World) -> Result<T, CommandError> + Send + 'static,
+    ) -> Result<oneshot::Receiver<Result<T, CommandError>>, CommandError> {
+        let (reply, result) = oneshot::channel();
+        let job: Job = Box::new(move |world| {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| job(&mut *world)));
+            let _ = reply.send(outcome.unwrap_or_else(|_| {
+                world.recover();
+                Err(panicked())
+            }));
+        });
+        self.jobs.send(job).map_err(|_| stopped())?;
+        Ok(result)
+    }
+
+    /// Runs `job` on every instance at once, for what spans several.
+    pub(crate) async fn world<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut World) -> Result<T, CommandError> + Send + 'static,
+    ) -> Result<T, CommandError> {
+        self.send(job)?.await.map_err(|_| stopped())?
+    }
+
+    /// `world` from sync code. On a runtime's thread, as Tauri's main
+    /// thread is under `#[tokio::main]`, the wait is done on a thread of its
+    /// own, since the runtime won't let its threads block; the physics task
+    /// must then run on another of the runtime's threads, or another runtime.
+    pub(crate) fn world_blocking<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut World) -> Result<T, CommandError> + Send + 'static,
+    ) -> Result<T, CommandError> {
+        let result = self.send(job)?;
+        let received = if tokio::runtime::Handle::try_current().is_ok() {
+            std::thread::scope(|scope| scope.spawn(|| result.blocking_recv()).join())
+                .map_err(|_| panicked())?
+        } else {
+            result.blocking_recv()
+        };
+        received.map_err(|_| stopped())?
+    }
+
+    /// Runs `job` on the instance with `id`, the primary one for `None`.
+    pub(crate) async fn call<T: Send + 'static>(
+        &self,
+        id: Option<u64>,
+        job: impl FnOnce(&mut AppDataInner) -> Result<T, CommandError> + Send + 'static,
+    ) -> Result<T, CommandError> {
+        self.world(on_instance(id, job)).await
+    }
+
+    /// `call` from a thread that may block, never from async code.
+    pub(crate) fn call_blocking<T: Send + 'static>(
+        &self,
+        id: Option<u64>,
+        job: impl FnOnce(&mut AppDataInner) -> Result<T, CommandError> + Send + 'static,
+    ) -> Result<T, CommandError> {
+        self.world_blocking(on_instance(id, job))
+    }
+
+    /// `call` with a frame of what `edit` left pushed to every subscriber
+    /// at once; see `stream::edit_and_push`.
+    pub(crate) async fn edit<T: Send + 'static>(
+        &self,
+        id: Option<u64>,
+        edit: impl FnOnce(&mut AppDataInner) -> Result<T, CommandError> + Send + 'static,
+    ) -> Result<T, CommandError> {
+        self.call(id, move |data| stream::edit_and_push(data, edit))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventSink, SimEvent};
+    use crate::state::DEFAULT_DT;
+    use crate::stream::physics_loop;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit(&self, _: SimEvent) {}
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn instances_step_apart_within_their_limits() {
+        let (instances, mut physics) = Instances::new(AppDataInner::default());
+        let world = &mut physics.world;
+        let preset = presets::list()[1].name.clone();
+        let config = InstanceConfig {
+            preset: Some(preset),
+            max_bobs: Some(8),
+            ..InstanceConfig::default()
+        };
+        let second = world.create(&config).unwrap();
+        assert_ne!(second, PRIMARY_INSTANCE);
+        assert!(world.get(Some(PRIMARY_INSTANCE)).is_ok());
+        assert!(matches!(
+            world.get(Some(99)),
+            Err(CommandError::NotFound { .. })
+        ));
+
+        // settings are the instance's own, and the physics task steps both
+        world.get(Some(second)).unwrap().time_scale = 2.0;
+        for id in world.ids() {
+            world.get(Some(id)).unwrap().pause_without_subscribers = false;
+        }
+        let task = tokio::spawn(async move { physics_loop(physics, &NoEvents).await });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let list = instances.world(|world| Ok(world.list())).await.unwrap();
+        let times: Vec<f64> = list.iter().map(|i| i.sim_time).collect();
+        assert!(
+            (times[0] - 1.0).abs() < 0.1 && (times[1] - 2.0).abs() < 0.1,
+            "{times:?}"
+        );
+        let dt = instances.call(None, |data| Ok(data.dt)).await;
+        assert_eq!(dt, Ok(DEFAULT_DT));
+        assert_eq!((list[0].primary, list[1].primary), (true, false));
+        assert_eq!(list[1].max_bobs, 8);
+
+        // the limits, as typed errors
+        let greedy = instances
+            .world(|world| world.set_max_bobs(None, MAX_TOTAL_BOBS))
+            .await;
+        assert!(matches!(
+            greedy,
+            Err(CommandError::BobBudgetExceeded { available, .. })
+                if available == MAX_TOTAL_BOBS - 8
+        ));
+        let capped = instances.world(move |world| world.set_max_bobs(Some(second), 4));
+        assert_eq!(capped.await, Ok(None));
+        let created = instances.world(move |world| {
+            let mut created = vec![second];
+            let small = InstanceConfig {
+                max_bobs: Some(4),
+                ..InstanceConfig::default()
+            };
+            while created.len() + 1 < MAX_INSTANCES {
+                created.push(world.create(&small)?);
+            }
+            assert_eq!(
+                world.create(&small),
+                Err(CommandError::TooManyInstances { max: MAX_INSTANCES })
+            );
+            world.destroy(created.pop().unwrap())?;
+            Ok(created)
+        });
+        assert_eq!(created.await.unwrap().len(), MAX_INSTANCES - 2);
+        let greedy = InstanceConfig {
+            max_bobs: Some(MAX_TOTAL_BOBS),
+            ..InstanceConfig::default()
+        };
+        assert!(matches!(
+            instances.world(move |world| world.create(&greedy)).await,
+            Err(CommandError::BobBudgetExceeded { .. })
+        ));
+        let both = InstanceConfig {
+            preset: Some("x".into()),
+            state: Some("y".into()),
+            ..InstanceConfig::default()
+        };
+        assert!(instances
+            .world(move |world| world.create(&both))
+            .await
+            .is_err());
+
+        let destroy = |id| instances.world(move |world| world.destroy(id));
+        assert!(destroy(PRIMARY_INSTANCE).await.is_err());
+        destroy(second).await.unwrap();
+        assert!(destroy(second).await.is_err());
+        assert!(instances.call(Some(second), |_| Ok(())).await.is_err());
+        let list = instances.world(|world| Ok(world.list())).await.unwrap();
+        assert_eq!(list.len(), MAX_INSTANCES - 2);
+        task.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn commands_from_many_tasks_run_whole_and_in_order() {
+        let (instances, mut physics) = Instances::new(AppDataInner::default());
+        physics.world.primary().pause_without_subscribers = false;
+        let task = tokio::spawn(async move { physics_loop(physics, &NoEvents).await });
+
+        // every edit is a read and a write no other command gets between,
+        // and each task's run in the order it sent them, with the physics
+        // ticking on meanwhile
+        let mass = instances.call(None, |data| Ok(data.pendulum.bobs[0].mass));
+        let mass = mass.await.unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let senders: Vec<_> = (0..8)
+            .map(|sender| {
+                let (instances, order) = (instances.clone(), order.clone());
+                tokio::spawn(async move {
+                    for i in 0..100 {
+                        let order = order.clone();
+                        let edit = move |data: &mut AppDataInner| {
+                            order.lock().unwrap().push((sender, i));
+                            data.pendulum.bobs[0].mass += 1.0;
+                            Ok(())
+                        };
+                        instances.edit(None, edit).await.unwrap();
+                        tokio::time::sleep(Duration::from_millis(3)).await;
+                    }
+                })
+            })
+            .collect();
+        for sender in senders {
+            sender.await.unwrap();
+        }
+        let read = |data: &mut AppDataInner| {
+            Ok((data.pendulum.bobs[0].mass, data.sim_time, data.heartbeat))
+        };
+        let (edited, sim_time, heartbeat) = instances.call(None, read).await.unwrap();
+        assert_eq!(edited, mass + 800.0);
+        let order = order.lock().unwrap().clone();
+        for sender in 0..8 {
+            let sent: Vec<_> = (order.iter())
+                .filter(|(s, _)| *s == sender)
+                .map(|&(_, i)| i)
+                .collect();
+            assert_eq!(sent, (0..100).collect::<Vec<_>>());
+        }
+        // 300 ms of edits, which held no tick up
+        assert!((sim_time - 0.3).abs() < 0.02, "{sim_time}");
+        assert!((37..=39).contains(&heartbeat), "{heartbeat}");
+
+        // errors come back as they are, and a job that panics leaves the
+        // instances and the task in working order
+        assert!(matches!(
+            instances.call(Some(5), |_| Ok(())).await,
+            Err(CommandError::NotFound { .. })
+        ));
+        let panicked: Result<(), _> = instances
+            .world(|world| {
+                world.primary().history.in_edit = true;
+                panic!("a bug in a job");
+            })
+            .await;
+        assert!(matches!(panicked, Err(CommandError::Internal { .. })));
+        let in_edit = instances.call(None, |data| Ok(data.history.in_edit));
+        assert_eq!(in_edit.await, Ok(false));
+
+        // the task ends with the last handle
+        drop(instances);
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+use pendulum_core::{Integrator, UpdateOrder, INTEGRATOR};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::CommandError;
+
+/// The type and range of one tunable parameter, tagged by `type`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub(crate) enum ParameterKind {
+    /// One of `options`, as a string.
+    Choice {
+        options: Vec<&'static str>,
+        default: &'static str,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ParameterSchema {
+    pub(crate) name: &'static str,
+    pub(crate) description: &'static str,
+    #[serde(flatten)]
+    pub(crate) kind: ParameterKind,
+}
+
+/// A method as `list_integrators` describes it, for building its settings
+/// without knowing about it in advance.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IntegratorInfo {
+    pub(crate) id: &'static str,
+    pub(crate) name: &'static str,
+    /// Order of accuracy of the global error.
+    pub(crate) order: u32,
+    pub(crate) symplectic: bool,
+    /// Whether it picks its own step sizes within a substep.
+    pub(crate) adaptive: bool,
+    pub(crate) parameters: Vec<ParameterSchema>,
+}
+
+/// An integrator's id and parameter values, as `get_integrator_settings`
+/// returns them and `set_integrator_settings` takes them. Parameters left
+/// out take their defaults.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IntegratorSettings {
+    pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) values: Map<String, Value>,
+}
+
+/// Every method `set_integrator_settings` can pick, the default first.
+pub(crate) fn list() -> Vec<IntegratorInfo> {
+    vec![IntegratorInfo {
+        id: INTEGRATOR,
+        name: "Symplectic Euler",
+        order: 1,
+        symplectic: true,
+        adaptive: false,
+        parameters: vec![ParameterSchema {
+            name: "updateOrder",
+            description: "Whether a step moves the angular velocities or the angles first",
+            kind: ParameterKind::Choice {
+                options: UpdateOrder::NAMES.to_vec(),
+                default: UpdateOrder::default().name(),
+            },
+        }],
+    }]
+}
+
+/// `value` checked against `schema`, as the option it names.
+fn choice<'a>(
+    schema: &ParameterSchema,
+    value: Option<&'a Value>,
+) -> Result<Option<&'a str>, CommandError> {
+    let ParameterKind::Choice { options, .. } = &schema.kind;
+    let name = || format!("values.{}", schema.name);
+    match value {
+        None => Ok(None),
+        Some(Value::String(option)) if options.contains(&option.as_str()) => Ok(Some(option)),
+        Some(other) => Err(CommandError::invalid(
+            name(),
+            format!("must be one of {}, got {other}", options.join(", ")),
+        )),
+    }
+}
+
+/// The app's side of an `Integrator`: the settings the commands and the
+/// save files carry it as.
+pub(crate) trait IntegratorExt: Sized {
+    fn settings(&self) -> IntegratorSettings;
+
+    /// The integrator `settings` pick, each value checked against its
+    /// parameter's schema and unknown parameters refused, with the error
+    /// naming the parameter.
+    fn from_settings(settings: &IntegratorSettings) -> Result<Self, CommandError>;
+}
+
+impl IntegratorExt for Integrator {
+    fn settings(&self) -> IntegratorSettings {
+        let values = match self {
+            Self::SymplecticEuler { order } => {
+                Map::from_iter([("updateOrder".into(), order.name().into())])
+            }
+        };
+        IntegratorSettings {
+            id: self.id().into(),
+            values,
+        }
+    }
+
+    fn from_settings(settings: &IntegratorSettings) -> Result<Self, CommandError> {
+        let info = (list().into_iter())
+            .find(|info| info.id == settings.id)
+            .ok_or_else(|| CommandError::not_found("integrator", &settings.id))?;
+        if let Some(unknown) = (settings.values.keys())
+            .find(|key| !info.parameters.iter().any(|p| p.name == key.as_str()))
+        {
+            return Err(CommandError::invalid(
+                format!("values.{unknown}"),
+                format!("is not a parameter of {}", info.id),
+            ));
+        }
+        let value = |name: &str| settings.values.get(name);
+        match info.id {
+            INTEGRATOR => {
+                let order = match choice(&info.parameters[0], value("updateOrder"))? {
+                    Some("positionFirst") => UpdateOrder::PositionFirst,
+                    _ => UpdateOrder::VelocityFirst,
+                };
+                Ok(Self::SymplecticEuler { order })
+            }
+            id => unreachable!("listed integrator {id} has no settings"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pendulum_core::{Bob, Pendulum};
+    use serde_json::json;
+
+    #[test]
+    fn every_integrator_has_a_usable_schema_and_bad_settings_name_the_parameter() {
+        for info in list() {
+            assert!(!info.name.is_empty() && info.order >= 1);
+            let mut names: Vec<_> = info.parameters.iter().map(|p| p.name).collect();
+            names.dedup();
+            assert_eq!(names.len(), info.parameters.len(), "{}", info.id);
+            for parameter in &info.parameters {
+                let ParameterKind::Choice { options, default } = &parameter.kind;
+                assert!(options.contains(default), "{}", parameter.name);
+                let json = serde_json::to_value(parameter).unwrap();
+                assert_eq!(json["type"], "choice");
+            }
+            // the defaults and an empty set of values are the same integrator
+            let bare = IntegratorSettings {
+                id: info.id.into(),
+                values: Map::new(),
+            };
+            let integrator = Integrator::from_settings(&bare).unwrap();
+            assert_eq!(
+                Integrator::from_settings(&integrator.settings()),
+                Ok(integrator)
+            );
+        }
+        assert_eq!(list()[0].id, Integrator::default().id());
+
+        let settings = |id: &str, values: Value| IntegratorSettings {
+            id: id.into(),
+            values: values.as_object().unwrap().clone(),
+        };
+        let name = |error: CommandError| match error {
+            CommandError::InvalidParameter { name, .. } => name,
+            other => panic!("expected an invalid parameter, got {other:?}"),
+        };
+        let bad_order = settings(INTEGRATOR, json!({ "updateOrder": "sideways" }));
+        let error = Integrator::from_settings(&bad_order).unwrap_err();
+        assert_eq!(name(error), "values.updateOrder");
+        let bad_type = settings(INTEGRATOR, json!({ "updateOrder": 1 }));
+        let error = Integrator::from_settings(&bad_type).unwrap_err();
+        assert_eq!(name(error), "values.updateOrder");
+        let unknown = settings(INTEGRATOR, json!({ "tolerance": 1e-6 }));
+        let error = Integrator::from_settings(&unknown).unwrap_err();
+        assert_eq!(name(error), "values.tolerance");
+        assert!(matches!(
+            Integrator::from_settings(&settings("rk45", json!({}))),
+            Err(CommandError::NotFound { .. })
+        ));
+
+        // from rest, moving the angles first leaves them for one step
+        let position_first = settings(INTEGRATOR, json!({ "updateOrder": "positionFirst" }));
+        let at_rest = Pendulum::new(vec![
+            Bob::new(1.0, 1.0, 2.0, 0.0),
+            Bob::new(1.0, 1.0, 1.0, 0.0),
+        ]);
+        let mut pendulum = at_rest.clone();
+        pendulum.integrator = Integrator::from_settings(&position_first).unwrap();
+        pendulum.step(0.01);
+        assert_eq!(pendulum.bobs[1].theta, 1.0);
+        assert_ne!(pendulum.bobs[1].omega, 0.0);
+        let mut pendulum = at_rest;
+        pendulum.step(0.01);
+        assert_ne!(pendulum.bobs[1].theta, 1.0);
+    }
+}
@@ -0,0 +1,309 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::callsite::Identifier;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+use crate::error::CommandError;
+
+/// Name of the log file in the app's data directory. It holds an entry per
+/// line, as JSON.
+pub(crate) const LOG_FILE: &str = "double-pendulum.log";
+/// Size a log file grows to before it is moved aside for a new one.
+pub(crate) const MAX_LOG_FILE_BYTES: u64 = 1 << 20;
+/// Files moved aside that are kept, as `double-pendulum.log.1` for the
+/// newest of them; the oldest goes when another is moved aside.
+pub(crate) const OLD_LOG_FILES: usize = 2;
+/// Entries kept for `get_recent_logs`.
+pub(crate) const RECENT_LOG_ENTRIES: usize = 500;
+/// Entries one line of code logs in a `REPEAT_WINDOW` before the rest of
+/// them are only counted.
+const REPEATS_PER_WINDOW: u32 = 5;
+const REPEAT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Most to least severe, so a level takes in the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::ERROR => Self::Error,
+            Level::WARN => Self::Warn,
+            Level::INFO => Self::Info,
+            Level::DEBUG => Self::Debug,
+            Level::TRACE => Self::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LogEntry {
+    /// Milliseconds since the Unix epoch.
+    pub(crate) time_ms: u64,
+    pub(crate) level: LogLevel,
+    /// The module it was logged from.
+    pub(crate) target: String,
+    /// The message, then its fields as `name=value`.
+    pub(crate) message: String,
+    /// Entries from the same line that were left out since the last one
+    /// that wasn't, for coming too thick and fast.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub(crate) suppressed: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// How often one line of code has logged lately.
+struct Repeats {
+    since: Instant,
+    count: u32,
+    suppressed: u64,
+}
+
+#[derive(Default)]
+struct LogsInner {
+    recent: VecDeque<LogEntry>,
+    file: Option<File>,
+    /// Bytes in `file` so far.
+    written: u64,
+    repeats: HashMap<Identifier, Repeats>,
+}
+
+/// What the app logs, kept in memory for the diagnostics panel and written
+/// to a file that bug reports can attach. A line of code that logs over
+/// and over is cut down to `REPEATS_PER_WINDOW` entries a `REPEAT_WINDOW`,
+/// the next one that gets through saying how many were left out, so
+/// neither grows faster than that however the simulation misbehaves.
+pub(crate) struct Logs {
+    /// The file, if there is one.
+    path: Option<PathBuf>,
+    inner: Mutex<LogsInner>,
+}
+
+impl Logs {
+    /// Logs kept in memory and, if `path` is given, written there, after
+    /// what earlier runs left in it.
+    pub(crate) fn new(path: Option<PathBuf>) -> Self {
+        let mut inner = LogsInner::default();
+        if let Some(path) = &path {
+            let _ = std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")));
+            let file = (File::options().create(true).append(true)).open(path);
+            if let Ok(file) = file {
+                inner.written = file.metadata().map_or(0, |m| m.len());
+                inner.file = Some(file);
+            }
+        }
+        Self {
+            path,
+            inner: Mutex::new(inner),
+        }
+    }
+
+    /// Sends everything logged with `tracing` from now on to `logs`. Only
+    /// info and more severe is taken; a second call changes nothing.
+    pub(crate) fn install(logs: Arc<Self>) {
+        let subscriber = tracing_subscriber::registry().with(LogLayer(logs));
+        let _ = tracing::subscriber::set_global_default(subscriber);
+    }
+
+    fn inner(&self) -> MutexGuard<'_, LogsInner> {
+        // an entry is written whole or not at all, so poison says nothing
+        self.inner.lock().unwrap_or_else(|poisoned| {
+            self.inner.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    pub(crate) fn path(&self) -> Result<&Path, CommandError> {
+        (self.path.as_deref()).ok_or_else(|| CommandError::unavailable("there is no log file"))
+    }
+
+    /// The newest `limit` entries at `level` or more severe, oldest first.
+    pub(crate) fn recent(&self, level: LogLevel, limit: usize) -> Vec<LogEntry> {
+        let inner = self.inner();
+        let mut entries: Vec<LogEntry> = (inner.recent.iter().rev())
+            .filter(|entry| entry.level <= level)
+            .take(limit)
+            .cloned()
+            .collect();
+        entries.reverse();
+        entries
+    }
+
+    /// Keeps `entry`, logged by the line `callsite`, unless that
+    /// line has logged too much lately.
+    fn push(&self, callsite: Identifier, mut entry: LogEntry) {
+        let now = Instant::now();
+        let mut inner = self.inner();
+        let repeats = (inner.repeats.entry(callsite)).or_insert(Repeats {
+            since: now,
+            count: 0,
+            suppressed: 0,
+        });
+        if now.duration_since(repeats.since) >= REPEAT_WINDOW {
+            (repeats.since, repeats.count) = (now, 0);
+        }
+        if repeats.count == REPEATS_PER_WINDOW {
+            repeats.suppressed += 1;
+            return;
+        }
+        repeats.count += 1;
+        entry.suppressed = std::mem::take(&mut repeats.suppressed);
+        if inner.recent.len() == RECENT_LOG_ENTRIES {
+            inner.recent.pop_front();
+        }
+        if let Ok(line) = serde_json::to_string(&entry) {
+            self.write(&mut inner, line + "\n");
+        }
+        inner.recent.push_back(entry);
+    }
+
+    /// Appends `line` to the file, moving it aside first if it would grow
+    /// past `MAX_LOG_FILE_BYTES`. A file that can't be written to is given
+    /// up on, and only the entries in memory are kept.
+    fn write(&self, inner: &mut LogsInner, line: String) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if inner.written > 0 && inner.written + line.len() as u64 > MAX_LOG_FILE_BYTES {
+            inner.file = None;
+            let old = |k: usize| PathBuf::from(format!("{}.{k}", path.display()));
+            for k in (1..OLD_LOG_FILES).rev() {
+                let _ = std::fs::rename(old(k), old(k + 1));
+            }
+            let _ = std::fs::rename(path, old(1));
+            inner.file = File::create(path).ok();
+            inner.written = 0;
+        }
+        let Some(file) = &mut inner.file else {
+            return;
+        };
+        match file.write_all(line.as_bytes()) {
+            Ok(()) => inner.written += line.len() as u64,
+            Err(_) => inner.file = None,
+        }
+    }
+}
+
+/// Puts an event's message first and its other fields after it.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: Vec<String>,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields.push(format!("{}={value:?}", field.name()));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.push(format!("{}={value}", field.name()));
+        }
+    }
+}
+
+struct LogLayer(Arc<Logs>);
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>, _: Context<'_, S>) -> bool {
+        *metadata.level() <= Level::INFO
+    }
+
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let mut message = visitor.message;
+        for field in visitor.fields {
+            message += &format!(" {field}");
+        }
+        let time_ms = SystemTime::now().duration_since(UNIX_EPOCH);
+        let entry = LogEntry {
+            time_ms: time_ms.map_or(0, |t| t.as_millis() as u64),
+            level: (*metadata.level()).into(),
+            target: metadata.target().to_string(),
+            message,
+            suppressed: 0,
+        };
+        self.0.push(metadata.callsite(), entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_are_cut_down_and_the_file_moves_aside() {
+        let dir = std::env::temp_dir().join(format!("logs-{}", std::process::id()));
+        let path = dir.join(LOG_FILE);
+        let logs = Arc::new(Logs::new(Some(path.clone())));
+        let subscriber = || tracing_subscriber::registry().with(LogLayer(logs.clone()));
+        let repeated = || {
+            for step in 0..20 {
+                tracing::warn!(step, "singular mass matrix");
+            }
+        };
+        tracing::subscriber::with_default(subscriber(), || {
+            repeated();
+            tracing::info!(subscribers = 2, "subscribed");
+            tracing::debug!("left out");
+        });
+        let warnings = logs.recent(LogLevel::Warn, 100);
+        assert_eq!(warnings.len(), REPEATS_PER_WINDOW as usize);
+        assert_eq!(warnings[1].message, "singular mass matrix step=1");
+        let everything = logs.recent(LogLevel::Trace, 100);
+        assert_eq!(everything.len(), REPEATS_PER_WINDOW as usize + 1);
+        assert_eq!(everything.last().unwrap().level, LogLevel::Info);
+        assert_eq!(logs.recent(LogLevel::Trace, 2)[0], warnings[4]);
+        let lines = std::fs::read_to_string(logs.path().unwrap()).unwrap();
+        let first: LogEntry = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!((first, lines.lines().count()), (warnings[0].clone(), 6));
+
+        // once the window has passed, the next says how many were left out
+        for repeats in logs.inner().repeats.values_mut() {
+            repeats.since = repeats.since.checked_sub(REPEAT_WINDOW).unwrap();
+        }
+        tracing::subscriber::with_default(subscriber(), repeated);
+        let warnings = logs.recent(LogLevel::Warn, 100);
+        assert_eq!(warnings.len(), 2 * REPEATS_PER_WINDOW as usize);
+        assert_eq!(warnings[5].suppressed, 15);
+        assert_eq!(warnings[6].suppressed, 0);
+
+        // past the size limit the file is moved aside, the oldest dropped
+        logs.inner().written = MAX_LOG_FILE_BYTES;
+        tracing::subscriber::with_default(subscriber(), || tracing::error!("after"));
+        let old = std::fs::read_to_string(format!("{}.1", path.display())).unwrap();
+        assert_eq!(old.lines().count(), 11);
+        assert!(old.starts_with(&lines));
+        let new = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(new.lines().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(Logs::new(None).path().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+/// Shortest match the format can encode.
+const MIN_MATCH: usize = 4;
+/// A block always ends in at least this many literals.
+const LAST_LITERALS: usize = 5;
+/// No match starts closer to the end of the block than this.
+const MATCH_LIMIT: usize = 12;
+/// Farthest back a match can point.
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Appends the part of `length` past its token's nibble.
+fn write_length(out: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        out.push(255);
+        length -= 255;
+    }
+    out.push(length as u8);
+}
+
+fn write_literals(out: &mut Vec<u8>, token: &mut u8, literals: &[u8]) {
+    *token |= (literals.len().min(15) as u8) << 4;
+    out.push(*token);
+    if literals.len() >= 15 {
+        write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+}
+
+/// `input` as one block of the LZ4 block format, matching greedily on four
+/// bytes at a time, for binary recordings to compress their frames with.
+/// Only bare blocks are written, never LZ4 frames; the recording says how
+/// long each block is and how long it comes out to.
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    // where each hashed sequence was last seen, plus one; 0 for never
+    let mut seen = vec![0usize; 1 << HASH_BITS];
+    let (mut anchor, mut i) = (0, 0);
+    while i + MATCH_LIMIT < input.len() {
+        let sequence = read_u32(input, i);
+        let slot = &mut seen[hash(sequence)];
+        let candidate = std::mem::replace(slot, i + 1).checked_sub(1);
+        let Some(from) =
+            candidate.filter(|&c| i - c <= MAX_OFFSET && read_u32(input, c) == sequence)
+        else {
+            i += 1;
+            continue;
+        };
+        let end = input.len() - LAST_LITERALS;
+        let mut length = MIN_MATCH;
+        while i + length < end && input[from + length] == input[i + length] {
+            length += 1;
+        }
+        let mut token = (length - MIN_MATCH).min(15) as u8;
+        write_literals(&mut out, &mut token, &input[anchor..i]);
+        out.extend_from_slice(&((i - from) as u16).to_le_bytes());
+        if length - MIN_MATCH >= 15 {
+            write_length(&mut out, length - MIN_MATCH - 15);
+        }
+        i += length;
+        anchor = i;
+    }
+    write_literals(&mut out, &mut 0, &input[anchor..]);
+    out
+}
+
+fn read_length(input: &[u8], at: &mut usize) -> Option<usize> {
+    let mut length = 0usize;
+    loop {
+        let byte = *input.get(*at)?;
+        *at += 1;
+        length = length.checked_add(usize::from(byte))?;
+        if byte != 255 {
+            return Some(length);
+        }
+    }
+}
+
+/// The `raw_len` bytes LZ4 block `input` holds, `None` if it is corrupt or
+/// holds any other number of them.
+pub(crate) fn decompress(input: &[u8], raw_len: usize) -> Option<Vec<u8>> {
+    // no block comes out more than 255 times as long, whatever it claims
+    let mut out = Vec::with_capacity(raw_len.min(input.len().saturating_mul(255)));
+    let mut at = 0;
+    loop {
+        let token = *input.get(at)?;
+        at += 1;
+        let mut literals = usize::from(token >> 4);
+        if literals == 15 {
+            literals += read_length(input, &mut at)?;
+        }
+        out.extend_from_slice(input.get(at..at.checked_add(literals)?)?);
+        at += literals;
+        if out.len() > raw_len {
+            return None;
+        }
+        if at == input.len() {
+            break;
+        }
+        let offset = usize::from(u16::from_le_bytes([*input.get(at)?, *input.get(at + 1)?]));
+        at += 2;
+        let mut length = usize::from(token & 15);
+        if length == 15 {
+            length += read_length(input, &mut at)?;
+        }
+        length += MIN_MATCH;
+        if offset == 0 || offset > out.len() || out.len() + length > raw_len {
+            return None;
+        }
+        // byte by byte, since a match may overlap what it copies
+        for _ in 0..length {
+            out.push(out[out.len() - offset]);
+        }
+    }
+    (out.len() == raw_len).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_round_trip_and_corruption_is_caught() {
+        let mut samples = Vec::new();
+        let mut x = 0.3f64;
+        for i in 0..5_000 {
+            x = (x + 0.001 * f64::from(i % 7)).sin();
+            samples.extend_from_slice(&x.to_le_bytes());
+            samples.extend_from_slice(&2.5f64.to_le_bytes());
+        }
+        let runs = [b"abcd".repeat(10_000), b"a".repeat(300)].concat();
+        for input in [&samples[..], &runs, b"short", b""] {
+            let block = compress(input);
+            assert_eq!(decompress(&block, input.len()).as_deref(), Some(input));
+        }
+        assert!(compress(&runs).len() < runs.len() / 50);
+        assert!(compress(&samples).len() < samples.len());
+
+        let block = compress(&runs);
+        assert_eq!(decompress(&block, runs.len() - 1), None);
+        assert_eq!(decompress(&block[..block.len() / 2], runs.len()), None);
+        let mut bad = block.clone();
+        bad[2] = 0xff;
+        bad[3] = 0xff;
+        assert_ne!(decompress(&bad, runs.len()).as_deref(), Some(&runs[..]));
+    }
+}
@@ -0,0 +1,169 @@
+use pendulum_core::Pendulum;
+use rosc::{encoder, OscBundle, OscMessage, OscPacket, OscTime, OscType};
+use std::{net::UdpSocket, time::Duration};
+use tokio::time::Instant;
+
+use crate::error::CommandError;
+
+/// Bounds of the OSC send rate, in bundles per second.
+pub(crate) const MIN_OSC_RATE: u32 = 1;
+pub(crate) const MAX_OSC_RATE: u32 = 240;
+
+/// OSC time tag meaning "process on arrival".
+const IMMEDIATELY: OscTime = OscTime {
+    seconds: 0,
+    fractional: 1,
+};
+
+/// Sends the bobs to an OSC receiver over UDP, one bundle per frame so every
+/// value in it comes from the same substep.
+pub(crate) struct OscSender {
+    socket: UdpSocket,
+    prefix: String,
+    period: Duration,
+    next_due: Option<Instant>,
+}
+
+impl OscSender {
+    /// Binds a local socket and aims it at `host:port`.
+    pub(crate) fn connect(
+        host: &str,
+        port: u16,
+        rate: u32,
+        prefix: &str,
+    ) -> Result<Self, CommandError> {
+        if !(MIN_OSC_RATE..=MAX_OSC_RATE).contains(&rate) {
+            return Err(CommandError::invalid(
+                "rate",
+                format!("must be between {MIN_OSC_RATE} and {MAX_OSC_RATE}, got {rate}"),
+            ));
+        }
+        if !prefix.starts_with('/') || prefix.ends_with('/') || prefix.contains([' ', '#']) {
+            return Err(CommandError::invalid(
+                "prefix",
+                format!("must start with / and not end with one, got {prefix:?}"),
+            ));
+        }
+        let socket = UdpSocket::bind(("0.0.0.0", 0))
+            .and_then(|socket| socket.connect((host, port)).map(|_| socket))
+            .map_err(|e| {
+                CommandError::internal(format!("Can't open an OSC socket to {host}:{port}: {e}"))
+            })?;
+        // a full send buffer drops the bundle rather than stalling the physics
+        socket
+            .set_nonblocking(true)
+            .map_err(CommandError::internal)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.into(),
+            period: Duration::from_secs(1) / rate,
+            next_due: None,
+        })
+    }
+
+    fn bundle(&self, pendulum: &Pendulum, sim_time: f64) -> OscPacket {
+        let message = |addr: String, value: f64| {
+            OscPacket::Message(OscMessage {
+                addr,
+                args: vec![OscType::Float(value as f32)],
+            })
+        };
+        let mut content = vec![message(format!("{}/time", self.prefix), sim_time)];
+        for (i, bob) in pendulum.bobs.iter().enumerate() {
+            let bob_prefix = format!("{}/bob/{i}", self.prefix);
+            content.extend([
+                message(format!("{bob_prefix}/theta"), bob.theta),
+                message(format!("{bob_prefix}/omega"), bob.omega),
+                message(format!("{bob_prefix}/x"), bob.coordinate.x),
+                message(format!("{bob_prefix}/y"), bob.coordinate.y),
+            ]);
+        }
+        OscPacket::Bundle(OscBundle {
+            timetag: IMMEDIATELY,
+            content,
+        })
+    }
+
+    /// Sends a bundle if one is due at `now`. Due times advance by whole
+    /// periods, like a subscription's frames, and never burst to catch up.
+    /// Send failures are ignored: UDP is lossy anyway, and the receiver may
+    /// simply not be listening yet.
+    pub(crate) fn send_due(&mut self, now: Instant, pendulum: &Pendulum, sim_time: f64) {
+        let due = self.next_due.get_or_insert(now);
+        if now < *due {
+            return;
+        }
+        *due += self.period;
+        if *due <= now {
+            *due = now + self.period;
+        }
+        if let Ok(bytes) = encoder::encode(&self.bundle(pendulum, sim_time)) {
+            let _ = self.socket.send(&bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::decoder;
+
+    fn receiver() -> (UdpSocket, u16) {
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let port = socket.local_addr().unwrap().port();
+        (socket, port)
+    }
+
+    fn receive(socket: &UdpSocket) -> OscBundle {
+        let mut buffer = [0; 4096];
+        let size = socket.recv(&mut buffer).unwrap();
+        match decoder::decode_udp(&buffer[..size]).unwrap().1 {
+            OscPacket::Bundle(bundle) => bundle,
+            OscPacket::Message(_) => panic!("expected a bundle"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bundles_carry_every_bob_at_the_requested_rate() {
+        let (socket, port) = receiver();
+        let mut sender = OscSender::connect("127.0.0.1", port, 10, "/pendulum").unwrap();
+        let pendulum = Pendulum::default();
+        let start = Instant::now();
+        for ms in (0..300).step_by(8) {
+            sender.send_due(start + Duration::from_millis(ms), &pendulum, 1.5);
+        }
+        let mut bundles = Vec::new();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let mut buffer = [0; 4096];
+        while let Ok(size) = socket.recv(&mut buffer) {
+            bundles.push(decoder::decode_udp(&buffer[..size]).unwrap().1);
+        }
+        // 0, 100 and 200 ms
+        assert_eq!(bundles.len(), 3);
+
+        sender.send_due(start + Duration::from_secs(1), &pendulum, 1.5);
+        let bundle = receive(&socket);
+        assert_eq!(bundle.content.len(), 1 + 4 * pendulum.n());
+        let OscPacket::Message(theta) = &bundle.content[1] else {
+            panic!("expected a message");
+        };
+        assert_eq!(theta.addr, "/pendulum/bob/0/theta");
+        assert_eq!(
+            theta.args,
+            vec![OscType::Float(pendulum.bobs[0].theta as f32)]
+        );
+    }
+
+    #[test]
+    fn bad_settings_are_rejected() {
+        assert!(OscSender::connect("127.0.0.1", 9000, 0, "/pendulum").is_err());
+        assert!(OscSender::connect("127.0.0.1", 9000, 60, "pendulum").is_err());
+        assert!(OscSender::connect("127.0.0.1", 9000, 60, "/pendulum/").is_err());
+        assert!(OscSender::connect("no such host.invalid", 9000, 60, "/p").is_err());
+    }
+}
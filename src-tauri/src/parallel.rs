@@ -0,0 +1,196 @@
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::error::CommandError;
+
+/// Cores a batch leaves to the live run and the UI unless told otherwise.
+const RESERVED_CORES: usize = 1;
+/// Threads a batch may ask for per core. Past a few they only take turns,
+/// and each is an OS thread of its own.
+const MAX_THREADS_PER_CORE: usize = 4;
+
+/// How far a batch has got, sent as each of its jobs finishes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BatchProgress {
+    pub(crate) completed: u64,
+    pub(crate) total: u64,
+    /// Wall seconds the rest should take, at the pace so far.
+    pub(crate) eta_seconds: f64,
+}
+
+fn cores() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Threads a batch runs on by default: all the cores but
+/// `RESERVED_CORES`, and at least one.
+pub(crate) fn default_threads() -> usize {
+    cores().saturating_sub(RESERVED_CORES).max(1)
+}
+
+/// `threads` checked, `default_threads` for `None`. More than there are
+/// cores is allowed, they just take turns, up to `MAX_THREADS_PER_CORE`
+/// each.
+pub(crate) fn validate_threads(threads: Option<usize>) -> Result<usize, CommandError> {
+    let most = MAX_THREADS_PER_CORE * cores();
+    match threads {
+        None => Ok(default_threads()),
+        Some(threads) if (1..=most).contains(&threads) => Ok(threads),
+        Some(threads) => Err(CommandError::invalid(
+            "threads",
+            format!("must be between 1 and {most}, got {threads}"),
+        )),
+    }
+}
+
+/// The pool of `default_threads`, built on first use and kept, for work
+/// that runs too often to build one each time.
+fn shared_pool() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        (rayon::ThreadPoolBuilder::new().num_threads(default_threads()))
+            .thread_name(|i| format!("batch-{i}"))
+            .build()
+            .expect("the batch pool can be built")
+    })
+}
+
+/// Calls `f` on every item, in parallel on the shared pool, for stepping
+/// many copies at once every tick.
+pub(crate) fn for_each_mut<T: Send>(items: &mut [T], f: impl Fn(&mut T) + Sync + Send) {
+    shared_pool().install(|| items.par_iter_mut().for_each(f));
+}
+
+/// Runs `job` for each of `0..total` on `threads` threads, `default_threads`
+/// if `None`, and returns the results in that order, however the jobs
+/// finish. `cancel` is looked at before each job starts; a job that sees it
+/// raised partway may give up with `Ok(None)`. Either way the batch
+/// returns `None` once the jobs under way are done. The first error ends
+/// it early too. `progress` is called from the worker threads as jobs
+/// finish.
+pub(crate) fn run_batch<R: Send>(
+    total: u64,
+    threads: Option<usize>,
+    cancel: &AtomicBool,
+    progress: impl Fn(BatchProgress) + Sync,
+    job: impl Fn(u64) -> Result<Option<R>, CommandError> + Sync,
+) -> Result<Option<Vec<R>>, CommandError> {
+    let threads = validate_threads(threads)?;
+    let pool = (rayon::ThreadPoolBuilder::new().num_threads(threads))
+        .build()
+        .map_err(CommandError::internal)?;
+    let (started, completed) = (Instant::now(), AtomicU64::new(0));
+    let results: Result<Vec<Option<R>>, CommandError> = pool.install(|| {
+        (0..total)
+            .into_par_iter()
+            .map(|k| {
+                if cancel.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+                let result = job(k)?;
+                if result.is_some() {
+                    let completed = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                    let pace = started.elapsed().as_secs_f64() / completed as f64;
+                    progress(BatchProgress {
+                        completed,
+                        total,
+                        eta_seconds: pace * (total - completed) as f64,
+                    });
+                }
+                Ok(result)
+            })
+            .collect()
+    });
+    Ok(results?.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pendulum_core::Pendulum;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[test]
+    fn batches_match_serial_runs_and_stop_when_cancelled() {
+        // later jobs finish first, yet come back in order, as run serially
+        let final_theta = |k: u64| {
+            let mut pendulum = Pendulum::default();
+            pendulum.bobs[0].theta += k as f64 * 0.1;
+            for _ in 0..(2_000 - 100 * k) {
+                pendulum.step(1e-3);
+            }
+            pendulum.bobs[1].theta
+        };
+        let serial: Vec<f64> = (0..16).map(final_theta).collect();
+        let never = AtomicBool::new(false);
+        let reports = Mutex::new(Vec::new());
+        let parallel = run_batch(
+            16,
+            Some(4),
+            &never,
+            |p| reports.lock().unwrap().push(p),
+            |k| Ok(Some(final_theta(k))),
+        );
+        assert_eq!(parallel.unwrap(), Some(serial));
+        let mut reports = reports.into_inner().unwrap();
+        // two jobs finishing together may report out of turn
+        reports.sort_by_key(|p| p.completed);
+        let completed: Vec<u64> = reports.iter().map(|p| p.completed).collect();
+        assert_eq!(completed, (1..=16).collect::<Vec<_>>());
+        assert!(reports
+            .iter()
+            .all(|p| p.total == 16 && p.eta_seconds >= 0.0));
+        assert_eq!(reports.last().unwrap().eta_seconds, 0.0);
+
+        // jobs not yet started when the flag goes up never start
+        let cancel = AtomicBool::new(false);
+        let ran = AtomicU64::new(0);
+        let started = Instant::now();
+        let cancelled = run_batch(
+            1_000,
+            Some(2),
+            &cancel,
+            |p| {
+                if p.completed == 3 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            },
+            |_| {
+                ran.fetch_add(1, Ordering::Relaxed);
+                std::thread::sleep(Duration::from_millis(5));
+                Ok(Some(()))
+            },
+        );
+        assert_eq!(cancelled.unwrap(), None);
+        assert!(ran.into_inner() <= 6);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // an error ends the batch, and threads are checked
+        let failed = run_batch(
+            8,
+            Some(2),
+            &never,
+            |_| {},
+            |k| match k {
+                5 => Err(CommandError::invalid("k", "is 5")),
+                _ => Ok(Some(k)),
+            },
+        );
+        assert!(failed.is_err());
+        assert!(validate_threads(Some(0)).is_err());
+        let most = MAX_THREADS_PER_CORE * cores();
+        assert_eq!(validate_threads(Some(most)).unwrap(), most);
+        assert!(validate_threads(Some(1_000_000)).is_err());
+        assert_eq!(validate_threads(None).unwrap(), default_threads());
+
+        let mut items = vec![1, 2, 3];
+        for_each_mut(&mut items, |i| *i *= 2);
+        assert_eq!(items, [2, 4, 6]);
+    }
+}
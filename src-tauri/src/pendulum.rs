@@ -0,0 +1,55 @@
+use pendulum_core::{validate_parameter, Bob, InvalidBob};
+use serde::{Deserialize, Serialize};
+
+/// A bob as handed in from outside, before it joins a chain.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BobSpec {
+    pub(crate) length_rod: f64,
+    pub(crate) mass: f64,
+    pub(crate) theta: f64,
+    pub(crate) omega: f64,
+    /// Kept by `set_state`, so a saved chain comes back with the same ids;
+    /// ignored when adding a bob, which always gets a new one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<u64>,
+    /// See `Bob::locked`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) locked: bool,
+    #[serde(flatten)]
+    pub(crate) meta: BobMeta,
+}
+
+/// How the frontends show a bob. The app keeps it by bob id, next to the
+/// chain; the physics never reads it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BobMeta {
+    /// A CSS color.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<String>,
+}
+
+impl BobMeta {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.color.is_none() && self.label.is_none()
+    }
+}
+
+impl BobSpec {
+    pub(crate) fn validate(&self) -> Result<(), InvalidBob> {
+        validate_parameter("length_rod", self.length_rod)?;
+        validate_parameter("mass", self.mass)?;
+        validate_parameter("theta", self.theta)?;
+        validate_parameter("omega", self.omega)
+    }
+
+    pub(crate) fn bob(&self) -> Bob {
+        Bob {
+            locked: self.locked,
+            ..Bob::new(self.length_rod, self.mass, self.theta, self.omega)
+        }
+    }
+}
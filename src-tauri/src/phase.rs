@@ -0,0 +1,279 @@
+use pendulum_core::{unwrap_near, wrap_angle, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::CommandError;
+use crate::recording::Recording;
+use crate::savefile;
+use crate::state::AppDataInner;
+use crate::trajectory::{read_recording, FileReport};
+
+/// What `export_phase_space` takes its samples from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "kind"
+)]
+pub(crate) enum PhaseSource {
+    /// The states kept for `rewind`, then the current one.
+    History,
+    /// A finished recording.
+    Recording { id: u64 },
+    /// A trajectory file, CSV or binary.
+    File { path: String },
+}
+
+/// Where the Poincaré section lies: every time bob `bob` swings through
+/// `theta`, canonical radians, turning the positive way.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct PoincareSection {
+    /// Index in the chain.
+    pub(crate) bob: usize,
+    /// Straight down, to begin with.
+    pub(crate) theta: f64,
+}
+
+impl Default for PoincareSection {
+    fn default() -> Self {
+        Self { bob: 0, theta: PI }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct PhaseSpaceOptions {
+    /// Add each bob's generalized momentum, for the canonical (θ, p) pairs.
+    pub(crate) momenta: bool,
+    /// Write only the crossings of this section, interpolated between the
+    /// samples either side, instead of every sample.
+    pub(crate) section: Option<PoincareSection>,
+}
+
+/// A `PhaseSource` with what it needs copied out of the state.
+#[derive(Clone, Debug)]
+pub(crate) enum PhaseInput {
+    Recording(Arc<Recording>),
+    File(PathBuf),
+}
+
+impl PhaseSource {
+    pub(crate) fn capture(self, data: &AppDataInner) -> Result<PhaseInput, CommandError> {
+        match self {
+            Self::History => {
+                let mut recording = Recording::default();
+                for sample in data.rewind_buffer.samples() {
+                    let pendulum = Pendulum {
+                        bobs: sample.bobs.clone(),
+                        pivot: sample.pivot,
+                        ..data.pendulum.clone()
+                    };
+                    recording.push(&pendulum, sample.sim_time);
+                }
+                recording.push(&data.pendulum, data.sim_time);
+                Ok(PhaseInput::Recording(Arc::new(recording)))
+            }
+            Self::Recording { id } => (data.recordings.iter())
+                .find(|(recording_id, _)| *recording_id == id)
+                .map(|(_, recording)| PhaseInput::Recording(recording.clone()))
+                .ok_or_else(|| CommandError::not_found("recording", id)),
+            Self::File { path } => Ok(PhaseInput::File(path.into())),
+        }
+    }
+}
+
+/// The header row: sim time, then for each of `bobs`, numbered from 1 in
+/// the chain, the angle wrapped to (-π, π] and unwrapped, counting whole
+/// turns, the angular velocity and, with `momenta`, the momentum.
+fn header(bobs: &[usize], momenta: bool) -> String {
+    let mut header = String::from("sim_time_s");
+    for i in bobs.iter().map(|b| b + 1) {
+        header += &format!(",theta{i}_wrapped_rad,theta{i}_unwrapped_rad,omega{i}_rad_per_s");
+        if momenta {
+            header += &format!(",p{i}_kg_m2_per_s");
+        }
+    }
+    header
+}
+
+/// Every sample's row of `bobs`, in header order. The unwrapped angles are
+/// carried on from the row before, so even a file of wrapped angles comes
+/// out counting turns.
+fn rows(recording: &Recording, bobs: &[usize], momenta: bool) -> Vec<Vec<f64>> {
+    let mut unwrapped: Vec<Option<f64>> = vec![None; bobs.len()];
+    let mut rows = Vec::with_capacity(recording.len());
+    for sample in recording.samples() {
+        let pendulum = sample.pendulum();
+        let p = if momenta {
+            pendulum.momenta()
+        } else {
+            Vec::new()
+        };
+        let mut row = vec![recording.start() + sample.time];
+        for (k, &index) in bobs.iter().enumerate() {
+            let bob = &pendulum.bobs[index];
+            let theta = unwrapped[k].map_or(bob.theta, |last| unwrap_near(bob.theta, last));
+            unwrapped[k] = Some(theta);
+            row.extend([wrap_angle(theta), theta, bob.omega]);
+            if momenta {
+                row.push(p[index]);
+            }
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+/// The rows where the unwrapped angle in column `column` goes up through
+/// `theta` or a whole turn from it, each value in a straight line between
+/// the rows either side. The wrapped angles are wrapped again after.
+fn crossings(rows: &[Vec<f64>], column: usize, theta: f64, per_bob: usize) -> Vec<Vec<f64>> {
+    let mut crossings = Vec::new();
+    for pair in rows.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let (from, to) = (a[column], b[column]);
+        let level = theta + 2.0 * PI * ((from - theta) / (2.0 * PI)).floor();
+        let level = if level <= from {
+            level + 2.0 * PI
+        } else {
+            level
+        };
+        if to < level {
+            continue;
+        }
+        let s = (level - from) / (to - from);
+        let mut row: Vec<f64> = (a.iter().zip(b)).map(|(x, y)| x + s * (y - x)).collect();
+        for wrapped in (1..row.len()).step_by(per_bob) {
+            row[wrapped] = wrap_angle(row[wrapped + 1]);
+        }
+        crossings.push(row);
+    }
+    crossings
+}
+
+/// The phase-space CSV of `bobs`, by index, all of them if `None`.
+fn csv(
+    recording: &Recording,
+    bobs: Option<Vec<usize>>,
+    options: PhaseSpaceOptions,
+) -> Result<(String, u64), CommandError> {
+    let len = (recording.samples().iter())
+        .map(|s| s.bobs.len())
+        .min()
+        .ok_or_else(|| CommandError::invalid("source", "the recording has no samples"))?;
+    let bobs = bobs.unwrap_or_else(|| (0..len).collect());
+    if let Some(&index) = bobs.iter().find(|&&index| index >= len) {
+        return Err(CommandError::IndexOutOfBounds { index, len });
+    }
+    let mut rows = rows(recording, &bobs, options.momenta);
+    if let Some(section) = options.section {
+        let Some(k) = bobs.iter().position(|&b| b == section.bob) else {
+            return Err(CommandError::invalid(
+                "options.section.bob",
+                format!("must be one of the bobs exported, got {}", section.bob),
+            ));
+        };
+        let per_bob = 3 + usize::from(options.momenta);
+        rows = crossings(&rows, 2 + k * per_bob, section.theta, per_bob);
+    }
+    let mut lines = vec![header(&bobs, options.momenta)];
+    for row in &rows {
+        let values: Vec<String> = row.iter().map(f64::to_string).collect();
+        lines.push(values.join(","));
+    }
+    Ok((lines.join("\n") + "\n", rows.len() as u64))
+}
+
+pub(crate) fn export(
+    path: &Path,
+    input: PhaseInput,
+    bobs: Option<Vec<usize>>,
+    options: PhaseSpaceOptions,
+) -> Result<FileReport, CommandError> {
+    let recording = match input {
+        PhaseInput::Recording(recording) => recording,
+        PhaseInput::File(file) => {
+            let bytes = std::fs::read(&file).map_err(|e| CommandError::io(&file, e))?;
+            Arc::new(read_recording(&bytes)?)
+        }
+    };
+    let (csv, rows) = csv(&recording, bobs, options)?;
+    savefile::write_atomically(path, csv.as_bytes()).map_err(|e| CommandError::io(path, e))?;
+    Ok(FileReport {
+        path: path.display().to_string(),
+        rows,
+        bytes: csv.len() as u64,
+        dropped_rows: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_space_keeps_both_angles_and_crosses_the_section() {
+        let mut data = AppDataInner::default();
+        for bob in &mut data.pendulum.bobs {
+            (bob.theta, bob.omega) = (PI, 0.0);
+        }
+        // the first bob goes round and round
+        data.pendulum.bobs[0].omega = 12.0;
+        data.pendulum.update_coordinates();
+        for _ in 0..2_000 {
+            let dt = data.dt;
+            data.substep(dt, &mut Vec::new());
+        }
+        let PhaseInput::Recording(history) = PhaseSource::History.capture(&data).unwrap() else {
+            panic!("the history is copied");
+        };
+        assert_eq!(
+            history.samples().last().unwrap().bobs[0].theta,
+            data.pendulum.bobs[0].theta
+        );
+
+        let options = PhaseSpaceOptions {
+            momenta: true,
+            section: None,
+        };
+        let (text, rows) = csv(&history, Some(vec![0]), options).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "sim_time_s,theta1_wrapped_rad,theta1_unwrapped_rad,omega1_rad_per_s,p1_kg_m2_per_s"
+        );
+        assert_eq!(rows as usize, history.len());
+        let last: Vec<f64> = lines
+            .last()
+            .unwrap()
+            .split(',')
+            .map(|v| v.parse().unwrap())
+            .collect();
+        let turns = (last[2] - PI) / (2.0 * PI);
+        assert!(turns > 1.0, "{turns}");
+        assert!((-PI..=PI).contains(&last[1]));
+        assert!((wrap_angle(last[2]) - last[1]).abs() < 1e-12);
+        assert_eq!(last[4], data.pendulum.momenta()[0]);
+
+        // a crossing of straight down for every turn the bob made
+        let options = PhaseSpaceOptions {
+            momenta: false,
+            section: Some(PoincareSection::default()),
+        };
+        let (text, rows) = csv(&history, None, options).unwrap();
+        assert_eq!(rows, turns.floor() as u64);
+        for line in text.lines().skip(1) {
+            let row: Vec<f64> = line.split(',').map(|v| v.parse().unwrap()).collect();
+            assert!((wrap_angle(row[2] - PI)).abs() < 1e-9, "{row:?}");
+            assert!(row[3] > 0.0);
+        }
+        assert!(matches!(
+            csv(&history, Some(vec![7]), options),
+            Err(CommandError::IndexOutOfBounds { index: 7, .. })
+        ));
+        assert!(csv(&history, Some(vec![1]), options).is_err());
+    }
+}
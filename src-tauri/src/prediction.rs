@@ -0,0 +1,173 @@
+use pendulum_core::{Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::CommandError;
+use crate::events::{FlipDetector, FlipEvent};
+
+/// Longest horizon a prediction may look ahead, in sim seconds.
+pub(crate) const MAX_PREDICTION_HORIZON: f64 = 60.0;
+/// Most samples a prediction may return.
+pub(crate) const MAX_PREDICTION_SAMPLES: usize = 10_000;
+/// Most substeps a prediction may take, so a tiny dt can't stall a core.
+pub(crate) const MAX_PREDICTION_STEPS: u64 = 1_000_000;
+
+/// How many steps a prediction takes between cancel checks.
+const CANCEL_CHECK_INTERVAL: u64 = 1_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PredictedSample {
+    pub(crate) sim_time: f64,
+    pub(crate) thetas: Vec<f64>,
+    pub(crate) positions: Vec<Coordinate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Prediction {
+    /// The current state first, then one sample at the substep nearest to
+    /// every multiple of the sample interval.
+    pub(crate) samples: Vec<PredictedSample>,
+    /// Flips over the top within the horizon, on the live sim time axis.
+    pub(crate) flips: Vec<FlipEvent>,
+}
+
+fn steps(horizon: f64, dt: f64) -> u64 {
+    (horizon / dt).ceil() as u64
+}
+
+/// Checks the request against the caps for a simulation stepping at `dt`.
+pub(crate) fn validate(horizon: f64, sample_dt: f64, dt: f64) -> Result<(), CommandError> {
+    if !(horizon > 0.0 && horizon <= MAX_PREDICTION_HORIZON) {
+        return Err(CommandError::invalid(
+            "horizon_seconds",
+            format!("must be positive and at most {MAX_PREDICTION_HORIZON} s, got {horizon}"),
+        ));
+    }
+    if !(sample_dt.is_finite() && sample_dt > 0.0) {
+        return Err(CommandError::invalid(
+            "sample_dt",
+            format!("must be positive and finite, got {sample_dt}"),
+        ));
+    }
+    let samples = (horizon / sample_dt).ceil() + 1.0;
+    if samples > MAX_PREDICTION_SAMPLES as f64 {
+        return Err(CommandError::invalid(
+            "sample_dt",
+            format!(
+                "of {sample_dt} s gives {samples} samples, more than the limit of \
+                 {MAX_PREDICTION_SAMPLES}"
+            ),
+        ));
+    }
+    let steps = steps(horizon, dt);
+    if steps > MAX_PREDICTION_STEPS {
+        return Err(CommandError::invalid(
+            "dt",
+            format!("of {dt} needs {steps} steps, more than the limit of {MAX_PREDICTION_STEPS}"),
+        ));
+    }
+    Ok(())
+}
+
+fn sample(pendulum: &Pendulum, sim_time: f64) -> PredictedSample {
+    PredictedSample {
+        sim_time,
+        thetas: pendulum.bobs.iter().map(|b| b.theta).collect(),
+        positions: pendulum.bobs.iter().map(|b| b.coordinate).collect(),
+    }
+}
+
+/// Steps `pendulum`, a copy of the live one at `sim_time`, `horizon` sim
+/// seconds ahead at `dt`. Returns `None` if `cancel` is raised first.
+pub(crate) fn predict(
+    mut pendulum: Pendulum,
+    sim_time: f64,
+    dt: f64,
+    horizon: f64,
+    sample_dt: f64,
+    cancel: &AtomicBool,
+) -> Option<Prediction> {
+    let mut flips = FlipDetector::default();
+    flips.observe(&pendulum, sim_time, dt);
+    let mut prediction = Prediction {
+        samples: vec![sample(&pendulum, sim_time)],
+        flips: Vec::new(),
+    };
+    let mut next_sample = sample_dt;
+    for step in 1..=steps(horizon, dt) {
+        if step % CANCEL_CHECK_INTERVAL == 0 && cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        pendulum.step(dt);
+        let elapsed = step as f64 * dt;
+        let t = sim_time + elapsed;
+        prediction.flips.extend(flips.observe(&pendulum, t, dt));
+        let reach = elapsed + 0.5 * dt;
+        if reach >= next_sample {
+            prediction.samples.push(sample(&pendulum, t));
+            while next_sample <= reach {
+                next_sample += sample_dt;
+            }
+        }
+    }
+    Some(prediction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pendulum_core::Bob;
+
+    #[test]
+    fn samples_follow_the_live_integration() {
+        let pendulum = Pendulum::default();
+        let cancel = AtomicBool::new(false);
+        let prediction = predict(pendulum.clone(), 3.0, 0.01, 2.0, 0.1, &cancel).unwrap();
+        assert_eq!(prediction.samples.len(), 21);
+        assert_eq!(prediction.samples[0].sim_time, 3.0);
+
+        let mut live = pendulum;
+        for _ in 0..100 {
+            live.step(0.01);
+        }
+        let at_one = prediction
+            .samples
+            .iter()
+            .find(|s| (s.sim_time - 4.0).abs() < 1e-6)
+            .unwrap();
+        let thetas: Vec<f64> = live.bobs.iter().map(|b| b.theta).collect();
+        assert_eq!(at_one.thetas, thetas);
+    }
+
+    #[test]
+    fn flips_within_the_horizon_are_reported() {
+        // a single bob spinning steadily through the top
+        let pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, std::f64::consts::PI, 10.0)]);
+        let cancel = AtomicBool::new(false);
+        let prediction = predict(pendulum, 0.0, 1e-3, 3.0, 0.5, &cancel).unwrap();
+        assert!(prediction.flips.len() >= 3);
+        assert!(prediction
+            .flips
+            .windows(2)
+            .all(|w| w[0].sim_time < w[1].sim_time));
+        assert!(prediction.flips.iter().all(|f| f.sim_time <= 3.0));
+    }
+
+    #[test]
+    fn caps_and_cancellation() {
+        assert!(validate(MAX_PREDICTION_HORIZON + 1.0, 0.1, 0.016).is_err());
+        assert!(validate(f64::NAN, 0.1, 0.016).is_err());
+        assert!(validate(10.0, 0.0, 0.016).is_err());
+        assert!(validate(60.0, 1e-3, 0.016).is_err());
+        assert!(validate(60.0, 0.1, 1e-5).is_err());
+        assert!(validate(5.0, 0.05, 0.016).is_ok());
+
+        let cancel = AtomicBool::new(true);
+        assert_eq!(
+            predict(Pendulum::default(), 0.0, 1e-4, 10.0, 1.0, &cancel),
+            None
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use pendulum_core::Coordinate;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+use crate::clock::MAX_TIME_SCALE;
+use crate::error::CommandError;
+use crate::pendulum::BobSpec;
+use crate::state::DEFAULT_DT;
+
+/// A named configuration: the bobs and the world settings they are meant to
+/// run with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Preset {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) bobs: Vec<BobSpec>,
+    #[serde(default = "default_dt")]
+    pub(crate) dt: f64,
+    #[serde(default = "default_time_scale")]
+    pub(crate) time_scale: f64,
+    /// Where to hang the chain; it stays where it is if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pivot: Option<Coordinate>,
+}
+
+fn default_dt() -> f64 {
+    DEFAULT_DT
+}
+
+fn default_time_scale() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PresetInfo {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) bob_count: usize,
+    pub(crate) source: PresetSource,
+}
+
+/// Where a listed preset comes from, and so which command loads it:
+/// `load_preset` or `load_user_preset`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PresetSource {
+    Builtin,
+    User,
+}
+
+/// The built-in presets, in the order they are listed. Adding one only
+/// takes an entry in `presets.json`.
+static PRESETS: LazyLock<Vec<Preset>> = LazyLock::new(|| {
+    serde_json::from_str(include_str!("presets.json")).expect("presets.json is valid")
+});
+
+impl Preset {
+    pub(crate) fn info(&self) -> PresetInfo {
+        PresetInfo {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            bob_count: self.bobs.len(),
+            source: PresetSource::Builtin,
+        }
+    }
+
+    /// Checks the world settings; the bobs are checked by `set_state`.
+    pub(crate) fn validate(&self) -> Result<(), CommandError> {
+        if !(self.dt.is_finite() && self.dt > 0.0) {
+            return Err(CommandError::invalid(
+                "dt",
+                format!("must be positive and finite, got {}", self.dt),
+            ));
+        }
+        if !(self.time_scale.is_finite()
+            && self.time_scale > 0.0
+            && self.time_scale <= MAX_TIME_SCALE)
+        {
+            return Err(CommandError::invalid(
+                "time_scale",
+                format!("must be in (0, {MAX_TIME_SCALE}], got {}", self.time_scale),
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn list() -> Vec<PresetInfo> {
+    PRESETS.iter().map(Preset::info).collect()
+}
+
+pub(crate) fn find(name: &str) -> Result<&'static Preset, CommandError> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| CommandError::not_found("preset named", format!("{name:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppDataInner;
+
+    #[test]
+    fn every_preset_loads_and_runs_without_blowing_up() {
+        assert!(list().len() >= 4);
+        let mut names: Vec<&str> = PRESETS.iter().map(|p| p.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), PRESETS.len());
+
+        for preset in PRESETS.iter() {
+            let mut data = AppDataInner {
+                sim_time: 3.0,
+                ..AppDataInner::default()
+            };
+            data.load_preset(preset).unwrap();
+            assert_eq!(data.sim_time, 0.0);
+            assert_eq!(data.pendulum.n(), preset.bobs.len());
+            assert_eq!(data.dt, preset.dt);
+            for _ in 0..1_000 {
+                data.pendulum.step(data.dt);
+                assert!(data.pendulum.is_finite(), "{} blew up", preset.name);
+            }
+        }
+        assert!(find("no such preset").is_err());
+    }
+}
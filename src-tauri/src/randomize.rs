@@ -0,0 +1,147 @@
+use pendulum_core::{minimum, Pendulum};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+use crate::error::CommandError;
+use crate::pendulum::{BobMeta, BobSpec};
+
+/// Closed interval a value is drawn from, uniformly.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ValueRange {
+    pub(crate) min: f64,
+    pub(crate) max: f64,
+}
+
+impl ValueRange {
+    fn validate(&self, name: &str) -> Result<(), CommandError> {
+        if self.min.is_finite() && self.max.is_finite() && self.min <= self.max {
+            Ok(())
+        } else {
+            Err(CommandError::invalid(
+                format!("ranges.{name}"),
+                format!(
+                    "must be finite with min <= max, got {} to {}",
+                    self.min, self.max
+                ),
+            ))
+        }
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        if self.min == self.max {
+            self.min
+        } else {
+            rng.gen_range(self.min..=self.max)
+        }
+    }
+}
+
+/// What `randomize` draws. Values without a range are left alone, except
+/// angular velocities, which are zeroed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct RandomRanges {
+    pub(crate) theta: ValueRange,
+    pub(crate) omega: Option<ValueRange>,
+    /// Lower bounds must be at least `MIN_MASS` and `MIN_LENGTH_ROD`, so
+    /// every draw is.
+    pub(crate) mass: Option<ValueRange>,
+    pub(crate) length_rod: Option<ValueRange>,
+}
+
+impl Default for RandomRanges {
+    fn default() -> Self {
+        Self {
+            theta: ValueRange { min: -PI, max: PI },
+            omega: None,
+            mass: None,
+            length_rod: None,
+        }
+    }
+}
+
+impl RandomRanges {
+    pub(crate) fn validate(&self) -> Result<(), CommandError> {
+        self.theta.validate("theta")?;
+        if let Some(omega) = self.omega {
+            omega.validate("omega")?;
+        }
+        for (name, range) in [("mass", self.mass), ("length_rod", self.length_rod)] {
+            let Some(range) = range else { continue };
+            range.validate(name)?;
+            let min = minimum(name).expect("masses and lengths have a minimum");
+            if range.min < min {
+                return Err(CommandError::invalid(
+                    format!("ranges.{name}"),
+                    format!("must stay at or above {min}, got a min of {}", range.min),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The bobs of `pendulum`, ids kept, with values drawn from the ranges.
+    /// The same seed and ranges always give the same draws.
+    pub(crate) fn draw(&self, pendulum: &Pendulum, seed: u64) -> Vec<BobSpec> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        pendulum
+            .bobs
+            .iter()
+            .map(|bob| BobSpec {
+                length_rod: self
+                    .length_rod
+                    .map_or(bob.length_rod, |r| r.sample(&mut rng)),
+                mass: self.mass.map_or(bob.mass, |r| r.sample(&mut rng)),
+                theta: self.theta.sample(&mut rng),
+                omega: self.omega.map_or(0.0, |r| r.sample(&mut rng)),
+                id: Some(bob.id),
+                locked: bob.locked,
+                meta: BobMeta::default(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_draws_within_the_ranges() {
+        let pendulum = Pendulum::default();
+        let ranges = RandomRanges {
+            theta: ValueRange { min: 0.0, max: 1.0 },
+            omega: Some(ValueRange {
+                min: -2.0,
+                max: -1.0,
+            }),
+            mass: Some(ValueRange { min: 1.0, max: 1.0 }),
+            length_rod: None,
+        };
+        let drawn = ranges.draw(&pendulum, 48151623);
+        assert_eq!(drawn, ranges.draw(&pendulum, 48151623));
+        assert_ne!(drawn, ranges.draw(&pendulum, 48151624));
+        for (spec, bob) in drawn.iter().zip(&pendulum.bobs) {
+            assert!((0.0..=1.0).contains(&spec.theta));
+            assert!((-2.0..=-1.0).contains(&spec.omega));
+            assert_eq!(spec.mass, 1.0);
+            assert_eq!(spec.length_rod, bob.length_rod);
+            assert_eq!(spec.id, Some(bob.id));
+        }
+
+        let zero_mass = RandomRanges {
+            mass: Some(ValueRange { min: 0.0, max: 5.0 }),
+            ..RandomRanges::default()
+        };
+        assert!(zero_mass.validate().is_err());
+        let backwards = RandomRanges {
+            theta: ValueRange { min: 1.0, max: 0.0 },
+            ..RandomRanges::default()
+        };
+        assert!(backwards.validate().is_err());
+        assert!(RandomRanges::default().validate().is_ok());
+    }
+}
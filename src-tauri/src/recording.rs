@@ -0,0 +1,431 @@
+use pendulum_core::{Bob, Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::error::CommandError;
+use crate::state::GhostBobState;
+
+/// Most samples a recording keeps, a little under an hour at the default dt.
+/// Substeps past the cap are not recorded.
+pub(crate) const MAX_RECORDING_SAMPLES: usize = 200_000;
+/// Most recordings an instance keeps; keeping another drops the oldest, so
+/// a long session of takes can't grow without end. A ghost or replay of a
+/// dropped recording plays on.
+pub(crate) const MAX_KEPT_RECORDINGS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RecordedBob {
+    pub(crate) theta: f64,
+    pub(crate) omega: f64,
+    pub(crate) length_rod: f64,
+    pub(crate) mass: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Sample {
+    /// Sim seconds since the first sample.
+    pub(crate) time: f64,
+    pub(crate) pivot: Coordinate,
+    pub(crate) bobs: Vec<RecordedBob>,
+}
+
+impl Sample {
+    /// The chain as sampled, with ids by position in the chain.
+    pub(crate) fn pendulum(&self) -> Pendulum {
+        let states = self.bobs.iter().map(|b| (b.theta, b.omega));
+        chain(self.pivot, &self.bobs, states)
+    }
+}
+
+/// `bobs` with their angles and angular velocities from `states`, hung from
+/// `pivot`, with ids by position in the chain.
+fn chain(
+    pivot: Coordinate,
+    bobs: &[RecordedBob],
+    states: impl Iterator<Item = (f64, f64)>,
+) -> Pendulum {
+    let bobs = (bobs.iter().zip(states).enumerate())
+        .map(|(i, (bob, (theta, omega)))| Bob {
+            id: i as u64,
+            ..Bob::new(bob.length_rod, bob.mass, theta, omega)
+        })
+        .collect();
+    let mut pendulum = Pendulum {
+        pivot,
+        ..Pendulum::new(bobs)
+    };
+    pendulum.update_coordinates();
+    pendulum
+}
+
+/// What a recording keeps of the chain as it started, besides the samples:
+/// with them, enough to work its energies out again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RecordingConfiguration {
+    /// Per bob, in kilograms; CSV rows don't carry them.
+    pub(crate) masses: Vec<f64>,
+    /// The joint friction, as `Pendulum::damping`.
+    pub(crate) damping: f64,
+}
+
+impl RecordingConfiguration {
+    pub(crate) fn of(pendulum: &Pendulum) -> Self {
+        Self {
+            masses: pendulum.bobs.iter().map(|b| b.mass).collect(),
+            damping: pendulum.damping,
+        }
+    }
+}
+
+/// The cubic Hermite from value and derivative `a` to `b` over `h`
+/// seconds, at fraction `s` of the way.
+pub(crate) fn hermite(s: f64, h: f64, a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (s2, s3) = (s * s, s * s * s);
+    (2.0 * s3 - 3.0 * s2 + 1.0) * a.0
+        + (s3 - 2.0 * s2 + s) * h * a.1
+        + (-2.0 * s3 + 3.0 * s2) * b.0
+        + (s3 - s2) * h * b.1
+}
+
+/// A moment between two samples: the earlier one, the angles and angular
+/// velocities then, and the pivot.
+struct Interpolated<'a> {
+    sample: &'a Sample,
+    thetas: Vec<f64>,
+    omegas: Vec<f64>,
+    pivot: Coordinate,
+}
+
+/// A run captured substep by substep, in memory. Samples carry their own
+/// times and layout, so edits, bob changes and dt changes mid-recording are
+/// all kept as they happened.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Recording {
+    /// Live sim time of the first sample.
+    start: Option<f64>,
+    samples: Vec<Sample>,
+    /// As of the first sample; files from before it was kept have none.
+    configuration: Option<RecordingConfiguration>,
+}
+
+impl Recording {
+    /// A recording of `samples`, read back from a file, whose first was
+    /// taken at live sim time `start`.
+    pub(crate) fn from_samples(
+        start: f64,
+        samples: Vec<Sample>,
+        configuration: Option<RecordingConfiguration>,
+    ) -> Self {
+        Self {
+            start: Some(start),
+            samples,
+            configuration,
+        }
+    }
+
+    pub(crate) fn configuration(&self) -> Option<&RecordingConfiguration> {
+        self.configuration.as_ref()
+    }
+
+    pub(crate) fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// Live sim time of the first sample, on which the recording's own
+    /// sim time axis starts.
+    pub(crate) fn start(&self) -> f64 {
+        self.start.unwrap_or(0.0)
+    }
+
+    /// Adds the pendulum's current state, unless the recording is full.
+    pub(crate) fn push(&mut self, pendulum: &Pendulum, sim_time: f64) {
+        if self.samples.len() >= MAX_RECORDING_SAMPLES {
+            return;
+        }
+        let start = *self.start.get_or_insert(sim_time);
+        (self.configuration).get_or_insert_with(|| RecordingConfiguration::of(pendulum));
+        self.samples.push(Sample {
+            time: sim_time - start,
+            pivot: pendulum.pivot,
+            bobs: pendulum
+                .bobs
+                .iter()
+                .map(|b| RecordedBob {
+                    theta: b.theta,
+                    omega: b.omega,
+                    length_rod: b.length_rod,
+                    mass: b.mass,
+                })
+                .collect(),
+        });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Sim seconds from the first sample to the last.
+    pub(crate) fn duration(&self) -> f64 {
+        self.samples.last().map_or(0.0, |s| s.time)
+    }
+
+    /// The recorded bobs `time` seconds after the first sample, `None`
+    /// outside the recording. Angles between samples follow the cubic
+    /// Hermite through both samples' angles and velocities, as in
+    /// `HERMITE_INTERPOLATION`; across a layout change the earlier sample is
+    /// held. The positions are hung from `pivot`.
+    pub(crate) fn bobs_at(&self, time: f64, pivot: Coordinate) -> Option<Vec<GhostBobState>> {
+        let Interpolated { sample, thetas, .. } = self.interpolate(time)?;
+        let (mut x, mut y) = (pivot.x, pivot.y);
+        Some(
+            sample
+                .bobs
+                .iter()
+                .zip(thetas)
+                .map(|(bob, theta)| {
+                    x += bob.length_rod * theta.sin();
+                    y += bob.length_rod * theta.cos();
+                    GhostBobState {
+                        theta,
+                        position: Coordinate::new(x, y),
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Recording {
+    /// The state `time` seconds after the first sample, as `bobs_at`
+    /// interpolates it; the angular velocities follow the same cubics and
+    /// the pivot goes in a straight line.
+    fn interpolate(&self, time: f64) -> Option<Interpolated<'_>> {
+        if self.samples.is_empty() || !(0.0..=self.duration()).contains(&time) {
+            return None;
+        }
+        let next = self.samples.partition_point(|s| s.time <= time);
+        let a = &self.samples[next.saturating_sub(1)];
+        let held = || Interpolated {
+            sample: a,
+            thetas: a.bobs.iter().map(|b| b.theta).collect(),
+            omegas: a.bobs.iter().map(|b| b.omega).collect(),
+            pivot: a.pivot,
+        };
+        let Some(b) =
+            (self.samples.get(next)).filter(|b| b.bobs.len() == a.bobs.len() && b.time > a.time)
+        else {
+            return Some(held());
+        };
+        let h = b.time - a.time;
+        let s = (time - a.time) / h;
+        let s2 = s * s;
+        let pairs = || a.bobs.iter().zip(&b.bobs);
+        Some(Interpolated {
+            sample: a,
+            thetas: pairs()
+                .map(|(a, b)| hermite(s, h, (a.theta, a.omega), (b.theta, b.omega)))
+                .collect(),
+            omegas: pairs()
+                .map(|(a, b)| {
+                    (6.0 * s2 - 6.0 * s) / h * a.theta
+                        + (3.0 * s2 - 4.0 * s + 1.0) * a.omega
+                        + (-6.0 * s2 + 6.0 * s) / h * b.theta
+                        + (3.0 * s2 - 2.0 * s) * b.omega
+                })
+                .collect(),
+            pivot: Coordinate::new(
+                a.pivot.x + s * (b.pivot.x - a.pivot.x),
+                a.pivot.y + s * (b.pivot.y - a.pivot.y),
+            ),
+        })
+    }
+
+    /// The recorded chain `time` seconds after the first sample, hung from
+    /// the recorded pivot, with ids by position in the chain; `None`
+    /// outside the recording.
+    pub(crate) fn pendulum_at(&self, time: f64) -> Option<Pendulum> {
+        let Interpolated {
+            sample,
+            thetas,
+            omegas,
+            pivot,
+        } = self.interpolate(time)?;
+        Some(chain(pivot, &sample.bobs, thetas.into_iter().zip(omegas)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RecordingSummary {
+    pub(crate) id: u64,
+    pub(crate) samples: usize,
+    /// Sim seconds covered.
+    pub(crate) duration: f64,
+    /// Whether recording stopped early at `MAX_RECORDING_SAMPLES`.
+    pub(crate) truncated: bool,
+}
+
+impl RecordingSummary {
+    pub(crate) fn new(id: u64, recording: &Recording) -> Self {
+        Self {
+            id,
+            samples: recording.len(),
+            duration: recording.duration(),
+            truncated: recording.len() >= MAX_RECORDING_SAMPLES,
+        }
+    }
+}
+
+/// A recording played back alongside the live run, on the live sim clock.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Ghost {
+    recording: Arc<Recording>,
+    /// Live sim time at which playback started.
+    anchor: f64,
+    /// Recording time shown at `anchor`.
+    offset: f64,
+    /// Start over at the end instead of disappearing.
+    looped: bool,
+}
+
+impl Ghost {
+    pub(crate) fn new(recording: Arc<Recording>, sim_time: f64, offset: f64, looped: bool) -> Self {
+        Self {
+            recording,
+            anchor: sim_time,
+            offset,
+            looped,
+        }
+    }
+
+    /// Starts playback over from `offset`, at live sim time `sim_time`.
+    pub(crate) fn rewind(&mut self, sim_time: f64) {
+        self.anchor = sim_time;
+    }
+
+    /// The ghost's bobs at live sim time `sim_time`, `None` while it is
+    /// before the start or, unless looped, past the end of the recording.
+    /// It hangs from `pivot`, the live chain's.
+    pub(crate) fn bobs_at(&self, sim_time: f64, pivot: Coordinate) -> Option<Vec<GhostBobState>> {
+        let mut time = sim_time - self.anchor + self.offset;
+        let duration = self.recording.duration();
+        if self.looped && duration > 0.0 {
+            time = time.rem_euclid(duration);
+        }
+        self.recording.bobs_at(time, pivot)
+    }
+}
+
+pub(crate) fn validate_offset(offset: f64) -> Result<f64, CommandError> {
+    if offset.is_finite() {
+        Ok(offset)
+    } else {
+        Err(CommandError::invalid(
+            "offset",
+            format!("must be finite, got {offset}"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: Coordinate = Coordinate { x: 0.0, y: 0.0 };
+
+    fn record(pendulum: &mut Pendulum, dt: f64, steps: usize) -> Recording {
+        let mut recording = Recording::default();
+        let mut t = 10.0;
+        recording.push(pendulum, t);
+        for _ in 0..steps {
+            pendulum.step(dt);
+            t += dt;
+            recording.push(pendulum, t);
+        }
+        recording
+    }
+
+    /// The default chain on rods a hundred times as long, slow enough for
+    /// 10 ms samples to follow closely.
+    fn slow() -> Pendulum {
+        let mut pendulum = Pendulum::default();
+        for bob in &mut pendulum.bobs {
+            bob.length_rod *= 100.0;
+        }
+        pendulum.update_coordinates();
+        pendulum
+    }
+
+    #[test]
+    fn samples_replay_exactly_and_interpolate_between() {
+        let mut coarse = slow();
+        let recording = record(&mut coarse, 0.01, 200);
+        assert!((recording.duration() - 2.0).abs() < 1e-9);
+
+        // at a sample, the recorded state itself, hanging from the live pivot
+        let mut live = Pendulum {
+            pivot: Coordinate::new(40.0, -25.0),
+            ..slow()
+        };
+        for _ in 0..100 {
+            live.step(0.01);
+        }
+        let at_sample = recording
+            .bobs_at(recording.samples[100].time, live.pivot)
+            .unwrap();
+        for (ghost, bob) in at_sample.iter().zip(&live.bobs) {
+            assert_eq!(ghost.theta, bob.theta);
+            assert!((ghost.position.x - bob.coordinate.x).abs() < 1e-9);
+            assert!((ghost.position.y - bob.coordinate.y).abs() < 1e-9);
+        }
+
+        // halfway between, close to a finer run of the same motion
+        let mut fine = slow();
+        for _ in 0..1005 {
+            fine.step(0.001);
+        }
+        let between = recording.bobs_at(1.005, ORIGIN).unwrap();
+        for (ghost, bob) in between.iter().zip(&fine.bobs) {
+            assert!((ghost.theta - bob.theta).abs() < 0.05);
+        }
+        let replayed = recording.pendulum_at(1.005).unwrap();
+        for (replayed, bob) in replayed.bobs.iter().zip(&fine.bobs) {
+            assert!((replayed.omega - bob.omega).abs() < 0.05, "{replayed:?}");
+            assert_eq!(replayed.mass, bob.mass);
+        }
+        assert!(recording.bobs_at(-0.1, ORIGIN).is_none());
+        assert!(recording.bobs_at(2.1, ORIGIN).is_none());
+    }
+
+    #[test]
+    fn ghost_follows_the_live_clock_and_loops() {
+        let mut pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, 2.0, 0.0)]);
+        let recording = Arc::new(record(&mut pendulum, 0.02, 50));
+        let at = |ghost: &Ghost, t| ghost.bobs_at(t, ORIGIN).map(|b| b[0].theta);
+
+        let once = Ghost::new(recording.clone(), 5.0, 0.25, false);
+        assert_eq!(
+            at(&once, 5.5),
+            recording.bobs_at(0.75, ORIGIN).map(|b| b[0].theta)
+        );
+        assert_eq!(at(&once, 6.0), None);
+
+        let looped = Ghost::new(recording.clone(), 5.0, 0.0, true);
+        let later = at(&looped, 5.0 + 3.0 * recording.duration() + 0.3).unwrap();
+        assert!((later - at(&looped, 5.3).unwrap()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ghost_keeps_the_recorded_layout() {
+        let mut pendulum = Pendulum::default();
+        let mut recording = record(&mut pendulum, 0.01, 10);
+        pendulum.bobs.pop();
+        pendulum.step(0.01);
+        recording.push(&pendulum, 10.11);
+        assert_eq!(recording.bobs_at(0.05, ORIGIN).unwrap().len(), 4);
+        assert_eq!(recording.bobs_at(0.105, ORIGIN).unwrap().len(), 4);
+        let end = recording.duration();
+        assert_eq!(recording.bobs_at(end, ORIGIN).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,251 @@
+use pendulum_core::Pendulum;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::time::Instant;
+
+use crate::error::CommandError;
+use crate::recording::Recording;
+use crate::state::{AppDataInner, BobState, PendulumState, FRAME_SCHEMA_VERSION};
+
+/// Bounds of a replay's speed, as a multiple of the pace it was recorded at.
+pub(crate) const MIN_REPLAY_SPEED: f64 = 0.01;
+pub(crate) const MAX_REPLAY_SPEED: f64 = 100.0;
+
+pub(crate) fn validate_speed(speed: f64) -> Result<f64, CommandError> {
+    if (MIN_REPLAY_SPEED..=MAX_REPLAY_SPEED).contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(CommandError::invalid(
+            "speed",
+            format!("must be between {MIN_REPLAY_SPEED} and {MAX_REPLAY_SPEED}, got {speed}"),
+        ))
+    }
+}
+
+/// What `start_replay` and `seek_replay` return.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ReplayStatus {
+    pub(crate) recording_id: u64,
+    /// Where playback is, on the recording's own sim time axis.
+    pub(crate) sim_time: f64,
+    /// Sim time of the recording's first and last samples.
+    pub(crate) start: f64,
+    pub(crate) end: f64,
+    pub(crate) speed: f64,
+    pub(crate) looped: bool,
+}
+
+/// A recording played back in place of the live run, which stands still
+/// meanwhile and is left exactly as it was. The positions frames are built
+/// from the recording instead, interpolated between its samples, at `speed`
+/// times wall time; the other topics keep describing the live run.
+#[derive(Clone, Debug)]
+pub(crate) struct Replay {
+    recording_id: u64,
+    recording: Arc<Recording>,
+    /// Seconds past the first sample.
+    time: f64,
+    speed: f64,
+    /// Start over at the end instead of holding the last sample.
+    looped: bool,
+    last_tick: Option<Instant>,
+}
+
+impl Replay {
+    pub(crate) fn new(
+        recording_id: u64,
+        recording: Arc<Recording>,
+        speed: f64,
+        looped: bool,
+    ) -> Result<Self, CommandError> {
+        if recording.pendulum_at(0.0).is_none() {
+            return Err(CommandError::invalid(
+                "recording_id",
+                format!("recording {recording_id} has no samples"),
+            ));
+        }
+        Ok(Self {
+            recording_id,
+            recording,
+            time: 0.0,
+            speed: validate_speed(speed)?,
+            looped,
+            last_tick: None,
+        })
+    }
+
+    /// Moves playback on by the wall time since the previous tick, unless
+    /// `running` is false, as while the user has paused. Returns whether it
+    /// went round to the start.
+    pub(crate) fn advance(&mut self, now: Instant, running: bool) -> bool {
+        let elapsed = self
+            .last_tick
+            .replace(now)
+            .map_or(0.0, |last| (now - last).as_secs_f64());
+        if !running {
+            return false;
+        }
+        let duration = self.recording.duration();
+        let time = self.time + elapsed * self.speed;
+        if self.looped && duration > 0.0 && time > duration {
+            self.time = time.rem_euclid(duration);
+            true
+        } else {
+            self.time = time.min(duration);
+            false
+        }
+    }
+
+    /// Jumps to `sim_time` on the recording's axis.
+    pub(crate) fn seek(&mut self, sim_time: f64) -> Result<(), CommandError> {
+        let start = self.recording.start();
+        let end = start + self.recording.duration();
+        if !(start..=end).contains(&sim_time) {
+            return Err(CommandError::invalid(
+                "time",
+                format!("must be between {start} and {end}, the recording's, got {sim_time}"),
+            ));
+        }
+        self.time = sim_time - start;
+        Ok(())
+    }
+
+    pub(crate) fn sim_time(&self) -> f64 {
+        self.recording.start() + self.time
+    }
+
+    /// The recorded chain where playback is.
+    pub(crate) fn pendulum(&self) -> Pendulum {
+        (self.recording.pendulum_at(self.time)).expect("playback stays within the recording")
+    }
+
+    pub(crate) fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            recording_id: self.recording_id,
+            sim_time: self.sim_time(),
+            start: self.recording.start(),
+            end: self.recording.start() + self.recording.duration(),
+            speed: self.speed,
+            looped: self.looped,
+        }
+    }
+
+    /// The positions frame of the moment playback is at, marked `replay`.
+    /// The bobs' ids are their places in the chain, and the live run's
+    /// overlays are left out, since none of them follow the recording.
+    pub(crate) fn frame(&self, data: &AppDataInner, wall_time_ms: f64) -> PendulumState {
+        let pendulum = self.pendulum();
+        let bobs = (pendulum.bobs.iter().zip(pendulum.accelerations()))
+            .map(|(bob, alpha)| BobState {
+                id: bob.id,
+                theta: bob.theta,
+                position: bob.coordinate,
+                mass: bob.mass,
+                length_rod: bob.length_rod,
+                omega: bob.omega,
+                alpha,
+                locked: false,
+                meta: Default::default(),
+            })
+            .collect();
+        PendulumState {
+            schema: FRAME_SCHEMA_VERSION,
+            seq: 0,
+            span: 0.0,
+            sim_time: self.sim_time(),
+            wall_time_ms,
+            time_deficit: data.clock.deficit,
+            elapsed_time: data.clock.elapsed,
+            step_count: data.clock.steps,
+            tick_interval: data.tick_meter.average,
+            paused: data.pause_reason(),
+            slow_motion: None,
+            pixels_per_meter: data.pixels_per_meter,
+            pivot: pendulum.pivot,
+            bobs,
+            analytic: None,
+            dynamics: None,
+            ghost_bobs: None,
+            ensemble: None,
+            comparison: None,
+            wave: None,
+            bounds: None,
+            replay: true,
+            transitions: Vec::new(),
+            structure_changes: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{PauseReason, StructuralOperation};
+    use crate::trajectory::{read_csv, RecordingOptions, TrajectoryFile, TrajectoryRecording};
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn a_csv_replays_at_speed_and_the_live_run_waits() {
+        let path = std::env::temp_dir().join(format!("replay-{}.csv", std::process::id()));
+        let mut data = AppDataInner::default();
+        data.pendulum.pivot = pendulum_core::Coordinate::new(0.5, -1.0);
+        data.pendulum.update_coordinates();
+        data.sim_time = 4.0;
+        let file = TrajectoryFile::create(&path, RecordingOptions::default()).unwrap();
+        data.trajectory_recording =
+            Some(TrajectoryRecording::start(file, &data.pendulum, data.dt).unwrap());
+        let first = data.pendulum.clone();
+        for _ in 0..1000 {
+            let dt = data.dt;
+            data.substep(dt, &mut Vec::new());
+        }
+        data.trajectory_recording.take().unwrap().finish().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let recording = read_csv(&text).unwrap();
+        assert!((recording.start() - (4.0 + data.dt)).abs() < 1e-12);
+        assert!((recording.duration() - 999.0 * data.dt).abs() < 1e-9);
+
+        // lengths and pivot come back from the positions
+        let replayed = recording.pendulum_at(0.0).unwrap();
+        assert_eq!(replayed.pivot, first.pivot);
+        for (a, b) in replayed.bobs.iter().zip(&first.bobs) {
+            assert!((a.length_rod - b.length_rod).abs() < 1e-9);
+        }
+        assert!(read_csv("time,x\n1,2").is_err());
+        let header = text.lines().next().unwrap();
+        assert!(read_csv(&format!("{header}\n1,2")).is_err());
+
+        // wall time drives playback while the live state stands still
+        let live = (data.pendulum.clone(), data.sim_time);
+        let mut replay = Replay::new(7, Arc::new(recording), 0.5, false).unwrap();
+        replay.advance(Instant::now(), true);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        replay.advance(Instant::now(), true);
+        assert!((replay.time - 0.5).abs() < 1e-9, "{}", replay.time);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        replay.advance(Instant::now(), false);
+        assert!((replay.time - 0.5).abs() < 1e-9);
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(!replay.advance(Instant::now(), true));
+        assert_eq!(replay.time, replay.recording.duration());
+
+        data.replay = Some(replay);
+        let frame = data.replay.as_ref().unwrap().frame(&data, 0.0);
+        assert!(frame.replay);
+        assert_eq!(frame.paused, Some(PauseReason::Replaying));
+        assert_eq!(frame.sim_time, data.replay.as_ref().unwrap().sim_time());
+        assert_eq!((data.pendulum.clone(), data.sim_time), live);
+        assert!(StructuralOperation::Replay.starts_over());
+
+        // seeking stays within the recording
+        let replay = data.replay.as_mut().unwrap();
+        let start = replay.status().start;
+        replay.seek(start + 1.0).unwrap();
+        assert!((replay.time - 1.0).abs() < 1e-9);
+        assert!(replay.seek(start - 0.1).is_err());
+        assert!(Replay::new(0, Arc::new(Recording::default()), 1.0, false).is_err());
+        assert!(validate_speed(0.0).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+use pendulum_core::{unwrap_near, Bob, Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::error::CommandError;
+use crate::state::StructuralOperation;
+
+/// Sim seconds between the states kept for `rewind`, to begin with.
+pub(crate) const DEFAULT_REWIND_INTERVAL: f64 = 0.05;
+/// How far back `rewind` can go, to begin with, in sim seconds.
+pub(crate) const DEFAULT_REWIND_SECONDS: f64 = 180.0;
+/// Most states kept however the buffer is configured. Each holds a copy of
+/// every bob, so with the bob cap this bounds the memory the buffer takes.
+pub(crate) const MAX_REWIND_SAMPLES: usize = 100_000;
+/// Kept states at most this many sim seconds apart are interpolated between
+/// for `get_state_at`; further apart, the state is integrated again from the
+/// earlier one, since the motion between them is far from a straight line.
+pub(crate) const MAX_LINEAR_GAP: f64 = 0.01;
+/// Most substeps `get_state_at` takes integrating again; a longer span takes
+/// longer substeps.
+const MAX_REINTEGRATION_STEPS: usize = 10_000;
+
+/// How often the states are kept and for how long.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RewindConfig {
+    pub(crate) interval: f64,
+    pub(crate) seconds: f64,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_REWIND_INTERVAL,
+            seconds: DEFAULT_REWIND_SECONDS,
+        }
+    }
+}
+
+impl RewindConfig {
+    pub(crate) fn validate(&self) -> Result<(), CommandError> {
+        if !(self.interval.is_finite() && self.interval > 0.0) {
+            return Err(CommandError::invalid(
+                "interval",
+                format!("must be positive and finite, got {}", self.interval),
+            ));
+        }
+        if !(self.seconds.is_finite() && self.seconds > 0.0) {
+            return Err(CommandError::invalid(
+                "seconds",
+                format!("must be positive and finite, got {}", self.seconds),
+            ));
+        }
+        let samples = self.seconds / self.interval;
+        if samples > MAX_REWIND_SAMPLES as f64 {
+            return Err(CommandError::invalid(
+                "seconds",
+                format!(
+                    "would keep {} states at this interval, past the {MAX_REWIND_SAMPLES} allowed",
+                    samples.ceil()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        (self.seconds / self.interval).ceil() as usize + 1
+    }
+}
+
+/// The buffer's configuration and how far back it reaches now, as
+/// `get_rewind_buffer` returns them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RewindStatus {
+    pub(crate) config: RewindConfig,
+    /// Sim seconds `rewind` can go back.
+    pub(crate) available: f64,
+}
+
+/// How `get_state_at` came by a state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PastSource {
+    /// A kept state, or the live one, is at that sim time.
+    Kept,
+    /// Linearly between the kept states either side.
+    Interpolated,
+    /// Integrated from the kept state before it.
+    Reintegrated,
+}
+
+/// The chain at a past sim time, as `get_state_at` returns it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PastState {
+    pub(crate) sim_time: f64,
+    pub(crate) thetas: Vec<f64>,
+    pub(crate) omegas: Vec<f64>,
+    pub(crate) positions: Vec<Coordinate>,
+    pub(crate) pivot: Coordinate,
+    pub(crate) energy: f64,
+    pub(crate) source: PastSource,
+}
+
+impl PastState {
+    fn new(pendulum: &Pendulum, sim_time: f64, source: PastSource) -> Self {
+        Self {
+            sim_time,
+            thetas: pendulum.bobs.iter().map(|b| b.theta).collect(),
+            omegas: pendulum.bobs.iter().map(|b| b.omega).collect(),
+            positions: pendulum.bobs.iter().map(|b| b.coordinate).collect(),
+            pivot: pendulum.pivot,
+            energy: pendulum.total_energy(),
+            source,
+        }
+    }
+}
+
+/// The chain at one sim time, as kept for going back to.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RewindSample {
+    pub(crate) sim_time: f64,
+    pub(crate) bobs: Vec<Bob>,
+    pub(crate) pivot: Coordinate,
+}
+
+/// The states of the last `seconds` of sim time, one every `interval`,
+/// oldest first. Its history is the current layout's only: a structural
+/// change empties it, and it remembers which one and when, to say why it
+/// can't go further back.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RewindBuffer {
+    config: RewindConfig,
+    samples: VecDeque<RewindSample>,
+    /// The change the history starts after, and at what sim time.
+    cut: Option<(StructuralOperation, f64)>,
+}
+
+impl RewindBuffer {
+    pub(crate) fn status(&self, sim_time: f64) -> RewindStatus {
+        RewindStatus {
+            config: self.config,
+            available: self.available(sim_time),
+        }
+    }
+
+    /// Takes a new configuration, keeping the newest states that fit.
+    pub(crate) fn configure(&mut self, config: RewindConfig) {
+        self.config = config;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.samples.len() > self.config.capacity() {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Keeps the chain's state at `sim_time` if an interval has passed since
+    /// the last one kept.
+    pub(crate) fn observe(&mut self, pendulum: &Pendulum, sim_time: f64) {
+        let due = self
+            .samples
+            .back()
+            .is_none_or(|last| sim_time - last.sim_time >= self.config.interval - 1e-9);
+        if due {
+            self.samples.push_back(RewindSample {
+                sim_time,
+                bobs: pendulum.bobs.clone(),
+                pivot: pendulum.pivot,
+            });
+            self.trim();
+        }
+    }
+
+    /// The kept states, oldest first.
+    pub(crate) fn samples(&self) -> impl Iterator<Item = &RewindSample> {
+        self.samples.iter()
+    }
+
+    /// Forgets everything before `operation` at `sim_time`.
+    pub(crate) fn cut(&mut self, operation: StructuralOperation, sim_time: f64) {
+        self.samples.clear();
+        self.cut = Some((operation, sim_time));
+    }
+
+    /// Sim seconds back from `sim_time` that are kept.
+    pub(crate) fn available(&self, sim_time: f64) -> f64 {
+        self.samples
+            .front()
+            .map_or(0.0, |oldest| (sim_time - oldest.sim_time).max(0.0))
+    }
+
+    /// Whether `target` is no more than `slack` before the oldest kept
+    /// state, and if not, an error saying what is in the way.
+    fn reaches(&self, target: f64, sim_time: f64, slack: f64) -> Result<(), CommandError> {
+        let oldest = self.samples.front().map_or(sim_time, |s| s.sim_time);
+        if target >= oldest - slack {
+            return Ok(());
+        }
+        let change = (self.cut)
+            .filter(|&(_, at)| at >= oldest - self.config.interval)
+            .map(|(operation, _)| operation.describe());
+        Err(CommandError::OutOfHistory {
+            requested: target,
+            oldest,
+            change,
+        })
+    }
+
+    /// The kept state closest to `seconds` before `sim_time`, with the ones
+    /// after it forgotten, since the run goes on from there. Going further
+    /// back than the buffer reaches is an error saying what is in the way.
+    pub(crate) fn rewind(
+        &mut self,
+        seconds: f64,
+        sim_time: f64,
+    ) -> Result<RewindSample, CommandError> {
+        if !(seconds.is_finite() && seconds >= 0.0) {
+            return Err(CommandError::invalid(
+                "seconds",
+                format!("must be non-negative and finite, got {seconds}"),
+            ));
+        }
+        let target = sim_time - seconds;
+        self.reaches(target, sim_time, self.config.interval / 2.0)?;
+        let index = (self.samples.iter())
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                (a.sim_time - target)
+                    .abs()
+                    .total_cmp(&(b.sim_time - target).abs())
+            })
+            .map(|(i, _)| i)
+            .ok_or_else(|| CommandError::unavailable("Nothing kept to rewind to yet"))?;
+        self.samples.truncate(index + 1);
+        Ok(self.samples[index].clone())
+    }
+
+    /// The chain at `target`, anywhere from the oldest kept state up to
+    /// `sim_time`, where `live` is. Between two kept states it is
+    /// interpolated if they are close, or else integrated again at about
+    /// `dt` from the earlier one, without the eases that may have been
+    /// steering it. Edits that aren't structural changes, like a dragged
+    /// bob, leave no mark here, so a state just before one is worked out as
+    /// if it never came.
+    pub(crate) fn state_at(
+        &self,
+        target: f64,
+        live: &Pendulum,
+        sim_time: f64,
+        dt: f64,
+    ) -> Result<PastState, CommandError> {
+        if !(target.is_finite() && target <= sim_time + 1e-9) {
+            return Err(CommandError::invalid(
+                "sim_time",
+                format!("must be at most the current sim time {sim_time}, got {target}"),
+            ));
+        }
+        self.reaches(target, sim_time, 1e-9)?;
+        let now = RewindSample {
+            sim_time,
+            bobs: live.bobs.clone(),
+            pivot: live.pivot,
+        };
+        let kept: Vec<&RewindSample> = self.samples.iter().chain([&now]).collect();
+        let after = kept.partition_point(|s| s.sim_time < target - 1e-9);
+        let restore = |sample: &RewindSample| Pendulum {
+            bobs: sample.bobs.clone(),
+            pivot: sample.pivot,
+            ..live.clone()
+        };
+        let next = kept[after];
+        if (next.sim_time - target).abs() <= 1e-9 {
+            return Ok(PastState::new(
+                &restore(next),
+                next.sim_time,
+                PastSource::Kept,
+            ));
+        }
+        let previous = kept[after - 1];
+        let gap = next.sim_time - previous.sim_time;
+        let span = target - previous.sim_time;
+        let mut pendulum = restore(previous);
+        if gap <= MAX_LINEAR_GAP && next.bobs.len() == previous.bobs.len() {
+            let s = span / gap;
+            let lerp = |a: f64, b: f64| a + (b - a) * s;
+            for (bob, later) in pendulum.bobs.iter_mut().zip(&next.bobs) {
+                bob.theta = lerp(bob.theta, unwrap_near(later.theta, bob.theta));
+                bob.omega = lerp(bob.omega, later.omega);
+            }
+            pendulum.pivot = Coordinate::new(
+                lerp(previous.pivot.x, next.pivot.x),
+                lerp(previous.pivot.y, next.pivot.y),
+            );
+            pendulum.update_coordinates();
+            return Ok(PastState::new(&pendulum, target, PastSource::Interpolated));
+        }
+        let steps = ((span / dt - 1e-9).ceil() as usize).clamp(1, MAX_REINTEGRATION_STEPS);
+        for _ in 0..steps {
+            pendulum.step(span / steps as f64);
+        }
+        Ok(PastState::new(&pendulum, target, PastSource::Reintegrated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AppDataInner, DEFAULT_DT};
+
+    #[test]
+    fn rewinding_replays_the_same_motion_but_not_across_a_layout_change() {
+        let mut data = AppDataInner::default();
+        let run = |data: &mut AppDataInner, seconds: f64| {
+            let end = data.sim_time + seconds;
+            while data.sim_time < end - 1e-9 {
+                data.substep(DEFAULT_DT, &mut Vec::new());
+            }
+        };
+        run(&mut data, 2.0);
+        let at_one = data
+            .rewind_buffer
+            .clone()
+            .rewind(1.0, data.sim_time)
+            .unwrap();
+        run(&mut data, 1.0);
+        let ahead = data.pendulum.bobs.clone();
+
+        // two seconds back lands on the state kept at one second
+        let landed = data.rewind(2.0).unwrap();
+        assert!((landed - 1.0).abs() < 1e-9);
+        assert_eq!(data.pendulum.bobs, at_one.bobs);
+        assert!(data.energy_reference.is_none());
+        assert!(StructuralOperation::Rewound.starts_over());
+        // and runs on exactly as before, keeping states again as it goes
+        run(&mut data, 2.0);
+        assert_eq!(data.pendulum.bobs, ahead);
+        assert!((data.rewind_buffer.available(data.sim_time) - 3.0).abs() < 1e-6);
+
+        assert!(data.rewind(10.0).is_err());
+        assert!(data.rewind(f64::NAN).is_err());
+        data.remove_bob(0).unwrap();
+        run(&mut data, 0.5);
+        let error = data.rewind(1.0).unwrap_err();
+        assert!(matches!(
+            error,
+            CommandError::OutOfHistory {
+                change: Some("removal of a bob"),
+                ..
+            }
+        ));
+        data.rewind(0.4).unwrap();
+
+        // the memory is capped by configuration
+        let config = RewindConfig {
+            interval: 0.1,
+            seconds: 0.5,
+        };
+        config.validate().unwrap();
+        data.rewind_buffer.configure(config);
+        run(&mut data, 2.0);
+        assert!(data.rewind_buffer.available(data.sim_time) < 0.6);
+        let too_deep = RewindConfig {
+            interval: 1e-4,
+            seconds: 600.0,
+        };
+        assert!(too_deep.validate().is_err());
+    }
+
+    #[test]
+    fn past_states_match_the_run_that_went_through_them() {
+        let mut data = AppDataInner::default();
+        let mut seen = vec![(data.sim_time, data.pendulum.bobs.clone())];
+        for _ in 0..1000 {
+            data.substep(DEFAULT_DT, &mut Vec::new());
+            seen.push((data.sim_time, data.pendulum.bobs.clone()));
+        }
+        let at = |data: &AppDataInner, t| {
+            (data.rewind_buffer).state_at(t, &data.pendulum, data.sim_time, data.dt)
+        };
+        let thetas = |bobs: &[Bob]| bobs.iter().map(|b| b.theta).collect::<Vec<_>>();
+
+        // on a kept state, between two, and now
+        let (t, bobs) = &seen[500];
+        let past = at(&data, *t).unwrap();
+        assert_eq!(
+            (past.source, &past.thetas),
+            (PastSource::Kept, &thetas(bobs))
+        );
+        let (t, bobs) = &seen[515];
+        let past = at(&data, *t).unwrap();
+        assert_eq!(past.source, PastSource::Reintegrated);
+        for (a, b) in past.thetas.iter().zip(thetas(bobs)) {
+            assert!((a - b).abs() < 1e-9, "{a} vs {b}");
+        }
+        assert_eq!(at(&data, data.sim_time).unwrap().source, PastSource::Kept);
+        assert!(at(&data, data.sim_time + 1.0).is_err());
+
+        // kept densely enough, states in between are interpolated
+        let mut data = AppDataInner::default();
+        data.rewind_buffer.configure(RewindConfig {
+            interval: 0.005,
+            seconds: 1.0,
+        });
+        let mut seen = vec![data.pendulum.bobs.clone()];
+        for _ in 0..10 {
+            data.substep(DEFAULT_DT, &mut Vec::new());
+            seen.push(data.pendulum.bobs.clone());
+        }
+        // kept at steps 0, 3, 6 and 9
+        let past = at(&data, 4.5 * DEFAULT_DT).unwrap();
+        assert_eq!(past.source, PastSource::Interpolated);
+        for ((a, b), c) in past.thetas.iter().zip(&seen[3]).zip(&seen[6]) {
+            assert!((a - (b.theta + c.theta) / 2.0).abs() < 1e-12);
+        }
+
+        // too old, and before a structural change
+        data.remove_bob(0).unwrap();
+        assert!(matches!(
+            at(&data, 0.0),
+            Err(CommandError::OutOfHistory {
+                change: Some("removal of a bob"),
+                ..
+            })
+        ));
+        for _ in 0..1000 {
+            data.substep(DEFAULT_DT, &mut Vec::new());
+        }
+        assert!(matches!(
+            at(&data, 0.5),
+            Err(CommandError::OutOfHistory { change: None, .. })
+        ));
+    }
+}
@@ -0,0 +1,400 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::ConfigFile;
+use crate::error::CommandError;
+use crate::events::{AutosaveFailedEvent, AutosaveStage, FlipDetector, SimEvent};
+use crate::instances::Instances;
+use crate::state::AppDataInner;
+use crate::transition::PoseTransition;
+
+/// Version of the files `save_simulation` writes.
+pub(crate) const SAVE_VERSION: u32 = 1;
+/// Name of the autosave file in the app data directory.
+pub(crate) const AUTOSAVE_FILE: &str = "autosave.json";
+/// How often the simulation is autosaved unless set otherwise.
+pub(crate) const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// Range of `set_autosave_interval`, in seconds.
+pub(crate) const MIN_AUTOSAVE_INTERVAL: f64 = 5.0;
+pub(crate) const MAX_AUTOSAVE_INTERVAL: f64 = 3600.0;
+
+/// A simulation as `save_simulation` writes it: the configuration, with the
+/// angles unwrapped and the velocities as they are, and where the run has
+/// got to. The integrator keeps nothing between steps beyond the bobs, so
+/// this is all it takes to carry on exactly where it stopped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SavedSimulation {
+    pub(crate) version: u32,
+    pub(crate) config: ConfigFile,
+    pub(crate) run: SavedRun,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SavedRun {
+    pub(crate) sim_time: f64,
+    /// Ids of the bobs, in chain order, so their colors, labels and locks
+    /// stay theirs.
+    pub(crate) bob_ids: Vec<u64>,
+    pub(crate) next_bob_id: u64,
+    /// The run clock; see `SimClock::elapsed`.
+    pub(crate) elapsed_time: f64,
+    pub(crate) step_count: u64,
+    /// Energy the diagnostics drift is measured from.
+    pub(crate) energy_reference: Option<f64>,
+    /// Where flip detection stands, so a flip under way still counts.
+    pub(crate) flips: FlipDetector,
+    pub(crate) transitions: Vec<PoseTransition>,
+}
+
+/// Reads a saved simulation, refusing files from newer versions. What is
+/// read is checked by `SavedRun::validate` and the config's own checks
+/// before any of it is applied.
+pub(crate) fn parse(text: &str) -> Result<SavedSimulation, CommandError> {
+    let invalid = |reason: String| CommandError::invalid("file", reason);
+    let value: Value =
+        serde_json::from_str(text).map_err(|e| invalid(format!("isn't valid JSON: {e}")))?;
+    let version = (value.get("version").and_then(Value::as_u64))
+        .ok_or_else(|| CommandError::invalid("version", "is missing"))?;
+    let version = u32::try_from(version)
+        .map_err(|_| CommandError::invalid("version", format!("{version} is out of range")))?;
+    if version > SAVE_VERSION {
+        return Err(CommandError::UnsupportedVersion {
+            what: "Save file",
+            version,
+            newest: SAVE_VERSION,
+        });
+    }
+    serde_json::from_value(value).map_err(|e| invalid(format!("isn't a saved simulation: {e}")))
+}
+
+/// Writes `contents` to a file next to `path` and renames it over `path`, so
+/// a crash part way leaves the old file whole rather than half of the new
+/// one. The directory is created if it isn't there yet.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = std::fs::File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&temporary, path)
+}
+
+/// `saved`, from `AppDataInner::save_simulation`, written to `path` in one
+/// go.
+pub(crate) fn save_to(saved: &SavedSimulation, path: &Path) -> Result<(), CommandError> {
+    let json = serde_json::to_vec_pretty(saved).map_err(CommandError::internal)?;
+    write_atomically(path, &json).map_err(|e| CommandError::io(path, e))
+}
+
+/// Saving the primary instance to one file now and then and on exit, and
+/// carrying on from it at launch.
+pub(crate) struct Autosave {
+    path: PathBuf,
+    /// Milliseconds between saves, 0 while periodic saving is off.
+    interval_ms: AtomicU64,
+}
+
+impl Autosave {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            interval_ms: AtomicU64::new(DEFAULT_AUTOSAVE_INTERVAL.as_millis() as u64),
+        }
+    }
+
+    pub(crate) fn interval(&self) -> Option<Duration> {
+        let ms = self.interval_ms.load(Ordering::Relaxed);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+
+    /// Saves every `seconds` from now on, or only on exit for `None`.
+    pub(crate) fn set_interval(&self, seconds: Option<f64>) -> Result<(), CommandError> {
+        let ms = match seconds {
+            None => 0,
+            Some(s) if (MIN_AUTOSAVE_INTERVAL..=MAX_AUTOSAVE_INTERVAL).contains(&s) => {
+                (s * 1000.0).round() as u64
+            }
+            Some(s) => {
+                return Err(CommandError::invalid(
+                    "seconds",
+                    format!(
+                        "must be between {MIN_AUTOSAVE_INTERVAL} and {MAX_AUTOSAVE_INTERVAL}, \
+                         got {s}"
+                    ),
+                ))
+            }
+        };
+        self.interval_ms.store(ms, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub(crate) fn save(&self, saved: &SavedSimulation) -> Result<(), CommandError> {
+        save_to(saved, &self.path)
+    }
+
+    /// Saves the primary instance now, from sync code such as the close and
+    /// exit handlers, which may run on a runtime's thread.
+    pub(crate) fn save_now(&self, instances: &Instances) -> Result<(), CommandError> {
+        let saved = instances.call_blocking(None, |data| Ok(data.save_simulation()))?;
+        self.save(&saved)
+    }
+
+    /// Carries on from the autosave, if there is one. One that can't be
+    /// loaded, being corrupt or from a newer version, leaves `data` as it
+    /// was, with an `AutosaveFailed` event for the next tick.
+    pub(crate) fn restore(&self, data: &mut AppDataInner) {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return,
+            Err(error) => {
+                return data
+                    .pending_events
+                    .push(self.failed(AutosaveStage::Restore, error))
+            }
+        };
+        if let Err(error) = data.load_simulation(&text) {
+            data.pending_events
+                .push(self.failed(AutosaveStage::Restore, error));
+        }
+    }
+
+    fn failed(&self, stage: AutosaveStage, reason: impl ToString) -> SimEvent {
+        SimEvent::AutosaveFailed(AutosaveFailedEvent {
+            path: self.path.display().to_string(),
+            stage,
+            reason: reason.to_string(),
+        })
+    }
+
+    /// Saves the primary instance every interval, for as long as the app
+    /// runs. A save that fails is reported with an `AutosaveFailed` event
+    /// and tried again the next time.
+    pub(crate) async fn run(&self, instances: &Instances) {
+        let mut since_save = Duration::ZERO;
+        let poll = Duration::from_secs(1);
+        loop {
+            tokio::time::sleep(poll).await;
+            since_save += poll;
+            let Some(interval) = self.interval() else {
+                since_save = Duration::ZERO;
+                continue;
+            };
+            if since_save < interval {
+                continue;
+            }
+            since_save = Duration::ZERO;
+            let save = |data: &mut AppDataInner| Ok(data.save_simulation());
+            let Ok(saved) = instances.call(None, save).await else {
+                // the physics task is gone, and the app with it
+                return;
+            };
+            if let Err(error) = self.save(&saved) {
+                let event = self.failed(AutosaveStage::Save, error);
+                let report = |data: &mut AppDataInner| {
+                    data.pending_events.push(event);
+                    Ok(())
+                };
+                let _ = instances.call(None, report).await;
+            }
+        }
+    }
+}
+
+impl SavedRun {
+    pub(crate) fn validate(&self, bobs: usize) -> Result<(), CommandError> {
+        let invalid =
+            |name: &str, reason: String| CommandError::invalid(format!("run.{name}"), reason);
+        if self.bob_ids.len() != bobs {
+            return Err(invalid(
+                "bobIds",
+                format!("has {} ids for {bobs} bobs", self.bob_ids.len()),
+            ));
+        }
+        for (name, value) in [
+            ("simTime", self.sim_time),
+            ("elapsedTime", self.elapsed_time),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(invalid(
+                    name,
+                    format!("must be non-negative and finite, got {value}"),
+                ));
+            }
+        }
+        if let Some(energy) = self.energy_reference.filter(|e| !e.is_finite()) {
+            return Err(invalid(
+                "energyReference",
+                format!("must be finite, got {energy}"),
+            ));
+        }
+        for (i, transition) in self.transitions.iter().enumerate() {
+            if !transition.is_well_formed() || !self.bob_ids.contains(&transition.id) {
+                return Err(invalid(
+                    &format!("transitions[{i}]"),
+                    "isn't a transition of one of the bobs".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventSink, SimEvent};
+    use crate::stream::physics_loop;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit(&self, _: SimEvent) {}
+    }
+
+    fn run(data: &mut AppDataInner, steps: usize) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        for _ in 0..steps {
+            let dt = data.dt;
+            data.substep(dt, &mut events);
+        }
+        events
+    }
+
+    #[test]
+    fn a_loaded_simulation_carries_on_exactly_as_the_saved_one() {
+        let mut original = AppDataInner::default();
+        original.pendulum.bobs[0].omega = 6.0;
+        original.pendulum.damping = 0.01;
+        run(&mut original, 1500);
+        let text = serde_json::to_string(&original.save_simulation()).unwrap();
+
+        let mut loaded = AppDataInner::default();
+        loaded.load_simulation(&text).unwrap();
+        assert_eq!(loaded.sim_time, original.sim_time);
+        let (ours, theirs) = (run(&mut original, 2000), run(&mut loaded, 2000));
+        assert_eq!(ours, theirs);
+        assert_eq!(loaded.pendulum.bobs, original.pendulum.bobs);
+        assert_eq!(loaded.sim_time, original.sim_time);
+        assert_eq!(
+            (loaded.clock.elapsed, loaded.clock.steps),
+            (original.clock.elapsed, original.clock.steps)
+        );
+        assert_eq!(loaded.energy_reference, original.energy_reference);
+
+        // anything wrong refuses the whole file
+        let before = loaded.save_simulation();
+        let mut saved = original.save_simulation();
+        saved.run.bob_ids.pop();
+        let short_ids = serde_json::to_string(&saved).unwrap();
+        let mut newer: Value = serde_json::from_str(&text).unwrap();
+        newer["version"] = Value::from(300);
+        for (bad, expected) in [
+            (&text[..text.len() / 2], "file"),
+            ("{\"version\": 1}", "file"),
+            (short_ids.as_str(), "run.bobIds"),
+        ] {
+            match loaded.load_simulation(bad) {
+                Err(CommandError::InvalidParameter { name, .. }) => assert_eq!(name, expected),
+                other => panic!("expected an invalid {expected}, got {other:?}"),
+            }
+        }
+        assert!(matches!(
+            loaded.load_simulation(&newer.to_string()),
+            Err(CommandError::UnsupportedVersion {
+                what: "Save file",
+                version: 300,
+                ..
+            })
+        ));
+        assert_eq!(loaded.save_simulation(), before);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn autosaves_replace_the_file_whole_and_bad_ones_fall_back() {
+        let dir = std::env::temp_dir().join(format!("autosave-{}", std::process::id()));
+        let autosave = Autosave::new(dir.join(AUTOSAVE_FILE));
+        let (instances, mut physics) = Instances::new(AppDataInner::default());
+        assert!(autosave.set_interval(Some(1.0)).is_err());
+        autosave.set_interval(Some(10.0)).unwrap();
+
+        // nothing saved yet is no trouble
+        let mut fresh = AppDataInner::default();
+        autosave.restore(&mut fresh);
+        assert!(fresh.pending_events.is_empty());
+
+        run(physics.world.primary(), 500);
+        let physics = tokio::spawn(async move { physics_loop(physics, &NoEvents).await });
+        let autosave = std::sync::Arc::new(autosave);
+        let task = {
+            let (instances, autosave) = (instances.clone(), autosave.clone());
+            tokio::spawn(async move { autosave.run(&instances).await })
+        };
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(!autosave.path.exists());
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        task.abort();
+        let save = |data: &mut AppDataInner| Ok(data.save_simulation());
+        let saved = instances.call(None, save).await.unwrap();
+        physics.abort();
+        assert!(autosave.path.exists());
+        let leftovers: Vec<_> = (std::fs::read_dir(&dir).unwrap())
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(leftovers, [AUTOSAVE_FILE]);
+
+        let mut restored = AppDataInner::default();
+        autosave.restore(&mut restored);
+        assert!(restored.pending_events.is_empty());
+        assert_eq!(restored.save_simulation(), saved);
+
+        // a corrupt one leaves the default chain, with a warning
+        std::fs::write(&autosave.path, "{\"version\": 1, \"config\": ").unwrap();
+        let mut fallback = AppDataInner::default();
+        autosave.restore(&mut fallback);
+        assert_eq!(
+            fallback.save_simulation(),
+            AppDataInner::default().save_simulation()
+        );
+        let [SimEvent::AutosaveFailed(event)] = &fallback.pending_events[..] else {
+            panic!(
+                "expected an autosave event, got {:?}",
+                fallback.pending_events
+            );
+        };
+        assert_eq!(event.stage, AutosaveStage::Restore);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn the_exit_save_runs_on_a_runtime_thread() {
+        // as on exit, from sync code on a thread the runtime runs
+        let dir = std::env::temp_dir().join(format!("exit-save-{}", std::process::id()));
+        let autosave = Autosave::new(dir.join(AUTOSAVE_FILE));
+        let (instances, mut physics) = Instances::new(AppDataInner::default());
+        run(physics.world.primary(), 100);
+        let physics = tokio::spawn(async move { physics_loop(physics, &NoEvents).await });
+        autosave.save_now(&instances).unwrap();
+        assert_eq!(
+            instances.world_blocking(|world| Ok(world.ids())).unwrap(),
+            [0]
+        );
+        let saved = (instances.call(None, |data| Ok(data.save_simulation()))).await;
+        physics.abort();
+        let mut restored = AppDataInner::default();
+        autosave.restore(&mut restored);
+        assert!(restored.pending_events.is_empty());
+        assert_eq!(restored.save_simulation(), saved.unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
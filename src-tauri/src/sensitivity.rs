@@ -0,0 +1,182 @@
+use pendulum_core::{wrap_angle, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::error::CommandError;
+
+/// Upper bound on the steps of a single run, so a tiny dt with a long
+/// horizon can't tie up a core for minutes.
+pub(crate) const MAX_SENSITIVITY_STEPS: u64 = 5_000_000;
+
+/// How many steps a run takes between progress updates and cancel checks.
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SensitivityRun {
+    /// The dt that was asked for.
+    pub(crate) requested_dt: f64,
+    /// The dt actually integrated: the requested one nudged so that a whole
+    /// number of steps lands exactly on the horizon.
+    pub(crate) dt: f64,
+    pub(crate) steps: u64,
+    pub(crate) final_thetas: Vec<f64>,
+    pub(crate) final_omegas: Vec<f64>,
+    /// (E_end - E_start) / |E_start|.
+    pub(crate) energy_drift: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SensitivityReport {
+    /// One run per requested dt, sorted by ascending dt.
+    pub(crate) runs: Vec<SensitivityRun>,
+    /// `divergences[i][j]` is the largest wrapped joint-angle difference, in
+    /// rad, between the final states of runs `i` and `j`.
+    pub(crate) divergences: Vec<Vec<f64>>,
+    /// The smallest dt, which every other run is judged against.
+    pub(crate) reference_dt: f64,
+    /// The largest requested dt whose final state is within the tolerance of
+    /// the reference.
+    pub(crate) largest_trusted_dt: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SensitivityProgress {
+    pub(crate) completed_steps: u64,
+    pub(crate) total_steps: u64,
+}
+
+fn run_steps(dt: f64, horizon: f64) -> u64 {
+    ((horizon / dt).round() as u64).max(1)
+}
+
+/// Checks the request and returns the total number of steps it will take.
+pub(crate) fn validate(dts: &[f64], horizon: f64, tolerance: f64) -> Result<u64, CommandError> {
+    if dts.is_empty() {
+        return Err(CommandError::invalid("dts", "must hold at least one dt"));
+    }
+    if !(horizon.is_finite() && horizon > 0.0) {
+        return Err(CommandError::invalid(
+            "horizon",
+            format!("must be positive and finite, got {horizon}"),
+        ));
+    }
+    if !(tolerance.is_finite() && tolerance >= 0.0) {
+        return Err(CommandError::invalid(
+            "tolerance",
+            format!("must be non-negative and finite, got {tolerance}"),
+        ));
+    }
+    let mut total = 0;
+    for (i, &dt) in dts.iter().enumerate() {
+        let name = format!("dts[{i}]");
+        if !(dt.is_finite() && dt > 0.0) {
+            return Err(CommandError::invalid(
+                name,
+                format!("must be positive and finite, got {dt}"),
+            ));
+        }
+        let steps = run_steps(dt, horizon);
+        if steps > MAX_SENSITIVITY_STEPS {
+            return Err(CommandError::invalid(
+                name,
+                format!(
+                    "of {dt} needs {steps} steps, more than the limit of {MAX_SENSITIVITY_STEPS}"
+                ),
+            ));
+        }
+        total += steps;
+    }
+    Ok(total)
+}
+
+fn max_angle_difference(a: &SensitivityRun, b: &SensitivityRun) -> f64 {
+    a.final_thetas
+        .iter()
+        .zip(&b.final_thetas)
+        .map(|(x, y)| wrap_angle(x - y).abs())
+        .fold(0.0, f64::max)
+}
+
+/// Integrates a copy of `pendulum` to `horizon` once per dt, each on its own
+/// thread. Returns `None` if `cancel` is raised before every run finishes.
+/// `progress` is called from the worker threads as steps complete.
+pub(crate) fn timestep_sensitivity(
+    pendulum: &Pendulum,
+    dts: &[f64],
+    horizon: f64,
+    tolerance: f64,
+    cancel: &AtomicBool,
+    progress: impl Fn(SensitivityProgress) + Sync,
+) -> Option<SensitivityReport> {
+    let mut dts = dts.to_vec();
+    dts.sort_by(f64::total_cmp);
+    let total_steps = dts.iter().map(|&dt| run_steps(dt, horizon)).sum();
+    let completed = AtomicU64::new(0);
+    let start_energy = pendulum.total_energy();
+
+    let runs: Vec<Option<SensitivityRun>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = dts
+            .iter()
+            .map(|&requested_dt| {
+                let completed = &completed;
+                let progress = &progress;
+                let mut copy = pendulum.clone();
+                scope.spawn(move || {
+                    let steps = run_steps(requested_dt, horizon);
+                    let dt = horizon / steps as f64;
+                    let mut reported = 0;
+                    for step in 1..=steps {
+                        copy.step(dt);
+                        if step % PROGRESS_INTERVAL == 0 || step == steps {
+                            if cancel.load(Ordering::Relaxed) {
+                                return None;
+                            }
+                            let done = step - reported;
+                            reported = step;
+                            let completed_steps =
+                                completed.fetch_add(done, Ordering::Relaxed) + done;
+                            progress(SensitivityProgress {
+                                completed_steps,
+                                total_steps,
+                            });
+                        }
+                    }
+                    Some(SensitivityRun {
+                        requested_dt,
+                        dt,
+                        steps,
+                        final_thetas: copy.bobs.iter().map(|b| b.theta).collect(),
+                        final_omegas: copy.bobs.iter().map(|b| b.omega).collect(),
+                        energy_drift: (copy.total_energy() - start_energy) / start_energy.abs(),
+                    })
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("sensitivity run panicked"))
+            .collect()
+    });
+    let runs: Vec<SensitivityRun> = runs.into_iter().collect::<Option<_>>()?;
+
+    let divergences: Vec<Vec<f64>> = runs
+        .iter()
+        .map(|a| runs.iter().map(|b| max_angle_difference(a, b)).collect())
+        .collect();
+    let largest_trusted_dt = runs
+        .iter()
+        .zip(&divergences[0])
+        .rev()
+        .find(|(_, &d)| d <= tolerance)
+        .map(|(run, _)| run.requested_dt);
+
+    Some(SensitivityReport {
+        reference_dt: runs[0].requested_dt,
+        runs,
+        divergences,
+        largest_trusted_dt,
+    })
+}
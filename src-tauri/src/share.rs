@@ -0,0 +1,254 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use pendulum_core::Coordinate;
+
+use crate::error::CommandError;
+use crate::pendulum::{BobMeta, BobSpec};
+use crate::presets::Preset;
+
+/// The layout `export_state_string` writes. `import_state_string` reads it
+/// and every older one, and turns newer ones down.
+pub(crate) const STATE_STRING_VERSION: u8 = 1;
+
+const LOCKED: u8 = 1;
+const HAS_COLOR: u8 = 2;
+const HAS_LABEL: u8 = 4;
+/// Bytes a bob takes at the least: four `f64`s and the flags.
+const MIN_BOB_LEN: usize = 4 * 8 + 1;
+
+/// FNV-1a, to catch a string mangled on its way through a chat.
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn corrupt(reason: impl std::fmt::Display) -> CommandError {
+    CommandError::invalid("s", format!("is not a state string: {reason}"))
+}
+
+/// Writes a chain and its world settings, a preset without the name, as
+/// URL-safe base64 of: the version byte; `dt`, `time_scale` and the pivot's
+/// x and y as little-endian `f64`s; the bob count as a `u32`; per bob its
+/// length, mass, θ and ω as `f64`s, a flags byte, and the color and label
+/// it has, each a `u16` byte count and UTF-8; and last, whatever the
+/// version, a `u32` checksum of everything before it. Angles are in the
+/// canonical convention and radians, so strings read the same whatever the
+/// display settings are.
+pub(crate) fn encode(setup: &Preset) -> String {
+    let mut bytes = vec![STATE_STRING_VERSION];
+    let pivot = setup.pivot.unwrap_or_default();
+    for value in [setup.dt, setup.time_scale, pivot.x, pivot.y] {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend((setup.bobs.len() as u32).to_le_bytes());
+    for spec in &setup.bobs {
+        for value in [spec.length_rod, spec.mass, spec.theta, spec.omega] {
+            bytes.extend(value.to_le_bytes());
+        }
+        let meta = &spec.meta;
+        let flags = [
+            (spec.locked, LOCKED),
+            (meta.color.is_some(), HAS_COLOR),
+            (meta.label.is_some(), HAS_LABEL),
+        ];
+        bytes.push(flags.iter().filter(|(set, _)| *set).map(|(_, f)| f).sum());
+        for text in [&meta.color, &meta.label].into_iter().flatten() {
+            bytes.extend((text.len() as u16).to_le_bytes());
+            bytes.extend(text.as_bytes());
+        }
+    }
+    bytes.extend(checksum(&bytes).to_le_bytes());
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CommandError> {
+        if self.bytes.len() < len {
+            return Err(corrupt("it ends early"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CommandError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn f64(&mut self) -> Result<f64, CommandError> {
+        self.array().map(f64::from_le_bytes)
+    }
+
+    fn text(&mut self) -> Result<String, CommandError> {
+        let len = u16::from_le_bytes(self.array()?);
+        let bytes = self.take(len.into())?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| corrupt("a color or label isn't UTF-8"))
+    }
+}
+
+/// Reads a string `encode` wrote back into the setup, checking that it is
+/// whole and of a version this app knows. The values themselves are only
+/// checked when the setup is applied, as for a preset.
+pub(crate) fn decode(s: &str) -> Result<Preset, CommandError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(s.trim())
+        .map_err(|e| corrupt(format!("bad base64, {e}")))?;
+    let Some((body, sum)) = bytes.split_last_chunk::<4>() else {
+        return Err(corrupt("it is too short"));
+    };
+    if checksum(body) != u32::from_le_bytes(*sum) {
+        return Err(corrupt(
+            "its checksum doesn't match, so it was cut short or changed",
+        ));
+    }
+    let mut reader = Reader { bytes: body };
+    let [version] = reader.array()?;
+    match version {
+        0 => return Err(corrupt("version 0 was never written")),
+        STATE_STRING_VERSION => {}
+        _ => {
+            return Err(CommandError::UnsupportedVersion {
+                what: "State string",
+                version: version.into(),
+                newest: STATE_STRING_VERSION.into(),
+            })
+        }
+    }
+    let dt = reader.f64()?;
+    let time_scale = reader.f64()?;
+    let pivot = Coordinate::new(reader.f64()?, reader.f64()?);
+    let count = u32::from_le_bytes(reader.array()?) as usize;
+    if count > reader.bytes.len() / MIN_BOB_LEN {
+        return Err(corrupt(format!("{count} bobs can't fit in what is left")));
+    }
+    let mut bobs = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (length_rod, mass) = (reader.f64()?, reader.f64()?);
+        let (theta, omega) = (reader.f64()?, reader.f64()?);
+        let [flags] = reader.array()?;
+        if flags & !(LOCKED | HAS_COLOR | HAS_LABEL) != 0 {
+            return Err(corrupt(format!("unknown bob flags {flags:#04x}")));
+        }
+        let color = (flags & HAS_COLOR != 0)
+            .then(|| reader.text())
+            .transpose()?;
+        let label = (flags & HAS_LABEL != 0)
+            .then(|| reader.text())
+            .transpose()?;
+        bobs.push(BobSpec {
+            length_rod,
+            mass,
+            theta,
+            omega,
+            id: None,
+            locked: flags & LOCKED != 0,
+            meta: BobMeta { color, label },
+        });
+    }
+    if !reader.bytes.is_empty() {
+        return Err(corrupt(format!("{} bytes too many", reader.bytes.len())));
+    }
+    Ok(Preset {
+        name: String::new(),
+        description: String::new(),
+        bobs,
+        dt,
+        time_scale,
+        pivot: Some(pivot),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Edit;
+    use crate::state::AppDataInner;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn random_setup(rng: &mut ChaCha8Rng) -> Preset {
+        let text = |rng: &mut ChaCha8Rng| {
+            let len = rng.gen_range(0..12);
+            (0..len)
+                .map(|_| ['a', 'Z', '#', '0', ' ', 'é', '振', '🎨'][rng.gen_range(0..8)])
+                .collect::<String>()
+        };
+        let bobs = (0..rng.gen_range(1..12))
+            .map(|_| BobSpec {
+                length_rod: rng.gen_range(0.01..5.0),
+                mass: rng.gen_range(0.01..10.0),
+                theta: rng.gen_range(-20.0..20.0),
+                omega: rng.gen_range(-50.0..50.0),
+                id: None,
+                locked: rng.gen_bool(0.2),
+                meta: BobMeta {
+                    color: rng.gen_bool(0.5).then(|| text(rng)),
+                    label: rng.gen_bool(0.5).then(|| text(rng)),
+                },
+            })
+            .collect();
+        Preset {
+            name: String::new(),
+            description: String::new(),
+            bobs,
+            dt: rng.gen_range(1e-4..0.02),
+            time_scale: rng.gen_range(0.1..4.0),
+            pivot: Some(Coordinate::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+            )),
+        }
+    }
+
+    #[test]
+    fn state_strings_reproduce_the_setup_exactly_and_refuse_damage() {
+        let mut rng = ChaCha8Rng::seed_from_u64(169);
+        let mut data = AppDataInner::default();
+        for _ in 0..200 {
+            let setup = random_setup(&mut rng);
+            let s = encode(&setup);
+            assert!(s
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            assert_eq!(decode(&s).unwrap(), setup);
+            // through the app and back out, to the bit
+            data.import_state_string(&s).unwrap();
+            assert_eq!(data.export_state_string(), s);
+        }
+        assert_eq!(data.history.summary().undo[0], Edit::ImportState);
+
+        let s = data.export_state_string();
+        for len in 0..s.len() {
+            assert!(decode(&s[..len]).is_err(), "cut to {len}");
+        }
+        let mut bytes = URL_SAFE_NO_PAD.decode(&s).unwrap();
+        bytes[20] ^= 0x10;
+        assert!(decode(&URL_SAFE_NO_PAD.encode(&bytes)).is_err());
+        assert!(decode("not a state string!").is_err());
+
+        // a later version, whole, is turned down by version
+        let mut future = URL_SAFE_NO_PAD.decode(&s).unwrap();
+        future.truncate(future.len() - 4);
+        future[0] = STATE_STRING_VERSION + 1;
+        future.extend(checksum(&future).to_le_bytes());
+        assert_eq!(
+            decode(&URL_SAFE_NO_PAD.encode(&future)).unwrap_err(),
+            CommandError::UnsupportedVersion {
+                what: "State string",
+                version: (STATE_STRING_VERSION + 1).into(),
+                newest: STATE_STRING_VERSION.into(),
+            }
+        );
+
+        // a bad value leaves the simulation as it was
+        let before = data.export_state_string();
+        let mut bad = decode(&before).unwrap();
+        bad.bobs[0].mass = -1.0;
+        assert!(data.import_state_string(&encode(&bad)).is_err());
+        assert_eq!(data.export_state_string(), before);
+    }
+}
@@ -0,0 +1,226 @@
+use pendulum_core::Pendulum;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::error::CommandError;
+
+/// When slow motion engages around a flip, and how it eases in and out.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct SlowMotionConfig {
+    /// The bob whose flips are slowed down.
+    pub(crate) bob: usize,
+    /// Time scale multiplier once fully ramped down.
+    pub(crate) factor: f64,
+    /// Engages when the bob rises above its joint past this height, as a
+    /// fraction of its rod (1 is straight up)...
+    pub(crate) engage_height: f64,
+    /// ...while turning toward the top at least this fast, in rad/s.
+    pub(crate) min_omega: f64,
+    /// Releases once the bob is back below this height. The gap to
+    /// `engage_height` keeps it from strobing near the threshold.
+    pub(crate) release_height: f64,
+    /// Wall seconds from full speed down to `factor`.
+    pub(crate) ramp_in: f64,
+    /// Wall seconds from `factor` back up to full speed.
+    pub(crate) ramp_out: f64,
+}
+
+impl Default for SlowMotionConfig {
+    fn default() -> Self {
+        Self {
+            bob: 1,
+            factor: 0.2,
+            engage_height: 0.7,
+            min_omega: 1.0,
+            release_height: 0.3,
+            ramp_in: 0.3,
+            ramp_out: 0.8,
+        }
+    }
+}
+
+impl SlowMotionConfig {
+    pub(crate) fn validate(&self) -> Result<(), CommandError> {
+        if !(self.factor > 0.0 && self.factor <= 1.0) {
+            return Err(CommandError::invalid(
+                "factor",
+                format!("must be in (0, 1], got {}", self.factor),
+            ));
+        }
+        if !(-1.0 <= self.release_height
+            && self.release_height < self.engage_height
+            && self.engage_height <= 1.0)
+        {
+            return Err(CommandError::invalid(
+                "release_height",
+                format!(
+                    "and engage_height must satisfy -1 <= release < engage <= 1, got {} and {}",
+                    self.release_height, self.engage_height
+                ),
+            ));
+        }
+        for (name, value) in [
+            ("min_omega", self.min_omega),
+            ("ramp_in", self.ramp_in),
+            ("ramp_out", self.ramp_out),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(CommandError::invalid(
+                    name,
+                    format!("must be non-negative and finite, got {value}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Slows the simulation down while a flip of the watched bob is under way,
+/// on top of the configured time scale.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SlowMotion {
+    config: SlowMotionConfig,
+    engaged: bool,
+    /// Multiplier in force, between `factor` and 1.
+    level: f64,
+    last_update: Option<Instant>,
+}
+
+impl SlowMotion {
+    pub(crate) fn new(config: SlowMotionConfig) -> Self {
+        Self {
+            config,
+            engaged: false,
+            level: 1.0,
+            last_update: None,
+        }
+    }
+
+    fn imminent(&self, pendulum: &Pendulum) -> Option<bool> {
+        let bob = pendulum.bobs.get(self.config.bob)?;
+        let height = bob.theta.cos();
+        if self.engaged {
+            Some(height >= self.config.release_height)
+        } else {
+            // d(cos θ)/dt = -ω sin θ
+            let rising = -bob.omega * bob.theta.sin();
+            Some(
+                height >= self.config.engage_height
+                    && rising > 0.0
+                    && bob.omega.abs() >= self.config.min_omega,
+            )
+        }
+    }
+
+    /// Checks the state at wall time `now` and moves the multiplier toward
+    /// its target by the wall time passed since the previous call. Returns
+    /// the multiplier for the coming tick.
+    pub(crate) fn update(&mut self, now: Instant, pendulum: &Pendulum) -> f64 {
+        self.engaged = self.imminent(pendulum).unwrap_or(false);
+        let elapsed = self
+            .last_update
+            .replace(now)
+            .map_or(0.0, |last| (now - last).as_secs_f64());
+        let (target, ramp) = if self.engaged {
+            (self.config.factor, self.config.ramp_in)
+        } else {
+            (1.0, self.config.ramp_out)
+        };
+        let max_change = if ramp > 0.0 {
+            elapsed * (1.0 - self.config.factor) / ramp
+        } else {
+            f64::INFINITY
+        };
+        self.level += (target - self.level).clamp(-max_change, max_change);
+        self.level
+    }
+
+    /// Forgets the previous update, so time spent paused doesn't count
+    /// toward a ramp.
+    pub(crate) fn pause(&mut self) {
+        self.last_update = None;
+    }
+
+    /// The multiplier, while slow motion is engaged or still ramping back.
+    pub(crate) fn active(&self) -> Option<f64> {
+        (self.engaged || self.level < 1.0).then_some(self.level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pendulum_core::Bob;
+    use std::f64::consts::PI;
+    use std::time::Duration;
+
+    #[test]
+    fn engages_once_per_flip_despite_jitter() {
+        let config = SlowMotionConfig {
+            bob: 1,
+            ramp_in: 0.1,
+            ramp_out: 0.2,
+            ..SlowMotionConfig::default()
+        };
+        let mut slow = SlowMotion::new(config.clone());
+        let mut pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, PI, 0.0); 2]);
+        // the second bob swings up toward the top, hovering around the
+        // engage height on the way, goes over, and comes down with the
+        // height hovering around the release height
+        let heights = [
+            -1.0, 0.0, 0.5, 0.69, 0.71, 0.69, 0.72, 0.9, 1.0, 0.9, 0.6, 0.31, 0.29, 0.31, 0.2,
+            -0.5, -1.0,
+        ];
+        let start = Instant::now();
+        let mut changes = Vec::new();
+        let mut levels = Vec::new();
+        for (i, &height) in heights.iter().enumerate() {
+            let now_past_top = i > 8;
+            let angle = f64::acos(height);
+            // rising from the left side, falling on the right
+            let bob = &mut pendulum.bobs[1];
+            bob.theta = if now_past_top { angle } else { -angle };
+            bob.omega = 3.0;
+            let was = slow.engaged;
+            levels.push(slow.update(start + Duration::from_millis(50 * i as u64), &pendulum));
+            if slow.engaged != was {
+                changes.push((i, slow.engaged));
+            }
+        }
+        assert_eq!(changes, vec![(4, true), (12, false)]);
+        // ramped all the way down within two ticks of 50 ms, then back up
+        // over four
+        assert!((levels[6] - config.factor).abs() < 1e-12);
+        assert!(levels[13] > config.factor && levels[13] < 1.0);
+        assert_eq!(levels[16], 1.0);
+        assert_eq!(slow.active(), None);
+
+        // too slow to make it over: no slow motion
+        let mut slow = SlowMotion::new(config);
+        pendulum.bobs[1].theta = -f64::acos(0.9);
+        pendulum.bobs[1].omega = 0.5;
+        slow.update(start, &pendulum);
+        assert_eq!(slow.active(), None);
+    }
+
+    #[test]
+    fn bad_configs_are_rejected() {
+        let bad = [
+            SlowMotionConfig {
+                factor: 0.0,
+                ..SlowMotionConfig::default()
+            },
+            SlowMotionConfig {
+                release_height: 0.8,
+                ..SlowMotionConfig::default()
+            },
+            SlowMotionConfig {
+                ramp_in: f64::NAN,
+                ..SlowMotionConfig::default()
+            },
+        ];
+        assert!(bad.iter().all(|c| c.validate().is_err()));
+        assert!(SlowMotionConfig::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+use crate::history::Configuration;
+use crate::transition::PoseTransition;
+
+/// Most snapshots kept at once; saving another takes deleting one first.
+pub(crate) const MAX_SNAPSHOTS: usize = 32;
+/// Longest snapshot name, in characters.
+pub(crate) const MAX_SNAPSHOT_NAME_LEN: usize = 100;
+
+/// The simulation at one instant, as `save_snapshot` keeps it: what an undo
+/// puts back, plus sim time and the eases in progress. The integrator
+/// carries nothing else from one step to the next, so running on from a
+/// restored snapshot is running on from the moment it was saved.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Snapshot {
+    pub(crate) configuration: Configuration,
+    pub(crate) sim_time: f64,
+    pub(crate) transitions: Vec<PoseTransition>,
+    /// Wall-clock milliseconds since `AppDataInner::epoch` when it was saved.
+    pub(crate) saved_at_ms: f64,
+}
+
+/// A snapshot as `list_snapshots` describes it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SnapshotInfo {
+    pub(crate) name: String,
+    pub(crate) sim_time: f64,
+    pub(crate) saved_at_ms: f64,
+    pub(crate) bob_count: usize,
+}
+
+/// The named snapshots, in memory only, oldest first.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Snapshots {
+    entries: Vec<(String, Snapshot)>,
+}
+
+impl Snapshots {
+    /// Keeps `snapshot` under `name`, replacing one already there, and
+    /// returns whether one was.
+    pub(crate) fn save(&mut self, name: &str, snapshot: Snapshot) -> Result<bool, CommandError> {
+        let len = name.chars().count();
+        if !(1..=MAX_SNAPSHOT_NAME_LEN).contains(&len) {
+            return Err(CommandError::invalid(
+                "name",
+                format!("must be 1 to {MAX_SNAPSHOT_NAME_LEN} characters, got {len}"),
+            ));
+        }
+        let replaced = self.remove(name);
+        if self.entries.len() >= MAX_SNAPSHOTS {
+            return Err(CommandError::unavailable(format!(
+                "At most {MAX_SNAPSHOTS} snapshots are kept; delete one first"
+            )));
+        }
+        self.entries.push((name.to_owned(), snapshot));
+        Ok(replaced)
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Result<&Snapshot, CommandError> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, snapshot)| snapshot)
+            .ok_or_else(|| CommandError::not_found("snapshot named", format!("{name:?}")))
+    }
+
+    /// Forgets a snapshot, returning whether it existed.
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|(n, _)| n != name);
+        self.entries.len() != before
+    }
+
+    pub(crate) fn list(&self) -> Vec<SnapshotInfo> {
+        self.entries
+            .iter()
+            .map(|(name, snapshot)| SnapshotInfo {
+                name: name.clone(),
+                sim_time: snapshot.sim_time,
+                saved_at_ms: snapshot.saved_at_ms,
+                bob_count: snapshot.configuration.bobs.len(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AppDataInner, StructuralOperation, DEFAULT_DT};
+
+    #[test]
+    fn a_restored_snapshot_runs_on_as_if_never_left() {
+        let mut data = AppDataInner::default();
+        let run = |data: &mut AppDataInner, steps| {
+            for _ in 0..steps {
+                data.substep(DEFAULT_DT, &mut Vec::new());
+            }
+        };
+        run(&mut data, 50);
+        assert!(!data.save_snapshot("before", 1.0).unwrap());
+        let saved_at = data.sim_time;
+        run(&mut data, 50);
+        let expected = (data.pendulum.bobs.clone(), data.sim_time);
+
+        // something destructive, then back
+        data.remove_bob(0).unwrap();
+        data.time_scale = 4.0;
+        run(&mut data, 20);
+        data.restore_snapshot("before").unwrap();
+        assert_eq!(data.sim_time, saved_at);
+        assert_eq!(data.time_scale, 1.0);
+        assert!(data.energy_reference.is_none());
+        let frame = data.frame(0, 0.0);
+        assert_eq!(frame.bobs.len(), 4);
+        run(&mut data, 50);
+        assert_eq!((data.pendulum.bobs.clone(), data.sim_time), expected);
+        // and the snapshot stays for another go
+        data.restore_snapshot("before").unwrap();
+        assert_eq!(data.sim_time, saved_at);
+        assert!(StructuralOperation::Restored.starts_over());
+
+        assert!(data.save_snapshot("before", 2.0).unwrap());
+        let list = data.snapshots.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!((list[0].saved_at_ms, list[0].bob_count), (2.0, 4));
+        assert!(data.snapshots.remove("before"));
+        assert!(!data.snapshots.remove("before"));
+        assert!(data.restore_snapshot("before").is_err());
+
+        assert!(data.save_snapshot("", 0.0).is_err());
+        for i in 0..MAX_SNAPSHOTS {
+            data.save_snapshot(&i.to_string(), 0.0).unwrap();
+        }
+        assert!(data.save_snapshot("one more", 0.0).is_err());
+        // replacing one is still fine when full
+        assert!(data.save_snapshot("0", 0.0).unwrap());
+    }
+}
@@ -0,0 +1,551 @@
+use pendulum_core::GRAVITATIONAL_ACCELERATION;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use toml::{Table, Value};
+
+use crate::comparison;
+use crate::error::CommandError;
+use crate::instances::{World, MAX_TOTAL_BOBS};
+use crate::pendulum::BobSpec;
+use crate::presets::Preset;
+use crate::rewind::RewindConfig;
+use crate::state::{AppDataInner, SetStateOptions, DEFAULT_DT, DEFAULT_MAX_BOBS};
+use crate::stream::{validate_fps, DEFAULT_STREAM_FPS};
+
+/// Name of the startup configuration file in the app's config directory.
+pub(crate) const STARTUP_CONFIG_FILE: &str = "config.toml";
+
+const SECTIONS: &[&str] = &["world", "limits", "stream", "history", "bobs"];
+const WORLD_KEYS: &[&str] = &["dt", "time_scale", "gravity", "damping"];
+const LIMITS_KEYS: &[&str] = &["max_bobs"];
+const STREAM_KEYS: &[&str] = &["fps"];
+const HISTORY_KEYS: &[&str] = &["interval", "seconds"];
+const BOB_KEYS: &[&str] = &["length_rod", "mass", "theta", "omega"];
+
+/// The defaults a power user may change without rebuilding, from an
+/// optional TOML file: `[world]` with `dt`, `time_scale`, `gravity` and
+/// `damping`, `[limits]` with `max_bobs`, `[stream]` with `fps`,
+/// `[history]` with the rewind `interval` and `seconds`, and the chain to
+/// start with as `[[bobs]]` tables of `length_rod`, `mass`, `theta` and
+/// `omega`, in meters, kilograms and canonical radians. Anything left out
+/// keeps the built-in default.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StartupConfig {
+    /// The default chain if `None`.
+    pub(crate) bobs: Option<Vec<BobSpec>>,
+    pub(crate) dt: f64,
+    pub(crate) time_scale: f64,
+    pub(crate) damping: f64,
+    pub(crate) max_bobs: usize,
+    /// Of subscriptions that don't ask for a rate.
+    pub(crate) stream_fps: u32,
+    pub(crate) history: RewindConfig,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            bobs: None,
+            dt: DEFAULT_DT,
+            time_scale: 1.0,
+            damping: 0.0,
+            max_bobs: DEFAULT_MAX_BOBS,
+            stream_fps: DEFAULT_STREAM_FPS,
+            history: RewindConfig::default(),
+        }
+    }
+}
+
+/// What `reload_config` returns.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ReloadReport {
+    /// The settings that changed and now apply, as `section.key`.
+    pub(crate) applied: Vec<String>,
+    /// The settings that changed but only apply from the next start.
+    pub(crate) requires_restart: Vec<String>,
+    /// Unknown keys, values that fell back to their defaults and the like.
+    pub(crate) warnings: Vec<String>,
+}
+
+/// The reason `error` gives, without the name of what it is about.
+fn reason(error: CommandError) -> String {
+    match error {
+        CommandError::InvalidParameter { reason, .. } => reason,
+        other => other.to_string(),
+    }
+}
+
+/// A preset of just `dt` and `time_scale`, for its checks of them.
+fn timing(dt: f64, time_scale: f64) -> Preset {
+    Preset {
+        name: String::new(),
+        description: String::new(),
+        bobs: Vec::new(),
+        dt,
+        time_scale,
+        pivot: None,
+    }
+}
+
+/// Reads the file's settings one at a time, noting every one it can't use.
+struct Reader<'a> {
+    diagnostics: &'a mut Vec<String>,
+}
+
+impl Reader<'_> {
+    /// The table `name` of `root`, empty if it isn't there or isn't a table.
+    fn section<'t>(&mut self, root: &'t Table, name: &str, keys: &[&str]) -> Option<&'t Table> {
+        let table = match root.get(name)? {
+            Value::Table(table) => table,
+            _ => {
+                self.diagnostics
+                    .push(format!("{name} must be a table; ignored"));
+                return None;
+            }
+        };
+        self.unknown_keys(table, keys, &format!("{name}."));
+        Some(table)
+    }
+
+    fn unknown_keys(&mut self, table: &Table, known: &[&str], at: &str) {
+        for key in table.keys().filter(|key| !known.contains(&key.as_str())) {
+            self.diagnostics
+                .push(format!("Ignored unknown key {at}{key}"));
+        }
+    }
+
+    fn number(&mut self, table: Option<&Table>, key: &str, name: &str) -> Option<f64> {
+        match table?.get(key)? {
+            Value::Float(value) => Some(*value),
+            Value::Integer(value) => Some(*value as f64),
+            _ => {
+                self.diagnostics.push(format!("{name} must be a number"));
+                None
+            }
+        }
+    }
+
+    fn integer(&mut self, table: Option<&Table>, key: &str, name: &str) -> Option<i64> {
+        match table?.get(key)? {
+            Value::Integer(value) => Some(*value),
+            _ => {
+                self.diagnostics
+                    .push(format!("{name} must be a whole number"));
+                None
+            }
+        }
+    }
+
+    /// `value` if there is one and `check` passes it, or else `default`,
+    /// with a diagnostic if it didn't pass.
+    fn setting<T: Copy + std::fmt::Display>(
+        &mut self,
+        value: Option<T>,
+        default: T,
+        name: &str,
+        check: impl FnOnce(T) -> Result<(), CommandError>,
+    ) -> T {
+        let Some(value) = value else {
+            return default;
+        };
+        match check(value) {
+            Ok(()) => value,
+            Err(error) => {
+                let reason = reason(error);
+                (self.diagnostics).push(format!("{name} {reason}; using {default} instead"));
+                default
+            }
+        }
+    }
+
+    /// The `[[bobs]]` array, `None` with a diagnostic if any bob in it
+    /// isn't usable, so the default chain is started with.
+    fn bobs(&mut self, root: &Table, max_bobs: usize) -> Option<Vec<BobSpec>> {
+        let entries = match root.get("bobs")? {
+            Value::Array(entries) => entries,
+            _ => {
+                self.diagnostics
+                    .push("bobs must be an array of tables; using the default chain".into());
+                return None;
+            }
+        };
+        let mut bobs = Vec::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            let name = format!("bobs[{i}]");
+            let Value::Table(table) = entry else {
+                (self.diagnostics).push(format!("{name} must be a table; using the default chain"));
+                return None;
+            };
+            self.unknown_keys(table, BOB_KEYS, &format!("{name}."));
+            let mut values = [0.0; 4];
+            for (value, key) in values.iter_mut().zip(BOB_KEYS) {
+                let at = format!("{name}.{key}");
+                let Some(number) = self.number(Some(table), key, &at) else {
+                    if !table.contains_key(*key) {
+                        (self.diagnostics)
+                            .push(format!("{at} is missing; using the default chain"));
+                    }
+                    return None;
+                };
+                *value = number;
+            }
+            let [length_rod, mass, theta, omega] = values;
+            let bob = BobSpec {
+                length_rod,
+                mass,
+                theta,
+                omega,
+                id: None,
+                locked: false,
+                meta: Default::default(),
+            };
+            if let Err(error) = bob.validate() {
+                (self.diagnostics).push(format!("{name}: {error}; using the default chain"));
+                return None;
+            }
+            bobs.push(bob);
+        }
+        if bobs.is_empty() || bobs.len() > max_bobs {
+            self.diagnostics.push(format!(
+                "bobs must hold 1 to {max_bobs} bobs, got {}; using the default chain",
+                bobs.len()
+            ));
+            return None;
+        }
+        Some(bobs)
+    }
+}
+
+impl StartupConfig {
+    /// Reads the file's text. It never fails: a malformed file gives the
+    /// defaults, an unusable value its own default, each with a diagnostic
+    /// saying so, and unknown keys are warned about and ignored.
+    pub(crate) fn parse(text: &str) -> (Self, Vec<String>) {
+        let mut diagnostics = Vec::new();
+        let root: Table = match text.parse() {
+            Ok(root) => root,
+            Err(error) => {
+                let error = error.to_string();
+                diagnostics.push(format!("Not valid TOML, so every default is kept: {error}"));
+                return (Self::default(), diagnostics);
+            }
+        };
+        let mut reader = Reader {
+            diagnostics: &mut diagnostics,
+        };
+        reader.unknown_keys(&root, SECTIONS, "");
+        let defaults = Self::default();
+
+        let world = reader.section(&root, "world", WORLD_KEYS);
+        let value = reader.number(world, "dt", "world.dt");
+        let dt = reader.setting(value, defaults.dt, "world.dt", |dt| {
+            timing(dt, defaults.time_scale).validate()
+        });
+        let value = reader.number(world, "time_scale", "world.time_scale");
+        let time_scale = reader.setting(value, defaults.time_scale, "world.time_scale", |scale| {
+            timing(defaults.dt, scale).validate()
+        });
+        let value = reader.number(world, "gravity", "world.gravity");
+        reader.setting(value, GRAVITATIONAL_ACCELERATION, "world.gravity", |g| {
+            if g == GRAVITATIONAL_ACCELERATION {
+                Ok(())
+            } else {
+                Err(CommandError::invalid(
+                    "gravity",
+                    format!("must be {GRAVITATIONAL_ACCELERATION}, the gravity the simulation runs with, got {g}"),
+                ))
+            }
+        });
+        let value = reader.number(world, "damping", "world.damping");
+        let damping = reader.setting(value, defaults.damping, "world.damping", |damping| {
+            comparison::validate_damping(damping).map(|_| ())
+        });
+
+        let limits = reader.section(&root, "limits", LIMITS_KEYS);
+        let value = reader.integer(limits, "max_bobs", "limits.max_bobs");
+        let max_bobs = reader.setting(value, defaults.max_bobs as i64, "limits.max_bobs", |max| {
+            if (1..=MAX_TOTAL_BOBS as i64).contains(&max) {
+                Ok(())
+            } else {
+                Err(CommandError::invalid(
+                    "max_bobs",
+                    format!("must be between 1 and {MAX_TOTAL_BOBS}, got {max}"),
+                ))
+            }
+        }) as usize;
+
+        let stream = reader.section(&root, "stream", STREAM_KEYS);
+        let value = reader.integer(stream, "fps", "stream.fps");
+        let fps = reader.setting(value, i64::from(defaults.stream_fps), "stream.fps", |fps| {
+            let fps = u32::try_from(fps).unwrap_or(0);
+            validate_fps(fps).map(|_| ())
+        });
+
+        let history = reader.section(&root, "history", HISTORY_KEYS);
+        let interval = reader.number(history, "interval", "history.interval");
+        let seconds = reader.number(history, "seconds", "history.seconds");
+        let requested = RewindConfig {
+            interval: interval.unwrap_or(defaults.history.interval),
+            seconds: seconds.unwrap_or(defaults.history.seconds),
+        };
+        let history = match requested.validate() {
+            Ok(()) => requested,
+            Err(error) => {
+                let reason = reason(error);
+                (reader.diagnostics).push(format!(
+                    "history {reason}; using an interval of {} s and {} s instead",
+                    defaults.history.interval, defaults.history.seconds
+                ));
+                defaults.history
+            }
+        };
+
+        let bobs = reader.bobs(&root, max_bobs);
+        let config = Self {
+            bobs,
+            dt,
+            time_scale,
+            damping,
+            max_bobs,
+            stream_fps: fps as u32,
+            history,
+        };
+        (config, diagnostics)
+    }
+
+    /// Reads the file at `path`, the defaults if there is none, with a
+    /// diagnostic if it is there but can't be read.
+    pub(crate) fn load(path: &Path) -> (Self, Vec<String>) {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                (Self::default(), Vec::new())
+            }
+            Err(error) => {
+                let error = CommandError::io(path, error);
+                (
+                    Self::default(),
+                    vec![format!("{error}; every default is kept")],
+                )
+            }
+        }
+    }
+
+    /// Sets up a simulation that is just starting. Returns what couldn't be
+    /// used, as `set_state` refusing the chain.
+    pub(crate) fn apply(&self, data: &mut AppDataInner) -> Vec<String> {
+        let mut diagnostics = Vec::new();
+        match data.set_max_bobs(self.max_bobs) {
+            Ok(warning) => diagnostics.extend(warning),
+            Err(error) => diagnostics.push(format!("limits.{error}")),
+        }
+        if let Some(bobs) = &self.bobs {
+            match data.set_state(bobs, SetStateOptions::default()) {
+                Ok(_) => data.initial = data.pendulum.clone(),
+                Err(error) => diagnostics.push(format!("bobs: {error}; using the default chain")),
+            }
+        }
+        self.apply_world(data);
+        data.rewind_buffer.configure(self.history);
+        data.stream_fps = self.stream_fps;
+        diagnostics
+    }
+
+    fn apply_world(&self, data: &mut AppDataInner) {
+        data.dt = self.dt;
+        data.time_scale = self.time_scale;
+        data.pendulum.damping = self.damping;
+        data.initial.damping = self.damping;
+    }
+
+    /// The `section.key` of every setting `other` has differently, the
+    /// reloadable ones first.
+    fn changes(&self, other: &Self) -> (Vec<String>, Vec<String>) {
+        let reloadable = [
+            ("world.dt", self.dt != other.dt),
+            ("world.time_scale", self.time_scale != other.time_scale),
+            ("world.damping", self.damping != other.damping),
+            ("limits.max_bobs", self.max_bobs != other.max_bobs),
+        ];
+        let restart = [
+            ("bobs", self.bobs != other.bobs),
+            ("stream.fps", self.stream_fps != other.stream_fps),
+            ("history", self.history != other.history),
+        ];
+        let changed = |settings: &[(&str, bool)]| {
+            (settings.iter())
+                .filter(|(_, changed)| *changed)
+                .map(|(name, _)| name.to_string())
+                .collect()
+        };
+        (changed(&reloadable), changed(&restart))
+    }
+}
+
+/// The file the app started with and the settings of it in effect, for
+/// `reload_config`.
+pub(crate) struct Startup {
+    path: PathBuf,
+    running: Mutex<StartupConfig>,
+}
+
+impl Startup {
+    pub(crate) fn new(path: PathBuf, running: StartupConfig) -> Self {
+        Self {
+            path,
+            running: Mutex::new(running),
+        }
+    }
+
+    /// The file as it is now, with what was wrong in it, for `reload`.
+    pub(crate) fn read(&self) -> (StartupConfig, Vec<String>) {
+        StartupConfig::load(&self.path)
+    }
+
+    /// Applies what changed in the file, as `read` found it, of the world
+    /// settings to the primary instance, and of the limits, leaving the
+    /// rest of it as the user has it. Settings only read at startup are
+    /// reported, and keep being until the app restarts.
+    pub(crate) fn reload(
+        &self,
+        (config, mut warnings): (StartupConfig, Vec<String>),
+        world: &mut World,
+    ) -> ReloadReport {
+        // each setting stands alone, so poison says nothing
+        let mut running = self.running.lock().unwrap_or_else(|poisoned| {
+            self.running.clear_poison();
+            poisoned.into_inner()
+        });
+        let (changed, requires_restart) = running.changes(&config);
+        let mut applied = Vec::new();
+        for name in changed {
+            let result = match name.as_str() {
+                "limits.max_bobs" => world.set_max_bobs(None, config.max_bobs).map(|warning| {
+                    warnings.extend(warning);
+                    running.max_bobs = config.max_bobs;
+                }),
+                _ => {
+                    let data = world.primary();
+                    match name.as_str() {
+                        "world.dt" => (data.dt, running.dt) = (config.dt, config.dt),
+                        "world.time_scale" => {
+                            (data.time_scale, running.time_scale) =
+                                (config.time_scale, config.time_scale)
+                        }
+                        _ => {
+                            data.pendulum.damping = config.damping;
+                            data.initial.damping = config.damping;
+                            running.damping = config.damping;
+                        }
+                    }
+                    Ok(())
+                }
+            };
+            match result {
+                Ok(()) => applied.push(name),
+                Err(error) => warnings.push(format!("{name}: {error}; left as it was")),
+            }
+        }
+        ReloadReport {
+            applied,
+            requires_restart,
+            warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_file_sets_defaults_and_reloads_what_it_can() {
+        let text = r#"
+            colour = "red"
+            [world]
+            dt = 0.001
+            time_scale = -2
+            gravity = 9.81
+            damping = 0.25
+            [limits]
+            max_bobs = 8
+            [stream]
+            fps = 30
+            [history]
+            interval = 0.1
+            seconds = 20
+            [[bobs]]
+            length_rod = 0.5
+            mass = 2
+            theta = 3.0
+            omega = 0
+            [[bobs]]
+            length_rod = 0.75
+            mass = 1
+            theta = 3.1
+            omega = 0.5
+        "#;
+        let (config, diagnostics) = StartupConfig::parse(text);
+        assert_eq!(diagnostics.len(), 2, "{diagnostics:?}");
+        assert!(diagnostics[0].contains("colour"));
+        assert!(
+            diagnostics[1].starts_with("world.time_scale must be"),
+            "{diagnostics:?}"
+        );
+        assert_eq!(
+            (config.dt, config.time_scale, config.damping),
+            (0.001, 1.0, 0.25)
+        );
+        assert_eq!((config.max_bobs, config.stream_fps), (8, 30));
+        assert_eq!(config.history.seconds, 20.0);
+        assert_eq!(config.bobs.as_ref().unwrap()[1].omega, 0.5);
+
+        let mut data = AppDataInner::default();
+        assert!(config.apply(&mut data).is_empty());
+        assert_eq!(data.pendulum.n(), 2);
+        assert_eq!(data.initial.bobs[0].mass, 2.0);
+        assert_eq!((data.dt, data.stream_fps, data.max_bobs), (0.001, 30, 8));
+        assert_eq!(data.pendulum.damping, 0.25);
+
+        // a broken file or a wrong bob only costs what it touches
+        let (broken, diagnostics) = StartupConfig::parse("[world\ndt = ");
+        assert_eq!((broken, diagnostics.len()), (StartupConfig::default(), 1));
+        let (config, diagnostics) = StartupConfig::parse("[[bobs]]\nlength_rod = 1\nmass = 1");
+        assert_eq!(config.bobs, None);
+        assert!(
+            diagnostics[0].contains("bobs[0].theta is missing"),
+            "{diagnostics:?}"
+        );
+        let missing = std::env::temp_dir().join("startup-none/config.toml");
+        assert_eq!(
+            StartupConfig::load(&missing),
+            (StartupConfig::default(), Vec::new())
+        );
+
+        // reloading applies the world settings and limits, and says what
+        // waits for a restart
+        let path = std::env::temp_dir().join(format!("startup-{}.toml", std::process::id()));
+        std::fs::write(&path, "[world]\ndt = 0.001\n").unwrap();
+        let (running, _) = StartupConfig::load(&path);
+        let mut world = World::new(AppDataInner::default());
+        running.apply(world.primary());
+        let startup = Startup::new(path.clone(), running);
+        std::fs::write(
+            &path,
+            "[world]\ndt = 0.004\n[limits]\nmax_bobs = 5\n[stream]\nfps = 10\nwhat = 1\n",
+        )
+        .unwrap();
+        let report = startup.reload(startup.read(), &mut world);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.applied, ["world.dt", "limits.max_bobs"]);
+        assert_eq!(report.requires_restart, ["stream.fps"]);
+        assert_eq!(report.warnings.len(), 1, "{report:?}");
+        let data = world.primary();
+        assert_eq!(
+            (data.dt, data.max_bobs, data.stream_fps),
+            (0.004, 5, DEFAULT_STREAM_FPS)
+        );
+    }
+}
@@ -0,0 +1,3200 @@
+use pendulum_core::{
+    unwrap_near, validate_parameter, Bob, Coordinate, DynamicsTerms, Integrator, LinearSolution,
+    Pendulum, DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA, GRAVITATIONAL_ACCELERATION,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+use crate::bounds::{Bounds, MotionBounds};
+use crate::clock::{SimClock, TickMeter, MAX_TIME_SCALE};
+use crate::compact::CompactFrame;
+use crate::comparison::{Comparison, ComparisonState};
+use crate::config::{self, ConfigFile, ImportReport, WorldConfig, CONFIG_VERSION};
+use crate::deeplink::{self, LinkPayload};
+use crate::ensemble::{Ensemble, EnsembleState};
+use crate::error::CommandError;
+use crate::events::{
+    Alerts, FlipDetector, LinkRejectedEvent, OverloadEvent, RecoveryEvent, SimEvent,
+};
+use crate::heatmap::Occupancy;
+use crate::history::{Configuration, Edit, History};
+use crate::integrator::IntegratorExt;
+#[cfg(feature = "osc")]
+use crate::osc::OscSender;
+use crate::pendulum::{BobMeta, BobSpec};
+use crate::presets::Preset;
+use crate::randomize::RandomRanges;
+use crate::recording::{Ghost, Recording, RecordingSummary, MAX_KEPT_RECORDINGS};
+use crate::replay::Replay;
+use crate::rewind::RewindBuffer;
+use crate::savefile::{self, SavedRun, SavedSimulation, SAVE_VERSION};
+use crate::share;
+use crate::slowmo::SlowMotion;
+use crate::snapshots::{Snapshot, Snapshots};
+use crate::stats::{ConfigSummary, SimCounters};
+use crate::stream::{Subscriber, DEFAULT_STREAM_FPS, DEFAULT_TICK_PERIOD};
+use crate::trails::TrailBuffer;
+use crate::trajectory::TrajectoryRecording;
+use crate::transition::{PoseTransition, TransitionState};
+use crate::units::{
+    AngleConvention, AngleFormat, AngleUnit, ThetaPolicy, WorldFrame, DEFAULT_PIXELS_PER_METER,
+};
+use crate::wave::{Wave, WaveState};
+#[cfg(feature = "websocket")]
+use crate::ws::WsServer;
+
+/// Substep to begin with, in sim seconds: a small part of the swing of a
+/// rod a meter long.
+pub(crate) const DEFAULT_DT: f64 = 0.002;
+/// Most bobs a chain may have until `set_max_bobs` says otherwise.
+pub(crate) const DEFAULT_MAX_BOBS: usize = 64;
+/// Past this many bobs a single step can take long enough to stall the app;
+/// `set_max_bobs` allows it, with a warning.
+pub(crate) const BOB_COUNT_CEILING: usize = 256;
+/// Longest bob color or label, in characters.
+pub(crate) const MAX_META_LEN: usize = 200;
+
+/// Version of the frame schema, bumped whenever frame fields are added or
+/// change meaning. Version 1 had no interpolation data, version 2 no
+/// structural change notices, version 3 no pause reason, version 4 no
+/// ghost, version 5 no ensemble, version 6 no slow motion, version 7 no bob
+/// ids, version 8 no pivot, version 9 no pose transitions, version 10 no
+/// joint locks, version 11 no render scale and lengths in pixels, version 12
+/// no run clock, version 13 no comparison, version 14 no pendulum wave,
+/// version 15 no motion bounds, version 16 no replay flag.
+pub(crate) const FRAME_SCHEMA_VERSION: u32 = 17;
+
+/// How clients are meant to interpolate between frames, as served by the
+/// `frame_schema` command.
+pub(crate) const HERMITE_INTERPOLATION: &str = "Between consecutive frames a and b of one \
+subscription (b.seq = a.seq + 1), let h = b.span and s = (t - a.simTime) / h in [0, 1]. \
+Each bob's angle is the cubic Hermite \
+theta(s) = (2s^3 - 3s^2 + 1) a.theta + (s^3 - 2s^2 + s) h a.omega \
++ (-2s^3 + 3s^2) b.theta + (s^3 - s^2) h b.omega. \
+Angles are never wrapped, so no unwrapping is needed. Positions follow from the \
+interpolated angles as x_i = sum_{k<=i} l_k sin(theta_k), y_i = sum_{k<=i} l_k cos(theta_k). \
+alpha is the angular acceleration at each frame, for checking the fit or a quintic. \
+With h = 0 or a gap in seq, hold frame b.";
+
+pub(crate) struct AppDataInner {
+    /// Zero of every wall-clock timestamp the backend hands out.
+    pub(crate) epoch: Instant,
+    /// Physics ticks so far, paused or not.
+    pub(crate) heartbeat: u64,
+    /// Positions frames built so far.
+    pub(crate) frames_built: u64,
+    pub(crate) pendulum: Pendulum,
+    /// Id of the next bob to join the chain.
+    pub(crate) next_bob_id: u64,
+    /// Colors and labels by bob id. Kept when a bob leaves the chain, so
+    /// undoing that brings them back with it.
+    pub(crate) bob_meta: HashMap<u64, BobMeta>,
+    /// What `reset` goes back to: the configuration at startup or at the
+    /// last `set_initial_conditions`.
+    pub(crate) initial: Pendulum,
+    /// Most bobs an edit may leave the chain with.
+    pub(crate) max_bobs: usize,
+    /// Rate of the subscriptions that don't ask for one.
+    pub(crate) stream_fps: u32,
+    /// Unit of the angles commands take and return.
+    pub(crate) angle_unit: AngleUnit,
+    /// What those angles are measured from, and which way.
+    pub(crate) angle_convention: AngleConvention,
+    /// Which way y points in the positions commands take and return.
+    pub(crate) world_frame: WorldFrame,
+    /// How many pixels the frontends draw a meter as.
+    pub(crate) pixels_per_meter: f64,
+    /// What angle edits do to the angular velocities.
+    pub(crate) pose_edit_policy: PoseEditPolicy,
+    /// Sim seconds an angle edit eases over, or `None` for it to jump.
+    pub(crate) pose_transition: Option<f64>,
+    /// Bobs easing to an edited angle, driven along their paths by `substep`.
+    pub(crate) transitions: Vec<PoseTransition>,
+    /// Edits of the configuration, for `undo` and `redo`.
+    pub(crate) history: History,
+    pub(crate) sim_time: f64,
+    /// Fixed integration step in sim seconds.
+    pub(crate) dt: f64,
+    /// Sim seconds per wall second.
+    pub(crate) time_scale: f64,
+    /// Automatic slow motion around flips, applied on top of `time_scale`.
+    pub(crate) slow_motion: Option<SlowMotion>,
+    pub(crate) clock: SimClock,
+    pub(crate) flips: FlipDetector,
+    pub(crate) alerts: Alerts,
+    /// Substeps taken by the most recent tick.
+    pub(crate) last_substeps: u32,
+    /// The most recent tick ran out of time before its last substep.
+    pub(crate) overloaded: bool,
+    pub(crate) counters: SimCounters,
+    /// Energy that the diagnostics drift is measured from. Taken on the first
+    /// diagnostics frame and cleared whenever the pendulum is edited.
+    pub(crate) energy_reference: Option<f64>,
+    /// How often the physics task wakes up.
+    pub(crate) tick_period: Duration,
+    pub(crate) tick_meter: TickMeter,
+    /// Linearized comparison solution, present while the overlay is enabled.
+    pub(crate) analytic: Option<LinearSolution>,
+    /// The recording in progress, appended to on every substep.
+    pub(crate) recording: Option<Recording>,
+    /// The file the trajectory is being written to, CSV or binary.
+    pub(crate) trajectory_recording: Option<TrajectoryRecording>,
+    /// Finished recordings by id.
+    pub(crate) recordings: Vec<(u64, Arc<Recording>)>,
+    pub(crate) next_recording_id: u64,
+    /// Named moments `restore_snapshot` can go back to.
+    pub(crate) snapshots: Snapshots,
+    /// The recent states `rewind` can go back to.
+    pub(crate) rewind_buffer: RewindBuffer,
+    /// The recent motion of each bob, for drawing trails.
+    pub(crate) trails: TrailBuffer,
+    /// Where the motion has kept to lately, for framing the view.
+    pub(crate) motion_bounds: MotionBounds,
+    /// Where the tip has been, for `export_heatmap_png`.
+    pub(crate) occupancy: Occupancy,
+    /// A recording playing back alongside the live run.
+    pub(crate) ghost: Option<Ghost>,
+    /// A recording playing back in place of the live run, which waits.
+    pub(crate) replay: Option<Replay>,
+    /// Perturbed copies stepped alongside the pendulum.
+    pub(crate) ensemble: Option<Ensemble>,
+    /// A damped clone stepped in lockstep, as another instance.
+    pub(crate) comparison: Option<Comparison>,
+    /// The rest of the pendulum wave this leads, as other instances.
+    pub(crate) wave: Option<Wave>,
+    /// Stepped by the instance this is the comparison clone of, or whose
+    /// wave this is part of, rather than by its own clock.
+    pub(crate) lockstep: bool,
+    #[cfg(feature = "osc")]
+    pub(crate) osc: Option<OscSender>,
+    #[cfg(feature = "websocket")]
+    pub(crate) ws_server: Option<WsServer>,
+    /// Send the dynamics terms with every n-th frame, when set.
+    pub(crate) dynamics_overlay_every: Option<u32>,
+    /// Cancel flag of the most recent timestep sensitivity run.
+    pub(crate) sensitivity_cancel: Arc<AtomicBool>,
+    /// Cancel flag of the most recent dense export.
+    pub(crate) dense_cancel: Arc<AtomicBool>,
+    /// Cancel flag of the most recent parameter sweep.
+    pub(crate) sweep_cancel: Arc<AtomicBool>,
+    /// Cancel flag of the most recent prediction.
+    pub(crate) prediction_cancel: Arc<AtomicBool>,
+    /// Channels receiving the frames broadcast by the physics task.
+    pub(crate) subscribers: Vec<Subscriber>,
+    pub(crate) next_subscriber_id: u64,
+    /// Stop stepping while nobody is subscribed, so the simulation only
+    /// advances while someone is watching.
+    pub(crate) pause_without_subscribers: bool,
+    /// Paused from the UI.
+    pub(crate) user_paused: bool,
+    /// A warm-up is stepping the simulation on its own.
+    pub(crate) warming_up: bool,
+    /// Cancel flag of the most recent warm-up.
+    pub(crate) warm_up_cancel: Arc<AtomicBool>,
+    /// Every window is hidden or minimized.
+    pub(crate) in_background: bool,
+    /// Stop stepping while `in_background`.
+    pub(crate) pause_in_background: bool,
+    /// Events raised outside a substep, emitted with the next tick's.
+    pub(crate) pending_events: Vec<SimEvent>,
+}
+
+impl Default for AppDataInner {
+    fn default() -> Self {
+        let mut pendulum = Pendulum::default();
+        for (id, bob) in pendulum.bobs.iter_mut().enumerate() {
+            bob.id = id as u64;
+        }
+        Self {
+            epoch: Instant::now(),
+            heartbeat: 0,
+            frames_built: 0,
+            counters: SimCounters::default(),
+            next_bob_id: pendulum.n() as u64,
+            bob_meta: HashMap::new(),
+            initial: pendulum.clone(),
+            max_bobs: DEFAULT_MAX_BOBS,
+            stream_fps: DEFAULT_STREAM_FPS,
+            history: History::default(),
+            angle_unit: AngleUnit::default(),
+            angle_convention: AngleConvention::default(),
+            world_frame: WorldFrame::default(),
+            pixels_per_meter: DEFAULT_PIXELS_PER_METER,
+            pose_edit_policy: PoseEditPolicy::default(),
+            pose_transition: None,
+            transitions: Vec::new(),
+            pendulum,
+            sim_time: 0.0,
+            dt: DEFAULT_DT,
+            time_scale: 1.0,
+            slow_motion: None,
+            clock: SimClock::default(),
+            flips: FlipDetector::default(),
+            alerts: Alerts::default(),
+            last_substeps: 0,
+            overloaded: false,
+            energy_reference: None,
+            tick_period: DEFAULT_TICK_PERIOD,
+            tick_meter: TickMeter::default(),
+            analytic: None,
+            recording: None,
+            trajectory_recording: None,
+            recordings: Vec::new(),
+            next_recording_id: 0,
+            snapshots: Snapshots::default(),
+            rewind_buffer: RewindBuffer::default(),
+            trails: TrailBuffer::default(),
+            motion_bounds: MotionBounds::default(),
+            occupancy: Occupancy::default(),
+            ghost: None,
+            replay: None,
+            ensemble: None,
+            comparison: None,
+            wave: None,
+            lockstep: false,
+            #[cfg(feature = "osc")]
+            osc: None,
+            #[cfg(feature = "websocket")]
+            ws_server: None,
+            dynamics_overlay_every: None,
+            sensitivity_cancel: Arc::new(AtomicBool::new(false)),
+            dense_cancel: Arc::new(AtomicBool::new(false)),
+            sweep_cancel: Arc::new(AtomicBool::new(false)),
+            prediction_cancel: Arc::new(AtomicBool::new(false)),
+            subscribers: Vec::new(),
+            next_subscriber_id: 0,
+            pause_without_subscribers: true,
+            user_paused: false,
+            warming_up: false,
+            warm_up_cancel: Arc::new(AtomicBool::new(false)),
+            in_background: false,
+            pause_in_background: true,
+            pending_events: Vec::new(),
+        }
+    }
+}
+
+impl AppDataInner {
+    /// Bookkeeping after a bob was added or removed: the overlays and
+    /// references that depend on the layout are redone, and every positions
+    /// subscriber is told on its first frame with the new layout. The states
+    /// kept for `rewind` are of the old layout, so they go, unless this is
+    /// the rewind itself.
+    pub(crate) fn structure_changed(&mut self, operation: StructuralOperation, index: usize) {
+        if operation != StructuralOperation::Rewound {
+            self.rewind_buffer.cut(operation, self.sim_time);
+        }
+        self.pendulum.update_coordinates();
+        (self.trails).structure_changed(operation, index, &self.pendulum);
+        (self.motion_bounds).recompute(&self.pendulum, &self.trails, self.sim_time);
+        if operation.starts_over() {
+            self.occupancy.clear();
+        }
+        self.resync_analytic();
+        self.energy_reference = None;
+        self.copies_follow_reference();
+        self.drop_stale_transitions();
+        let change = StructuralChange {
+            operation,
+            index,
+            bob_count: self.pendulum.n(),
+        };
+        for subscriber in &mut self.subscribers {
+            subscriber.notify_structural_change(change);
+        }
+    }
+
+    /// Sets the given properties of bob `index`, for every frontend that can
+    /// edit bobs, or none if any of them is invalid. The angle may be given
+    /// wrapped or unbounded; it keeps the bob's turns either way, see
+    /// `unwrap_near`. An angle without an angular velocity leaves the
+    /// velocities to `pose_edit_policy`.
+    ///
+    /// With `pose_transition` set and the simulation running, the angle
+    /// isn't set but eased to, ending at `omega` or at rest; see
+    /// `PoseTransition`. A new angle for a bob already easing starts over
+    /// from where it is. Paused, there is no sim time to ease over, so the
+    /// angle jumps as usual.
+    pub(crate) fn modify_bob(
+        &mut self,
+        index: usize,
+        length: Option<f64>,
+        mass: Option<f64>,
+        theta: Option<f64>,
+        omega: Option<f64>,
+    ) -> Result<(), CommandError> {
+        self.undoable(Edit::ModifyBob, |data| {
+            let energy = data.pendulum.total_energy();
+            let ease = data
+                .pose_transition
+                .filter(|_| data.pause_reason().is_none());
+            let len = data.pendulum.n();
+            let bob = data
+                .pendulum
+                .bobs
+                .get_mut(index)
+                .ok_or(CommandError::IndexOutOfBounds { index, len })?;
+            let ease = ease.filter(|_| !bob.locked);
+            for (field, value) in [
+                ("length_rod", length),
+                ("mass", mass),
+                ("theta", theta),
+                ("omega", omega),
+            ] {
+                if let Some(value) = value {
+                    validate_parameter(field, value)?;
+                }
+            }
+            if let Some(l) = length {
+                bob.length_rod = l;
+            }
+            if let Some(m) = mass {
+                bob.mass = m;
+            }
+            let mut eased = None;
+            if let Some(t) = theta {
+                let target = unwrap_near(t, bob.theta);
+                match ease {
+                    Some(duration) => {
+                        let end_omega = omega.unwrap_or(0.0);
+                        eased = Some(PoseTransition::new(
+                            bob,
+                            target,
+                            end_omega,
+                            data.sim_time,
+                            duration,
+                        ));
+                    }
+                    None => bob.theta = target,
+                }
+            }
+            if let (Some(o), None) = (omega, &eased) {
+                bob.omega = o;
+            }
+            if theta.is_some() && omega.is_none() && eased.is_none() {
+                data.apply_pose_edit_policy(energy);
+            }
+            data.state_edited();
+            if let Some(transition) = eased {
+                data.transitions.retain(|t| t.id != transition.id);
+                data.transitions.push(transition);
+            }
+            Ok(())
+        })
+    }
+
+    /// Locks bob `index`'s joint where it is, at rest, or lets it swing again
+    /// from there; see `Bob::locked`. Edits still move a locked bob, which
+    /// then stays where it was put.
+    pub(crate) fn set_joint_locked(
+        &mut self,
+        index: usize,
+        locked: bool,
+    ) -> Result<(), CommandError> {
+        self.undoable(Edit::LockJoint, |data| {
+            let len = data.pendulum.n();
+            let bob = data
+                .pendulum
+                .bobs
+                .get_mut(index)
+                .ok_or(CommandError::IndexOutOfBounds { index, len })?;
+            bob.locked = locked;
+            if locked {
+                bob.omega = 0.0;
+            }
+            data.state_edited();
+            Ok(())
+        })
+    }
+
+    /// Forgets the transitions of bobs that are gone or that something other
+    /// than their transition has moved since.
+    fn drop_stale_transitions(&mut self) {
+        let (bobs, sim_time) = (&self.pendulum.bobs, self.sim_time);
+        self.transitions.retain(|t| {
+            bobs.iter()
+                .find(|b| b.id == t.id)
+                .is_some_and(|b| t.follows(b, sim_time))
+        });
+    }
+
+    /// The accelerations of the joints being eased, for
+    /// `Pendulum::step_prescribed`.
+    fn transition_accelerations(&self) -> Vec<(usize, f64)> {
+        self.transitions
+            .iter()
+            .filter_map(|t| {
+                let index = self.pendulum.bobs.iter().position(|b| b.id == t.id)?;
+                Some((index, t.at(self.sim_time).2))
+            })
+            .collect()
+    }
+
+    /// Puts the eased bobs where their paths are at the current sim time, and
+    /// hands the ones that arrived back to the dynamics.
+    fn advance_transitions(&mut self) {
+        if self.transitions.is_empty() {
+            return;
+        }
+        for t in &self.transitions {
+            if let Some(bob) = self.pendulum.bobs.iter_mut().find(|b| b.id == t.id) {
+                (bob.theta, bob.omega, _) = t.at(self.sim_time);
+            }
+        }
+        let sim_time = self.sim_time;
+        self.transitions.retain(|t| !t.is_done(sim_time));
+        self.pendulum.update_coordinates();
+    }
+
+    /// Does what `pose_edit_policy` says to the angular velocities after an
+    /// edit moved angles; `energy` is the total before it.
+    fn apply_pose_edit_policy(&mut self, energy: f64) {
+        match self.pose_edit_policy {
+            PoseEditPolicy::KeepOmega => {}
+            PoseEditPolicy::ZeroOmega => {
+                for bob in &mut self.pendulum.bobs {
+                    bob.omega = 0.0;
+                }
+            }
+            PoseEditPolicy::PreserveEnergy => {
+                self.pendulum.rescale_to_energy(energy);
+            }
+        }
+    }
+
+    /// Applies every update in turn, or none if any of them names a missing
+    /// bob or would leave one invalid. The positions are redone once, after
+    /// the lot. Angles keep the bobs' turns, as in `modify_bob`, and if none
+    /// of the updates gives an angular velocity, moved angles leave the
+    /// velocities to `pose_edit_policy`. Returns, per update, the fields
+    /// whose value it changed, not counting what the policy did.
+    pub(crate) fn modify_bobs(
+        &mut self,
+        updates: &[BobUpdate],
+    ) -> Result<Vec<ModifiedBob>, CommandError> {
+        self.undoable(Edit::ModifyBobs, |data| {
+            let mut bobs = data.pendulum.bobs.clone();
+            let mut modified = Vec::with_capacity(updates.len());
+            for (i, update) in updates.iter().enumerate() {
+                let index = match (update.index, update.id) {
+                    (Some(index), None) if index < bobs.len() => index,
+                    (Some(index), None) => {
+                        return Err(CommandError::invalid(
+                            format!("updates[{i}].index"),
+                            format!("{index} is out of bounds, there are {}", bobs.len()),
+                        ))
+                    }
+                    (None, Some(id)) => data.index_of(id).map_err(|_| {
+                        CommandError::invalid(
+                            format!("updates[{i}].id"),
+                            format!("{id} names no bob"),
+                        )
+                    })?,
+                    _ => {
+                        return Err(CommandError::invalid(
+                            format!("updates[{i}]"),
+                            "must give either an index or an id",
+                        ))
+                    }
+                };
+                let bob = &mut bobs[index];
+                let mut changed = Vec::new();
+                let theta = update.theta.map(|t| unwrap_near(t, bob.theta));
+                for (field, value, target) in [
+                    ("length_rod", update.length, &mut bob.length_rod),
+                    ("mass", update.mass, &mut bob.mass),
+                    ("theta", theta, &mut bob.theta),
+                    ("omega", update.omega, &mut bob.omega),
+                ] {
+                    if let Some(value) = value {
+                        if *target != value {
+                            *target = value;
+                            changed.push(field);
+                        }
+                    }
+                }
+                let spec = BobSpec {
+                    length_rod: bob.length_rod,
+                    mass: bob.mass,
+                    theta: bob.theta,
+                    omega: bob.omega,
+                    id: None,
+                    locked: bob.locked,
+                    meta: BobMeta::default(),
+                };
+                spec.validate()
+                    .map_err(|e| CommandError::from(e).within(&format!("updates[{i}]")))?;
+                modified.push(ModifiedBob {
+                    index,
+                    id: bob.id,
+                    changed,
+                });
+            }
+            if modified.iter().any(|m| !m.changed.is_empty()) {
+                let energy = data.pendulum.total_energy();
+                data.pendulum.bobs = bobs;
+                let posed = modified.iter().any(|m| m.changed.contains(&"theta"));
+                if posed && updates.iter().all(|u| u.omega.is_none()) {
+                    data.apply_pose_edit_policy(energy);
+                }
+                data.state_edited();
+            }
+            Ok(modified)
+        })
+    }
+
+    /// Gives every bob the same rod length, mass or angular velocity, or
+    /// with `scale` multiplies each bob's own by the value given; missing
+    /// ones stay. Made of one `modify_bobs`, so it is all or nothing, one
+    /// coordinates update and one edit to undo. Errors name the bob as
+    /// `bobs[i]`.
+    pub(crate) fn modify_all_bobs(
+        &mut self,
+        length: Option<f64>,
+        mass: Option<f64>,
+        omega: Option<f64>,
+        scale: bool,
+    ) -> Result<Vec<ModifiedBob>, CommandError> {
+        for (field, value) in [("length_rod", length), ("mass", mass), ("omega", omega)] {
+            match value {
+                Some(factor) if scale && !factor.is_finite() => {
+                    return Err(CommandError::invalid(
+                        field,
+                        format!("factor must be finite, got {factor}"),
+                    ))
+                }
+                Some(value) if !scale => validate_parameter(field, value)?,
+                _ => {}
+            }
+        }
+        let apply = |own: f64, value: Option<f64>| value.map(|v| if scale { own * v } else { v });
+        let updates: Vec<BobUpdate> = (self.pendulum.bobs.iter().enumerate())
+            .map(|(index, bob)| BobUpdate {
+                index: Some(index),
+                length: apply(bob.length_rod, length),
+                mass: apply(bob.mass, mass),
+                omega: apply(bob.omega, omega),
+                ..BobUpdate::default()
+            })
+            .collect();
+        self.undoable(Edit::ModifyAllBobs, |data| data.modify_bobs(&updates))
+            .map_err(|e| match e {
+                CommandError::InvalidParameter { name, reason } => CommandError::InvalidParameter {
+                    name: name.replacen("updates[", "bobs[", 1),
+                    reason,
+                },
+                other => other,
+            })
+    }
+
+    /// Runs `edit` so that `undo` can take it back, unless it is part of a
+    /// larger edit that already is.
+    fn undoable<T>(
+        &mut self,
+        kind: Edit,
+        edit: impl FnOnce(&mut Self) -> Result<T, CommandError>,
+    ) -> Result<T, CommandError> {
+        if self.history.in_edit {
+            return edit(self);
+        }
+        let before = self.configuration();
+        self.history.in_edit = true;
+        let result = edit(self);
+        self.history.in_edit = false;
+        if result.is_ok() {
+            let after = self.configuration();
+            self.history.record(kind, before, &after, Instant::now());
+        }
+        result
+    }
+
+    fn configuration(&self) -> Configuration {
+        Configuration {
+            bobs: self.pendulum.bobs.clone(),
+            pivot: self.pendulum.pivot,
+            dt: self.dt,
+            time_scale: self.time_scale,
+        }
+    }
+
+    /// Takes back the last edit, sim time carrying on. Subscribers see it as
+    /// an edit in place if the bobs are the same ones, or else get a
+    /// `Replaced` notice.
+    pub(crate) fn undo(&mut self) -> Result<Edit, CommandError> {
+        let (edit, configuration) = self
+            .history
+            .undo(self.configuration())
+            .ok_or_else(|| CommandError::unavailable("Nothing to undo"))?;
+        self.restore(configuration);
+        Ok(edit)
+    }
+
+    /// Does the last undone edit again, like `undo` does.
+    pub(crate) fn redo(&mut self) -> Result<Edit, CommandError> {
+        let (edit, configuration) = self
+            .history
+            .redo(self.configuration())
+            .ok_or_else(|| CommandError::unavailable("Nothing to redo"))?;
+        self.restore(configuration);
+        Ok(edit)
+    }
+
+    fn restore(&mut self, configuration: Configuration) {
+        let same_bobs =
+            (self.pendulum.bobs.iter().map(|b| b.id)).eq(configuration.bobs.iter().map(|b| b.id));
+        self.pendulum.bobs = configuration.bobs;
+        self.move_pivot(configuration.pivot);
+        self.dt = configuration.dt;
+        self.time_scale = configuration.time_scale;
+        if same_bobs {
+            self.state_edited();
+        } else {
+            self.flips.suppress_next();
+            self.structure_changed(StructuralOperation::Replaced, 0);
+        }
+    }
+
+    /// Stops every bob where it is: the angular velocities go to zero and the
+    /// pose stays. The integrator carries nothing over between steps, so the
+    /// velocity-dependent state left is the energy baseline and the flip
+    /// detector's, which `state_edited` starts over.
+    pub(crate) fn zero_velocities(&mut self) -> Result<(), CommandError> {
+        self.undoable(Edit::ZeroVelocities, |data| {
+            for bob in &mut data.pendulum.bobs {
+                bob.omega = 0.0;
+            }
+            data.state_edited();
+            Ok(())
+        })
+    }
+
+    /// Reflects the pendulum about the vertical: every angle and angular
+    /// velocity is negated. The energy is the same, so its baseline stays,
+    /// and the flip detector follows the reflection rather than starting
+    /// over.
+    pub(crate) fn mirror(&mut self) -> Result<(), CommandError> {
+        self.undoable(Edit::Mirror, |data| {
+            for bob in &mut data.pendulum.bobs {
+                bob.theta = -bob.theta;
+                bob.omega = -bob.omega;
+            }
+            data.pendulum.update_coordinates();
+            data.flips.mirror();
+            data.resync_analytic();
+            data.copies_follow_reference();
+            Ok(())
+        })
+    }
+
+    /// Multiplies every rod length by `length_factor` and every mass by
+    /// `mass_factor`. Masses alone don't change the motion; lengths scale
+    /// its time by √`length_factor`, and with `similar` the angular
+    /// velocities are divided by that too, so the motion goes on as a
+    /// slowed or sped up copy of itself.
+    pub(crate) fn scale(
+        &mut self,
+        length_factor: f64,
+        mass_factor: f64,
+        similar: bool,
+    ) -> Result<(), CommandError> {
+        for (name, factor) in [
+            ("length_factor", length_factor),
+            ("mass_factor", mass_factor),
+        ] {
+            if !(factor.is_finite() && factor > 0.0) {
+                return Err(CommandError::invalid(
+                    name,
+                    format!("must be positive and finite, got {factor}"),
+                ));
+            }
+        }
+        for (i, bob) in self.pendulum.bobs.iter().enumerate() {
+            validate_parameter("length_rod", bob.length_rod * length_factor)
+                .and(validate_parameter("mass", bob.mass * mass_factor))
+                .map_err(|e| CommandError::from(e).within(&format!("bobs[{i}]")))?;
+        }
+        self.undoable(Edit::Scale, |data| {
+            let omega_factor = if similar {
+                1.0 / length_factor.sqrt()
+            } else {
+                1.0
+            };
+            for bob in &mut data.pendulum.bobs {
+                bob.length_rod *= length_factor;
+                bob.mass *= mass_factor;
+                bob.omega *= omega_factor;
+            }
+            data.state_edited();
+            Ok(())
+        })
+    }
+
+    /// Poses the chain, at rest, as near `points` as the rod lengths allow;
+    /// see `Pendulum::fit_pose`. Returns how far each bob ended up from its
+    /// point.
+    pub(crate) fn set_pose_from_points(
+        &mut self,
+        points: &[Coordinate],
+    ) -> Result<Vec<f64>, CommandError> {
+        if points.len() != self.pendulum.n() {
+            return Err(CommandError::invalid(
+                "points",
+                format!(
+                    "must hold {} points, one per bob, got {}",
+                    self.pendulum.n(),
+                    points.len()
+                ),
+            ));
+        }
+        if let Some(i) = points
+            .iter()
+            .position(|p| !(p.x.is_finite() && p.y.is_finite()))
+        {
+            return Err(CommandError::invalid(
+                format!("points[{i}]"),
+                "must be finite",
+            ));
+        }
+        self.undoable(Edit::SetPose, |data| {
+            let thetas = data.pendulum.fit_pose(points);
+            for (bob, theta) in data.pendulum.bobs.iter_mut().zip(thetas) {
+                bob.theta = theta;
+                bob.omega = 0.0;
+            }
+            data.state_edited();
+            Ok(data
+                .pendulum
+                .bobs
+                .iter()
+                .zip(points)
+                .map(|(bob, p)| (bob.coordinate.x - p.x).hypot(bob.coordinate.y - p.y))
+                .collect())
+        })
+    }
+
+    /// Moves the pivot, and the whole chain with it. The motion relative to
+    /// the pivot is untouched, so nothing starts over.
+    pub(crate) fn set_pivot(&mut self, pivot: Coordinate) -> Result<(), CommandError> {
+        validate_pivot(pivot)?;
+        self.undoable(Edit::SetPivot, |data| {
+            data.move_pivot(pivot);
+            Ok(())
+        })
+    }
+
+    /// Steps the chain, and the ensemble and predictions with it, with
+    /// `integrator` from the next substep on.
+    pub(crate) fn set_integrator(&mut self, integrator: Integrator) {
+        self.pendulum.integrator = integrator;
+        self.initial.integrator = integrator;
+        if let Some(ensemble) = &mut self.ensemble {
+            ensemble.set_integrator(integrator);
+        }
+        self.comparison_follows();
+    }
+
+    fn move_pivot(&mut self, pivot: Coordinate) {
+        self.pendulum.pivot = pivot;
+        self.pendulum.update_coordinates();
+        if let Some(ensemble) = &mut self.ensemble {
+            ensemble.set_pivot(pivot);
+        }
+        self.comparison_follows();
+    }
+
+    /// Passes the edit just made on to the comparison's clone; see
+    /// `Comparison::follow`.
+    fn comparison_follows(&mut self) {
+        if let Some(mut comparison) = self.comparison.take() {
+            comparison.follow(self);
+            self.comparison = Some(comparison);
+        }
+    }
+
+    /// Keeps the current moment under `name`, replacing one saved there
+    /// before, and returns whether one was.
+    pub(crate) fn save_snapshot(
+        &mut self,
+        name: &str,
+        saved_at_ms: f64,
+    ) -> Result<bool, CommandError> {
+        let snapshot = Snapshot {
+            configuration: self.configuration(),
+            sim_time: self.sim_time,
+            transitions: self.transitions.clone(),
+            saved_at_ms,
+        };
+        self.snapshots.save(name, snapshot)
+    }
+
+    /// Goes back to the moment saved under `name`, sim time included, in one
+    /// go. Like after a reset, the recording in progress is finished, the
+    /// energy baseline and flip detection start over, and subscribers get a
+    /// `Restored` notice with a zero span. It isn't an edit, so there is
+    /// nothing to undo, and the snapshot stays for going back again.
+    pub(crate) fn restore_snapshot(&mut self, name: &str) -> Result<(), CommandError> {
+        let snapshot = self.snapshots.get(name)?.clone();
+        let configuration = snapshot.configuration;
+        self.pendulum.bobs = configuration.bobs;
+        self.move_pivot(configuration.pivot);
+        self.dt = configuration.dt;
+        self.time_scale = configuration.time_scale;
+        self.sim_time = snapshot.sim_time;
+        self.transitions = snapshot.transitions;
+        self.finish_recording();
+        if let Some(ghost) = &mut self.ghost {
+            ghost.rewind(self.sim_time);
+        }
+        self.flips.suppress_next();
+        self.structure_changed(StructuralOperation::Restored, 0);
+        Ok(())
+    }
+
+    /// Goes back about `seconds` of sim time, to the kept state closest to
+    /// that, and carries on from there, paused or not. Subscribers get a
+    /// `Rewound` notice with a zero span, so trails are cleared. Returns the
+    /// sim time landed at.
+    pub(crate) fn rewind(&mut self, seconds: f64) -> Result<f64, CommandError> {
+        let sample = self.rewind_buffer.rewind(seconds, self.sim_time)?;
+        self.pendulum.bobs = sample.bobs;
+        self.move_pivot(sample.pivot);
+        self.sim_time = sample.sim_time;
+        self.transitions.clear();
+        self.finish_recording();
+        self.flips.suppress_next();
+        self.structure_changed(StructuralOperation::Rewound, 0);
+        Ok(self.sim_time)
+    }
+
+    /// Puts the bobs back to `initial`, with sim time, the counters and the
+    /// energy baseline starting over. Subscribers get a `Reset` notice on the
+    /// next frame, which also has a zero span, so they can clear trails and
+    /// stop interpolating. Whether stepping is paused, and where the pivot
+    /// is, are left as they were.
+    pub(crate) fn reset(&mut self) {
+        self.pendulum.bobs = self.initial.bobs.clone();
+        self.counters = SimCounters::default();
+        self.clock.deficit = 0.0;
+        self.clock.elapsed = 0.0;
+        self.clock.steps = 0;
+        self.restart_sim_time();
+        self.flips.suppress_next();
+        self.structure_changed(StructuralOperation::Reset, 0);
+    }
+
+    /// Replaces every bob at once, or nothing if any entry is invalid. Bobs
+    /// keep the ids they come with and the rest get new ones. Sim time starts
+    /// over unless `options` keep it, and the pivot moves if they give one.
+    /// Subscribers get a `Replaced` notice, handled like a reset's.
+    pub(crate) fn set_state(
+        &mut self,
+        bobs: &[BobSpec],
+        options: SetStateOptions,
+    ) -> Result<(), CommandError> {
+        if options.lengths_in_pixels {
+            let bobs: Vec<BobSpec> = (bobs.iter())
+                .map(|spec| BobSpec {
+                    length_rod: spec.length_rod / DEFAULT_PIXELS_PER_METER,
+                    ..spec.clone()
+                })
+                .collect();
+            let pivot = (options.pivot).map(|p| {
+                Coordinate::new(
+                    p.x / DEFAULT_PIXELS_PER_METER,
+                    p.y / DEFAULT_PIXELS_PER_METER,
+                )
+            });
+            let options = SetStateOptions {
+                pivot,
+                lengths_in_pixels: false,
+                ..options
+            };
+            return self.set_state(&bobs, options);
+        }
+        self.undoable(Edit::SetState, |data| {
+            if let Some(pivot) = options.pivot {
+                validate_pivot(pivot)?;
+            }
+            data.check_bob_count(bobs.len())?;
+            for (i, spec) in bobs.iter().enumerate() {
+                spec.validate()
+                    .map_err(|e| CommandError::from(e).within(&format!("bobs[{i}]")))?;
+                validate_meta(&spec.meta).map_err(|e| e.within(&format!("bobs[{i}]")))?;
+                if let Some(id) = spec.id {
+                    if bobs[..i].iter().any(|other| other.id == Some(id)) {
+                        return Err(CommandError::invalid(
+                            format!("bobs[{i}].id"),
+                            format!("{id} is used twice"),
+                        ));
+                    }
+                }
+            }
+            // fresh ids go past every kept one
+            if let Some(max) = bobs.iter().filter_map(|spec| spec.id).max() {
+                data.next_bob_id = data.next_bob_id.max(max + 1);
+            }
+            let bobs = bobs
+                .iter()
+                .map(|spec| match spec.id {
+                    Some(id) => {
+                        data.set_meta(id, spec.meta.clone());
+                        Bob { id, ..spec.bob() }
+                    }
+                    None => data.new_bob(spec),
+                })
+                .collect();
+            data.pendulum.bobs = bobs;
+            if let Some(pivot) = options.pivot {
+                data.move_pivot(pivot);
+            }
+            if !options.keep_sim_time {
+                data.restart_sim_time();
+            }
+            data.flips.suppress_next();
+            data.structure_changed(StructuralOperation::Replaced, 0);
+            Ok(())
+        })
+    }
+
+    /// Swaps in `preset`'s bobs through `set_state`, sim time starting over,
+    /// along with its dt, time scale and pivot, if it has one; nothing
+    /// changes if it is invalid.
+    pub(crate) fn load_preset(&mut self, preset: &Preset) -> Result<(), CommandError> {
+        self.undoable(Edit::LoadPreset, |data| data.apply_preset(preset))
+    }
+
+    /// `load_preset` without an undo entry, for a simulation that starts
+    /// out from the preset.
+    pub(crate) fn apply_preset(&mut self, preset: &Preset) -> Result<(), CommandError> {
+        preset.validate()?;
+        let options = SetStateOptions {
+            pivot: preset.pivot,
+            ..SetStateOptions::default()
+        };
+        self.set_state(&preset.bobs, options)?;
+        self.dt = preset.dt;
+        self.time_scale = preset.time_scale;
+        Ok(())
+    }
+
+    /// The bobs as they are, with their colors and labels but not their ids.
+    pub(crate) fn bob_specs(&self) -> Vec<BobSpec> {
+        (self.pendulum.bobs.iter())
+            .map(|bob| BobSpec {
+                length_rod: bob.length_rod,
+                mass: bob.mass,
+                theta: bob.theta,
+                omega: bob.omega,
+                id: None,
+                locked: bob.locked,
+                meta: self.bob_meta.get(&bob.id).cloned().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// The configuration `export_config` writes; see `ConfigFile`.
+    pub(crate) fn export_config(&self) -> ConfigFile {
+        ConfigFile {
+            version: CONFIG_VERSION,
+            bobs: self.bob_specs(),
+            world: WorldConfig {
+                gravity: GRAVITATIONAL_ACCELERATION,
+                dt: self.dt,
+                time_scale: self.time_scale,
+                integrator: self.pendulum.integrator.settings(),
+                damping: self.pendulum.damping,
+                pivot: self.pendulum.pivot,
+            },
+        }
+    }
+
+    /// Applies a configuration file's text, all of it or, if anything in it
+    /// is invalid, none of it. The bobs go through `set_state`, so sim time
+    /// starts over.
+    pub(crate) fn import_config(&mut self, text: &str) -> Result<ImportReport, CommandError> {
+        let (config, warnings) = config::parse(text)?;
+        self.apply_config(&config, Edit::ImportConfig)?;
+        Ok(ImportReport { warnings })
+    }
+
+    /// Applies a parsed configuration as one `edit`, all of it or none.
+    pub(crate) fn apply_config(
+        &mut self,
+        config: &ConfigFile,
+        edit: Edit,
+    ) -> Result<(), CommandError> {
+        let integrator = config.validate()?;
+        let damping = config.world.damping;
+        self.undoable(edit, |data| {
+            data.apply_preset(&config.preset())?;
+            data.pendulum.damping = damping;
+            data.initial.damping = damping;
+            data.copies_follow_reference();
+            Ok(())
+        })?;
+        self.set_integrator(integrator);
+        Ok(())
+    }
+
+    /// Everything `load_simulation` takes to carry on from this moment.
+    pub(crate) fn save_simulation(&self) -> SavedSimulation {
+        SavedSimulation {
+            version: SAVE_VERSION,
+            config: self.export_config(),
+            run: SavedRun {
+                sim_time: self.sim_time,
+                bob_ids: self.pendulum.bobs.iter().map(|bob| bob.id).collect(),
+                next_bob_id: self.next_bob_id,
+                elapsed_time: self.clock.elapsed,
+                step_count: self.clock.steps,
+                energy_reference: self.energy_reference,
+                flips: self.flips.clone(),
+                transitions: self.transitions.clone(),
+            },
+        }
+    }
+
+    /// Carries on from a file of `save_simulation`, where it was saved: the
+    /// same steps follow as would have followed the save. All of it is
+    /// checked first, so a corrupt file changes nothing. The bobs go in as
+    /// an edit, which undoing takes back, sim time aside.
+    pub(crate) fn load_simulation(&mut self, text: &str) -> Result<(), CommandError> {
+        let saved = savefile::parse(text)?;
+        let integrator = saved.config.validate()?;
+        let (config, run) = (saved.config, saved.run);
+        run.validate(config.bobs.len())?;
+        let bobs: Vec<BobSpec> = (config.bobs.iter().zip(&run.bob_ids))
+            .map(|(spec, &id)| BobSpec {
+                id: Some(id),
+                ..spec.clone()
+            })
+            .collect();
+        let world = config.world;
+        self.undoable(Edit::LoadSimulation, |data| {
+            let options = SetStateOptions {
+                pivot: Some(world.pivot),
+                ..SetStateOptions::default()
+            };
+            data.set_state(&bobs, options)?;
+            data.dt = world.dt;
+            data.time_scale = world.time_scale;
+            data.pendulum.damping = world.damping;
+            data.initial.damping = world.damping;
+            data.copies_follow_reference();
+            Ok(())
+        })?;
+        self.set_integrator(integrator);
+        self.sim_time = run.sim_time;
+        self.next_bob_id = self.next_bob_id.max(run.next_bob_id);
+        self.clock.elapsed = run.elapsed_time;
+        self.clock.steps = run.step_count;
+        self.energy_reference = run.energy_reference;
+        self.flips = run.flips;
+        self.transitions = run.transitions;
+        if let Some(ghost) = &mut self.ghost {
+            ghost.rewind(self.sim_time);
+        }
+        Ok(())
+    }
+
+    /// The chain, with its colors and labels, and the world settings, as a
+    /// string to paste somewhere; see `share::encode`. Bob ids stay behind.
+    pub(crate) fn export_state_string(&self) -> String {
+        share::encode(&Preset {
+            name: String::new(),
+            description: String::new(),
+            bobs: self.bob_specs(),
+            dt: self.dt,
+            time_scale: self.time_scale,
+            pivot: Some(self.pendulum.pivot),
+        })
+    }
+
+    /// Opens a link to the app, see `deeplink::parse`: a state string loads
+    /// as by `import_state_string`, bob parameters go through `set_state`.
+    /// A link that can't be opened changes nothing and sends a
+    /// `LinkRejected` event with the next tick instead.
+    pub(crate) fn open_link(&mut self, url: &str) {
+        let opened = deeplink::parse(url).and_then(|payload| match payload {
+            LinkPayload::Setup(setup) => {
+                self.undoable(Edit::ImportState, |data| data.apply_preset(&setup))
+            }
+            LinkPayload::Bobs(bobs) => self.set_state(&bobs, SetStateOptions::default()),
+        });
+        if let Err(error) = opened {
+            self.pending_events
+                .push(SimEvent::LinkRejected(LinkRejectedEvent {
+                    url: url.to_owned(),
+                    reason: error.to_string(),
+                }));
+        }
+    }
+
+    /// Loads a string from `export_state_string` like a preset, in one
+    /// undoable edit; a damaged string or bad values in it change nothing.
+    pub(crate) fn import_state_string(&mut self, s: &str) -> Result<(), CommandError> {
+        let setup = share::decode(s)?;
+        self.undoable(Edit::ImportState, |data| data.apply_preset(&setup))
+    }
+
+    /// Redraws the bobs from `ranges` with `seed`, or a random seed, through
+    /// `set_state`, so sim time and the energy baseline start over. Returns
+    /// the seed, which gives the same configuration again on the same chain.
+    pub(crate) fn randomize(
+        &mut self,
+        seed: Option<u64>,
+        ranges: &RandomRanges,
+    ) -> Result<u64, CommandError> {
+        self.undoable(Edit::Randomize, |data| {
+            ranges.validate()?;
+            let seed = seed.unwrap_or_else(rand::random);
+            let mut bobs = ranges.draw(&data.pendulum, seed);
+            for (spec, bob) in bobs.iter_mut().zip(&data.pendulum.bobs) {
+                spec.meta = data.bob_meta.get(&bob.id).cloned().unwrap_or_default();
+            }
+            data.set_state(&bobs, SetStateOptions::default())?;
+            Ok(seed)
+        })
+    }
+
+    /// Splices a bob into the chain at `index`, `len` appending. The angles
+    /// are absolute, so the bobs below keep their orientation and move with
+    /// the new rod; with `preserve_pose` the bob right below is instead
+    /// turned to point back at where it was, which keeps everything below in
+    /// place as nearly as its rod length allows.
+    pub(crate) fn insert_bob(
+        &mut self,
+        index: usize,
+        spec: BobSpec,
+        preserve_pose: bool,
+    ) -> Result<AddedBob, CommandError> {
+        self.undoable(Edit::InsertBob, |data| {
+            let len = data.pendulum.n();
+            if index > len {
+                return Err(CommandError::IndexOutOfBounds { index, len });
+            }
+            data.check_bob_count(len + 1)?;
+            spec.validate()?;
+            validate_meta(&spec.meta)?;
+            let old = data.pendulum.bobs.get(index).map(|bob| bob.coordinate);
+            let bob = data.new_bob(&spec);
+            data.pendulum.bobs.insert(index, bob);
+            data.pendulum.update_coordinates();
+            if let (true, Some(old)) = (preserve_pose, old) {
+                let new = data.pendulum.bobs[index].coordinate;
+                let below = &mut data.pendulum.bobs[index + 1];
+                let (dx, dy) = (old.x - new.x, old.y - new.y);
+                if dx != 0.0 || dy != 0.0 {
+                    // x = l sin θ, y = l cos θ; keep the same turn count
+                    let aimed = dx.atan2(dy);
+                    let turns = ((below.theta - aimed) / (2.0 * PI)).round();
+                    below.theta = aimed + 2.0 * PI * turns;
+                }
+            }
+            data.flips.suppress_next();
+            data.structure_changed(StructuralOperation::Added, index);
+            Ok(AddedBob { index, id: bob.id })
+        })
+    }
+
+    /// Appends a bob. Missing values copy the last bob's rod length and mass
+    /// and continue its angle, at rest; on an empty chain they are
+    /// `DEFAULT_LENGTH_ROD`, `DEFAULT_MASS` and `DEFAULT_THETA`.
+    pub(crate) fn add_bob(
+        &mut self,
+        length_rod: Option<f64>,
+        mass: Option<f64>,
+        theta: Option<f64>,
+        omega: Option<f64>,
+    ) -> Result<AddedBob, CommandError> {
+        self.undoable(Edit::AddBob, |data| {
+            let last = data.last_or_default();
+            let spec = BobSpec {
+                length_rod: length_rod.unwrap_or(last.length_rod),
+                mass: mass.unwrap_or(last.mass),
+                theta: theta.unwrap_or(last.theta),
+                omega: omega.unwrap_or(0.0),
+                id: None,
+                locked: false,
+                meta: BobMeta::default(),
+            };
+            data.insert_bob(data.pendulum.n(), spec, false)
+        })
+    }
+
+    /// Truncates or extends the chain to `count` bobs, leaving the ones that
+    /// stay as they are. New bobs are made to `template`, or else copy the
+    /// last bob, or `add_bob`'s defaults on an empty chain. An empty chain
+    /// is allowed; it just idles. Subscribers get one `Resized` notice for
+    /// the lot.
+    pub(crate) fn set_bob_count(
+        &mut self,
+        count: usize,
+        template: Option<BobSpec>,
+    ) -> Result<(), CommandError> {
+        self.undoable(Edit::SetBobCount, |data| {
+            data.check_bob_count(count)?;
+            let template = match template {
+                Some(spec) => spec,
+                None => {
+                    let last = data.last_or_default();
+                    BobSpec {
+                        length_rod: last.length_rod,
+                        mass: last.mass,
+                        theta: last.theta,
+                        omega: last.omega,
+                        id: None,
+                        locked: false,
+                        meta: BobMeta::default(),
+                    }
+                }
+            };
+            template.validate()?;
+            validate_meta(&template.meta)?;
+            let first_changed = count.min(data.pendulum.n());
+            if count == data.pendulum.n() {
+                return Ok(());
+            }
+            data.pendulum.bobs.truncate(count);
+            while data.pendulum.n() < count {
+                let bob = data.new_bob(&template);
+                data.pendulum.bobs.push(bob);
+            }
+            data.flips.suppress_next();
+            data.structure_changed(StructuralOperation::Resized, first_changed);
+            Ok(())
+        })
+    }
+
+    /// Refuses to grow the chain to `count` bobs if that is more than
+    /// `max_bobs`.
+    fn check_bob_count(&self, count: usize) -> Result<(), CommandError> {
+        if count > self.max_bobs && count > self.pendulum.n() {
+            Err(CommandError::TooManyBobs { max: self.max_bobs })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Changes how many bobs edits may leave the chain with. A chain already
+    /// longer stays as it is, but can't grow. Returns a warning past
+    /// `BOB_COUNT_CEILING`.
+    pub(crate) fn set_max_bobs(&mut self, max: usize) -> Result<Option<String>, CommandError> {
+        if max == 0 {
+            return Err(CommandError::invalid("max", "must be at least 1"));
+        }
+        self.max_bobs = max;
+        Ok((max > BOB_COUNT_CEILING).then(|| {
+            format!(
+                "More than {BOB_COUNT_CEILING} bobs can make every step slow enough that the \
+                 simulation falls behind wall time"
+            )
+        }))
+    }
+
+    /// Where the bob with `id` currently is in the chain.
+    pub(crate) fn index_of(&self, id: u64) -> Result<usize, CommandError> {
+        self.pendulum
+            .bobs
+            .iter()
+            .position(|bob| bob.id == id)
+            .ok_or_else(|| CommandError::not_found("bob with id", id))
+    }
+
+    pub(crate) fn remove_bob(&mut self, index: usize) -> Result<(), CommandError> {
+        self.undoable(Edit::RemoveBob, |data| {
+            let len = data.pendulum.n();
+            if index >= len {
+                return Err(CommandError::IndexOutOfBounds { index, len });
+            }
+            data.pendulum.bobs.remove(index);
+            data.structure_changed(StructuralOperation::Removed, index);
+            Ok(())
+        })
+    }
+
+    /// What new bobs are modelled on when nothing is given.
+    fn last_or_default(&self) -> Bob {
+        self.pendulum.bobs.last().map_or(
+            Bob::new(DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA, 0.0),
+            |bob| *bob,
+        )
+    }
+
+    /// A bob made to `spec` with the next id.
+    fn new_bob(&mut self, spec: &BobSpec) -> Bob {
+        let mut bob = spec.bob();
+        bob.id = self.next_bob_id;
+        self.next_bob_id += 1;
+        self.set_meta(bob.id, spec.meta.clone());
+        bob
+    }
+
+    fn set_meta(&mut self, id: u64, meta: BobMeta) {
+        if meta.is_empty() {
+            self.bob_meta.remove(&id);
+        } else {
+            self.bob_meta.insert(id, meta);
+        }
+    }
+
+    /// Replaces bob `index`'s color and label. They only matter to the
+    /// frontends, so this is no edit of the pendulum: nothing to undo, and
+    /// the simulation carries on as it was.
+    pub(crate) fn set_bob_meta(&mut self, index: usize, meta: BobMeta) -> Result<(), CommandError> {
+        let len = self.pendulum.n();
+        let id = (self.pendulum.bobs.get(index))
+            .ok_or(CommandError::IndexOutOfBounds { index, len })?
+            .id;
+        validate_meta(&meta)?;
+        self.set_meta(id, meta);
+        Ok(())
+    }
+
+    /// Puts sim time back to 0. The recording in progress is finished, since
+    /// its time would run backwards, and a ghost starts over.
+    fn restart_sim_time(&mut self) {
+        self.sim_time = 0.0;
+        self.transitions.clear();
+        self.finish_recording();
+        if let Some(ghost) = &mut self.ghost {
+            ghost.rewind(self.sim_time);
+        }
+    }
+
+    /// Ends the recording in progress, if any, and keeps it under a new id.
+    pub(crate) fn finish_recording(&mut self) -> Option<RecordingSummary> {
+        let recording = self.recording.take()?;
+        Some(self.keep_recording(recording))
+    }
+
+    /// Keeps `recording` under a new id, for ghosts and replays, dropping
+    /// the oldest past `MAX_KEPT_RECORDINGS`.
+    pub(crate) fn keep_recording(&mut self, recording: Recording) -> RecordingSummary {
+        let id = self.next_recording_id;
+        self.next_recording_id += 1;
+        let summary = RecordingSummary::new(id, &recording);
+        self.recordings.push((id, Arc::new(recording)));
+        if self.recordings.len() > MAX_KEPT_RECORDINGS {
+            self.recordings.remove(0);
+        }
+        summary
+    }
+
+    /// Drops the recording `id`, returning whether it was kept. A ghost or
+    /// replay of it plays on.
+    pub(crate) fn delete_recording(&mut self, id: u64) -> bool {
+        let kept = self.recordings.len();
+        self.recordings.retain(|(kept_id, _)| *kept_id != id);
+        self.recordings.len() < kept
+    }
+
+    /// Tells every subscriber that the positions frames switched between
+    /// the live run and the replay, or jumped within the replay. Unlike
+    /// `structure_changed` this leaves the live run's history alone.
+    pub(crate) fn replay_changed(&mut self) {
+        let bob_count = (self.replay.as_ref()).map_or(self.pendulum.n(), |r| r.pendulum().n());
+        let change = StructuralChange {
+            operation: StructuralOperation::Replay,
+            index: 0,
+            bob_count,
+        };
+        for subscriber in &mut self.subscribers {
+            subscriber.notify_structural_change(change);
+        }
+    }
+
+    /// Bookkeeping after bobs were edited in place. The positions are redone
+    /// at once, so the edit shows even while paused.
+    pub(crate) fn state_edited(&mut self) {
+        self.pendulum.update_coordinates();
+        self.energy_reference = None;
+        self.flips.suppress_next();
+        self.copies_follow_reference();
+        self.drop_stale_transitions();
+    }
+
+    /// The ensemble and the comparison's clone after an edit, per their
+    /// edit policies.
+    fn copies_follow_reference(&mut self) {
+        if let Some(ensemble) = self.ensemble.take() {
+            self.ensemble = ensemble.follow(&self.pendulum);
+        }
+        self.comparison_follows();
+    }
+
+    /// Takes one integration step of `dt` with everything that rides along:
+    /// the counters, NaN recovery, the ensemble, the recording, and flip and
+    /// alert detection, whose events are added to `events`.
+    pub(crate) fn substep(&mut self, dt: f64, events: &mut Vec<SimEvent>) {
+        self.rewind_buffer.observe(&self.pendulum, self.sim_time);
+        self.trails.observe(&self.pendulum, self.sim_time);
+        (self.motion_bounds).observe(&self.pendulum, &self.trails, self.sim_time);
+        let before = self.pendulum.bobs.clone();
+        let started = std::time::Instant::now();
+        let prescribed = self.transition_accelerations();
+        let regular = self.pendulum.step_prescribed(dt, &prescribed);
+        self.counters.record_step(dt, started.elapsed(), !regular);
+        if !regular {
+            let sim_time = self.sim_time;
+            tracing::warn!(
+                sim_time,
+                "Singular mass matrix; stepped with zero accelerations"
+            );
+        }
+        if !self.pendulum.is_finite() {
+            // blown up: back to the last good angles, at rest
+            self.pendulum.bobs = before;
+            for bob in &mut self.pendulum.bobs {
+                bob.omega = 0.0;
+            }
+            self.counters.nan_recoveries += 1;
+            let sim_time = self.sim_time;
+            tracing::warn!(
+                sim_time,
+                "The state blew up; put back at rest at the last good angles"
+            );
+        }
+        self.occupancy.observe(&self.pendulum);
+        self.sim_time += dt;
+        self.clock.record(dt);
+        self.advance_transitions();
+        if let Some(ensemble) = &mut self.ensemble {
+            ensemble.step(dt);
+        }
+        if let Some(comparison) = &mut self.comparison {
+            comparison.step(dt);
+        }
+        if let Some(wave) = &mut self.wave {
+            wave.step(dt);
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.push(&self.pendulum, self.sim_time);
+        }
+        if let Some(file) = &mut self.trajectory_recording {
+            if let Err(reason) = file.push(&self.pendulum, self.sim_time) {
+                events.push(SimEvent::RecordingFailed(file.failed(reason)));
+                self.trajectory_recording = None;
+            }
+        }
+        let flips = self.flips.observe(&self.pendulum, self.sim_time, dt);
+        events.extend(flips.into_iter().map(SimEvent::Flip));
+        let alerts = self.alerts.evaluate(&self.pendulum, self.sim_time);
+        events.extend(alerts.into_iter().map(SimEvent::Alert));
+    }
+
+    /// Takes up to `steps` substeps of `dt`, stopping early once `budget` of
+    /// wall time is spent, so a chain too long to step in time slows the
+    /// simulation down instead of stalling the app. One is taken if any is
+    /// due. The sim time skipped counts as deficit, and the first tick of a
+    /// stretch that falls short adds an `Overloaded` event to `events`.
+    /// Returns the substeps taken.
+    pub(crate) fn substeps_within(
+        &mut self,
+        steps: u32,
+        dt: f64,
+        budget: Duration,
+        events: &mut Vec<SimEvent>,
+    ) -> u32 {
+        let started = std::time::Instant::now();
+        let mut taken = 0;
+        while taken < steps {
+            self.substep(dt, events);
+            taken += 1;
+            if started.elapsed() >= budget {
+                break;
+            }
+        }
+        let short = taken < steps;
+        if short {
+            self.clock.deficit += f64::from(steps - taken) * dt;
+            if !self.overloaded {
+                events.push(SimEvent::Overloaded(OverloadEvent {
+                    substeps: taken,
+                    due: steps,
+                    sim_time: self.sim_time,
+                }));
+            }
+        }
+        self.overloaded = short;
+        taken
+    }
+
+    /// Puts things right after a command or tick panicked, which may have
+    /// stopped an edit halfway, so the commands after it and the stepping
+    /// carry on. The flags only raised while an edit or a warm-up runs are
+    /// lowered; a running warm-up raises its own again on its next chunk.
+    /// If the chain, pivot or world settings are left invalid they go back
+    /// to the defaults, with sim time starting over and subscribers getting
+    /// a `Replaced` notice. Either way a `Recovered` event goes out with the
+    /// next tick.
+    pub(crate) fn recover(&mut self) {
+        self.history.in_edit = false;
+        self.warming_up = false;
+        let valid_bobs = |pendulum: &Pendulum| {
+            pendulum.bobs.iter().all(|b| {
+                [
+                    ("length_rod", b.length_rod),
+                    ("mass", b.mass),
+                    ("theta", b.theta),
+                    ("omega", b.omega),
+                ]
+                .into_iter()
+                .all(|(field, value)| validate_parameter(field, value).is_ok())
+            }) && validate_pivot(pendulum.pivot).is_ok()
+        };
+        let reset = !(valid_bobs(&self.pendulum)
+            && valid_bobs(&self.initial)
+            && self.dt.is_finite()
+            && self.dt > 0.0
+            && self.time_scale > 0.0
+            && self.time_scale <= MAX_TIME_SCALE
+            && self.sim_time.is_finite());
+        if reset {
+            let bobs = Pendulum::default()
+                .bobs
+                .into_iter()
+                .map(|bob| {
+                    let id = self.next_bob_id;
+                    self.next_bob_id += 1;
+                    Bob { id, ..bob }
+                })
+                .collect();
+            self.pendulum = Pendulum::new(bobs);
+            self.initial = self.pendulum.clone();
+            self.dt = DEFAULT_DT;
+            self.time_scale = 1.0;
+            self.restart_sim_time();
+            self.flips.suppress_next();
+            self.structure_changed(StructuralOperation::Replaced, 0);
+        }
+        self.pending_events.push(SimEvent::Recovered(RecoveryEvent {
+            reset,
+            sim_time: self.sim_time,
+        }));
+    }
+
+    /// Milliseconds from `epoch` to `now`.
+    pub(crate) fn wall_time_ms(&self, now: Instant) -> f64 {
+        (now - self.epoch).as_secs_f64() * 1000.0
+    }
+
+    /// Why stepping is paused, if it is. A warm-up is reported first, since
+    /// the simulation is not standing still then; a user pause wins over a
+    /// background one, since it outlasts the window coming back.
+    pub(crate) fn pause_reason(&self) -> Option<PauseReason> {
+        if self.warming_up {
+            Some(PauseReason::WarmingUp)
+        } else if self.replay.is_some() {
+            Some(PauseReason::Replaying)
+        } else if self.user_paused {
+            Some(PauseReason::User)
+        } else if self.in_background && self.pause_in_background {
+            Some(PauseReason::Background)
+        } else {
+            None
+        }
+    }
+
+    /// Refits the analytic overlay to the current state, if it is enabled.
+    pub(crate) fn resync_analytic(&mut self) {
+        if self.analytic.is_some() {
+            self.analytic = LinearSolution::fit(&self.pendulum, self.sim_time);
+        }
+    }
+
+    /// Builds the streamed state; `frame` counts the frames built so far. The
+    /// sequence number and span are filled in per subscriber when the frame
+    /// is sent. Everything else describes the state after the tick's last
+    /// substep, however many substeps the tick took.
+    pub(crate) fn frame(&self, frame: u64, wall_time_ms: f64) -> PendulumState {
+        let alphas = self.pendulum.accelerations();
+        let bob_states: Vec<BobState> = self
+            .pendulum
+            .bobs
+            .iter()
+            .zip(alphas)
+            .map(|(bob, alpha)| BobState {
+                id: bob.id,
+                theta: bob.theta,
+                position: bob.coordinate,
+                mass: bob.mass,
+                length_rod: bob.length_rod,
+                omega: bob.omega,
+                alpha,
+                locked: bob.locked,
+                meta: self.bob_meta.get(&bob.id).cloned().unwrap_or_default(),
+            })
+            .collect();
+        let analytic = self
+            .analytic
+            .as_ref()
+            .map(|linear| AnalyticState::new(linear, &self.pendulum, self.sim_time));
+        let dynamics = self
+            .dynamics_overlay_every
+            .filter(|&every| frame.is_multiple_of(u64::from(every)))
+            .map(|_| self.pendulum.dynamics_terms());
+        PendulumState {
+            schema: FRAME_SCHEMA_VERSION,
+            seq: 0,
+            span: 0.0,
+            sim_time: self.sim_time,
+            wall_time_ms,
+            time_deficit: self.clock.deficit,
+            elapsed_time: self.clock.elapsed,
+            step_count: self.clock.steps,
+            tick_interval: self.tick_meter.average,
+            paused: self.pause_reason(),
+            slow_motion: self.slow_motion.as_ref().and_then(SlowMotion::active),
+            pixels_per_meter: self.pixels_per_meter,
+            pivot: self.pendulum.pivot,
+            bobs: bob_states,
+            analytic,
+            dynamics,
+            ghost_bobs: (self.ghost.as_ref())
+                .and_then(|g| g.bobs_at(self.sim_time, self.pendulum.pivot)),
+            ensemble: self.ensemble.as_ref().map(Ensemble::state),
+            comparison: (self.comparison.as_ref()).map(|c| c.state(&self.pendulum)),
+            wave: (self.wave.as_ref()).map(|w| w.state(&self.pendulum)),
+            bounds: self.motion_bounds.for_frame(frame, &self.pendulum),
+            replay: false,
+            transitions: (self.transitions.iter())
+                .map(|t| t.state(self.sim_time))
+                .collect(),
+            structure_changes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn config_summary(&self) -> ConfigSummary {
+        ConfigSummary {
+            bob_count: self.pendulum.n(),
+            integrator: self.pendulum.integrator.id().into(),
+            gravity: GRAVITATIONAL_ACCELERATION,
+            dt: self.dt,
+            time_scale: self.time_scale,
+            tick_period: self.tick_period.as_secs_f64(),
+        }
+    }
+
+    /// How commands take and return angles.
+    pub(crate) fn angle_format(&self) -> AngleFormat {
+        AngleFormat {
+            unit: self.angle_unit,
+            convention: self.angle_convention,
+        }
+    }
+
+    /// The current state and configuration in one piece, for reading them
+    /// without a stream. Built under one lock, so it never mixes substeps.
+    /// `get_state`'s result, its angles given per `theta_policy`, in
+    /// `angle_convention` and in `angle_unit`, and its positions in
+    /// `world_frame`. The angles are wrapped once in the convention, so
+    /// wrapped angles stay in (-π, π] there.
+    pub(crate) fn snapshot(&self, wall_time_ms: f64, theta_policy: ThetaPolicy) -> StateSnapshot {
+        let mut snapshot = self.angle_convention.outgoing(StateSnapshot {
+            state: self.frame(0, wall_time_ms),
+            energetics: self.energetics_frame(wall_time_ms),
+            config: self.config_summary(),
+            angle_unit: self.angle_unit,
+            angle_convention: self.angle_convention,
+            world_frame: self.world_frame,
+        });
+        theta_policy.present(&mut snapshot.state);
+        self.world_frame
+            .outgoing(self.angle_unit.outgoing(snapshot))
+    }
+
+    pub(crate) fn energetics_frame(&self, wall_time_ms: f64) -> EnergeticsFrame {
+        let pendulum = &self.pendulum;
+        let kinetic = pendulum.kinetic_energy();
+        let potential = pendulum.potential_energy();
+        EnergeticsFrame {
+            seq: 0,
+            sim_time: self.sim_time,
+            wall_time_ms,
+            kinetic,
+            potential,
+            total: kinetic + potential,
+            momenta: pendulum.momenta(),
+            tensions: pendulum.rod_tensions(&pendulum.accelerations()),
+        }
+    }
+
+    pub(crate) fn diagnostics_frame(&mut self, wall_time_ms: f64) -> DiagnosticsFrame {
+        let energy = self.pendulum.total_energy();
+        let reference = *self.energy_reference.get_or_insert(energy);
+        DiagnosticsFrame {
+            seq: 0,
+            sim_time: self.sim_time,
+            wall_time_ms,
+            condition_number: self.pendulum.mass_matrix_condition(),
+            heartbeat: self.heartbeat,
+            substeps: self.last_substeps,
+            energy_drift: (energy - reference) / reference.abs(),
+            time_deficit: self.clock.deficit,
+            tick_interval: self.tick_meter.average,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BobState {
+    /// Stays with the bob as others are added or removed around it.
+    pub(crate) id: u64,
+    pub(crate) theta: f64,
+    pub(crate) omega: f64,
+    pub(crate) position: Coordinate,
+    pub(crate) mass: f64,
+    pub(crate) length_rod: f64,
+    /// Angular acceleration in rad/s².
+    pub(crate) alpha: f64,
+    /// See `set_joint_locked`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) locked: bool,
+    /// Color and label, see `set_bob_meta`.
+    #[serde(flatten)]
+    pub(crate) meta: BobMeta,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PendulumState {
+    /// `FRAME_SCHEMA_VERSION` of the server that built the frame.
+    pub(crate) schema: u32,
+    /// Position of this frame in its subscription, starting at 0 and going up
+    /// by one per frame sent. Never reset, not even with the simulation, so a
+    /// gap always means a lost frame.
+    pub(crate) seq: u64,
+    /// Sim seconds since the previous frame of the subscription, 0 for the
+    /// first. The interval to interpolate over; see `HERMITE_INTERPOLATION`.
+    pub(crate) span: f64,
+    /// Simulation time of this frame in seconds. Restarts at 0 whenever the
+    /// simulation is reset.
+    pub(crate) sim_time: f64,
+    /// Wall-clock milliseconds since `AppDataInner::epoch` when the frame was
+    /// produced, on the same clock as `ping`. Never reset; use it for latency
+    /// and rate measurements.
+    pub(crate) wall_time_ms: f64,
+    /// Sim seconds dropped so far because the physics couldn't keep up with
+    /// wall time. Growing means the machine is overloaded.
+    pub(crate) time_deficit: f64,
+    /// Sim seconds integrated since the last reset: every substep's dt
+    /// added up, paused time and dropped deficit not included. It only ever
+    /// grows until `reset`, even when `sim_time` jumps for a restore,
+    /// rewind or replaced state, so it is the time base for anything that
+    /// runs along with the simulation.
+    pub(crate) elapsed_time: f64,
+    /// Substeps integrated since the last reset.
+    pub(crate) step_count: u64,
+    /// Measured seconds between physics ticks, averaged. Compare with the
+    /// configured tick period to spot scheduling trouble.
+    pub(crate) tick_interval: Option<f64>,
+    /// Why the simulation is standing still, if it is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) paused: Option<PauseReason>,
+    /// Multiplier automatic slow motion applies to the time scale, present
+    /// while it is engaged or ramping back to full speed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) slow_motion: Option<f64>,
+    /// How many pixels to draw each meter of the positions and lengths as.
+    pub(crate) pixels_per_meter: f64,
+    /// Where the first rod hangs from, in the frame of the bobs' positions.
+    pub(crate) pivot: Coordinate,
+    pub(crate) bobs: Vec<BobState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) analytic: Option<AnalyticState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dynamics: Option<DynamicsTerms>,
+    /// The playing ghost's bobs, in the recording's layout, which need not
+    /// match the live one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ghost_bobs: Option<Vec<GhostBobState>>,
+    /// Tips of the ensemble copies; the bobs above are its reference.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ensemble: Option<EnsembleState>,
+    /// The damped clone of `start_comparison` and how far it has drifted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) comparison: Option<ComparisonState>,
+    /// Every tip of the pendulum wave this instance leads, its own first.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) wave: Option<WaveState>,
+    /// The box the motion has kept to lately, with every n-th frame; see
+    /// `set_motion_bounds`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) bounds: Option<Bounds>,
+    /// Built from a recording by `start_replay` rather than the live run;
+    /// `sim_time` is then on the recording's axis.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) replay: bool,
+    /// Bobs easing to an edited angle, see `set_pose_transition`. Until they
+    /// are handed back, their motion is the ease and not the dynamics.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) transitions: Vec<TransitionState>,
+    /// Bobs added or removed since the subscription's previous frame, oldest
+    /// first. Present exactly on the first frame with the new layout, so
+    /// per-bob client state can be migrated before it is used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) structure_changes: Vec<StructuralChange>,
+}
+
+/// What `get_state` returns. `state` is shaped like a positions frame but
+/// belongs to no subscription, so its `seq` and `span` are 0; whether the
+/// simulation is paused is its `paused`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StateSnapshot {
+    pub(crate) state: PendulumState,
+    pub(crate) energetics: EnergeticsFrame,
+    pub(crate) config: ConfigSummary,
+    /// Unit of the angles in `state`, unlike a streamed frame's.
+    pub(crate) angle_unit: AngleUnit,
+    /// Convention of the angles in `state`, also unlike a streamed frame's.
+    pub(crate) angle_convention: AngleConvention,
+    /// Frame of the positions in `state`, also unlike a streamed frame's.
+    pub(crate) world_frame: WorldFrame,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PauseReason {
+    /// `warm_up` is skipping ahead; the next frame jumps in sim time.
+    WarmingUp,
+    /// `set_paused` from the UI.
+    User,
+    /// Every window is hidden or minimized.
+    Background,
+    /// `start_replay` is playing a recording in the live run's place.
+    Replaying,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum StructuralOperation {
+    Added,
+    Removed,
+    /// Everything went back to the initial conditions, possibly with a
+    /// different layout; trails and other history no longer apply.
+    Reset,
+    /// `set_state` or an undo swapped in a whole new chain; as with
+    /// `Reset`, history no longer applies.
+    Replaced,
+    /// `set_bob_count` added or removed bobs from `index` on.
+    Resized,
+    /// `restore_snapshot` went back to a saved moment; as with `Reset`,
+    /// history no longer applies.
+    Restored,
+    /// `rewind` went back in sim time; as with `Reset`, history no longer
+    /// applies.
+    Rewound,
+    /// The frames switched between the live run and a replay, or the replay
+    /// jumped; the live run's history is kept, but frames before the change
+    /// don't lead up to the next.
+    Replay,
+}
+
+impl StructuralOperation {
+    /// Whether everything before the change is history, rather than one bob
+    /// coming or going.
+    pub(crate) fn starts_over(self) -> bool {
+        matches!(
+            self,
+            Self::Reset | Self::Replaced | Self::Restored | Self::Rewound | Self::Replay
+        )
+    }
+
+    /// What the change was, for messages.
+    pub(crate) fn describe(self) -> &'static str {
+        match self {
+            Self::Added => "addition of a bob",
+            Self::Removed => "removal of a bob",
+            Self::Reset => "reset",
+            Self::Replaced => "replacement of the chain",
+            Self::Resized => "resizing of the chain",
+            Self::Restored => "restored snapshot",
+            Self::Rewound => "rewind",
+            Self::Replay => "replay",
+        }
+    }
+}
+
+/// Where a new bob ended up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AddedBob {
+    pub(crate) index: usize,
+    /// The bob's `id` in the frames, which stays put as indices shift.
+    pub(crate) id: u64,
+}
+
+/// One entry of `modify_bobs`: the bob, by either `index` or `id`, and the
+/// values to give it. Missing values stay as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BobUpdate {
+    pub(crate) index: Option<usize>,
+    pub(crate) id: Option<u64>,
+    pub(crate) length: Option<f64>,
+    pub(crate) mass: Option<f64>,
+    pub(crate) theta: Option<f64>,
+    pub(crate) omega: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ModifiedBob {
+    pub(crate) index: usize,
+    pub(crate) id: u64,
+    /// Names of the fields that got a different value, as in `BobSpec`
+    /// errors.
+    pub(crate) changed: Vec<&'static str>,
+}
+
+/// What `modify_bob` and `modify_bobs` do to the angular velocities when they
+/// move angles without giving velocities, which were the old pose's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PoseEditPolicy {
+    /// They stay, whatever that does to the energy.
+    #[default]
+    KeepOmega,
+    /// Every bob stops.
+    ZeroOmega,
+    /// They are scaled so the total energy is what it was before the edit,
+    /// or zeroed if the new pose alone has more; see
+    /// `Pendulum::rescale_to_energy`.
+    PreserveEnergy,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct SetStateOptions {
+    /// Carry on from the current sim time instead of starting over at 0.
+    pub(crate) keep_sim_time: bool,
+    /// Where to move the pivot; it stays put if `None`.
+    pub(crate) pivot: Option<Coordinate>,
+    /// The rod lengths and the pivot are in pixels, as states saved before
+    /// lengths were in meters have them, and are read at
+    /// `DEFAULT_PIXELS_PER_METER`.
+    pub(crate) lengths_in_pixels: bool,
+}
+
+fn validate_meta(meta: &BobMeta) -> Result<(), CommandError> {
+    for (name, value) in [("color", &meta.color), ("label", &meta.label)] {
+        let len = value.as_ref().map_or(0, |v| v.chars().count());
+        if len > MAX_META_LEN {
+            return Err(CommandError::invalid(
+                name,
+                format!("must be at most {MAX_META_LEN} characters, got {len}"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_pivot(pivot: Coordinate) -> Result<(), CommandError> {
+    if pivot.x.is_finite() && pivot.y.is_finite() {
+        Ok(())
+    } else {
+        Err(CommandError::invalid(
+            "pivot",
+            format!("must be finite, got ({}, {})", pivot.x, pivot.y),
+        ))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StructuralChange {
+    pub(crate) operation: StructuralOperation,
+    /// Index of the added or removed bob, or of the first one a resize
+    /// touched; 0 when the whole chain changed.
+    pub(crate) index: usize,
+    /// Number of bobs after the change.
+    pub(crate) bob_count: usize,
+}
+
+/// Frame of the `energetics` topic.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EnergeticsFrame {
+    pub(crate) seq: u64,
+    pub(crate) sim_time: f64,
+    pub(crate) wall_time_ms: f64,
+    pub(crate) kinetic: f64,
+    pub(crate) potential: f64,
+    pub(crate) total: f64,
+    /// Generalized momentum of each joint.
+    pub(crate) momenta: Vec<f64>,
+    /// Tension in each rod, positive when pulling.
+    pub(crate) tensions: Vec<f64>,
+}
+
+/// Frame of the `diagnostics` topic.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DiagnosticsFrame {
+    pub(crate) seq: u64,
+    pub(crate) sim_time: f64,
+    pub(crate) wall_time_ms: f64,
+    /// Physics ticks so far. Keeps counting while stepping is paused or
+    /// frames are suppressed, as long as the physics task is alive.
+    pub(crate) heartbeat: u64,
+    /// Condition number of the mass matrix.
+    pub(crate) condition_number: f64,
+    /// Substeps taken by the tick that produced the frame.
+    pub(crate) substeps: u32,
+    /// (E - E_ref) / |E_ref|, with E_ref the energy when the drift
+    /// measurement started or the pendulum was last edited.
+    pub(crate) energy_drift: f64,
+    pub(crate) time_deficit: f64,
+    pub(crate) tick_interval: Option<f64>,
+}
+
+/// What a subscriber's drain task actually sends. Untagged, so each variant
+/// goes out as its bare frame. Most frames are full ones, so they aren't
+/// boxed to make the rarer kinds smaller.
+#[derive(Clone, Serialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum StreamFrame {
+    Full(PendulumState),
+    Compact(CompactFrame),
+    Energetics(EnergeticsFrame),
+    Diagnostics(DiagnosticsFrame),
+}
+
+/// Describes the frames, for clients that check what they are talking to.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FrameSchema {
+    pub(crate) version: u32,
+    pub(crate) interpolation: &'static str,
+    /// Unit of every angle in the frames, which is radians whatever
+    /// `set_angle_unit` says.
+    pub(crate) angle_unit: AngleUnit,
+    /// Convention of every angle in the frames, the simulation's own
+    /// whatever `set_angle_convention` says.
+    pub(crate) angle_convention: AngleConvention,
+    /// Frame of every position in the frames, y up whatever
+    /// `set_world_frame` says.
+    pub(crate) world_frame: WorldFrame,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AnalyticBobState {
+    pub(crate) theta: f64,
+    pub(crate) position: Coordinate,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GhostBobState {
+    pub(crate) theta: f64,
+    pub(crate) position: Coordinate,
+}
+
+/// Default decimation of the dynamics overlay: the terms are O(n²), so they
+/// go out at roughly a tenth of the frame rate.
+pub(crate) const DYNAMICS_OVERLAY_EVERY: u32 = 10;
+
+/// The linearized solution evaluated at the frame's sim time, next to the
+/// full nonlinear state.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AnalyticState {
+    pub(crate) bobs: Vec<AnalyticBobState>,
+    /// Per-bob angle of the nonlinear state minus the linear one, in rad.
+    pub(crate) discrepancy: Vec<f64>,
+}
+
+impl AnalyticState {
+    pub(crate) fn new(linear: &LinearSolution, pendulum: &Pendulum, t: f64) -> Self {
+        let thetas = linear.thetas_at(t);
+        let positions = pendulum.positions_for(&thetas);
+        Self {
+            bobs: thetas
+                .iter()
+                .zip(positions)
+                .map(|(&theta, position)| AnalyticBobState { theta, position })
+                .collect(),
+            discrepancy: linear.discrepancy(pendulum, t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::randomize::ValueRange;
+    use crate::units::validate_pixels_per_meter;
+    use pendulum_core::{minimum, InvalidBob, INTEGRATOR, MIN_LENGTH_ROD, MIN_MASS};
+
+    /// `HERMITE_INTERPOLATION` for one angle.
+    fn hermite(a: &BobState, b: &BobState, h: f64, s: f64) -> f64 {
+        let (s2, s3) = (s * s, s * s * s);
+        (2.0 * s3 - 3.0 * s2 + 1.0) * a.theta
+            + (s3 - 2.0 * s2 + s) * h * a.omega
+            + (-2.0 * s3 + 3.0 * s2) * b.theta
+            + (s3 - s2) * h * b.omega
+    }
+
+    #[test]
+    fn hermite_spans_follow_varying_substep_counts() {
+        let mut data = AppDataInner::default();
+        for bob in &mut data.pendulum.bobs {
+            bob.omega = 2.0;
+        }
+        let dt = 0.002;
+        let mut previous = data.frame(0, 0.0);
+        let (mut hermite_error, mut lerp_error) = (0.0_f64, 0.0_f64);
+        for (frame, substeps) in [1, 4, 7, 2, 9, 5].into_iter().cycle().take(60).enumerate() {
+            // the true trajectory at every substep inside the span
+            let mut inside = Vec::new();
+            for _ in 0..substeps {
+                data.pendulum.step(dt);
+                data.sim_time += dt;
+                inside.push((data.sim_time, data.pendulum.bobs.clone()));
+            }
+            let next = PendulumState {
+                span: data.sim_time - previous.sim_time,
+                ..data.frame(frame as u64 + 1, 0.0)
+            };
+            assert!((next.span - substeps as f64 * dt).abs() < 1e-12);
+            for (t, bobs) in &inside {
+                let s = (t - previous.sim_time) / next.span;
+                for ((a, b), truth) in previous.bobs.iter().zip(&next.bobs).zip(bobs) {
+                    hermite_error =
+                        hermite_error.max((hermite(a, b, next.span, s) - truth.theta).abs());
+                    let lerp = a.theta + s * (b.theta - a.theta);
+                    lerp_error = lerp_error.max((lerp - truth.theta).abs());
+                }
+            }
+            for (bob, alpha) in next.bobs.iter().zip(data.pendulum.accelerations()) {
+                assert_eq!(bob.alpha, alpha);
+            }
+            previous = next;
+        }
+        assert!(
+            hermite_error < lerp_error / 4.0,
+            "{hermite_error} vs {lerp_error}"
+        );
+    }
+
+    #[test]
+    fn set_state_swaps_everything_or_nothing() {
+        let mut data = AppDataInner::default();
+        for _ in 0..10 {
+            data.substep(DEFAULT_DT, &mut Vec::new());
+        }
+        let before = data.pendulum.clone();
+        let spec = |mass| BobSpec {
+            length_rod: 2.0,
+            mass,
+            theta: 0.5,
+            omega: 1.0,
+            id: None,
+            locked: false,
+            meta: BobMeta::default(),
+        };
+        let error = data
+            .set_state(&[spec(1.0), spec(-1.0)], SetStateOptions::default())
+            .unwrap_err();
+        assert_eq!(
+            error,
+            CommandError::invalid("bobs[1].mass", "must be at least 0.000001, got -1")
+        );
+        assert_eq!(data.pendulum, before);
+        assert!(data.sim_time > 0.0);
+
+        let sim_time = data.sim_time;
+        let keep = SetStateOptions {
+            keep_sim_time: true,
+            ..SetStateOptions::default()
+        };
+        data.set_state(&[spec(1.0), spec(3.0)], keep).unwrap();
+        assert_eq!(data.sim_time, sim_time);
+        assert_eq!(data.pendulum.n(), 2);
+        let tip = data.pendulum.bobs[1].coordinate;
+        assert!((tip.x - 4.0 * 0.5f64.sin()).abs() < 1e-12);
+        assert!((tip.y - 4.0 * 0.5f64.cos()).abs() < 1e-12);
+        assert_eq!(data.energy_reference, None);
+
+        data.set_state(&[], SetStateOptions::default()).unwrap();
+        assert_eq!((data.pendulum.n(), data.sim_time), (0, 0.0));
+    }
+
+    #[test]
+    fn inserted_bobs_can_keep_the_pose_below() {
+        let spec = BobSpec {
+            length_rod: 50.0,
+            mass: 5.0,
+            theta: 1.0,
+            omega: 0.0,
+            id: None,
+            locked: false,
+            meta: BobMeta::default(),
+        };
+        let mut data = AppDataInner::default();
+        data.pendulum.update_coordinates();
+        let before = data.pendulum.clone();
+        assert!(data.insert_bob(5, spec.clone(), false).is_err());
+        assert!(data
+            .insert_bob(
+                0,
+                BobSpec {
+                    mass: 0.0,
+                    ..spec.clone()
+                },
+                false
+            )
+            .is_err());
+        assert_eq!(data.pendulum, before);
+
+        // the bob below is re-aimed at where it was, so everything below
+        // moves only by how far its rod falls short of reaching back
+        data.insert_bob(1, spec.clone(), true).unwrap();
+        assert_eq!(data.pendulum.n(), 5);
+        let after = &data.pendulum.bobs;
+        let inserted = after[1].coordinate;
+        for (old, new) in before.bobs[1..].iter().zip(&after[2..]) {
+            let moved =
+                (old.coordinate.x - new.coordinate.x).hypot(old.coordinate.y - new.coordinate.y);
+            // off by how much the rod falls short of reaching back
+            let reach = (before.bobs[1].coordinate.x - inserted.x)
+                .hypot(before.bobs[1].coordinate.y - inserted.y);
+            assert!((moved - (reach - old.length_rod).abs()).abs() < 1e-9);
+        }
+        assert_eq!(after[3].theta, before.bobs[2].theta);
+
+        // without it the bobs below just move with the new rod
+        let mut data = AppDataInner::default();
+        data.pendulum.update_coordinates();
+        data.insert_bob(4, spec.clone(), false).unwrap();
+        let appended = data.pendulum.bobs[4].coordinate;
+        let tip = before.bobs[3].coordinate;
+        assert!((appended.x - tip.x - 50.0 * 1.0f64.sin()).abs() < 1e-9);
+        data.insert_bob(0, spec, false).unwrap();
+        let first = data.pendulum.bobs[1];
+        assert_eq!(first.theta, before.bobs[0].theta);
+    }
+
+    #[test]
+    fn added_bobs_get_defaults_and_stable_ids() {
+        let mut data = AppDataInner::default();
+        data.set_state(&[], SetStateOptions::default()).unwrap();
+        let first = data.add_bob(None, None, None, None).unwrap();
+        assert_eq!(first.index, 0);
+        let bob = data.pendulum.bobs[0];
+        assert_eq!(
+            (bob.length_rod, bob.mass, bob.theta, bob.omega),
+            (DEFAULT_LENGTH_ROD, DEFAULT_MASS, DEFAULT_THETA, 0.0)
+        );
+        let tip = bob.coordinate;
+        assert!((tip.x - DEFAULT_LENGTH_ROD * DEFAULT_THETA.sin()).abs() < 1e-9);
+
+        // the rest continue the last bob
+        data.modify_bob(0, Some(50.0), Some(3.0), Some(1.0), Some(2.0))
+            .unwrap();
+        let second = data.add_bob(None, Some(7.0), None, None).unwrap();
+        let bob = data.pendulum.bobs[1];
+        assert_eq!(
+            (bob.length_rod, bob.mass, bob.theta, bob.omega),
+            (50.0, 7.0, 1.0, 0.0)
+        );
+        assert_ne!(first.id, second.id);
+
+        // ids stay with their bobs as indices shift
+        let inserted = data.insert_bob(0, spec_of(bob), false);
+        let ids: Vec<u64> = data.frame(0, 0.0).bobs.iter().map(|b| b.id).collect();
+        assert_eq!(ids, [inserted.unwrap().id, first.id, second.id]);
+
+        assert_eq!(
+            BobSpec {
+                mass: -1.0,
+                ..spec_of(bob)
+            }
+            .validate(),
+            Err(InvalidBob::BelowMinimum {
+                field: "mass",
+                value: -1.0,
+                min: MIN_MASS,
+            })
+        );
+        assert!(data.add_bob(Some(f64::NAN), None, None, None).is_err());
+        assert_eq!(data.pendulum.n(), 3);
+    }
+
+    /// Each way of setting a bob, given each kind of bad value for each
+    /// parameter, fails naming it and leaves everything as it was.
+    #[test]
+    fn every_bob_edit_refuses_invalid_values_untouched() {
+        let mut data = AppDataInner::default();
+        data.pendulum.update_coordinates();
+        for field in ["length_rod", "mass", "theta", "omega"] {
+            let mut invalid = vec![f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+            if let Some(min) = minimum(field) {
+                invalid.extend([0.0, -1.0, min / 2.0]);
+            }
+            for value in invalid {
+                let pick = |f: &str| (f == field).then_some(value);
+                let mut spec = spec_of(data.pendulum.bobs[0]);
+                *match field {
+                    "length_rod" => &mut spec.length_rod,
+                    "mass" => &mut spec.mass,
+                    "theta" => &mut spec.theta,
+                    _ => &mut spec.omega,
+                } = value;
+                let update = BobUpdate {
+                    index: Some(1),
+                    length: pick("length_rod"),
+                    mass: pick("mass"),
+                    theta: pick("theta"),
+                    omega: pick("omega"),
+                    ..BobUpdate::default()
+                };
+                let (before, history) = (data.pendulum.clone(), data.history.summary());
+                let results = [
+                    (
+                        "add_bob",
+                        data.add_bob(
+                            pick("length_rod"),
+                            pick("mass"),
+                            pick("theta"),
+                            pick("omega"),
+                        )
+                        .map(drop),
+                    ),
+                    (
+                        "insert_bob",
+                        data.insert_bob(1, spec.clone(), false).map(drop),
+                    ),
+                    (
+                        "modify_bob",
+                        data.modify_bob(
+                            1,
+                            pick("length_rod"),
+                            pick("mass"),
+                            pick("theta"),
+                            pick("omega"),
+                        ),
+                    ),
+                    ("modify_bobs", data.modify_bobs(&[update]).map(drop)),
+                    (
+                        "set_state",
+                        data.set_state(&[spec.clone()], SetStateOptions::default()),
+                    ),
+                    ("set_bob_count", data.set_bob_count(6, Some(spec))),
+                ];
+                for (command, result) in results {
+                    let error = result.expect_err(command);
+                    let name = match error {
+                        CommandError::InvalidParameter { name, .. } => name,
+                        other => panic!("{command}, {value}: {other}"),
+                    };
+                    assert!(name.ends_with(field), "{command}, {value}: {name}");
+                }
+                assert_eq!(data.pendulum, before, "{field} = {value}");
+                assert_eq!(data.history.summary(), history);
+            }
+        }
+
+        // scaling and randomizing under the minimums
+        let before = data.pendulum.clone();
+        let least = |f: fn(&Bob) -> f64| {
+            data.pendulum
+                .bobs
+                .iter()
+                .map(f)
+                .fold(f64::INFINITY, f64::min)
+        };
+        let (shortest, lightest) = (least(|b| b.length_rod), least(|b| b.mass));
+        assert!(data
+            .scale(MIN_LENGTH_ROD / shortest / 2.0, 1.0, true)
+            .is_err());
+        assert!(data.scale(1.0, MIN_MASS / lightest / 2.0, true).is_err());
+        assert!(data.scale(MIN_LENGTH_ROD / shortest, 1.0, true).is_ok());
+        data.undo().unwrap();
+        let light = RandomRanges {
+            mass: Some(ValueRange {
+                min: MIN_MASS / 2.0,
+                max: 1.0,
+            }),
+            ..RandomRanges::default()
+        };
+        assert!(data.randomize(Some(1), &light).is_err());
+        assert_eq!(data.pendulum, before);
+    }
+
+    fn spec_of(bob: Bob) -> BobSpec {
+        BobSpec {
+            length_rod: bob.length_rod,
+            mass: bob.mass,
+            theta: bob.theta,
+            omega: bob.omega,
+            id: None,
+            locked: bob.locked,
+            meta: BobMeta::default(),
+        }
+    }
+
+    #[test]
+    fn angle_edits_keep_zero_or_rescale_the_velocities() {
+        let swinging = || {
+            let mut data = AppDataInner::default();
+            for bob in &mut data.pendulum.bobs {
+                bob.omega = 1.5;
+            }
+            data
+        };
+        let mut data = swinging();
+        data.modify_bob(1, None, None, Some(2.5), None).unwrap();
+        assert!(data.pendulum.bobs.iter().all(|b| b.omega == 1.5));
+
+        let mut data = swinging();
+        data.pose_edit_policy = PoseEditPolicy::ZeroOmega;
+        data.modify_bob(1, None, None, Some(2.5), None).unwrap();
+        assert!(data.pendulum.bobs.iter().all(|b| b.omega == 0.0));
+        // a velocity given with the angle is the one wanted
+        data.modify_bob(1, None, None, Some(2.4), Some(0.5))
+            .unwrap();
+        assert_eq!(data.pendulum.bobs[1].omega, 0.5);
+
+        let mut data = swinging();
+        data.pose_edit_policy = PoseEditPolicy::PreserveEnergy;
+        let energy = data.pendulum.total_energy();
+        data.modify_bob(1, None, None, Some(2.5), None).unwrap();
+        let omegas: Vec<f64> = data.pendulum.bobs.iter().map(|b| b.omega).collect();
+        assert!(omegas[0] != 1.5 && omegas.iter().all(|&o| o == omegas[0]));
+        assert!((data.pendulum.total_energy() - energy).abs() < 1e-9 * energy.abs());
+        let energy = data.pendulum.total_energy();
+        let updates = [BobUpdate {
+            index: Some(0),
+            theta: Some(2.8),
+            ..BobUpdate::default()
+        }];
+        data.modify_bobs(&updates).unwrap();
+        assert!((data.pendulum.total_energy() - energy).abs() < 1e-9 * energy.abs());
+        // held straight up, the pose alone outweighs the old energy of a
+        // slow swing
+        for bob in &mut data.pendulum.bobs {
+            bob.omega = 0.01;
+        }
+        let upright: Vec<BobUpdate> = (0..data.pendulum.n())
+            .map(|index| BobUpdate {
+                index: Some(index),
+                theta: Some(0.0),
+                ..BobUpdate::default()
+            })
+            .collect();
+        data.modify_bobs(&upright).unwrap();
+        assert!(data.pendulum.bobs.iter().all(|b| b.omega == 0.0));
+        // and the edit undoes as one, velocities included
+        data.undo().unwrap();
+        assert!(data.pendulum.bobs.iter().all(|b| b.omega == 0.01));
+    }
+
+    #[test]
+    fn the_bob_cap_holds_for_every_way_of_growing_the_chain() {
+        let mut data = AppDataInner::default();
+        assert_eq!(data.set_max_bobs(5), Ok(None));
+        let too_many = Err(CommandError::TooManyBobs { max: 5 });
+        data.add_bob(None, None, None, None).unwrap();
+        assert_eq!(data.add_bob(None, None, None, None).map(|_| ()), too_many);
+        let spec = spec_of(data.pendulum.bobs[0]);
+        assert_eq!(
+            data.insert_bob(0, spec.clone(), false).map(|_| ()),
+            too_many
+        );
+        assert_eq!(data.set_bob_count(6, None), too_many);
+        assert_eq!(
+            data.set_state(&vec![spec; 6], SetStateOptions::default()),
+            too_many
+        );
+        assert_eq!(data.pendulum.n(), 5);
+        // shrinking the cap leaves the chain, which may still shrink
+        data.set_max_bobs(3).unwrap();
+        data.set_bob_count(4, None).unwrap();
+        assert_eq!(data.pendulum.n(), 4);
+        assert!(data.set_max_bobs(0).is_err());
+        assert!(data.set_max_bobs(BOB_COUNT_CEILING + 1).unwrap().is_some());
+    }
+
+    #[test]
+    fn an_overrun_tick_takes_fewer_substeps_and_warns_once() {
+        let mut data = AppDataInner::default();
+        let mut events = Vec::new();
+        let dt = data.dt;
+        assert_eq!(data.substeps_within(8, dt, Duration::ZERO, &mut events), 1);
+        assert_eq!(data.substeps_within(8, dt, Duration::ZERO, &mut events), 1);
+        assert!((data.clock.deficit - 14.0 * dt).abs() < 1e-12);
+        assert!((data.sim_time - 2.0 * dt).abs() < 1e-12);
+        let overloads = |events: &[SimEvent]| {
+            events
+                .iter()
+                .filter(|e| matches!(e, SimEvent::Overloaded(_)))
+                .count()
+        };
+        assert_eq!(overloads(&events), 1);
+
+        // a tick in full ends the stretch; the next overrun warns again
+        let budget = Duration::from_secs(60);
+        assert_eq!(data.substeps_within(8, dt, budget, &mut events), 8);
+        assert!(!data.overloaded);
+        data.substeps_within(8, dt, Duration::ZERO, &mut events);
+        assert_eq!(overloads(&events), 2);
+    }
+
+    #[test]
+    fn batched_edits_apply_together_or_not_at_all() {
+        let mut data = AppDataInner::default();
+        let before = data.pendulum.clone();
+        let third = before.bobs[2].id;
+        let updates = [
+            BobUpdate {
+                index: Some(0),
+                theta: Some(1.0),
+                omega: Some(before.bobs[0].omega),
+                ..BobUpdate::default()
+            },
+            BobUpdate {
+                id: Some(third),
+                length: Some(80.0),
+                mass: Some(3.0),
+                ..BobUpdate::default()
+            },
+        ];
+        let modified = data.modify_bobs(&updates).unwrap();
+        assert_eq!(
+            modified,
+            [
+                ModifiedBob {
+                    index: 0,
+                    id: before.bobs[0].id,
+                    changed: vec!["theta"],
+                },
+                ModifiedBob {
+                    index: 2,
+                    id: third,
+                    changed: vec!["length_rod", "mass"],
+                },
+            ]
+        );
+        let mut expected = before.clone();
+        expected.bobs[0].theta = 1.0;
+        expected.bobs[2].length_rod = 80.0;
+        expected.bobs[2].mass = 3.0;
+        expected.update_coordinates();
+        assert_eq!(data.pendulum, expected);
+
+        // one bad entry and nothing is applied
+        let edited = data.pendulum.clone();
+        for bad in [
+            BobUpdate {
+                index: Some(9),
+                ..BobUpdate::default()
+            },
+            BobUpdate {
+                id: Some(999),
+                ..BobUpdate::default()
+            },
+            BobUpdate {
+                index: Some(1),
+                id: Some(third),
+                ..BobUpdate::default()
+            },
+            BobUpdate {
+                index: Some(1),
+                mass: Some(-1.0),
+                ..BobUpdate::default()
+            },
+        ] {
+            let updates = [updates[1], bad];
+            let error = data.modify_bobs(&updates).unwrap_err();
+            assert!(
+                matches!(&error, CommandError::InvalidParameter { name, .. } if name.starts_with("updates[1]")),
+                "{error}"
+            );
+            assert_eq!(data.pendulum, edited);
+        }
+    }
+
+    #[test]
+    fn randomizing_starts_over_and_reproduces_with_its_seed() {
+        let mut data = AppDataInner::default();
+        let original = data.pendulum.clone();
+        data.sim_time = 12.0;
+        data.energy_reference = Some(1.0);
+        let ranges = RandomRanges::default();
+        let seed = data.randomize(None, &ranges).unwrap();
+        assert_eq!(data.sim_time, 0.0);
+        assert_eq!(data.energy_reference, None);
+        assert!(data.pendulum.bobs.iter().all(|b| b.omega == 0.0));
+        let drawn = data.pendulum.clone();
+
+        data.pendulum = original.clone();
+        assert_eq!(data.randomize(Some(seed), &ranges).unwrap(), seed);
+        assert_eq!(data.pendulum, drawn);
+        assert_ne!(data.pendulum, original);
+    }
+
+    #[test]
+    fn a_mirrored_start_runs_as_the_mirror_image() {
+        let run = |mirrored: bool| {
+            let mut data = AppDataInner::default();
+            for (i, bob) in data.pendulum.bobs.iter_mut().enumerate() {
+                bob.theta = 1.0 + 0.4 * i as f64;
+                bob.omega = 0.5 - 0.3 * i as f64;
+            }
+            if mirrored {
+                data.mirror().unwrap();
+            }
+            for _ in 0..2_000 {
+                data.substep(0.002, &mut Vec::new());
+            }
+            data.pendulum
+        };
+        let (original, mirrored) = (run(false), run(true));
+        for (a, b) in original.bobs.iter().zip(&mirrored.bobs) {
+            assert!((a.theta + b.theta).abs() < 1e-9);
+            assert!((a.omega + b.omega).abs() < 1e-9);
+            assert!((a.coordinate.x + b.coordinate.x).abs() < 1e-6);
+            assert!((a.coordinate.y - b.coordinate.y).abs() < 1e-6);
+        }
+
+        // a flip under way at the mirroring still counts, the other way
+        let mut data = AppDataInner {
+            pendulum: Pendulum::new(vec![Bob::new(1.0, 1.0, 0.004, -3.0)]),
+            ..AppDataInner::default()
+        };
+        let mut events = Vec::new();
+        data.substep(0.001, &mut events);
+        data.mirror().unwrap();
+        data.substep(0.001, &mut events);
+        let [SimEvent::Flip(flip)] = &events[..] else {
+            panic!("expected one flip, got {events:?}");
+        };
+        assert_eq!(flip.direction, crate::events::Direction::Increasing);
+        assert_eq!(data.history.summary().undo, [Edit::Mirror]);
+    }
+
+    #[test]
+    fn similar_scaling_runs_a_time_scaled_copy() {
+        let start = || {
+            let mut data = AppDataInner::default();
+            for (i, bob) in data.pendulum.bobs.iter_mut().enumerate() {
+                bob.theta = 2.0 - 0.3 * i as f64;
+                bob.omega = 0.2 * i as f64;
+            }
+            data
+        };
+        let mut original = start();
+        let mut scaled = start();
+        scaled.energy_reference = Some(1.0);
+        // four times the lengths takes twice as long
+        scaled.scale(4.0, 3.0, true).unwrap();
+        assert_eq!(scaled.energy_reference, None);
+        for _ in 0..1_000 {
+            original.substep(0.002, &mut Vec::new());
+            scaled.substep(0.004, &mut Vec::new());
+        }
+        for (a, b) in original.pendulum.bobs.iter().zip(&scaled.pendulum.bobs) {
+            assert!((a.theta - b.theta).abs() < 1e-9);
+            assert!((a.omega - 2.0 * b.omega).abs() < 1e-9);
+            assert!((4.0 * a.coordinate.x - b.coordinate.x).abs() < 1e-6);
+        }
+
+        let mut data = start();
+        let before = data.pendulum.clone();
+        data.scale(1.0, 2.0, false).unwrap();
+        let bob = (data.pendulum.bobs[1], before.bobs[1]);
+        assert_eq!((bob.0.mass, bob.0.omega), (2.0 * bob.1.mass, bob.1.omega));
+        assert!(data.scale(0.0, 1.0, true).is_err());
+        assert!(data.scale(1.0, f64::NAN, true).is_err());
+        assert_eq!(data.history.summary().undo, [Edit::Scale]);
+    }
+
+    #[test]
+    fn posing_from_points_reports_the_snap() {
+        let mut data = AppDataInner::default();
+        let target = [0.5, 1.0, 1.5, 2.0];
+        let mut points = data.pendulum.positions_for(&target);
+        points[3].x += 10.0;
+        let residuals = data.set_pose_from_points(&points).unwrap();
+        assert!(residuals[..3].iter().all(|&r| r < 10.0));
+        assert!(residuals[3] > 0.0 && residuals[3] < 10.0);
+        assert!(data.pendulum.bobs.iter().all(|b| b.omega == 0.0));
+        assert_eq!(data.history.summary().undo, [Edit::SetPose]);
+        assert!(data.set_pose_from_points(&points[..2]).is_err());
+        points[0].y = f64::NAN;
+        assert!(data.set_pose_from_points(&points).is_err());
+    }
+
+    #[test]
+    fn moving_the_pivot_carries_the_chain_and_not_the_motion() {
+        let mut data = AppDataInner::default();
+        let mut fixed = AppDataInner::default();
+        for _ in 0..50 {
+            data.substep(DEFAULT_DT, &mut Vec::new());
+            fixed.substep(DEFAULT_DT, &mut Vec::new());
+        }
+        let pivot = Coordinate::new(150.0, -80.0);
+        data.set_pivot(pivot).unwrap();
+        for _ in 0..50 {
+            data.substep(DEFAULT_DT, &mut Vec::new());
+            fixed.substep(DEFAULT_DT, &mut Vec::new());
+        }
+        for (moved, bob) in data.pendulum.bobs.iter().zip(&fixed.pendulum.bobs) {
+            assert_eq!((moved.theta, moved.omega), (bob.theta, bob.omega));
+            assert!((moved.coordinate.x - bob.coordinate.x - pivot.x).abs() < 1e-9);
+            assert!((moved.coordinate.y - bob.coordinate.y - pivot.y).abs() < 1e-9);
+        }
+        assert_eq!(data.frame(0, 0.0).pivot, pivot);
+
+        // it stays through a reset and a set_state that doesn't say otherwise
+        data.reset();
+        let bobs: Vec<BobSpec> = data.pendulum.bobs.iter().map(|&b| spec_of(b)).collect();
+        data.set_state(&bobs, SetStateOptions::default()).unwrap();
+        assert_eq!(data.pendulum.pivot, pivot);
+        let options = SetStateOptions {
+            pivot: Some(Coordinate::new(0.0, 10.0)),
+            ..SetStateOptions::default()
+        };
+        data.set_state(&bobs, options).unwrap();
+        let first = data.pendulum.bobs[0];
+        assert!((first.coordinate.y - 10.0 - first.length_rod * first.theta.cos()).abs() < 1e-9);
+
+        data.undo().unwrap();
+        assert_eq!(data.pendulum.pivot, pivot);
+        assert!(data.set_pivot(Coordinate::new(f64::NAN, 0.0)).is_err());
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_edits() {
+        let mut data = AppDataInner::default();
+        let mut original = data.pendulum.clone();
+        original.update_coordinates();
+        // a drag, merged into one step
+        for theta in [0.1, 0.2, 0.3] {
+            data.modify_bob(1, None, None, Some(theta), None).unwrap();
+        }
+        let dragged = data.pendulum.clone();
+        data.remove_bob(0).unwrap();
+        let preset = crate::presets::find("classic double").unwrap();
+        data.load_preset(preset).unwrap();
+        assert!(data.modify_bob(9, None, Some(1.0), None, None).is_err());
+        assert_eq!(
+            data.history.summary().undo,
+            [Edit::LoadPreset, Edit::RemoveBob, Edit::ModifyBob]
+        );
+
+        data.sim_time = 2.0;
+        assert_eq!(data.undo(), Ok(Edit::LoadPreset));
+        assert_eq!(data.undo(), Ok(Edit::RemoveBob));
+        assert_eq!(data.pendulum, dragged);
+        assert_eq!((data.dt, data.sim_time), (DEFAULT_DT, 2.0));
+        assert_eq!(data.undo(), Ok(Edit::ModifyBob));
+        assert_eq!(data.pendulum, original);
+        assert!(data.undo().is_err());
+
+        assert_eq!(data.redo(), Ok(Edit::ModifyBob));
+        assert_eq!(data.pendulum, dragged);
+        assert_eq!(
+            data.history.summary().redo,
+            [Edit::RemoveBob, Edit::LoadPreset]
+        );
+        data.add_bob(None, None, None, None).unwrap();
+        assert!(data.redo().is_err());
+        assert_eq!(data.history.summary().undo, [Edit::AddBob, Edit::ModifyBob]);
+    }
+
+    #[test]
+    fn resizing_keeps_the_bobs_that_stay() {
+        let mut data = AppDataInner::default();
+        let mut before = data.pendulum.clone();
+        before.update_coordinates();
+        data.set_bob_count(2, None).unwrap();
+        assert_eq!(data.pendulum.bobs, before.bobs[..2]);
+
+        let template = spec_of(Bob::new(30.0, 2.0, 0.5, 0.0));
+        data.set_bob_count(5, Some(template)).unwrap();
+        assert_eq!(data.pendulum.bobs[..2], before.bobs[..2]);
+        assert!(data.pendulum.bobs[2..]
+            .iter()
+            .all(|b| b.mass == 2.0 && b.length_rod == 30.0));
+        let ids: Vec<u64> = data.pendulum.bobs.iter().map(|b| b.id).collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+        // copies of the last bob, then of the defaults once empty
+        data.set_bob_count(6, None).unwrap();
+        assert_eq!(
+            spec_of(data.pendulum.bobs[5]),
+            spec_of(data.pendulum.bobs[4])
+        );
+        data.set_bob_count(0, None).unwrap();
+        assert_eq!(data.pendulum.n(), 0);
+        data.set_bob_count(1, None).unwrap();
+        assert_eq!(data.pendulum.bobs[0].mass, DEFAULT_MASS);
+
+        let bad = spec_of(Bob::new(-1.0, 1.0, 0.0, 0.0));
+        assert!(data.set_bob_count(3, Some(bad)).is_err());
+        assert!(data.set_bob_count(DEFAULT_MAX_BOBS + 1, None).is_err());
+        assert_eq!(data.pendulum.n(), 1);
+    }
+
+    #[test]
+    fn edits_by_id_land_on_the_intended_bob() {
+        let mut data = AppDataInner::default();
+        let ids: Vec<u64> = data.pendulum.bobs.iter().map(|b| b.id).collect();
+        // queued up against the layout at the start, then applied in turn
+        // while earlier ones shift the indices
+        let added = data.add_bob(None, Some(99.0), None, None).unwrap().id;
+        let index = data.index_of(ids[1]).unwrap();
+        data.remove_bob(index).unwrap();
+        data.insert_bob(0, spec_of(data.pendulum.bobs[0]), false)
+            .unwrap();
+        let index = data.index_of(ids[2]).unwrap();
+        data.modify_bob(index, None, Some(42.0), None, None)
+            .unwrap();
+        let index = data.index_of(added).unwrap();
+        data.modify_bob(index, None, None, Some(-1.0), None)
+            .unwrap();
+
+        let bob = |id| data.pendulum.bobs[data.index_of(id).unwrap()];
+        assert_eq!(bob(ids[2]).mass, 42.0);
+        assert_eq!((bob(added).mass, bob(added).theta), (99.0, -1.0));
+        assert_eq!(bob(ids[0]).mass, DEFAULT_MASS);
+        assert!(data.index_of(ids[1]).is_err());
+
+        // ids survive a round trip through set_state
+        let saved: Vec<BobSpec> = data
+            .pendulum
+            .bobs
+            .iter()
+            .map(|&b| BobSpec {
+                id: Some(b.id),
+                ..spec_of(b)
+            })
+            .collect();
+        let json = serde_json::to_string(&saved).unwrap();
+        let loaded: Vec<BobSpec> = serde_json::from_str(&json).unwrap();
+        let before = data.pendulum.clone();
+        data.set_state(&loaded, SetStateOptions::default()).unwrap();
+        assert_eq!(data.pendulum.bobs.len(), before.bobs.len());
+        assert!(data
+            .pendulum
+            .bobs
+            .iter()
+            .zip(&before.bobs)
+            .all(|(a, b)| a.id == b.id && a.mass == b.mass));
+        let fresh = data.add_bob(None, None, None, None).unwrap().id;
+        assert!(before.bobs.iter().all(|b| b.id != fresh));
+        let twice = [loaded[0].clone(), loaded[0].clone()];
+        assert!(data.set_state(&twice, SetStateOptions::default()).is_err());
+    }
+
+    #[test]
+    fn snapshot_describes_one_instant() {
+        let mut data = AppDataInner::default();
+        for _ in 0..10 {
+            data.substep(DEFAULT_DT, &mut Vec::new());
+        }
+        data.user_paused = true;
+        let snapshot = data.snapshot(5.0, ThetaPolicy::Unbounded);
+        assert_eq!(snapshot.state.sim_time, data.sim_time);
+        assert_eq!(snapshot.energetics.sim_time, data.sim_time);
+        assert_eq!(snapshot.energetics.total, data.pendulum.total_energy());
+        assert_eq!(snapshot.state.paused, Some(PauseReason::User));
+        assert_eq!(snapshot.config.bob_count, snapshot.state.bobs.len());
+        assert_eq!(snapshot.config.dt, DEFAULT_DT);
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["config"]["integrator"], INTEGRATOR);
+        assert_eq!(json["state"]["paused"], "user");
+    }
+
+    #[test]
+    fn eased_angle_edits_compose_retarget_and_hand_back_smoothly() {
+        let mut data = AppDataInner {
+            pose_transition: Some(0.5),
+            ..AppDataInner::default()
+        };
+        let mut events = Vec::new();
+        let mut previous: Vec<Bob> = data.pendulum.bobs.clone();
+        let mut step = |data: &mut AppDataInner| {
+            data.substep(data.dt, &mut events);
+            // eased or not, no bob jumps between substeps
+            for (bob, before) in data.pendulum.bobs.iter().zip(&previous) {
+                assert!((bob.theta - before.theta).abs() < 0.1);
+                assert!((bob.omega - before.omega).abs() < 1.0);
+            }
+            previous = data.pendulum.bobs.clone();
+        };
+
+        let start = data.pendulum.bobs[0].theta;
+        data.modify_bob(0, None, None, Some(2.0), None).unwrap();
+        assert_eq!(data.pendulum.bobs[0].theta, start);
+        let frame = data.frame(0, 0.0);
+        assert_eq!(frame.transitions.len(), 1);
+        assert_eq!(frame.transitions[0].target, 2.0);
+        assert_eq!(frame.transitions[0].progress, 0.0);
+
+        for _ in 0..10 {
+            step(&mut data);
+        }
+        data.modify_bob(1, None, None, Some(1.0), None).unwrap();
+        for _ in 0..5 {
+            step(&mut data);
+        }
+        let progress: Vec<f64> = (data.frame(1, 0.0).transitions.iter())
+            .map(|t| t.progress)
+            .collect();
+        assert!(progress[0] > progress[1] && progress[1] > 0.0);
+        // a new angle for bob 0 picks up from where it is, moving
+        assert!(data.pendulum.bobs[0].omega.abs() > 0.1);
+        data.modify_bob(0, None, None, Some(2.5), None).unwrap();
+        assert_eq!(data.transitions.len(), 2);
+
+        let mut steps = 0;
+        while !data.transitions.is_empty() {
+            step(&mut data);
+            steps += 1;
+            assert!(f64::from(steps) * data.dt < 1.0);
+        }
+        let (b0, b1) = (&data.pendulum.bobs[0], &data.pendulum.bobs[1]);
+        assert_eq!((b0.theta, b0.omega), (2.5, 0.0));
+        assert!((b1.theta - 1.0).abs() < 0.1);
+        assert!(data.frame(2, 0.0).transitions.is_empty());
+        // back under the dynamics
+        step(&mut data);
+        assert_ne!(data.pendulum.bobs[0].theta, 2.5);
+
+        // editing an eased bob's angle some other way cancels its ease
+        data.modify_bob(0, None, None, Some(2.0), None).unwrap();
+        data.pendulum.bobs[0].theta = 1.0;
+        data.state_edited();
+        assert!(data.transitions.is_empty());
+        // while paused, angles jump
+        data.user_paused = true;
+        data.modify_bob(0, None, None, Some(2.2), None).unwrap();
+        assert_eq!(data.pendulum.bobs[0].theta, 2.2);
+        assert!(data.transitions.is_empty());
+    }
+
+    #[test]
+    fn a_locked_first_joint_leaves_the_second_a_simple_pendulum() {
+        let mut data = AppDataInner::default();
+        let bobs = [
+            BobSpec {
+                length_rod: 100.0,
+                mass: 10.0,
+                theta: 2.0,
+                omega: 1.0,
+                id: None,
+                locked: false,
+                meta: BobMeta::default(),
+            },
+            BobSpec {
+                length_rod: 80.0,
+                mass: 3.0,
+                theta: 2.6,
+                omega: -0.5,
+                id: None,
+                locked: false,
+                meta: BobMeta::default(),
+            },
+        ];
+        data.set_state(&bobs, SetStateOptions::default()).unwrap();
+        data.set_joint_locked(0, true).unwrap();
+        let anchor = data.pendulum.bobs[0].coordinate;
+        let mut single = Pendulum::new(vec![Bob::new(80.0, 3.0, 2.6, -0.5)]);
+        single.pivot = anchor;
+        for _ in 0..500 {
+            data.pendulum.step(data.dt);
+            single.step(data.dt);
+            let (first, second) = (&data.pendulum.bobs[0], &data.pendulum.bobs[1]);
+            assert_eq!((first.theta, first.omega), (2.0, 0.0));
+            assert!((second.theta - single.bobs[0].theta).abs() < 1e-9);
+            assert!((second.omega - single.bobs[0].omega).abs() < 1e-9);
+        }
+        let frame = data.frame(0, 0.0);
+        assert!(frame.bobs[0].locked && !frame.bobs[1].locked);
+        assert_eq!(frame.bobs[0].alpha, 0.0);
+
+        // saved from a frame and loaded back, the lock holds
+        let saved: Vec<BobSpec> =
+            serde_json::from_value(serde_json::to_value(&frame.bobs).unwrap()).unwrap();
+        let mut loaded = AppDataInner::default();
+        loaded
+            .set_state(&saved, SetStateOptions::default())
+            .unwrap();
+        assert!(loaded.pendulum.bobs[0].locked);
+
+        // unlocked, it sets off from the frozen pose at rest
+        data.set_joint_locked(0, false).unwrap();
+        data.pendulum.step(data.dt);
+        let first = data.pendulum.bobs[0];
+        assert!(first.omega != 0.0 && first.omega.abs() < 0.1);
+        assert!((first.theta - 2.0).abs() < 1e-3);
+        assert_eq!(data.history.summary().undo[..2], [Edit::LockJoint; 2]);
+    }
+
+    #[test]
+    fn bob_colors_and_labels_follow_ids_and_stay_out_of_the_physics() {
+        let mut data = AppDataInner::default();
+        let red = BobMeta {
+            color: Some("#e33".into()),
+            label: Some("elbow".into()),
+        };
+        data.set_bob_meta(1, red.clone()).unwrap();
+        let id = data.pendulum.bobs[1].id;
+        data.remove_bob(0).unwrap();
+        data.add_bob(None, None, None, None).unwrap();
+        data.randomize(Some(7), &RandomRanges::default()).unwrap();
+        let frame = data.frame(0, 0.0);
+        assert_eq!((frame.bobs[0].id, &frame.bobs[0].meta), (id, &red));
+        assert!(frame.bobs[1..].iter().all(|b| b.meta.is_empty()));
+        let json = serde_json::to_value(&frame.bobs[0]).unwrap();
+        assert_eq!(
+            (json["color"].as_str(), json["label"].as_str()),
+            (Some("#e33"), Some("elbow"))
+        );
+
+        // saved from a frame and loaded back, they stay with their bob
+        let saved: Vec<BobSpec> =
+            serde_json::from_value(serde_json::to_value(&frame.bobs).unwrap()).unwrap();
+        let mut loaded = AppDataInner::default();
+        loaded
+            .set_state(&saved, SetStateOptions::default())
+            .unwrap();
+        assert_eq!(loaded.frame(0, 0.0).bobs[0].meta, red);
+
+        let mut plain = AppDataInner::default();
+        plain.set_state(&saved, SetStateOptions::default()).unwrap();
+        plain.set_bob_meta(0, BobMeta::default()).unwrap();
+        assert!(plain.bob_meta.is_empty());
+        let mut events = Vec::new();
+        for _ in 0..200 {
+            loaded.substep(loaded.dt, &mut events);
+            plain.substep(plain.dt, &mut events);
+        }
+        assert_eq!(loaded.pendulum.bobs, plain.pendulum.bobs);
+
+        let long = BobMeta {
+            label: Some("x".repeat(MAX_META_LEN + 1)),
+            ..BobMeta::default()
+        };
+        assert!(matches!(
+            data.set_bob_meta(0, long),
+            Err(CommandError::InvalidParameter { name, .. }) if name == "label"
+        ));
+        assert!(data.set_bob_meta(9, red).is_err());
+    }
+
+    #[test]
+    fn one_value_goes_to_every_bob_as_a_single_edit() {
+        let mut data = AppDataInner::default();
+        let lengths: Vec<f64> = data.pendulum.bobs.iter().map(|b| b.length_rod).collect();
+        data.modify_all_bobs(None, Some(3.0), None, false).unwrap();
+        assert!(data.pendulum.bobs.iter().all(|b| b.mass == 3.0));
+        data.modify_all_bobs(Some(0.5), Some(2.0), None, true)
+            .unwrap();
+        for (bob, length) in data.pendulum.bobs.iter().zip(&lengths) {
+            assert_eq!((bob.length_rod, bob.mass), (length * 0.5, 6.0));
+        }
+        // the tip sits where the halved rods put it
+        let tip = data.pendulum.bobs.last().unwrap().coordinate;
+        assert_eq!(tip, {
+            let mut p = data.pendulum.clone();
+            p.update_coordinates();
+            p.bobs.last().unwrap().coordinate
+        });
+        assert_eq!(data.history.summary().undo.len(), 2);
+
+        let before = data.pendulum.bobs.clone();
+        for error in [
+            data.modify_all_bobs(None, Some(0.0), None, false),
+            data.modify_all_bobs(None, Some(0.0), None, true),
+            data.modify_all_bobs(Some(f64::NAN), None, None, true),
+        ] {
+            assert!(matches!(
+                error,
+                Err(CommandError::InvalidParameter { name, .. })
+                    if ["mass", "bobs[0].mass", "length_rod"].contains(&name.as_str())
+            ));
+        }
+        assert_eq!(data.pendulum.bobs, before);
+        data.undo().unwrap();
+        data.undo().unwrap();
+        assert!(data.pendulum.bobs.iter().map(|b| b.length_rod).eq(lengths));
+    }
+
+    #[test]
+    fn states_saved_in_pixels_load_in_meters_and_draw_where_they_were() {
+        let mut data = AppDataInner::default();
+        let old = |length_rod| BobSpec {
+            length_rod,
+            mass: 10.0,
+            theta: 2.0,
+            omega: 0.5,
+            id: None,
+            locked: false,
+            meta: BobMeta::default(),
+        };
+        let options = SetStateOptions {
+            pivot: Some(Coordinate::new(150.0, -80.0)),
+            lengths_in_pixels: true,
+            ..SetStateOptions::default()
+        };
+        data.set_state(&[old(120.0), old(60.0)], options).unwrap();
+        let lengths: Vec<f64> = data.pendulum.bobs.iter().map(|b| b.length_rod).collect();
+        assert_eq!(lengths, [1.2, 0.6]);
+        assert_eq!(data.pendulum.pivot, Coordinate::new(1.5, -0.8));
+        let frame = data.frame(0, 0.0);
+        assert_eq!(frame.pixels_per_meter, DEFAULT_PIXELS_PER_METER);
+        let tip = frame.bobs[1].position;
+        let x = 150.0 + 180.0 * 2.0f64.sin();
+        assert!((tip.x * frame.pixels_per_meter - x).abs() < 1e-9);
+        // a rod too short in meters is too short in pixels too
+        assert!(data.set_state(&[old(0.5)], options).is_err());
+        assert!(validate_pixels_per_meter(0.0).is_err());
+
+        // energies in joules: a kilogram hanging a meter below the pivot
+        let hanging = BobSpec {
+            length_rod: 1.0,
+            mass: 1.0,
+            theta: PI,
+            omega: 0.0,
+            ..old(1.0)
+        };
+        data.set_state(&[hanging], SetStateOptions::default())
+            .unwrap();
+        let energy = data.pendulum.total_energy();
+        assert!((energy + GRAVITATIONAL_ACCELERATION).abs() < 1e-9);
+    }
+
+    #[test]
+    fn kept_recordings_drop_the_oldest_and_can_be_deleted() {
+        let mut data = AppDataInner::default();
+        let ids: Vec<u64> = (0..MAX_KEPT_RECORDINGS + 2)
+            .map(|_| data.keep_recording(Recording::default()).id)
+            .collect();
+        let kept: Vec<u64> = data.recordings.iter().map(|(id, _)| *id).collect();
+        assert_eq!(kept, ids[2..]);
+
+        // whatever plays a deleted recording holds on to it
+        let playing = data.recordings[0].1.clone();
+        assert!(data.delete_recording(ids[2]));
+        assert!(!data.delete_recording(ids[2]));
+        assert!(!data.delete_recording(ids[0]));
+        assert_eq!(data.recordings.len(), MAX_KEPT_RECORDINGS - 1);
+        assert_eq!(Arc::strong_count(&playing), 1);
+    }
+}
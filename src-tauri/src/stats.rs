@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::Duration};
+
+/// How many of the most recent substep timings the step time figures cover.
+pub(crate) const STEP_TIME_SAMPLES: usize = 1024;
+
+/// Counters the physics task keeps under the app lock, since `reset_stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct SimCounters {
+    pub(crate) substeps: u64,
+    /// Sim seconds stepped.
+    pub(crate) sim_time: f64,
+    /// Substeps whose mass matrix could not be solved, so the accelerations
+    /// fell back to zero.
+    pub(crate) singular_fallbacks: u64,
+    /// Substeps that produced a non-finite state and were rolled back.
+    pub(crate) nan_recoveries: u64,
+    /// Compute time of the latest substeps, in seconds, oldest first.
+    step_times: VecDeque<f64>,
+}
+
+impl SimCounters {
+    pub(crate) fn record_step(&mut self, dt: f64, elapsed: Duration, singular: bool) {
+        self.substeps += 1;
+        self.sim_time += dt;
+        if singular {
+            self.singular_fallbacks += 1;
+        }
+        if self.step_times.len() == STEP_TIME_SAMPLES {
+            self.step_times.pop_front();
+        }
+        self.step_times.push_back(elapsed.as_secs_f64());
+    }
+
+    /// Mean and 95th percentile of the recorded step times, `None` before
+    /// the first step.
+    pub(crate) fn step_time_summary(&self) -> Option<StepTimes> {
+        if self.step_times.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.step_times.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (sorted.len() * 95).div_ceil(100);
+        Some(StepTimes {
+            average: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p95: sorted[rank - 1],
+        })
+    }
+}
+
+/// Substep compute times in seconds, over the latest `STEP_TIME_SAMPLES`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StepTimes {
+    pub(crate) average: f64,
+    pub(crate) p95: f64,
+}
+
+/// How the simulation is currently set up.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ConfigSummary {
+    pub(crate) bob_count: usize,
+    pub(crate) integrator: String,
+    /// Gravitational acceleration in m/s².
+    pub(crate) gravity: f64,
+    pub(crate) dt: f64,
+    pub(crate) time_scale: f64,
+    /// Physics tick period in seconds.
+    pub(crate) tick_period: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_times_cover_the_latest_samples() {
+        let mut counters = SimCounters::default();
+        assert_eq!(counters.step_time_summary(), None);
+        // a slow first run of steps that falls out of the window
+        for _ in 0..STEP_TIME_SAMPLES {
+            counters.record_step(0.01, Duration::from_millis(5), false);
+        }
+        for micros in 1..=100 {
+            for _ in 0..STEP_TIME_SAMPLES / 100 {
+                counters.record_step(0.01, Duration::from_micros(micros), micros == 1);
+            }
+        }
+        let times = counters.step_time_summary().unwrap();
+        assert!((times.p95 - 98e-6).abs() < 1e-12);
+        assert!(times.average < 1e-3);
+        assert_eq!(counters.substeps, 2 * STEP_TIME_SAMPLES as u64 - 24);
+        assert_eq!(counters.singular_fallbacks, STEP_TIME_SAMPLES as u64 / 100);
+    }
+}
@@ -0,0 +1,2170 @@
+use pendulum_core::Pendulum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::Notify,
+    time::{Instant, Interval, MissedTickBehavior},
+};
+
+use crate::compact::{validate_format, FrameEncoder, FrameFormat};
+use crate::error::CommandError;
+use crate::events::{EventSink, SimEvent};
+use crate::instances::Physics;
+use crate::state::{
+    AppDataInner, DiagnosticsFrame, EnergeticsFrame, PauseReason, PendulumState, StreamFrame,
+    StructuralChange,
+};
+use crate::stats::{ConfigSummary, SimCounters, StepTimes};
+use crate::units::ThetaPolicy;
+
+/// Bounds and default of the physics tick period.
+pub(crate) const MIN_TICK_PERIOD: Duration = Duration::from_millis(1);
+pub(crate) const MAX_TICK_PERIOD: Duration = Duration::from_millis(100);
+pub(crate) const DEFAULT_TICK_PERIOD: Duration = Duration::from_millis(8);
+
+/// Bounds and default of a subscription's frame rate. The default matches
+/// the default physics tick, so every tick is sent.
+pub(crate) const MIN_STREAM_FPS: u32 = 1;
+pub(crate) const MAX_STREAM_FPS: u32 = 240;
+pub(crate) const DEFAULT_STREAM_FPS: u32 = 125;
+
+/// Default change threshold below which a frame counts as unchanged.
+pub(crate) const DEFAULT_CHANGE_EPSILON: f64 = 1e-9;
+/// How often a subscriber with unchanged state still gets a frame, so it
+/// knows the stream is alive.
+pub(crate) const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frames a subscriber may have waiting before the oldest are dropped.
+pub(crate) const FRAME_QUEUE_CAPACITY: usize = 8;
+
+/// Where streamed frames go. Implemented for the Tauri channel and for test
+/// doubles. Sends may block; they run on the subscriber's own drain task.
+pub(crate) trait FrameSink: Send + Sync {
+    /// Delivers a frame, returning `false` once the subscriber is gone.
+    fn send(&self, frame: StreamFrame) -> bool;
+}
+
+/// Frames waiting for a subscriber's drain task. Bounded, so a slow sink
+/// loses its oldest frames instead of holding up the physics task.
+#[derive(Default)]
+struct FrameQueue {
+    frames: Mutex<VecDeque<StreamFrame>>,
+    ready: Notify,
+    dropped: AtomicU64,
+    /// Frames taken off the queue by the receiver.
+    sent: AtomicU64,
+    closed: AtomicBool,
+    /// Set when the client asks for a keyframe out of turn.
+    resync: AtomicBool,
+}
+
+impl FrameQueue {
+    /// Queues a frame, dropping the oldest if full. Structural change
+    /// notices of a dropped frame move to the frame after it, so they are
+    /// never lost and still arrive before the new layout is used.
+    fn push(&self, frame: StreamFrame) {
+        if let Ok(mut frames) = self.frames.lock() {
+            frames.push_back(frame);
+            if frames.len() > FRAME_QUEUE_CAPACITY {
+                if let Some(StreamFrame::Full(dropped)) = frames.pop_front() {
+                    if let Some(StreamFrame::Full(next)) = frames.front_mut() {
+                        let later = std::mem::replace(
+                            &mut next.structure_changes,
+                            dropped.structure_changes,
+                        );
+                        next.structure_changes.extend(later);
+                    }
+                }
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.ready.notify_one();
+    }
+
+    fn pop(&self) -> Option<StreamFrame> {
+        self.frames.lock().ok()?.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.frames.lock().map_or(0, |frames| frames.len())
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.ready.notify_one();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+}
+
+/// The receiving end of a subscription. Encoding happens here rather than
+/// in the physics task, and after the queue, so a dropped frame is never one
+/// that later deltas depend on. Dropping the receiver ends the subscription.
+pub(crate) struct FrameReceiver {
+    queue: Arc<FrameQueue>,
+    encoder: FrameEncoder,
+}
+
+impl FrameReceiver {
+    /// Waits for the next frame, encoded in the subscription's format.
+    /// `None` once the subscription has ended.
+    pub(crate) async fn recv(&mut self) -> Option<StreamFrame> {
+        loop {
+            match self.queue.pop() {
+                Some(frame) => {
+                    if self.queue.resync.swap(false, Ordering::Relaxed) {
+                        self.encoder.resync();
+                    }
+                    self.queue.sent.fetch_add(1, Ordering::Relaxed);
+                    return Some(match frame {
+                        StreamFrame::Full(state) => self.encoder.encode(state),
+                        other => other,
+                    });
+                }
+                None if self.queue.is_closed() => return None,
+                None => self.queue.ready.notified().await,
+            }
+        }
+    }
+}
+
+impl Drop for FrameReceiver {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+/// Moves received frames into the sink until the sink closes or the
+/// subscription ends.
+async fn drain(mut frames: FrameReceiver, sink: Arc<dyn FrameSink>) {
+    while let Some(frame) = frames.recv().await {
+        if !sink.send(frame) {
+            return;
+        }
+    }
+}
+
+pub(crate) struct Subscriber {
+    pub(crate) id: u64,
+    pub(crate) topic: Topic,
+    queue: Arc<FrameQueue>,
+    pub(crate) fps: u32,
+    /// Skip frames whose state moved less than this since the last frame
+    /// sent, keepalives aside. `None` sends every due frame.
+    pub(crate) change_epsilon: Option<f64>,
+    theta_policy: ThetaPolicy,
+    /// When this subscriber is next owed a frame.
+    next_due: Instant,
+    /// Sequence number of the next frame sent to this subscriber.
+    next_seq: u64,
+    /// What the last frame sent looked like, for change detection.
+    last_sent: Option<(Vec<f64>, Instant)>,
+    /// Sim time of the last frame sent.
+    last_sim_time: Option<f64>,
+    /// Structural changes not yet announced to this subscriber.
+    pending_changes: Vec<StructuralChange>,
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        // lets the drain task finish
+        self.queue.close();
+    }
+}
+
+/// A frame's destination, collected as the subscribers come due and queued
+/// once the frames are built.
+struct Delivery {
+    topic: Topic,
+    queue: Arc<FrameQueue>,
+    seq: u64,
+    span: f64,
+    theta_policy: ThetaPolicy,
+    structure_changes: Vec<StructuralChange>,
+}
+
+/// The values change detection compares: every bob's full state and the
+/// pivot, so edits count as changes too.
+fn change_signature(pendulum: &Pendulum) -> Vec<f64> {
+    pendulum
+        .bobs
+        .iter()
+        .flat_map(|b| [b.theta, b.omega, b.length_rod, b.mass])
+        .chain([pendulum.pivot.x, pendulum.pivot.y])
+        .collect()
+}
+
+/// The change signature and sim time of what the positions frames show:
+/// the replay while one is playing, the live run otherwise.
+fn shown(app_data: &AppDataInner) -> (Vec<f64>, f64) {
+    match &app_data.replay {
+        Some(replay) => (change_signature(&replay.pendulum()), replay.sim_time()),
+        None => (change_signature(&app_data.pendulum), app_data.sim_time),
+    }
+}
+
+fn max_change(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() != b.len() {
+        return f64::INFINITY;
+    }
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f64::max)
+}
+
+impl Subscriber {
+    /// Queues a change for the next frame, if this subscriber receives bobs.
+    /// After a reset or replacement the next frame goes out regardless of
+    /// change detection, with a zero span.
+    pub(crate) fn notify_structural_change(&mut self, change: StructuralChange) {
+        if change.operation.starts_over() {
+            self.last_sent = None;
+            self.last_sim_time = None;
+        }
+        if self.topic == Topic::Positions {
+            self.pending_changes.push(change);
+        }
+    }
+
+    fn period(&self) -> Duration {
+        Duration::from_secs(1) / self.fps
+    }
+
+    /// Whether a frame built at `now` goes to this subscriber, and with
+    /// which sequence number and span. Due times advance by whole periods so the
+    /// average rate is exact even though frames can only go out on physics
+    /// ticks. A due frame is still skipped when it would repeat the last one,
+    /// unless a keepalive is owed; a new subscriber always gets its first.
+    fn take_due(&mut self, now: Instant, signature: &[f64], sim_time: f64) -> Option<Delivery> {
+        if now < self.next_due {
+            return None;
+        }
+        self.next_due += self.period();
+        if self.next_due <= now {
+            // fell more than a period behind; don't burst to catch up
+            self.next_due = now + self.period();
+        }
+        if let (Some(epsilon), Some((last, at))) = (self.change_epsilon, &self.last_sent) {
+            if max_change(last, signature) <= epsilon && now - *at < KEEPALIVE_INTERVAL {
+                return None;
+            }
+        }
+        Some(self.take_now(now, signature, sim_time))
+    }
+
+    /// The next frame of this subscriber, due or not. Leaves the cadence
+    /// alone.
+    fn take_now(&mut self, now: Instant, signature: &[f64], sim_time: f64) -> Delivery {
+        self.last_sent = Some((signature.to_vec(), now));
+        let span = self
+            .last_sim_time
+            .replace(sim_time)
+            .map_or(0.0, |t| sim_time - t);
+        self.next_seq += 1;
+        Delivery {
+            topic: self.topic,
+            queue: self.queue.clone(),
+            seq: self.next_seq - 1,
+            span,
+            theta_policy: self.theta_policy,
+            structure_changes: std::mem::take(&mut self.pending_changes),
+        }
+    }
+}
+
+pub(crate) fn validate_change_epsilon(epsilon: f64) -> Result<f64, CommandError> {
+    if epsilon.is_finite() && epsilon >= 0.0 {
+        Ok(epsilon)
+    } else {
+        Err(CommandError::invalid(
+            "change_epsilon",
+            format!("must be non-negative and finite, got {epsilon}"),
+        ))
+    }
+}
+
+pub(crate) fn validate_fps(fps: u32) -> Result<u32, CommandError> {
+    if (MIN_STREAM_FPS..=MAX_STREAM_FPS).contains(&fps) {
+        Ok(fps)
+    } else {
+        Err(CommandError::invalid(
+            "fps",
+            format!("must be between {MIN_STREAM_FPS} and {MAX_STREAM_FPS}, got {fps}"),
+        ))
+    }
+}
+
+/// What a subscription streams. The energetics and diagnostics are only
+/// computed on ticks where one of their subscribers is due a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Topic {
+    /// `PendulumState`s: the bobs and any enabled overlays.
+    #[default]
+    Positions,
+    /// `EnergeticsFrame`s: energies, momenta and rod tensions.
+    Energetics,
+    /// `DiagnosticsFrame`s: conditioning, substeps and energy drift.
+    Diagnostics,
+}
+
+/// How a new subscription treats the ones already registered to the same
+/// topic. The physics task is the only thing that steps the pendulum, so
+/// none of these change the simulation speed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum StreamPolicy {
+    /// Join the existing subscribers.
+    #[default]
+    Shared,
+    /// Drop every existing subscriber first. Suits hot reloads, where the
+    /// old channel is already dead.
+    Replace,
+    /// Fail if anyone is already subscribed.
+    Reject,
+}
+
+/// Settings of a new subscription.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SubscribeOptions {
+    pub(crate) topic: Topic,
+    pub(crate) policy: StreamPolicy,
+    pub(crate) fps: u32,
+    /// Unchanged-frame suppression threshold, `None` to send every frame.
+    pub(crate) change_epsilon: Option<f64>,
+    /// Wire format; only the positions topic has a choice.
+    pub(crate) format: FrameFormat,
+    /// How the positions frames give the angles.
+    pub(crate) theta_policy: ThetaPolicy,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        Self {
+            topic: Topic::default(),
+            policy: StreamPolicy::default(),
+            fps: DEFAULT_STREAM_FPS,
+            change_epsilon: None,
+            format: FrameFormat::default(),
+            theta_policy: ThetaPolicy::default(),
+        }
+    }
+}
+
+/// Registers a sink for the broadcast frames. Returns the subscription id
+/// and the task that feeds the sink, which the caller must spawn.
+pub(crate) fn subscribe(
+    app_data: &mut AppDataInner,
+    sink: Arc<dyn FrameSink>,
+    options: SubscribeOptions,
+) -> Result<(u64, impl Future<Output = ()> + Send + 'static), CommandError> {
+    let (id, frames) = subscribe_receiver(app_data, options)?;
+    Ok((id, drain(frames, sink)))
+}
+
+/// Registers a subscription whose frames the caller pulls from the returned
+/// receiver at its own pace; the slowest it can go is the queue dropping
+/// frames, never the physics waiting.
+pub(crate) fn subscribe_receiver(
+    app_data: &mut AppDataInner,
+    options: SubscribeOptions,
+) -> Result<(u64, FrameReceiver), CommandError> {
+    let SubscribeOptions {
+        topic,
+        policy,
+        fps,
+        change_epsilon,
+        format,
+        theta_policy,
+    } = options;
+    let fps = validate_fps(fps)?;
+    let change_epsilon = change_epsilon.map(validate_change_epsilon).transpose()?;
+    let format = validate_format(format)?;
+    match policy {
+        StreamPolicy::Shared => {}
+        StreamPolicy::Replace => app_data.subscribers.retain(|s| s.topic != topic),
+        StreamPolicy::Reject => {
+            if app_data.subscribers.iter().any(|s| s.topic == topic) {
+                return Err(CommandError::busy(format!(
+                    "A {topic:?} stream is already running"
+                )));
+            }
+        }
+    }
+    let id = app_data.next_subscriber_id;
+    app_data.next_subscriber_id += 1;
+    let queue = Arc::new(FrameQueue::default());
+    app_data.subscribers.push(Subscriber {
+        id,
+        topic,
+        queue: queue.clone(),
+        fps,
+        change_epsilon,
+        theta_policy,
+        next_due: Instant::now(),
+        next_seq: 0,
+        last_sent: None,
+        last_sim_time: None,
+        pending_changes: Vec::new(),
+    });
+    let subscribers = app_data.subscribers.len();
+    tracing::info!(id, ?topic, subscribers, "Subscribed");
+    let frames = FrameReceiver {
+        queue,
+        encoder: FrameEncoder::new(format),
+    };
+    Ok((id, frames))
+}
+
+/// Changes how often a subscription receives frames.
+pub(crate) fn set_stream_rate(
+    app_data: &mut AppDataInner,
+    id: u64,
+    fps: u32,
+) -> Result<(), CommandError> {
+    let fps = validate_fps(fps)?;
+    let subscriber = app_data
+        .subscribers
+        .iter_mut()
+        .find(|s| s.id == id)
+        .ok_or_else(|| CommandError::not_found("subscription", id))?;
+    subscriber.fps = fps;
+    Ok(())
+}
+
+/// Turns unchanged-frame suppression on (with the given threshold) or off.
+pub(crate) fn set_frame_suppression(
+    app_data: &mut AppDataInner,
+    id: u64,
+    change_epsilon: Option<f64>,
+) -> Result<(), CommandError> {
+    let change_epsilon = change_epsilon.map(validate_change_epsilon).transpose()?;
+    let subscriber = app_data
+        .subscribers
+        .iter_mut()
+        .find(|s| s.id == id)
+        .ok_or_else(|| CommandError::not_found("subscription", id))?;
+    subscriber.change_epsilon = change_epsilon;
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SubscriberStats {
+    pub(crate) id: u64,
+    pub(crate) topic: Topic,
+    pub(crate) fps: u32,
+    /// Frames waiting to be sent.
+    pub(crate) queued: usize,
+    /// Frames sent since the last `reset_stats`.
+    pub(crate) sent_frames: u64,
+    /// Frames dropped because the queue was full, since the last
+    /// `reset_stats`.
+    pub(crate) dropped_frames: u64,
+}
+
+impl Subscriber {
+    fn stats(&self) -> SubscriberStats {
+        SubscriberStats {
+            id: self.id,
+            topic: self.topic,
+            fps: self.fps,
+            queued: self.queue.len(),
+            sent_frames: self.queue.sent.load(Ordering::Relaxed),
+            dropped_frames: self.queue.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StreamStats {
+    pub(crate) subscribers: Vec<SubscriberStats>,
+}
+
+pub(crate) fn stats(app_data: &AppDataInner) -> Result<StreamStats, CommandError> {
+    Ok(StreamStats {
+        subscribers: app_data.subscribers.iter().map(Subscriber::stats).collect(),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SimulationStats {
+    pub(crate) substeps: u64,
+    /// Sim seconds stepped.
+    pub(crate) sim_time: f64,
+    /// `None` until a substep has been timed.
+    pub(crate) step_times: Option<StepTimes>,
+    pub(crate) singular_fallbacks: u64,
+    pub(crate) nan_recoveries: u64,
+    pub(crate) subscribers: Vec<SubscriberStats>,
+    pub(crate) config: ConfigSummary,
+}
+
+/// Everything the physics task counts, since the last `reset_stats`.
+pub(crate) fn simulation_stats(app_data: &AppDataInner) -> Result<SimulationStats, CommandError> {
+    let counters = &app_data.counters;
+    Ok(SimulationStats {
+        substeps: counters.substeps,
+        sim_time: counters.sim_time,
+        step_times: counters.step_time_summary(),
+        singular_fallbacks: counters.singular_fallbacks,
+        nan_recoveries: counters.nan_recoveries,
+        subscribers: app_data.subscribers.iter().map(Subscriber::stats).collect(),
+        config: app_data.config_summary(),
+    })
+}
+
+/// Zeroes the simulation counters and every subscriber's frame counts.
+pub(crate) fn reset_stats(app_data: &mut AppDataInner) -> Result<(), CommandError> {
+    app_data.counters = SimCounters::default();
+    for s in &app_data.subscribers {
+        s.queue.sent.store(0, Ordering::Relaxed);
+        s.queue.dropped.store(0, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Makes the next frame of a delta-encoded subscription a keyframe, for a
+/// client that lost track of the current one.
+pub(crate) fn request_keyframe(app_data: &AppDataInner, id: u64) -> Result<(), CommandError> {
+    let subscriber = app_data
+        .subscribers
+        .iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| CommandError::not_found("subscription", id))?;
+    subscriber.queue.resync.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Pong {
+    /// Echoed back, so the client can pair replies with requests.
+    pub(crate) client_timestamp: f64,
+    /// When the ping was handled, on the clock of the frames' `wallTimeMs`.
+    pub(crate) server_time_ms: f64,
+    /// Positions frames built so far, across all subscriptions.
+    pub(crate) frames_built: u64,
+    /// `seq` of the last frame queued for the given subscription.
+    pub(crate) last_seq: Option<u64>,
+}
+
+/// Answers a latency probe. Half the round trip estimates the one-way IPC
+/// delay, and `server_time_ms` relates the frame timestamps to the client's
+/// clock.
+pub(crate) fn ping(
+    app_data: &AppDataInner,
+    client_timestamp: f64,
+    subscription: Option<u64>,
+) -> Result<Pong, CommandError> {
+    let last_seq = subscription
+        .map(|id| {
+            app_data
+                .subscribers
+                .iter()
+                .find(|s| s.id == id)
+                .ok_or_else(|| CommandError::not_found("subscription", id))
+                .map(|s| s.next_seq.checked_sub(1))
+        })
+        .transpose()?
+        .flatten();
+    Ok(Pong {
+        client_timestamp,
+        server_time_ms: app_data.wall_time_ms(Instant::now()),
+        frames_built: app_data.frames_built,
+        last_seq,
+    })
+}
+
+/// Removes a subscription, returning whether it existed.
+pub(crate) fn unsubscribe(app_data: &mut AppDataInner, id: u64) -> Result<bool, CommandError> {
+    let before = app_data.subscribers.len();
+    app_data.subscribers.retain(|s| s.id != id);
+    let left = app_data.subscribers.len();
+    if left != before {
+        tracing::info!(id, subscribers = left, "Unsubscribed");
+    }
+    Ok(left != before)
+}
+
+/// One tick's frames, built only for the topics someone is due.
+struct Frames {
+    positions: Option<PendulumState>,
+    energetics: Option<EnergeticsFrame>,
+    diagnostics: Option<DiagnosticsFrame>,
+}
+
+impl Frames {
+    /// The frame for one subscriber.
+    fn for_delivery(&self, d: &Delivery) -> Option<StreamFrame> {
+        Some(match d.topic {
+            Topic::Positions => {
+                let mut state = PendulumState {
+                    seq: d.seq,
+                    span: d.span,
+                    structure_changes: d.structure_changes.clone(),
+                    ..self.positions.clone()?
+                };
+                d.theta_policy.present(&mut state);
+                StreamFrame::Full(state)
+            }
+            Topic::Energetics => StreamFrame::Energetics(EnergeticsFrame {
+                seq: d.seq,
+                ..self.energetics.clone()?
+            }),
+            Topic::Diagnostics => StreamFrame::Diagnostics(DiagnosticsFrame {
+                seq: d.seq,
+                ..self.diagnostics.clone()?
+            }),
+        })
+    }
+}
+
+/// Advances the simulation by the wall time since the previous tick and
+/// returns the frames with the subscribers they are due to. Nothing is
+/// stepped while the task is idling without subscribers, and a topic's frame
+/// is only built when one of its subscribers is due. Subscribers whose sink
+/// has closed are dropped first. Events raised by the substeps are added to
+/// `events`.
+fn tick(
+    app_data: &mut AppDataInner,
+    events: &mut Vec<SimEvent>,
+) -> Option<(Frames, Vec<Delivery>)> {
+    events.append(&mut app_data.pending_events);
+    let now = Instant::now();
+    app_data.tick_meter.record(now);
+    app_data.heartbeat += 1;
+    let before = app_data.subscribers.len();
+    app_data.subscribers.retain(|s| !s.queue.is_closed());
+    let subscribers = app_data.subscribers.len();
+    if subscribers != before {
+        let closed = before - subscribers;
+        tracing::info!(
+            closed,
+            subscribers,
+            "Dropped subscriptions whose channel closed"
+        );
+    }
+    if app_data.subscribers.is_empty() && app_data.pause_without_subscribers {
+        app_data.clock.pause();
+        return None;
+    }
+    let running = !app_data.user_paused;
+    if let Some(replay) = &mut app_data.replay {
+        if replay.advance(now, running) {
+            app_data.replay_changed();
+        }
+    }
+    let (time_scale, dt) = (app_data.time_scale, app_data.dt);
+    let steps = if app_data.lockstep {
+        // a comparison clone or wave member takes its substeps with its leader's
+        app_data.clock.pause();
+        0
+    } else if app_data.pause_reason().is_some() {
+        // frames keep flowing, but the paused time is never owed
+        app_data.clock.pause();
+        if let Some(slow_motion) = &mut app_data.slow_motion {
+            slow_motion.pause();
+        }
+        0
+    } else {
+        let inner = &mut *app_data;
+        let factor = inner
+            .slow_motion
+            .as_mut()
+            .map_or(1.0, |s| s.update(now, &inner.pendulum));
+        inner.clock.advance(now, time_scale * factor, dt)
+    };
+    let budget = app_data.tick_period;
+    app_data.last_substeps = app_data.substeps_within(steps, dt, budget, events);
+    #[cfg(feature = "osc")]
+    {
+        let inner = &mut *app_data;
+        if let Some(osc) = &mut inner.osc {
+            osc.send_due(now, &inner.pendulum, inner.sim_time);
+        }
+    }
+    let (signature, sim_time) = shown(app_data);
+    let due: Vec<Delivery> = app_data
+        .subscribers
+        .iter_mut()
+        .filter_map(|s| s.take_due(now, &signature, sim_time))
+        .collect();
+    build_frames(app_data, now, due)
+}
+
+/// Builds the frames of the topics `due` asks for, `None` if it is empty.
+fn build_frames(
+    app_data: &mut AppDataInner,
+    now: Instant,
+    due: Vec<Delivery>,
+) -> Option<(Frames, Vec<Delivery>)> {
+    if due.is_empty() {
+        return None;
+    }
+    let wall_time_ms = app_data.wall_time_ms(now);
+    let wanted = |topic| due.iter().any(|d| d.topic == topic);
+    let positions = wanted(Topic::Positions).then(|| {
+        app_data.frames_built += 1;
+        match &app_data.replay {
+            Some(replay) => replay.frame(app_data, wall_time_ms),
+            None => app_data.frame(app_data.frames_built - 1, wall_time_ms),
+        }
+    });
+    let frames = Frames {
+        positions,
+        energetics: wanted(Topic::Energetics).then(|| app_data.energetics_frame(wall_time_ms)),
+        diagnostics: wanted(Topic::Diagnostics).then(|| app_data.diagnostics_frame(wall_time_ms)),
+    };
+    Some((frames, due))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StepReport {
+    pub(crate) sim_time: f64,
+    /// The step taken, in sim seconds.
+    pub(crate) dt: f64,
+    pub(crate) kinetic: f64,
+    pub(crate) potential: f64,
+    pub(crate) total: f64,
+    /// Angular accelerations after the step, in rad/s².
+    pub(crate) alphas: Vec<f64>,
+}
+
+/// Takes one substep of `dt`, the configured one by default, while paused,
+/// and sends every subscriber a frame of the result straight away, outside
+/// its cadence and change detection. The step's events are added to
+/// `events`, for the caller to emit.
+pub(crate) fn single_step(
+    app_data: &mut AppDataInner,
+    dt: Option<f64>,
+    events: &mut Vec<SimEvent>,
+) -> Result<StepReport, CommandError> {
+    edit_and_push(app_data, |app_data| {
+        match app_data.pause_reason() {
+            None => return Err(CommandError::NotPaused),
+            Some(PauseReason::WarmingUp) => {
+                return Err(CommandError::busy("A warm-up is in progress"))
+            }
+            Some(PauseReason::Replaying) => return Err(CommandError::busy("A replay is playing")),
+            Some(PauseReason::User | PauseReason::Background) => {}
+        }
+        let dt = dt.unwrap_or(app_data.dt);
+        if !(dt.is_finite() && dt > 0.0) {
+            return Err(CommandError::invalid(
+                "dt",
+                format!("must be positive and finite, got {dt}"),
+            ));
+        }
+        app_data.substep(dt, events);
+        app_data.last_substeps = 1;
+        let pendulum = &app_data.pendulum;
+        let (kinetic, potential) = (pendulum.kinetic_energy(), pendulum.potential_energy());
+        Ok(app_data.angle_format().outgoing(StepReport {
+            sim_time: app_data.sim_time,
+            dt,
+            kinetic,
+            potential,
+            total: kinetic + potential,
+            alphas: pendulum.accelerations(),
+        }))
+    })
+}
+
+/// Runs `edit` and sends every subscriber a frame of what it left straight
+/// away, outside its cadence and change detection, so the result shows even
+/// while paused.
+pub(crate) fn edit_and_push<T>(
+    app_data: &mut AppDataInner,
+    edit: impl FnOnce(&mut AppDataInner) -> Result<T, CommandError>,
+) -> Result<T, CommandError> {
+    let result = edit(app_data)?;
+    let now = Instant::now();
+    let (signature, sim_time) = shown(app_data);
+    let due: Vec<Delivery> = app_data
+        .subscribers
+        .iter_mut()
+        .map(|s| s.take_now(now, &signature, sim_time))
+        .collect();
+    if let Some((frames, deliveries)) = build_frames(app_data, now, due) {
+        broadcast(frames, deliveries);
+    }
+    Ok(result)
+}
+
+/// Queues the frame for each subscriber. Never waits on a sink.
+fn broadcast(frames: Frames, deliveries: Vec<Delivery>) {
+    for d in deliveries {
+        if let Some(frame) = frames.for_delivery(&d) {
+            d.queue.push(frame);
+        }
+    }
+}
+
+/// Ticks at `period`, skipping (not bursting) ticks missed while a tick
+/// overran.
+fn tick_interval(period: Duration) -> Interval {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
+
+pub(crate) fn validate_tick_period(period: Duration) -> Result<Duration, CommandError> {
+    if (MIN_TICK_PERIOD..=MAX_TICK_PERIOD).contains(&period) {
+        Ok(period)
+    } else {
+        Err(CommandError::invalid(
+            "tick_period",
+            format!("must be between {MIN_TICK_PERIOD:?} and {MAX_TICK_PERIOD:?}, got {period:?}"),
+        ))
+    }
+}
+
+/// The physics task: owns every instance, the stepping cadence, keeps sim
+/// time in step with wall time, and broadcasts frames to the subscribers,
+/// for every instance in turn each tick. Runs until every `Instances`
+/// handle is gone. Between ticks it runs the jobs the commands send it, in
+/// the order they were sent, so a command never waits on a tick nor a tick
+/// on a command for longer than the other takes. The interval keeps ticking
+/// while stepping is paused, so the task stays responsive to new
+/// subscribers and settings. It ticks at the shortest period any instance
+/// asks for; the others still step with wall time, just in smaller
+/// bites. A tick's events are emitted before its frames are queued, and an
+/// instance whose tick panics is recovered and carries on at the next.
+pub(crate) async fn physics_loop(physics: Physics, event_sink: &dyn EventSink) {
+    let Physics {
+        mut world,
+        mut jobs,
+    } = physics;
+    let mut period = DEFAULT_TICK_PERIOD;
+    let mut interval = tick_interval(period);
+    let mut events = Vec::new();
+    loop {
+        tokio::select! {
+            biased;
+            _ = interval.tick() => {}
+            job = jobs.recv() => match job {
+                Some(job) => {
+                    job(&mut world);
+                    continue;
+                }
+                None => return,
+            },
+        }
+        let started = Instant::now();
+        let mut wanted = MAX_TICK_PERIOD;
+        for id in world.ids() {
+            let Ok(app_data) = world.get(Some(id)) else {
+                continue;
+            };
+            let ticked = panic::catch_unwind(AssertUnwindSafe(|| tick(app_data, &mut events)));
+            let due = ticked.unwrap_or_else(|_| {
+                tracing::error!(instance = id, "A physics tick panicked");
+                app_data.recover();
+                None
+            });
+            for event in events.drain(..) {
+                event_sink.emit(event);
+            }
+            if let Some((frames, deliveries)) = due {
+                broadcast(frames, deliveries);
+            }
+            wanted = wanted.min(app_data.tick_period);
+        }
+        let took = started.elapsed();
+        if took > period {
+            let (took_ms, period_ms) = (took.as_secs_f64() * 1e3, period.as_secs_f64() * 1e3);
+            tracing::warn!(took_ms, period_ms, "A physics tick overran its period");
+        }
+        if wanted != period {
+            period = wanted;
+            interval = tick_interval(period);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Edit;
+    use crate::instances::Instances;
+    use crate::state::{StructuralOperation, DEFAULT_DT};
+    use pendulum_core::LinearSolution;
+    use pendulum_core::{Bob, INTEGRATOR};
+    use std::f64::consts::PI;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    /// Seconds of one tick at the default period, which sim time may lag by.
+    const TICK: f64 = DEFAULT_TICK_PERIOD.as_secs_f64();
+    /// Substeps a tick takes at the default dt and period, when on time.
+    const SUBSTEPS_PER_TICK: u32 = (TICK / DEFAULT_DT) as u32 + 1;
+
+    /// Accepts `capacity` frames, then reports a closed channel.
+    struct ClosingSink {
+        capacity: usize,
+        attempts: AtomicUsize,
+        frames: Mutex<Vec<StreamFrame>>,
+    }
+
+    impl ClosingSink {
+        fn new(capacity: usize) -> Arc<Self> {
+            Arc::new(Self {
+                capacity,
+                attempts: AtomicUsize::new(0),
+                frames: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn attempts(&self) -> usize {
+            self.attempts.load(Ordering::SeqCst)
+        }
+
+        fn full_frames(&self) -> Vec<PendulumState> {
+            self.frames
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|frame| match frame {
+                    StreamFrame::Full(state) => state.clone(),
+                    _ => panic!("expected a full frame"),
+                })
+                .collect()
+        }
+    }
+
+    impl FrameSink for ClosingSink {
+        fn send(&self, frame: StreamFrame) -> bool {
+            let accepted = self.attempts.fetch_add(1, Ordering::SeqCst) < self.capacity;
+            if accepted {
+                self.frames.lock().unwrap().push(frame);
+            }
+            accepted
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordedEvents(Mutex<Vec<SimEvent>>);
+
+    impl EventSink for RecordedEvents {
+        fn emit(&self, event: SimEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    /// A handle to a default primary instance, and the physics task's side,
+    /// whose `world` sets things up before it is spawned.
+    fn new_data() -> (Instances, Physics) {
+        Instances::new(AppDataInner::default())
+    }
+
+    fn spawn_physics(physics: Physics) -> tokio::task::JoinHandle<()> {
+        spawn_physics_with_events(physics, Arc::default())
+    }
+
+    fn spawn_physics_with_events(
+        physics: Physics,
+        events: Arc<RecordedEvents>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move { physics_loop(physics, &*events).await })
+    }
+
+    /// Runs `job` on the primary instance between two ticks, as a command
+    /// does.
+    async fn with<T: Send + 'static>(
+        instances: &Instances,
+        job: impl FnOnce(&mut AppDataInner) -> T + Send + 'static,
+    ) -> T {
+        instances
+            .call(None, move |data| Ok(job(data)))
+            .await
+            .unwrap()
+    }
+
+    async fn sim_time(instances: &Instances) -> f64 {
+        with(instances, |data| data.sim_time).await
+    }
+
+    /// Stops the physics task, handing back the primary instance as the last
+    /// tick left it.
+    async fn stop(instances: &Instances, physics: tokio::task::JoinHandle<()>) -> AppDataInner {
+        let job = |world: &mut crate::instances::World| Ok(std::mem::take(world.primary()));
+        let data = instances.world(job).await.unwrap();
+        physics.abort();
+        data
+    }
+
+    /// Subscribes and spawns the drain task, as the command does.
+    fn join(
+        data: &mut AppDataInner,
+        sink: Arc<dyn FrameSink>,
+        policy: StreamPolicy,
+        fps: u32,
+        change_epsilon: Option<f64>,
+    ) -> Result<u64, CommandError> {
+        let options = SubscribeOptions {
+            policy,
+            fps,
+            change_epsilon,
+            ..SubscribeOptions::default()
+        };
+        let (id, drain) = subscribe(data, sink, options)?;
+        tokio::spawn(drain);
+        Ok(id)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disconnected_subscriber_is_dropped_and_stepping_stops() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(3);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 20).await;
+        let after_disconnect = sim_time(&instances).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 20).await;
+        let data = stop(&instances, physics).await;
+
+        // three delivered frames plus the one whose send failed, nothing after
+        assert_eq!(sink.attempts(), 4);
+        assert!(data.subscribers.is_empty());
+        assert_eq!(data.sim_time, after_disconnect);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn subscribers_share_one_driver() {
+        let (instances, mut physics) = new_data();
+        let first = ClosingSink::new(usize::MAX);
+        let second = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            first.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 5).await;
+        let sink = second.clone();
+        with(&instances, move |data| {
+            join(data, sink, StreamPolicy::Shared, DEFAULT_STREAM_FPS, None).unwrap()
+        })
+        .await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+        let data = stop(&instances, physics).await;
+
+        // two subscribers, but sim time still advanced at the wall-clock rate
+        let elapsed = (DEFAULT_TICK_PERIOD * 15).as_secs_f64();
+        assert!((data.sim_time - elapsed).abs() <= 2.0 * TICK);
+        assert!(second.attempts() > 0 && second.attempts() < first.attempts());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sim_time_tracks_wall_time() {
+        let (instances, mut physics) = new_data();
+        // at most one substep per tick, which a busy test machine can't
+        // leave half done and count as deficit
+        physics.world.primary().dt = 2.0 * TICK;
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!((sim_time(&instances).await - 5.0).abs() <= 2.0 * TICK);
+
+        with(&instances, |data| data.time_scale = 2.0).await;
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let data = stop(&instances, physics).await;
+        assert!((data.sim_time - 15.0).abs() <= 3.0 * TICK);
+        assert_eq!(data.clock.deficit, 0.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_run_clock_never_jumps_or_runs_backwards() {
+        let (instances, mut physics) = new_data();
+        let dt = 2.0 * TICK;
+        physics.world.primary().dt = dt;
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+
+        // each phase edits the state, then runs a second of ticks
+        type Phase = fn(&mut AppDataInner);
+        let phases: [(Phase, f64); 6] = [
+            (|_| {}, 1.0),
+            (|d| d.save_snapshot("start", 0.0).map(drop).unwrap(), 1.0),
+            (|d| d.user_paused = true, 0.0),
+            (|d| d.user_paused = false, 1.0),
+            (|d| d.time_scale = 4.0, 4.0),
+            (|d| d.restore_snapshot("start").unwrap(), 1.0),
+        ];
+        let read = |data: &mut AppDataInner| (data.clock.elapsed, data.clock.steps);
+        let (mut last, _) = with(&instances, read).await;
+        for (edit, rate) in phases {
+            with(&instances, edit).await;
+            let (from, _) = with(&instances, read).await;
+            for _ in 0..(1.0 / TICK).round() as u32 {
+                tokio::time::sleep(DEFAULT_TICK_PERIOD).await;
+                let (elapsed, steps) = with(&instances, read).await;
+                assert!(elapsed >= last, "ran backwards from {last} to {elapsed}");
+                assert!(elapsed - last <= rate * TICK + dt + 1e-9, "jumped");
+                assert!((elapsed - steps as f64 * dt).abs() < 1e-9);
+                last = elapsed;
+            }
+            // a second of wall time, at the scale, paused time not counted
+            assert!(
+                (last - from - rate).abs() <= 2.0 * dt,
+                "{last} {from} {rate}"
+            );
+        }
+        // the restore took sim time back, the run clock ran on
+        assert!(sim_time(&instances).await < last - 3.0);
+
+        let mut data = stop(&instances, physics).await;
+        data.reset();
+        assert_eq!(read(&mut data), (0.0, 0));
+        assert_eq!(data.frame(0, 0.0).step_count, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replace_and_reject_policies() {
+        let mut data = AppDataInner::default();
+        let first = ClosingSink::new(usize::MAX);
+        let second = ClosingSink::new(usize::MAX);
+        join(
+            &mut data,
+            first.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+
+        assert!(join(
+            &mut data,
+            second.clone(),
+            StreamPolicy::Reject,
+            DEFAULT_STREAM_FPS,
+            None
+        )
+        .is_err());
+        let id = join(
+            &mut data,
+            second.clone(),
+            StreamPolicy::Replace,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        assert_eq!(data.subscribers.len(), 1);
+        assert_eq!(data.subscribers[0].id, id);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn frame_rate_is_decimated_per_subscriber() {
+        let (instances, mut physics) = new_data();
+        let slow = ClosingSink::new(usize::MAX);
+        let fast = ClosingSink::new(usize::MAX);
+        let primary = physics.world.primary();
+        join(primary, slow.clone(), StreamPolicy::Shared, 30, None).unwrap();
+        let fast_id = join(primary, fast.clone(), StreamPolicy::Shared, 240, None).unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let mut data = stop(&instances, physics).await;
+
+        // 30 fps over two streamed seconds, give or take a tick
+        assert!((59..=61).contains(&slow.attempts()), "{}", slow.attempts());
+        // 240 fps is faster than the physics tick, so it gets every tick
+        assert!(fast.attempts() > 240, "{}", fast.attempts());
+
+        // sequence numbers count each subscription's own frames, so a
+        // decimated stream has no gaps; sim and wall time only move forward
+        for sink in [&slow, &fast] {
+            let frames = sink.full_frames();
+            for (i, pair) in frames.windows(2).enumerate() {
+                assert_eq!(pair[0].seq, i as u64);
+                assert_eq!(pair[1].seq, i as u64 + 1);
+                assert!(pair[1].sim_time >= pair[0].sim_time);
+                assert_eq!(pair[1].span, pair[1].sim_time - pair[0].sim_time);
+                assert!(pair[1].wall_time_ms > pair[0].wall_time_ms);
+            }
+        }
+
+        assert!(set_stream_rate(&mut data, fast_id, 0).is_err());
+        assert!(set_stream_rate(&mut data, fast_id, 241).is_err());
+        assert!(join(&mut data, fast.clone(), StreamPolicy::Shared, 0, None).is_err());
+        set_stream_rate(&mut data, fast_id, 1).unwrap();
+    }
+
+    /// Runs on the real clock: the interval must hold its period despite the
+    /// time spent stepping and sending.
+    #[tokio::test]
+    async fn achieved_tick_period_matches_configuration() {
+        let (instances, mut physics) = new_data();
+        let period = Duration::from_millis(20);
+        physics.world.primary().tick_period = validate_tick_period(period).unwrap();
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            MAX_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+
+        // let the loop pick up the new period before measuring
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let start = (Instant::now(), sink.attempts());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let end = (Instant::now(), sink.attempts());
+        let data = stop(&instances, physics).await;
+
+        let achieved = (end.0 - start.0).as_secs_f64() / (end.1 - start.1) as f64;
+        let relative_error = (achieved - period.as_secs_f64()).abs() / period.as_secs_f64();
+        assert!(relative_error < 0.05, "achieved {achieved} s");
+        let average = data.tick_meter.average.unwrap();
+        assert!((average - period.as_secs_f64()).abs() / period.as_secs_f64() < 0.05);
+        assert!(validate_tick_period(Duration::ZERO).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unchanged_frames_are_suppressed_except_keepalives() {
+        let (instances, mut physics) = new_data();
+        let primary = physics.world.primary();
+        // at rest in the hanging position, so the state never changes
+        primary.pendulum.excite_mode(&[0.0; 4], 0.0);
+        let quiet = ClosingSink::new(usize::MAX);
+        let every = ClosingSink::new(usize::MAX);
+        let quiet_id = join(
+            primary,
+            quiet.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            Some(DEFAULT_CHANGE_EPSILON),
+        )
+        .unwrap();
+        join(
+            primary,
+            every.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_millis(3500)).await;
+
+        // the first frame plus one keepalive per second
+        assert_eq!(quiet.attempts(), 4);
+        assert!(every.attempts() > 400);
+
+        // a late joiner gets a frame straight away
+        let late = ClosingSink::new(usize::MAX);
+        let sink = late.clone();
+        with(&instances, move |data| {
+            let epsilon = Some(DEFAULT_CHANGE_EPSILON);
+            join(
+                data,
+                sink,
+                StreamPolicy::Shared,
+                DEFAULT_STREAM_FPS,
+                epsilon,
+            )
+            .unwrap()
+        })
+        .await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        assert_eq!(late.attempts(), 1);
+
+        // and an edit counts as a change
+        with(&instances, |data| data.pendulum.bobs[0].mass += 1.0).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        assert_eq!(quiet.attempts(), 5);
+
+        with(&instances, move |data| {
+            set_frame_suppression(data, quiet_id, None).unwrap()
+        })
+        .await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+        let mut data = stop(&instances, physics).await;
+        assert!(quiet.attempts() >= 14);
+        assert!(set_frame_suppression(&mut data, quiet_id, Some(f64::NAN)).is_err());
+    }
+
+    /// Takes `delay` to accept each frame, like a busy webview.
+    struct StallingSink {
+        delay: Duration,
+        received: AtomicUsize,
+    }
+
+    impl FrameSink for StallingSink {
+        fn send(&self, _frame: StreamFrame) -> bool {
+            std::thread::sleep(self.delay);
+            self.received.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    /// Runs on the real clock with a blocking sink: the physics must keep
+    /// pace while the slow subscriber loses frames.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn slow_sink_drops_frames_without_stalling_physics() {
+        let (instances, mut physics) = new_data();
+        let slow = Arc::new(StallingSink {
+            delay: Duration::from_millis(50),
+            received: AtomicUsize::new(0),
+        });
+        let fast = ClosingSink::new(usize::MAX);
+        let slow_id = join(
+            physics.world.primary(),
+            slow.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        join(
+            physics.world.primary(),
+            fast.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let data = stop(&instances, physics).await;
+
+        assert!((data.sim_time - 1.0).abs() < 0.1, "{}", data.sim_time);
+        assert!(fast.attempts() > 100, "{}", fast.attempts());
+        assert!(slow.received.load(Ordering::SeqCst) <= 21);
+
+        let stats = stats(&data).unwrap();
+        let slow_stats = stats.subscribers.iter().find(|s| s.id == slow_id).unwrap();
+        assert!(slow_stats.dropped_frames > 50, "{slow_stats:?}");
+        assert!(slow_stats.queued <= FRAME_QUEUE_CAPACITY);
+        assert!(stats.subscribers.iter().any(|s| s.dropped_frames == 0));
+    }
+
+    /// Bursts of edits between every tick, on the paused clock so the count
+    /// of ticks is exact: they must neither hold the ticks up nor leave a
+    /// gap in the frames.
+    #[tokio::test(start_paused = true)]
+    async fn rapid_edits_keep_the_frame_cadence() {
+        const BURST: usize = 10;
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        let primary = physics.world.primary();
+        let mass = primary.pendulum.bobs[0].mass;
+        join(primary, sink.clone(), StreamPolicy::Shared, 60, None).unwrap();
+        let physics = spawn_physics(physics);
+
+        let started = Instant::now();
+        let heartbeat = with(&instances, |data| data.heartbeat).await;
+        let edits = Arc::new(AtomicUsize::new(0));
+        let editors: Vec<_> = (0..4)
+            .map(|_| {
+                let (instances, edits) = (instances.clone(), edits.clone());
+                tokio::spawn(async move {
+                    while started.elapsed() < Duration::from_secs(1) {
+                        for _ in 0..BURST {
+                            let edit = instances.edit(None, |data| {
+                                let mass = data.pendulum.bobs[0].mass + 1.0;
+                                data.modify_bob(0, None, Some(mass), None, None)
+                            });
+                            edit.await.unwrap();
+                            edits.fetch_add(1, Ordering::SeqCst);
+                        }
+                        tokio::time::sleep(Duration::from_secs_f64(TICK / 4.0)).await;
+                    }
+                })
+            })
+            .collect();
+        for editor in editors {
+            editor.await.unwrap();
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+        let data = stop(&instances, physics).await;
+
+        let edits = edits.load(Ordering::SeqCst);
+        assert!(edits > 1_000, "{edits}");
+        assert_eq!(data.pendulum.bobs[0].mass, mass + edits as f64);
+        let ticks = (data.heartbeat - heartbeat) as f64;
+        assert!((ticks - elapsed / TICK).abs() <= 1.0, "{ticks} ticks");
+        assert!(
+            (data.sim_time - elapsed).abs() <= 2.0 * TICK,
+            "{}",
+            data.sim_time
+        );
+        let times: Vec<f64> = (sink.full_frames().iter())
+            .map(|f| f.wall_time_ms)
+            .collect();
+        let gap = times.windows(2).map(|w| w[1] - w[0]).fold(0.0, f64::max);
+        assert!(gap < 3.0 * 1e3 / 60.0, "a gap of {gap} ms");
+    }
+
+    fn join_topic(
+        data: &mut AppDataInner,
+        sink: Arc<dyn FrameSink>,
+        topic: Topic,
+        fps: u32,
+    ) -> u64 {
+        let options = SubscribeOptions {
+            topic,
+            fps,
+            ..SubscribeOptions::default()
+        };
+        let (id, drain) = subscribe(data, sink, options).unwrap();
+        tokio::spawn(drain);
+        id
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn topics_are_computed_only_for_their_subscribers() {
+        let (instances, mut physics) = new_data();
+        let primary = physics.world.primary();
+        let positions = ClosingSink::new(usize::MAX);
+        join_topic(
+            primary,
+            positions.clone(),
+            Topic::Positions,
+            DEFAULT_STREAM_FPS,
+        );
+        let (frames, _) = tick(primary, &mut Vec::new()).unwrap();
+        assert!(frames.positions.is_some());
+        assert!(frames.energetics.is_none() && frames.diagnostics.is_none());
+
+        let energetics = ClosingSink::new(usize::MAX);
+        let diagnostics = ClosingSink::new(usize::MAX);
+        join_topic(primary, energetics.clone(), Topic::Energetics, 10);
+        join_topic(primary, diagnostics.clone(), Topic::Diagnostics, 5);
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let mut data = stop(&instances, physics).await;
+
+        // each topic at its own rate, with its own frames
+        assert!(positions.attempts() > 200);
+        assert!((19..=21).contains(&energetics.attempts()));
+        assert!((9..=11).contains(&diagnostics.attempts()));
+        for frame in energetics.frames.lock().unwrap().iter() {
+            let StreamFrame::Energetics(e) = frame else {
+                panic!("expected an energetics frame");
+            };
+            assert!((e.total - e.kinetic - e.potential).abs() < 1e-9 * e.total.abs());
+            assert_eq!(e.tensions.len(), 4);
+        }
+        for frame in diagnostics.frames.lock().unwrap().iter() {
+            let StreamFrame::Diagnostics(d) = frame else {
+                panic!("expected a diagnostics frame");
+            };
+            assert!(d.condition_number >= 1.0);
+            assert!(d.substeps <= SUBSTEPS_PER_TICK);
+            assert!(d.energy_drift.abs() < 0.1);
+        }
+        positions.full_frames();
+
+        // replacing one topic's subscribers leaves the others alone
+        let replacement = ClosingSink::new(usize::MAX);
+        let options = SubscribeOptions {
+            topic: Topic::Energetics,
+            policy: StreamPolicy::Replace,
+            ..SubscribeOptions::default()
+        };
+        let (_, drain) = subscribe(&mut data, replacement, options).unwrap();
+        tokio::spawn(drain);
+        let topics: Vec<Topic> = data.subscribers.iter().map(|s| s.topic).collect();
+        assert_eq!(
+            topics,
+            [Topic::Positions, Topic::Diagnostics, Topic::Energetics]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flips_are_emitted_as_events() {
+        let (instances, mut physics) = new_data();
+        {
+            let app_data = physics.world.primary();
+            app_data.pause_without_subscribers = false;
+            app_data.pendulum = Pendulum::new(vec![Bob::new(1.0, 1.0, PI, 12.0)]);
+            app_data.dt = 1e-3;
+        }
+        let events = Arc::new(RecordedEvents::default());
+        let physics = spawn_physics_with_events(physics, events.clone());
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+        let data = stop(&instances, physics).await;
+
+        // the loop catches up at most 32 substeps of 1 ms per 8 ms tick, so
+        // it runs at full speed; each turn is reported once
+        let theta = data.pendulum.bobs[0].theta;
+        let events = events.0.lock().unwrap();
+        assert_eq!(events.len(), (theta / (2.0 * PI)).floor() as usize);
+        assert!(events.len() > 2);
+        assert!(events.iter().all(|e| e.name() == crate::events::FLIP_EVENT));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn structural_changes_arrive_with_the_new_layout() {
+        let (instances, mut physics) = new_data();
+        let every = ClosingSink::new(usize::MAX);
+        let slow = ClosingSink::new(usize::MAX);
+        let primary = physics.world.primary();
+        join(
+            primary,
+            every.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        join(primary, slow.clone(), StreamPolicy::Shared, 2, None).unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        with(&instances, |app_data| {
+            app_data.pendulum.bobs.remove(1);
+            app_data.structure_changed(StructuralOperation::Removed, 1);
+            app_data.pendulum.bobs.push(Bob::new(1.0, 1.0, PI, 0.0));
+            app_data.structure_changed(StructuralOperation::Added, 3);
+        })
+        .await;
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        physics.abort();
+
+        // the decimated subscriber gets both notices together, and nobody
+        // sees the new layout before its notice
+        for sink in [&every, &slow] {
+            let frames = sink.full_frames();
+            let first_new = frames.iter().position(|f| !f.structure_changes.is_empty());
+            let first_new = first_new.unwrap();
+            assert!(frames[..first_new].iter().all(|f| f.bobs.len() == 4));
+            assert_eq!(
+                frames[first_new].structure_changes,
+                [
+                    StructuralChange {
+                        operation: StructuralOperation::Removed,
+                        index: 1,
+                        bob_count: 3,
+                    },
+                    StructuralChange {
+                        operation: StructuralOperation::Added,
+                        index: 3,
+                        bob_count: 4,
+                    },
+                ]
+            );
+            assert!(frames[first_new + 1..]
+                .iter()
+                .all(|f| f.structure_changes.is_empty()));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_empty_chain_idles_on_every_topic() {
+        let (instances, mut physics) = new_data();
+        let primary = physics.world.primary();
+        let sinks: Vec<_> = [Topic::Positions, Topic::Energetics, Topic::Diagnostics]
+            .into_iter()
+            .map(|topic| {
+                let sink = ClosingSink::new(usize::MAX);
+                join_topic(primary, sink.clone(), topic, DEFAULT_STREAM_FPS);
+                sink
+            })
+            .collect();
+        primary.analytic = LinearSolution::fit(&primary.pendulum, 0.0);
+        primary.set_bob_count(0, None).unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let (sim_time, nan_recoveries) = with(&instances, |data| {
+            (data.sim_time, data.counters.nan_recoveries)
+        })
+        .await;
+        assert!(sim_time > 0.0);
+        assert_eq!(nan_recoveries, 0);
+        let frames = sinks[0].full_frames();
+        let first = frames
+            .iter()
+            .position(|f| !f.structure_changes.is_empty())
+            .unwrap();
+        assert_eq!(
+            frames[first].structure_changes,
+            [StructuralChange {
+                operation: StructuralOperation::Resized,
+                index: 0,
+                bob_count: 0,
+            }]
+        );
+        assert!(frames[first..].iter().all(|f| f.bobs.is_empty()));
+        assert!(sinks.iter().all(|sink| sink.attempts() > 10));
+
+        // and picks up again when bobs come back
+        with(&instances, |data| data.set_bob_count(2, None).unwrap()).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        physics.abort();
+        let frames = sinks[0].full_frames();
+        let last = frames.last().unwrap();
+        assert_eq!(last.bobs.len(), 2);
+        assert!(last.bobs.iter().all(|b| b.theta.is_finite()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reset_restores_the_initial_conditions_in_a_clean_frame() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        let app_data = physics.world.primary();
+        join(
+            app_data,
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            Some(DEFAULT_CHANGE_EPSILON),
+        )
+        .unwrap();
+        app_data.pendulum.bobs.pop();
+        app_data.initial = app_data.pendulum.clone();
+        app_data.pendulum.bobs.push(Bob::new(1.0, 2.0, PI, 1.0));
+        let initial = app_data.initial.clone();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        with(&instances, |data| data.user_paused = true).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        let before = sink.full_frames().len();
+
+        with(&instances, AppDataInner::reset).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        let frames = sink.full_frames();
+        let frame = &frames[before];
+        assert_eq!(frame.sim_time, 0.0);
+        assert_eq!(frame.span, 0.0);
+        assert_eq!(frame.paused, Some(PauseReason::User));
+        assert_eq!(
+            frame.structure_changes,
+            [StructuralChange {
+                operation: StructuralOperation::Reset,
+                index: 0,
+                bob_count: 3,
+            }]
+        );
+        let thetas: Vec<f64> = frame.bobs.iter().map(|b| b.theta).collect();
+        let expected: Vec<f64> = initial.bobs.iter().map(|b| b.theta).collect();
+        assert_eq!(thetas, expected);
+        let positions = initial.positions_for(&expected);
+        assert!(frame
+            .bobs
+            .iter()
+            .zip(positions)
+            .all(|(b, p)| b.position == p));
+        let (substeps, energy_reference) = with(&instances, |data| {
+            (data.counters.substeps, data.energy_reference)
+        })
+        .await;
+        assert_eq!(substeps, 0);
+        assert_eq!(energy_reference, None);
+
+        // and stepping carries on from there once resumed
+        with(&instances, |data| data.user_paused = false).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let data = stop(&instances, physics).await;
+        assert!((data.sim_time - 1.0).abs() <= 2.0 * TICK);
+    }
+
+    #[test]
+    fn dropped_frames_pass_their_notices_on() {
+        let queue = FrameQueue::default();
+        let mut data = AppDataInner::default();
+        data.pendulum.bobs.pop();
+        let change = StructuralChange {
+            operation: StructuralOperation::Removed,
+            index: 3,
+            bob_count: 3,
+        };
+        queue.push(StreamFrame::Full(PendulumState {
+            structure_changes: vec![change],
+            ..data.frame(0, 0.0)
+        }));
+        for i in 1..=FRAME_QUEUE_CAPACITY as u64 {
+            queue.push(StreamFrame::Full(data.frame(i, 0.0)));
+        }
+        assert_eq!(queue.dropped.load(Ordering::Relaxed), 1);
+        let Some(StreamFrame::Full(first)) = queue.pop() else {
+            panic!("expected a full frame");
+        };
+        assert_eq!(first.structure_changes, [change]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn background_pause_is_reported_and_not_caught_up() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        with(&instances, |data| data.in_background = true).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        let hidden_at = sim_time(&instances).await;
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(sim_time(&instances).await, hidden_at);
+        let last = sink.full_frames().pop().unwrap();
+        assert_eq!(last.paused, Some(PauseReason::Background));
+
+        // a user pause outranks the background one and outlasts it
+        with(&instances, |data| {
+            data.user_paused = true;
+            data.in_background = false;
+        })
+        .await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        assert_eq!(sim_time(&instances).await, hidden_at);
+        let last = sink.full_frames().pop().unwrap();
+        assert_eq!(last.paused, Some(PauseReason::User));
+
+        // on resume the minute away is not simulated
+        with(&instances, |data| data.user_paused = false).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!((sim_time(&instances).await - hidden_at - 1.0).abs() <= 2.0 * TICK);
+        assert_eq!(sink.full_frames().pop().unwrap().paused, None);
+
+        // and it can be turned off
+        with(&instances, |data| {
+            data.pause_in_background = false;
+            data.in_background = true;
+        })
+        .await;
+        let before = sim_time(&instances).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let data = stop(&instances, physics).await;
+        assert!(data.sim_time > before + 0.9);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn edits_while_paused_show_at_once_and_resume_continues() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            Some(DEFAULT_CHANGE_EPSILON),
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        with(&instances, |data| data.user_paused = true).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        let paused_at = sim_time(&instances).await;
+
+        let edit = |data: &mut AppDataInner| data.modify_bob(1, None, None, Some(2.0), Some(0.0));
+        instances.call(None, edit).await.unwrap();
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        let last = sink.full_frames().pop().unwrap();
+        assert_eq!(last.paused, Some(PauseReason::User));
+        assert_eq!(last.sim_time, paused_at);
+        assert_eq!(last.bobs[1].theta, 2.0);
+        let x = last.bobs[0].position.x + last.bobs[1].length_rod * 2.0f64.sin();
+        assert!((last.bobs[1].position.x - x).abs() < 1e-9);
+
+        // ten seconds later, sim time picks up where it stopped
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(sim_time(&instances).await, paused_at);
+        with(&instances, |data| data.user_paused = false).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let data = stop(&instances, physics).await;
+        assert!((data.sim_time - paused_at - 1.0).abs() <= 2.0 * TICK);
+        let last = sink.full_frames().pop().unwrap();
+        assert_eq!(last.paused, None);
+        assert_ne!(last.bobs[1].theta, 2.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_paused_edit_is_in_the_very_next_frame() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            Some(DEFAULT_CHANGE_EPSILON),
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        with(&instances, |data| data.user_paused = true).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+        let sent = sink.full_frames().len();
+
+        instances
+            .edit(None, |app_data| {
+                app_data.modify_bob(0, Some(150.0), None, Some(1.0), None)
+            })
+            .await
+            .unwrap();
+        // well before the next tick, let alone the next frame due
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        physics.abort();
+        let frames = sink.full_frames();
+        assert_eq!(frames.len(), sent + 1);
+        let next = frames.last().unwrap();
+        let pivot = next.pivot;
+        let (x, y) = (
+            pivot.x + 150.0 * 1.0f64.sin(),
+            pivot.y + 150.0 * 1.0f64.cos(),
+        );
+        assert!((next.bobs[0].position.x - x).abs() < 1e-9);
+        assert!((next.bobs[0].position.y - y).abs() < 1e-9);
+        let below = next.bobs[1].position;
+        let theta = next.bobs[1].theta;
+        assert!((below.x - x - next.bobs[1].length_rod * theta.sin()).abs() < 1e-9);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn single_steps_push_a_frame_at_once_while_paused() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            1,
+            Some(DEFAULT_CHANGE_EPSILON),
+        )
+        .unwrap();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        let step = |dt| instances.call(None, move |data| single_step(data, dt, &mut Vec::new()));
+        assert!(step(None).await.is_err());
+
+        with(&instances, |data| data.user_paused = true).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 2).await;
+        let (paused_at, sent) = (sim_time(&instances).await, sink.attempts());
+        let last_sent = sink.full_frames().pop().unwrap().sim_time;
+        let report = step(Some(0.001)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(sink.attempts(), sent + 1);
+        assert_eq!(report.sim_time, paused_at + 0.001);
+        let frame = sink.full_frames().pop().unwrap();
+        assert_eq!(frame.sim_time, report.sim_time);
+        assert_eq!(frame.span, report.sim_time - last_sent);
+        let alphas: Vec<f64> = frame.bobs.iter().map(|b| b.alpha).collect();
+        assert_eq!(alphas, report.alphas);
+        let total = with(&instances, |data| data.pendulum.total_energy()).await;
+        assert_eq!(report.total, total);
+
+        // the stepping itself stays paused
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(step(Some(-1.0)).await.is_err());
+        let data = stop(&instances, physics).await;
+        assert_eq!(data.sim_time, report.sim_time);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zeroing_velocities_freezes_the_pose_in_a_pushed_frame() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            1,
+            None,
+        )
+        .unwrap();
+        let events = Arc::new(RecordedEvents::default());
+        let physics = spawn_physics_with_events(physics, events.clone());
+        tokio::time::sleep(Duration::from_millis(2_500)).await;
+        let sent = sink.attempts();
+        let before = with(&instances, |data| data.pendulum.clone()).await;
+        (instances.edit(None, AppDataInner::zero_velocities).await).unwrap();
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(sink.attempts(), sent + 1);
+        let frame = sink.full_frames().pop().unwrap();
+        assert!(frame.bobs.iter().all(|b| b.omega == 0.0));
+        let thetas = |bobs: &[Bob]| bobs.iter().map(|b| b.theta).collect::<Vec<_>>();
+        let frame_thetas: Vec<f64> = frame.bobs.iter().map(|b| b.theta).collect();
+        assert_eq!(frame_thetas, thetas(&before.bobs));
+        let (energy_reference, history) = with(&instances, |data| {
+            (data.energy_reference, data.history.summary())
+        })
+        .await;
+        assert_eq!(energy_reference, None);
+        assert_eq!(history.undo, [Edit::ZeroVelocities]);
+
+        // it falls from rest from there, with no flips out of the edit
+        let flips = events.0.lock().unwrap().len();
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 4).await;
+        let app_data = stop(&instances, physics).await;
+        assert_eq!(events.0.lock().unwrap().len(), flips);
+        let moved = thetas(&app_data.pendulum.bobs);
+        assert!(moved
+            .iter()
+            .zip(thetas(&before.bobs))
+            .all(|(a, b)| (a - b).abs() < 0.1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn each_subscription_reads_the_angles_its_way() {
+        let mut data = AppDataInner::default();
+        let subscribe_with = |data: &mut AppDataInner, theta_policy| {
+            let sink = ClosingSink::new(usize::MAX);
+            let options = SubscribeOptions {
+                theta_policy,
+                ..SubscribeOptions::default()
+            };
+            let (_, drain) = subscribe(data, sink.clone(), options).unwrap();
+            tokio::spawn(drain);
+            sink
+        };
+        let unbounded = subscribe_with(&mut data, ThetaPolicy::Unbounded);
+        let wrapped = subscribe_with(&mut data, ThetaPolicy::Wrapped);
+        let turned = 4.0 * PI + 1.0;
+        edit_and_push(&mut data, |app_data| {
+            app_data.pendulum.bobs[0].theta = turned;
+            app_data.state_edited();
+            Ok(())
+        })
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        let first_theta = |sink: &ClosingSink| sink.full_frames().pop().unwrap().bobs[0].theta;
+        assert_eq!(first_theta(&unbounded), turned);
+        assert!((first_theta(&wrapped) - 1.0).abs() < 1e-12);
+        assert_eq!(data.pendulum.bobs[0].theta, turned);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ping_answers_on_the_frame_clock() {
+        let (instances, mut physics) = new_data();
+        let primary = physics.world.primary();
+        let positions = ClosingSink::new(usize::MAX);
+        let diagnostics = ClosingSink::new(usize::MAX);
+        let id = join_topic(
+            primary,
+            positions.clone(),
+            Topic::Positions,
+            DEFAULT_STREAM_FPS,
+        );
+        join_topic(
+            primary,
+            diagnostics.clone(),
+            Topic::Diagnostics,
+            DEFAULT_STREAM_FPS,
+        );
+        primary.user_paused = true;
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let pong = (instances.call(None, move |data| ping(data, 1234.5, Some(id)))).await;
+        let pong = pong.unwrap();
+        tokio::time::sleep(DEFAULT_TICK_PERIOD).await;
+        let data = stop(&instances, physics).await;
+
+        assert_eq!(pong.client_timestamp, 1234.5);
+        let frames = positions.full_frames();
+        let last = frames
+            .iter()
+            .rfind(|f| Some(f.seq) == pong.last_seq)
+            .unwrap();
+        assert!(last.wall_time_ms <= pong.server_time_ms);
+        assert!((pong.server_time_ms - 500.0).abs() < 1.0);
+        assert!(pong.frames_built > 0);
+        assert!(ping(&data, 0.0, Some(id + 100)).is_err());
+
+        // the heartbeat keeps counting while paused and suppressed
+        let heartbeats: Vec<u64> = diagnostics
+            .frames
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|frame| match frame {
+                StreamFrame::Diagnostics(d) => d.heartbeat,
+                _ => panic!("expected a diagnostics frame"),
+            })
+            .collect();
+        assert!(heartbeats.len() > 2);
+        assert!(heartbeats.windows(2).all(|w| w[1] > w[0]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn simulation_stats_count_fallbacks_and_recoveries_until_reset() {
+        let mut data = AppDataInner {
+            pause_without_subscribers: false,
+            ..AppDataInner::default()
+        };
+        let steps = |data: &mut AppDataInner| {
+            tick(data, &mut Vec::new());
+            data.last_substeps as u64
+        };
+        steps(&mut data);
+        tokio::time::advance(Duration::from_millis(100)).await;
+        let regular = steps(&mut data);
+
+        // a blown-up state is rolled back to rest
+        data.pendulum.bobs[1].omega = f64::INFINITY;
+        tokio::time::advance(Duration::from_millis(100)).await;
+        let recovered = steps(&mut data);
+        assert!(data.pendulum.is_finite());
+
+        // a zero-length first rod makes the mass matrix singular
+        data.pendulum.bobs[0].length_rod = 0.0;
+        tokio::time::advance(Duration::from_millis(100)).await;
+        let singular = steps(&mut data);
+
+        let stats = simulation_stats(&data).unwrap();
+        assert_eq!(stats.substeps, regular + recovered + singular);
+        assert!((stats.sim_time - stats.substeps as f64 * DEFAULT_DT).abs() < 1e-9);
+        assert_eq!(stats.nan_recoveries, 1);
+        assert_eq!(stats.singular_fallbacks, singular);
+        assert!(singular > 0);
+        assert_eq!(stats.config.bob_count, 4);
+        assert_eq!(stats.config.integrator, INTEGRATOR);
+        let times = stats.step_times.unwrap();
+        assert!(times.p95 >= 0.0 && times.average >= 0.0);
+
+        reset_stats(&mut data).unwrap();
+        let stats = simulation_stats(&data).unwrap();
+        assert_eq!((stats.substeps, stats.singular_fallbacks), (0, 0));
+        assert_eq!(stats.step_times, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_stepping_without_subscribers_when_configured() {
+        let (instances, physics) = new_data();
+        let physics = spawn_physics(physics);
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+        assert_eq!(sim_time(&instances).await, 0.0);
+
+        with(&instances, |data| data.pause_without_subscribers = false).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+        let data = stop(&instances, physics).await;
+        assert!(data.sim_time > 0.0);
+    }
+
+    /// Runs a command that panics, as a bug in one would, after `corrupt`
+    /// had its way with the state.
+    async fn panic_in(
+        instances: &Instances,
+        corrupt: impl FnOnce(&mut AppDataInner) + Send + 'static,
+    ) {
+        let result: Result<(), _> = instances
+            .call(None, |app_data| {
+                corrupt(app_data);
+                panic!("a bug halfway through an edit");
+            })
+            .await;
+        assert!(matches!(result, Err(CommandError::Internal { .. })));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn commands_and_stepping_carry_on_after_a_panic() {
+        let (instances, mut physics) = new_data();
+        let sink = ClosingSink::new(usize::MAX);
+        join(
+            physics.world.primary(),
+            sink.clone(),
+            StreamPolicy::Shared,
+            DEFAULT_STREAM_FPS,
+            None,
+        )
+        .unwrap();
+        let events = Arc::new(RecordedEvents::default());
+        let physics = spawn_physics_with_events(physics, events.clone());
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+
+        // a panic that left the state sound: the physics task gets past it
+        // and the simulation goes on as it was, an edit's flag lowered
+        let before = sim_time(&instances).await;
+        panic_in(&instances, |app_data| app_data.history.in_edit = true).await;
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+        assert!(sim_time(&instances).await > before);
+        let edit =
+            |app_data: &mut AppDataInner| app_data.modify_bob(0, Some(2.0), None, None, None);
+        instances.edit(None, edit).await.unwrap();
+        let history = with(&instances, |data| data.history.summary()).await;
+        assert_eq!(history.undo, [Edit::ModifyBob]);
+
+        // one that left a bob invalid and the chain short: the commands
+        // after it carry on, and the default chain starts over
+        panic_in(&instances, |app_data| {
+            app_data.pendulum.bobs[1].mass = f64::NAN;
+            app_data.pendulum.bobs.pop();
+        })
+        .await;
+        (instances.edit(None, AppDataInner::zero_velocities).await).unwrap();
+        tokio::time::sleep(DEFAULT_TICK_PERIOD * 10).await;
+        let data = stop(&instances, physics).await;
+        assert!(data.sim_time > 0.0);
+        let frames = sink.full_frames();
+        let replaced = frames
+            .iter()
+            .find(|frame| !frame.structure_changes.is_empty())
+            .unwrap();
+        let defaults = Pendulum::default();
+        assert_eq!(replaced.sim_time, 0.0);
+        assert_eq!(
+            replaced.structure_changes[0].operation,
+            StructuralOperation::Replaced
+        );
+        assert_eq!(replaced.bobs.len(), defaults.n());
+        assert!(replaced
+            .bobs
+            .iter()
+            .zip(&defaults.bobs)
+            .all(|(a, b)| a.mass == b.mass && a.omega == 0.0));
+
+        let recoveries: Vec<bool> = events
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                SimEvent::Recovered(recovery) => Some(recovery.reset),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(recoveries, [false, true]);
+    }
+}
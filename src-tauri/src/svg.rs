@@ -0,0 +1,493 @@
+use pendulum_core::{Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bounds::Bounds;
+use crate::error::CommandError;
+use crate::recording::Recording;
+use crate::savefile;
+use crate::state::AppDataInner;
+
+/// Widest stroke an export takes, in pixels.
+pub(crate) const MAX_STROKE_WIDTH: f64 = 100.0;
+/// Widest padding an export takes, in pixels.
+pub(crate) const MAX_PADDING: f64 = 10_000.0;
+/// Most colors a speed gradient is split into.
+pub(crate) const MAX_GRADIENT_SEGMENTS: u32 = 256;
+
+/// Colors the path by speed, from `slow` at the slowest point drawn to
+/// `fast` at the fastest, in `segments` steps, each one a path of its own.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct SpeedGradient {
+    pub(crate) slow: String,
+    pub(crate) fast: String,
+    pub(crate) segments: u32,
+}
+
+impl Default for SpeedGradient {
+    fn default() -> Self {
+        Self {
+            slow: "#2c7bb6".into(),
+            fast: "#d7191c".into(),
+            segments: 16,
+        }
+    }
+}
+
+/// What `export_trail_svg` draws and how. Colors are `#rgb` or `#rrggbb`;
+/// lengths are pixels, each meter drawn `pixels_per_meter` long as in the
+/// frontends.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct SvgOptions {
+    /// Indices of the bobs whose paths are drawn, the tip's alone if unset.
+    pub(crate) bobs: Option<Vec<usize>>,
+    /// Draws the paths of this recording instead of the stored trails.
+    pub(crate) recording_id: Option<u64>,
+    pub(crate) stroke_width: f64,
+    pub(crate) color: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) speed_gradient: Option<SpeedGradient>,
+    /// Draws the chain as it ends up, its rods as lines and its bobs as
+    /// circles.
+    pub(crate) pose: bool,
+    /// Transparent if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) background: Option<String>,
+    /// Space around the motion, on every side.
+    pub(crate) padding: f64,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            bobs: None,
+            recording_id: None,
+            stroke_width: 2.0,
+            color: "#202020".into(),
+            speed_gradient: None,
+            pose: true,
+            background: None,
+            padding: 20.0,
+        }
+    }
+}
+
+/// The red, green and blue of a `#rgb` or `#rrggbb` color.
+fn parse_color(name: &str, color: &str) -> Result<[u8; 3], CommandError> {
+    let invalid = || {
+        CommandError::invalid(
+            name,
+            format!("must be a color like #rgb or #rrggbb, got {color:?}"),
+        )
+    };
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| hex.is_ascii())
+        .ok_or_else(invalid)?;
+    let digit = |i: usize, len: usize| u8::from_str_radix(&hex[i..i + len], 16);
+    match hex.len() {
+        3 => {
+            let mut rgb = [0; 3];
+            for (i, channel) in rgb.iter_mut().enumerate() {
+                *channel = digit(i, 1).map_err(|_| invalid())? * 17;
+            }
+            Ok(rgb)
+        }
+        6 => {
+            let mut rgb = [0; 3];
+            for (i, channel) in rgb.iter_mut().enumerate() {
+                *channel = digit(2 * i, 2).map_err(|_| invalid())?;
+            }
+            Ok(rgb)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+impl SvgOptions {
+    pub(crate) fn validate(&self) -> Result<(), CommandError> {
+        let sw = self.stroke_width;
+        if !(sw.is_finite() && sw > 0.0 && sw <= MAX_STROKE_WIDTH) {
+            return Err(CommandError::invalid(
+                "options.stroke_width",
+                format!("must be positive and at most {MAX_STROKE_WIDTH}, got {sw}"),
+            ));
+        }
+        if !(self.padding.is_finite() && (0.0..=MAX_PADDING).contains(&self.padding)) {
+            return Err(CommandError::invalid(
+                "options.padding",
+                format!("must be between 0 and {MAX_PADDING}, got {}", self.padding),
+            ));
+        }
+        parse_color("options.color", &self.color)?;
+        if let Some(background) = &self.background {
+            parse_color("options.background", background)?;
+        }
+        if let Some(gradient) = &self.speed_gradient {
+            parse_color("options.speed_gradient.slow", &gradient.slow)?;
+            parse_color("options.speed_gradient.fast", &gradient.fast)?;
+            if !(1..=MAX_GRADIENT_SEGMENTS).contains(&gradient.segments) {
+                return Err(CommandError::invalid(
+                    "options.speed_gradient.segments",
+                    format!(
+                        "must be between 1 and {MAX_GRADIENT_SEGMENTS}, got {}",
+                        gradient.segments
+                    ),
+                ));
+            }
+        }
+        if self.bobs.as_ref().is_some_and(Vec::is_empty) {
+            return Err(CommandError::invalid("options.bobs", "must not be empty"));
+        }
+        Ok(())
+    }
+
+    /// The bobs drawn of a chain of `len`.
+    fn bobs(&self, len: usize) -> Result<Vec<usize>, CommandError> {
+        let bobs = match &self.bobs {
+            Some(bobs) => bobs.clone(),
+            None if len > 0 => vec![len - 1],
+            None => return Err(CommandError::unavailable("There are no bobs to draw")),
+        };
+        match bobs.iter().find(|&&index| index >= len) {
+            Some(&index) => Err(CommandError::IndexOutOfBounds { index, len }),
+            None => Ok(bobs),
+        }
+    }
+}
+
+/// What an export draws, in meters with y up: each path as its points with
+/// the speed there, and the chain's last pose.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Scene {
+    paths: Vec<Vec<(Coordinate, f64)>>,
+    pose: Pendulum,
+    pixels_per_meter: f64,
+}
+
+/// What an export is made from, copied under the lock so the drawing needs
+/// none.
+pub(crate) enum SvgSource {
+    Trails(Scene),
+    Recording {
+        recording: Arc<Recording>,
+        pixels_per_meter: f64,
+    },
+}
+
+impl SvgSource {
+    pub(crate) fn capture(data: &AppDataInner, options: &SvgOptions) -> Result<Self, CommandError> {
+        let pixels_per_meter = data.pixels_per_meter;
+        if let Some(id) = options.recording_id {
+            let recording = (data.recordings.iter())
+                .find(|(recording_id, _)| *recording_id == id)
+                .map(|(_, recording)| recording.clone())
+                .ok_or_else(|| CommandError::not_found("recording", id))?;
+            return Ok(Self::Recording {
+                recording,
+                pixels_per_meter,
+            });
+        }
+        let paths = (options.bobs(data.pendulum.n())?.into_iter())
+            .map(|index| {
+                let points = data.trails.trail_of(data.pendulum.bobs[index].id);
+                points.iter().map(|p| (p.position, p.speed)).collect()
+            })
+            .collect();
+        Ok(Self::Trails(Scene {
+            paths,
+            pose: data.pendulum.clone(),
+            pixels_per_meter,
+        }))
+    }
+
+    /// The scene, worked out from every sample for a recording.
+    fn scene(self, options: &SvgOptions) -> Result<Scene, CommandError> {
+        let (recording, pixels_per_meter) = match self {
+            Self::Trails(scene) => return Ok(scene),
+            Self::Recording {
+                recording,
+                pixels_per_meter,
+            } => (recording, pixels_per_meter),
+        };
+        let samples = recording.samples();
+        let first = samples
+            .first()
+            .ok_or_else(|| CommandError::unavailable("The recording has no samples"))?;
+        let bobs = options.bobs(first.bobs.len())?;
+        let mut paths = vec![Vec::with_capacity(samples.len()); bobs.len()];
+        let mut pose = first.pendulum();
+        for sample in samples {
+            pose = sample.pendulum();
+            let velocities = pose.velocities();
+            for (path, &index) in paths.iter_mut().zip(&bobs) {
+                // samples after a layout change may lack the bob
+                if let (Some(bob), Some(v)) = (pose.bobs.get(index), velocities.get(index)) {
+                    path.push((bob.coordinate, v.x.hypot(v.y)));
+                }
+            }
+        }
+        Ok(Scene {
+            paths,
+            pose,
+            pixels_per_meter,
+        })
+    }
+}
+
+/// A color `at` of the way from `a` to `b`.
+fn mix(a: [u8; 3], b: [u8; 3], at: f64) -> String {
+    let channel =
+        |i: usize| (f64::from(a[i]) + at * (f64::from(b[i]) - f64::from(a[i]))).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(0), channel(1), channel(2))
+}
+
+/// The scene as an SVG document, framed by the box around everything
+/// drawn, with y turned to point down as SVG's does.
+pub(crate) fn render(scene: &Scene, options: &SvgOptions) -> Result<String, CommandError> {
+    let mut bounds = Bounds::around(scene.pose.pivot);
+    let pose = scene.pose.bobs.iter().map(|b| b.coordinate);
+    let points = scene.paths.iter().flatten().map(|(p, _)| *p);
+    for point in points.chain(pose.clone().filter(|_| options.pose)) {
+        bounds.extend(point);
+    }
+    let corners = [bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y];
+    if !corners.iter().all(|v| v.is_finite()) {
+        return Err(CommandError::internal("the motion isn't finite"));
+    }
+    let (scale, pad, sw) = (
+        scene.pixels_per_meter,
+        options.padding,
+        options.stroke_width,
+    );
+    let width = (bounds.max.x - bounds.min.x) * scale + 2.0 * pad;
+    let height = (bounds.max.y - bounds.min.y) * scale + 2.0 * pad;
+    let map = |p: Coordinate| {
+        (
+            (p.x - bounds.min.x) * scale + pad,
+            (bounds.max.y - p.y) * scale + pad,
+        )
+    };
+    let path = |points: &mut dyn Iterator<Item = Coordinate>, color: &str| {
+        let mut d = String::new();
+        for (i, point) in points.enumerate() {
+            let (x, y) = map(point);
+            d += &format!("{}{x:.2} {y:.2}", if i == 0 { "M" } else { " L" });
+        }
+        format!(
+            r#"<path d="{d}" fill="none" stroke="{color}" stroke-width="{sw}" stroke-linecap="round" stroke-linejoin="round"/>"#
+        )
+    };
+    let circle = |point: Coordinate, r: f64| {
+        let (x, y) = map(point);
+        format!(
+            r#"<circle cx="{x:.2}" cy="{y:.2}" r="{r:.2}" fill="{}"/>"#,
+            options.color
+        )
+    };
+
+    let mut svg = vec![format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width:.2} {height:.2}" width="{width:.0}" height="{height:.0}">"#
+    )];
+    if let Some(background) = &options.background {
+        svg.push(format!(
+            r#"<rect width="100%" height="100%" fill="{background}"/>"#
+        ));
+    }
+    let drawn = scene.paths.iter().filter(|points| points.len() > 1);
+    match &options.speed_gradient {
+        None => {
+            for points in drawn {
+                svg.push(path(&mut points.iter().map(|(p, _)| *p), &options.color));
+            }
+        }
+        Some(gradient) => {
+            let speeds = || scene.paths.iter().flatten().map(|(_, speed)| *speed);
+            let slowest = speeds().fold(f64::INFINITY, f64::min);
+            let range = speeds().fold(f64::NEG_INFINITY, f64::max) - slowest;
+            let segments = f64::from(gradient.segments);
+            let (slow, fast) = (
+                parse_color("slow", &gradient.slow)?,
+                parse_color("fast", &gradient.fast)?,
+            );
+            for points in drawn {
+                // the step of the mean speed between point i and the next
+                let step = |i: usize| {
+                    let speed = 0.5 * (points[i].1 + points[i + 1].1);
+                    if range > 0.0 {
+                        ((speed - slowest) / range * segments).min(segments - 1.0) as u32
+                    } else {
+                        0
+                    }
+                };
+                let mut start = 0;
+                while start + 1 < points.len() {
+                    let current = step(start);
+                    let mut end = start + 1;
+                    while end + 1 < points.len() && step(end) == current {
+                        end += 1;
+                    }
+                    let at = match gradient.segments {
+                        1 => 0.0,
+                        n => f64::from(current) / f64::from(n - 1),
+                    };
+                    let run = &mut points[start..=end].iter().map(|(p, _)| *p);
+                    svg.push(path(run, &mix(slow, fast, at)));
+                    start = end;
+                }
+            }
+        }
+    }
+    if options.pose {
+        let rods = &mut std::iter::once(scene.pose.pivot).chain(pose.clone());
+        svg.push(path(rods, &options.color));
+        svg.push(circle(scene.pose.pivot, 1.5 * sw));
+        svg.extend(pose.map(|bob| circle(bob, 4.0 * sw)));
+    }
+    svg.push("</svg>\n".into());
+    Ok(svg.join("\n"))
+}
+
+/// What `export_trail_svg` returns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SvgReport {
+    pub(crate) path: String,
+    /// Size of the file, in bytes.
+    pub(crate) bytes: u64,
+    /// Points on the paths drawn.
+    pub(crate) points: usize,
+}
+
+/// Draws `source` and writes it to `path`, replacing what is there.
+pub(crate) fn export(
+    path: &Path,
+    source: SvgSource,
+    options: &SvgOptions,
+) -> Result<SvgReport, CommandError> {
+    let scene = source.scene(options)?;
+    let svg = render(&scene, options)?;
+    savefile::write_atomically(path, svg.as_bytes()).map_err(|e| CommandError::io(path, e))?;
+    Ok(SvgReport {
+        path: path.display().to_string(),
+        bytes: svg.len() as u64,
+        points: scene.paths.iter().map(Vec::len).sum(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(data: &mut AppDataInner, steps: usize) {
+        for _ in 0..steps {
+            let dt = data.dt;
+            data.substep(dt, &mut Vec::new());
+        }
+    }
+
+    #[test]
+    fn trails_draw_inside_the_view_box_with_the_pose_on_top() {
+        let mut data = AppDataInner::default();
+        let options = SvgOptions::default();
+        run(&mut data, 2_000);
+        let source = SvgSource::capture(&data, &options).unwrap();
+        let SvgSource::Trails(scene) = &source else {
+            panic!("expected the trails");
+        };
+        assert_eq!(scene.paths.len(), 1);
+        let svg = render(scene, &options).unwrap();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 "));
+        assert!(svg.ends_with("</svg>\n"));
+        // the tip's path and the rods, the pivot and every bob
+        assert_eq!(svg.matches("<path").count(), 2);
+        assert_eq!(svg.matches("<circle").count(), 1 + data.pendulum.n());
+
+        // every point lies within the view box
+        let view: Vec<f64> = svg
+            .split("viewBox=\"")
+            .nth(1)
+            .unwrap()
+            .split('"')
+            .next()
+            .unwrap()
+            .split(' ')
+            .map(|v| v.parse().unwrap())
+            .collect();
+        let d = svg.split("d=\"").nth(1).unwrap().split('"').next().unwrap();
+        for pair in d.split(" L") {
+            let mut xy = pair
+                .trim_start_matches('M')
+                .split(' ')
+                .map(|v| v.parse::<f64>().unwrap());
+            let (x, y) = (xy.next().unwrap(), xy.next().unwrap());
+            assert!(
+                x >= 0.0 && x <= view[2] && y >= 0.0 && y <= view[3],
+                "{x} {y}"
+            );
+        }
+
+        // a gradient splits the path into colors; y points down in the file
+        let gradient = SvgOptions {
+            speed_gradient: Some(SpeedGradient::default()),
+            background: Some("#fff".into()),
+            bobs: Some(vec![0, 3]),
+            ..SvgOptions::default()
+        };
+        let SvgSource::Trails(scene) = SvgSource::capture(&data, &gradient).unwrap() else {
+            panic!("expected the trails");
+        };
+        let svg = render(&scene, &gradient).unwrap();
+        assert!(svg.matches("<path").count() > 4);
+        assert!(svg.contains("stroke=\"#2c7bb6\"") && svg.contains("stroke=\"#d7191c\""));
+        assert!(svg.contains("fill=\"#fff\""));
+        for bob in &mut data.pendulum.bobs {
+            (bob.theta, bob.omega) = (std::f64::consts::PI, 0.0);
+        }
+        data.pendulum.update_coordinates();
+        let hanging = Scene {
+            paths: Vec::new(),
+            pose: data.pendulum.clone(),
+            ..scene
+        };
+        let svg = render(&hanging, &options).unwrap();
+        let cy: Vec<f64> = (svg.split("cy=\"").skip(1))
+            .map(|rest| rest.split('"').next().unwrap().parse().unwrap())
+            .collect();
+        assert!(cy.windows(2).all(|w| w[0] < w[1]), "{cy:?}");
+
+        // malformed options name what is wrong
+        let name = |options: SvgOptions| match options.validate() {
+            Err(CommandError::InvalidParameter { name, .. }) => name,
+            other => panic!("expected an invalid parameter, got {other:?}"),
+        };
+        let with = |f: fn(&mut SvgOptions)| {
+            let mut options = SvgOptions::default();
+            f(&mut options);
+            options
+        };
+        assert_eq!(name(with(|o| o.stroke_width = 0.0)), "options.stroke_width");
+        assert_eq!(name(with(|o| o.color = "red\"/>".into())), "options.color");
+        assert_eq!(
+            name(with(|o| o.background = Some("#12345".into()))),
+            "options.background"
+        );
+        assert_eq!(name(with(|o| o.padding = f64::NAN)), "options.padding");
+        let no_segments = with(|o| {
+            o.speed_gradient = Some(SpeedGradient {
+                segments: 0,
+                ..SpeedGradient::default()
+            })
+        });
+        assert_eq!(name(no_segments), "options.speed_gradient.segments");
+        let out_of_range = with(|o| o.bobs = Some(vec![9]));
+        assert!(matches!(
+            SvgSource::capture(&data, &out_of_range),
+            Err(CommandError::IndexOutOfBounds { index: 9, .. })
+        ));
+        assert_eq!(parse_color("c", "#0a0").unwrap(), [0, 170, 0]);
+    }
+}
@@ -0,0 +1,652 @@
+use pendulum_core::{validate_parameter, wrap_angle, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::comparison;
+use crate::error::CommandError;
+use crate::events::FlipDetector;
+use crate::parallel;
+use crate::recording::RecordingConfiguration;
+use crate::savefile;
+use crate::trajectory::{write_trajectory, RecordingFormat};
+
+/// Name of the summary a sweep writes in its output directory.
+pub(crate) const SWEEP_SUMMARY_FILE: &str = "sweep.csv";
+/// Most values one axis of a sweep takes.
+pub(crate) const MAX_AXIS_STEPS: u32 = 1_000;
+/// Most runs one sweep takes.
+pub(crate) const MAX_SWEEP_RUNS: u64 = 10_000;
+/// Most steps one run of a sweep takes.
+pub(crate) const MAX_SWEEP_RUN_STEPS: u64 = 5_000_000;
+/// Steps between looks at the cancel flag.
+const CANCEL_INTERVAL: u64 = 10_000;
+
+/// One field of every bob.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum BobField {
+    LengthRod,
+    Mass,
+    Theta,
+    Omega,
+}
+
+impl BobField {
+    fn name(self) -> &'static str {
+        match self {
+            Self::LengthRod => "length_rod",
+            Self::Mass => "mass",
+            Self::Theta => "theta",
+            Self::Omega => "omega",
+        }
+    }
+}
+
+/// What an axis of a sweep varies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "kind"
+)]
+pub(crate) enum SweepParameter {
+    /// A field of the bob at `index` in the chain, in meters, kilograms and
+    /// canonical radians.
+    Bob { index: usize, field: BobField },
+    /// Joint friction, in N·m·s/rad.
+    Damping,
+    /// The step each run takes, in seconds.
+    Dt,
+}
+
+impl SweepParameter {
+    /// Its column in the summary.
+    fn name(&self) -> String {
+        match self {
+            Self::Bob { index, field } => format!("bobs[{index}].{}", field.name()),
+            Self::Damping => "damping".into(),
+            Self::Dt => "dt_s".into(),
+        }
+    }
+
+    fn check(&self, value: f64, pendulum: &Pendulum, horizon: f64) -> Result<(), CommandError> {
+        match *self {
+            Self::Bob { index, field } => {
+                let len = pendulum.n();
+                if index >= len {
+                    return Err(CommandError::IndexOutOfBounds { index, len });
+                }
+                Ok(validate_parameter(field.name(), value)?)
+            }
+            Self::Damping => comparison::validate_damping(value).map(|_| ()),
+            Self::Dt => {
+                if !(value.is_finite() && value > 0.0) {
+                    return Err(CommandError::invalid(
+                        "dt",
+                        format!("must be positive and finite, got {value}"),
+                    ));
+                }
+                let steps = run_steps(horizon, value);
+                if steps > MAX_SWEEP_RUN_STEPS {
+                    return Err(CommandError::invalid(
+                        "dt",
+                        format!("of {value} s takes {steps} steps a run, more than the limit of {MAX_SWEEP_RUN_STEPS}"),
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn apply(&self, value: f64, pendulum: &mut Pendulum, dt: &mut f64) {
+        match *self {
+            Self::Bob { index, field } => {
+                let bob = &mut pendulum.bobs[index];
+                match field {
+                    BobField::LengthRod => bob.length_rod = value,
+                    BobField::Mass => bob.mass = value,
+                    BobField::Theta => bob.theta = value,
+                    BobField::Omega => bob.omega = value,
+                }
+                pendulum.update_coordinates();
+            }
+            Self::Damping => pendulum.damping = value,
+            Self::Dt => *dt = value,
+        }
+    }
+}
+
+/// `steps` evenly spaced values of `parameter` from `from` to `to`, both
+/// taken; just `from` if `steps` is 1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SweepAxis {
+    pub(crate) parameter: SweepParameter,
+    pub(crate) from: f64,
+    pub(crate) to: f64,
+    pub(crate) steps: u32,
+}
+
+impl SweepAxis {
+    fn value(&self, k: u32) -> f64 {
+        if self.steps == 1 {
+            return self.from;
+        }
+        self.from + (self.to - self.from) * f64::from(k) / f64::from(self.steps - 1)
+    }
+}
+
+/// A summary column of every run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SweepQuantity {
+    /// Sim time of the first flip of any bob, empty if none flipped.
+    FirstFlipTime,
+    /// Total energy at the horizon, in joules.
+    FinalEnergy,
+    /// Fastest the tip went, in m/s.
+    MaxTipSpeed,
+    /// Mean time between the first bob's swings up through straight down,
+    /// in seconds, empty for fewer than two of them. For a bob going round
+    /// and round this is the time a turn takes.
+    PeriodEstimate,
+}
+
+impl SweepQuantity {
+    fn name(self) -> &'static str {
+        match self {
+            Self::FirstFlipTime => "first_flip_time_s",
+            Self::FinalEnergy => "final_energy_j",
+            Self::MaxTipSpeed => "max_tip_speed_m_per_s",
+            Self::PeriodEstimate => "period_s",
+        }
+    }
+}
+
+/// What `run_parameter_sweep` runs: every combination of the values of one
+/// or two axes, each from the live state with that combination applied,
+/// for `horizon` sim seconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SweepSpec {
+    pub(crate) axes: Vec<SweepAxis>,
+    pub(crate) horizon: f64,
+    pub(crate) quantities: Vec<SweepQuantity>,
+    /// Writes each run's trajectory next to the summary, as `run-0001.csv`
+    /// or `run-0001.dptr` by run number, in this format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) recordings: Option<RecordingFormat>,
+    /// Threads the runs share, all the cores but one if left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) threads: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SweepProgress {
+    pub(crate) completed_runs: u64,
+    pub(crate) total_runs: u64,
+    /// Wall seconds the rest should take, at the pace so far.
+    pub(crate) eta_seconds: f64,
+}
+
+/// What `run_parameter_sweep` returns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SweepReport {
+    /// Of the summary, a row per run.
+    pub(crate) path: String,
+    pub(crate) runs: u64,
+    /// Of the recordings, in run order; empty unless asked for.
+    pub(crate) recordings: Vec<String>,
+}
+
+fn run_steps(horizon: f64, dt: f64) -> u64 {
+    ((horizon / dt).round() as u64).max(1)
+}
+
+impl SweepSpec {
+    /// Checks the spec against the chain it starts from, `dt` the one it
+    /// steps with unless an axis varies it. Returns the number of runs.
+    pub(crate) fn validate(&self, pendulum: &Pendulum, dt: f64) -> Result<u64, CommandError> {
+        if !(1..=2).contains(&self.axes.len()) {
+            return Err(CommandError::invalid(
+                "spec.axes",
+                format!("must hold one or two axes, got {}", self.axes.len()),
+            ));
+        }
+        if self.axes.len() == 2 && self.axes[0].parameter == self.axes[1].parameter {
+            return Err(CommandError::invalid(
+                "spec.axes",
+                "must vary two different parameters",
+            ));
+        }
+        if !(self.horizon.is_finite() && self.horizon > 0.0) {
+            return Err(CommandError::invalid(
+                "spec.horizon",
+                format!("must be positive and finite, got {}", self.horizon),
+            ));
+        }
+        if self.quantities.is_empty() && self.recordings.is_none() {
+            return Err(CommandError::invalid(
+                "spec.quantities",
+                "must name at least one quantity, unless the runs are recorded",
+            ));
+        }
+        parallel::validate_threads(self.threads).map_err(|e| e.within("spec"))?;
+        let varies_dt = (self.axes.iter()).any(|axis| axis.parameter == SweepParameter::Dt);
+        if !varies_dt {
+            SweepParameter::Dt.check(dt, pendulum, self.horizon)?;
+        }
+        let mut runs = 1;
+        for (i, axis) in self.axes.iter().enumerate() {
+            if !(1..=MAX_AXIS_STEPS).contains(&axis.steps) {
+                return Err(CommandError::invalid(
+                    format!("spec.axes[{i}].steps"),
+                    format!("must be between 1 and {MAX_AXIS_STEPS}, got {}", axis.steps),
+                ));
+            }
+            // the values lie between the two ends, so checking those does
+            for end in [axis.from, axis.to] {
+                (axis.parameter.check(end, pendulum, self.horizon))
+                    .map_err(|e| e.within(&format!("spec.axes[{i}]")))?;
+            }
+            runs *= u64::from(axis.steps);
+        }
+        if runs > MAX_SWEEP_RUNS {
+            return Err(CommandError::invalid(
+                "spec.axes",
+                format!("make {runs} runs, more than the limit of {MAX_SWEEP_RUNS}"),
+            ));
+        }
+        Ok(runs)
+    }
+
+    /// The values of run `run`, the first axis changing slowest.
+    fn values(&self, run: u64) -> Vec<f64> {
+        let mut rest = run;
+        let mut values = vec![0.0; self.axes.len()];
+        for (value, axis) in values.iter_mut().zip(&self.axes).rev() {
+            *value = axis.value((rest % u64::from(axis.steps)) as u32);
+            rest /= u64::from(axis.steps);
+        }
+        values
+    }
+}
+
+/// The quantities of one run, followed along as it steps.
+struct Tracker {
+    flips: FlipDetector,
+    first_flip: Option<f64>,
+    max_tip_speed: f64,
+    /// The first bob's angle from straight down, wrapped.
+    last_offset: f64,
+    /// Of its swings up through straight down: the first, the last and how
+    /// many.
+    crossings: Option<(f64, f64, u64)>,
+}
+
+impl Tracker {
+    fn new(pendulum: &Pendulum) -> Self {
+        let mut tracker = Self {
+            flips: FlipDetector::default(),
+            first_flip: None,
+            max_tip_speed: 0.0,
+            last_offset: 0.0,
+            crossings: None,
+        };
+        tracker.observe(pendulum, 0.0, 0.0);
+        tracker
+    }
+
+    fn observe(&mut self, pendulum: &Pendulum, sim_time: f64, dt: f64) {
+        let flips = self.flips.observe(pendulum, sim_time, dt);
+        if let Some(flip) = flips.first().filter(|_| self.first_flip.is_none()) {
+            self.first_flip = Some(flip.sim_time);
+        }
+        if let Some(tip) = pendulum.velocities().last() {
+            self.max_tip_speed = self.max_tip_speed.max(tip.x.hypot(tip.y));
+        }
+        let offset = wrap_angle(pendulum.bobs[0].theta - PI);
+        if dt > 0.0 && self.last_offset < 0.0 && offset >= 0.0 && offset - self.last_offset < PI {
+            let at = sim_time - dt * offset / (offset - self.last_offset);
+            self.crossings = Some(match self.crossings {
+                None => (at, at, 1),
+                Some((first, _, count)) => (first, at, count + 1),
+            });
+        }
+        self.last_offset = offset;
+    }
+
+    fn quantity(&self, quantity: SweepQuantity, pendulum: &Pendulum) -> Option<f64> {
+        match quantity {
+            SweepQuantity::FirstFlipTime => self.first_flip,
+            SweepQuantity::FinalEnergy => Some(pendulum.total_energy()),
+            SweepQuantity::MaxTipSpeed => Some(self.max_tip_speed),
+            SweepQuantity::PeriodEstimate => (self.crossings)
+                .filter(|(_, _, count)| *count >= 2)
+                .map(|(first, last, count)| (last - first) / (count - 1) as f64),
+        }
+    }
+}
+
+/// A copy of the chain stepped to the horizon.
+struct SweepRun<'a> {
+    pendulum: Pendulum,
+    tracker: Tracker,
+    dt: f64,
+    step: u64,
+    steps: u64,
+    cancel: &'a AtomicBool,
+    cancelled: bool,
+}
+
+impl SweepRun<'_> {
+    /// Takes the next step, or returns false at the horizon or once
+    /// cancelled.
+    fn advance(&mut self) -> bool {
+        if self.step == self.steps {
+            return false;
+        }
+        if self.step.is_multiple_of(CANCEL_INTERVAL) && self.cancel.load(Ordering::Relaxed) {
+            self.cancelled = true;
+            return false;
+        }
+        self.pendulum.step(self.dt);
+        self.step += 1;
+        let sim_time = self.step as f64 * self.dt;
+        self.tracker.observe(&self.pendulum, sim_time, self.dt);
+        true
+    }
+}
+
+/// Run `run` of `spec` from `pendulum`, recorded to `recording` if given.
+/// Its quantities, or `None` if cancelled.
+fn run(
+    spec: &SweepSpec,
+    run: u64,
+    pendulum: &Pendulum,
+    dt: f64,
+    recording: Option<&Path>,
+    cancel: &AtomicBool,
+) -> Result<Option<Vec<Option<f64>>>, CommandError> {
+    let (mut pendulum, mut dt) = (pendulum.clone(), dt);
+    for (axis, value) in spec.axes.iter().zip(spec.values(run)) {
+        axis.parameter.apply(value, &mut pendulum, &mut dt);
+    }
+    let mut sweep_run = SweepRun {
+        tracker: Tracker::new(&pendulum),
+        steps: run_steps(spec.horizon, dt),
+        pendulum,
+        dt,
+        step: 0,
+        cancel,
+        cancelled: false,
+    };
+    match (recording, spec.recordings) {
+        (Some(path), Some(format)) => {
+            let bobs = sweep_run.pendulum.n();
+            let configuration = RecordingConfiguration::of(&sweep_run.pendulum);
+            let mut first = Some(sweep_run.pendulum.clone());
+            let frames = std::iter::from_fn(|| match first.take() {
+                Some(start) => Some((start, 0.0)),
+                None => (sweep_run.advance())
+                    .then(|| (sweep_run.pendulum.clone(), sweep_run.step as f64 * dt)),
+            });
+            write_trajectory(path, format, bobs, dt, Some(&configuration), frames)?;
+        }
+        _ => while sweep_run.advance() {},
+    }
+    if sweep_run.cancelled {
+        return Ok(None);
+    }
+    let quantities = (spec.quantities.iter())
+        .map(|&quantity| sweep_run.tracker.quantity(quantity, &sweep_run.pendulum))
+        .collect();
+    Ok(Some(quantities))
+}
+
+/// A sweep's summary: a row per run with its number, values and
+/// quantities, empty where a quantity has no value.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SweepTable {
+    pub(crate) columns: Vec<String>,
+    pub(crate) rows: Vec<Vec<Option<f64>>>,
+}
+
+impl SweepTable {
+    fn new(spec: &SweepSpec, quantities: Vec<Vec<Option<f64>>>) -> Self {
+        let mut columns = vec![String::from("run")];
+        columns.extend(spec.axes.iter().map(|axis| axis.parameter.name()));
+        columns.extend(spec.quantities.iter().map(|q| q.name().to_string()));
+        let rows = (quantities.into_iter().enumerate())
+            .map(|(run, quantities)| {
+                let mut row = vec![Some((run + 1) as f64)];
+                row.extend(spec.values(run as u64).into_iter().map(Some));
+                row.extend(quantities);
+                row
+            })
+            .collect();
+        Self { columns, rows }
+    }
+
+    pub(crate) fn csv(&self) -> String {
+        let mut lines = vec![self.columns.join(",")];
+        for row in &self.rows {
+            let values: Vec<String> = (row.iter())
+                .map(|value| value.map_or_else(String::new, |value| value.to_string()))
+                .collect();
+            lines.push(values.join(","));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// A JSON object per row, keyed by column, a quantity without a value
+    /// as null.
+    pub(crate) fn ndjson(&self) -> String {
+        let mut text = String::new();
+        for row in &self.rows {
+            let mut object = serde_json::Map::new();
+            for (column, &value) in self.columns.iter().zip(row) {
+                // the run number is a count, not a measurement
+                let value = match column.as_str() {
+                    "run" => value.map(|run| run as u64).into(),
+                    _ => value.into(),
+                };
+                object.insert(column.clone(), value);
+            }
+            text += &serde_json::Value::Object(object).to_string();
+            text += "\n";
+        }
+        text
+    }
+}
+
+/// Runs every combination of `spec` from `pendulum`, stepped with `dt`
+/// where no axis varies it, in parallel on `spec.threads`, by default all
+/// but one of the cores so the live run keeps one to itself; see
+/// `parallel::run_batch`. Any recordings are written to
+/// `recordings_dir`. Returns the summary and the recordings, or `None`,
+/// with the recordings removed, if `cancel` is raised first. `progress` is
+/// called from the worker threads as runs finish.
+pub(crate) fn sweep(
+    spec: &SweepSpec,
+    pendulum: &Pendulum,
+    dt: f64,
+    recordings_dir: Option<&Path>,
+    cancel: &AtomicBool,
+    progress: impl Fn(SweepProgress) + Sync,
+) -> Result<Option<(SweepTable, Vec<PathBuf>)>, CommandError> {
+    let total_runs = spec.validate(pendulum, dt)?;
+    let extension = match spec.recordings {
+        Some(RecordingFormat::Csv) => "csv",
+        _ => "dptr",
+    };
+    let recordings: Vec<PathBuf> = match (spec.recordings, recordings_dir) {
+        (Some(_), Some(dir)) => (1..=total_runs)
+            .map(|run| dir.join(format!("run-{run:04}.{extension}")))
+            .collect(),
+        (Some(_), None) => {
+            return Err(CommandError::invalid(
+                "spec.recordings",
+                "need a directory to be written to",
+            ))
+        }
+        (None, _) => Vec::new(),
+    };
+    if let Some(dir) = recordings_dir {
+        std::fs::create_dir_all(dir).map_err(|e| CommandError::io(dir, e))?;
+    }
+    let report = |p: parallel::BatchProgress| {
+        progress(SweepProgress {
+            completed_runs: p.completed,
+            total_runs: p.total,
+            eta_seconds: p.eta_seconds,
+        })
+    };
+    let rows = parallel::run_batch(total_runs, spec.threads, cancel, report, |k| {
+        let recording = recordings.get(k as usize).map(PathBuf::as_path);
+        run(spec, k, pendulum, dt, recording, cancel)
+    });
+    let Some(rows) = rows? else {
+        for path in &recordings {
+            let _ = std::fs::remove_file(path);
+        }
+        return Ok(None);
+    };
+    Ok(Some((SweepTable::new(spec, rows), recordings)))
+}
+
+/// `sweep` with the summary and any recordings written to `output_dir`.
+pub(crate) fn run_sweep(
+    spec: &SweepSpec,
+    pendulum: &Pendulum,
+    dt: f64,
+    output_dir: &Path,
+    cancel: &AtomicBool,
+    progress: impl Fn(SweepProgress) + Sync,
+) -> Result<Option<SweepReport>, CommandError> {
+    let swept = sweep(spec, pendulum, dt, Some(output_dir), cancel, progress)?;
+    let Some((table, recordings)) = swept else {
+        return Ok(None);
+    };
+    let path = output_dir.join(SWEEP_SUMMARY_FILE);
+    let csv = table.csv();
+    savefile::write_atomically(&path, csv.as_bytes()).map_err(|e| CommandError::io(&path, e))?;
+    Ok(Some(SweepReport {
+        path: path.display().to_string(),
+        runs: table.rows.len() as u64,
+        recordings: recordings.iter().map(|p| p.display().to_string()).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trajectory::read_recording;
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn a_grid_of_runs_is_summarized_and_recorded() {
+        let mut pendulum = Pendulum::default();
+        for bob in &mut pendulum.bobs {
+            (bob.theta, bob.omega) = (PI + 0.3, 0.0);
+        }
+        pendulum.update_coordinates();
+        let spec = SweepSpec {
+            axes: vec![
+                SweepAxis {
+                    parameter: SweepParameter::Bob {
+                        index: 1,
+                        field: BobField::Mass,
+                    },
+                    from: 1.0,
+                    to: 3.0,
+                    steps: 3,
+                },
+                SweepAxis {
+                    parameter: SweepParameter::Damping,
+                    from: 0.0,
+                    to: 0.5,
+                    steps: 2,
+                },
+            ],
+            horizon: 8.0,
+            quantities: vec![
+                SweepQuantity::FirstFlipTime,
+                SweepQuantity::FinalEnergy,
+                SweepQuantity::MaxTipSpeed,
+                SweepQuantity::PeriodEstimate,
+            ],
+            recordings: Some(RecordingFormat::Binary { compressed: true }),
+            threads: Some(2),
+        };
+        assert_eq!(spec.values(3), [2.0, 0.5]);
+        let dir = std::env::temp_dir().join(format!("sweep-{}", std::process::id()));
+        let cancel = AtomicBool::new(false);
+        let reports = AtomicU64::new(0);
+        let count = |_| {
+            reports.fetch_add(1, Ordering::Relaxed);
+        };
+        let report = run_sweep(&spec, &pendulum, 2e-3, &dir, &cancel, count);
+        let report = report.unwrap().unwrap();
+        assert_eq!((report.runs, reports.into_inner()), (6, 6));
+
+        let text = std::fs::read_to_string(&report.path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "run,bobs[1].mass,damping,first_flip_time_s,final_energy_j,max_tip_speed_m_per_s,period_s"
+        );
+        assert_eq!(lines.len(), 7);
+        // a run on its own gives the same row, and the damped one with the
+        // same mass ends with less energy
+        let mut alone = pendulum.clone();
+        alone.bobs[1].mass = 2.0;
+        alone.damping = 0.5;
+        let mut tracker = Tracker::new(&alone);
+        for step in 1..=4000 {
+            alone.step(2e-3);
+            tracker.observe(&alone, f64::from(step) * 2e-3, 2e-3);
+        }
+        let row: Vec<&str> = lines[4].split(',').collect();
+        assert_eq!(row[..3], ["4", "2", "0.5"]);
+        assert_eq!(row[4].parse::<f64>().unwrap(), alone.total_energy());
+        assert_eq!(row[5].parse::<f64>().unwrap(), tracker.max_tip_speed);
+        let undamped: f64 = lines[3].split(',').nth(4).unwrap().parse().unwrap();
+        assert!(alone.total_energy() < undamped);
+        // the gentle swing doesn't flip, but swings through the bottom
+        // about every period
+        assert_eq!(row[3], "");
+        let period: f64 = row[6].parse().unwrap();
+        assert!(period > 1.0 && period < 4.0, "{period}");
+
+        let recording = read_recording(&std::fs::read(&report.recordings[3]).unwrap()).unwrap();
+        assert_eq!(recording.len(), 4001);
+        assert_eq!(recording.configuration().unwrap().masses[1], 2.0);
+
+        // cancelled, nothing is left behind
+        cancel.store(true, Ordering::Relaxed);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            run_sweep(&spec, &pendulum, 2e-3, &dir, &cancel, |_| {}).unwrap(),
+            None
+        );
+        assert!(!dir.join("run-0001.dptr").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut bad = spec.clone();
+        bad.axes[0].to = -1.0;
+        assert!(bad.validate(&pendulum, 2e-3).is_err());
+        bad.axes[0] = bad.axes[1];
+        assert!(bad.validate(&pendulum, 2e-3).is_err());
+        bad.axes.truncate(1);
+        bad.axes[0].parameter = SweepParameter::Bob {
+            index: 9,
+            field: BobField::Theta,
+        };
+        assert!(bad.validate(&pendulum, 2e-3).is_err());
+    }
+}
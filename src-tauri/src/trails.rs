@@ -0,0 +1,530 @@
+use pendulum_core::{Coordinate, Pendulum};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::error::CommandError;
+use crate::state::StructuralOperation;
+
+/// Sim seconds between the points kept of each trail, to begin with.
+pub(crate) const DEFAULT_TRAIL_INTERVAL: f64 = 0.01;
+/// Points kept of each trail, to begin with.
+pub(crate) const DEFAULT_TRAIL_POINTS: usize = 1_000;
+/// Most points a trail may be configured to keep.
+pub(crate) const MAX_TRAIL_POINTS: usize = 100_000;
+/// Most points kept of all the trails together; a long chain has shorter
+/// trails than `points` rather than the buffer growing with the bob cap.
+pub(crate) const MAX_TRAIL_POINTS_TOTAL: usize = 1_000_000;
+
+/// How often trail points are kept and how many of them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrailConfig {
+    pub(crate) interval: f64,
+    /// Points per bob.
+    pub(crate) points: usize,
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_TRAIL_INTERVAL,
+            points: DEFAULT_TRAIL_POINTS,
+        }
+    }
+}
+
+impl TrailConfig {
+    pub(crate) fn validate(&self) -> Result<(), CommandError> {
+        if !(self.interval.is_finite() && self.interval > 0.0) {
+            return Err(CommandError::invalid(
+                "interval",
+                format!("must be positive and finite, got {}", self.interval),
+            ));
+        }
+        if !(2..=MAX_TRAIL_POINTS).contains(&self.points) {
+            return Err(CommandError::invalid(
+                "points",
+                format!(
+                    "must be between 2 and {MAX_TRAIL_POINTS}, got {}",
+                    self.points
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Where a bob was at one sim time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TrailPoint {
+    pub(crate) sim_time: f64,
+    pub(crate) position: Coordinate,
+    /// How fast the bob was going, in m/s.
+    pub(crate) speed: f64,
+}
+
+/// A bob's trail as `get_trails` returns it, oldest point first.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Trail {
+    pub(crate) bob_id: u64,
+    pub(crate) sim_times: Vec<f64>,
+    pub(crate) positions: Vec<Coordinate>,
+    /// How fast the bob was going at each point, in m/s.
+    pub(crate) speeds: Vec<f64>,
+    /// The slowest and fastest of `speeds`, both 0 for an empty trail, so
+    /// a color ramp can be fitted without going over them again.
+    pub(crate) min_speed: f64,
+    pub(crate) max_speed: f64,
+}
+
+/// The slowest and fastest of `speeds`, or zeros if there are none.
+fn speed_range(speeds: &[f64]) -> (f64, f64) {
+    if speeds.is_empty() {
+        return (0.0, 0.0);
+    }
+    (speeds.iter()).fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &s| {
+        (min.min(s), max.max(s))
+    })
+}
+
+/// The recent positions of every bob, one every `interval` of sim time, so
+/// a window opened late draws the same trails as one open all along. A
+/// bob's trail only covers the motion it has in the current layout: one
+/// that moves with a structural change starts over.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TrailBuffer {
+    config: TrailConfig,
+    /// In chain order, with each bob's id.
+    trails: Vec<(u64, VecDeque<TrailPoint>)>,
+    last_sample: Option<f64>,
+}
+
+impl TrailBuffer {
+    /// Takes a new configuration, keeping the newest points that fit.
+    pub(crate) fn configure(&mut self, config: TrailConfig) {
+        self.config = config;
+        self.trim();
+    }
+
+    /// Points kept per bob: `points`, or less for a chain too long for
+    /// `MAX_TRAIL_POINTS_TOTAL`.
+    fn capacity(&self) -> usize {
+        let bobs = self.trails.len().max(1);
+        self.config.points.min(MAX_TRAIL_POINTS_TOTAL / bobs)
+    }
+
+    fn trim(&mut self) {
+        let capacity = self.capacity();
+        for (_, trail) in &mut self.trails {
+            while trail.len() > capacity {
+                trail.pop_front();
+            }
+        }
+    }
+
+    /// Adds every bob's position at `sim_time` if an interval has passed
+    /// since the last points were added.
+    pub(crate) fn observe(&mut self, pendulum: &Pendulum, sim_time: f64) {
+        let due = (self.last_sample)
+            .is_none_or(|last| sim_time - last >= self.config.interval - 1e-9 || sim_time < last);
+        if !due {
+            return;
+        }
+        let in_step = self.trails.len() == pendulum.n()
+            && (self.trails.iter().zip(&pendulum.bobs)).all(|((id, _), bob)| *id == bob.id);
+        if !in_step {
+            self.follow(pendulum, pendulum.n());
+        }
+        self.last_sample = Some(sim_time);
+        let velocities = pendulum.velocities();
+        for (((_, trail), bob), velocity) in
+            (self.trails.iter_mut().zip(&pendulum.bobs)).zip(velocities)
+        {
+            trail.push_back(TrailPoint {
+                sim_time,
+                position: bob.coordinate,
+                speed: velocity.x.hypot(velocity.y),
+            });
+        }
+        self.trim();
+    }
+
+    /// Matches the trails to the chain after a structural change that moved
+    /// the bobs from `index` on, whose trails start over; the bobs before
+    /// it keep theirs.
+    fn follow(&mut self, pendulum: &Pendulum, index: usize) {
+        let mut old = std::mem::take(&mut self.trails);
+        self.trails = (pendulum.bobs.iter().enumerate())
+            .map(|(i, bob)| {
+                let kept = (i < index)
+                    .then(|| old.iter().position(|(id, _)| *id == bob.id))
+                    .flatten()
+                    .map(|at| old.swap_remove(at).1);
+                (bob.id, kept.unwrap_or_default())
+            })
+            .collect();
+        self.trim();
+    }
+
+    /// Clears the trails `operation` at `index` made stale: all of them when
+    /// the chain started over, those from `index` on when a bob came or
+    /// went or the chain was resized there.
+    pub(crate) fn structure_changed(
+        &mut self,
+        operation: StructuralOperation,
+        index: usize,
+        pendulum: &Pendulum,
+    ) {
+        let index = if operation.starts_over() { 0 } else { index };
+        self.follow(pendulum, index);
+        if index == 0 {
+            self.last_sample = None;
+        }
+    }
+
+    /// A copy of the trail of the bob with `id`, to be worked on without
+    /// holding the state; empty if it has none yet.
+    pub(crate) fn trail_of(&self, id: u64) -> Vec<TrailPoint> {
+        (self.trails.iter().find(|(bob, _)| *bob == id))
+            .map_or_else(Vec::new, |(_, trail)| trail.iter().copied().collect())
+    }
+
+    /// Calls `f` with every point kept from `since` on.
+    pub(crate) fn for_each_since(&self, since: f64, mut f: impl FnMut(Coordinate)) {
+        for (_, trail) in &self.trails {
+            // the points are in time order, so the old ones are skipped in bulk
+            let first = trail.partition_point(|p| p.sim_time < since);
+            trail.range(first..).for_each(|p| f(p.position));
+        }
+    }
+
+    /// Every bob's trail, in chain order, with only its newest `max_points`
+    /// points if given.
+    pub(crate) fn trails(&self, max_points: Option<usize>) -> Vec<Trail> {
+        (self.trails.iter())
+            .map(|(id, trail)| {
+                let skip = max_points.map_or(0, |max| trail.len().saturating_sub(max));
+                let speeds: Vec<f64> = trail.iter().skip(skip).map(|p| p.speed).collect();
+                let (min_speed, max_speed) = speed_range(&speeds);
+                Trail {
+                    bob_id: *id,
+                    sim_times: trail.iter().skip(skip).map(|p| p.sim_time).collect(),
+                    positions: trail.iter().skip(skip).map(|p| p.position).collect(),
+                    speeds,
+                    min_speed,
+                    max_speed,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A bob's trail cut down by `simplify`, as `get_simplified_trail` returns
+/// it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SimplifiedTrail {
+    pub(crate) bob_id: u64,
+    pub(crate) sim_times: Vec<f64>,
+    pub(crate) positions: Vec<Coordinate>,
+    /// The speed at each point kept, or the fastest of the points left out
+    /// nearer to it than to the points either side, so a burst of speed
+    /// still shows in the colors after its points are gone.
+    pub(crate) speeds: Vec<f64>,
+    pub(crate) min_speed: f64,
+    pub(crate) max_speed: f64,
+    /// Points in the trail before it was simplified.
+    pub(crate) original_points: usize,
+    /// Farthest any point left out is from the simplified polyline, in
+    /// meters: within the tolerance asked for unless the point cap was
+    /// reached first.
+    pub(crate) max_error: f64,
+}
+
+/// A stretch of the polyline between two kept points, with the point
+/// between them farthest from the segment joining them.
+struct Split {
+    from: usize,
+    to: usize,
+    farthest: usize,
+    error: f64,
+}
+
+impl PartialEq for Split {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Split {}
+
+impl PartialOrd for Split {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Split {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.error.total_cmp(&other.error)
+    }
+}
+
+/// Distance from `p` to the segment from `a` to `b`, or to `a` if the two
+/// are the same point.
+fn segment_distance(p: Coordinate, a: Coordinate, b: Coordinate) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (p.x - (a.x + t * dx)).hypot(p.y - (a.y + t * dy))
+}
+
+fn split(points: &[Coordinate], from: usize, to: usize) -> Option<Split> {
+    (from + 1..to)
+        .map(|i| (i, segment_distance(points[i], points[from], points[to])))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(farthest, error)| Split {
+            from,
+            to,
+            farthest,
+            error,
+        })
+}
+
+/// Douglas–Peucker: the indices of the points of `points` to keep, in
+/// order, and the farthest any other point is from the polyline through
+/// them. The stretch that is off by the most is split first, so with the
+/// cap reached before the tolerance the points kept are still the ones
+/// that matter most. The ends are always kept.
+pub(crate) fn simplify(
+    points: &[Coordinate],
+    tolerance: f64,
+    max_points: usize,
+) -> (Vec<usize>, f64) {
+    if points.len() <= 2 {
+        return ((0..points.len()).collect(), 0.0);
+    }
+    let last = points.len() - 1;
+    let mut kept = vec![0, last];
+    let mut splits: BinaryHeap<Split> = split(points, 0, last).into_iter().collect();
+    while let Some(worst) = splits.peek() {
+        if worst.error <= tolerance || kept.len() >= max_points {
+            break;
+        }
+        let worst = splits.pop().expect("peeked");
+        kept.push(worst.farthest);
+        splits.extend(split(points, worst.from, worst.farthest));
+        splits.extend(split(points, worst.farthest, worst.to));
+    }
+    kept.sort_unstable();
+    (kept, splits.peek().map_or(0.0, |worst| worst.error))
+}
+
+/// Speeds for the points `kept` of a trail: each its own, or the fastest
+/// of the points left out on its side of the middle of the stretches
+/// either side of it.
+fn carry_speeds(speeds: &[f64], kept: &[usize]) -> Vec<f64> {
+    let mut carried: Vec<f64> = kept.iter().map(|&i| speeds[i]).collect();
+    for (j, w) in kept.windows(2).enumerate() {
+        let middle = (w[0] + w[1]).div_ceil(2);
+        for (i, &speed) in speeds.iter().enumerate().take(w[1]).skip(w[0] + 1) {
+            let nearest = if i < middle { j } else { j + 1 };
+            carried[nearest] = carried[nearest].max(speed);
+        }
+    }
+    carried
+}
+
+/// Bob `bob_id`'s trail `points` through `simplify`.
+pub(crate) fn simplified(
+    bob_id: u64,
+    points: &[TrailPoint],
+    tolerance: f64,
+    max_points: usize,
+) -> SimplifiedTrail {
+    let positions: Vec<Coordinate> = points.iter().map(|p| p.position).collect();
+    let (kept, max_error) = simplify(&positions, tolerance, max_points);
+    let speeds: Vec<f64> = points.iter().map(|p| p.speed).collect();
+    let speeds = carry_speeds(&speeds, &kept);
+    let (min_speed, max_speed) = speed_range(&speeds);
+    SimplifiedTrail {
+        bob_id,
+        sim_times: kept.iter().map(|&i| points[i].sim_time).collect(),
+        positions: kept.iter().map(|&i| positions[i]).collect(),
+        speeds,
+        min_speed,
+        max_speed,
+        original_points: points.len(),
+        max_error,
+    }
+}
+
+/// Checks the `get_simplified_trail` arguments.
+pub(crate) fn validate_simplification(
+    tolerance: f64,
+    max_points: usize,
+) -> Result<(), CommandError> {
+    if !(tolerance.is_finite() && tolerance >= 0.0) {
+        return Err(CommandError::invalid(
+            "tolerance",
+            format!("must be finite and not negative, got {tolerance}"),
+        ));
+    }
+    if max_points < 2 {
+        return Err(CommandError::invalid(
+            "max_points",
+            format!("must be at least 2, got {max_points}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks a `get_trails` argument.
+pub(crate) fn validate_max_points(max_points: Option<usize>) -> Result<(), CommandError> {
+    match max_points {
+        Some(0) => Err(CommandError::invalid("max_points", "must be at least 1")),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppDataInner;
+
+    fn run(data: &mut AppDataInner, steps: usize) {
+        for _ in 0..steps {
+            let dt = data.dt;
+            data.substep(dt, &mut Vec::new());
+        }
+    }
+
+    #[test]
+    fn trails_keep_the_recent_motion_and_restart_where_the_chain_changed() {
+        let mut data = AppDataInner::default();
+        data.trails.configure(TrailConfig {
+            interval: 0.01,
+            points: 50,
+        });
+        run(&mut data, 1_000);
+        let trails = data.trails.trails(None);
+        assert_eq!(trails.len(), data.pendulum.n());
+        let first = &trails[0];
+        assert_eq!(first.bob_id, data.pendulum.bobs[0].id);
+        assert_eq!((first.positions.len(), first.sim_times.len()), (50, 50));
+        assert!(first.sim_times.windows(2).all(|w| w[1] - w[0] > 0.0099));
+        assert!(data.sim_time - first.sim_times[49] < 0.01 + 1e-9);
+        let length = data.pendulum.bobs[0].length_rod;
+        for p in &first.positions {
+            let r = (p.x - data.pendulum.pivot.x).hypot(p.y - data.pendulum.pivot.y);
+            assert!((r - length).abs() < 1e-6, "{r}");
+        }
+        for trail in &trails {
+            assert!(trail.speeds.iter().all(|&s| s >= 0.0 && s.is_finite()));
+            let fastest = trail.speeds.iter().copied().fold(0.0, f64::max);
+            let slowest = trail.speeds.iter().copied().fold(f64::INFINITY, f64::min);
+            assert_eq!((trail.min_speed, trail.max_speed), (slowest, fastest));
+        }
+        assert_eq!(data.trails.trails(Some(10))[0].positions.len(), 10);
+        assert_eq!(
+            data.trails.trails(Some(10))[0].positions[..],
+            first.positions[40..]
+        );
+
+        // removing the last bob leaves the others' trails alone
+        let last = data.pendulum.n() - 1;
+        data.remove_bob(last).unwrap();
+        let trails = data.trails.trails(None);
+        assert_eq!(trails.len(), last);
+        assert!(trails.iter().all(|t| t.positions.len() == 50));
+
+        // removing the first moves every other bob, so theirs start over
+        data.remove_bob(0).unwrap();
+        assert!(data
+            .trails
+            .trails(None)
+            .iter()
+            .all(|t| t.positions.is_empty()));
+        run(&mut data, 50);
+        assert!(data
+            .trails
+            .trails(None)
+            .iter()
+            .all(|t| t.positions.len() == 10));
+        data.reset();
+        assert!(data
+            .trails
+            .trails(None)
+            .iter()
+            .all(|t| t.positions.is_empty()));
+
+        let bad = TrailConfig {
+            interval: 0.0,
+            points: 10,
+        };
+        assert!(bad.validate().is_err());
+        assert!(validate_max_points(Some(0)).is_err());
+    }
+
+    /// How far each point of `points` is from the polyline through the kept
+    /// ones, worked out the slow way.
+    fn worst_error(points: &[Coordinate], kept: &[usize]) -> f64 {
+        (kept.windows(2))
+            .flat_map(|w| {
+                (w[0]..=w[1]).map(move |i| segment_distance(points[i], points[w[0]], points[w[1]]))
+            })
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn simplified_trails_stay_within_the_tolerance_and_the_cap() {
+        // a circle needs its sagitta r·(1 - cos(θ/2)) within the tolerance
+        let circle: Vec<Coordinate> = (0..=1_000)
+            .map(|i| {
+                let angle = i as f64 / 1_000.0 * std::f64::consts::TAU;
+                Coordinate::new(angle.cos(), angle.sin())
+            })
+            .collect();
+        let (kept, error) = simplify(&circle, 0.01, 1_000);
+        assert!(error <= 0.01 && worst_error(&circle, &kept) <= error + 1e-12);
+        assert!((20..60).contains(&kept.len()), "{}", kept.len());
+        assert_eq!((kept[0], *kept.last().unwrap()), (0, 1_000));
+
+        // every corner of a zigzag matters, so only the cap cuts it down
+        let zigzag: Vec<Coordinate> = (0..200)
+            .map(|i| Coordinate::new(i as f64, if i % 2 == 0 { 0.0 } else { 1.0 }))
+            .collect();
+        let (kept, error) = simplify(&zigzag, 0.5, 1_000);
+        assert_eq!((kept.len(), error), (200, 0.0));
+        let (kept, error) = simplify(&zigzag, 0.5, 10);
+        assert_eq!(kept.len(), 10);
+        assert!(error > 0.5 && worst_error(&zigzag, &kept) <= error + 1e-12);
+
+        // and none of these get stuck or panic
+        let same = vec![Coordinate::new(0.3, -0.2); 100];
+        assert_eq!(simplify(&same, 0.0, 10), (vec![0, 99], 0.0));
+        assert_eq!(simplify(&same[..1], 0.0, 10), (vec![0], 0.0));
+        assert_eq!(simplify(&[], 0.1, 10), (vec![], 0.0));
+        assert!(validate_simplification(f64::NAN, 10).is_err());
+
+        // a burst of speed on a straight stretch outlives its point
+        let points: Vec<TrailPoint> = (0..=100)
+            .map(|i| TrailPoint {
+                sim_time: f64::from(i),
+                position: Coordinate::new(f64::from(i), 0.0),
+                speed: if i == 40 { 9.0 } else { 1.0 },
+            })
+            .collect();
+        let trail = simplified(7, &points, 0.01, 100);
+        assert_eq!(trail.sim_times, [0.0, 100.0]);
+        assert_eq!(trail.speeds, [9.0, 1.0]);
+        assert_eq!((trail.min_speed, trail.max_speed), (1.0, 9.0));
+        assert!(validate_simplification(0.1, 1).is_err());
+    }
+}
@@ -0,0 +1,612 @@
+use pendulum_core::{Coordinate, Pendulum, DEFAULT_MASS};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+
+use crate::binary::{self, BinaryHeader};
+use crate::error::CommandError;
+use crate::events::RecordingFailedEvent;
+use crate::recording::{
+    RecordedBob, Recording, RecordingConfiguration, Sample, MAX_RECORDING_SAMPLES,
+};
+
+/// Rows handed to the writer at a time.
+const ROWS_PER_CHUNK: usize = 256;
+/// Chunks that may wait for the writer. Past that a chunk is dropped and
+/// counted rather than the physics waiting on the disk, which also bounds
+/// the memory a recording takes however long it runs.
+const CHUNK_QUEUE: usize = 64;
+/// Largest `decimation` a trajectory recording takes.
+pub(crate) const MAX_DECIMATION: u32 = 1_000_000;
+
+/// What a trajectory file is written as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "kind"
+)]
+pub(crate) enum RecordingFormat {
+    /// A row of text per substep; see `start_recording`.
+    #[default]
+    Csv,
+    /// Fixed-size little-endian frames after a short header, in LZ4 blocks
+    /// if `compressed`; see `binary::read`. A fraction of the size of CSV
+    /// and much quicker to write, for long runs.
+    Binary {
+        #[serde(default)]
+        compressed: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct RecordingOptions {
+    /// Write every n-th substep, 1 for every one.
+    pub(crate) decimation: u32,
+    pub(crate) format: RecordingFormat,
+}
+
+impl Default for RecordingOptions {
+    fn default() -> Self {
+        Self {
+            decimation: 1,
+            format: RecordingFormat::Csv,
+        }
+    }
+}
+
+/// What writing a file returns once it is complete, as `stop_recording`
+/// does.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileReport {
+    pub(crate) path: String,
+    /// Rows written, the header not counted.
+    pub(crate) rows: u64,
+    /// Size of the file, in bytes.
+    pub(crate) bytes: u64,
+    /// Rows left out while the disk couldn't keep up.
+    pub(crate) dropped_rows: u64,
+}
+
+/// Columns of the pivot, which end every row.
+const PIVOT_COLUMNS: &str = ",pivot_x_m,pivot_y_m";
+
+/// The header row: sim time, then each bob's angle, angular velocity and
+/// position, numbered from 1, with their units, then the pivot.
+fn header(bobs: usize) -> String {
+    let mut header = String::from("sim_time_s");
+    for i in 1..=bobs {
+        header += &format!(",theta{i}_rad,omega{i}_rad_per_s,x{i}_m,y{i}_m");
+    }
+    header + PIVOT_COLUMNS
+}
+
+/// Starts the line before the header that holds the recording's
+/// configuration, as JSON.
+const CONFIGURATION_PREFIX: &str = "# configuration ";
+
+/// The configuration line, if there is a configuration, then the header.
+fn head(bobs: usize, configuration: Option<&RecordingConfiguration>) -> String {
+    match configuration {
+        Some(configuration) => {
+            let json = serde_json::to_string(configuration).expect("configurations serialize");
+            format!("{CONFIGURATION_PREFIX}{json}\n{}", header(bobs))
+        }
+        None => header(bobs),
+    }
+}
+
+fn columns(bobs: usize) -> usize {
+    3 + 4 * bobs
+}
+
+/// Reads back a file `TrajectoryRecording` wrote, for `load_recording`. The rod
+/// lengths are worked out from the positions and the masses come from the
+/// configuration line, as they were when recording started. Files from
+/// before that line give every bob `DEFAULT_MASS`, and files from before
+/// the pivot columns hang from the origin.
+/// Rows past `MAX_RECORDING_SAMPLES` are left out, as a recording would.
+pub(crate) fn read_csv(text: &str) -> Result<Recording, CommandError> {
+    let invalid = |reason: String| CommandError::invalid("file", reason);
+    let mut lines = text.lines().enumerate().peekable();
+    let configuration: Option<RecordingConfiguration> = lines
+        .next_if(|(_, line)| line.starts_with(CONFIGURATION_PREFIX))
+        .map(|(_, line)| serde_json::from_str(line[CONFIGURATION_PREFIX.len()..].trim_end()))
+        .transpose()
+        .map_err(|e| invalid(format!("line 1: configuration: {e}")))?;
+    let first = lines.next().map_or("", |(_, line)| line.trim_end());
+    let has_pivot = first.ends_with(PIVOT_COLUMNS);
+    let bobs = first
+        .split(',')
+        .count()
+        .saturating_sub(1 + 2 * usize::from(has_pivot))
+        / 4;
+    let expected = header(bobs);
+    if first != expected && first != expected.trim_end_matches(PIVOT_COLUMNS) {
+        return Err(invalid("doesn't start with a trajectory header".into()));
+    }
+    let mut start = None;
+    let mut samples: Vec<Sample> = Vec::new();
+    for (number, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+        if samples.len() == MAX_RECORDING_SAMPLES {
+            break;
+        }
+        let row = (line.trim_end().split(','))
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|e| invalid(format!("line {}: {e}", number + 1)))?;
+        if row.len() != columns(bobs) - 2 * usize::from(!has_pivot) {
+            return Err(invalid(format!(
+                "line {} has {} values where the header has {}",
+                number + 1,
+                row.len(),
+                first.split(',').count()
+            )));
+        }
+        if !row.iter().all(|v| v.is_finite()) {
+            return Err(invalid(format!(
+                "line {}: values must be finite",
+                number + 1
+            )));
+        }
+        let start = *start.get_or_insert(row[0]);
+        let time = row[0] - start;
+        if samples.last().is_some_and(|last| time < last.time) {
+            return Err(invalid(format!(
+                "line {}: sim time runs backwards",
+                number + 1
+            )));
+        }
+        let pivot = if has_pivot {
+            Coordinate::new(row[row.len() - 2], row[row.len() - 1])
+        } else {
+            Coordinate::default()
+        };
+        let mut above = pivot;
+        let masses = (configuration.as_ref())
+            .map(|c| &c.masses[..])
+            .filter(|masses| masses.len() == bobs);
+        let bobs = (row[1..1 + 4 * bobs].chunks(4).enumerate())
+            .map(|(i, bob)| {
+                let position = Coordinate::new(bob[2], bob[3]);
+                let length_rod = (position.x - above.x).hypot(position.y - above.y);
+                above = position;
+                RecordedBob {
+                    theta: bob[0],
+                    omega: bob[1],
+                    length_rod,
+                    mass: masses.map_or(DEFAULT_MASS, |masses| masses[i]),
+                }
+            })
+            .collect();
+        samples.push(Sample { time, pivot, bobs });
+    }
+    let start = start.ok_or_else(|| invalid("has no rows".into()))?;
+    Ok(Recording::from_samples(start, samples, configuration))
+}
+
+/// Reads a trajectory file of either format, telling them apart by the
+/// first bytes.
+pub(crate) fn read_recording(bytes: &[u8]) -> Result<Recording, CommandError> {
+    if binary::is_binary(bytes) {
+        return binary::read(bytes);
+    }
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| CommandError::invalid("file", "is neither CSV nor a binary recording"))?;
+    read_csv(text)
+}
+
+/// Writes the trajectory file at `path` in the other format, next to it
+/// with the extension changed: CSV for `to_csv`, compressed binary
+/// otherwise. Converted from CSV, the masses are `read_csv`'s.
+pub(crate) fn convert(path: &Path, to_csv: bool) -> Result<FileReport, CommandError> {
+    let bytes = std::fs::read(path).map_err(|e| CommandError::io(path, e))?;
+    if binary::is_binary(&bytes) != to_csv {
+        let format = if to_csv { "CSV" } else { "binary" };
+        return Err(CommandError::invalid(
+            "to_csv",
+            format!("the file is {format} already"),
+        ));
+    }
+    let recording = read_recording(&bytes)?;
+    let samples = recording.samples();
+    let target = path.with_extension(if to_csv { "csv" } else { "dptr" });
+    let format = if to_csv {
+        RecordingFormat::Csv
+    } else {
+        RecordingFormat::Binary { compressed: true }
+    };
+    let dt = samples.get(1).map_or(0.0, |s| s.time);
+    let frames =
+        (samples.iter()).map(|sample| (sample.pendulum(), recording.start() + sample.time));
+    let bobs = samples[0].bobs.len();
+    write_trajectory(&target, format, bobs, dt, recording.configuration(), frames)
+}
+
+/// Writes a new trajectory file at `path`, in `format`, of `frames`, each a
+/// chain of `bobs` bobs and its sim time, for files made in one go rather
+/// than substep by substep. `dt` is what a binary header records.
+pub(crate) fn write_trajectory(
+    path: &Path,
+    format: RecordingFormat,
+    bobs: usize,
+    dt: f64,
+    configuration: Option<&RecordingConfiguration>,
+    frames: impl Iterator<Item = (Pendulum, f64)>,
+) -> Result<FileReport, CommandError> {
+    let file = File::create(path).map_err(|e| CommandError::io(path, e))?;
+    let rows = match format {
+        RecordingFormat::Csv => write_csv(file, bobs, configuration, frames),
+        RecordingFormat::Binary { compressed } => {
+            let header = BinaryHeader {
+                bobs,
+                dt,
+                compressed,
+                configuration: configuration.cloned(),
+            };
+            binary::write_frames(file, &header, chunked(frames, bobs, binary::row))
+        }
+    }
+    .map_err(|e| CommandError::io(path, e))?;
+    let bytes = (std::fs::metadata(path))
+        .map_err(|e| CommandError::io(path, e))?
+        .len();
+    Ok(FileReport {
+        path: path.display().to_string(),
+        rows,
+        bytes,
+        dropped_rows: 0,
+    })
+}
+
+/// Writes `frames` to `out` as a CSV file of them would have them, for
+/// output that isn't a file of its own, like the CLI's standard output.
+/// Returns the rows written.
+pub(crate) fn write_csv(
+    out: impl Write,
+    bobs: usize,
+    configuration: Option<&RecordingConfiguration>,
+    frames: impl Iterator<Item = (Pendulum, f64)>,
+) -> io::Result<u64> {
+    let chunks = chunked(frames, bobs, csv_row);
+    write_rows(out, &head(bobs, configuration), columns(bobs), chunks)
+}
+
+/// `frames` as chunks of `ROWS_PER_CHUNK` rows, each appended by `row`.
+fn chunked(
+    frames: impl Iterator<Item = (Pendulum, f64)>,
+    bobs: usize,
+    row: fn(&Pendulum, f64, &mut Vec<f64>),
+) -> impl Iterator<Item = Vec<f64>> {
+    let mut frames = frames.peekable();
+    std::iter::from_fn(move || {
+        frames.peek()?;
+        let mut chunk = Vec::with_capacity(ROWS_PER_CHUNK * columns(bobs));
+        for (pendulum, sim_time) in frames.by_ref().take(ROWS_PER_CHUNK) {
+            row(&pendulum, sim_time, &mut chunk);
+        }
+        Some(chunk)
+    })
+}
+
+/// Appends the CSV row of `pendulum` at `sim_time`.
+fn csv_row(pendulum: &Pendulum, sim_time: f64, row: &mut Vec<f64>) {
+    row.push(sim_time);
+    for bob in &pendulum.bobs {
+        row.extend([bob.theta, bob.omega, bob.coordinate.x, bob.coordinate.y]);
+    }
+    row.extend([pendulum.pivot.x, pendulum.pivot.y]);
+}
+
+/// The writer's side: the header, then every row it is sent, until the
+/// recording lets go of the channel. Returns the rows written.
+fn write_rows(
+    out: impl Write,
+    header: &str,
+    columns: usize,
+    chunks: impl IntoIterator<Item = Vec<f64>>,
+) -> io::Result<u64> {
+    let mut out = BufWriter::new(out);
+    writeln!(out, "{header}")?;
+    let mut rows = 0;
+    for chunk in chunks {
+        for row in chunk.chunks(columns) {
+            for (i, value) in row.iter().enumerate() {
+                if i > 0 {
+                    out.write_all(b",")?;
+                }
+                write!(out, "{value}")?;
+            }
+            out.write_all(b"\n")?;
+            rows += 1;
+        }
+        out.flush()?;
+    }
+    out.flush()?;
+    Ok(rows)
+}
+
+/// A trajectory being written to a file, CSV or binary, one row per
+/// substep or per `decimation` of them. Rows go to a thread of their own in
+/// chunks, so the physics never waits on the disk. Angles are canonical
+/// radians, from up and clockwise, and positions meters with y up, whatever
+/// the commands show them as.
+pub(crate) struct TrajectoryRecording {
+    path: PathBuf,
+    bobs: usize,
+    decimation: u32,
+    format: RecordingFormat,
+    since_row: u32,
+    chunk: Vec<f64>,
+    chunks: Option<SyncSender<Vec<f64>>>,
+    writer: Option<JoinHandle<io::Result<u64>>>,
+    /// Rows handed to the writer.
+    rows: u64,
+    dropped_rows: u64,
+}
+
+/// The file a `TrajectoryRecording` writes, created before the recording
+/// starts so the physics never waits on opening it either.
+pub(crate) struct TrajectoryFile {
+    path: PathBuf,
+    file: File,
+    options: RecordingOptions,
+}
+
+impl TrajectoryFile {
+    /// Checks `options` and creates the file at `path`, replacing what is
+    /// there.
+    pub(crate) fn create(
+        path: impl Into<PathBuf>,
+        options: RecordingOptions,
+    ) -> Result<Self, CommandError> {
+        if !(1..=MAX_DECIMATION).contains(&options.decimation) {
+            return Err(CommandError::invalid(
+                "options.decimation",
+                format!(
+                    "must be between 1 and {MAX_DECIMATION}, got {}",
+                    options.decimation
+                ),
+            ));
+        }
+        let path = path.into();
+        let file = File::create(&path).map_err(|e| CommandError::io(&path, e))?;
+        Ok(Self {
+            path,
+            file,
+            options,
+        })
+    }
+}
+
+impl TrajectoryRecording {
+    /// Starts the writer on `file`. `dt` is the substep, which a binary
+    /// header records.
+    pub(crate) fn start(
+        file: TrajectoryFile,
+        pendulum: &Pendulum,
+        dt: f64,
+    ) -> Result<Self, CommandError> {
+        let TrajectoryFile {
+            path,
+            file,
+            options,
+        } = file;
+        let bobs = pendulum.n();
+        let configuration = RecordingConfiguration::of(pendulum);
+        let (sender, receiver) = mpsc::sync_channel(CHUNK_QUEUE);
+        let write = move || match options.format {
+            RecordingFormat::Csv => {
+                let head = head(bobs, Some(&configuration));
+                write_rows(file, &head, columns(bobs), receiver)
+            }
+            RecordingFormat::Binary { compressed } => {
+                let header = BinaryHeader {
+                    bobs,
+                    dt: dt * f64::from(options.decimation),
+                    compressed,
+                    configuration: Some(configuration),
+                };
+                binary::write_frames(file, &header, receiver)
+            }
+        };
+        let writer = std::thread::Builder::new()
+            .name("trajectory-recording".into())
+            .spawn(write)
+            .map_err(CommandError::internal)?;
+        Ok(Self {
+            path,
+            bobs,
+            decimation: options.decimation,
+            format: options.format,
+            since_row: 0,
+            chunk: Vec::new(),
+            chunks: Some(sender),
+            writer: Some(writer),
+            rows: 0,
+            dropped_rows: 0,
+        })
+    }
+
+    fn columns(&self) -> usize {
+        columns(self.bobs)
+    }
+
+    /// Adds a row for the substep just taken, if it is one of every
+    /// `decimation`. An error ends the recording, saying why: the writer
+    /// failed, or the chain no longer has the header's bobs.
+    pub(crate) fn push(&mut self, pendulum: &Pendulum, sim_time: f64) -> Result<(), String> {
+        if pendulum.n() != self.bobs {
+            return Err(format!(
+                "the chain went from {} to {} bobs",
+                self.bobs,
+                pendulum.n()
+            ));
+        }
+        self.since_row += 1;
+        if self.since_row < self.decimation {
+            return Ok(());
+        }
+        self.since_row = 0;
+        match self.format {
+            RecordingFormat::Csv => csv_row(pendulum, sim_time, &mut self.chunk),
+            RecordingFormat::Binary { .. } => binary::row(pendulum, sim_time, &mut self.chunk),
+        }
+        if self.chunk.len() >= ROWS_PER_CHUNK * self.columns() {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<(), String> {
+        let rows = (self.chunk.len() / self.columns()) as u64;
+        let chunk = std::mem::take(&mut self.chunk);
+        let sender = self.chunks.as_ref().expect("sent to after finishing");
+        match sender.try_send(chunk) {
+            Ok(()) => self.rows += rows,
+            Err(TrySendError::Full(_)) => self.dropped_rows += rows,
+            Err(TrySendError::Disconnected(_)) => return Err(self.writer_failure()),
+        }
+        Ok(())
+    }
+
+    /// Why the writer stopped early.
+    fn writer_failure(&mut self) -> String {
+        match self.writer.take().map(JoinHandle::join) {
+            Some(Ok(Err(error))) => error.to_string(),
+            Some(Err(_)) => "the writer panicked".into(),
+            Some(Ok(Ok(_))) | None => "the writer stopped".into(),
+        }
+    }
+
+    pub(crate) fn failed(&self, reason: String) -> RecordingFailedEvent {
+        RecordingFailedEvent {
+            path: self.path.display().to_string(),
+            rows: self.rows,
+            reason,
+        }
+    }
+
+    /// Hands the writer the last rows and waits for it to finish the file.
+    pub(crate) fn finish(mut self) -> Result<FileReport, CommandError> {
+        if !self.chunk.is_empty() {
+            let chunk = std::mem::take(&mut self.chunk);
+            // the physics is no longer waiting, so this one may block
+            let sender = self.chunks.take().expect("finished once");
+            if sender.send(chunk).is_err() {
+                let reason = self.writer_failure();
+                return Err(CommandError::io(&self.path, reason));
+            }
+        }
+        self.chunks = None;
+        let rows = match self.writer.take().map(JoinHandle::join) {
+            Some(Ok(Ok(rows))) => rows,
+            Some(Ok(Err(error))) => return Err(CommandError::io(&self.path, error)),
+            Some(Err(_)) | None => {
+                return Err(CommandError::io(&self.path, "the writer panicked"));
+            }
+        };
+        let bytes = (std::fs::metadata(&self.path))
+            .map_err(|e| CommandError::io(&self.path, e))?
+            .len();
+        Ok(FileReport {
+            path: self.path.display().to_string(),
+            rows,
+            bytes,
+            dropped_rows: self.dropped_rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::SimEvent;
+    use crate::state::AppDataInner;
+
+    fn run(data: &mut AppDataInner, steps: usize) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        for _ in 0..steps {
+            let dt = data.dt;
+            data.substep(dt, &mut events);
+        }
+        events
+    }
+
+    #[test]
+    fn csv_rows_follow_the_substeps_and_disk_errors_end_the_recording() {
+        let path = std::env::temp_dir().join(format!("trajectory-{}.csv", std::process::id()));
+        let mut data = AppDataInner::default();
+        data.pendulum.bobs[1].mass = 2.5;
+        let options = RecordingOptions {
+            decimation: 0,
+            ..RecordingOptions::default()
+        };
+        assert!(TrajectoryFile::create(&path, options).is_err());
+        let missing = path.join("nowhere/run.csv");
+        assert!(matches!(
+            TrajectoryFile::create(&missing, RecordingOptions::default()),
+            Err(CommandError::Io { .. })
+        ));
+
+        let options = RecordingOptions {
+            decimation: 3,
+            ..RecordingOptions::default()
+        };
+        let file = TrajectoryFile::create(&path, options).unwrap();
+        data.trajectory_recording =
+            Some(TrajectoryRecording::start(file, &data.pendulum, data.dt).unwrap());
+        let failed = |e: &SimEvent| matches!(e, SimEvent::RecordingFailed(_));
+        assert!(!run(&mut data, 1000).iter().any(failed));
+        let report = data.trajectory_recording.take().unwrap().finish().unwrap();
+        assert_eq!((report.rows, report.dropped_rows), (333, 0));
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(report.bytes, text.len() as u64);
+        let lines: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(lines.len(), 334);
+        assert_eq!(lines[0], header(data.pendulum.n()));
+        assert!(lines[0].starts_with("sim_time_s,theta1_rad,omega1_rad_per_s,x1_m,y1_m,"));
+        let last: Vec<f64> = lines[333].split(',').map(|v| v.parse().unwrap()).collect();
+        assert_eq!(last.len(), 3 + 4 * data.pendulum.n());
+        assert!(lines[0].ends_with(",pivot_x_m,pivot_y_m"));
+        let first_time: f64 = lines[1].split(',').next().unwrap().parse().unwrap();
+        assert!((first_time - 3.0 * data.dt).abs() < 1e-12, "{first_time}");
+        // the configuration line before the header keeps the masses
+        let configuration = text.lines().next().unwrap();
+        assert!(
+            configuration.starts_with(CONFIGURATION_PREFIX),
+            "{configuration}"
+        );
+        let recording = read_csv(&text).unwrap();
+        assert_eq!(recording.samples()[0].bobs[1].mass, 2.5);
+        let configuration = recording.configuration().unwrap();
+        assert_eq!(configuration, &RecordingConfiguration::of(&data.pendulum));
+        std::fs::remove_file(&path).unwrap();
+
+        // a full disk stops the recording with an event, never the physics
+        if cfg!(target_os = "linux") {
+            let file = TrajectoryFile::create("/dev/full", RecordingOptions::default()).unwrap();
+            data.trajectory_recording =
+                Some(TrajectoryRecording::start(file, &data.pendulum, data.dt).unwrap());
+            let mut events = Vec::new();
+            for _ in 0..200 {
+                events.extend(run(&mut data, ROWS_PER_CHUNK).into_iter().filter(failed));
+                if data.trajectory_recording.is_none() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            assert!(data.trajectory_recording.is_none());
+            let [SimEvent::RecordingFailed(failed)] = &events[..] else {
+                panic!("expected a failed recording event, got {events:?}");
+            };
+            assert_eq!(failed.path, "/dev/full");
+            assert!(!failed.reason.is_empty());
+        }
+    }
+}
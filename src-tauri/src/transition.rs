@@ -0,0 +1,131 @@
+use pendulum_core::Bob;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandError;
+
+/// Longest eased angle edit `set_pose_transition` accepts, in sim seconds.
+pub(crate) const MAX_TRANSITION_DURATION: f64 = 10.0;
+
+/// A bob's angle easing from where it was to where an edit put it, instead
+/// of jumping there. The path is the cubic Hermite from the angle and
+/// angular velocity at the edit to the target and `to_omega`, so the bob
+/// leaves its old motion and joins the dynamics again without a kink.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PoseTransition {
+    /// The bob being eased, by id.
+    pub(crate) id: u64,
+    from: f64,
+    from_omega: f64,
+    to: f64,
+    to_omega: f64,
+    /// Sim time of the edit.
+    start: f64,
+    duration: f64,
+}
+
+/// Where a transition stands, as streamed with every frame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TransitionState {
+    pub(crate) id: u64,
+    /// The angle the bob is easing to.
+    pub(crate) target: f64,
+    /// From 0 at the edit to 1 when the bob is handed back.
+    pub(crate) progress: f64,
+}
+
+impl PoseTransition {
+    /// Eases `bob` from its current motion to `to` at `to_omega`, over
+    /// `duration` sim seconds from `start`.
+    pub(crate) fn new(bob: &Bob, to: f64, to_omega: f64, start: f64, duration: f64) -> Self {
+        Self {
+            id: bob.id,
+            from: bob.theta,
+            from_omega: bob.omega,
+            to,
+            to_omega,
+            start,
+            duration,
+        }
+    }
+
+    fn progress(&self, sim_time: f64) -> f64 {
+        ((sim_time - self.start) / self.duration).clamp(0.0, 1.0)
+    }
+
+    /// Angle, angular velocity and angular acceleration on the path at
+    /// `sim_time`; past the end, the target's.
+    pub(crate) fn at(&self, sim_time: f64) -> (f64, f64, f64) {
+        let s = self.progress(sim_time);
+        let h = self.duration;
+        let (p0, v0, p1, v1) = (self.from, self.from_omega * h, self.to, self.to_omega * h);
+        let (s2, s3) = (s * s, s * s * s);
+        let theta = (2.0 * s3 - 3.0 * s2 + 1.0) * p0
+            + (s3 - 2.0 * s2 + s) * v0
+            + (-2.0 * s3 + 3.0 * s2) * p1
+            + (s3 - s2) * v1;
+        let omega = ((6.0 * s2 - 6.0 * s) * p0
+            + (3.0 * s2 - 4.0 * s + 1.0) * v0
+            + (-6.0 * s2 + 6.0 * s) * p1
+            + (3.0 * s2 - 2.0 * s) * v1)
+            / h;
+        let alpha = ((12.0 * s - 6.0) * p0
+            + (6.0 * s - 4.0) * v0
+            + (-12.0 * s + 6.0) * p1
+            + (6.0 * s - 2.0) * v1)
+            / (h * h);
+        if s >= 1.0 {
+            (self.to, self.to_omega, alpha)
+        } else {
+            (theta, omega, alpha)
+        }
+    }
+
+    /// Whether a transition read back from a file is one `new` could have
+    /// made: finite, with a duration `set_pose_transition` accepts.
+    pub(crate) fn is_well_formed(&self) -> bool {
+        let values = [
+            self.from,
+            self.from_omega,
+            self.to,
+            self.to_omega,
+            self.start,
+        ];
+        values.iter().all(|v| v.is_finite())
+            && self.duration > 0.0
+            && self.duration <= MAX_TRANSITION_DURATION
+    }
+
+    /// The bob reaches the target by `sim_time`.
+    pub(crate) fn is_done(&self, sim_time: f64) -> bool {
+        sim_time >= self.start + self.duration
+    }
+
+    /// `bob` is still on the path at `sim_time`, so nothing else has moved it
+    /// since the last step.
+    pub(crate) fn follows(&self, bob: &Bob, sim_time: f64) -> bool {
+        let (theta, omega, _) = self.at(sim_time);
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-9 * (1.0 + b.abs());
+        bob.id == self.id && close(bob.theta, theta) && close(bob.omega, omega)
+    }
+
+    pub(crate) fn state(&self, sim_time: f64) -> TransitionState {
+        TransitionState {
+            id: self.id,
+            target: self.to,
+            progress: self.progress(sim_time),
+        }
+    }
+}
+
+/// Checks a `set_pose_transition` duration; `None` turns easing off.
+pub(crate) fn validate_duration(duration: Option<f64>) -> Result<(), CommandError> {
+    match duration {
+        Some(d) if !(d > 0.0 && d <= MAX_TRANSITION_DURATION) => Err(CommandError::invalid(
+            "duration",
+            format!("must be in (0, {MAX_TRANSITION_DURATION}], got {d}"),
+        )),
+        _ => Ok(()),
+    }
+}
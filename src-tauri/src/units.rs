@@ -0,0 +1,881 @@
+use pendulum_core::{wrap_angle, Coordinate};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use crate::bounds::Bounds;
+use crate::error::CommandError;
+use crate::pendulum::BobSpec;
+use crate::prediction::Prediction;
+use crate::randomize::{RandomRanges, ValueRange};
+use crate::rewind::PastState;
+use crate::state::{BobUpdate, PendulumState, SetStateOptions, StateSnapshot};
+use crate::stream::StepReport;
+use crate::trails::{SimplifiedTrail, Trail};
+use crate::warmup::SteppedState;
+
+/// Unit of the angles, angular velocities and accelerations that commands
+/// take and return. Internally, and in the streamed frames, it is always
+/// radians.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum AngleUnit {
+    #[default]
+    #[serde(rename = "rad")]
+    Radians,
+    #[serde(rename = "deg")]
+    Degrees,
+}
+
+impl AngleUnit {
+    /// `value` in this unit, converted to radians.
+    pub(crate) fn to_radians(self, value: f64) -> f64 {
+        match self {
+            Self::Radians => value,
+            Self::Degrees => value.to_radians(),
+        }
+    }
+
+    /// `value` in radians, converted to this unit.
+    pub(crate) fn in_unit(self, value: f64) -> f64 {
+        match self {
+            Self::Radians => value,
+            Self::Degrees => value.to_degrees(),
+        }
+    }
+
+    /// A command argument, with its angles brought to radians.
+    pub(crate) fn incoming<T: Angles>(self, mut value: T) -> T {
+        let f = |x| self.to_radians(x);
+        value.map_angles(&AngleMap {
+            angle: &f,
+            rate: &f,
+        });
+        value
+    }
+
+    /// A command result, with its angles brought to this unit.
+    pub(crate) fn outgoing<T: Angles>(self, mut value: T) -> T {
+        let f = |x| self.in_unit(x);
+        value.map_angles(&AngleMap {
+            angle: &f,
+            rate: &f,
+        });
+        value
+    }
+}
+
+/// Where an angle of 0 points, as seen in the frames' positions: x to the
+/// right, y up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AngleReference {
+    /// Straight up, so a hanging bob is at π. The simulation's own.
+    #[default]
+    Up,
+    /// Straight down, so a hanging bob is at 0.
+    Down,
+    /// Level, toward +x.
+    Right,
+    /// Level, toward -x.
+    Left,
+}
+
+/// Which way angles grow, as seen in the frames' positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AngleDirection {
+    /// From up toward +x. The simulation's own.
+    #[default]
+    Clockwise,
+    Counterclockwise,
+}
+
+/// What an angle given to or taken from a command is measured from and which
+/// way. The simulation, the streamed frames and `HERMITE_INTERPOLATION` keep
+/// to the default, from straight up and clockwise, where
+/// x = l sin θ and y = l cos θ; every other convention is converted at the
+/// command boundary, like `AngleUnit`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct AngleConvention {
+    pub(crate) zero: AngleReference,
+    pub(crate) direction: AngleDirection,
+}
+
+impl AngleConvention {
+    /// The simulation's angle of this convention's 0.
+    fn zero_angle(self) -> f64 {
+        match self.zero {
+            AngleReference::Up => 0.0,
+            AngleReference::Right => FRAC_PI_2,
+            AngleReference::Down => PI,
+            AngleReference::Left => -FRAC_PI_2,
+        }
+    }
+
+    fn sign(self) -> f64 {
+        match self.direction {
+            AngleDirection::Clockwise => 1.0,
+            AngleDirection::Counterclockwise => -1.0,
+        }
+    }
+
+    /// An angle in this convention, as the simulation measures it.
+    pub(crate) fn to_canonical(self, angle: f64) -> f64 {
+        self.zero_angle() + self.sign() * angle
+    }
+
+    /// An angle as the simulation measures it, in this convention.
+    pub(crate) fn in_convention(self, angle: f64) -> f64 {
+        self.sign() * (angle - self.zero_angle())
+    }
+
+    /// A command argument, with its angles brought to the simulation's
+    /// convention. Rates and differences only change sign.
+    pub(crate) fn incoming<T: Angles>(self, mut value: T) -> T {
+        value.map_angles(&AngleMap {
+            angle: &|x| self.to_canonical(x),
+            rate: &|x| self.sign() * x,
+        });
+        value
+    }
+
+    /// A command result, with its angles brought to this convention.
+    pub(crate) fn outgoing<T: Angles>(self, mut value: T) -> T {
+        value.map_angles(&AngleMap {
+            angle: &|x| self.in_convention(x),
+            rate: &|x| self.sign() * x,
+        });
+        value
+    }
+}
+
+/// How commands take and return angles: a unit and a convention.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct AngleFormat {
+    pub(crate) unit: AngleUnit,
+    pub(crate) convention: AngleConvention,
+}
+
+impl AngleFormat {
+    /// A command argument, with its angles brought to the simulation's.
+    pub(crate) fn incoming<T: Angles>(self, value: T) -> T {
+        self.convention.incoming(self.unit.incoming(value))
+    }
+
+    /// A command result, with its angles brought to this format.
+    pub(crate) fn outgoing<T: Angles>(self, value: T) -> T {
+        self.unit.outgoing(self.convention.outgoing(value))
+    }
+
+    /// A lone angle argument, like `modify_bob`'s `theta`.
+    pub(crate) fn incoming_angle(self, angle: f64) -> f64 {
+        self.convention.to_canonical(self.unit.to_radians(angle))
+    }
+
+    /// A lone rate or difference argument, like `modify_bob`'s `omega`.
+    pub(crate) fn incoming_rate(self, rate: f64) -> f64 {
+        self.convention.sign() * self.unit.to_radians(rate)
+    }
+
+    /// A lone size of an angle or rate, like `create_ensemble`'s `scale`,
+    /// which no convention turns negative.
+    pub(crate) fn incoming_magnitude(self, magnitude: f64) -> f64 {
+        self.unit.to_radians(magnitude)
+    }
+}
+
+/// How many pixels the frontends draw a meter as, to begin with, and what
+/// lengths saved before they were in meters were drawn at.
+pub(crate) const DEFAULT_PIXELS_PER_METER: f64 = 100.0;
+
+/// Checks a `set_render_scale` argument.
+pub(crate) fn validate_pixels_per_meter(pixels_per_meter: f64) -> Result<(), CommandError> {
+    if pixels_per_meter.is_finite() && pixels_per_meter > 0.0 {
+        Ok(())
+    } else {
+        Err(CommandError::invalid(
+            "pixels_per_meter",
+            format!("must be positive and finite, got {pixels_per_meter}"),
+        ))
+    }
+}
+
+/// Which way y points in the positions commands take and return. The
+/// simulation's, like the streamed frames', points up, so a bob hanging at
+/// rest has a smaller y than the pivot. Only y is mirrored: x, and what
+/// `AngleConvention` calls clockwise, stay as seen with y up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WorldFrame {
+    pub(crate) y_up: bool,
+}
+
+impl Default for WorldFrame {
+    fn default() -> Self {
+        Self { y_up: true }
+    }
+}
+
+impl WorldFrame {
+    /// A position as the simulation has it, in this frame, or the other way
+    /// round: mirroring is its own inverse.
+    fn map(self, position: Coordinate) -> Coordinate {
+        if self.y_up {
+            position
+        } else {
+            Coordinate::new(position.x, -position.y)
+        }
+    }
+
+    /// A command argument, with its positions brought to the simulation's
+    /// frame.
+    pub(crate) fn incoming<T: Positions>(self, mut value: T) -> T {
+        value.map_positions(&|p| self.map(p));
+        value
+    }
+
+    /// A command result, with its positions brought to this frame.
+    pub(crate) fn outgoing<T: Positions>(self, mut value: T) -> T {
+        value.map_positions(&|p| self.map(p));
+        value
+    }
+}
+
+/// How the angles a client reads are given. The simulation keeps them
+/// unwrapped, counting whole turns, and flip detection, the linear fits and
+/// recordings use those whatever a client reads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ThetaPolicy {
+    /// As integrated, so turns show as whole multiples of 2π.
+    #[default]
+    Unbounded,
+    /// Wrapped to (-π, π].
+    Wrapped,
+}
+
+impl ThetaPolicy {
+    pub(crate) fn apply(self, theta: f64) -> f64 {
+        match self {
+            Self::Unbounded => theta,
+            Self::Wrapped => wrap_angle(theta),
+        }
+    }
+
+    /// Gives the bobs' angles this way, and the overlays' that follow them.
+    /// Angular velocities and differences are the same either way.
+    pub(crate) fn present(self, state: &mut PendulumState) {
+        if self == Self::Unbounded {
+            return;
+        }
+        for bob in &mut state.bobs {
+            bob.theta = self.apply(bob.theta);
+        }
+        if let Some(analytic) = &mut state.analytic {
+            for bob in &mut analytic.bobs {
+                bob.theta = self.apply(bob.theta);
+            }
+        }
+        for bob in state.ghost_bobs.iter_mut().flatten() {
+            bob.theta = self.apply(bob.theta);
+        }
+        for transition in &mut state.transitions {
+            transition.target = self.apply(transition.target);
+        }
+    }
+}
+
+/// Values with angle-bearing fields. Every command argument or result that
+/// has some goes through `AngleFormat::incoming` or `outgoing`, so the fields
+/// are listed here, once per type, each as an angle or a rate.
+pub(crate) trait Angles {
+    fn map_angles(&mut self, map: &AngleMap);
+}
+
+/// A conversion of angle-bearing values.
+pub(crate) struct AngleMap<'a> {
+    /// For angles, which a change of reference shifts.
+    pub(crate) angle: &'a dyn Fn(f64) -> f64,
+    /// For angular velocities, accelerations and differences of angles,
+    /// which it only turns around.
+    pub(crate) rate: &'a dyn Fn(f64) -> f64,
+}
+
+impl<T: Angles> Angles for Option<T> {
+    fn map_angles(&mut self, map: &AngleMap) {
+        if let Some(value) = self {
+            value.map_angles(map);
+        }
+    }
+}
+
+impl<T: Angles> Angles for Vec<T> {
+    fn map_angles(&mut self, map: &AngleMap) {
+        for value in self {
+            value.map_angles(map);
+        }
+    }
+}
+
+impl Angles for BobSpec {
+    fn map_angles(&mut self, map: &AngleMap) {
+        self.theta = (map.angle)(self.theta);
+        self.omega = (map.rate)(self.omega);
+    }
+}
+
+impl Angles for BobUpdate {
+    fn map_angles(&mut self, map: &AngleMap) {
+        self.theta = self.theta.map(map.angle);
+        self.omega = self.omega.map(map.rate);
+    }
+}
+
+/// `range` through `f`, kept in order should `f` turn it around.
+fn map_range(range: &mut ValueRange, f: &dyn Fn(f64) -> f64) {
+    let (a, b) = (f(range.min), f(range.max));
+    (range.min, range.max) = (a.min(b), a.max(b));
+}
+
+impl Angles for RandomRanges {
+    fn map_angles(&mut self, map: &AngleMap) {
+        map_range(&mut self.theta, map.angle);
+        if let Some(omega) = &mut self.omega {
+            map_range(omega, map.rate);
+        }
+    }
+}
+
+/// The bobs' angles and the overlays' that follow them; the dynamics terms
+/// stay as they are.
+impl Angles for StateSnapshot {
+    fn map_angles(&mut self, map: &AngleMap) {
+        let state = &mut self.state;
+        for bob in &mut state.bobs {
+            bob.theta = (map.angle)(bob.theta);
+            bob.omega = (map.rate)(bob.omega);
+            bob.alpha = (map.rate)(bob.alpha);
+        }
+        if let Some(analytic) = &mut state.analytic {
+            for bob in &mut analytic.bobs {
+                bob.theta = (map.angle)(bob.theta);
+            }
+            for difference in &mut analytic.discrepancy {
+                *difference = (map.rate)(*difference);
+            }
+        }
+        for bob in state.ghost_bobs.iter_mut().flatten() {
+            bob.theta = (map.angle)(bob.theta);
+        }
+        for transition in &mut state.transitions {
+            transition.target = (map.angle)(transition.target);
+        }
+    }
+}
+
+impl Angles for SteppedState {
+    fn map_angles(&mut self, map: &AngleMap) {
+        for theta in &mut self.thetas {
+            *theta = (map.angle)(*theta);
+        }
+        for omega in &mut self.omegas {
+            *omega = (map.rate)(*omega);
+        }
+    }
+}
+
+impl Angles for PastState {
+    fn map_angles(&mut self, map: &AngleMap) {
+        for theta in &mut self.thetas {
+            *theta = (map.angle)(*theta);
+        }
+        for omega in &mut self.omegas {
+            *omega = (map.rate)(*omega);
+        }
+    }
+}
+
+impl Angles for StepReport {
+    fn map_angles(&mut self, map: &AngleMap) {
+        for alpha in &mut self.alphas {
+            *alpha = (map.rate)(*alpha);
+        }
+    }
+}
+
+/// Values with positions in them, which go through `WorldFrame::incoming`
+/// or `outgoing` like angles go through `AngleFormat`.
+pub(crate) trait Positions {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate);
+}
+
+impl Positions for Coordinate {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        *self = map(*self);
+    }
+}
+
+impl<T: Positions> Positions for Option<T> {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        if let Some(value) = self {
+            value.map_positions(map);
+        }
+    }
+}
+
+impl<T: Positions> Positions for Vec<T> {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        for value in self {
+            value.map_positions(map);
+        }
+    }
+}
+
+impl Positions for SetStateOptions {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        self.pivot.map_positions(map);
+    }
+}
+
+/// The pivot, the bobs and the overlays drawn next to them.
+impl Positions for StateSnapshot {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        let state = &mut self.state;
+        state.pivot.map_positions(map);
+        for bob in &mut state.bobs {
+            bob.position.map_positions(map);
+        }
+        if let Some(analytic) = &mut state.analytic {
+            for bob in &mut analytic.bobs {
+                bob.position.map_positions(map);
+            }
+        }
+        for bob in state.ghost_bobs.iter_mut().flatten() {
+            bob.position.map_positions(map);
+        }
+        if let Some(ensemble) = &mut state.ensemble {
+            for (x, y) in ensemble.tip_x.iter_mut().zip(&mut ensemble.tip_y) {
+                let tip = map(Coordinate::new(*x, *y));
+                (*x, *y) = (tip.x, tip.y);
+            }
+        }
+        if let Some(comparison) = &mut state.comparison {
+            comparison.positions.map_positions(map);
+        }
+        state.bounds = state.bounds.map(|bounds| bounds.mapped(map));
+        if let Some(wave) = &mut state.wave {
+            for (x, y) in wave.tip_x.iter_mut().zip(&mut wave.tip_y) {
+                let tip = map(Coordinate::new(*x, *y));
+                (*x, *y) = (tip.x, tip.y);
+            }
+        }
+    }
+}
+
+impl Positions for SteppedState {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        self.positions.map_positions(map);
+    }
+}
+
+impl Positions for PastState {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        self.positions.map_positions(map);
+        self.pivot.map_positions(map);
+    }
+}
+
+impl Positions for Bounds {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        *self = self.mapped(map);
+    }
+}
+
+impl Positions for Trail {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        self.positions.map_positions(map);
+    }
+}
+
+impl Positions for SimplifiedTrail {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        self.positions.map_positions(map);
+    }
+}
+
+impl Positions for Prediction {
+    fn map_positions(&mut self, map: &dyn Fn(Coordinate) -> Coordinate) {
+        for sample in &mut self.samples {
+            sample.positions.map_positions(map);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventSink, SimEvent};
+    use crate::instances::Instances;
+    use crate::pendulum::BobMeta;
+    use crate::randomize::RandomRanges;
+    use crate::state::{AppDataInner, SetStateOptions};
+    use crate::stream::physics_loop;
+    use crate::warmup::{self, WARM_UP_WALL_BUDGET};
+    use std::f64::consts::PI;
+
+    const UNITS: [AngleUnit; 2] = [AngleUnit::Radians, AngleUnit::Degrees];
+
+    /// Every unit in every convention.
+    fn formats() -> Vec<AngleFormat> {
+        let mut formats = Vec::new();
+        for unit in UNITS {
+            for zero in [
+                AngleReference::Up,
+                AngleReference::Down,
+                AngleReference::Right,
+                AngleReference::Left,
+            ] {
+                for direction in [AngleDirection::Clockwise, AngleDirection::Counterclockwise] {
+                    let convention = AngleConvention { zero, direction };
+                    formats.push(AngleFormat { unit, convention });
+                }
+            }
+        }
+        formats
+    }
+
+    /// An angle and a rate of the simulation's, as a client in `format`
+    /// gives them.
+    fn given(format: AngleFormat, theta: f64, omega: f64) -> (f64, f64) {
+        let convention = format.convention;
+        (
+            format.unit.in_unit(convention.in_convention(theta)),
+            format.unit.in_unit(convention.sign() * omega),
+        )
+    }
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit(&self, _: SimEvent) {}
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1.0)
+    }
+
+    /// The commands' arguments, given in each unit and convention, land as
+    /// the same radians.
+    #[test]
+    fn arguments_come_in_as_the_simulations_radians() {
+        let (theta, omega) = (PI / 4.0, -PI / 6.0);
+        for unit in formats() {
+            let given = given(unit, theta, omega);
+            let mut data = AppDataInner {
+                angle_unit: unit.unit,
+                angle_convention: unit.convention,
+                ..AppDataInner::default()
+            };
+            let landed = |data: &AppDataInner, index: usize| {
+                let bob = data.pendulum.bobs[index];
+                close(bob.theta, theta) && close(bob.omega, omega)
+            };
+
+            // add_bob
+            let added = data
+                .add_bob(
+                    None,
+                    None,
+                    Some(unit.incoming_angle(given.0)),
+                    Some(unit.incoming_rate(given.1)),
+                )
+                .unwrap();
+            assert!(landed(&data, added.index), "add_bob in {unit:?}");
+
+            // insert_bob and set_state
+            let spec = BobSpec {
+                length_rod: 50.0,
+                mass: 2.0,
+                theta: given.0,
+                omega: given.1,
+                id: None,
+                locked: false,
+                meta: BobMeta::default(),
+            };
+            data.insert_bob(0, unit.incoming(spec.clone()), false)
+                .unwrap();
+            assert!(landed(&data, 0), "insert_bob in {unit:?}");
+            let bobs = unit.incoming(vec![spec; 2]);
+            data.set_state(&bobs, SetStateOptions::default()).unwrap();
+            assert!(landed(&data, 1), "set_state in {unit:?}");
+
+            // modify_bob, modify_bob_by_id, and the WebSocket's modify_bob
+            data.pendulum.bobs[0].theta = 0.0;
+            let (t, o) = (unit.incoming_angle(given.0), unit.incoming_rate(given.1));
+            let (t, o) = (Some(t), Some(o));
+            data.modify_bob(0, None, None, t, o).unwrap();
+            assert!(landed(&data, 0), "modify_bob in {unit:?}");
+
+            // modify_bobs
+            let update = BobUpdate {
+                index: Some(1),
+                theta: Some(given.0),
+                omega: Some(given.1),
+                ..BobUpdate::default()
+            };
+            data.pendulum.bobs[1].theta = 0.0;
+            data.modify_bobs(&unit.incoming(vec![update])).unwrap();
+            assert!(landed(&data, 1), "modify_bobs in {unit:?}");
+
+            // randomize
+            let ranges = RandomRanges {
+                theta: ValueRange {
+                    min: given.0,
+                    max: given.0,
+                },
+                omega: Some(ValueRange {
+                    min: given.1,
+                    max: given.1,
+                }),
+                ..RandomRanges::default()
+            };
+            data.randomize(Some(1), &unit.incoming(ranges)).unwrap();
+            assert!(
+                landed(&data, 0) && landed(&data, 1),
+                "randomize in {unit:?}"
+            );
+
+            // create_ensemble's scale and the thresholds on ω keep their sign
+            let magnitude = unit.incoming_magnitude(unit.unit.in_unit(0.3));
+            assert!(close(magnitude, 0.3), "a magnitude in {unit:?}");
+        }
+    }
+
+    /// Wrapping gives (-π, π], and an edit given either way keeps the turns
+    /// the bob has made, even across the boundary.
+    #[test]
+    fn edits_near_the_wrap_boundary_keep_the_turns() {
+        let wrapped = ThetaPolicy::Wrapped;
+        assert_eq!(wrapped.apply(PI), PI);
+        assert_eq!(wrapped.apply(-PI), PI);
+        assert!(close(wrapped.apply(PI + 1e-6), -PI + 1e-6));
+        assert!(close(wrapped.apply(87.3), 87.3 - 28.0 * PI));
+        assert_eq!(ThetaPolicy::Unbounded.apply(87.3), 87.3);
+
+        // six turns and hanging just short of the bottom
+        let turned = 13.0 * PI - 0.01;
+        let mut data = AppDataInner::default();
+        data.pendulum.bobs[0].theta = turned;
+        data.state_edited();
+        let read = |data: &AppDataInner, policy| data.snapshot(0.0, policy).state.bobs[0].theta;
+        assert!(close(read(&data, wrapped), PI - 0.01));
+        assert_eq!(read(&data, ThetaPolicy::Unbounded), turned);
+
+        // nudged across the bottom, wrapped, then unbounded
+        data.modify_bob(0, None, None, Some(-PI + 0.01), None)
+            .unwrap();
+        assert!(close(data.pendulum.bobs[0].theta, 13.0 * PI + 0.01));
+        data.modify_bob(0, None, None, Some(13.0 * PI + 0.02), None)
+            .unwrap();
+        assert!(close(data.pendulum.bobs[0].theta, 13.0 * PI + 0.02));
+        let back = BobUpdate {
+            index: Some(0),
+            theta: Some(PI - 0.01),
+            ..BobUpdate::default()
+        };
+        let modified = data.modify_bobs(&[back]).unwrap();
+        assert_eq!(modified[0].changed, ["theta"]);
+        assert!(close(data.pendulum.bobs[0].theta, turned));
+        // the same angle again changes nothing
+        let again = BobUpdate {
+            theta: Some(data.pendulum.bobs[0].theta),
+            ..back
+        };
+        assert!(data.modify_bobs(&[again]).unwrap()[0].changed.is_empty());
+    }
+
+    /// The commands' results come out in the unit set.
+    #[tokio::test]
+    async fn results_go_out_in_the_unit_set() {
+        for unit in UNITS {
+            let format = AngleFormat {
+                unit,
+                ..AngleFormat::default()
+            };
+            let mut data = AppDataInner {
+                angle_unit: unit,
+                user_paused: true,
+                ..AppDataInner::default()
+            };
+
+            // single_step
+            let report = crate::stream::single_step(&mut data, None, &mut Vec::new()).unwrap();
+            let alphas = data.pendulum.accelerations();
+            let expected: Vec<f64> = alphas.iter().map(|&a| unit.in_unit(a)).collect();
+            assert_eq!(report.alphas, expected, "single_step in {unit:?}");
+
+            // get_state
+            let snapshot = data.snapshot(0.0, ThetaPolicy::Unbounded);
+            let bobs = data.pendulum.bobs.clone();
+            assert_eq!(snapshot.angle_unit, unit);
+            for (state, bob) in snapshot.state.bobs.iter().zip(&bobs) {
+                assert!(close(state.theta, unit.in_unit(bob.theta)));
+                assert!(close(state.omega, unit.in_unit(bob.omega)));
+            }
+            // the frames themselves stay in radians
+            let frame = data.frame(0, 0.0);
+            assert_eq!(frame.bobs[0].theta, bobs[0].theta);
+
+            // step_n, converted like the command does, on the physics task
+            let cancel = data.warm_up_cancel.clone();
+            let (instances, physics) = Instances::new(data);
+            let physics = tokio::spawn(async move { physics_loop(physics, &NoEvents).await });
+            let (stepped, thetas) = tokio::task::spawn_blocking(move || {
+                let stepped =
+                    warmup::step_n(&instances, None, 10, 0.01, WARM_UP_WALL_BUDGET, &cancel);
+                let thetas = |app_data: &mut AppDataInner| {
+                    Ok(app_data
+                        .pendulum
+                        .bobs
+                        .iter()
+                        .map(|b| b.theta)
+                        .collect::<Vec<_>>())
+                };
+                (
+                    stepped.unwrap(),
+                    instances.call_blocking(None, thetas).unwrap(),
+                )
+            })
+            .await
+            .unwrap();
+            physics.abort();
+            let stepped = format.outgoing(stepped);
+            assert!(
+                stepped
+                    .thetas
+                    .iter()
+                    .zip(&thetas)
+                    .all(|(&a, &b)| close(a, unit.in_unit(b))),
+                "step_n in {unit:?}"
+            );
+        }
+        let json = serde_json::to_string(&AngleUnit::Degrees).unwrap();
+        assert_eq!(json, "\"deg\"");
+    }
+
+    /// Saved through `get_state` and loaded with `set_state` in any format,
+    /// a state comes back as it was, and the conventions read the hanging
+    /// chain the way their names say.
+    #[test]
+    fn conventions_round_trip_and_read_as_named() {
+        for format in formats() {
+            let mut data = AppDataInner {
+                angle_unit: format.unit,
+                angle_convention: format.convention,
+                ..AppDataInner::default()
+            };
+            for (i, bob) in data.pendulum.bobs.iter_mut().enumerate() {
+                bob.theta = 2.0 + 0.3 * i as f64;
+                bob.omega = 0.5 - 0.4 * i as f64;
+            }
+            data.state_edited();
+            let original = data.pendulum.bobs.clone();
+            let snapshot = data.snapshot(0.0, ThetaPolicy::Unbounded);
+            assert_eq!(snapshot.angle_convention, format.convention);
+            let saved: Vec<BobSpec> =
+                serde_json::from_value(serde_json::to_value(&snapshot.state.bobs).unwrap())
+                    .unwrap();
+            let mut loaded = AppDataInner {
+                angle_unit: format.unit,
+                angle_convention: format.convention,
+                ..AppDataInner::default()
+            };
+            loaded
+                .set_state(&format.incoming(saved), SetStateOptions::default())
+                .unwrap();
+            for (a, b) in loaded.pendulum.bobs.iter().zip(&original) {
+                assert!(close(a.theta, b.theta), "{format:?}");
+                assert!(close(a.omega, b.omega), "{format:?}");
+            }
+            // wrapped in the convention itself
+            let wrapped = data.snapshot(0.0, ThetaPolicy::Wrapped);
+            let limit = format.unit.in_unit(PI) + 1e-9;
+            assert!(wrapped.state.bobs.iter().all(|b| b.theta.abs() <= limit));
+        }
+
+        // a bob hanging straight down, and one a little to its right
+        let read =
+            |zero, direction, theta: f64| AngleConvention { zero, direction }.in_convention(theta);
+        use {AngleDirection::*, AngleReference::*};
+        assert!(close(read(Up, Clockwise, PI), PI));
+        assert!(close(read(Down, Counterclockwise, PI), 0.0));
+        assert!(read(Down, Counterclockwise, PI - 0.1) > 0.0);
+        assert!(close(read(Right, Counterclockwise, PI), -PI / 2.0));
+        assert!(close(read(Left, Clockwise, PI), 3.0 * PI / 2.0));
+        let json = serde_json::to_value(AngleConvention {
+            zero: Down,
+            direction: Counterclockwise,
+        })
+        .unwrap();
+        assert_eq!(json["zero"], "down");
+        assert_eq!(json["direction"], "counterclockwise");
+    }
+
+    /// Positions read in either frame go back in as the same pose, and the
+    /// pivot given in it lands where that frame says.
+    #[test]
+    fn positions_round_trip_through_either_frame() {
+        for y_up in [true, false] {
+            let frame = WorldFrame { y_up };
+            let mut data = AppDataInner {
+                world_frame: frame,
+                ..AppDataInner::default()
+            };
+            data.set_pivot(frame.incoming(Coordinate::new(30.0, 20.0)))
+                .unwrap();
+            assert_eq!(data.pendulum.pivot.y, if y_up { 20.0 } else { -20.0 });
+            for (i, bob) in data.pendulum.bobs.iter_mut().enumerate() {
+                bob.theta = 2.5 + 0.4 * i as f64;
+            }
+            data.state_edited();
+            let snapshot = data.snapshot(0.0, ThetaPolicy::Unbounded);
+            assert_eq!(snapshot.world_frame, frame);
+            assert_eq!(snapshot.state.pivot, Coordinate::new(30.0, 20.0));
+            // hanging below the pivot reads as smaller y only with y up
+            let below = snapshot.state.bobs[0].position.y < snapshot.state.pivot.y;
+            assert_eq!(below, y_up);
+            // the streamed frames keep the simulation's
+            assert_eq!(data.frame(0, 0.0).pivot, data.pendulum.pivot);
+
+            let points: Vec<Coordinate> = snapshot.state.bobs.iter().map(|b| b.position).collect();
+            let mut posed = AppDataInner {
+                world_frame: frame,
+                ..AppDataInner::default()
+            };
+            let options = SetStateOptions {
+                pivot: Some(snapshot.state.pivot),
+                ..SetStateOptions::default()
+            };
+            let bobs: Vec<BobSpec> = (data.pendulum.bobs.iter())
+                .map(|b| BobSpec {
+                    length_rod: b.length_rod,
+                    mass: b.mass,
+                    theta: 0.0,
+                    omega: 0.0,
+                    id: None,
+                    locked: false,
+                    meta: BobMeta::default(),
+                })
+                .collect();
+            posed.set_state(&bobs, frame.incoming(options)).unwrap();
+            let snapped = posed.set_pose_from_points(&frame.incoming(points)).unwrap();
+            assert!(snapped.iter().all(|&d| d < 1e-6), "{snapped:?}");
+            for (a, b) in posed.pendulum.bobs.iter().zip(&data.pendulum.bobs) {
+                assert!(
+                    close(wrap_angle(a.theta), wrap_angle(b.theta)),
+                    "y_up {y_up}"
+                );
+            }
+        }
+    }
+}
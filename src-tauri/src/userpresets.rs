@@ -0,0 +1,340 @@
+use pendulum_core::Coordinate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, ConfigFile};
+use crate::error::CommandError;
+use crate::presets::{PresetInfo, PresetSource};
+use crate::savefile;
+use crate::share;
+use crate::state::AppDataInner;
+
+/// Version of the preset files `save_user_preset` writes.
+pub(crate) const USER_PRESET_VERSION: u32 = 1;
+/// Name of the directory in the app data directory the presets are kept in.
+pub(crate) const USER_PRESETS_DIR: &str = "presets";
+/// Longest name a user preset may have, in characters.
+pub(crate) const MAX_PRESET_NAME_LEN: usize = 64;
+/// Longest run of a name kept in its file name.
+const FILE_STEM_LEN: usize = 32;
+
+/// What the picker shows of a user preset, worked out when it is saved so
+/// the list needs nothing else from the file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PresetSummary {
+    pub(crate) bob_count: usize,
+    /// Where each bob starts, in meters from the pivot with y up, in chain
+    /// order, for drawing a thumbnail.
+    pub(crate) positions: Vec<Coordinate>,
+}
+
+/// A user preset as it is written to disk.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UserPresetFile {
+    pub(crate) version: u32,
+    /// The name as it was given, which the file name only approximates.
+    pub(crate) name: String,
+    /// When it was saved, in milliseconds since the Unix epoch.
+    pub(crate) saved_at: u64,
+    pub(crate) summary: PresetSummary,
+    pub(crate) config: ConfigFile,
+}
+
+/// A user preset in `list_user_presets`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UserPresetInfo {
+    pub(crate) name: String,
+    pub(crate) saved_at: u64,
+    #[serde(flatten)]
+    pub(crate) summary: PresetSummary,
+}
+
+impl UserPresetInfo {
+    pub(crate) fn info(&self) -> PresetInfo {
+        PresetInfo {
+            name: self.name.clone(),
+            description: String::new(),
+            bob_count: self.summary.bob_count,
+            source: PresetSource::User,
+        }
+    }
+}
+
+pub(crate) fn validate_name(name: &str) -> Result<(), CommandError> {
+    if name.trim().is_empty() {
+        return Err(CommandError::invalid("name", "must not be blank"));
+    }
+    let len = name.chars().count();
+    if len > MAX_PRESET_NAME_LEN {
+        return Err(CommandError::invalid(
+            "name",
+            format!("must be at most {MAX_PRESET_NAME_LEN} characters, got {len}"),
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(CommandError::invalid(
+            "name",
+            "must not contain control characters",
+        ));
+    }
+    Ok(())
+}
+
+/// The file a preset named `name` is kept in: the name's letters and digits,
+/// lowercased, with a checksum of the whole name, so every file name is
+/// safe on any file system and two names never share a file.
+pub(crate) fn file_name(name: &str) -> String {
+    let mut stem = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            stem.push(c);
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+        if stem.len() == FILE_STEM_LEN {
+            break;
+        }
+    }
+    let stem = stem.trim_end_matches('-');
+    let checksum = share::checksum(name.as_bytes());
+    if stem.is_empty() {
+        format!("{checksum:08x}.json")
+    } else {
+        format!("{stem}-{checksum:08x}.json")
+    }
+}
+
+/// Reads a preset file, refusing ones from newer versions. The
+/// configuration in it is read as `import_config` reads one.
+pub(crate) fn parse(text: &str) -> Result<(UserPresetFile, Vec<String>), CommandError> {
+    let invalid = |reason: String| CommandError::invalid("file", reason);
+    let mut value: Value =
+        serde_json::from_str(text).map_err(|e| invalid(format!("isn't valid JSON: {e}")))?;
+    let version = (value.get("version").and_then(Value::as_u64))
+        .ok_or_else(|| CommandError::invalid("version", "is missing"))?;
+    let version = u32::try_from(version)
+        .map_err(|_| CommandError::invalid("version", format!("{version} is out of range")))?;
+    if version > USER_PRESET_VERSION {
+        return Err(CommandError::UnsupportedVersion {
+            what: "Preset file",
+            version,
+            newest: USER_PRESET_VERSION,
+        });
+    }
+    let config = (value.get_mut("config").map(Value::take))
+        .ok_or_else(|| CommandError::invalid("config", "is missing"))?;
+    let (config, warnings) = config::parse(&config.to_string()).map_err(|e| e.within("config"))?;
+    let header: Header =
+        serde_json::from_value(value).map_err(|e| invalid(format!("isn't a preset: {e}")))?;
+    Ok((
+        UserPresetFile {
+            version: header.version,
+            name: header.name,
+            saved_at: header.saved_at,
+            summary: header.summary,
+            config,
+        },
+        warnings,
+    ))
+}
+
+/// A preset file without its configuration, which is all listing reads.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    version: u32,
+    name: String,
+    saved_at: u64,
+    summary: PresetSummary,
+}
+
+/// The presets the user has saved, one file each in a directory of the app
+/// data directory.
+#[derive(Clone)]
+pub(crate) struct UserPresets {
+    dir: PathBuf,
+}
+
+impl UserPresets {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(file_name(name))
+    }
+
+    /// Saves the configuration of `data` as `name`. A preset of that name
+    /// is only replaced with `overwrite`.
+    pub(crate) fn save(
+        &self,
+        name: &str,
+        data: &AppDataInner,
+        overwrite: bool,
+    ) -> Result<UserPresetInfo, CommandError> {
+        validate_name(name)?;
+        let path = self.path(name);
+        if !overwrite && path.exists() {
+            return Err(CommandError::invalid(
+                "name",
+                format!("a preset named {name:?} exists already; pass overwrite to replace it"),
+            ));
+        }
+        let pivot = data.pendulum.pivot;
+        let summary = PresetSummary {
+            bob_count: data.pendulum.n(),
+            positions: (data.pendulum.bobs.iter())
+                .map(|bob| Coordinate::new(bob.coordinate.x - pivot.x, bob.coordinate.y - pivot.y))
+                .collect(),
+        };
+        let saved_at = (SystemTime::now().duration_since(UNIX_EPOCH))
+            .map_or(0, |since| since.as_millis() as u64);
+        let file = UserPresetFile {
+            version: USER_PRESET_VERSION,
+            name: name.to_owned(),
+            saved_at,
+            summary,
+            config: data.export_config(),
+        };
+        let json = serde_json::to_vec_pretty(&file).map_err(CommandError::internal)?;
+        savefile::write_atomically(&path, &json).map_err(|e| CommandError::io(&path, e))?;
+        Ok(UserPresetInfo {
+            name: file.name,
+            saved_at,
+            summary: file.summary,
+        })
+    }
+
+    /// Every preset saved, by name. Files that can't be read are left out
+    /// rather than hiding the rest.
+    pub(crate) fn list(&self) -> Result<Vec<UserPresetInfo>, CommandError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(CommandError::io(&self.dir, error)),
+        };
+        let mut presets: Vec<UserPresetInfo> = entries
+            .filter_map(|entry| read_header(&entry.ok()?.path()))
+            .map(|header| UserPresetInfo {
+                name: header.name,
+                saved_at: header.saved_at,
+                summary: header.summary,
+            })
+            .collect();
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(presets)
+    }
+
+    /// The configuration saved as `name`, with warnings for any fields in
+    /// it this version doesn't know.
+    pub(crate) fn load(&self, name: &str) -> Result<(ConfigFile, Vec<String>), CommandError> {
+        let path = self.path(name);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(CommandError::not_found(
+                    "user preset named",
+                    format!("{name:?}"),
+                ));
+            }
+            Err(error) => return Err(CommandError::io(&path, error)),
+        };
+        let (file, warnings) = parse(&text)?;
+        Ok((file.config, warnings))
+    }
+
+    pub(crate) fn delete(&self, name: &str) -> Result<(), CommandError> {
+        let path = self.path(name);
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Err(CommandError::not_found(
+                "user preset named",
+                format!("{name:?}"),
+            )),
+            Err(error) => Err(CommandError::io(&path, error)),
+        }
+    }
+}
+
+fn read_header(path: &Path) -> Option<Header> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    let header: Header = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    (header.version <= USER_PRESET_VERSION).then_some(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Edit;
+
+    #[test]
+    fn user_presets_save_list_load_and_delete() {
+        let dir = std::env::temp_dir().join(format!("user-presets-{}", std::process::id()));
+        let presets = UserPresets::new(dir.clone());
+        assert!(presets.list().unwrap().is_empty());
+
+        let mut data = AppDataInner::default();
+        data.pendulum.bobs[0].theta = 2.0;
+        data.pendulum.update_coordinates();
+        data.pendulum.damping = 0.2;
+        let name = "My chaos: take 2/3";
+        let saved = presets.save(name, &data, false).unwrap();
+        assert_eq!(saved.summary.bob_count, data.pendulum.n());
+        let first = saved.summary.positions[0];
+        let length = data.pendulum.bobs[0].length_rod;
+        assert!((first.x - length * 2f64.sin()).abs() < 1e-9, "{first:?}");
+
+        // the file name only keeps what every file system takes
+        let file = file_name(name);
+        assert!(file.starts_with("my-chaos-take-2-3-") && file.ends_with(".json"));
+        assert_ne!(file_name("a b"), file_name("a_b"));
+        assert!(dir.join(&file).exists());
+
+        // an existing name is only replaced when asked
+        assert!(matches!(
+            presets.save(name, &data, false),
+            Err(CommandError::InvalidParameter { name, .. }) if name == "name"
+        ));
+        presets
+            .save("Another", &AppDataInner::default(), false)
+            .unwrap();
+        presets.save("Another", &data, true).unwrap();
+        assert!(presets.save("   ", &data, false).is_err());
+        assert!(presets.save(&"x".repeat(65), &data, false).is_err());
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+        let listed = presets.list().unwrap();
+        let names: Vec<&str> = listed.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Another", name]);
+        assert_eq!(listed[1].summary, saved.summary);
+        assert_eq!(listed[1].info().source, PresetSource::User);
+
+        // loading puts the configuration back as one undoable edit
+        let mut other = AppDataInner::default();
+        let (config, warnings) = presets.load(name).unwrap();
+        assert!(warnings.is_empty());
+        other.apply_config(&config, Edit::LoadPreset).unwrap();
+        assert_eq!(other.export_config(), data.export_config());
+        assert_eq!(other.undo().unwrap(), Edit::LoadPreset);
+        assert_eq!(
+            other.export_config().bobs,
+            AppDataInner::default().bob_specs()
+        );
+
+        presets.delete(name).unwrap();
+        assert!(matches!(
+            presets.load(name),
+            Err(CommandError::NotFound { .. })
+        ));
+        assert!(presets.delete(name).is_err());
+        assert_eq!(presets.list().unwrap().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,371 @@
+use pendulum_core::Coordinate;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::error::CommandError;
+use crate::instances::Instances;
+use crate::state::AppDataInner;
+
+/// Longest warm-up that may be asked for, in sim seconds.
+pub(crate) const MAX_WARM_UP_SECONDS: f64 = 86_400.0;
+/// Most substeps a `step_n` may take.
+pub(crate) const MAX_STEP_N_STEPS: u64 = 1_000_000;
+/// Wall time after which a warm-up stops where it got to.
+pub(crate) const WARM_UP_WALL_BUDGET: Duration = Duration::from_secs(30);
+/// Substeps taken per job on the physics task, a few milliseconds' worth,
+/// so commands and ticks get in between.
+const WARM_UP_CHUNK: u64 = 2_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WarmUpProgress {
+    /// Sim seconds skipped so far.
+    pub(crate) completed: f64,
+    pub(crate) total: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum WarmUpOutcome {
+    Completed,
+    Cancelled,
+    /// Stopped at `WARM_UP_WALL_BUDGET`.
+    OutOfTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WarmUpReport {
+    pub(crate) outcome: WarmUpOutcome,
+    /// Sim seconds actually skipped; they stay skipped whatever the outcome.
+    pub(crate) sim_seconds: f64,
+    pub(crate) substeps: u64,
+}
+
+pub(crate) fn validate(seconds: f64) -> Result<f64, CommandError> {
+    if seconds > 0.0 && seconds <= MAX_WARM_UP_SECONDS {
+        Ok(seconds)
+    } else {
+        Err(CommandError::invalid(
+            "seconds",
+            format!("must be positive and at most {MAX_WARM_UP_SECONDS} s, got {seconds}"),
+        ))
+    }
+}
+
+/// Steps the live simulation `seconds` ahead as fast as it goes, in chunks,
+/// at the dt in force when it starts, with real-time stepping paused
+/// meanwhile. Flips and alerts of the skipped interval are not emitted.
+/// Blocks, so it belongs on a blocking thread.
+pub(crate) fn warm_up(
+    instances: &Instances,
+    instance: Option<u64>,
+    seconds: f64,
+    budget: Duration,
+    cancel: &Arc<AtomicBool>,
+    progress: impl Fn(WarmUpProgress),
+) -> Result<WarmUpReport, CommandError> {
+    let dt = instances.call_blocking(instance, |app_data| Ok(app_data.dt))?;
+    let total_steps = ((seconds / dt).round() as u64).max(1);
+    let (report, ()) = run_steps(
+        instances,
+        instance,
+        total_steps,
+        dt,
+        budget,
+        cancel,
+        progress,
+        |_| (),
+    )?;
+    Ok(report)
+}
+
+/// What one job of `run_steps` did.
+enum Chunk<T> {
+    Stepped(u64),
+    Stopped(WarmUpOutcome, T),
+}
+
+/// The chunked loop behind `warm_up` and `step_n`, a job on the physics
+/// task per chunk. `on_stop` sees the state it stopped at in the same job,
+/// before real-time stepping picks up again.
+#[allow(clippy::too_many_arguments)]
+fn run_steps<T: Send + 'static>(
+    instances: &Instances,
+    instance: Option<u64>,
+    total_steps: u64,
+    dt: f64,
+    budget: Duration,
+    cancel: &Arc<AtomicBool>,
+    progress: impl Fn(WarmUpProgress),
+    on_stop: fn(&AppDataInner) -> T,
+) -> Result<(WarmUpReport, T), CommandError> {
+    let started = Instant::now();
+    let mut report = WarmUpReport {
+        outcome: WarmUpOutcome::Completed,
+        sim_seconds: 0.0,
+        substeps: 0,
+    };
+    loop {
+        let (cancel, substeps) = (cancel.clone(), report.substeps);
+        let job = move |app_data: &mut AppDataInner| {
+            let stop = if cancel.load(Ordering::Relaxed) {
+                Some(WarmUpOutcome::Cancelled)
+            } else if substeps == total_steps {
+                Some(WarmUpOutcome::Completed)
+            } else if started.elapsed() >= budget {
+                Some(WarmUpOutcome::OutOfTime)
+            } else {
+                None
+            };
+            if let Some(outcome) = stop {
+                // a newer warm-up owns the flag now
+                if Arc::ptr_eq(&app_data.warm_up_cancel, &cancel) {
+                    app_data.warming_up = false;
+                }
+                return Ok(Chunk::Stopped(outcome, on_stop(app_data)));
+            }
+            app_data.warming_up = true;
+            let chunk = WARM_UP_CHUNK.min(total_steps - substeps);
+            let mut skipped = Vec::new();
+            for _ in 0..chunk {
+                app_data.substep(dt, &mut skipped);
+            }
+            Ok(Chunk::Stepped(chunk))
+        };
+        let chunk = match instances.call_blocking(instance, job)? {
+            Chunk::Stepped(chunk) => chunk,
+            Chunk::Stopped(outcome, stopped) => {
+                report.outcome = outcome;
+                return Ok((report, stopped));
+            }
+        };
+        report.substeps += chunk;
+        report.sim_seconds = report.substeps as f64 * dt;
+        progress(WarmUpProgress {
+            completed: report.sim_seconds,
+            total: total_steps as f64 * dt,
+        });
+    }
+}
+
+/// The state `step_n` leaves the simulation in.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SteppedState {
+    pub(crate) sim_time: f64,
+    pub(crate) thetas: Vec<f64>,
+    pub(crate) omegas: Vec<f64>,
+    pub(crate) positions: Vec<Coordinate>,
+    pub(crate) energy: f64,
+}
+
+pub(crate) fn validate_step_n(steps: u64, dt: f64) -> Result<(), CommandError> {
+    if !(1..=MAX_STEP_N_STEPS).contains(&steps) {
+        return Err(CommandError::invalid(
+            "steps",
+            format!("must be between 1 and {MAX_STEP_N_STEPS}, got {steps}"),
+        ));
+    }
+    if !(dt.is_finite() && dt > 0.0) {
+        return Err(CommandError::invalid(
+            "dt",
+            format!("must be positive and finite, got {dt}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Takes exactly `steps` substeps of `dt` the way a warm-up does, with
+/// real-time stepping paused meanwhile, and returns where they led. The same
+/// state, steps and dt always give the same result, bit for bit. Fails,
+/// with the steps taken so far kept, if it runs out of wall time or a
+/// warm-up cancels it.
+pub(crate) fn step_n(
+    instances: &Instances,
+    instance: Option<u64>,
+    steps: u64,
+    dt: f64,
+    budget: Duration,
+    cancel: &Arc<AtomicBool>,
+) -> Result<SteppedState, CommandError> {
+    let (report, state) = run_steps(
+        instances,
+        instance,
+        steps,
+        dt,
+        budget,
+        cancel,
+        |_| {},
+        |app_data| {
+            let bobs = &app_data.pendulum.bobs;
+            SteppedState {
+                sim_time: app_data.sim_time,
+                thetas: bobs.iter().map(|b| b.theta).collect(),
+                omegas: bobs.iter().map(|b| b.omega).collect(),
+                positions: bobs.iter().map(|b| b.coordinate).collect(),
+                energy: app_data.pendulum.total_energy(),
+            }
+        },
+    )?;
+    if report.outcome != WarmUpOutcome::Completed {
+        return Err(CommandError::Cancelled {
+            reason: format!(
+                "Stopped ({:?}) after {} of {steps} steps",
+                report.outcome, report.substeps
+            ),
+        });
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventSink, SimEvent};
+    use crate::state::DEFAULT_DT;
+    use crate::stream::physics_loop;
+    use std::sync::Mutex;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit(&self, _: SimEvent) {}
+    }
+
+    /// Runs `run` on a blocking thread, as the commands do, against a
+    /// default primary instance on a physics task of its own.
+    async fn on_physics<T: Send + 'static>(
+        run: impl FnOnce(&Instances) -> T + Send + 'static,
+    ) -> T {
+        let (instances, physics) = Instances::new(AppDataInner::default());
+        let task = tokio::spawn(async move { physics_loop(physics, &NoEvents).await });
+        let result = tokio::task::spawn_blocking(move || run(&instances)).await;
+        task.abort();
+        result.unwrap()
+    }
+
+    fn cancel_of(instances: &Instances) -> Arc<AtomicBool> {
+        let cancel = |app_data: &mut AppDataInner| Ok(app_data.warm_up_cancel.clone());
+        instances.call_blocking(None, cancel).unwrap()
+    }
+
+    #[tokio::test]
+    async fn skips_ahead_and_reports_progress() {
+        on_physics(|instances| {
+            let cancel = cancel_of(instances);
+            let updates = Mutex::new(Vec::new());
+            let report = warm_up(instances, None, 100.0, WARM_UP_WALL_BUDGET, &cancel, |p| {
+                updates.lock().unwrap().push(p.completed)
+            })
+            .unwrap();
+            assert_eq!(report.outcome, WarmUpOutcome::Completed);
+            assert_eq!(report.substeps, (100.0 / DEFAULT_DT).round() as u64);
+
+            let read = |app_data: &mut AppDataInner| {
+                Ok((
+                    app_data.sim_time,
+                    app_data.counters.substeps,
+                    app_data.warming_up,
+                ))
+            };
+            let (sim_time, substeps, warming_up) = instances.call_blocking(None, read).unwrap();
+            assert!((sim_time - 100.0).abs() < DEFAULT_DT);
+            assert_eq!(substeps, report.substeps);
+            assert!(!warming_up);
+            let updates = updates.lock().unwrap();
+            assert!(updates.len() > 1);
+            assert!(updates.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(updates.last(), Some(&report.sim_seconds));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn stops_on_cancel_or_budget() {
+        on_physics(|instances| {
+            let cancel = cancel_of(instances);
+            let report = warm_up(
+                instances,
+                None,
+                1_000.0,
+                WARM_UP_WALL_BUDGET,
+                &cancel,
+                |p| {
+                    if p.completed > 10.0 {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                },
+            )
+            .unwrap();
+            assert_eq!(report.outcome, WarmUpOutcome::Cancelled);
+            assert!(report.sim_seconds > 10.0 && report.sim_seconds < 1_000.0);
+
+            let cancel = Arc::new(AtomicBool::new(false));
+            let replace = cancel.clone();
+            let replace = move |app_data: &mut AppDataInner| {
+                app_data.warm_up_cancel = replace;
+                Ok(())
+            };
+            instances.call_blocking(None, replace).unwrap();
+            let report = warm_up(instances, None, 1_000.0, Duration::ZERO, &cancel, |_| {});
+            let report = report.unwrap();
+            assert_eq!(report.outcome, WarmUpOutcome::OutOfTime);
+            assert_eq!(report.substeps, 0);
+            assert!(warm_up(
+                instances,
+                Some(7),
+                1.0,
+                WARM_UP_WALL_BUDGET,
+                &cancel,
+                |_| {}
+            )
+            .is_err());
+        })
+        .await;
+        assert!(validate(f64::NAN).is_err());
+        assert!(validate(MAX_WARM_UP_SECONDS * 2.0).is_err());
+    }
+
+    #[tokio::test]
+    async fn step_n_reproduces_the_golden_state() {
+        let run = || {
+            on_physics(|instances| {
+                let cancel = cancel_of(instances);
+                step_n(instances, None, 1_000, 0.0005, WARM_UP_WALL_BUDGET, &cancel).unwrap()
+            })
+        };
+        let state = run().await;
+        // the default pendulum after 0.5 s at dt = 0.0005; any change to the
+        // integrator or the default configuration shows up here
+        assert!((state.sim_time - 0.5).abs() < 1e-9);
+        assert_eq!(
+            state.thetas,
+            [
+                0.8526764343683548,
+                0.10320049644384054,
+                0.3272037663365257,
+                0.3137003266894458,
+            ]
+        );
+        assert_eq!(
+            state.omegas,
+            [
+                2.5052581231272866,
+                -0.9704873313954736,
+                0.06253406107292732,
+                -0.002315160989154481,
+            ]
+        );
+        assert_eq!(state.energy, 134.3346750949449);
+        assert_eq!(state, run().await);
+        assert!(validate_step_n(MAX_STEP_N_STEPS + 1, 0.01).is_err());
+        assert!(validate_step_n(10, 0.0).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use pendulum_core::{Bob, Pendulum, DEFAULT_MASS, GRAVITATIONAL_ACCELERATION};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+use crate::error::CommandError;
+use crate::instances::MAX_INSTANCES;
+use crate::pendulum::{BobMeta, BobSpec};
+use crate::state::{AppDataInner, SetStateOptions};
+
+/// Most pendulums a wave may have: one instance each, next to the primary.
+pub(crate) const MAX_WAVE_PENDULUMS: usize = MAX_INSTANCES - 1;
+/// Longest common period a wave may have, in seconds.
+pub(crate) const MAX_WAVE_PERIOD: f64 = 600.0;
+/// How far from hanging straight down the pendulums are released, in rad,
+/// when `setup_pendulum_wave` is given no amplitude.
+pub(crate) const DEFAULT_WAVE_AMPLITUDE: f64 = 0.3;
+
+/// The other pendulums of a wave, which its first pendulum's instance
+/// keeps and steps along with its own, substep for substep, so they all
+/// share one clock and realign together.
+pub(crate) struct Wave {
+    /// Id of the instance stepping the wave.
+    pub(crate) leader: u64,
+    pub(crate) members: Vec<(u64, AppDataInner)>,
+}
+
+/// What `setup_pendulum_wave` made.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WaveInfo {
+    /// One instance per pendulum, the longest first; the first steps the
+    /// others and its frames carry them all.
+    pub(crate) instances: Vec<u64>,
+    /// Rod lengths, in meters, in the same order.
+    pub(crate) lengths: Vec<f64>,
+    pub(crate) period: f64,
+    pub(crate) base_cycles: u32,
+    /// Release angle from hanging straight down, in rad.
+    pub(crate) amplitude: f64,
+}
+
+/// Every pendulum's tip, in the first instance's positions frames, so the
+/// whole wave is drawn from one subscription.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WaveState {
+    pub(crate) instances: Vec<u64>,
+    pub(crate) tip_x: Vec<f64>,
+    pub(crate) tip_y: Vec<f64>,
+}
+
+pub(crate) fn validate(
+    n: usize,
+    period: f64,
+    base_cycles: u32,
+    amplitude: f64,
+) -> Result<(), CommandError> {
+    if !(2..=MAX_WAVE_PENDULUMS).contains(&n) {
+        return Err(CommandError::invalid(
+            "n",
+            format!("must be between 2 and {MAX_WAVE_PENDULUMS}, got {n}"),
+        ));
+    }
+    if !(period.is_finite() && period > 0.0 && period <= MAX_WAVE_PERIOD) {
+        return Err(CommandError::invalid(
+            "period",
+            format!("must be positive and at most {MAX_WAVE_PERIOD} s, got {period}"),
+        ));
+    }
+    if base_cycles == 0 {
+        return Err(CommandError::invalid("base_cycles", "must be at least 1"));
+    }
+    if !(amplitude > 0.0 && amplitude < PI / 2.0) {
+        return Err(CommandError::invalid(
+            "amplitude",
+            format!("must be between 0 and π/2, got {amplitude}"),
+        ));
+    }
+    Ok(())
+}
+
+/// The complete elliptic integral of the first kind K(k), by the
+/// arithmetic-geometric mean.
+fn elliptic_k(k: f64) -> f64 {
+    let (mut a, mut b) = (1.0, (1.0 - k * k).sqrt());
+    while (a - b).abs() > 1e-15 * a {
+        (a, b) = ((a + b) / 2.0, (a * b).sqrt());
+    }
+    PI / (2.0 * a)
+}
+
+/// Rod lengths for which the i-th pendulum, released `amplitude` from
+/// hanging, swings `base_cycles + i` times in `period`. Each comes from the
+/// exact period 4·√(L/g)·K(sin(amplitude/2)) rather than the small-angle
+/// 2π·√(L/g), so the wave realigns even from a wide release.
+pub(crate) fn lengths(n: usize, period: f64, base_cycles: u32, amplitude: f64) -> Vec<f64> {
+    let k = elliptic_k((amplitude / 2.0).sin());
+    (0..n)
+        .map(|i| {
+            let swing = period / f64::from(base_cycles + i as u32);
+            GRAVITATIONAL_ACCELERATION * (swing / (4.0 * k)).powi(2)
+        })
+        .collect()
+}
+
+/// A single bob of rod `length`, at rest `amplitude` short of hanging
+/// straight down, allowed only the one bob.
+pub(crate) fn member(length: f64, amplitude: f64) -> Result<AppDataInner, CommandError> {
+    let mut data = AppDataInner::default();
+    let bob = BobSpec {
+        length_rod: length,
+        mass: DEFAULT_MASS,
+        theta: PI - amplitude,
+        omega: 0.0,
+        id: None,
+        locked: false,
+        meta: BobMeta::default(),
+    };
+    data.set_state(&[bob], SetStateOptions::default())?;
+    data.set_max_bobs(1)?;
+    Ok(data)
+}
+
+impl Wave {
+    /// Takes the substep the leader just took, each member's events kept
+    /// for its own next tick.
+    pub(crate) fn step(&mut self, dt: f64) {
+        for (_, member) in &mut self.members {
+            let mut events = Vec::new();
+            member.substep(dt, &mut events);
+            member.pending_events.append(&mut events);
+        }
+    }
+
+    pub(crate) fn state(&self, leader: &Pendulum) -> WaveState {
+        let tip = |p: &Pendulum| p.bobs.last().map_or(p.pivot, |b: &Bob| b.coordinate);
+        let mut state = WaveState {
+            instances: vec![self.leader],
+            tip_x: vec![tip(leader).x],
+            tip_y: vec![tip(leader).y],
+        };
+        for (id, member) in &self.members {
+            let tip = tip(&member.pendulum);
+            state.instances.push(*id);
+            state.tip_x.push(tip.x);
+            state.tip_y.push(tip.y);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instances::World;
+    use crate::state::AppDataInner;
+
+    #[test]
+    fn the_wave_realigns_after_one_period() {
+        let mut world = World::new(AppDataInner::default());
+        assert!(world.setup_pendulum_wave(1, 8.0, 5, 0.3).is_err());
+        assert!(world.setup_pendulum_wave(4, -1.0, 5, 0.3).is_err());
+        assert!(world.setup_pendulum_wave(4, 8.0, 0, 0.3).is_err());
+
+        // in the small-angle limit the lengths are g·(T / 2πk)²
+        let small = lengths(2, 8.0, 5, 1e-6);
+        let expected = GRAVITATIONAL_ACCELERATION * (8.0 / (2.0 * PI * 5.0)).powi(2);
+        assert!((small[0] - expected).abs() < 1e-9, "{small:?}");
+
+        let (period, amplitude) = (8.0, 0.3);
+        let info = world.setup_pendulum_wave(6, period, 5, amplitude).unwrap();
+        assert_eq!(info.instances.len(), 6);
+        assert!(info.lengths.windows(2).all(|w| w[0] > w[1]));
+        let leader = Some(info.instances[0]);
+        let run = |world: &mut World, steps| {
+            let data = world.get(leader).unwrap();
+            for _ in 0..steps {
+                let dt = data.dt;
+                data.substep(dt, &mut Vec::new());
+            }
+        };
+        let thetas = |world: &mut World| -> Vec<f64> {
+            (info.instances.iter())
+                .map(|&id| world.get(Some(id)).unwrap().pendulum.bobs[0].theta)
+                .collect()
+        };
+        let half = (period / 2.0 / world.get(leader).unwrap().dt).round() as usize;
+
+        // halfway, the odd cycle counts have only swung half their last
+        run(&mut world, half);
+        for (i, theta) in thetas(&mut world).into_iter().enumerate() {
+            let released = if i % 2 == 0 {
+                PI + amplitude
+            } else {
+                PI - amplitude
+            };
+            assert!((theta - released).abs() < 0.01, "{i}: {theta}");
+        }
+        let frame = world.get(leader).unwrap().frame(0, 0.0);
+        let wave = frame.wave.unwrap();
+        assert_eq!(wave.instances, info.instances);
+        assert_eq!(wave.tip_x.len(), 6);
+
+        // after the whole period they are all back where they started
+        run(&mut world, half);
+        let sim_time = world.get(leader).unwrap().sim_time;
+        for (i, &id) in info.instances.iter().enumerate() {
+            let data = world.get(Some(id)).unwrap();
+            let bob = data.pendulum.bobs[0];
+            assert!((bob.theta - (PI - amplitude)).abs() < 0.01, "{i}: {bob:?}");
+            assert!(bob.omega.abs() < 0.1, "{i}: {bob:?}");
+            assert_eq!(data.sim_time, sim_time);
+        }
+
+        // the wave goes with its first instance
+        world.destroy(info.instances[0]).unwrap();
+        assert_eq!(world.list().len(), 1);
+    }
+}
@@ -0,0 +1,306 @@
+use futures_util::{stream::FuturesUnordered, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{net::Ipv4Addr, sync::Arc};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::Notify,
+};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    Message,
+};
+
+use crate::error::CommandError;
+use crate::instances::Instances;
+use crate::state::AppDataInner;
+use crate::stream::{self, SubscribeOptions, DEFAULT_CHANGE_EPSILON};
+
+/// A running WebSocket server. Dropping it shuts the server down and ends
+/// every connection.
+pub(crate) struct WsServer {
+    port: u16,
+    token: String,
+    shutdown: Arc<Notify>,
+}
+
+impl Drop for WsServer {
+    fn drop(&mut self) {
+        self.shutdown.notify_one();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WsServerInfo {
+    pub(crate) port: u16,
+    /// Clients must present it as the `token` query parameter.
+    pub(crate) token: String,
+    /// Where a client on this machine connects, token included.
+    pub(crate) url: String,
+}
+
+impl WsServer {
+    /// Raised when the server is dropped; `serve` returns on it.
+    pub(crate) fn shutdown_signal(&self) -> Arc<Notify> {
+        self.shutdown.clone()
+    }
+
+    pub(crate) fn info(&self) -> WsServerInfo {
+        WsServerInfo {
+            port: self.port,
+            token: self.token.clone(),
+            url: format!("ws://127.0.0.1:{}/?token={}", self.port, self.token),
+        }
+    }
+}
+
+/// Binds the listening socket, on loopback unless `allow_remote`, with a new
+/// random token. Port 0 picks a free port. The returned listener is for
+/// `serve`.
+pub(crate) async fn bind(
+    port: u16,
+    allow_remote: bool,
+) -> Result<(TcpListener, WsServer), CommandError> {
+    let host = if allow_remote {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        Ipv4Addr::LOCALHOST
+    };
+    let listener = TcpListener::bind((host, port))
+        .await
+        .map_err(|e| CommandError::internal(format!("Can't listen on port {port}: {e}")))?;
+    let port = listener
+        .local_addr()
+        .map_err(CommandError::internal)?
+        .port();
+    let server = WsServer {
+        port,
+        token: format!("{:032x}", rand::random::<u128>()),
+        shutdown: Arc::new(Notify::new()),
+    };
+    Ok((listener, server))
+}
+
+/// Serves connections on `listener` until `shutdown` is raised. Each
+/// connection is a positions subscription with the defaults of
+/// `pendulum_state`, so a client that can't keep up loses frames like any
+/// other subscriber, of the instance with `instance`.
+pub(crate) async fn serve(
+    instances: &Instances,
+    instance: Option<u64>,
+    listener: TcpListener,
+    token: &str,
+    shutdown: &Notify,
+) {
+    let mut connections = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            () = shutdown.notified() => return,
+            accepted = listener.accept() => {
+                if let Ok((stream, _)) = accepted {
+                    connections.push(connection(instances, instance, stream, token));
+                }
+            }
+            Some(()) = connections.next(), if !connections.is_empty() => {}
+        }
+    }
+}
+
+fn query_token(request: &Request) -> Option<&str> {
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+}
+
+// the handshake callback's error type is tungstenite's, not ours
+#[allow(clippy::result_large_err)]
+async fn connection(instances: &Instances, instance: Option<u64>, stream: TcpStream, token: &str) {
+    let authenticate = |request: &Request, response: Response| {
+        if query_token(request) == Some(token) {
+            Ok(response)
+        } else {
+            let mut refusal = ErrorResponse::new(Some("Missing or wrong token".into()));
+            *refusal.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(refusal)
+        }
+    };
+    let Ok(mut socket) = tokio_tungstenite::accept_hdr_async(stream, authenticate).await else {
+        return;
+    };
+    let options = SubscribeOptions {
+        change_epsilon: Some(DEFAULT_CHANGE_EPSILON),
+        ..SubscribeOptions::default()
+    };
+    let subscribe =
+        move |app_data: &mut AppDataInner| stream::subscribe_receiver(app_data, options);
+    let Ok((id, mut frames)) = instances.call(instance, subscribe).await else {
+        return;
+    };
+    loop {
+        tokio::select! {
+            frame = frames.recv() => {
+                let Some(frame) = frame else { break };
+                let Ok(text) = serde_json::to_string(&frame) else { continue };
+                if socket.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = handle_control(instances, instance, &text).await;
+                    let reply = reply.to_string();
+                    if socket.send(Message::text(reply)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                // pings are answered by the socket itself
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+    let unsubscribe = move |app_data: &mut AppDataInner| stream::unsubscribe(app_data, id);
+    let _ = instances.call(instance, unsubscribe).await;
+}
+
+#[derive(Deserialize)]
+struct ControlRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct ModifyBobParams {
+    index: usize,
+    length: Option<f64>,
+    mass: Option<f64>,
+    theta: Option<f64>,
+    omega: Option<f64>,
+}
+
+/// Runs one control message, `{"id": .., "method": .., "params": {..}}`, and
+/// returns the reply: `{"id": .., "result": null}` or `{"id": .., "error":
+/// {..}}`, the error shaped as the commands' are. The methods mirror the
+/// Tauri commands `set_paused` and `modify_bob`.
+async fn handle_control(instances: &Instances, instance: Option<u64>, text: &str) -> Value {
+    let request: ControlRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = CommandError::invalid("request", format!("is not a control message: {e}"));
+            return json!({ "id": null, "error": error });
+        }
+    };
+    match control(instances, instance, &request.method, request.params).await {
+        Ok(()) => json!({ "id": request.id, "result": null }),
+        Err(e) => json!({ "id": request.id, "error": e }),
+    }
+}
+
+async fn control(
+    instances: &Instances,
+    instance: Option<u64>,
+    method: &str,
+    params: Value,
+) -> Result<(), CommandError> {
+    match method {
+        "pause" | "resume" => {
+            let paused = method == "pause";
+            let pause = move |app_data: &mut AppDataInner| {
+                app_data.user_paused = paused;
+                Ok(())
+            };
+            instances.call(instance, pause).await
+        }
+        "modify_bob" => {
+            let p: ModifyBobParams = serde_json::from_value(params)
+                .map_err(|e| CommandError::invalid("params", e.to_string()))?;
+            let edit = move |app_data: &mut AppDataInner| {
+                let format = app_data.angle_format();
+                let theta = p.theta.map(|t| format.incoming_angle(t));
+                let omega = p.omega.map(|o| format.incoming_rate(o));
+                app_data.modify_bob(p.index, p.length, p.mass, theta, omega)
+            };
+            instances.edit(instance, edit).await
+        }
+        _ => Err(CommandError::not_found("method", format!("{method:?}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{EventSink, SimEvent};
+    use tokio_tungstenite::connect_async;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit(&self, _: SimEvent) {}
+    }
+
+    async fn next_json(
+        socket: &mut (impl StreamExt<Item = tokio_tungstenite::tungstenite::Result<Message>> + Unpin),
+    ) -> Value {
+        loop {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn streams_frames_and_takes_commands_with_the_token() {
+        let (instances, physics) = Instances::new(AppDataInner::default());
+        let physics = tokio::spawn(async move { stream::physics_loop(physics, &NoEvents).await });
+        let (listener, server) = bind(0, false).await.unwrap();
+        let info = server.info();
+        let serving = {
+            let instances = instances.clone();
+            let (token, shutdown) = (info.token.clone(), server.shutdown_signal());
+            tokio::spawn(async move { serve(&instances, None, listener, &token, &shutdown).await })
+        };
+
+        let wrong = format!("ws://127.0.0.1:{}/?token=nope", info.port);
+        assert!(connect_async(wrong).await.is_err());
+
+        let (mut socket, _) = connect_async(info.url.clone()).await.unwrap();
+        let frame = next_json(&mut socket).await;
+        assert_eq!(frame["bobs"].as_array().unwrap().len(), 4);
+
+        let request = json!({ "id": 7, "method": "pause" }).to_string();
+        socket.send(Message::text(request)).await.unwrap();
+        let request = json!({ "id": 8, "method": "modify_bob", "params": { "index": 9 } });
+        socket
+            .send(Message::text(request.to_string()))
+            .await
+            .unwrap();
+        let mut replies = Vec::new();
+        while replies.len() < 2 {
+            let message = next_json(&mut socket).await;
+            if message.get("id").is_some() {
+                replies.push(message);
+            }
+        }
+        assert_eq!(replies[0], json!({ "id": 7, "result": null }));
+        assert_eq!(replies[1]["error"]["code"], "indexOutOfBounds");
+        assert_eq!(replies[1]["error"]["index"], 9);
+        let paused = |app_data: &mut AppDataInner| Ok(app_data.user_paused);
+        assert!(instances.call(None, paused).await.unwrap());
+
+        // stopping the server ends the connection and its subscription
+        drop(server);
+        serving.await.unwrap();
+        while let Some(Ok(_)) = socket.next().await {}
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let subscribers = |app_data: &mut AppDataInner| Ok(app_data.subscribers.len());
+        assert_eq!(instances.call(None, subscribers).await.unwrap(), 0);
+        physics.abort();
+    }
+}
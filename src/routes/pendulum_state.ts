@@ -1,3 +1,4 @@
 export type PendulumState = {
-    bobs: { theta: number; position: { x: number; y: number }; mass: number; lengthRod: number; omega: number }[];
+    pixelsPerMeter: number;
+    bobs: { theta: number; position: { x: number; y: number }; mass: number; lengthRod: number; omega: number; color?: string; label?: string }[];
 };
\ No newline at end of file